@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE file_integrity_mismatches(
+    id BIGSERIAL NOT NULL,
+
+    sys_file_id BIGINT NOT NULL,
+    expected_hash VARCHAR NOT NULL,
+    -- 为空表示文件已丢失
+    actual_hash VARCHAR,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    PRIMARY KEY (id)
+);
+
+SELECT diesel_manage_updated_at('file_integrity_mismatches');
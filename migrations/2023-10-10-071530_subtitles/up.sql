@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE subtitles(
+    id BIGSERIAL NOT NULL,
+
+    -- 视频和字幕都是用户文件
+    video_file_id BIGINT NOT NULL,
+    subtitle_file_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    language VARCHAR,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    PRIMARY KEY (id),
+    UNIQUE (video_file_id, subtitle_file_id)
+);
+
+SELECT diesel_manage_updated_at('subtitles');
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE credit_transactions;
+ALTER TABLE transcode_tasks DROP COLUMN credits;
+ALTER TABLE users DROP COLUMN credits;
@@ -0,0 +1,26 @@
+ALTER TABLE users ADD COLUMN credits BIGINT NOT NULL DEFAULT 0;
+COMMENT ON COLUMN users.credits IS '积分余额';
+
+ALTER TABLE transcode_tasks ADD COLUMN credits BIGINT NOT NULL DEFAULT 0;
+COMMENT ON COLUMN transcode_tasks.credits IS '创建任务时扣除的积分';
+
+CREATE TABLE credit_transactions(
+    id BIGSERIAL NOT NULL,
+    user_id BIGINT NOT NULL,
+
+    -- 正数为增加，负数为扣除
+    amount BIGINT NOT NULL,
+    -- 本次变动后的余额
+    balance BIGINT NOT NULL,
+    kind smallint NOT NULL,
+    order_id BIGINT,
+    task_id BIGINT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX credit_transactions_user_id ON credit_transactions(user_id);
+
+SELECT diesel_manage_updated_at('credit_transactions');
@@ -0,0 +1,21 @@
+CREATE TABLE notifications(
+    id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+
+    kind smallint NOT NULL,
+    message VARCHAR NOT NULL,
+    file_id BIGINT,
+    task_id BIGINT,
+    read BOOLEAN NOT NULL DEFAULT FALSE,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX notifications_user_id ON notifications(user_id, read);
+
+SELECT diesel_manage_updated_at('notifications');
+
+COMMENT ON TABLE notifications IS '用户通知';
+COMMENT ON COLUMN notifications.kind IS '通知类型：0 上传完成，1 转码成功，2 转码失败';
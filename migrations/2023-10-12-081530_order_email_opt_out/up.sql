@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN order_email_opt_out BOOLEAN NOT NULL DEFAULT FALSE;
+COMMENT ON COLUMN users.order_email_opt_out IS '是否退订转码订单完成邮件';
@@ -0,0 +1,35 @@
+CREATE TABLE webhooks(
+    id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    url VARCHAR(512) NOT NULL,
+    secret VARCHAR NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX webhooks_user_id ON webhooks(user_id);
+
+SELECT diesel_manage_updated_at('webhooks');
+
+CREATE TABLE webhook_deliveries(
+    id BIGSERIAL NOT NULL,
+    webhook_id BIGINT NOT NULL,
+    event VARCHAR NOT NULL,
+    payload TEXT NOT NULL,
+
+    -- 0 等待投递，1 投递成功，2 多次重试后放弃
+    status smallint NOT NULL DEFAULT 0,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX webhook_deliveries_pending ON webhook_deliveries(next_attempt_at) WHERE status = 0;
+
+SELECT diesel_manage_updated_at('webhook_deliveries');
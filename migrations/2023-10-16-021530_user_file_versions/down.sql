@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN max_file_versions;
+
+DROP TABLE user_file_versions;
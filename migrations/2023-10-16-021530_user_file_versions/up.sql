@@ -0,0 +1,18 @@
+CREATE TABLE user_file_versions(
+    id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    -- 被覆盖前的文件内容
+    sys_file_id BIGINT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX user_file_versions_user_file_id ON user_file_versions(user_file_id);
+
+SELECT diesel_manage_updated_at('user_file_versions');
+
+ALTER TABLE users ADD COLUMN max_file_versions INT;
+COMMENT ON COLUMN users.max_file_versions IS '每个文件保留的历史版本数，为空时使用系统默认值';
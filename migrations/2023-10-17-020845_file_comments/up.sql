@@ -0,0 +1,17 @@
+CREATE TABLE file_comments(
+    id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    -- 评论者
+    user_id BIGINT NOT NULL,
+    content VARCHAR(2000) NOT NULL,
+    -- 评论针对的视频时间点（毫秒），为空表示针对整个文件
+    timecode_ms INT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX file_comments_user_file_id ON file_comments(user_file_id, create_at);
+
+SELECT diesel_manage_updated_at('file_comments');
@@ -0,0 +1,22 @@
+CREATE TABLE tags(
+    id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    name VARCHAR(32) NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id),
+    UNIQUE (user_id, name)
+);
+
+SELECT diesel_manage_updated_at('tags');
+
+CREATE TABLE file_tags(
+    user_file_id BIGINT NOT NULL,
+    tag_id BIGINT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_file_id, tag_id)
+);
+
+CREATE INDEX file_tags_tag_id ON file_tags(tag_id);
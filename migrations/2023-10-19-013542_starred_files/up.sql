@@ -0,0 +1,10 @@
+CREATE TABLE starred_files(
+    user_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+
+    -- 收藏时间
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, user_file_id)
+);
+
+CREATE INDEX starred_files_user_id_create_at ON starred_files(user_id, create_at);
@@ -0,0 +1,9 @@
+-- 用户最近一次访问文件（下载、预览、作为转码源）的时间，由 redis 中的记录定期写入
+CREATE TABLE file_accesses(
+    user_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    accessed_at TIMESTAMPTz NOT NULL,
+    PRIMARY KEY (user_id, user_file_id)
+);
+
+CREATE INDEX file_accesses_user_id_accessed_at ON file_accesses(user_id, accessed_at);
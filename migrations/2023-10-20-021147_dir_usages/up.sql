@@ -0,0 +1,11 @@
+-- 目录（含所有子目录）占用空间的缓存，目录下的文件有变动时删除
+CREATE TABLE dir_usages(
+    dir_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    size BIGINT NOT NULL,
+    file_count BIGINT NOT NULL,
+    video_duration_ms BIGINT NOT NULL,
+
+    computed_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (dir_id)
+);
@@ -0,0 +1,10 @@
+-- 转码任务运行期间锁定源文件，防止被删除、移动或重命名
+CREATE TABLE file_locks(
+    task_id BIGINT PRIMARY KEY,
+    user_file_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX file_locks_user_file_id ON file_locks(user_file_id);
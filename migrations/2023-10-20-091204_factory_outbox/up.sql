@@ -0,0 +1,20 @@
+-- 发送给 av1-factory 的任务请求，与订单在同一个事务中写入，由后台任务投递
+CREATE TABLE factory_outbox(
+    id BIGSERIAL NOT NULL,
+    task_id BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+
+    -- 0 等待发送，1 已发送，2 多次重试后放弃
+    status smallint NOT NULL DEFAULT 0,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX factory_outbox_pending ON factory_outbox(next_attempt_at) WHERE status = 0;
+
+SELECT diesel_manage_updated_at('factory_outbox');
@@ -0,0 +1,23 @@
+-- 转码工厂的回调，先保存再由后台任务处理，处理失败时按退避时间重试
+CREATE TABLE factory_callbacks(
+    id BIGSERIAL NOT NULL,
+    -- 0 转码完成，1 视频解析完成
+    kind smallint NOT NULL,
+    task_id BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+
+    -- 0 等待处理，1 处理成功，2 多次重试后放弃，需要人工重放
+    status smallint NOT NULL DEFAULT 0,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX factory_callbacks_pending ON factory_callbacks(next_attempt_at) WHERE status = 0;
+CREATE INDEX factory_callbacks_dead ON factory_callbacks(create_at) WHERE status = 2;
+
+SELECT diesel_manage_updated_at('factory_callbacks');
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX transcode_tasks_processing;
+ALTER TABLE transcode_tasks DROP COLUMN retries;
+ALTER TABLE transcode_tasks DROP COLUMN started_at;
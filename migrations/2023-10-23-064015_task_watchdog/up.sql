@@ -0,0 +1,6 @@
+-- 任务开始（或重新下发）的时间，超时检测从这里开始计算
+ALTER TABLE transcode_tasks ADD COLUMN started_at TIMESTAMPTz NOT NULL DEFAULT NOW();
+-- 超时后自动重新下发的次数
+ALTER TABLE transcode_tasks ADD COLUMN retries smallint NOT NULL DEFAULT 0;
+
+CREATE INDEX transcode_tasks_processing ON transcode_tasks(started_at) WHERE status = 0;
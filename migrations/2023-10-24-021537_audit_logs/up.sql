@@ -0,0 +1,12 @@
+-- 员工的敏感操作记录，如查看未脱敏的用户联系方式
+CREATE TABLE audit_logs(
+    id BIGSERIAL NOT NULL,
+    employee_id BIGINT NOT NULL,
+    action VARCHAR NOT NULL,
+    -- 操作对象等信息，json 格式
+    detail TEXT NOT NULL,
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX audit_logs_employee ON audit_logs(employee_id, create_at);
@@ -0,0 +1,16 @@
+-- 员工发起的用户列表导出任务，结果文件保存在存储根目录的 exports 下
+CREATE TABLE user_export_jobs(
+    id BIGSERIAL NOT NULL,
+    employee_id BIGINT NOT NULL,
+    -- 导出条件，json 格式
+    params TEXT NOT NULL,
+    -- 0 进行中，1 完成，2 失败
+    status smallint NOT NULL DEFAULT 0,
+    row_count BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+SELECT diesel_manage_updated_at('user_export_jobs');
@@ -0,0 +1,14 @@
+-- 文件的访问记录，文件所有者可以查看，超过保留天数后删除
+CREATE TABLE file_access_logs(
+    id BIGSERIAL NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    -- download、preview
+    kind VARCHAR NOT NULL,
+    -- 访问者，未登录时为空
+    accessor_id BIGINT,
+    accessed_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX file_access_logs_file ON file_access_logs(user_file_id, accessed_at);
+CREATE INDEX file_access_logs_time ON file_access_logs(accessed_at);
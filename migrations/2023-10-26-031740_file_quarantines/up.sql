@@ -0,0 +1,14 @@
+-- 扫描出问题的文件内容，按 hash 记录，所有引用该内容的用户文件都不能下载
+CREATE TABLE file_quarantines(
+    hash VARCHAR NOT NULL,
+    -- 扫描器名字，如 clamav
+    scanner VARCHAR NOT NULL,
+    -- 扫描器给出的特征名
+    signature VARCHAR NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT  NOW(),
+    PRIMARY KEY (hash)
+);
+
+SELECT diesel_manage_updated_at('file_quarantines');
@@ -0,0 +1,2 @@
+-- 上传完成时根据文件内容识别的 MIME 类型，旧数据为空
+ALTER TABLE sys_files ADD COLUMN mime VARCHAR;
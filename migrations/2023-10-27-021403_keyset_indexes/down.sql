@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX user_files_children_by_id;
+DROP INDEX user_files_children_by_name;
+DROP INDEX users_name_id;
@@ -0,0 +1,4 @@
+-- 游标分页按 (排序键, id) 查找下一页
+CREATE INDEX users_name_id ON users(name, id);
+CREATE INDEX user_files_children_by_name ON user_files(parent_id, is_dir DESC, file_name, id) WHERE NOT deleted;
+CREATE INDEX user_files_children_by_id ON user_files(parent_id, is_dir DESC, id) WHERE NOT deleted;
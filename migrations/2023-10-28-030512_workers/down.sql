@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX transcode_tasks_worker_id;
+ALTER TABLE transcode_tasks DROP COLUMN worker_id;
+DROP TABLE workers;
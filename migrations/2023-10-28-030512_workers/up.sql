@@ -0,0 +1,16 @@
+-- 转码节点，同一个地址重新注册时替换旧的记录
+CREATE TABLE workers (
+    id BIGINT PRIMARY KEY,
+    endpoint VARCHAR NOT NULL UNIQUE,
+    codecs TEXT[] NOT NULL,
+    max_width INT NOT NULL,
+    max_height INT NOT NULL,
+    concurrency INT NOT NULL,
+    running INT NOT NULL DEFAULT 0,
+    last_heartbeat_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    create_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 任务被分配到的节点，节点下线后重新分配
+ALTER TABLE transcode_tasks ADD COLUMN worker_id BIGINT;
+CREATE INDEX transcode_tasks_worker_id ON transcode_tasks(worker_id) WHERE status = 0;
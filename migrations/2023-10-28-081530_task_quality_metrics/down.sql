@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transcode_tasks
+    DROP COLUMN vmaf,
+    DROP COLUMN psnr,
+    DROP COLUMN source_bitrate,
+    DROP COLUMN output_bitrate;
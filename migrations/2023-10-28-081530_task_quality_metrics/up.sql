@@ -0,0 +1,7 @@
+-- 转码工厂在任务完成时上报的质量指标，旧版本的工厂不上报时为空
+ALTER TABLE transcode_tasks
+    ADD COLUMN vmaf FLOAT8,
+    ADD COLUMN psnr FLOAT8,
+    -- 源视频和转码结果的码率，bit/s
+    ADD COLUMN source_bitrate BIGINT,
+    ADD COLUMN output_bitrate BIGINT;
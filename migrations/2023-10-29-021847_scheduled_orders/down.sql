@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX orders_scheduled_idx;
+
+ALTER TABLE orders DROP COLUMN start_after;
@@ -0,0 +1,4 @@
+-- 预约转码：订单在 start_after 之后才下发，下发前订单和任务的状态为 4
+ALTER TABLE orders ADD COLUMN start_after TIMESTAMPTZ;
+
+CREATE INDEX orders_scheduled_idx ON orders (start_after) WHERE status = 4;
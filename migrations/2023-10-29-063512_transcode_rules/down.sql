@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE transcode_rule_matches;
+DROP TABLE transcode_rules;
@@ -0,0 +1,30 @@
+-- 自动转码规则，上传到 watch_dir 的视频按 params 自动创建转码订单
+CREATE TABLE transcode_rules(
+    id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    watch_dir VARCHAR NOT NULL,
+    recursive BOOLEAN NOT NULL DEFAULT FALSE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    params TEXT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX transcode_rules_user_id ON transcode_rules(user_id);
+
+SELECT diesel_manage_updated_at('transcode_rules');
+
+-- 匹配了规则、等待视频信息解析完成后创建订单的文件
+CREATE TABLE transcode_rule_matches(
+    rule_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    sys_file_id BIGINT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (rule_id, user_file_id)
+);
+
+CREATE INDEX transcode_rule_matches_sys_file_id ON transcode_rule_matches(sys_file_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transcode_tasks
+    DROP COLUMN output_hash;
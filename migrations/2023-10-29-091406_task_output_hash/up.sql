@@ -0,0 +1,3 @@
+-- 转码结果的文件 hash，用于关联结果的系统文件。系统文件按 hash 去重，不能直接记录 id
+ALTER TABLE transcode_tasks
+    ADD COLUMN output_hash VARCHAR;
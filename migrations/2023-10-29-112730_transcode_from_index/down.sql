@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX sys_files_transcode_from_idx;
@@ -0,0 +1,12 @@
+-- 按源文件查找转码结果
+CREATE INDEX sys_files_transcode_from_idx ON sys_files (transcode_from)
+    WHERE transcode_from IS NOT NULL;
+
+-- 之前完成的任务没有记录 transcode_from，按转码结果的 hash 补上
+UPDATE sys_files
+SET transcode_from = t.sys_file_id
+FROM transcode_tasks t
+WHERE t.output_hash = sys_files.hash
+    AND t.status = 1
+    AND sys_files.transcode_from IS NULL
+    AND sys_files.id <> t.sys_file_id;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE factory_outbox
+    DROP COLUMN priority;
+
+ALTER TABLE users
+    DROP COLUMN level;
@@ -0,0 +1,7 @@
+-- 用户等级：0 普通用户，1 VIP，2 SVIP
+ALTER TABLE users
+    ADD COLUMN level SMALLINT NOT NULL DEFAULT 0;
+
+-- 转码请求的下发优先级，来自用户等级的配置，越大越先下发
+ALTER TABLE factory_outbox
+    ADD COLUMN priority SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users
+    DROP COLUMN bonus_quota;
+
+DROP TABLE referrals;
+
+DROP TABLE referral_codes;
@@ -0,0 +1,29 @@
+-- 用户的邀请码，每个用户可以生成多个
+CREATE TABLE referral_codes(
+    code VARCHAR(16) NOT NULL,
+    user_id BIGINT NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (code)
+);
+
+CREATE INDEX referral_codes_user_id ON referral_codes(user_id);
+
+-- 邀请记录，每个用户最多被邀请一次。奖励按注册时的配置记录
+CREATE TABLE referrals(
+    invitee_id BIGINT NOT NULL,
+    inviter_id BIGINT NOT NULL,
+    code VARCHAR(16) NOT NULL,
+    inviter_credits BIGINT NOT NULL DEFAULT 0,
+    invitee_credits BIGINT NOT NULL DEFAULT 0,
+    bonus_quota BIGINT NOT NULL DEFAULT 0,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (invitee_id)
+);
+
+CREATE INDEX referrals_inviter_id ON referrals(inviter_id);
+
+-- 邀请奖励的存储配额（字节），叠加在用户等级的配额上
+ALTER TABLE users
+    ADD COLUMN bonus_quota BIGINT NOT NULL DEFAULT 0;
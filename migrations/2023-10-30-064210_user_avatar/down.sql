@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX users_avatar;
+
+ALTER TABLE users
+    DROP COLUMN avatar;
@@ -0,0 +1,5 @@
+-- 头像文件的 hash，文件保存在归档目录下，为空时使用默认头像
+ALTER TABLE users
+    ADD COLUMN avatar VARCHAR;
+
+CREATE INDEX users_avatar ON users(avatar) WHERE avatar IS NOT NULL;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+UPDATE users SET mobile_number = substring(mobile_number FROM 4) WHERE mobile_number ~ '^\+861[3-9][0-9]{9}$';
+UPDATE employees SET mobile_number = substring(mobile_number FROM 4) WHERE mobile_number ~ '^\+861[3-9][0-9]{9}$';
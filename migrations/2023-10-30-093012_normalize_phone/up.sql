@@ -0,0 +1,3 @@
+-- 手机号统一保存为 E.164 格式，之前保存的都是不带国家码的大陆手机号
+UPDATE users SET mobile_number = '+86' || mobile_number WHERE mobile_number ~ '^1[3-9][0-9]{9}$';
+UPDATE employees SET mobile_number = '+86' || mobile_number WHERE mobile_number ~ '^1[3-9][0-9]{9}$';
@@ -0,0 +1,13 @@
+-- 第三方登录的账号，一个用户可以关联多个第三方账号
+CREATE TABLE provider_identities(
+    provider VARCHAR(32) NOT NULL,
+    subject VARCHAR NOT NULL,
+    user_id BIGINT NOT NULL,
+    -- 关联时第三方账号的已验证邮箱，只用于展示
+    email VARCHAR,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX provider_identities_user_id ON provider_identities(user_id);
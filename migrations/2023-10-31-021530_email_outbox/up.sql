@@ -0,0 +1,24 @@
+-- 待发送的邮件，由后台任务发送，失败后按退避时间重试
+CREATE TABLE email_outbox(
+    id BIGSERIAL NOT NULL,
+    to_addr VARCHAR NOT NULL,
+    subject VARCHAR NOT NULL,
+    -- 发送成功后清空，验证码等内容不再保留
+    body TEXT NOT NULL,
+
+    -- 0 等待发送，1 已发送，2 多次重试或过期后放弃
+    status smallint NOT NULL DEFAULT 0,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    -- 超过这个时间还没有发送成功时放弃，如验证码邮件
+    expire_at TIMESTAMPTz,
+    last_error TEXT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX email_outbox_pending ON email_outbox(next_attempt_at) WHERE status = 0;
+
+SELECT diesel_manage_updated_at('email_outbox');
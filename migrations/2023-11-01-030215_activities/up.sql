@@ -0,0 +1,19 @@
+-- 用户的动态，由领域事件的订阅者写入，只追加不修改
+CREATE TABLE activities(
+    id BIGSERIAL NOT NULL,
+    user_id BIGINT NOT NULL,
+
+    kind smallint NOT NULL,
+    message VARCHAR NOT NULL,
+    -- 相关文件的虚拟路径
+    file_path VARCHAR,
+    order_id BIGINT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX activities_user_id ON activities(user_id, id);
+
+COMMENT ON TABLE activities IS '用户动态';
+COMMENT ON COLUMN activities.kind IS '动态类型：0 上传文件，1 转码订单结束，2 文件被分享';
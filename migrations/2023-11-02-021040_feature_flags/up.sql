@@ -0,0 +1,17 @@
+-- 功能开关，用于逐步开放有风险的新功能
+CREATE TABLE feature_flags(
+    name VARCHAR NOT NULL,
+    description VARCHAR NOT NULL DEFAULT '',
+    -- 关闭时对所有用户都不生效
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    -- 按用户灰度的百分比，0 到 100
+    rollout_percent smallint NOT NULL DEFAULT 0,
+    -- 不受灰度比例限制，总是开启的用户
+    user_ids BIGINT[] NOT NULL DEFAULT '{}',
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (name)
+);
+
+SELECT diesel_manage_updated_at('feature_flags');
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE org_members;
+DROP TABLE organizations;
@@ -0,0 +1,33 @@
+CREATE TABLE organizations(
+    id BIGINT NOT NULL,
+    name VARCHAR NOT NULL,
+    -- 共享空间的账号，文件、配额和积分都记在这个账号上
+    space_id BIGINT NOT NULL,
+    -- 共享空间的存储配额（byte），为空时按空间账号的等级
+    storage_quota BIGINT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX organizations_space_id ON organizations(space_id);
+
+SELECT diesel_manage_updated_at('organizations');
+
+CREATE TABLE org_members(
+    org_id BIGINT NOT NULL,
+    user_id BIGINT NOT NULL,
+    role smallint NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, user_id)
+);
+
+CREATE INDEX org_members_user_id ON org_members(user_id);
+
+SELECT diesel_manage_updated_at('org_members');
+
+COMMENT ON TABLE organizations IS '组织';
+COMMENT ON COLUMN org_members.role IS '成员角色：0 所有者，1 管理员，2 普通成员';
@@ -0,0 +1,17 @@
+CREATE TABLE folder_shares(
+    dir_id BIGINT NOT NULL,
+    member_id BIGINT NOT NULL,
+    -- 冗余文件夹的所有者，按所有者查询成员被授权的目录
+    owner_id BIGINT NOT NULL,
+    permission smallint NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (dir_id, member_id)
+);
+
+CREATE INDEX folder_shares_member_owner ON folder_shares(member_id, owner_id);
+
+SELECT diesel_manage_updated_at('folder_shares');
+
+COMMENT ON COLUMN folder_shares.permission IS '成员的权限：0 只读，1 读写';
@@ -0,0 +1,12 @@
+CREATE TABLE file_edit_locks(
+    user_file_id BIGINT PRIMARY KEY,
+    holder_id BIGINT NOT NULL,
+    expire_at TIMESTAMPTz NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW()
+);
+
+SELECT diesel_manage_updated_at('file_edit_locks');
+
+COMMENT ON TABLE file_edit_locks IS '协作编辑时的文件锁，过期的锁不再生效，下次加锁时覆盖';
@@ -0,0 +1,43 @@
+CREATE TABLE publications(
+    id BIGINT NOT NULL,
+    -- 公开页面和播放地址中使用的短链接
+    slug VARCHAR NOT NULL,
+    user_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    title VARCHAR NOT NULL,
+    view_count BIGINT NOT NULL DEFAULT 0,
+    taken_down BOOLEAN NOT NULL DEFAULT FALSE,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX publications_slug ON publications(slug);
+CREATE UNIQUE INDEX publications_user_file_id ON publications(user_file_id);
+CREATE INDEX publications_user_id ON publications(user_id);
+
+SELECT diesel_manage_updated_at('publications');
+
+CREATE TABLE publication_reports(
+    id BIGINT NOT NULL,
+    publication_id BIGINT NOT NULL,
+    -- 未登录的访客举报时为空
+    reporter_id BIGINT,
+    reason TEXT NOT NULL,
+    state smallint NOT NULL DEFAULT 0,
+    -- 处理举报的管理员
+    handled_by BIGINT,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX publication_reports_pending ON publication_reports(create_at) WHERE state = 0;
+CREATE INDEX publication_reports_publication_id ON publication_reports(publication_id);
+
+SELECT diesel_manage_updated_at('publication_reports');
+
+COMMENT ON TABLE publications IS '公开发布的视频，用户取消发布时删除，被管理员下架的保留以禁止再次发布';
+COMMENT ON COLUMN publication_reports.state IS '处理状态：0 待处理，1 驳回，2 已下架';
@@ -0,0 +1,22 @@
+-- 文件的变更记录，与文件操作在同一事务中写入，只追加不修改
+CREATE TABLE file_changes(
+    id BIGSERIAL NOT NULL,
+    -- 文件所有者
+    user_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+
+    kind smallint NOT NULL,
+    is_dir BOOLEAN NOT NULL,
+    -- 变更后的虚拟路径，删除时为删除前的路径
+    path VARCHAR NOT NULL,
+    -- 重命名和移动前的虚拟路径
+    old_path VARCHAR,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX file_changes_user_id ON file_changes(user_id, id);
+
+COMMENT ON TABLE file_changes IS '文件变更记录，用于客户端增量同步';
+COMMENT ON COLUMN file_changes.kind IS '变更类型：0 创建，1 修改内容，2 重命名，3 移动，4 删除';
@@ -0,0 +1,16 @@
+-- 文件重命名和移动前的路径，旧链接据此找到文件现在的位置
+CREATE TABLE path_history(
+    id BIGSERIAL NOT NULL,
+    -- 文件所有者
+    user_id BIGINT NOT NULL,
+    user_file_id BIGINT NOT NULL,
+    old_path VARCHAR NOT NULL,
+    new_path VARCHAR NOT NULL,
+
+    create_at TIMESTAMPTz NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX path_history_old_path ON path_history(user_id, old_path);
+
+COMMENT ON TABLE path_history IS '文件路径的变更历史';
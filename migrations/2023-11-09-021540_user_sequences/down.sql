@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX file_changes_user_seq;
+CREATE INDEX file_changes_user_id ON file_changes(user_id, id);
+ALTER TABLE file_changes DROP COLUMN seq;
+
+DROP TABLE user_sequences;
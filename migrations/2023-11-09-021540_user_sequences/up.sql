@@ -0,0 +1,19 @@
+-- 按用户递增的序号，取号时锁住所在行直到事务提交，同一用户的序号与提交顺序一致
+CREATE TABLE user_sequences(
+    user_id BIGINT NOT NULL,
+    name VARCHAR NOT NULL,
+    value BIGINT NOT NULL,
+    PRIMARY KEY (user_id, name)
+);
+
+-- 客户端按 seq 增量读取变更。已有记录的 seq 沿用 id，旧的游标仍然有效
+ALTER TABLE file_changes ADD COLUMN seq BIGINT;
+UPDATE file_changes SET seq = id;
+ALTER TABLE file_changes ALTER COLUMN seq SET NOT NULL;
+INSERT INTO user_sequences(user_id, name, value)
+    SELECT user_id, 'file_changes', MAX(seq) FROM file_changes GROUP BY user_id;
+
+DROP INDEX file_changes_user_id;
+CREATE UNIQUE INDEX file_changes_user_seq ON file_changes(user_id, seq);
+
+COMMENT ON TABLE user_sequences IS '按用户递增的序号';
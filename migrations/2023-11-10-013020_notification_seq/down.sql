@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX notifications_user_seq;
+ALTER TABLE notifications DROP COLUMN seq;
+DELETE FROM user_sequences WHERE name = 'notifications';
@@ -0,0 +1,13 @@
+-- 推送通知时按 seq 断点续传，同一用户的 seq 与提交顺序一致。已有通知按创建顺序编号
+ALTER TABLE notifications ADD COLUMN seq BIGINT;
+UPDATE notifications n SET seq = numbered.seq
+    FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY create_at, id) AS seq
+        FROM notifications
+    ) numbered
+    WHERE n.id = numbered.id;
+ALTER TABLE notifications ALTER COLUMN seq SET NOT NULL;
+INSERT INTO user_sequences(user_id, name, value)
+    SELECT user_id, 'notifications', MAX(seq) FROM notifications GROUP BY user_id;
+
+CREATE UNIQUE INDEX notifications_user_seq ON notifications(user_id, seq);
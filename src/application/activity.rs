@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use tracing::warn;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    application::event_bus,
+    domain::{
+        activity::{Activity, ActivityEvent},
+        event::{TranscodeOrderFinished, UserFileCreated},
+        user::user::UserId,
+    },
+    infrastructure::repo_activity,
+};
+
+/// 用户动态由领域事件生成，写入失败只影响时间线，不影响业务
+pub(crate) fn subscribe_events() {
+    event_bus::subscribe(
+        "file-uploaded-activity",
+        |event: Arc<UserFileCreated>| async move {
+            let user_id = event.user_path.user_id();
+            let file_path = event.user_path.to_str().into_owned();
+            record(user_id, ActivityEvent::FileUploaded { file_path }).await
+        },
+    );
+    event_bus::subscribe(
+        "order-finished-activity",
+        |event: Arc<TranscodeOrderFinished>| async move {
+            let order = &event.order;
+            let Some(first) = order.tasks().first() else {
+                warn!(order_id = %order.id(), "finished order has no task");
+                return Ok(());
+            };
+            let (ok_count, failed_count) = order.task_counts();
+            let event = ActivityEvent::OrderFinished {
+                order_id: *order.id(),
+                file_path: first.virtual_path().clone(),
+                ok_count,
+                failed_count,
+            };
+            record(*order.user_id(), event).await
+        },
+    );
+}
+
+pub(crate) async fn record(user_id: UserId, event: ActivityEvent) -> Result<()> {
+    let activity = Activity::new(user_id, event);
+    let conn = &mut pg_conn().await?;
+    repo_activity::save(&activity, conn).await
+}
@@ -0,0 +1,56 @@
+//! 员工敏感操作的审计日志
+
+use anyhow::Result;
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    domain::user::employee::EmployeeId,
+    infrastructure::repo_audit::{self, NewAuditLogPo},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum AuditAction {
+    /// 查看未脱敏的用户联系方式
+    UnmaskUser,
+    /// 导出用户列表
+    ExportUsers,
+    /// 修改用户等级
+    SetUserLevel,
+    /// 创建或修改功能开关
+    SaveFeatureFlag,
+    /// 删除功能开关
+    DeleteFeatureFlag,
+    /// 修改组织的存储配额
+    SetOrgQuota,
+    /// 处理公开视频的举报
+    ModerateVideo,
+}
+
+impl AuditAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::UnmaskUser => "unmask_user",
+            Self::ExportUsers => "export_users",
+            Self::SetUserLevel => "set_user_level",
+            Self::SaveFeatureFlag => "save_feature_flag",
+            Self::DeleteFeatureFlag => "delete_feature_flag",
+            Self::SetOrgQuota => "set_org_quota",
+            Self::ModerateVideo => "moderate_video",
+        }
+    }
+}
+
+pub async fn record<T: Serialize>(
+    employee_id: EmployeeId,
+    action: AuditAction,
+    detail: &T,
+) -> Result<()> {
+    let log = NewAuditLogPo {
+        employee_id,
+        action: action.as_str(),
+        detail: serde_json::to_string(detail)?,
+    };
+    let conn = &mut pg_conn().await?;
+    repo_audit::save(&log, conn).await
+}
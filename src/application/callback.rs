@@ -0,0 +1,263 @@
+//! 转码工厂的回调
+//!
+//! 收到回调时只保存到数据库，由后台任务处理。处理失败（如创建转码后的文件出错）时按退避时间重试，
+//! 超过重试次数后不再自动处理，管理员确认问题已修复后可以手动重放。
+//! 处理过程是幂等的：已结束的任务会忽略重复的结果，视频信息会被覆盖
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    cqrs::MillionTimestamp,
+    domain::user::webhook,
+    ensure_biz,
+    http::BizResult,
+    infrastructure::repo_callback::{
+        self, CallbackKind, CallbackPo, CallbackStatus, NewCallbackPo,
+    },
+    log_if_err,
+    settings::get_settings,
+};
+
+use super::{
+    file_system::video_info,
+    transcode::{self, TaskResult, TranscodeResult},
+};
+
+/// 回调处理配置
+#[derive(Debug, Deserialize)]
+pub struct CallbackCfg {
+    /// 没有新回调时轮询的间隔
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 超过此次数后放弃处理，等待人工重放
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试的等待时间，之后每次翻倍
+    #[serde(default = "default_base_backoff_secs")]
+    pub base_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// 处理期间的租约，超时未更新状态时可以被重新处理
+    #[serde(default = "default_lease_secs")]
+    pub lease_secs: u64,
+}
+
+impl Default for CallbackCfg {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: default_poll_interval_secs(),
+            max_attempts: default_max_attempts(),
+            base_backoff_secs: default_base_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            lease_secs: default_lease_secs(),
+        }
+    }
+}
+
+fn default_poll_interval_secs() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_base_backoff_secs() -> u64 {
+    5
+}
+
+fn default_max_backoff_secs() -> u64 {
+    1800
+}
+
+fn default_lease_secs() -> u64 {
+    300
+}
+
+/// 收到新回调时唤醒处理任务，不必等到下一次轮询
+static WAKE: Notify = Notify::const_new();
+
+pub async fn receive_transcode_done(result: &TranscodeResult) -> Result<()> {
+    receive(CallbackKind::TranscodeDone, result.task_id.0, result).await
+}
+
+pub async fn receive_file_parsed(result: &TaskResult<Option<String>>) -> Result<()> {
+    receive(CallbackKind::FileParsed, result.task_id.0, result).await
+}
+
+async fn receive<T: Serialize>(kind: CallbackKind, task_id: i64, result: &T) -> Result<()> {
+    let callback = NewCallbackPo {
+        kind: kind as i16,
+        task_id,
+        payload: serde_json::to_string(result)?,
+    };
+    let conn = &mut pg_conn().await?;
+    repo_callback::save(&callback, conn).await?;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// 启动后台处理任务
+pub fn spawn_processor() {
+    let cfg = &get_settings().callback;
+
+    info!(?cfg, "factory callback processor started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(process_due(cfg).await);
+            let _ =
+                tokio::time::timeout(Duration::from_secs(cfg.poll_interval_secs), WAKE.notified())
+                    .await;
+        }
+    });
+}
+
+async fn process_due(cfg: &CallbackCfg) -> Result<()> {
+    let callbacks = {
+        let conn = &mut pg_conn().await?;
+        repo_callback::due_callbacks(100, conn).await?
+    };
+
+    futures_util::stream::iter(callbacks)
+        .for_each_concurrent(8, |callback| async move {
+            log_if_err!(process_one(cfg, callback).await);
+        })
+        .await;
+    Ok(())
+}
+
+async fn process_one(cfg: &CallbackCfg, callback: CallbackPo) -> Result<()> {
+    let lease = Local::now() + chrono::Duration::seconds(cfg.lease_secs as i64);
+    {
+        let conn = &mut pg_conn().await?;
+        if !repo_callback::claim(&callback, lease, conn).await? {
+            return Ok(());
+        }
+    }
+
+    let result = handle(&callback).await;
+
+    let attempts = callback.attempts + 1;
+    let conn = &mut pg_conn().await?;
+    match result {
+        Ok(()) => {
+            repo_callback::update(
+                callback.id,
+                CallbackStatus::Processed,
+                attempts,
+                Local::now(),
+                None,
+                conn,
+            )
+            .await?;
+        }
+        Err(err) => {
+            let err = format!("{:#}", err);
+            let (id, task_id) = (callback.id, callback.task_id);
+            let status = if attempts as u32 >= cfg.max_attempts {
+                warn!(id, task_id, %err, "callback moved to dead letter");
+                CallbackStatus::Dead
+            } else {
+                warn!(id, task_id, %err, "process callback failed, will retry");
+                CallbackStatus::Pending
+            };
+            let delay = webhook::retry_delay(
+                attempts as u32,
+                Duration::from_secs(cfg.base_backoff_secs),
+                Duration::from_secs(cfg.max_backoff_secs),
+            );
+            repo_callback::update(
+                callback.id,
+                status,
+                attempts,
+                Local::now() + chrono::Duration::seconds(delay.as_secs() as i64),
+                Some(&err),
+                conn,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn handle(callback: &CallbackPo) -> Result<()> {
+    let kind = CallbackKind::from_i16(callback.kind)
+        .ok_or_else(|| anyhow!("unknown callback kind: {}", callback.kind))?;
+    match kind {
+        CallbackKind::TranscodeDone => {
+            let result: TranscodeResult = serde_json::from_str(&callback.payload)?;
+            transcode::task_done(result).await
+        }
+        CallbackKind::FileParsed => {
+            let result: TaskResult<Option<String>> = serde_json::from_str(&callback.payload)?;
+            match result.result {
+                Ok(parsed) => video_info::file_parsed(result.file_id.into(), parsed).await,
+                Err(err) => {
+                    warn!(%err, "parse video failed");
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StuckCallbackDto {
+    id: i64,
+    /// transcodeDone 或 fileParsed
+    kind: &'static str,
+    task_id: i64,
+    /// 是否已放弃自动重试
+    dead: bool,
+    attempts: i32,
+    last_error: Option<String>,
+    create_at: MillionTimestamp,
+}
+
+/// 已放弃或仍在重试中的回调
+pub async fn list_stuck(page: u32, page_size: u32) -> Result<Vec<StuckCallbackDto>> {
+    let conn = &mut pg_conn().await?;
+    let offset = page.saturating_sub(1) as i64 * page_size as i64;
+    let callbacks = repo_callback::find_stuck(offset, page_size as i64, conn).await?;
+    let callbacks = callbacks
+        .into_iter()
+        .map(|c| StuckCallbackDto {
+            id: c.id,
+            kind: match CallbackKind::from_i16(c.kind) {
+                Some(CallbackKind::TranscodeDone) => "transcodeDone",
+                Some(CallbackKind::FileParsed) => "fileParsed",
+                None => "unknown",
+            },
+            task_id: c.task_id,
+            dead: c.status == CallbackStatus::Dead as i16,
+            attempts: c.attempts,
+            last_error: c.last_error,
+            create_at: c.create_at.into(),
+        })
+        .collect();
+    Ok(callbacks)
+}
+
+pub enum ReplayCallbackErr {
+    NotFound,
+}
+
+/// 将回调重新放入处理队列
+pub async fn replay(id: i64) -> BizResult<(), ReplayCallbackErr> {
+    let conn = &mut pg_conn().await?;
+    let effected = repo_callback::replay(id, conn).await?;
+    ensure_biz!(effected.is_effected(), ReplayCallbackErr::NotFound);
+    WAKE.notify_one();
+    biz_ok!(())
+}
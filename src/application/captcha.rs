@@ -0,0 +1,68 @@
+//! 发送验证码和注册前的人机验证
+//!
+//! 同一个 ip 或同一个目标邮箱、手机号在一段时间内的请求超过免验证次数后，需要在请求头中带上人机验证的 token。
+//! 不同场景分别计数，校验服务请求失败时视为验证不通过
+
+use std::net::IpAddr;
+
+use tracing::warn;
+
+use crate::{
+    biz_ok, ensure_biz, ensure_exist, http::BizResult, infrastructure::captcha,
+    settings::get_settings,
+};
+
+pub enum CaptchaErr {
+    Required,
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, derive_more::Display)]
+pub enum Scene {
+    #[display(fmt = "register")]
+    Register,
+    #[display(fmt = "email_code")]
+    EmailCode,
+    #[display(fmt = "sms_code")]
+    SmsCode,
+}
+
+/// 记录一次请求，`target` 为接收验证码或注册的邮箱、手机号，ip 或目标超过免验证次数时校验 token
+pub async fn check(
+    scene: Scene,
+    ip: Option<IpAddr>,
+    target: &str,
+    token: Option<&str>,
+) -> BizResult<(), CaptchaErr> {
+    let cfg = &get_settings().captcha;
+    if !cfg.enable {
+        return biz_ok!(());
+    }
+
+    // 拿不到 ip 时每次都需要验证
+    let count = match ip {
+        Some(ip) => {
+            let scene = scene.to_string();
+            let target = format!("to:{}", target.trim().to_lowercase());
+            let by_ip = captcha::incr_count(&scene, &format!("ip:{ip}"), cfg.window_secs).await?;
+            let by_target = captcha::incr_count(&scene, &target, cfg.window_secs).await?;
+            by_ip.max(by_target)
+        }
+        None => u64::MAX,
+    };
+    if count <= cfg.free_attempts {
+        return biz_ok!(());
+    }
+
+    let token = ensure_exist!(token.filter(|t| !t.is_empty()), CaptchaErr::Required);
+    let ip = ip.map(|ip| ip.to_string());
+    let passed = match cfg.verify(token, ip.as_deref()).await {
+        Ok(passed) => passed,
+        Err(err) => {
+            warn!(?err, %scene, "verify captcha failed");
+            false
+        }
+    };
+    ensure_biz!(passed, CaptchaErr::Invalid);
+    biz_ok!(())
+}
@@ -0,0 +1,36 @@
+use serde::Deserialize;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    biz_ok,
+    domain::{transcode_order::pricing::Pricing, user::user::UserId},
+    ensure_exist,
+    http::BizResult,
+    infrastructure::repo_credit::{self, CreditKind},
+    settings::get_settings,
+};
+
+/// 转码计费配置，未开启时创建订单不扣除积分
+#[derive(Debug, Deserialize, Default)]
+pub struct CreditsCfg {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default)]
+    pub pricing: Pricing,
+}
+
+pub(crate) fn cfg() -> &'static CreditsCfg {
+    &get_settings().credits
+}
+
+pub enum RechargeErr {
+    UserNotFound,
+}
+
+/// 管理员为用户充值积分，返回充值后的余额
+pub async fn recharge(user_id: UserId, amount: u64) -> BizResult<i64, RechargeErr> {
+    let conn = &mut pg_conn().await?;
+    let balance = repo_credit::add(user_id, amount, CreditKind::Recharge, None, None, conn).await?;
+    let balance = ensure_exist!(balance, RechargeErr::UserNotFound);
+    biz_ok!(balance)
+}
@@ -1,9 +1,26 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use serde::Serialize;
+use tracing::warn;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
 use crate::{
+    application::event_bus,
     biz_ok,
-    domain::user::{Email, EmailFormatErr},
+    domain::{
+        event::{TranscodeOrderFinished, UserRegistered},
+        transcode_order::TranscocdeOrder,
+        user::{user::UserId, Email, EmailFormatErr},
+    },
     ensure_biz, ensure_exist,
     http::BizResult,
-    infrastructure::email::EmailCodeSender,
+    infrastructure::{
+        email::{self, EmailCodeSender, OrderEmail, WelcomeEmail},
+        email_template, repo_user,
+    },
+    settings::get_settings,
 };
 
 #[derive(derive_more::From)]
@@ -35,3 +52,117 @@ pub async fn verify_email_code(email: String, code: String) -> BizResult<bool, C
     let sent_code = ensure_exist!(sent_code, CheckEmailCodeErr::NoEmailCode).to_string();
     biz_ok!(sent_code == code)
 }
+
+pub(crate) fn subscribe_events() {
+    event_bus::subscribe(
+        "order-finished-email",
+        |event: Arc<TranscodeOrderFinished>| async move { notify_order_finished(&event.order).await },
+    );
+    event_bus::subscribe("welcome-email", |event: Arc<UserRegistered>| async move {
+        send_welcome(event.user_id).await
+    });
+}
+
+/// 注册成功后发送欢迎邮件，没有真实邮箱的用户不发送
+async fn send_welcome(user_id: UserId) -> Result<()> {
+    if !get_settings().welcome_email.enable {
+        return Ok(());
+    }
+
+    let conn = &mut pg_conn().await?;
+    let Some(user) = repo_user::find(user_id, conn).await? else {
+        warn!(%user_id, "user not found");
+        return Ok(());
+    };
+    if user.has_placeholder_email() {
+        return Ok(());
+    }
+
+    let email = WelcomeEmail {
+        user_name: user.name(),
+    };
+    email::send(&user.email().to_string(), &email, None).await
+}
+
+/// 订单结束后给用户发送邮件，用户退订时不发送
+async fn notify_order_finished(order: &TranscocdeOrder) -> Result<()> {
+    if !email::order_mailer_enabled() {
+        return Ok(());
+    }
+
+    let conn = &mut pg_conn().await?;
+    let Some(user) = repo_user::find(*order.user_id(), conn).await? else {
+        warn!(user_id = %order.user_id(), "user not found");
+        return Ok(());
+    };
+    if *user.order_email_opt_out() {
+        return Ok(());
+    }
+
+    let (ok_count, failed_count) = order.task_counts();
+    let email = OrderEmail {
+        to: user.email().to_string(),
+        user_name: user.name().to_string(),
+        order_id: order.id().to_string(),
+        ok_count,
+        failed_count,
+    };
+    if !email::enqueue_order_email(email) {
+        warn!(order_id = %order.id(), "enqueue order email failed");
+    }
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailTemplateDto {
+    name: String,
+    /// 标题和正文中用到的变量
+    variables: Vec<String>,
+}
+
+pub fn list_templates() -> Vec<EmailTemplateDto> {
+    email_template::list()
+        .into_iter()
+        .map(|t| EmailTemplateDto {
+            name: t.name.clone(),
+            variables: t.variables.iter().cloned().collect(),
+        })
+        .collect()
+}
+
+pub enum PreviewTemplateErr {
+    NotFound,
+    MissingVariables(Vec<String>),
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedEmailDto {
+    subject: String,
+    body: String,
+}
+
+/// 用给定的参数渲染模板，不发送
+pub fn preview_template(
+    name: &str,
+    params: HashMap<String, String>,
+) -> BizResult<RenderedEmailDto, PreviewTemplateErr> {
+    let template = ensure_exist!(email_template::get(name), PreviewTemplateErr::NotFound);
+    let missing = template
+        .variables
+        .iter()
+        .filter(|v| !params.contains_key(*v))
+        .cloned()
+        .collect::<Vec<_>>();
+    ensure_biz!(
+        missing.is_empty(),
+        PreviewTemplateErr::MissingVariables(missing)
+    );
+
+    let email = template.render(&params)?;
+    biz_ok!(RenderedEmailDto {
+        subject: email.subject,
+        body: email.body,
+    })
+}
@@ -0,0 +1,81 @@
+//! 进程内的领域事件总线
+//!
+//! 业务代码只负责发布事件，邮件、信息采集请求等附带的反应由启动时注册的订阅者处理，
+//! 增加新的反应时不需要修改业务代码。
+//! 每个订阅者在单独的任务中异步执行，出错时只记录日志，不影响发布者和其他订阅者。
+//! 事件不持久化，需要和业务数据一起提交的记录（站内通知、webhook 投递）仍在事务中写入
+
+use std::{
+    any::{Any, TypeId},
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use tracing::warn;
+
+use crate::domain::event::DomainEvent;
+
+use super::{activity, email, file_system::upload};
+
+type Handler =
+    Box<dyn Fn(Arc<dyn Any + Send + Sync>) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+struct Subscriber {
+    name: &'static str,
+    handler: Handler,
+}
+
+static SUBSCRIBERS: RwLock<BTreeMap<TypeId, Vec<Arc<Subscriber>>>> = RwLock::new(BTreeMap::new());
+
+/// 注册所有订阅者，需要在发布事件之前调用
+pub fn init() {
+    activity::subscribe_events();
+    email::subscribe_events();
+    upload::subscribe_events();
+}
+
+/// 订阅 E 类型的事件，name 用于日志
+pub fn subscribe<E, F, Fut>(name: &'static str, handler: F)
+where
+    E: DomainEvent,
+    F: Fn(Arc<E>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let handler: Handler = Box::new(move |event| {
+        let event = event.downcast::<E>().expect("event type mismatch");
+        Box::pin(handler(event))
+    });
+    SUBSCRIBERS
+        .write()
+        .unwrap()
+        .entry(TypeId::of::<E>())
+        .or_default()
+        .push(Arc::new(Subscriber { name, handler }));
+}
+
+/// 发布事件，立即返回，订阅者在后台执行。
+/// 在事务中产生的事件应该在事务提交后发布
+pub fn publish<E: DomainEvent>(event: E) {
+    let subscribers = match SUBSCRIBERS.read().unwrap().get(&TypeId::of::<E>()) {
+        Some(subscribers) => subscribers.clone(),
+        None => return,
+    };
+
+    let event: Arc<dyn Any + Send + Sync> = Arc::new(event);
+    for subscriber in subscribers {
+        let handle = (subscriber.handler)(event.clone());
+        tokio::spawn(async move {
+            if let Err(err) = handle.await {
+                warn!(
+                    ?err,
+                    event = E::NAME,
+                    subscriber = subscriber.name,
+                    "event subscriber failed"
+                );
+            }
+        });
+    }
+}
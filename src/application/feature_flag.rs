@@ -0,0 +1,137 @@
+//! 功能开关
+//!
+//! 业务代码通过 [`is_enabled`] 判断新功能是否对用户开放，开关在 redis 中缓存
+//! [`CACHE_TTL_SECS`] 秒，管理员修改后立即清除缓存
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utils::db_pools::postgres::{pg_conn, pg_conn_read};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        feature_flag::{FeatureFlag, FeatureFlagErr},
+        user::{employee::EmployeeId, user::UserId},
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::repo_feature_flag,
+    log_if_err,
+};
+
+use super::audit::{self, AuditAction};
+
+const CACHE_TTL_SECS: u64 = 60;
+
+/// 开关是否对用户生效，开关不存在时视为关闭。
+/// 缓存不可用时直接查询数据库
+pub async fn is_enabled(name: &str, user_id: Option<UserId>) -> Result<bool> {
+    let flag = match repo_feature_flag::cached(name).await {
+        Ok(Some(flag)) => flag,
+        Ok(None) => {
+            let flag = load(name).await?;
+            log_if_err!(repo_feature_flag::cache(name, flag.as_ref(), CACHE_TTL_SECS).await);
+            flag
+        }
+        Err(err) => {
+            warn!(?err, name, "read cached feature flag failed");
+            load(name).await?
+        }
+    };
+    Ok(flag.is_some_and(|f| f.is_enabled_for(user_id)))
+}
+
+async fn load(name: &str) -> Result<Option<FeatureFlag>> {
+    let conn = &mut pg_conn_read().await?;
+    repo_feature_flag::find(name, conn).await
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureFlagDto {
+    name: String,
+    description: String,
+    enabled: bool,
+    rollout_percent: u8,
+    user_ids: Vec<UserId>,
+}
+
+impl From<FeatureFlag> for FeatureFlagDto {
+    fn from(flag: FeatureFlag) -> Self {
+        Self {
+            name: flag.name().clone(),
+            description: flag.description().clone(),
+            enabled: *flag.enabled(),
+            rollout_percent: *flag.rollout_percent(),
+            user_ids: flag.user_ids().clone(),
+        }
+    }
+}
+
+pub async fn list() -> Result<Vec<FeatureFlagDto>> {
+    let conn = &mut pg_conn_read().await?;
+    let flags = repo_feature_flag::list(conn).await?;
+    Ok(flags.into_iter().map(Into::into).collect())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveFeatureFlagDto {
+    #[serde(default)]
+    description: String,
+    enabled: bool,
+    /// 灰度比例，0 到 100
+    rollout_percent: u8,
+    /// 总是生效的用户
+    #[serde(default)]
+    user_ids: Vec<UserId>,
+}
+
+#[derive(Serialize)]
+struct SaveFlagDetail<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    params: &'a SaveFeatureFlagDto,
+}
+
+/// 创建或修改开关
+pub async fn save(
+    employee_id: EmployeeId,
+    name: String,
+    params: SaveFeatureFlagDto,
+) -> BizResult<(), FeatureFlagErr> {
+    let flag = ensure_biz!(FeatureFlag::new(
+        name,
+        params.description.clone(),
+        params.enabled,
+        params.rollout_percent,
+        params.user_ids.clone(),
+    ));
+
+    let conn = &mut pg_conn().await?;
+    repo_feature_flag::save(&flag, conn).await?;
+    repo_feature_flag::uncache(flag.name()).await?;
+
+    let detail = SaveFlagDetail {
+        name: flag.name(),
+        params: &params,
+    };
+    audit::record(employee_id, AuditAction::SaveFeatureFlag, &detail).await?;
+    biz_ok!(())
+}
+
+pub enum DeleteFeatureFlagErr {
+    NotFound,
+}
+
+pub async fn delete(employee_id: EmployeeId, name: String) -> BizResult<(), DeleteFeatureFlagErr> {
+    let conn = &mut pg_conn().await?;
+    let found = repo_feature_flag::delete(&name, conn).await?;
+    ensure_biz!(found, DeleteFeatureFlagErr::NotFound);
+    repo_feature_flag::uncache(&name).await?;
+
+    audit::record(employee_id, AuditAction::DeleteFeatureFlag, &name).await?;
+    biz_ok!(())
+}
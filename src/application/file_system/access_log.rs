@@ -0,0 +1,83 @@
+//! 文件的访问记录
+//!
+//! 下载、预览时记录访问者和时间，文件所有者通过 graphql 的 UserFile.accessLog 查看，
+//! 可以知道分享出去的文件有没有被访问。记录保留 access_log_retention_days 天，由后台任务定期清理
+
+use std::time::Duration;
+
+use anyhow::Result;
+use async_graphql::Enum;
+use chrono::Local;
+use tracing::{debug, info};
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    infrastructure::repo_file_access::{self, NewAccessLogPo},
+    log_if_err,
+    settings::get_settings,
+};
+
+/// 清理过期记录的间隔
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// 下载
+    Download,
+    /// 预览
+    Preview,
+}
+
+impl AccessKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Preview => "preview",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "download" => Some(Self::Download),
+            "preview" => Some(Self::Preview),
+            _ => None,
+        }
+    }
+}
+
+/// 在后台记录一次访问，不影响正常请求。accessor 为空表示未登录的访问
+pub fn record(file_id: UserFileId, kind: AccessKind, accessor: Option<UserId>) {
+    tokio::spawn(async move {
+        let log = NewAccessLogPo {
+            user_file_id: file_id,
+            kind: kind.as_str(),
+            accessor_id: accessor,
+        };
+        let save = async {
+            let conn = &mut pg_conn().await?;
+            repo_file_access::save_log(&log, conn).await
+        };
+        log_if_err!(save.await);
+    });
+}
+
+/// 启动后台任务，定期删除超过保留天数的访问记录
+pub fn spawn_pruner() {
+    let days = get_settings().file_system.access_log_retention_days;
+    info!(days, "file access log pruner started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(prune(days).await);
+            tokio::time::sleep(PRUNE_INTERVAL).await;
+        }
+    });
+}
+
+async fn prune(days: u32) -> Result<()> {
+    let before = Local::now() - chrono::Duration::days(days as i64);
+    let conn = &mut pg_conn().await?;
+    let deleted = repo_file_access::delete_logs_before(before, conn).await?;
+    debug!(deleted, "pruned file access logs");
+    Ok(())
+}
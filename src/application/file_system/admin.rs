@@ -0,0 +1,260 @@
+//! 员工访问用户的文件
+//!
+//! 员工的权限由角色决定：配置中 read_only_roles 里的角色只能浏览、下载和预览，
+//! 调用修改类的接口时返回 ReadOnly。权限在这里检查，而不是在接口层，
+//! 这样新增的管理接口只要经过这个模块就不会绕过限制
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_err, biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::{
+            file::{FileOperateErr, UserFileId},
+            service::path_manager,
+            service_upload::{SliceLayout, UploadTaskId},
+        },
+        user::{employee::Role, user::UserId},
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{file_sys, repo_upload_task, repo_user_file},
+    settings::get_settings,
+};
+
+use super::{
+    service,
+    upload::{self, FinishUploadTaskErr, UploadedUserFile},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct AdminFsCfg {
+    /// 只能查看用户文件的角色
+    #[serde(default = "default_read_only_roles")]
+    pub read_only_roles: Vec<Role>,
+}
+
+impl Default for AdminFsCfg {
+    fn default() -> Self {
+        Self {
+            read_only_roles: default_read_only_roles(),
+        }
+    }
+}
+
+fn default_read_only_roles() -> Vec<Role> {
+    vec![Role::Employee]
+}
+
+/// 员工对用户文件的权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsCapability {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl FsCapability {
+    /// 未知的角色只能查看
+    pub fn of(role: Option<Role>) -> Self {
+        let cfg = &get_settings().file_system.admin;
+        match role {
+            Some(role) if !cfg.read_only_roles.contains(&role) => Self::ReadWrite,
+            _ => Self::ReadOnly,
+        }
+    }
+
+    pub fn writable(&self) -> bool {
+        matches!(self, Self::ReadWrite)
+    }
+}
+
+pub enum AdminFsErr {
+    ReadOnly,
+    Operate(FileOperateErr),
+}
+
+impl From<FileOperateErr> for AdminFsErr {
+    fn from(value: FileOperateErr) -> Self {
+        Self::Operate(value)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminFileEntry {
+    id: UserFileId,
+    name: String,
+    is_dir: bool,
+    create_at: MillionTimestamp,
+    last_modified: MillionTimestamp,
+}
+
+pub enum BrowseErr {
+    NotFound,
+    NotDir,
+}
+
+/// 列出用户目录下的文件，任何权限都可以调用
+pub async fn list_dir(
+    user_id: UserId,
+    dir_id: UserFileId,
+) -> BizResult<Vec<AdminFileEntry>, BrowseErr> {
+    let conn = &mut pg_conn_read().await?;
+    let dir = ensure_exist!(
+        repo_user_file::load_tree_dep2_for_read((user_id, dir_id), conn).await?,
+        BrowseErr::NotFound
+    );
+    let children = ensure_exist!(dir.children(), BrowseErr::NotDir);
+
+    let entries = children
+        .iter()
+        .map(|child| AdminFileEntry {
+            id: *child.id(),
+            name: child.file_name().to_string(),
+            is_dir: child.is_dir(),
+            create_at: (*child.create_at()).into(),
+            last_modified: (*child.updated_at()).into(),
+        })
+        .collect();
+    biz_ok!(entries)
+}
+
+pub async fn create_dir(
+    cap: FsCapability,
+    user_id: UserId,
+    parent_id: UserFileId,
+    name: &str,
+) -> BizResult<UserFileId, AdminFsErr> {
+    ensure_biz!(cap.writable(), AdminFsErr::ReadOnly);
+    let file_id = ensure_biz!(service::create_dir(user_id, parent_id, name).await?);
+    biz_ok!(file_id)
+}
+
+pub async fn delete(
+    cap: FsCapability,
+    user_id: UserId,
+    file_ids: Vec<UserFileId>,
+) -> BizResult<(), AdminFsErr> {
+    ensure_biz!(cap.writable(), AdminFsErr::ReadOnly);
+    ensure_biz!(service::delete(user_id, user_id, file_ids).await?);
+    biz_ok!(())
+}
+
+pub async fn copy_to(
+    cap: FsCapability,
+    user_id: UserId,
+    from: Vec<UserFileId>,
+    to: UserFileId,
+) -> BizResult<(), AdminFsErr> {
+    ensure_biz!(cap.writable(), AdminFsErr::ReadOnly);
+    ensure_biz!(service::copy_to(user_id, from, to).await?);
+    biz_ok!(())
+}
+
+pub async fn move_to(
+    cap: FsCapability,
+    user_id: UserId,
+    from: Vec<UserFileId>,
+    to: UserFileId,
+) -> BizResult<(), AdminFsErr> {
+    ensure_biz!(cap.writable(), AdminFsErr::ReadOnly);
+    ensure_biz!(service::move_to(user_id, user_id, from, to).await?);
+    biz_ok!(())
+}
+
+pub async fn rename(
+    cap: FsCapability,
+    user_id: UserId,
+    file_id: UserFileId,
+    new_name: &str,
+) -> BizResult<(), AdminFsErr> {
+    ensure_biz!(cap.writable(), AdminFsErr::ReadOnly);
+    ensure_biz!(service::rename(user_id, user_id, file_id, new_name).await?);
+    biz_ok!(())
+}
+
+/// 列出的上传任务的最大数量
+const MAX_LISTED_UPLOAD_TASKS: usize = 1000;
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUploadTaskDto {
+    id: UploadTaskId,
+    user_id: UserId,
+    dst_path: String,
+    completed: bool,
+    /// 升级前注册的任务没有以下两个字段
+    create_at: Option<MillionTimestamp>,
+    age_secs: Option<i64>,
+    /// 已写入磁盘的分片字节数
+    uploaded_bytes: u64,
+    /// 注册时声明的文件大小
+    file_size: Option<u64>,
+    total_slices: Option<u64>,
+    uploaded_slices: u32,
+}
+
+/// 所有用户未过期的上传任务，最早注册的在前，用于排查卡住的上传
+pub async fn list_upload_tasks(user_id: Option<UserId>) -> anyhow::Result<Vec<AdminUploadTaskDto>> {
+    let mut tasks = repo_upload_task::list_all(MAX_LISTED_UPLOAD_TASKS).await?;
+    tasks.retain(|t| user_id.map_or(true, |id| *t.user_id() == id));
+    tasks.sort_by_key(|t| *t.create_at());
+
+    let now = Local::now();
+    let mut dtos = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let slice_dir = path_manager().upload_slice_dir(*task.id());
+        // 完成后分片在后台删除，目录可能已经不存在
+        let uploaded_bytes = if task.is_completed() {
+            0
+        } else {
+            file_sys::slices_size(&slice_dir).await.unwrap_or_default()
+        };
+        dtos.push(AdminUploadTaskDto {
+            id: *task.id(),
+            user_id: *task.user_id(),
+            dst_path: task.path().to_str().into_owned(),
+            completed: task.is_completed(),
+            create_at: (*task.create_at()).map(Into::into),
+            age_secs: (*task.create_at()).map(|at| (now - at).num_seconds()),
+            uploaded_bytes,
+            file_size: task.layout().as_ref().map(|l| l.file_size),
+            total_slices: task.layout().as_ref().map(SliceLayout::slice_count),
+            uploaded_slices: task.uploaded_slices().len() as u32,
+        });
+    }
+    Ok(dtos)
+}
+
+pub enum AdminUploadErr {
+    ReadOnly,
+    NoTask,
+    Finish(FinishUploadTaskErr),
+}
+
+/// 删除任务和已上传的分片，释放磁盘空间
+pub async fn expire_upload_task(
+    cap: FsCapability,
+    task_id: UploadTaskId,
+) -> BizResult<(), AdminUploadErr> {
+    ensure_biz!(cap.writable(), AdminUploadErr::ReadOnly);
+    ensure_biz!(upload::expire_task(task_id).await?, AdminUploadErr::NoTask);
+    biz_ok!(())
+}
+
+/// 用已上传的分片完成任务，与用户调用完成上传的检查相同
+pub async fn complete_upload_task(
+    cap: FsCapability,
+    task_id: UploadTaskId,
+) -> BizResult<UploadedUserFile, AdminUploadErr> {
+    ensure_biz!(cap.writable(), AdminUploadErr::ReadOnly);
+    match upload::upload_finished(task_id).await? {
+        Ok(file) => biz_ok!(file),
+        Err(FinishUploadTaskErr::NoTask) => biz_err!(AdminUploadErr::NoTask),
+        Err(err) => biz_err!(AdminUploadErr::Finish(err)),
+    }
+}
@@ -0,0 +1,47 @@
+//! 文件所在位置的面包屑
+//!
+//! 搜索结果等不是从目录逐级打开的文件，客户端用上级目录链显示文件所在的位置
+
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    ensure_exist,
+    http::BizResult,
+    infrastructure::repo_user_file,
+};
+
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Ancestor {
+    id: UserFileId,
+    name: String,
+}
+
+pub enum AncestorsErr {
+    NotFound,
+}
+
+/// 文件的上级目录，从用户的根目录开始，到文件的直接上级目录为止
+pub async fn ancestors(
+    user_id: UserId,
+    file_id: UserFileId,
+) -> BizResult<Vec<Ancestor>, AncestorsErr> {
+    let conn = &mut pg_conn_read().await?;
+    ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, file_id), conn).await?,
+        AncestorsErr::NotFound
+    );
+    let ancestors = repo_user_file::ancestors(file_id, conn)
+        .await?
+        .into_iter()
+        .map(|a| Ancestor {
+            id: a.id,
+            name: a.file_name,
+        })
+        .collect();
+    biz_ok!(ancestors)
+}
@@ -0,0 +1,216 @@
+//! 批量操作：每一项在各自的保存点中执行，某一项的业务错误只回滚这一项，
+//! 磁盘上的操作在所有数据库操作完成后统一执行
+
+use anyhow::Result;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection};
+use futures_util::{StreamExt, TryStreamExt};
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{
+            file::{FileOperateErr, UserFileId, VirtualPath},
+            share::FileOp,
+        },
+        user::user::UserId,
+    },
+    http::BizResult,
+    infrastructure::{file_sys, repo_user_file},
+    tx_func,
+};
+
+use super::{disk_space::Reservation, service, share};
+
+/// 同时执行的磁盘操作数
+const DISK_CONCURRENCY: usize = 8;
+
+/// 每一项的处理结果，与请求中的顺序一致
+pub type BulkResult = Vec<(UserFileId, Result<(), FileOperateErr>)>;
+
+/// 某一项被业务规则拒绝，用于回滚该项的保存点
+#[derive(derive_more::Display, Debug)]
+#[display(fmt = "item rejected: {_0:?}")]
+struct Rejected(FileOperateErr);
+
+impl std::error::Error for Rejected {}
+
+enum DiskOp {
+    Delete(VirtualPath),
+    Move(VirtualPath, VirtualPath),
+    /// 复制完成前一直持有登记的磁盘空间
+    Copy(VirtualPath, VirtualPath, Reservation),
+}
+
+impl DiskOp {
+    fn paths(&self) -> Vec<&VirtualPath> {
+        match self {
+            DiskOp::Delete(path) => vec![path],
+            DiskOp::Move(from, to) | DiskOp::Copy(from, to, _) => vec![from, to],
+        }
+    }
+
+    async fn run(&self) -> Result<()> {
+        match self {
+            DiskOp::Delete(path) => file_sys::virtual_delete(path).await,
+            DiskOp::Move(from, to) => file_sys::virtual_move(from, to).await,
+            DiskOp::Copy(from, to, _) => file_sys::virtual_copy(from, to).await,
+        }
+    }
+}
+
+/// 涉及的路径互不包含时并发执行，否则（如同时移动了目录和其中的文件）按顺序执行
+async fn run_disk_ops(ops: Vec<DiskOp>) -> Result<()> {
+    let overlapped =
+        |a: &VirtualPath, b: &VirtualPath| a.relative_to(b).is_some() || b.relative_to(a).is_some();
+    let paths: Vec<_> = ops.iter().flat_map(|op| op.paths()).collect();
+    let independent = paths
+        .iter()
+        .enumerate()
+        .all(|(i, a)| paths[i + 1..].iter().all(|b| !overlapped(a, b)));
+
+    if independent {
+        futures_util::stream::iter(ops.iter().map(|op| op.run()))
+            .buffer_unordered(DISK_CONCURRENCY)
+            .try_collect::<()>()
+            .await
+    } else {
+        for op in &ops {
+            op.run().await?;
+        }
+        Ok(())
+    }
+}
+
+/// 保存点中的错误：业务错误作为该项的结果，其他错误终止整个批量操作
+fn into_item_result(
+    result: Result<DiskOp>,
+    ops: &mut Vec<DiskOp>,
+) -> Result<Result<(), FileOperateErr>> {
+    match result {
+        Ok(op) => {
+            ops.push(op);
+            Ok(Ok(()))
+        }
+        Err(err) => match err.downcast::<Rejected>() {
+            Ok(Rejected(err)) => Ok(Err(err)),
+            Err(err) => Err(err),
+        },
+    }
+}
+
+/// 在保存点中执行一项操作，`$body` 的结果为 `BizResult<DiskOp, FileOperateErr>`
+macro_rules! savepoint {
+    ($conn:ident, $body:expr) => {
+        $conn
+            .transaction::<_, anyhow::Error, _>(|$conn| {
+                async move {
+                    let item: BizResult<DiskOp, FileOperateErr> = $body;
+                    match item {
+                        Ok(Ok(op)) => Ok(op),
+                        Ok(Err(err)) => Err(anyhow::Error::from(Rejected(err))),
+                        Err(err) => Err(err),
+                    }
+                }
+                .scope_boxed()
+            })
+            .await
+    };
+}
+
+/// member_id 为实际操作的用户，用于检查文件锁
+pub async fn delete(
+    user_id: UserId,
+    member_id: UserId,
+    file_ids: Vec<UserFileId>,
+) -> Result<BulkResult> {
+    tx_func!(delete_tx, user_id, member_id, file_ids)
+}
+
+async fn delete_tx(
+    user_id: UserId,
+    member_id: UserId,
+    file_ids: Vec<UserFileId>,
+    conn: &mut PgConn,
+) -> Result<BulkResult> {
+    let mut ops = vec![];
+    let mut results = vec![];
+    for file_id in file_ids {
+        let result = savepoint!(
+            conn,
+            service::delete_one(user_id, member_id, file_id, conn)
+                .await
+                .map(|path| path.map(DiskOp::Delete))
+        );
+        results.push((file_id, into_item_result(result, &mut ops)?));
+    }
+    run_disk_ops(ops).await?;
+
+    Ok(results)
+}
+
+pub enum BulkMoveErr {
+    ParentNotFound,
+}
+
+pub async fn move_to(
+    user_id: UserId,
+    member_id: UserId,
+    file_ids: Vec<UserFileId>,
+    new_parent_id: UserFileId,
+) -> Result<Result<BulkResult, BulkMoveErr>> {
+    tx_func!(
+        move_to_tx,
+        user_id,
+        member_id,
+        file_ids,
+        new_parent_id,
+        false
+    )
+}
+
+/// 复制不修改原文件，不检查文件锁
+pub async fn copy_to(
+    user_id: UserId,
+    file_ids: Vec<UserFileId>,
+    new_parent_id: UserFileId,
+) -> Result<Result<BulkResult, BulkMoveErr>> {
+    tx_func!(move_to_tx, user_id, user_id, file_ids, new_parent_id, true)
+}
+
+async fn move_to_tx(
+    user_id: UserId,
+    member_id: UserId,
+    file_ids: Vec<UserFileId>,
+    new_parent_id: UserFileId,
+    copy: bool,
+    conn: &mut PgConn,
+) -> Result<Result<BulkResult, BulkMoveErr>> {
+    let Some(owner) = share::owner_for(user_id, new_parent_id, FileOp::Write, conn).await? else {
+        return Ok(Err(BulkMoveErr::ParentNotFound));
+    };
+    let Some(mut new_parent) = repo_user_file::load_tree((owner, new_parent_id), 2, conn).await?
+    else {
+        return Ok(Err(BulkMoveErr::ParentNotFound));
+    };
+
+    let mut ops = vec![];
+    let mut results = vec![];
+    for file_id in file_ids {
+        let new_parent = &mut new_parent;
+        let result = savepoint!(conn, {
+            if copy {
+                service::copy_one(user_id, file_id, new_parent, conn)
+                    .await
+                    .map(|r| r.map(|(from, to, reserved)| DiskOp::Copy(from, to, reserved)))
+            } else {
+                service::move_one(user_id, member_id, file_id, new_parent, conn)
+                    .await
+                    .map(|r| r.map(|(from, to)| DiskOp::Move(from, to)))
+            }
+        });
+        results.push((file_id, into_item_result(result, &mut ops)?));
+    }
+    run_disk_ops(ops).await?;
+
+    Ok(Ok(results))
+}
@@ -0,0 +1,171 @@
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    application::notification,
+    biz_err, biz_ok,
+    domain::{
+        file_system::{
+            comment::{CommentErr, FileComment, FileCommentId},
+            file::UserFileId,
+            share::FileOp,
+        },
+        notification::NotificationEvent,
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_comment, repo_user, repo_user_file},
+    pg_tx,
+};
+
+use super::share;
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCommentDto {
+    file_id: UserFileId,
+    #[validate(length(min = 1, max = 2000))]
+    content: String,
+    /// 评论针对的视频时间点（毫秒），为空表示针对整个文件
+    timecode_ms: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCommentResp {
+    comment_id: FileCommentId,
+}
+
+#[derive(Debug)]
+pub enum CreateCommentErr {
+    BadContent,
+    FileNotFound,
+}
+
+/// 评论有读权限的文件，评论者不是文件所有者时通知文件所有者
+pub async fn create_comment(
+    user_id: UserId,
+    params: CreateCommentDto,
+) -> BizResult<CreateCommentResp, CreateCommentErr> {
+    let (resp, notified) = match pg_tx!(create_comment_tx, user_id, params)? {
+        Ok(res) => res,
+        Err(err) => return biz_err!(err),
+    };
+    if let Some(owner) = notified {
+        notification::wake(owner);
+    }
+    biz_ok!(resp)
+}
+
+/// 同时返回收到通知的用户
+async fn create_comment_tx(
+    user_id: UserId,
+    params: CreateCommentDto,
+    conn: &mut PgConn,
+) -> BizResult<(CreateCommentResp, Option<UserId>), CreateCommentErr> {
+    use CreateCommentErr::*;
+
+    // 有读权限即可评论，包括共享给自己的文件
+    let owner = ensure_exist!(
+        share::owner_for(user_id, params.file_id, FileOp::Read, conn).await?,
+        FileNotFound
+    );
+    let file = ensure_exist!(
+        repo_user_file::find_node((owner, params.file_id), conn).await?,
+        FileNotFound
+    );
+
+    let Ok(comment) = FileComment::create(*file.id(), user_id, params.content, params.timecode_ms)
+    else {
+        return biz_err!(BadContent);
+    };
+    repo_comment::save(&comment, conn).await?;
+
+    let notified = (owner != user_id).then_some(owner);
+    if notified.is_some() {
+        let author = repo_user::find(user_id, conn).await?;
+        let event = NotificationEvent::FileCommented {
+            file_id: *file.id(),
+            file_name: file.file_name().to_string(),
+            author_name: author.map(|a| a.name().to_string()).unwrap_or_default(),
+        };
+        notification::notify(owner, event, conn).await?;
+    }
+
+    let resp = CreateCommentResp {
+        comment_id: *comment.id(),
+    };
+    biz_ok!((resp, notified))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateCommentDto {
+    comment_id: FileCommentId,
+    #[validate(length(min = 1, max = 2000))]
+    content: String,
+}
+
+#[derive(From, Debug)]
+pub enum UpdateCommentErr {
+    Comment(CommentErr),
+    NotFound,
+}
+
+/// 修改评论内容，只有作者可以修改
+pub async fn update_comment(
+    user_id: UserId,
+    params: UpdateCommentDto,
+) -> BizResult<(), UpdateCommentErr> {
+    pg_tx!(update_comment_tx, user_id, params)
+}
+
+async fn update_comment_tx(
+    user_id: UserId,
+    params: UpdateCommentDto,
+    conn: &mut PgConn,
+) -> BizResult<(), UpdateCommentErr> {
+    let mut comment = ensure_exist!(
+        repo_comment::find(params.comment_id, conn).await?,
+        UpdateCommentErr::NotFound
+    );
+    ensure_biz!(comment.edit(user_id, params.content));
+    repo_comment::update(&comment, conn).await?;
+
+    biz_ok!(())
+}
+
+pub enum DeleteCommentErr {
+    NotFound,
+    NotAllowed,
+}
+
+/// 删除评论，作者和文件所有者都可以删除
+pub async fn delete_comment(
+    user_id: UserId,
+    comment_id: FileCommentId,
+) -> BizResult<(), DeleteCommentErr> {
+    pg_tx!(delete_comment_tx, user_id, comment_id)
+}
+
+async fn delete_comment_tx(
+    user_id: UserId,
+    comment_id: FileCommentId,
+    conn: &mut PgConn,
+) -> BizResult<(), DeleteCommentErr> {
+    use DeleteCommentErr::*;
+
+    let comment = ensure_exist!(repo_comment::find(comment_id, conn).await?, NotFound);
+    if *comment.author_id() != user_id {
+        let file = repo_user_file::find_node(*comment.user_file_id(), conn).await?;
+        let is_owner = file.is_some_and(|f| *f.user_id() == user_id);
+        ensure_biz!(is_owner, NotAllowed);
+    }
+    repo_comment::delete(comment_id, conn).await?;
+
+    biz_ok!(())
+}
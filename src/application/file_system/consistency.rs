@@ -0,0 +1,230 @@
+//! 数据库与磁盘的一致性检查
+//!
+//! 异常退出后，用户文件的记录和 user-space 中的目录、软链接可能不一致。逐个用户对比：
+//! - 有记录但磁盘上缺少目录或软链接：归档文件还在时可以自动修复
+//! - 记录引用的归档文件已丢失：只报告
+//! - 磁盘上指向不存在的文件的软链接，以及没有记录的路径：只报告
+//!
+//! 启动时的检查在后台运行，结果写入日志；员工可以通过接口按需检查
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::{
+            file::{FileNode, FileType, UserFileId},
+            service::{path_manager, PathManager},
+        },
+        user::user::UserId,
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{
+        file_sys::{self, DiskEntryKind},
+        repo_quarantine, repo_user_file,
+    },
+    settings::get_settings,
+};
+
+use super::admin::{AdminFsErr, FsCapability};
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ConsistencyCfg {
+    /// 启动时在后台检查
+    #[serde(default)]
+    pub on_startup: bool,
+    /// 启动时的检查是否自动修复
+    #[serde(default)]
+    pub repair: bool,
+}
+
+#[derive(Serialize, ToSchema, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsistencyReport {
+    pub checked_users: u32,
+    /// 磁盘上缺少目录或软链接的用户文件
+    pub missing_on_disk: Vec<Inconsistency>,
+    /// 归档文件已丢失的用户文件
+    pub missing_archive: Vec<Inconsistency>,
+    /// 指向不存在的文件的软链接，磁盘路径
+    pub dangling_links: Vec<String>,
+    /// 没有对应记录的磁盘路径
+    pub untracked: Vec<String>,
+    /// 已修复的数量
+    pub repaired: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Inconsistency {
+    pub user_file_id: UserFileId,
+    pub path: String,
+}
+
+impl Inconsistency {
+    fn of(node: &FileNode) -> Self {
+        Self {
+            user_file_id: *node.id(),
+            path: node.path().to_str().to_string(),
+        }
+    }
+}
+
+/// 启动时按配置在后台检查
+pub fn spawn_startup_check() {
+    let cfg = &get_settings().file_system.consistency;
+    if !cfg.on_startup {
+        return;
+    }
+
+    tokio::spawn(async move {
+        info!(
+            repair = cfg.repair,
+            "checking consistency between db and disk"
+        );
+        match check_all(cfg.repair).await {
+            Ok(report) => log_report(&report),
+            Err(err) => warn!(?err, "consistency check failed"),
+        }
+    });
+}
+
+fn log_report(report: &ConsistencyReport) {
+    let consistent = report.missing_on_disk.is_empty()
+        && report.missing_archive.is_empty()
+        && report.dangling_links.is_empty()
+        && report.untracked.is_empty();
+    if consistent {
+        info!(users = report.checked_users, "db and disk are consistent");
+        return;
+    }
+    for item in &report.missing_archive {
+        warn!(id = %item.user_file_id, path = %item.path, "archived file missing");
+    }
+    for path in &report.dangling_links {
+        warn!(%path, "dangling link");
+    }
+    warn!(
+        users = report.checked_users,
+        missing_on_disk = report.missing_on_disk.len(),
+        missing_archive = report.missing_archive.len(),
+        dangling_links = report.dangling_links.len(),
+        untracked = report.untracked.len(),
+        repaired = report.repaired,
+        "db and disk are inconsistent"
+    );
+}
+
+/// 检查所有用户，repair 为 true 时修复缺少的目录和软链接
+pub async fn check(cap: FsCapability, repair: bool) -> BizResult<ConsistencyReport, AdminFsErr> {
+    ensure_biz!(!repair || cap.writable(), AdminFsErr::ReadOnly);
+    biz_ok!(check_all(repair).await?)
+}
+
+async fn check_all(repair: bool) -> Result<ConsistencyReport> {
+    let mut report = ConsistencyReport::default();
+    let conn = &mut pg_conn().await?;
+
+    let owners = repo_user_file::home_owners(conn).await?;
+    let homes: HashSet<_> = owners
+        .iter()
+        .map(|user_id| path_manager().user_home(*user_id))
+        .collect();
+    for user_id in owners {
+        check_user(user_id, repair, &mut report, conn).await?;
+        report.checked_users += 1;
+    }
+
+    // 没有记录的家目录
+    for name in file_sys::child_file_names(path_manager().user_space()).await? {
+        let home = path_manager().user_space().join(&name);
+        if !homes.contains(&home) {
+            report.untracked.push(home.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(report)
+}
+
+async fn check_user(
+    user_id: UserId,
+    repair: bool,
+    report: &mut ConsistencyReport,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let mut nodes = repo_user_file::load_user_entries(user_id, conn).await?;
+    // 先处理上级目录，修复时软链接所在的目录已经存在
+    nodes.sort_by_key(|node| node.path().to_str().len());
+
+    let sys_ids: Vec<_> = nodes
+        .iter()
+        .filter_map(|node| match node.file_type() {
+            FileType::LazyFile(id) => Some(*id),
+            _ => None,
+        })
+        .collect();
+    let sys_files: HashMap<_, _> = repo_user_file::find_sys_files(&sys_ids, conn)
+        .await?
+        .into_iter()
+        .map(|file| (file.id, file))
+        .collect();
+    let hashes: Vec<_> = sys_files.values().map(|file| file.hash.as_str()).collect();
+    let quarantined = repo_quarantine::filter_quarantined(&hashes).await?;
+
+    let mut expected = HashSet::new();
+    for node in &nodes {
+        let disk_path = PathManager::virtual_to_disk(node.path());
+        let exists = file_sys::entry_exists(&disk_path).await?;
+        expected.insert(disk_path);
+
+        let FileType::LazyFile(sys_id) = node.file_type() else {
+            if !exists {
+                report.missing_on_disk.push(Inconsistency::of(node));
+                if repair {
+                    file_sys::create_dir(node.path()).await?;
+                    report.repaired += 1;
+                }
+            }
+            continue;
+        };
+
+        let Some(file) = sys_files.get(sys_id) else {
+            report.missing_archive.push(Inconsistency::of(node));
+            continue;
+        };
+        if !file_sys::entry_exists(&file.archived_path).await? {
+            report.missing_archive.push(Inconsistency::of(node));
+            continue;
+        }
+        // 被隔离的内容本来就没有软链接
+        if !exists && !quarantined.contains(&file.hash) {
+            report.missing_on_disk.push(Inconsistency::of(node));
+            if repair {
+                file_sys::create_user_link(&file.archived_path, node.path()).await?;
+                report.repaired += 1;
+            }
+        }
+    }
+
+    let home = path_manager().user_home(user_id);
+    for entry in file_sys::walk_dir(&home).await? {
+        let path = entry.path.to_string_lossy().to_string();
+        if !expected.contains(&entry.path) {
+            report.untracked.push(path);
+            continue;
+        }
+        if let DiskEntryKind::Link(target) = &entry.kind {
+            if !file_sys::entry_exists(target).await? {
+                report.dangling_links.push(path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+//! 目录列表缓存
+//!
+//! 不带过滤条件的目录列表按 (用户, 目录, 页) 缓存在 redis 中，用户的文件发生创建、移动、删除、
+//! 重命名时在事务提交后整体失效。redis 出错时直接查询数据库，不影响正常请求
+
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    infrastructure::repo_dir_cache,
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DirCacheCfg {
+    #[serde(default)]
+    pub enable: bool,
+    /// 缓存的过期时间，也是数据变化后最多可能读到旧数据的时间
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for DirCacheCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    10
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// 进程启动以来的命中情况
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DirCacheStats {
+    enabled: bool,
+    hits: u64,
+    misses: u64,
+}
+
+pub fn stats() -> DirCacheStats {
+    DirCacheStats {
+        enabled: get_settings().file_system.dir_cache.enable,
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
+
+/// 读取一页目录列表，没有缓存时调用 load 并缓存结果
+pub(crate) async fn get_or_load<T, F, Fut>(
+    user_id: UserId,
+    dir_id: UserFileId,
+    page: u32,
+    page_size: u32,
+    load: F,
+) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let cfg = &get_settings().file_system.dir_cache;
+    if !cfg.enable {
+        return load().await;
+    }
+
+    let key = match repo_dir_cache::page_key(user_id, dir_id, page, page_size).await {
+        Ok(key) => key,
+        Err(err) => {
+            warn!(?err, "dir cache unavailable");
+            return load().await;
+        }
+    };
+    match repo_dir_cache::get(&key).await {
+        Ok(Some(cached)) => match serde_json::from_str(&cached) {
+            Ok(value) => {
+                HITS.fetch_add(1, Ordering::Relaxed);
+                return Ok(value);
+            }
+            Err(err) => warn!(?err, %key, "bad dir cache"),
+        },
+        Ok(None) => {}
+        Err(err) => warn!(?err, "dir cache unavailable"),
+    }
+
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let value = load().await?;
+    let json = serde_json::to_string(&value)?;
+    if let Err(err) = repo_dir_cache::set(&key, &json, cfg.ttl_secs).await {
+        warn!(?err, "save dir cache failed");
+    }
+    Ok(value)
+}
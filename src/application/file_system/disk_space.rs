@@ -0,0 +1,92 @@
+//! 磁盘空间检查
+//!
+//! 合并分片、复制文件、创建转码任务前检查目标磁盘的可用空间，扣除 reserve 后放不下时拒绝操作，
+//! 避免写满磁盘后留下不完整的文件。进行中的操作在账本中登记将要写入的字节数，
+//! 并发的操作不会都以为空间足够
+
+use std::{collections::BTreeMap, path::Path, sync::Mutex};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    infrastructure::file_sys::{self, DeviceId},
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct DiskSpaceCfg {
+    /// 每个磁盘至少保留的空闲字节数
+    #[serde(default = "default_reserve")]
+    pub reserve: u64,
+}
+
+impl Default for DiskSpaceCfg {
+    fn default() -> Self {
+        Self {
+            reserve: default_reserve(),
+        }
+    }
+}
+
+fn default_reserve() -> u64 {
+    1024 * 1024 * 1024 * 10
+}
+
+/// 进行中的操作在各个磁盘上登记的字节数
+static LEDGER: Mutex<BTreeMap<DeviceId, u64>> = Mutex::new(BTreeMap::new());
+
+/// 登记的空间，操作完成后 drop 释放
+#[derive(Debug)]
+pub struct Reservation {
+    disks: Vec<(DeviceId, u64)>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut ledger = LEDGER.lock().unwrap();
+        for (device, bytes) in self.disks.drain(..) {
+            if let Some(reserved) = ledger.get_mut(&device) {
+                *reserved = reserved.saturating_sub(bytes);
+                if *reserved == 0 {
+                    ledger.remove(&device);
+                }
+            }
+        }
+    }
+}
+
+/// 在各个路径所在的磁盘上登记 bytes 字节，同一个磁盘只登记一次。任一磁盘空间不足时返回 None
+pub(crate) fn reserve(paths: &[&Path], bytes: u64) -> Result<Option<Reservation>> {
+    let keep_free = get_settings().file_system.disk_space.reserve;
+
+    let mut disks: Vec<(DeviceId, u64, &Path)> = vec![];
+    for path in paths {
+        let (device, available) = file_sys::disk_space(path)?;
+        if disks.iter().all(|(d, ..)| *d != device) {
+            disks.push((device, available, path));
+        }
+    }
+
+    let mut ledger = LEDGER.lock().unwrap();
+    for (device, available, path) in &disks {
+        let reserved = ledger.get(device).copied().unwrap_or_default();
+        let needed = bytes.saturating_add(reserved).saturating_add(keep_free);
+        if *available < needed {
+            warn!(?path, available, reserved, bytes, "insufficient disk space");
+            return Ok(None);
+        }
+    }
+
+    let disks: Vec<_> = disks.into_iter().map(|(d, ..)| (d, bytes)).collect();
+    for (device, bytes) in &disks {
+        *ledger.entry(device.clone()).or_default() += bytes;
+    }
+    Ok(Some(Reservation { disks }))
+}
+
+/// 只检查不登记，用于不在本服务写入的操作，如由 av1-factory 写入结果的转码任务
+pub(crate) fn is_enough(paths: &[&Path], bytes: u64) -> Result<bool> {
+    Ok(reserve(paths, bytes)?.is_some())
+}
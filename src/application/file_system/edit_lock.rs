@@ -0,0 +1,209 @@
+//! 协作编辑时的文件锁
+//!
+//! 重命名、移动、删除和覆盖上传前通过 [`check`] 和 [`check_tree`] 检查，
+//! 被其他用户锁定时返回 [`FileOperateErr::Locked`]。
+//! 权限按空间的所有者判断，锁的持有者记录实际操作的用户，组织空间中的成员共用空间的账号
+
+use chrono::Local;
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_err, biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::{
+            edit_lock::{self, EditLock, EditLockErr, FileLocked},
+            file::{FileNode, FileOperateErr, UserFileId},
+            share::FileOp,
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_edit_lock, repo_user, repo_user_file},
+    pg_tx,
+};
+
+use super::share;
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcquireLockDto {
+    file_id: UserFileId,
+    /// 有效期（秒），60 到 28800 之间
+    ttl_secs: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileLockDto {
+    file_id: UserFileId,
+    holder_id: UserId,
+    expire_at: MillionTimestamp,
+}
+
+impl From<EditLock> for FileLockDto {
+    fn from(value: EditLock) -> Self {
+        Self {
+            file_id: *value.user_file_id(),
+            holder_id: *value.holder_id(),
+            expire_at: (*value.expire_at()).into(),
+        }
+    }
+}
+
+#[derive(From, Debug)]
+pub enum AcquireLockErr {
+    Lock(EditLockErr),
+    FileNotFound,
+    NotFile,
+}
+
+/// 锁定文件，已经持有时续期。需要 user_id 对文件的写权限，锁由 member_id 持有
+pub async fn acquire(
+    user_id: UserId,
+    member_id: UserId,
+    params: AcquireLockDto,
+) -> BizResult<FileLockDto, AcquireLockErr> {
+    pg_tx!(acquire_tx, user_id, member_id, &params, false)
+}
+
+/// 抢占其他用户持有的锁
+pub async fn steal(
+    user_id: UserId,
+    member_id: UserId,
+    params: AcquireLockDto,
+) -> BizResult<FileLockDto, AcquireLockErr> {
+    pg_tx!(acquire_tx, user_id, member_id, &params, true)
+}
+
+async fn acquire_tx(
+    user_id: UserId,
+    member_id: UserId,
+    params: &AcquireLockDto,
+    steal: bool,
+    conn: &mut PgConn,
+) -> BizResult<FileLockDto, AcquireLockErr> {
+    use AcquireLockErr::*;
+
+    let file_id = params.file_id;
+    let permitted = share::owner_for(user_id, file_id, FileOp::Write, conn).await?;
+    ensure_biz!(permitted.is_some(), FileNotFound);
+    let file = ensure_exist!(
+        repo_user_file::find_node_for_read(file_id, conn).await?,
+        FileNotFound
+    );
+    ensure_biz!(not file.is_dir(), NotFile);
+
+    let now = Local::now();
+    let current = repo_edit_lock::find_for_update(file_id, conn).await?;
+    let lock = edit_lock::acquire(
+        current.as_ref(),
+        file_id,
+        member_id,
+        params.ttl_secs,
+        steal,
+        now,
+    );
+    let lock = match lock {
+        Ok(lock) => lock,
+        Err(err) => return biz_err!(name_holder(err, conn).await?.into()),
+    };
+    if current.is_some() {
+        repo_edit_lock::update(&lock, conn).await?;
+    } else if !repo_edit_lock::insert(&lock, conn).await? {
+        // 其他请求同时加锁并先插入了，按它的锁重新判断
+        let current = repo_edit_lock::find_for_update(file_id, conn).await?;
+        let lock = edit_lock::acquire(
+            current.as_ref(),
+            file_id,
+            member_id,
+            params.ttl_secs,
+            steal,
+            now,
+        );
+        let lock = match lock {
+            Ok(lock) => lock,
+            Err(err) => return biz_err!(name_holder(err, conn).await?.into()),
+        };
+        repo_edit_lock::update(&lock, conn).await?;
+        return biz_ok!(lock.into());
+    }
+
+    biz_ok!(lock.into())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseLockDto {
+    file_id: UserFileId,
+}
+
+/// 释放锁，只有持有者 member_id 可以释放
+pub async fn release(member_id: UserId, params: ReleaseLockDto) -> BizResult<(), EditLockErr> {
+    pg_tx!(release_tx, member_id, params.file_id)
+}
+
+async fn release_tx(
+    member_id: UserId,
+    file_id: UserFileId,
+    conn: &mut PgConn,
+) -> BizResult<(), EditLockErr> {
+    let current = repo_edit_lock::find_for_update(file_id, conn).await?;
+    if let Err(err) = edit_lock::release(current.as_ref(), member_id, Local::now()) {
+        return biz_err!(name_holder(err, conn).await?);
+    }
+    repo_edit_lock::delete(file_id, conn).await?;
+    biz_ok!(())
+}
+
+/// 在锁定错误中带上持有者的名字
+async fn name_holder(err: EditLockErr, conn: &mut PgConn) -> anyhow::Result<EditLockErr> {
+    match err {
+        EditLockErr::Locked(locked) => {
+            Ok(EditLockErr::Locked(with_holder_name(locked, conn).await?))
+        }
+        err => Ok(err),
+    }
+}
+
+async fn with_holder_name(mut locked: FileLocked, conn: &mut PgConn) -> anyhow::Result<FileLocked> {
+    let holder = repo_user::find(locked.holder_id, conn).await?;
+    locked.holder_name = holder.map(|user| user.name().to_string());
+    Ok(locked)
+}
+
+/// 文件是否被 user_id 以外的用户锁定，user_id 为实际操作的用户
+pub(crate) async fn check(
+    user_id: UserId,
+    file_id: UserFileId,
+    conn: &mut PgConn,
+) -> BizResult<(), FileOperateErr> {
+    check_files(user_id, &[file_id], conn).await
+}
+
+/// 整棵树中是否有被 user_id 以外的用户锁定的文件
+pub(crate) async fn check_tree(
+    user_id: UserId,
+    tree: &FileNode,
+    conn: &mut PgConn,
+) -> BizResult<(), FileOperateErr> {
+    let file_ids: Vec<_> = tree.all_files().iter().map(|file| *file.id()).collect();
+    check_files(user_id, &file_ids, conn).await
+}
+
+async fn check_files(
+    user_id: UserId,
+    file_ids: &[UserFileId],
+    conn: &mut PgConn,
+) -> BizResult<(), FileOperateErr> {
+    let now = Local::now();
+    let locks = repo_edit_lock::find_active(file_ids, now, conn).await?;
+    if let Err(locked) = edit_lock::check_all(&locks, user_id, now) {
+        return biz_err!(with_holder_name(locked, conn).await?.into());
+    }
+    biz_ok!(())
+}
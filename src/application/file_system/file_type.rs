@@ -0,0 +1,58 @@
+//! 上传文件的类型限制
+//!
+//! 类型根据文件开头的内容识别，不信任文件名和客户端声明的类型。
+//! 每个用户等级可以单独配置允许的类型，没有配置的等级使用 default
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::{
+    cqrs::user::UserLevel,
+    domain::{
+        file_system::{
+            file::FileNodeMetaData,
+            mime::{self, TypePolicy},
+        },
+        user::user::UserId,
+    },
+    infrastructure::file_sys,
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize, Default)]
+pub struct UploadTypesCfg {
+    #[serde(default)]
+    pub default: TypePolicy,
+    /// 键为用户等级，如 NORMAL、VIP
+    #[serde(default)]
+    pub levels: HashMap<UserLevel, TypePolicy>,
+}
+
+impl UploadTypesCfg {
+    fn policy(&self, level: UserLevel) -> &TypePolicy {
+        self.levels.get(&level).unwrap_or(&self.default)
+    }
+}
+
+/// 识别磁盘上文件的类型
+pub(crate) async fn sniff_file(path: &Path) -> Result<&'static str> {
+    let head = file_sys::read_head(path, mime::SNIFF_LEN).await?;
+    Ok(mime::sniff(&head))
+}
+
+/// 已归档的内容优先使用记录的类型，旧数据没有记录时重新识别
+pub(crate) async fn archived_mime(file: &FileNodeMetaData) -> Result<String> {
+    match &file.mime {
+        Some(mime) => Ok(mime.clone()),
+        None => Ok(sniff_file(&file.archived_path).await?.to_string()),
+    }
+}
+
+/// 该用户是否可以上传这种类型的文件
+pub(crate) async fn is_allowed(user_id: UserId, mime: &str) -> Result<bool> {
+    let level = UserLevel::of(user_id).await?;
+    let policy = get_settings().file_system.upload_types.policy(level);
+    Ok(policy.allows(mime))
+}
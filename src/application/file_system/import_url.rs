@@ -0,0 +1,467 @@
+//! 从远程地址导入文件
+//!
+//! 服务器直接下载 http/https 地址的文件，用户不需要先下载到本地再上传。
+//! 下载的内容与上传的文件一样计算 hash 并去重，完成后发送解析和缩略图请求。
+//! 导入与上传一样受存储配额、上传频率和文件类型的限制。
+//! 下载在后台进行，进度保存在 redis 中，客户端通过任务 id 查询
+
+use std::{fmt, net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Context};
+use reqwest::{header, redirect, Url};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    application::{notification, user::tier},
+    biz_ok,
+    domain::{
+        file_system::{
+            file::{FileNodeMetaData, FileOperateErr, UserFileId},
+            import_url::{self, ImportTask, ImportTaskId, ImportTaskState},
+        },
+        notification::NotificationEvent,
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        file_sys::{self, MergedFile},
+        public_host::{is_private_ip, is_public_host, PublicResolver},
+        repo_import_task, repo_user_file,
+    },
+    pg_tx,
+    settings::get_settings,
+};
+
+use super::{file_type, upload, version};
+
+#[derive(Debug, Deserialize)]
+pub struct ImportUrlCfg {
+    /// 允许导入的最大字节数
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+    /// 允许的 Content-Type 前缀，为空时不限制
+    #[serde(default = "default_content_types")]
+    pub allowed_content_types: Vec<String>,
+    /// 连续多少秒没有收到数据时放弃下载
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// 是否允许下载内网地址，默认禁止，避免用户借此访问内部服务
+    #[serde(default)]
+    pub allow_private_hosts: bool,
+}
+
+impl Default for ImportUrlCfg {
+    fn default() -> Self {
+        Self {
+            max_size: default_max_size(),
+            allowed_content_types: default_content_types(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            allow_private_hosts: false,
+        }
+    }
+}
+
+fn default_max_size() -> u64 {
+    1024 * 1024 * 1024 * 20
+}
+
+fn default_content_types() -> Vec<String> {
+    vec![
+        "video/".to_string(),
+        "application/octet-stream".to_string(),
+        "binary/octet-stream".to_string(),
+    ]
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    60
+}
+
+/// 每下载这么多字节更新一次进度
+const PROGRESS_STEP: u64 = 1024 * 1024 * 16;
+
+/// 最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportUrlDto {
+    /// http 或 https 地址
+    #[validate(length(min = 1, max = 2048))]
+    url: String,
+    parent_id: UserFileId,
+    /// 默认取 url 路径的最后一段
+    #[validate(length(min = 1, max = 255))]
+    file_name: Option<String>,
+    /// 覆盖同名文件，被覆盖的内容保存为历史版本。默认自动重命名
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportUrlResp {
+    task_id: ImportTaskId,
+}
+
+pub enum ImportUrlErr {
+    BadUrl,
+    HostNotAllowed,
+    NoParent,
+    NoFileName,
+    Unreachable,
+    BadStatus,
+    UnsupportedType,
+    TooLarge,
+    QuotaExceeded,
+    TooManyUploads,
+}
+
+/// 下载的文件不能导入的原因
+#[derive(Debug)]
+pub(crate) enum PlaceErr {
+    NoParent,
+    /// 识别出的文件类型
+    TypeNotAllowed(String),
+    QuotaExceeded,
+    Fs(FileOperateErr),
+}
+
+impl fmt::Display for PlaceErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaceErr::NoParent => write!(f, "parent dir not found"),
+            PlaceErr::TypeNotAllowed(mime) => write!(f, "file type not allowed: {mime}"),
+            PlaceErr::QuotaExceeded => write!(f, "storage quota exceeded"),
+            PlaceErr::Fs(err) => write!(f, "{:?}", err),
+        }
+    }
+}
+
+/// 检查地址并发起请求，收到响应头后在后台下载，返回任务 id
+pub async fn import_url(
+    user_id: UserId,
+    params: ImportUrlDto,
+) -> BizResult<ImportUrlResp, ImportUrlErr> {
+    use ImportUrlErr::*;
+    let cfg = &get_settings().file_system.import_url;
+
+    ensure_biz!(tier::try_upload(user_id).await?, TooManyUploads);
+    let Ok(url) = Url::parse(&params.url) else {
+        return Ok(Err(BadUrl));
+    };
+    ensure_biz!(matches!(url.scheme(), "http" | "https"), BadUrl);
+    // 连接时还会再检查一次，这里提前返回明确的错误
+    ensure_biz!(
+        cfg.allow_private_hosts || is_public_host(&url).await,
+        HostNotAllowed
+    );
+    let file_name = ensure_exist!(
+        params
+            .file_name
+            .or_else(|| import_url::file_name_from_url_path(url.path())),
+        NoFileName
+    );
+
+    {
+        let conn = &mut pg_conn().await?;
+        let parent = repo_user_file::find_node_for_read((user_id, params.parent_id), conn).await?;
+        ensure_biz!(parent.is_some_and(|p| p.is_dir()), NoParent);
+    }
+
+    let Ok(resp) = http_client(cfg)?.get(url).send().await else {
+        return Ok(Err(Unreachable));
+    };
+    ensure_biz!(resp.status().is_success(), BadStatus);
+    let content_type = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    ensure_biz!(
+        import_url::is_allowed_content_type(content_type, &cfg.allowed_content_types),
+        UnsupportedType
+    );
+    let total_bytes = resp.content_length();
+    ensure_biz!(total_bytes.unwrap_or(0) <= cfg.max_size, TooLarge);
+    {
+        let conn = &mut pg_conn().await?;
+        let bytes = total_bytes.unwrap_or(0);
+        ensure_biz!(
+            not tier::exceeds_quota(user_id, bytes, conn).await?,
+            QuotaExceeded
+        );
+    }
+
+    let task = ImportTask::new(
+        user_id,
+        params.url,
+        params.parent_id,
+        file_name,
+        params.overwrite,
+        total_bytes,
+    );
+    repo_import_task::save(&task).await?;
+    let task_id = *task.id();
+
+    info!(%user_id, %task_id, url = %task.url(), "import url started");
+    tokio::spawn(async move {
+        let mut task = task;
+        if let Err(err) = run_import(&mut task, resp).await {
+            warn!(%task_id, ?err, "import url failed");
+            task.failed(format!("{:#}", err));
+        }
+        if let Err(err) = repo_import_task::save(&task).await {
+            warn!(%task_id, ?err, "save import task failed");
+        }
+    });
+
+    biz_ok!(ImportUrlResp { task_id })
+}
+
+/// 每次连接前检查域名解析出的地址，包括重定向后的连接，不会因为域名重新绑定访问到内网。
+/// IP 字面量不经过解析，重定向时单独检查
+fn http_client(cfg: &ImportUrlCfg) -> anyhow::Result<reqwest::Client> {
+    let allow_private = cfg.allow_private_hosts;
+    let policy = redirect::Policy::custom(move |attempt| {
+        let url = attempt.url();
+        let private_ip = url
+            .host_str()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|host| host.parse::<IpAddr>().ok())
+            .is_some_and(is_private_ip);
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !matches!(url.scheme(), "http" | "https") {
+            attempt.error("unsupported scheme")
+        } else if !allow_private && private_ip {
+            attempt.error("private host")
+        } else {
+            attempt.follow()
+        }
+    });
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(cfg.idle_timeout_secs))
+        .redirect(policy);
+    if !allow_private {
+        // 经过代理时由代理解析域名，检查不到实际连接的地址
+        builder = builder.dns_resolver(Arc::new(PublicResolver)).no_proxy();
+    }
+    Ok(builder.build()?)
+}
+
+/// 下载、归档并为用户创建文件
+async fn run_import(task: &mut ImportTask, resp: reqwest::Response) -> anyhow::Result<()> {
+    let stored = store_response(resp, Some(&mut *task)).await?;
+    task.downloaded(stored.size);
+    if let Err(err) = check_stored(*task.user_id(), &stored).await? {
+        task.failed(err.to_string());
+        return Ok(());
+    }
+
+    let file_data = upload::archive_merged(stored).await?;
+    let placed = pg_tx!(
+        place_downloaded,
+        *task.user_id(),
+        *task.parent_id(),
+        task.file_name(),
+        *task.overwrite(),
+        file_data
+    )?;
+    match placed {
+        Ok((file_id, new_name)) => {
+            notification::wake(*task.user_id());
+            task.completed(file_id, new_name)
+        }
+        Err(err) => task.failed(err.to_string()),
+    }
+    Ok(())
+}
+
+/// 归档前检查文件类型和配额，与上传的检查相同，避免归档不能导入的内容
+pub(crate) async fn check_stored(
+    user_id: UserId,
+    stored: &MergedFile,
+) -> anyhow::Result<Result<(), PlaceErr>> {
+    let mime = file_type::sniff_file(stored.tmp_file.path()).await?;
+    if !file_type::is_allowed(user_id, mime).await? {
+        return Ok(Err(PlaceErr::TypeNotAllowed(mime.to_string())));
+    }
+    let conn = &mut pg_conn().await?;
+    if tier::exceeds_quota(user_id, stored.size, conn).await? {
+        return Ok(Err(PlaceErr::QuotaExceeded));
+    }
+    Ok(Ok(()))
+}
+
+/// 把响应内容写入临时文件并计算 hash，超过配置的大小或长时间没有数据时返回错误。
+/// 传入 task 时每下载 16MB 更新一次任务的进度
+pub(crate) async fn store_response(
+    resp: reqwest::Response,
+    task: Option<&mut ImportTask>,
+) -> anyhow::Result<MergedFile> {
+    let cfg = &get_settings().file_system.import_url;
+    let idle_timeout = Duration::from_secs(cfg.idle_timeout_secs);
+
+    let data = futures_util::stream::try_unfold(
+        (resp, task, 0u64, 0u64),
+        move |(mut resp, mut task, downloaded, reported)| async move {
+            let chunk = tokio::time::timeout(idle_timeout, resp.chunk())
+                .await
+                .context("download timed out")??;
+            let Some(chunk) = chunk else {
+                return Ok(None);
+            };
+            let downloaded = downloaded + chunk.len() as u64;
+            let mut reported = reported;
+            if let Some(task) = task.as_deref_mut() {
+                if downloaded - reported >= PROGRESS_STEP {
+                    task.downloaded(downloaded);
+                    repo_import_task::save(task).await?;
+                    reported = downloaded;
+                }
+            }
+            Ok::<_, anyhow::Error>(Some((chunk, (resp, task, downloaded, reported))))
+        },
+    );
+    let stored = file_sys::store_small_file(Box::pin(data), cfg.max_size)
+        .await?
+        .ok_or_else(|| anyhow!("file is larger than {} bytes", cfg.max_size))?;
+    Ok(stored)
+}
+
+/// 在目标目录创建下载的文件并通知用户，返回文件 id 和重命名后的文件名，不能创建时返回原因。
+/// 与上传一样在事务中检查文件类型和配额。事务提交后需要调用 [`notification::wake`]
+pub(crate) async fn place_downloaded(
+    user_id: UserId,
+    parent_id: UserFileId,
+    file_name: &str,
+    overwrite: bool,
+    file_data: FileNodeMetaData,
+    conn: &mut PgConn,
+) -> anyhow::Result<Result<(UserFileId, Option<String>), PlaceErr>> {
+    let Some(mut parent) = repo_user_file::load_tree_dep2((user_id, parent_id), conn).await? else {
+        return Ok(Err(PlaceErr::NoParent));
+    };
+    let mime = file_type::archived_mime(&file_data).await?;
+    if !file_type::is_allowed(user_id, &mime).await? {
+        return Ok(Err(PlaceErr::TypeNotAllowed(mime)));
+    }
+    if tier::exceeds_quota(user_id, file_data.size, conn).await? {
+        return Ok(Err(PlaceErr::QuotaExceeded));
+    }
+
+    let sys_file_id = file_data.id;
+    let file_data_path = file_data.archived_path.clone();
+    let hash = file_data.hash.clone();
+    let file =
+        match version::place_file(user_id, &mut parent, file_name, file_data, overwrite, conn)
+            .await?
+        {
+            Ok(file) => file,
+            Err(err) => return Ok(Err(PlaceErr::Fs(err))),
+        };
+
+    let new_name = file.file_name() != file_name;
+    let new_name = new_name.then(|| file.file_name().to_string());
+    let event = NotificationEvent::UploadFinished {
+        file_id: *file.id(),
+        file_name: file.file_name().to_string(),
+    };
+    notification::notify(user_id, event, conn).await?;
+
+    upload::on_user_file_created(sys_file_id, &hash, file_data_path, file.path()).await?;
+
+    Ok(Ok((*file.id(), new_name)))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportTaskDto {
+    task_id: ImportTaskId,
+    url: String,
+    file_name: String,
+    /// downloading、completed 或 failed
+    state: &'static str,
+    /// 远程服务器未声明大小时为空
+    total_bytes: Option<u64>,
+    downloaded_bytes: u64,
+    /// 完成后创建的文件
+    file_id: Option<UserFileId>,
+    /// 与已有文件重名时自动重命名后的文件名
+    new_name: Option<String>,
+    /// 失败原因
+    error: Option<String>,
+}
+
+pub enum ImportStatusErr {
+    NoTask,
+}
+
+pub async fn import_status(
+    user_id: UserId,
+    task_id: ImportTaskId,
+) -> BizResult<ImportTaskDto, ImportStatusErr> {
+    let task = ensure_exist!(
+        repo_import_task::find(task_id).await?,
+        ImportStatusErr::NoTask
+    );
+    ensure_biz!(*task.user_id() == user_id, ImportStatusErr::NoTask);
+
+    let (state, file_id, new_name, error) = match task.state().clone() {
+        ImportTaskState::Downloading => ("downloading", None, None, None),
+        ImportTaskState::Completed { file_id, new_name } => {
+            ("completed", Some(file_id), new_name, None)
+        }
+        ImportTaskState::Failed(reason) => ("failed", None, None, Some(reason)),
+    };
+    biz_ok!(ImportTaskDto {
+        task_id,
+        url: task.url().clone(),
+        file_name: task.file_name().clone(),
+        state,
+        total_bytes: *task.total_bytes(),
+        downloaded_bytes: *task.downloaded_bytes(),
+        file_id,
+        new_name,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn t_redirect_to_private_host() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                // 跟随了重定向时返回 200，请求会成功
+                let resp = if buf[..n].starts_with(b"GET / ") {
+                    format!("HTTP/1.1 302 Found\r\nLocation: http://localhost:{port}/a.mp4\r\nContent-Length: 0\r\n\r\n")
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string()
+                };
+                let _ = stream.write_all(resp.as_bytes()).await;
+            }
+        });
+
+        // 第一跳是 IP 字面量，不经过解析；重定向到解析为回环地址的域名时被拒绝
+        let client = http_client(&ImportUrlCfg::default()).unwrap();
+        let res = client.get(format!("http://127.0.0.1:{port}/")).send().await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! 从服务器磁盘导入文件
+//!
+//! 运维人员把大量文件直接放到存储服务器上后，通过这里把一个目录导入到指定用户的 /源视频 下，不需要经过 http 上传。
+//! 只允许导入配置中 allowed_roots 下的目录。文件按 hash 归档，已存在的内容直接复用，
+//! 导入后与上传的文件一样发送解析和缩略图请求。同一时间只运行一个导入任务。
+//! 需要删除源文件时，先复制归档，目录树在事务中保存成功后再删除
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::{
+            change::FileChange,
+            file::{FileNode, FileNodeMetaData, SysFileId, UserFileId, VirtualPath},
+            service::path_manager,
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{file_sys, repo_file_change, repo_user, repo_user_file},
+    log_if_err, pg_tx,
+    settings::get_settings,
+};
+
+use super::{admin::FsCapability, service, upload};
+
+#[derive(Debug, Deserialize, Default)]
+pub struct IngestCfg {
+    /// 允许导入的服务器目录，为空时不允许导入
+    #[serde(default)]
+    pub allowed_roots: Vec<PathBuf>,
+}
+
+/// 最多保留的错误信息条数
+const MAX_ERRORS: usize = 50;
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestDto {
+    user_id: UserId,
+    /// 服务器上的目录，必须位于配置的 allowed_roots 下
+    source_path: String,
+    /// 在 /源视频 下创建的目录名，默认使用源目录的名字
+    dir_name: Option<String>,
+    /// 导入后删除源文件，默认复制
+    #[serde(default)]
+    remove_source: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestProgress {
+    pub running: bool,
+    pub user_id: Option<UserId>,
+    pub source_path: Option<String>,
+    /// 导入后的目录，完成后才有值
+    pub dir_id: Option<UserFileId>,
+    /// 已扫描的文件数
+    pub scanned: u64,
+    /// 已导入的文件数
+    pub imported: u64,
+    /// 其中内容已存在、直接复用的文件数
+    pub reused: u64,
+    pub failed: u64,
+    /// 最近的错误信息，最多保留 50 条
+    pub errors: Vec<String>,
+}
+
+static PROGRESS: Mutex<IngestProgress> = Mutex::new(IngestProgress {
+    running: false,
+    user_id: None,
+    source_path: None,
+    dir_id: None,
+    scanned: 0,
+    imported: 0,
+    reused: 0,
+    failed: 0,
+    errors: Vec::new(),
+});
+
+pub fn progress() -> IngestProgress {
+    PROGRESS.lock().unwrap().clone()
+}
+
+fn record_err(err: String) {
+    warn!(%err, "ingest file failed");
+    let mut progress = PROGRESS.lock().unwrap();
+    progress.failed += 1;
+    if progress.errors.len() < MAX_ERRORS {
+        progress.errors.push(err);
+    }
+}
+
+pub enum IngestErr {
+    ReadOnly,
+    AlreadyRunning,
+    UserNotFound,
+    SourceNotFound,
+    SourceNotDir,
+    SourceNotAllowed,
+}
+
+/// 检查参数后在后台开始导入
+pub async fn start(cap: FsCapability, params: IngestDto) -> BizResult<(), IngestErr> {
+    use IngestErr::*;
+    ensure_biz!(cap.writable(), ReadOnly);
+
+    let IngestDto {
+        user_id,
+        source_path,
+        dir_name,
+        remove_source,
+    } = params;
+    {
+        let conn = &mut pg_conn().await?;
+        ensure_biz!(repo_user::exist(user_id, conn).await?, UserNotFound);
+    }
+
+    let Ok(source) = tokio::fs::canonicalize(&source_path).await else {
+        return Ok(Err(SourceNotFound));
+    };
+    ensure_biz!(tokio::fs::metadata(&source).await?.is_dir(), SourceNotDir);
+    ensure_biz!(is_allowed(&source), SourceNotAllowed);
+    let dir_name = ensure_exist!(
+        dir_name.or_else(|| Some(source.file_name()?.to_str()?.to_string())),
+        SourceNotAllowed
+    );
+
+    {
+        let mut progress = PROGRESS.lock().unwrap();
+        ensure_biz!(!progress.running, AlreadyRunning);
+        *progress = IngestProgress {
+            running: true,
+            user_id: Some(user_id),
+            source_path: Some(source.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+    }
+
+    info!(%user_id, ?source, %dir_name, remove_source, "ingest started");
+    tokio::spawn(async move {
+        let result = ingest(user_id, &source, &dir_name, remove_source).await;
+        let progress = {
+            let mut progress = PROGRESS.lock().unwrap();
+            progress.running = false;
+            if let Ok(dir_id) = &result {
+                progress.dir_id = Some(*dir_id);
+            }
+            progress.clone()
+        };
+        log_if_err!(result);
+        info!(?progress, "ingest finished");
+    });
+
+    biz_ok!(())
+}
+
+/// 软链接等不在 allowed_roots 下的路径在 canonicalize 后会被拒绝
+fn is_allowed(source: &Path) -> bool {
+    let cfg = &get_settings().file_system.ingest;
+    cfg.allowed_roots
+        .iter()
+        .filter_map(|root| std::fs::canonicalize(root).ok())
+        .any(|root| source.starts_with(root))
+}
+
+/// 导入时创建的目录和文件，保存到数据库后再在磁盘上创建
+#[derive(Default)]
+struct Imported {
+    dirs: Vec<VirtualPath>,
+    /// (系统文件 id, 内容 hash, 归档路径, 用户文件路径)
+    files: Vec<(SysFileId, String, PathBuf, VirtualPath)>,
+    /// 本次导入中已归档的内容，相同内容的文件只归档一次
+    archived: HashMap<String, FileNodeMetaData>,
+    /// 已导入的源文件，保存成功后需要删除源文件时使用
+    sources: Vec<PathBuf>,
+}
+
+async fn ingest(
+    user_id: UserId,
+    source: &Path,
+    dir_name: &str,
+    remove_source: bool,
+) -> Result<UserFileId> {
+    // 确保用户的根目录已创建
+    service::load_home(user_id).await?;
+
+    // 计算 hash 和复制文件耗时很长，期间不占用数据库连接
+    let mut parent = {
+        let conn = &mut pg_conn().await?;
+        let resource_dir = VirtualPath::resource_dir(user_id);
+        repo_user_file::load_tree_dep2(&resource_dir, conn)
+            .await?
+            .context("resource dir not found")?
+    };
+    let dir = parent
+        .create_dir(dir_name)
+        .map_err(|_| anyhow!("invalid dir name: {}", dir_name))?;
+
+    let mut imported = Imported::default();
+    import_dir(source, dir, &mut imported).await?;
+
+    pg_tx!(save_tree, dir)?;
+    for path in &imported.dirs {
+        file_sys::create_dir(path).await?;
+    }
+    for (sys_file_id, hash, archived_path, path) in imported.files {
+        upload::on_user_file_created(sys_file_id, &hash, archived_path, &path).await?;
+    }
+    if remove_source {
+        for path in &imported.sources {
+            if let Err(err) = file_sys::delete(path).await {
+                record_err(format!("{}: remove source: {:#}", path.display(), err));
+            }
+        }
+    }
+
+    Ok(*dir.id())
+}
+
+async fn save_tree(dir: &FileNode, conn: &mut PgConn) -> Result<()> {
+    let effected = repo_user_file::save_node(dir, conn).await?;
+    ensure!(effected.is_all_effected(), "dir already exists");
+    repo_file_change::insert(&FileChange::created(dir), conn).await
+}
+
+/// 递归导入目录，单个文件失败时记录错误并继续
+fn import_dir<'a>(
+    disk_dir: &'a Path,
+    node: &'a mut FileNode,
+    imported: &'a mut Imported,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        imported.dirs.push(node.path().clone());
+
+        let mut entries = tokio::fs::read_dir(disk_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let disk_path = entry.path();
+            let Some(name) = entry.file_name().to_str().map(ToOwned::to_owned) else {
+                record_err(format!("{}: file name is not utf-8", disk_path.display()));
+                continue;
+            };
+            let file_type = entry.file_type().await?;
+
+            if file_type.is_dir() {
+                match node.create_dir(&name) {
+                    Ok(child) => import_dir(&disk_path, child, imported).await?,
+                    Err(_) => record_err(format!("{}: invalid dir name", disk_path.display())),
+                }
+            } else if file_type.is_file() {
+                PROGRESS.lock().unwrap().scanned += 1;
+                let metadata = match import_file(&disk_path, imported).await {
+                    Ok(metadata) => metadata,
+                    Err(err) => {
+                        record_err(format!("{}: {:#}", disk_path.display(), err));
+                        continue;
+                    }
+                };
+                let sys_file_id = metadata.id;
+                let archived_path = metadata.archived_path.clone();
+                let hash = metadata.hash.clone();
+                match node.create_file(&name, metadata) {
+                    Ok(file) => {
+                        imported.files.push((
+                            sys_file_id,
+                            hash,
+                            archived_path,
+                            file.path().clone(),
+                        ));
+                        imported.sources.push(disk_path);
+                        PROGRESS.lock().unwrap().imported += 1;
+                    }
+                    Err(_) => record_err(format!("{}: invalid file name", disk_path.display())),
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// 计算 hash 并复制到归档目录，内容已存在时复用已有的系统文件，不修改源文件
+async fn import_file(path: &Path, imported: &mut Imported) -> Result<FileNodeMetaData> {
+    let size = tokio::fs::metadata(path).await?.len();
+    let hash = file_sys::file_hash(path)
+        .await?
+        .context("file disappeared")?;
+
+    let existed = match imported.archived.get(&hash) {
+        Some(metadata) => Some(metadata.clone()),
+        None => repo_user_file::get_filenode_data(&hash).await?,
+    };
+    if let Some(metadata) = existed {
+        PROGRESS.lock().unwrap().reused += 1;
+        return Ok(metadata);
+    }
+
+    let archived_path = path_manager().archived_path(&hash);
+    file_sys::create_dir_all(archived_path.parent().unwrap()).await?;
+    tokio::fs::copy(path, &archived_path).await?;
+
+    let metadata = FileNodeMetaData::new(size, hash.clone(), archived_path);
+    imported.archived.insert(hash, metadata.clone());
+    Ok(metadata)
+}
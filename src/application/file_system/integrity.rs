@@ -0,0 +1,128 @@
+use std::{borrow::Cow, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::file_system::file::{FileNodeMetaData, SysFileId},
+    ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        file_sys,
+        repo_integrity::{self, MismatchPo},
+        repo_user_file,
+    },
+    log_if_err,
+    settings::get_settings,
+};
+
+/// 后台校验归档文件完整性的配置
+#[derive(Debug, Deserialize)]
+pub struct ScrubberCfg {
+    #[serde(default)]
+    pub enable: bool,
+    /// 每轮校验之间的间隔
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 每轮随机抽取的文件数
+    #[serde(default = "default_batch_size")]
+    pub batch_size: i64,
+}
+
+impl Default for ScrubberCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval_secs: default_interval_secs(),
+            batch_size: default_batch_size(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    60 * 60
+}
+
+fn default_batch_size() -> i64 {
+    20
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyResult {
+    sys_file_id: SysFileId,
+    expected_hash: String,
+    /// 为空表示文件已丢失
+    actual_hash: Option<String>,
+    matched: bool,
+}
+
+pub enum VerifyErr {
+    NotFound,
+}
+
+pub async fn verify(sys_file_id: SysFileId) -> BizResult<VerifyResult, VerifyErr> {
+    let file = ensure_exist!(
+        repo_user_file::find_sys_file(sys_file_id).await?,
+        VerifyErr::NotFound
+    );
+    biz_ok!(verify_file(&file).await?)
+}
+
+/// 重新计算归档文件的 hash，与数据库中的记录不一致时记录下来
+async fn verify_file(file: &FileNodeMetaData) -> Result<VerifyResult> {
+    let actual_hash = file_sys::file_hash(&file.archived_path).await?;
+    let matched = actual_hash.as_deref() == Some(&*file.hash);
+
+    if !matched {
+        error!(
+            sys_file_id = %file.id,
+            path = ?file.archived_path,
+            expected = %file.hash,
+            actual = ?actual_hash,
+            "ALERT: archived file corrupted"
+        );
+        let mismatch = MismatchPo {
+            sys_file_id: file.id,
+            expected_hash: Cow::Borrowed(&file.hash),
+            actual_hash: actual_hash.as_deref().map(Cow::Borrowed),
+        };
+        repo_integrity::save_mismatch(&mismatch).await?;
+    }
+
+    Ok(VerifyResult {
+        sys_file_id: file.id,
+        expected_hash: file.hash.clone(),
+        actual_hash,
+        matched,
+    })
+}
+
+/// 启动后台校验任务，定期随机抽取文件进行校验
+pub fn spawn_scrubber() {
+    let cfg = &get_settings().file_system.scrubber;
+    if !cfg.enable {
+        return;
+    }
+
+    info!(?cfg, "file scrubber started");
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(cfg.interval_secs)).await;
+            log_if_err!(scrub(cfg.batch_size).await);
+        }
+    });
+}
+
+async fn scrub(batch_size: i64) -> Result<()> {
+    let files = repo_user_file::sample_sys_files(batch_size).await?;
+    for file in files {
+        log_if_err!(verify_file(&file).await);
+        // 低优先级任务，降低对磁盘的压力
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! 目录清单
+//!
+//! 同步大量文件的客户端先下载目录清单，与本地文件比较后只上传有变化的文件。
+//! 清单为 NDJSON 格式，每行一个文件，按路径排序，分块输出
+
+use std::iter;
+
+use actix_web::web::Bytes;
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::{file::UserFileId, share::FileOp},
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::repo_user_file,
+};
+
+use super::share;
+
+/// 每个分块包含的行数
+const LINES_PER_CHUNK: usize = 512;
+
+/// 清单中的一行
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// 相对于目录的路径，以 `/` 分隔
+    path: String,
+    size: u64,
+    /// 文件内容的 sha256
+    hash: String,
+    /// 最后修改时间
+    mtime: MillionTimestamp,
+}
+
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// 按需序列化，每次输出 LINES_PER_CHUNK 行
+    pub fn into_stream(self) -> impl Stream<Item = serde_json::Result<Bytes>> {
+        let mut entries = self.entries.into_iter();
+        stream::iter(iter::from_fn(move || {
+            let chunk: Vec<_> = entries.by_ref().take(LINES_PER_CHUNK).collect();
+            (!chunk.is_empty()).then(|| to_ndjson(&chunk))
+        }))
+    }
+}
+
+fn to_ndjson(entries: &[ManifestEntry]) -> serde_json::Result<Bytes> {
+    let mut buf = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut buf, entry)?;
+        buf.push(b'\n');
+    }
+    Ok(Bytes::from(buf))
+}
+
+pub enum ManifestErr {
+    NotFound,
+    NotDir,
+}
+
+/// 目录下所有文件的清单，需要目录的读权限
+pub async fn dir_manifest(user_id: UserId, dir_id: UserFileId) -> BizResult<Manifest, ManifestErr> {
+    let conn = &mut pg_conn_read().await?;
+    let owner = ensure_exist!(
+        share::owner_for(user_id, dir_id, FileOp::Read, conn).await?,
+        ManifestErr::NotFound
+    );
+    let dir = ensure_exist!(
+        repo_user_file::load_tree_all_for_read((owner, dir_id), conn).await?,
+        ManifestErr::NotFound
+    );
+    ensure_biz!(dir.is_dir(), ManifestErr::NotDir);
+
+    let mut entries: Vec<_> = dir
+        .all_files()
+        .into_iter()
+        .filter_map(|file| {
+            let data = file.file_data()?;
+            Some(ManifestEntry {
+                path: file.path().relative_to(dir.path())?.into_owned(),
+                size: data.size,
+                hash: data.hash.clone(),
+                mtime: (*file.updated_at()).into(),
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    biz_ok!(Manifest { entries })
+}
@@ -3,20 +3,136 @@ use std::path::PathBuf;
 use anyhow::Result;
 use serde::Deserialize;
 
-use crate::{domain::file_system::service::PathManager, settings::get_settings};
+use crate::{
+    domain::file_system::{home::HomeLayout, service::PathManager},
+    settings::get_settings,
+};
 
+pub mod access_log;
+pub mod admin;
+pub mod breadcrumb;
+pub mod bulk;
+pub mod comment;
+pub mod consistency;
+pub mod dir_cache;
+pub mod disk_space;
+pub mod edit_lock;
+pub mod file_type;
+pub mod import_url;
+pub mod ingest;
+pub mod integrity;
+pub mod manifest;
+pub mod path_history;
+pub mod playback;
+pub mod recent;
+pub mod reclaim;
+pub mod scan;
 pub mod service;
+pub mod share;
+pub mod star;
+pub mod subtitle;
+pub mod tag;
 pub mod upload;
+pub mod usage;
+pub mod version;
 pub mod video_info;
 
 #[derive(Debug, Deserialize)]
 pub struct FileSystemCfg {
     pub root_dir: PathBuf,
+    /// 用户家目录下的固定目录
+    #[serde(default)]
+    pub home: HomeLayout,
+    /// 通过 upload_small 接口一次性上传的文件的最大字节数
+    #[serde(default = "default_small_file_max_size")]
+    pub small_file_max_size: u64,
+    /// 分片上传的文件的最大字节数
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// 单个分片的最大字节数
+    #[serde(default = "default_max_slice_size")]
+    pub max_slice_size: u64,
+    /// 完成上传时合并标记的租约，进程退出后超过此时间客户端可以重试合并
+    #[serde(default = "default_merge_lease_secs")]
+    pub merge_lease_secs: u64,
+    #[serde(default)]
+    pub scrubber: integrity::ScrubberCfg,
+    /// 回收不再使用的归档文件
+    #[serde(default)]
+    pub reclaim: reclaim::ReclaimCfg,
+    /// 数据库与磁盘的一致性检查
+    #[serde(default)]
+    pub consistency: consistency::ConsistencyCfg,
+    /// 目录列表的 redis 缓存
+    #[serde(default)]
+    pub dir_cache: dir_cache::DirCacheCfg,
+    /// 用户未设置时，每个文件保留的历史版本数
+    #[serde(default = "default_max_file_versions")]
+    pub max_file_versions: u32,
+    /// 最近访问记录从 redis 写入数据库的间隔
+    #[serde(default = "default_access_flush_interval_secs")]
+    pub access_flush_interval_secs: u64,
+    /// 文件访问记录的保留天数
+    #[serde(default = "default_access_log_retention_days")]
+    pub access_log_retention_days: u32,
+    /// 员工访问用户文件的权限
+    #[serde(default)]
+    pub admin: admin::AdminFsCfg,
+    /// 从服务器磁盘导入文件
+    #[serde(default)]
+    pub ingest: ingest::IngestCfg,
+    /// 从远程地址导入文件
+    #[serde(default)]
+    pub import_url: import_url::ImportUrlCfg,
+    /// 新内容的病毒扫描
+    #[serde(default)]
+    pub scan: scan::ScanCfg,
+    /// 允许上传的文件类型
+    #[serde(default)]
+    pub upload_types: file_type::UploadTypesCfg,
+    /// 写入大量数据前的磁盘空间检查
+    #[serde(default)]
+    pub disk_space: disk_space::DiskSpaceCfg,
+}
+
+fn default_small_file_max_size() -> u64 {
+    1024 * 1024 * 20
+}
+
+fn default_max_file_size() -> u64 {
+    1024 * 1024 * 1024 * 100
+}
+
+fn default_max_slice_size() -> u64 {
+    1024 * 1024 * 100
+}
+
+fn default_merge_lease_secs() -> u64 {
+    60
+}
+
+fn default_max_file_versions() -> u32 {
+    10
+}
+
+fn default_access_flush_interval_secs() -> u64 {
+    30
+}
+
+fn default_access_log_retention_days() -> u32 {
+    30
 }
 
 pub async fn init() -> Result<()> {
     let settings = &get_settings().file_system;
     PathManager::init(settings.root_dir.to_owned())?;
+    HomeLayout::init(settings.home.clone())?;
+    integrity::spawn_scrubber();
+    reclaim::spawn_reclaimer();
+    consistency::spawn_startup_check();
+    recent::spawn_flusher();
+    access_log::spawn_pruner();
+    upload::spawn_merge_cleanup();
 
     Ok(())
 }
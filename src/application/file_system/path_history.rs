@@ -0,0 +1,80 @@
+//! 文件的历史路径
+//!
+//! 重命名和移动时记录原来的路径，之前发出的链接和收藏中的旧路径仍然可以找到文件现在的位置。
+//! 目录被移动后，旧路径下的文件按目录现在的位置拼接出新路径
+
+use std::iter;
+
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::file::{UserFileId, VirtualPath},
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_path_history, repo_user_file},
+};
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedPath {
+    file_id: UserFileId,
+    /// 文件现在的路径
+    path: String,
+    /// 文件在这个路径之后被重命名或移动过
+    moved: bool,
+}
+
+pub enum ResolvePathErr {
+    BadPath,
+    NotFound,
+}
+
+/// 找到路径现在对应的文件。路径上有文件时直接返回，否则按历史记录查找
+pub async fn resolve(user_id: UserId, path: &str) -> BizResult<ResolvedPath, ResolvePathErr> {
+    use ResolvePathErr::*;
+
+    let conn = &mut pg_conn_read().await?;
+    let path = ensure_biz!(VirtualPath::build(user_id, path).map_err(|_| BadPath));
+    if let Some(file) = repo_user_file::find_node_for_read(&path, conn).await? {
+        return biz_ok!(ResolvedPath {
+            file_id: *file.id(),
+            path: path.to_str().into_owned(),
+            moved: false,
+        });
+    }
+
+    // 路径本身和它的各级上级目录，不包括根目录
+    let candidates: Vec<String> = iter::successors(Some(path.clone()), VirtualPath::parent)
+        .take_while(|p| p.parent().is_some())
+        .map(|p| p.to_str().into_owned())
+        .collect();
+    let (old_path, moved_id) = ensure_exist!(
+        repo_path_history::find_latest(user_id, &candidates, conn).await?,
+        NotFound
+    );
+    let moved = ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, moved_id), conn).await?,
+        NotFound
+    );
+    ensure_biz!(!*moved.deleted(), NotFound);
+
+    let path = path.to_str();
+    let rest = path.strip_prefix(&*old_path).unwrap_or_default();
+    let current = format!("{}{}", moved.path().to_str(), rest);
+    let current = ensure_biz!(VirtualPath::build(user_id, current).map_err(|_| NotFound));
+    let file = ensure_exist!(
+        repo_user_file::find_node_for_read(&current, conn).await?,
+        NotFound
+    );
+    biz_ok!(ResolvedPath {
+        file_id: *file.id(),
+        path: current.to_str().into_owned(),
+        moved: true,
+    })
+}
@@ -0,0 +1,138 @@
+//! 带令牌的播放和下载地址
+//!
+//! 签发地址时检查用户的读权限，之后按地址中的令牌访问，不再需要登录
+
+use std::{net::IpAddr, path::PathBuf};
+
+use anyhow::Result;
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::{
+            file::{FileNode, FileType, UserFileId},
+            share::FileOp,
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::{
+        playback_token::{self, TokenErr},
+        BizResult,
+    },
+    infrastructure::repo_user_file,
+};
+
+use super::{scan, share};
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackUrlDto {
+    file_id: UserFileId,
+    /// 地址只能在当前 IP 使用
+    #[serde(default)]
+    bind_ip: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackUrl {
+    /// 播放地址，加上 `&download=true` 时作为附件下载
+    url: String,
+    expire_at: MillionTimestamp,
+}
+
+#[derive(From, Debug)]
+pub enum PlaybackUrlErr {
+    Token(TokenErr),
+    FileNotFound,
+    NotFile,
+    /// 要求绑定 IP，但是获取不到请求方的地址
+    UnknownIp,
+}
+
+/// 签发文件的播放地址，需要文件的读权限。ip 为请求方的地址，bind_ip 为真时使用
+pub async fn issue_url(
+    user_id: UserId,
+    params: PlaybackUrlDto,
+    ip: Option<IpAddr>,
+) -> BizResult<PlaybackUrl, PlaybackUrlErr> {
+    use PlaybackUrlErr::*;
+
+    let conn = &mut pg_conn_read().await?;
+    let file_id = params.file_id;
+    let permitted = share::owner_for(user_id, file_id, FileOp::Read, conn).await?;
+    ensure_biz!(permitted.is_some(), FileNotFound);
+    let file = ensure_exist!(
+        repo_user_file::find_node_for_read(file_id, conn).await?,
+        FileNotFound
+    );
+    ensure_biz!(!*file.deleted(), FileNotFound);
+    ensure_biz!(file.is_file(), NotFile);
+    ensure_biz!(servable_file(&file).await?.is_some(), FileNotFound);
+
+    let ip = if params.bind_ip {
+        Some(ensure_exist!(ip, UnknownIp))
+    } else {
+        None
+    };
+    let issued = ensure_biz!(playback_token::issue(file_id, ip));
+    biz_ok!(PlaybackUrl {
+        url: format!("/api/fs/play/{}?token={}", file_id, issued.token),
+        expire_at: issued.expire_at.into(),
+    })
+}
+
+/// 按令牌打开的文件
+pub struct PlaybackFile {
+    pub archived_path: PathBuf,
+    pub mime: Option<String>,
+    pub file_name: String,
+    pub hash: String,
+}
+
+/// 令牌校验通过后查找要播放的文件，文件已删除或内容被隔离时为空
+pub async fn open(file_id: UserFileId) -> Result<Option<PlaybackFile>> {
+    let conn = &mut pg_conn_read().await?;
+    let Some(file) = repo_user_file::find_node_for_read(file_id, conn).await? else {
+        return Ok(None);
+    };
+    if *file.deleted() {
+        return Ok(None);
+    }
+    servable_file(&file).await
+}
+
+/// 可以对外提供的存档文件，内容被隔离时为空
+pub(crate) async fn servable_file(file: &FileNode) -> Result<Option<PlaybackFile>> {
+    let Some(file) = archived_file(file).await? else {
+        return Ok(None);
+    };
+    if scan::is_quarantined(&file.hash).await? {
+        return Ok(None);
+    }
+    Ok(Some(file))
+}
+
+/// 文件的存档路径和识别出的 MIME 类型，目录和找不到存档的文件为空
+async fn archived_file(file: &FileNode) -> Result<Option<PlaybackFile>> {
+    let sys_file_id = match file.file_type() {
+        FileType::File(data) => data.id,
+        FileType::LazyFile(id) => *id,
+        FileType::Dir(_) => return Ok(None),
+    };
+    let Some(data) = repo_user_file::find_sys_file(sys_file_id).await? else {
+        return Ok(None);
+    };
+    Ok(Some(PlaybackFile {
+        archived_path: data.archived_path,
+        mime: data.mime,
+        file_name: file.file_name().to_string(),
+        hash: data.hash,
+    }))
+}
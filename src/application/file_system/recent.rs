@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use tracing::{debug, info};
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    infrastructure::repo_file_access,
+    log_if_err,
+    settings::get_settings,
+};
+
+/// 记录用户访问了文件（下载、预览、作为转码源）。
+/// 只是辅助功能，失败时记录日志，不影响正常请求
+pub async fn touch(user_id: UserId, file_id: UserFileId) {
+    log_if_err!(repo_file_access::record(user_id, file_id, Local::now()).await);
+}
+
+/// 启动后台任务，定期把 redis 中的访问记录写入数据库
+pub fn spawn_flusher() {
+    let interval = get_settings().file_system.access_flush_interval_secs;
+    info!(interval, "file access flusher started");
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+            log_if_err!(flush().await);
+        }
+    });
+}
+
+async fn flush() -> Result<()> {
+    let accesses = repo_file_access::take_pending().await?;
+    if accesses.is_empty() {
+        return Ok(());
+    }
+
+    debug!(count = accesses.len(), "flush file accesses");
+    let conn = &mut pg_conn().await?;
+    for chunk in accesses.chunks(1000) {
+        repo_file_access::save(chunk, conn).await?;
+    }
+    Ok(())
+}
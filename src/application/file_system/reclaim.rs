@@ -0,0 +1,167 @@
+//! 回收不再使用的归档文件
+//!
+//! 系统文件不再被用户文件、历史版本和进行中的转码任务引用后，删除它的记录和整个归档目录，
+//! 包括原文件、缩略图和转码的中间文件。
+//! 孤儿扫描用于找出没有系统文件记录的归档目录，如合并后未能保存记录的上传
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::file_system::{file::SysFileId, service::path_manager},
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{file_sys, repo_user_file},
+    log_if_err, pg_tx,
+    settings::get_settings,
+    LocalDataTime,
+};
+
+use super::admin::{AdminFsErr, FsCapability};
+
+/// 每次查询数据库的 hash 数
+const HASH_BATCH: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct ReclaimCfg {
+    #[serde(default)]
+    pub enable: bool,
+    /// 每轮回收之间的间隔
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 最近更新过的系统文件和归档目录不回收，避免与进行中的上传冲突
+    #[serde(default = "default_grace_hours")]
+    pub grace_hours: u32,
+    /// 每轮最多回收的文件数
+    #[serde(default = "default_batch_size")]
+    pub batch_size: i64,
+}
+
+impl Default for ReclaimCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval_secs: default_interval_secs(),
+            grace_hours: default_grace_hours(),
+            batch_size: default_batch_size(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    60 * 60
+}
+
+fn default_grace_hours() -> u32 {
+    24
+}
+
+fn default_batch_size() -> i64 {
+    100
+}
+
+/// 启动后台回收任务
+pub fn spawn_reclaimer() {
+    let cfg = &get_settings().file_system.reclaim;
+    if !cfg.enable {
+        return;
+    }
+
+    info!(?cfg, "storage reclaimer started");
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(cfg.interval_secs)).await;
+            log_if_err!(reclaim(cfg).await);
+        }
+    });
+}
+
+async fn reclaim(cfg: &ReclaimCfg) -> Result<()> {
+    let before = Local::now() - chrono::Duration::hours(cfg.grace_hours as i64);
+    let files = {
+        let conn = &mut pg_conn().await?;
+        repo_user_file::find_unreferenced_sys_files(before, cfg.batch_size, conn).await?
+    };
+    debug!(count = files.len(), "reclaiming unreferenced sys files");
+    for (id, hash) in files {
+        log_if_err!(pg_tx!(purge, id, &hash, before));
+    }
+    Ok(())
+}
+
+/// 删除系统文件的记录和归档目录下的所有文件。目录在删除记录的事务中删除，
+/// 期间按 hash 复用这个文件的请求会等待事务结束，之后找不到记录，重新归档内容
+async fn purge(id: SysFileId, hash: &str, before: LocalDataTime, conn: &mut PgConn) -> Result<()> {
+    if !repo_user_file::delete_unreferenced_sys_file(id, before, conn).await? {
+        return Ok(());
+    }
+    // 相同内容已有另一条记录或被用作头像时保留目录
+    if !repo_user_file::existing_hashes(&[hash.to_string()], conn)
+        .await?
+        .is_empty()
+    {
+        return Ok(());
+    }
+    file_sys::delete(&path_manager().archived_dir(hash)).await?;
+    info!(%id, hash, "sys file purged");
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanDir {
+    pub hash: String,
+    /// 目录下普通文件的总字节数
+    pub size: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanScanResult {
+    pub orphans: Vec<OrphanDir>,
+    /// 是否已删除这些目录
+    pub removed: bool,
+}
+
+/// 找出没有系统文件记录的归档目录，remove 为 true 时删除它们
+pub async fn scan_orphans(
+    cap: FsCapability,
+    remove: bool,
+) -> BizResult<OrphanScanResult, AdminFsErr> {
+    ensure_biz!(!remove || cap.writable(), AdminFsErr::ReadOnly);
+
+    let grace = Duration::from_secs(get_settings().file_system.reclaim.grace_hours as u64 * 3600);
+    let before = SystemTime::now() - grace;
+    let root = path_manager().archive_root();
+    let hashes = file_sys::child_dirs_modified_before(root, before).await?;
+
+    let conn = &mut pg_conn().await?;
+    let mut orphans = vec![];
+    for batch in hashes.chunks(HASH_BATCH) {
+        let existing = repo_user_file::existing_hashes(batch, conn).await?;
+        for hash in batch.iter().filter(|hash| !existing.contains(*hash)) {
+            let dir = path_manager().archived_dir(hash);
+            let size = file_sys::disk_usage(&dir).await?;
+            if remove {
+                file_sys::delete(&dir).await?;
+                info!(%hash, size, "orphan archive dir removed");
+            }
+            orphans.push(OrphanDir {
+                hash: hash.clone(),
+                size,
+            });
+        }
+    }
+
+    biz_ok!(OrphanScanResult {
+        orphans,
+        removed: remove,
+    })
+}
@@ -0,0 +1,214 @@
+//! 新内容的病毒扫描
+//!
+//! 合并后的文件归档前交给扫描器检查，发现问题时按 hash 隔离：文件照常归档，用户文件照常创建，
+//! 但不创建可下载的软链接、不发送解析请求，打包下载时也会跳过，同时邮件通知管理员。
+//! 默认不扫描，配置 clamav 后通过 clamd 的 INSTREAM 命令扫描
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{debug, error, warn};
+
+use crate::{
+    infrastructure::{
+        email,
+        file_sys::MergedFile,
+        repo_quarantine::{self, QuarantinePo},
+    },
+    log_if_err,
+    settings::get_settings,
+};
+
+/// 每次发送给 clamd 的数据块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ScanCfg {
+    /// 不配置时不扫描
+    #[serde(default)]
+    pub clamav: Option<ClamAvCfg>,
+    /// 扫描服务出错时是否放行，默认拒绝，上传会失败并可以重试
+    #[serde(default)]
+    pub fail_open: bool,
+    /// 发现问题文件时通知的管理员邮箱
+    #[serde(default)]
+    pub notify_emails: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClamAvCfg {
+    /// clamd 的 TCP 地址，如 127.0.0.1:3310
+    pub addr: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// 超过该大小的文件不扫描，应不大于 clamd 的 StreamMaxLength
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_size() -> u64 {
+    25 * 1024 * 1024
+}
+
+#[derive(Debug)]
+pub enum ScanVerdict {
+    Clean,
+    /// 扫描器给出的特征名
+    Infected(String),
+}
+
+#[async_trait::async_trait]
+pub trait Scanner: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn scan(&self, path: &Path, size: u64) -> Result<ScanVerdict>;
+}
+
+/// 未配置扫描器时使用，所有文件都视为正常
+pub struct NoopScanner;
+
+#[async_trait::async_trait]
+impl Scanner for NoopScanner {
+    fn name(&self) -> &'static str {
+        "noop"
+    }
+
+    async fn scan(&self, _path: &Path, _size: u64) -> Result<ScanVerdict> {
+        Ok(ScanVerdict::Clean)
+    }
+}
+
+pub struct ClamAv {
+    cfg: &'static ClamAvCfg,
+}
+
+impl ClamAv {
+    pub fn new(cfg: &'static ClamAvCfg) -> Self {
+        Self { cfg }
+    }
+
+    async fn instream(&self, path: &Path) -> Result<ScanVerdict> {
+        let mut file = File::open(path).await?;
+        let mut stream = TcpStream::connect(&self.cfg.addr).await?;
+        stream.write_all(b"zINSTREAM\0").await?;
+
+        // 数据以 <4 字节大端长度><数据> 的块发送，长度为 0 的块表示结束
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&(n as u32).to_be_bytes()).await?;
+            stream.write_all(&buf[..n]).await?;
+        }
+        stream.write_all(&0u32.to_be_bytes()).await?;
+
+        // 使用 z 前缀的命令，clamd 回复后会关闭连接
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        parse_reply(&reply)
+    }
+}
+
+/// 回复的格式为 "stream: OK"、"stream: <特征名> FOUND" 或 "<原因> ERROR"
+fn parse_reply(reply: &[u8]) -> Result<ScanVerdict> {
+    let reply = String::from_utf8_lossy(reply);
+    let reply = reply.trim_end_matches('\0').trim();
+    let result = reply.strip_prefix("stream: ").unwrap_or(reply);
+    if result == "OK" {
+        return Ok(ScanVerdict::Clean);
+    }
+    if let Some(signature) = result.strip_suffix(" FOUND") {
+        return Ok(ScanVerdict::Infected(signature.to_string()));
+    }
+    bail!("clamd error: {}", reply)
+}
+
+#[async_trait::async_trait]
+impl Scanner for ClamAv {
+    fn name(&self) -> &'static str {
+        "clamav"
+    }
+
+    async fn scan(&self, path: &Path, size: u64) -> Result<ScanVerdict> {
+        if size > self.cfg.max_size {
+            debug!(size, ?path, "file too large, skip scanning");
+            return Ok(ScanVerdict::Clean);
+        }
+        let timeout = Duration::from_secs(self.cfg.timeout_secs);
+        tokio::time::timeout(timeout, self.instream(path))
+            .await
+            .context("clamd timeout")?
+    }
+}
+
+fn scanner() -> Box<dyn Scanner> {
+    match &get_settings().file_system.scan.clamav {
+        Some(cfg) => Box::new(ClamAv::new(cfg)),
+        None => Box::new(NoopScanner),
+    }
+}
+
+/// 扫描合并后还未归档的文件，有问题时隔离该内容
+pub(crate) async fn scan_merged(merged: &MergedFile) -> Result<()> {
+    let cfg = &get_settings().file_system.scan;
+    let scanner = scanner();
+    let verdict = match scanner.scan(merged.tmp_file.path(), merged.size).await {
+        Ok(verdict) => verdict,
+        Err(err) if cfg.fail_open => {
+            warn!(?err, hash = %merged.hash, "scan failed, let it pass");
+            return Ok(());
+        }
+        Err(err) => return Err(err.context("scan file")),
+    };
+
+    if let ScanVerdict::Infected(signature) = verdict {
+        quarantine(&merged.hash, scanner.name(), &signature).await?;
+    }
+    Ok(())
+}
+
+async fn quarantine(hash: &str, scanner: &str, signature: &str) -> Result<()> {
+    error!(hash, scanner, signature, "file quarantined");
+    let po = QuarantinePo {
+        hash: hash.into(),
+        scanner: scanner.into(),
+        signature: signature.into(),
+    };
+    repo_quarantine::save(&po).await?;
+
+    let hash = hash.to_string();
+    let signature = signature.to_string();
+    tokio::spawn(async move {
+        log_if_err!(notify_admins(&hash, &signature).await);
+    });
+    Ok(())
+}
+
+async fn notify_admins(hash: &str, signature: &str) -> Result<()> {
+    let emails = &get_settings().file_system.scan.notify_emails;
+    let body = format!(
+        "<p>上传的文件被扫描出问题，已隔离</p><p>hash: {}</p><p>特征: {}</p>",
+        hash, signature
+    );
+    for to in emails {
+        email::enqueue(to, "文件已隔离", &body, None).await?;
+    }
+    Ok(())
+}
+
+/// 被隔离的内容不能下载
+pub(crate) async fn is_quarantined(hash: &str) -> Result<bool> {
+    repo_quarantine::is_quarantined(hash).await
+}
@@ -1,7 +1,12 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, io, path::PathBuf};
 
+use crate::application::file_system::disk_space::{self, Reservation};
+use crate::application::file_system::{edit_lock, share};
+use crate::application::outbox;
+use crate::domain::file_system::change::FileChange;
 use crate::domain::file_system::file::{FileNodeMetaData, FileOperateErr::*};
-use crate::domain::file_system::service::path_manager;
+use crate::domain::file_system::service::{path_manager, PathManager};
+use crate::domain::file_system::share::FileOp;
 use crate::infrastructure::av1_factory;
 use crate::{
     biz_ok,
@@ -12,21 +17,27 @@ use crate::{
     ensure_biz, ensure_exist,
     http::BizResult,
     infrastructure::{
-        file_sys,
+        file_sys, repo_file_change, repo_file_lock, repo_path_history, repo_quarantine,
         repo_user_file::{self, load_tree, load_tree_all},
     },
     pg_tx,
 };
+use actix_web::web::Bytes;
 use anyhow::{bail, ensure, Context, Result};
+use futures_util::Stream;
 use serde::Serialize;
 use tracing::debug;
 use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DirTree {
     pub id: UserFileId,
     pub name: String,
+    /// 固定目录在各语言下的显示名，其他目录为空
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub display_names: HashMap<String, String>,
     pub children: Vec<DirTree>,
 }
 
@@ -41,9 +52,15 @@ impl DirTree {
             bail!("tree has no children");
         };
 
+        let display_names = tree
+            .path()
+            .as_home_dir()
+            .map(|dir| dir.display_names.clone())
+            .unwrap_or_default();
         Ok(Self {
             id: *tree.id(),
             name: tree.file_name().to_string(),
+            display_names,
             children,
         })
     }
@@ -54,7 +71,19 @@ pub async fn load_home(user_id: UserId) -> Result<DirTree> {
     let tree = repo_user_file::load_tree_struct(&root).await?;
 
     let tree = match tree {
-        Some(tree) => tree,
+        Some(mut tree) => {
+            let created = tree.add_missing_home_dirs();
+            if !created.is_empty() {
+                debug!(count = created.len(), "create missing home dirs");
+                let conn = &mut pg_conn().await?;
+                for dir in &created {
+                    let _ = repo_user_file::save_node(dir, conn).await?;
+                    file_sys::create_dir(dir.path()).await?;
+                }
+                tree.sort_by_name();
+            }
+            tree
+        }
         None => {
             debug!("create user home");
             let tree = FileNode::user_home(user_id);
@@ -87,67 +116,110 @@ pub async fn create_dir_tx(
     name: &str,
     conn: &mut PgConn,
 ) -> BizResult<UserFileId, FileOperateErr> {
+    let owner = ensure_exist!(
+        share::owner_for(user_id, dir_id, FileOp::Write, conn).await?,
+        NoParent
+    );
     let mut parent = ensure_exist!(
-        repo_user_file::load_tree_dep2((user_id, dir_id), conn).await?,
+        repo_user_file::load_tree_dep2((owner, dir_id), conn).await?,
         NoParent
     );
     let child = ensure_biz!(parent.create_dir(name));
 
     let _ = repo_user_file::save_node(child, conn).await?;
+    repo_file_change::insert(&FileChange::created(child), conn).await?;
 
     file_sys::create_dir(child.path()).await?;
 
     biz_ok!(*child.id())
 }
 
-pub async fn delete(user_id: UserId, file_ids: Vec<UserFileId>) -> BizResult<(), FileOperateErr> {
-    pg_tx!(delete_tx, user_id, file_ids)
+/// member_id 为实际操作的用户，在组织空间中与空间的账号 user_id 不同，用于检查文件锁
+pub async fn delete(
+    user_id: UserId,
+    member_id: UserId,
+    file_ids: Vec<UserFileId>,
+) -> BizResult<(), FileOperateErr> {
+    pg_tx!(delete_tx, user_id, member_id, file_ids)
 }
 
 pub async fn delete_tx(
     user_id: UserId,
+    member_id: UserId,
     file_ids: Vec<UserFileId>,
     conn: &mut PgConn,
 ) -> BizResult<(), FileOperateErr> {
     for file_id in file_ids {
-        let mut node = ensure_exist!(
-            repo_user_file::load_tree_all((user_id, file_id), conn).await?,
-            NotFound
-        );
-        let old_path = node.path().clone();
-        ensure_biz!(node.delete());
-
-        let effected = repo_user_file::update(&node, conn).await?.is_effected();
-        ensure!(effected, "delete node failed");
-
+        let old_path = ensure_biz!(delete_one(user_id, member_id, file_id, conn).await?);
         file_sys::virtual_delete(&old_path).await?;
     }
 
     biz_ok!(())
 }
 
+/// 在数据库中删除一个文件，返回它原来的路径，磁盘上的操作由调用方执行
+pub(super) async fn delete_one(
+    user_id: UserId,
+    member_id: UserId,
+    file_id: UserFileId,
+    conn: &mut PgConn,
+) -> BizResult<VirtualPath, FileOperateErr> {
+    let owner = ensure_exist!(
+        share::owner_for(user_id, file_id, FileOp::Modify, conn).await?,
+        NotFound
+    );
+    let mut node = ensure_exist!(
+        repo_user_file::load_tree_all((owner, file_id), conn).await?,
+        NotFound
+    );
+    ensure_biz!(!is_busy(&node, conn).await?, FileBusy);
+    ensure_biz!(edit_lock::check_tree(member_id, &node, conn).await?);
+    let old_path = node.path().clone();
+    ensure_biz!(node.delete());
+
+    let effected = repo_user_file::update(&node, conn).await?.is_effected();
+    ensure!(effected, "delete node failed");
+    repo_file_change::insert(&FileChange::deleted(&node, &old_path), conn).await?;
+
+    biz_ok!(old_path)
+}
+
 pub async fn rename(
     user_id: UserId,
+    member_id: UserId,
     file_id: UserFileId,
     new_name: &str,
 ) -> BizResult<(), FileOperateErr> {
-    pg_tx!(rename_tx, user_id, file_id, new_name)
+    pg_tx!(rename_tx, user_id, member_id, file_id, new_name)
 }
 
 pub async fn rename_tx(
     user_id: UserId,
+    member_id: UserId,
     file_id: UserFileId,
     new_name: &str,
     conn: &mut PgConn,
 ) -> BizResult<(), FileOperateErr> {
+    let owner = ensure_exist!(
+        share::owner_for(user_id, file_id, FileOp::Modify, conn).await?,
+        NotFound
+    );
     let mut node = ensure_exist!(
-        repo_user_file::find_node((user_id, file_id), conn).await?,
+        repo_user_file::find_node((owner, file_id), conn).await?,
         NotFound
     );
+    if node.is_dir() {
+        let tree = ensure_exist!(load_tree_all((owner, file_id), conn).await?, NotFound);
+        ensure_biz!(!is_busy(&tree, conn).await?, FileBusy);
+        ensure_biz!(edit_lock::check_tree(member_id, &tree, conn).await?);
+    } else {
+        ensure_biz!(!is_busy(&node, conn).await?, FileBusy);
+        ensure_biz!(edit_lock::check(member_id, file_id, conn).await?);
+    }
     let parent_id = ensure_exist!(node.parent_id(), NotFound);
 
     let parent = ensure_exist!(
-        repo_user_file::load_tree((user_id, *parent_id), 2, conn).await?,
+        repo_user_file::load_tree((owner, *parent_id), 2, conn).await?,
         NotFound
     );
 
@@ -156,6 +228,8 @@ pub async fn rename_tx(
     let new_path = node.path().clone();
 
     let _ = repo_user_file::update(&node, conn).await?;
+    repo_file_change::insert(&FileChange::renamed(&node, &old_path), conn).await?;
+    repo_path_history::insert(&node, &old_path, conn).await?;
 
     file_sys::virtual_move(&old_path, &new_path).await?;
 
@@ -164,38 +238,62 @@ pub async fn rename_tx(
 
 pub async fn move_to(
     user_id: UserId,
+    member_id: UserId,
     file_id: Vec<UserFileId>,
     new_parent_id: UserFileId,
 ) -> BizResult<(), FileOperateErr> {
-    pg_tx!(move_to_tx, user_id, file_id, new_parent_id)
+    pg_tx!(move_to_tx, user_id, member_id, file_id, new_parent_id)
 }
 
 pub async fn move_to_tx(
     user_id: UserId,
+    member_id: UserId,
     file_ids: Vec<UserFileId>,
     new_parent_id: UserFileId,
     conn: &mut PgConn,
 ) -> BizResult<(), FileOperateErr> {
-    let mut new_parent = ensure_exist!(
-        load_tree((user_id, new_parent_id), 2, conn).await?,
+    let owner = ensure_exist!(
+        share::owner_for(user_id, new_parent_id, FileOp::Write, conn).await?,
         NotFound
     );
+    let mut new_parent = ensure_exist!(load_tree((owner, new_parent_id), 2, conn).await?, NotFound);
     for file_id in file_ids {
-        let origin_node = ensure_exist!(load_tree_all((user_id, file_id), conn).await?, NotFound);
-        let old_path = origin_node.path().clone();
-        let moved_node = ensure_biz!(origin_node.move_to(&mut new_parent));
-
-        let effected = repo_user_file::update(&moved_node, conn)
-            .await?
-            .is_all_effected();
-        ensure!(effected, "move node failed");
-
-        file_sys::virtual_move(&old_path, moved_node.path()).await?;
+        let (old_path, new_path) =
+            ensure_biz!(move_one(user_id, member_id, file_id, &mut new_parent, conn).await?);
+        file_sys::virtual_move(&old_path, &new_path).await?;
     }
 
     biz_ok!(())
 }
 
+/// 在数据库中移动一个文件，返回原来和现在的路径，磁盘上的操作由调用方执行。
+/// 只能在同一个所有者的文件之间移动
+pub(super) async fn move_one(
+    user_id: UserId,
+    member_id: UserId,
+    file_id: UserFileId,
+    new_parent: &mut FileNode,
+    conn: &mut PgConn,
+) -> BizResult<(VirtualPath, VirtualPath), FileOperateErr> {
+    let owner = new_parent.path().user_id();
+    let file_owner = share::owner_for(user_id, file_id, FileOp::Modify, conn).await?;
+    ensure_biz!(file_owner == Some(owner), NotFound);
+    let origin_node = ensure_exist!(load_tree_all((owner, file_id), conn).await?, NotFound);
+    ensure_biz!(!is_busy(&origin_node, conn).await?, FileBusy);
+    ensure_biz!(edit_lock::check_tree(member_id, &origin_node, conn).await?);
+    let old_path = origin_node.path().clone();
+    let moved_node = ensure_biz!(origin_node.move_to(new_parent));
+
+    let effected = repo_user_file::update(moved_node, conn)
+        .await?
+        .is_all_effected();
+    ensure!(effected, "move node failed");
+    repo_file_change::insert(&FileChange::moved(moved_node, &old_path), conn).await?;
+    repo_path_history::insert(moved_node, &old_path, conn).await?;
+
+    biz_ok!((old_path, moved_node.path().clone()))
+}
+
 pub async fn copy_to(
     user_id: UserId,
     file_id: Vec<UserFileId>,
@@ -210,24 +308,119 @@ pub async fn copy_to_tx(
     new_parent_id: UserFileId,
     conn: &mut PgConn,
 ) -> BizResult<(), FileOperateErr> {
-    let mut new_parent = ensure_exist!(
-        load_tree((user_id, new_parent_id), 2, conn).await?,
+    let owner = ensure_exist!(
+        share::owner_for(user_id, new_parent_id, FileOp::Write, conn).await?,
         NotFound
     );
+    let mut new_parent = ensure_exist!(load_tree((owner, new_parent_id), 2, conn).await?, NotFound);
 
     for file_id in file_ids {
-        let origin_node = ensure_exist!(load_tree_all((user_id, file_id), conn).await?, NotFound);
+        let (from, to, _reservation) =
+            ensure_biz!(copy_one(user_id, file_id, &mut new_parent, conn).await?);
+        file_sys::virtual_copy(&from, &to).await?;
+    }
+
+    biz_ok!(())
+}
+
+/// 在数据库中复制一个文件，返回源路径、新路径和登记的磁盘空间，磁盘上的操作由调用方执行。
+/// 只能在同一个所有者的文件之间复制
+pub(super) async fn copy_one(
+    user_id: UserId,
+    file_id: UserFileId,
+    new_parent: &mut FileNode,
+    conn: &mut PgConn,
+) -> BizResult<(VirtualPath, VirtualPath, Reservation), FileOperateErr> {
+    let owner = new_parent.path().user_id();
+    let file_owner = share::owner_for(user_id, file_id, FileOp::Read, conn).await?;
+    ensure_biz!(file_owner == Some(owner), NotFound);
+    let origin_node = ensure_exist!(load_tree_all((owner, file_id), conn).await?, NotFound);
+
+    // 用户文件大多是指向归档文件的软链接，只有普通文件需要占用空间
+    let size = file_sys::disk_usage(&PathManager::virtual_to_disk(origin_node.path())).await?;
+    let target_dir = PathManager::virtual_to_disk(new_parent.path());
+    let reservation = ensure_exist!(
+        disk_space::reserve(&[&target_dir], size)?,
+        InsufficientStorage
+    );
+
+    let new_node = ensure_biz!(origin_node.copy_to(new_parent));
+    let effected = repo_user_file::save_node(new_node, conn)
+        .await?
+        .is_all_effected();
+    ensure!(effected, "copy node failed");
+    repo_file_change::insert(&FileChange::created(new_node), conn).await?;
+
+    biz_ok!((
+        origin_node.path().clone(),
+        new_node.path().clone(),
+        reservation
+    ))
+}
+
+/// 整棵树中是否有文件正在被转码任务使用
+async fn is_busy(tree: &FileNode, conn: &mut PgConn) -> Result<bool> {
+    let file_ids: Vec<_> = tree.all_files().iter().map(|file| *file.id()).collect();
+    repo_file_lock::any_locked(&file_ids, conn).await
+}
 
-        let new_node = ensure_biz!(origin_node.copy_to(&mut new_parent));
-        let effected = repo_user_file::save_node(&new_node, conn)
-            .await?
-            .is_all_effected();
-        ensure!(effected, "copy node failed");
+/// 打包下载目录时，压缩包中的文件
+pub struct DirArchive {
+    pub name: String,
+    /// (压缩包内的路径, 磁盘路径)
+    pub entries: Vec<(String, PathBuf)>,
+}
 
-        file_sys::virtual_copy(origin_node.path(), new_node.path()).await?;
+impl DirArchive {
+    pub fn into_stream(self) -> impl Stream<Item = io::Result<Bytes>> {
+        file_sys::zip_stream(self.entries)
     }
+}
 
-    biz_ok!(())
+pub enum DownloadDirErr {
+    NotFound,
+    NotDir,
+}
+
+pub async fn dir_archive(
+    user_id: UserId,
+    dir_id: UserFileId,
+) -> BizResult<DirArchive, DownloadDirErr> {
+    let conn = &mut pg_conn().await?;
+    let owner = ensure_exist!(
+        share::owner_for(user_id, dir_id, FileOp::Read, conn).await?,
+        DownloadDirErr::NotFound
+    );
+    let dir = ensure_exist!(
+        repo_user_file::load_tree_all_for_read((owner, dir_id), conn).await?,
+        DownloadDirErr::NotFound
+    );
+    ensure_biz!(dir.is_dir(), DownloadDirErr::NotDir);
+
+    // 被隔离的文件没有软链接，不放入压缩包
+    let files = dir.all_files();
+    let hashes: Vec<_> = files
+        .iter()
+        .filter_map(|file| Some(&*file.file_data()?.hash))
+        .collect();
+    let quarantined = repo_quarantine::filter_quarantined(&hashes).await?;
+
+    let entries: Vec<_> = files
+        .into_iter()
+        .filter_map(|file| {
+            let data = file.file_data()?;
+            if quarantined.contains(&data.hash) {
+                return None;
+            }
+            let name = file.path().relative_to(dir.path())?.into_owned();
+            Some((name, PathManager::virtual_to_disk(file.path())))
+        })
+        .collect();
+
+    biz_ok!(DirArchive {
+        name: format!("{}.zip", dir.file_name()),
+        entries,
+    })
 }
 
 pub async fn thumbnail_names(file_id: UserFileId) -> Result<Option<(String, Vec<String>)>> {
@@ -245,11 +438,12 @@ pub fn thumbnail_path(hash: &str, file_name: &str) -> PathBuf {
     dir.join(file_name)
 }
 
+/// 把转码结果保存为用户文件，返回文件的 hash
 pub(crate) async fn create_user_file(
     src_path: PathBuf,
     dst_path: VirtualPath,
     conn: &mut PgConn,
-) -> Result<()> {
+) -> Result<String> {
     debug!("create user file");
     let mut parent = dst_path.parent().expect("dst path must have parent");
     let mut dir = loop {
@@ -266,6 +460,7 @@ pub(crate) async fn create_user_file(
         anyhow::anyhow!("file not found: {}", src_path.to_string_lossy().to_string())
     })?;
     let archived_path = path_manager().archived_path(&metadata.hash);
+    let hash = metadata.hash.clone();
     let metadata = FileNodeMetaData::new(metadata.size, metadata.hash, archived_path);
     let sys_file_id = metadata.id;
     let thumbnail_dir = path_manager().thumbnail_dir(&metadata.hash);
@@ -274,16 +469,24 @@ pub(crate) async fn create_user_file(
     file_sys::move_to(&src_path, &metadata.archived_path).await?;
 
     debug!("send parse req");
-    av1_factory::parse_file(sys_file_id, &metadata.archived_path)
+    let task = av1_factory::parse_task(sys_file_id, &metadata.archived_path)?;
+    outbox::send_or_enqueue(task)
         .await
         .context("send parse req")?;
     debug!("send thumbnail req");
-    av1_factory::generate_thumbnail(sys_file_id, &metadata.archived_path, &thumbnail_dir)
+    let task = av1_factory::thumbnail_task(sys_file_id, &metadata.archived_path, &thumbnail_dir)?;
+    outbox::send_or_enqueue(task)
         .await
         .context("send thumbnail req")?;
 
-    let _ = parent.create_file(dst_path.file_name(), metadata);
+    let created = parent
+        .create_file(dst_path.file_name(), metadata)
+        .ok()
+        .map(|file| FileChange::created(file));
     let _ = repo_user_file::save_node(&dir, conn).await?;
+    if let Some(change) = created {
+        repo_file_change::insert(&change, conn).await?;
+    }
 
-    Ok(())
+    Ok(hash)
 }
@@ -0,0 +1,182 @@
+//! 共享文件夹
+//!
+//! 文件系统的各项操作先通过 [`owner_for`] 检查权限，再以文件所有者的身份执行，
+//! 成员在共享文件夹中上传的文件属于文件夹的所有者，占用所有者的存储配额
+
+use anyhow::Result;
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, pg_conn_read, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    application::activity,
+    biz_err, biz_ok,
+    domain::{
+        activity::ActivityEvent,
+        file_system::{
+            file::{UserFileId, VirtualPath},
+            share::{self, AccessLevel, FileOp, ShareErr, SharePermission},
+        },
+        user::{user::UserId, Email},
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_folder_share, repo_user, repo_user_file},
+    log_if_err,
+};
+
+/// 用户能否对文件执行 op，可以时返回文件的所有者，操作应以所有者的身份执行。
+/// 文件不存在或没有权限时为空
+pub(crate) async fn owner_for(
+    user_id: UserId,
+    file_id: UserFileId,
+    op: FileOp,
+    conn: &mut PgConn,
+) -> Result<Option<UserId>> {
+    let Some(node) = repo_user_file::find_node_for_read(file_id, conn).await? else {
+        return Ok(None);
+    };
+    let owner = node.path().user_id();
+    if owner == user_id {
+        return Ok(Some(owner));
+    }
+    let shares = granted_paths(owner, user_id, conn).await?;
+    Ok(AccessLevel::permits(user_id, node.path(), &shares, op).then_some(owner))
+}
+
+/// 浏览目录时使用的所有者，没有权限时为空
+pub async fn readable_owner(user_id: UserId, dir_id: UserFileId) -> Result<Option<UserId>> {
+    let conn = &mut pg_conn_read().await?;
+    owner_for(user_id, dir_id, FileOp::Read, conn).await
+}
+
+/// 用户对文件的访问级别，没有权限时为空
+pub async fn access_level(user_id: UserId, file_id: UserFileId) -> Result<Option<AccessLevel>> {
+    let conn = &mut pg_conn_read().await?;
+    let Some(node) = repo_user_file::find_node_for_read(file_id, conn).await? else {
+        return Ok(None);
+    };
+    let owner = node.path().user_id();
+    let shares = if owner == user_id {
+        vec![]
+    } else {
+        granted_paths(owner, user_id, conn).await?
+    };
+    Ok(AccessLevel::of(user_id, node.path(), &shares, false))
+}
+
+/// owner 分享给 member 的文件夹的当前路径，已删除的文件夹不再生效
+async fn granted_paths(
+    owner: UserId,
+    member: UserId,
+    conn: &mut PgConn,
+) -> Result<Vec<(VirtualPath, SharePermission)>> {
+    let granted = repo_folder_share::granted(owner, member, conn).await?;
+    let mut shares = Vec::with_capacity(granted.len());
+    for (dir_id, permission) in granted {
+        if let Some(dir) = repo_user_file::find_node_for_read(dir_id, conn).await? {
+            shares.push((dir.path().clone(), permission));
+        }
+    }
+    Ok(shares)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareFolderDto {
+    dir_id: UserFileId,
+    /// 成员的邮箱
+    email: String,
+    permission: SharePermission,
+}
+
+#[derive(From, Debug)]
+pub enum ShareFolderErr {
+    Share(ShareErr),
+    DirNotFound,
+    UserNotFound,
+}
+
+/// 分享文件夹，已经分享给该成员时修改权限
+pub async fn share_folder(owner: UserId, params: ShareFolderDto) -> BizResult<(), ShareFolderErr> {
+    let conn = &mut pg_conn().await?;
+    let dir = ensure_exist!(
+        repo_user_file::find_node_for_read((owner, params.dir_id), conn).await?,
+        ShareFolderErr::DirNotFound
+    );
+    let Ok(email) = Email::try_from(params.email) else {
+        return biz_err!(ShareFolderErr::UserNotFound);
+    };
+    let member = ensure_exist!(
+        repo_user::find(&email, conn).await?,
+        ShareFolderErr::UserNotFound
+    );
+    let member_id = *member.id();
+    ensure_biz!(share::check_share(owner, member_id, dir.is_dir()));
+
+    repo_folder_share::save(params.dir_id, owner, member_id, params.permission, conn).await?;
+
+    let owner_name = repo_user::find(owner, conn)
+        .await?
+        .map(|owner| owner.name().to_string())
+        .unwrap_or_default();
+    let event = ActivityEvent::FileShared {
+        file_path: dir.path().to_str().into_owned(),
+        owner_name,
+    };
+    log_if_err!(activity::record(member_id, event).await);
+    biz_ok!(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnshareFolderDto {
+    dir_id: UserFileId,
+    member_id: UserId,
+}
+
+pub enum UnshareFolderErr {
+    NotShared,
+}
+
+pub async fn unshare_folder(
+    owner: UserId,
+    params: UnshareFolderDto,
+) -> BizResult<(), UnshareFolderErr> {
+    let conn = &mut pg_conn().await?;
+    let found = repo_folder_share::delete(owner, params.dir_id, params.member_id, conn).await?;
+    ensure_biz!(found, UnshareFolderErr::NotShared);
+    biz_ok!(())
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareMemberDto {
+    user_id: UserId,
+    email: String,
+    permission: SharePermission,
+}
+
+/// 文件夹的所有成员，只有所有者可以查看
+pub async fn members(
+    owner: UserId,
+    dir_id: UserFileId,
+) -> BizResult<Vec<ShareMemberDto>, ShareFolderErr> {
+    let conn = &mut pg_conn().await?;
+    let dir = repo_user_file::find_node_for_read((owner, dir_id), conn).await?;
+    ensure_biz!(dir.is_some(), ShareFolderErr::DirNotFound);
+
+    let mut members = vec![];
+    for (user_id, permission) in repo_folder_share::members(dir_id, conn).await? {
+        let Some(user) = repo_user::find(user_id, conn).await? else {
+            continue;
+        };
+        members.push(ShareMemberDto {
+            user_id,
+            email: user.email().to_string(),
+            permission,
+        });
+    }
+    biz_ok!(members)
+}
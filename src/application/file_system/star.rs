@@ -0,0 +1,39 @@
+use serde::Deserialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_star, repo_user_file},
+};
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StarDto {
+    file_id: UserFileId,
+}
+
+pub enum StarErr {
+    FileNotFound,
+}
+
+/// 收藏文件，重复收藏不会改变收藏时间
+pub async fn star(user_id: UserId, params: StarDto) -> BizResult<(), StarErr> {
+    let conn = &mut pg_conn().await?;
+    let file = ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, params.file_id), conn).await?,
+        StarErr::FileNotFound
+    );
+    repo_star::star(user_id, *file.id(), conn).await?;
+
+    biz_ok!(())
+}
+
+/// 取消收藏，未收藏时什么也不做
+pub async fn unstar(user_id: UserId, params: StarDto) -> anyhow::Result<()> {
+    let conn = &mut pg_conn().await?;
+    repo_star::unstar(user_id, params.file_id, conn).await
+}
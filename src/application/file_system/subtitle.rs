@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+use serde::Deserialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::{file::UserFileId, subtitle::SubtitleFormat},
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        repo_subtitle::{self, SubtitlePo},
+        repo_user_file,
+    },
+};
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachSubtitleDto {
+    video_id: UserFileId,
+    subtitle_id: UserFileId,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+pub enum AttachSubtitleErr {
+    VideoNotFound,
+    SubtitleNotFound,
+    NotAVideo,
+    NotSubtitle,
+}
+
+/// 将字幕文件关联到视频，重复关联时更新字幕语言
+pub async fn attach_subtitle(
+    user_id: UserId,
+    params: AttachSubtitleDto,
+) -> BizResult<(), AttachSubtitleErr> {
+    use AttachSubtitleErr::*;
+
+    let video = ensure_exist!(
+        repo_user_file::find_video(params.video_id).await?,
+        VideoNotFound
+    );
+    ensure_biz!(
+        *video.user_id() == user_id && !*video.deleted(),
+        VideoNotFound
+    );
+    let is_video = video
+        .file_data()
+        .is_some_and(|data| data.video_info.is_some());
+    ensure_biz!(is_video, NotAVideo);
+
+    let conn = &mut pg_conn().await?;
+    let subtitle = ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, params.subtitle_id), conn).await?,
+        SubtitleNotFound
+    );
+    ensure_biz!(SubtitleFormat::from_file(&subtitle).is_some(), NotSubtitle);
+
+    let po = SubtitlePo {
+        video_file_id: *video.id(),
+        subtitle_file_id: *subtitle.id(),
+        user_id,
+        language: params.language.as_deref().map(Cow::Borrowed),
+    };
+    let _ = repo_subtitle::save(&po, conn).await?;
+
+    biz_ok!(())
+}
@@ -0,0 +1,94 @@
+use anyhow::anyhow;
+use derive_more::From;
+use serde::Deserialize;
+use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::{
+            file::UserFileId,
+            tag::{self, Tag, TagErr},
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_tag, repo_user_file},
+    pg_tx,
+};
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct FileTagDto {
+    file_id: UserFileId,
+    /// 标签名，首尾空白会被去掉
+    #[validate(length(min = 1, max = 32))]
+    tag: String,
+}
+
+#[derive(From, Debug)]
+pub enum AddTagErr {
+    Tag(TagErr),
+    FileNotFound,
+}
+
+/// 给文件添加标签，标签不存在时自动创建
+pub async fn add_tag(user_id: UserId, params: FileTagDto) -> BizResult<(), AddTagErr> {
+    pg_tx!(add_tag_tx, user_id, &params)
+}
+
+async fn add_tag_tx(
+    user_id: UserId,
+    params: &FileTagDto,
+    conn: &mut PgConn,
+) -> BizResult<(), AddTagErr> {
+    let file = ensure_exist!(
+        repo_user_file::find_node((user_id, params.file_id), conn).await?,
+        AddTagErr::FileNotFound
+    );
+
+    let new_tag = ensure_biz!(Tag::create(user_id, &params.tag));
+    repo_tag::save(&new_tag, conn).await?;
+    let tag = repo_tag::find_by_name(user_id, new_tag.name(), conn)
+        .await?
+        .ok_or_else(|| anyhow!("tag {} not found after saving", new_tag.name()))?;
+
+    let attached = repo_tag::count_attached(*file.id(), conn).await?;
+    ensure_biz!(tag::ensure_can_attach(attached));
+    repo_tag::attach(*file.id(), *tag.id(), conn).await?;
+
+    biz_ok!(())
+}
+
+pub enum RemoveTagErr {
+    FileNotFound,
+}
+
+/// 移除文件的标签，标签不再被使用时一并删除
+pub async fn remove_tag(user_id: UserId, params: FileTagDto) -> BizResult<(), RemoveTagErr> {
+    pg_tx!(remove_tag_tx, user_id, &params)
+}
+
+async fn remove_tag_tx(
+    user_id: UserId,
+    params: &FileTagDto,
+    conn: &mut PgConn,
+) -> BizResult<(), RemoveTagErr> {
+    let file = ensure_exist!(
+        repo_user_file::find_node((user_id, params.file_id), conn).await?,
+        RemoveTagErr::FileNotFound
+    );
+
+    let Ok(name) = tag::normalize_name(&params.tag) else {
+        return biz_ok!(());
+    };
+    if let Some(tag) = repo_tag::find_by_name(user_id, &name, conn).await? {
+        repo_tag::detach(*file.id(), *tag.id(), conn).await?;
+        repo_tag::delete_if_unused(*tag.id(), conn).await?;
+    }
+
+    biz_ok!(())
+}
@@ -1,25 +1,40 @@
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 use derive_more::From;
+use futures_util::Stream;
 use serde::{Deserialize, Serialize};
-use tracing::warn;
+use tracing::{info, warn};
 use utils::db_pools::postgres::pg_conn;
 use utils::db_pools::postgres::PgConn;
 use utils::log_if_err;
+use utoipa::ToSchema;
+use validator::Validate;
 
+use crate::application::{event_bus, notification, outbox, transcode_rule, user::tier};
+use crate::domain::event::UserFileCreated;
+use crate::domain::file_system::file::FileNode;
 use crate::domain::file_system::file::FileNodeMetaData;
 use crate::domain::file_system::file::FileOperateErr;
+use crate::domain::file_system::file::SysFileId;
 use crate::domain::file_system::file::UserFileId;
+use crate::domain::file_system::file::VirtualPath;
 use crate::domain::file_system::service_upload;
-use crate::domain::file_system::service_upload::UploadTaskId;
+use crate::domain::file_system::service_upload::{SliceLayout, UploadTaskId};
+use crate::domain::file_system::share::FileOp;
+use crate::domain::notification::NotificationEvent;
+use crate::http::validation::sha256_hex;
 use crate::infrastructure::av1_factory;
 use crate::pg_tx;
+use crate::settings::get_settings;
 use crate::{
-    biz_ok,
+    biz_err, biz_ok,
     domain::{
         file_system::{
-            service::path_manager,
+            service::{path_manager, PathManager},
             service_upload::{UploadTask, UploadTaskState},
         },
         user::user::UserId,
@@ -27,18 +42,26 @@ use crate::{
     ensure_biz, ensure_exist,
     http::BizResult,
     infrastructure::{
-        file_sys::{self, UploadFileSlice},
+        file_sys::{self, MergedFile, StoredSlice, UploadFileSlice},
         repo_upload_task, repo_user_file,
     },
 };
 
+use super::{disk_space, file_type, scan, share, version};
+
 #[derive(From, Debug)]
 pub enum RegisterUploadTaskErr {
     Create(service_upload::CreateTaskErr),
     NoParent,
+    FileTooLarge,
+    SliceTooLarge,
+    /// 超出用户等级的存储配额
+    QuotaExceeded,
+    /// 超出用户等级的上传频率
+    TooManyUploads,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RegisterUploadTaskResp {
     pub task_id: UploadTaskId,
@@ -46,17 +69,29 @@ pub struct RegisterUploadTaskResp {
     pub dst_path_existed: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct RegisterUploadTaskDto {
+    #[validate(custom = "sha256_hex")]
     hash: String,
     parent_id: UserFileId,
+    #[validate(length(min = 1, max = 255))]
     file_name: String,
+    /// 文件大小，上传的分片和合并后的文件都按此检查
+    #[validate(range(min = 1))]
+    file_size: u64,
+    /// 除最后一个分片外，每个分片的大小
+    #[validate(range(min = 1))]
+    slice_size: u64,
+    /// 覆盖同名文件，被覆盖的内容保存为历史版本。默认自动重命名
+    #[serde(default)]
+    overwrite: bool,
 }
 
-/// return upload-task-id
+/// return upload-task-id。member_id 为实际上传的用户，完成时覆盖同名文件需要检查它是否被其他成员锁定
 pub async fn register_upload_task(
     user_id: UserId,
+    member_id: UserId,
     task: RegisterUploadTaskDto,
 ) -> BizResult<RegisterUploadTaskResp, RegisterUploadTaskErr> {
     use RegisterUploadTaskErr::*;
@@ -64,15 +99,35 @@ pub async fn register_upload_task(
     let conn = &mut pg_conn().await?;
     // create task
     let parent = ensure_exist!(
-        repo_user_file::find_node(task.parent_id, conn).await?,
+        repo_user_file::find_node_for_read(task.parent_id, conn).await?,
         NoParent
     );
-    ensure_biz!(*parent.user_id() == user_id, NoParent);
+    // 在共享文件夹中上传时，文件属于文件夹的所有者
+    let owner = *parent.user_id();
+    let permitted = share::owner_for(user_id, task.parent_id, FileOp::Write, conn).await?;
+    ensure_biz!(permitted == Some(owner), NoParent);
+
+    let cfg = &get_settings().file_system;
+    ensure_biz!(task.file_size <= cfg.max_file_size, FileTooLarge);
+    ensure_biz!(task.slice_size <= cfg.max_slice_size, SliceTooLarge);
+    // 提前按声明的大小检查配额，完成上传时还会按实际大小检查
+    ensure_biz!(
+        not tier::exceeds_quota(owner, task.file_size, conn).await?,
+        QuotaExceeded
+    );
+    ensure_biz!(tier::try_upload(user_id).await?, TooManyUploads);
 
+    let layout = SliceLayout {
+        file_size: task.file_size,
+        slice_size: task.slice_size,
+    };
     let task = ensure_biz!(service_upload::create_task(
+        member_id,
         &parent,
         &task.file_name,
-        task.hash
+        task.hash,
+        layout,
+        task.overwrite
     ));
 
     let conn = &mut pg_conn().await?;
@@ -94,14 +149,40 @@ pub async fn register_upload_task(
     })
 }
 
-#[derive(Serialize)]
+/// 上传的大小限制，客户端可以在上传前检查
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadLimits {
+    /// 分片上传的文件的最大字节数
+    max_file_size: u64,
+    /// 单个分片的最大字节数
+    max_slice_size: u64,
+    /// 通过 upload_small 一次性上传的文件的最大字节数
+    small_file_max_size: u64,
+}
+
+pub fn limits() -> UploadLimits {
+    let cfg = &get_settings().file_system;
+    UploadLimits {
+        max_file_size: cfg.max_file_size,
+        max_slice_size: cfg.max_slice_size,
+        small_file_max_size: cfg.small_file_max_size,
+    }
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadTaskDto {
     id: UploadTaskId,
     hash: String,
     file_name: String,
+    #[schema(value_type = Vec<u32>)]
     uploaded_slices: HashSet<u32>,
     dst_path: String,
+    /// 以下字段只在注册时声明了分片方式时返回
+    file_size: Option<u64>,
+    total_slices: Option<u64>,
+    bytes_remaining: Option<u64>,
 }
 
 impl UploadTaskDto {
@@ -112,6 +193,9 @@ impl UploadTaskDto {
             file_name: task.path().file_name().to_string(),
             uploaded_slices: task.uploaded_slices().clone(),
             dst_path: task.path().to_str().into_owned(),
+            file_size: task.layout().as_ref().map(|l| l.file_size),
+            total_slices: task.layout().as_ref().map(SliceLayout::slice_count),
+            bytes_remaining: task.bytes_remaining(),
         }
     }
 }
@@ -131,20 +215,27 @@ pub async fn get_upload_tasks(tasks: HashSet<UploadTaskId>) -> anyhow::Result<Ve
 
 pub async fn clear_upload_tasks(tasks: HashSet<UploadTaskId>) -> anyhow::Result<()> {
     for task_id in tasks {
-        let Some(task) = repo_upload_task::find(task_id).await? else {
+        if !expire_task(task_id).await? {
             warn!(%task_id, "upload task not found");
-            continue;
-        };
-        repo_upload_task::delete(task_id).await?;
-        task_clear_bg(task);
+        }
     }
     Ok(())
 }
 
+/// 删除任务和已上传的分片，任务不存在时返回 false
+pub(crate) async fn expire_task(task_id: UploadTaskId) -> anyhow::Result<bool> {
+    let Some(task) = repo_upload_task::find(task_id).await? else {
+        return Ok(false);
+    };
+    repo_upload_task::delete(task_id).await?;
+    task_clear_bg(task);
+    Ok(true)
+}
+
 fn task_clear_bg(task: UploadTask) {
     let clear_process = async move {
         let slice_dir = path_manager().upload_slice_dir(*task.id());
-        file_sys::delete(&slice_dir).await?;
+        file_sys::clear_slices(&slice_dir).await?;
 
         anyhow::Ok(())
     };
@@ -153,31 +244,86 @@ fn task_clear_bg(task: UploadTask) {
 
 pub enum StoreSliceErr {
     NoTask,
+    IndexOutOfRange,
+    TooLarge,
+    /// 与注册时声明的分片大小不一致
+    WrongSize,
 }
 
-pub async fn store_slice(
+pub async fn store_slice<S, B>(
     task_id: UploadTaskId,
     index: u32,
-    data: &[u8],
-) -> BizResult<(), StoreSliceErr> {
+    data: S,
+) -> BizResult<(), StoreSliceErr>
+where
+    S: Stream<Item = anyhow::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
     let mut task = ensure_exist!(
         repo_upload_task::find(task_id).await?,
         StoreSliceErr::NoTask
     );
+    ensure_biz!(task.is_valid_slice(index), StoreSliceErr::IndexOutOfRange);
     let dir = path_manager().upload_slice_dir(task_id);
     let slice = UploadFileSlice {
         index,
         data,
         dir: &dir,
+        max_size: get_settings().file_system.max_slice_size,
+        expected_size: task.layout().as_ref().map(|l| l.slice_len(index)),
     };
-    file_sys::store_slice(slice).await?;
+    match file_sys::store_slice(slice).await? {
+        StoredSlice::Stored => {}
+        StoredSlice::TooLarge => return biz_err!(StoreSliceErr::TooLarge),
+        StoredSlice::WrongSize => return biz_err!(StoreSliceErr::WrongSize),
+    }
     task.slice_done(index);
     repo_upload_task::update(&task).await?;
 
     biz_ok!(())
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadStatusDto {
+    task_id: UploadTaskId,
+    completed: bool,
+    uploaded_count: u32,
+    /// 以下字段只在注册时声明了分片方式时返回
+    total_slices: Option<u64>,
+    missing_slices: Option<Vec<u32>>,
+    bytes_remaining: Option<u64>,
+}
+
+pub enum UploadStatusErr {
+    NoTask,
+}
+
+pub async fn upload_status(
+    user_id: UserId,
+    task_id: UploadTaskId,
+) -> BizResult<UploadStatusDto, UploadStatusErr> {
+    let task = ensure_exist!(
+        repo_upload_task::find(task_id).await?,
+        UploadStatusErr::NoTask
+    );
+    if *task.user_id() != user_id {
+        let conn = &mut pg_conn().await?;
+        let owner = share::owner_for(user_id, *task.parent_dir_id(), FileOp::Write, conn).await?;
+        ensure_biz!(owner == Some(*task.user_id()), UploadStatusErr::NoTask);
+    }
+
+    biz_ok!(UploadStatusDto {
+        task_id,
+        completed: task.is_completed(),
+        uploaded_count: task.uploaded_slices().len() as u32,
+        total_slices: task.layout().as_ref().map(SliceLayout::slice_count),
+        missing_slices: task.missing_slices(),
+        bytes_remaining: task.bytes_remaining(),
+    })
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadedUserFile {
     new_name: Option<String>,
@@ -191,28 +337,65 @@ pub enum FinishUploadTaskErr {
     NoParent,
     NoSlice,
     NoTask,
+    /// 识别出的文件类型
+    TypeNotAllowed(String),
+    FileTooLarge,
+    /// 磁盘空间不足，稍后可以重试
+    SysBusy,
+    QuotaExceeded,
+    /// 其他请求正在合并，稍后可以重试
+    Merging,
+    /// 还有分片没有上传
+    MissingSlices(Vec<u32>),
+    /// 分片的总大小与声明的文件大小不一致
+    SizeNotMatch,
 }
 
+/// 可以重试：合并中断后分片仍在磁盘上，重新调用会再次合并
 pub async fn upload_finished(
     task_id: UploadTaskId,
 ) -> BizResult<UploadedUserFile, FinishUploadTaskErr> {
-    pg_tx!(upload_finished_tx, task_id)
+    let lease = get_settings().file_system.merge_lease_secs;
+    let Some(_marker) = repo_upload_task::mark_merging(task_id, lease).await? else {
+        return biz_err!(FinishUploadTaskErr::Merging);
+    };
+    let (uploaded, user_id) = match pg_tx!(upload_finished_tx, task_id)? {
+        Ok(res) => res,
+        Err(err) => return biz_err!(err),
+    };
+    notification::wake(user_id);
+    biz_ok!(uploaded)
 }
 
+/// 删除进程在合并中退出时留下的临时文件
+pub(crate) fn spawn_merge_cleanup() {
+    tokio::spawn(async move {
+        let lease = Duration::from_secs(get_settings().file_system.merge_lease_secs);
+        let before = SystemTime::now() - lease;
+        let root = path_manager().archive_root();
+        match file_sys::clean_merging_files(root, before).await {
+            Ok((0, _)) => {}
+            Ok((count, size)) => info!(count, size, "interrupted merge files removed"),
+            Err(err) => warn!(?err, "clean interrupted merge files failed"),
+        }
+    });
+}
+
+/// 同时返回收到通知的用户
 pub async fn upload_finished_tx(
     task_id: UploadTaskId,
     conn: &mut PgConn,
-) -> BizResult<UploadedUserFile, FinishUploadTaskErr> {
+) -> BizResult<(UploadedUserFile, UserId), FinishUploadTaskErr> {
     use FinishUploadTaskErr::*;
-    // TODO: get lock
 
     // load & check task
     let task = ensure_exist!(repo_upload_task::find(task_id).await?, NoTask);
     if let UploadTaskState::Completed(file_id) = task.state() {
-        return biz_ok!(UploadedUserFile {
+        let uploaded = UploadedUserFile {
             new_name: None,
-            file_id: file_id.to_string()
-        });
+            file_id: file_id.to_string(),
+        };
+        return biz_ok!((uploaded, *task.user_id()));
     }
 
     // load parent
@@ -224,32 +407,38 @@ pub async fn upload_finished_tx(
 
     // generate user file
     let file_data = ensure_biz!(load_sys_file(&task).await?);
+    ensure_biz!(
+        not tier::exceeds_quota(*task.user_id(), file_data.size, conn).await?,
+        QuotaExceeded
+    );
     let sys_file_id = file_data.id;
     let file_data_path = file_data.archived_path.clone();
-    let thumbnail_dir = path_manager().thumbnail_dir(&file_data.hash);
-    let file = ensure_biz!(parent.create_file(&task.path().file_name(), file_data));
+    let hash = file_data.hash.clone();
+    let file = ensure_biz!(
+        version::place_file(
+            task.uploader_id().unwrap_or(*task.user_id()),
+            &mut parent,
+            task.path().file_name(),
+            file_data,
+            *task.overwrite(),
+            conn
+        )
+        .await?
+    );
+
+    transcode_rule::on_uploaded(&file, conn).await?;
 
     let new_name = file.file_name() != task.path().file_name();
     let new_name = new_name.then(|| file.file_name().to_string());
-    let _effected = repo_user_file::save_node(&file, conn).await?;
+    let event = NotificationEvent::UploadFinished {
+        file_id: *file.id(),
+        file_name: file.file_name().to_string(),
+    };
+    notification::notify(*task.user_id(), event, conn).await?;
 
     // 以下操作不能回滚，要注意顺序，以保证这个函数的幂等性
 
-    // 为用户创建文件软链接
-    file_sys::create_user_link(&file_data_path, file.path()).await?;
-
-    // 发送信息采集的请求
-    // FIXME: 为了不影响正常的流程，暂时异步请求
-    tokio::spawn(async move {
-        log_if_err!(av1_factory::parse_file(sys_file_id, &file_data_path)
-            .await
-            .context("send parse req"));
-        log_if_err!(
-            av1_factory::generate_thumbnail(sys_file_id, &file_data_path, &thumbnail_dir)
-                .await
-                .context("send thumbnail req")
-        );
-    });
+    on_user_file_created(sys_file_id, &hash, file_data_path, file.path()).await?;
 
     // 更新 task 状态，必须是最后一个可能失败的操作
     let mut task = task;
@@ -257,8 +446,296 @@ pub async fn upload_finished_tx(
     repo_upload_task::update(&task).await?;
 
     // 确保前面的操作都成功后，异步执行清理操作
+    let user_id = *task.user_id();
     task_clear_bg(task);
 
+    let uploaded = UploadedUserFile {
+        new_name,
+        file_id: file.id().to_string(),
+    };
+    biz_ok!((uploaded, user_id))
+}
+
+/// 为用户创建文件软链接，并发送信息采集的请求。被隔离的内容不创建软链接，用户无法下载
+pub(crate) async fn on_user_file_created(
+    sys_file_id: SysFileId,
+    hash: &str,
+    file_data_path: PathBuf,
+    user_path: &VirtualPath,
+) -> anyhow::Result<()> {
+    if scan::is_quarantined(hash).await? {
+        warn!(%sys_file_id, ?user_path, "file content quarantined, skip user link");
+        // 覆盖上传时，删除仍指向原内容的软链接
+        file_sys::delete(&PathManager::virtual_to_disk(user_path)).await?;
+        return Ok(());
+    }
+
+    // 为用户创建文件软链接
+    file_sys::create_user_link(&file_data_path, user_path).await?;
+
+    event_bus::publish(UserFileCreated {
+        sys_file_id,
+        hash: hash.to_string(),
+        archived_path: file_data_path,
+        user_path: user_path.clone(),
+    });
+
+    Ok(())
+}
+
+pub(crate) fn subscribe_events() {
+    event_bus::subscribe("collect-file-info", collect_file_info);
+}
+
+/// 发送信息采集和生成缩略图的请求
+async fn collect_file_info(event: Arc<UserFileCreated>) -> anyhow::Result<()> {
+    let thumbnail_dir = path_manager().thumbnail_dir(&event.hash);
+    let task = av1_factory::parse_task(event.sys_file_id, &event.archived_path)?;
+    log_if_err!(outbox::send_or_enqueue(task)
+        .await
+        .context("send parse req"));
+    let task =
+        av1_factory::thumbnail_task(event.sys_file_id, &event.archived_path, &thumbnail_dir)?;
+    outbox::send_or_enqueue(task)
+        .await
+        .context("send thumbnail req")
+}
+
+#[derive(From, Debug)]
+pub enum UploadSmallErr {
+    FsDomain(FileOperateErr),
+    NoParent,
+    TooLarge,
+    /// 识别出的文件类型
+    TypeNotAllowed(String),
+    QuotaExceeded,
+    TooManyUploads,
+}
+
+/// 一次性上传小文件，不需要注册上传任务。member_id 为实际上传的用户，覆盖时用于检查文件锁
+pub async fn upload_small<S, B>(
+    user_id: UserId,
+    member_id: UserId,
+    parent_id: UserFileId,
+    file_name: String,
+    overwrite: bool,
+    data: S,
+) -> BizResult<UploadedUserFile, UploadSmallErr>
+where
+    S: Stream<Item = anyhow::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    ensure_biz!(
+        tier::try_upload(user_id).await?,
+        UploadSmallErr::TooManyUploads
+    );
+    let max_size = get_settings().file_system.small_file_max_size;
+    let stored = ensure_exist!(
+        file_sys::store_small_file(data, max_size).await?,
+        UploadSmallErr::TooLarge
+    );
+    let mime = file_type::sniff_file(stored.tmp_file.path()).await?;
+    ensure_biz!(
+        file_type::is_allowed(user_id, mime).await?,
+        UploadSmallErr::TypeNotAllowed(mime.to_string())
+    );
+    // 归档前先检查，被拒绝的上传不会在存档目录留下文件。事务中还会再检查一次
+    {
+        let conn = &mut pg_conn().await?;
+        ensure_biz!(small_upload_target(user_id, parent_id, stored.size, conn).await?);
+    }
+
+    let file_data = archive_merged(stored).await?;
+
+    pg_tx!(
+        upload_small_tx,
+        user_id,
+        member_id,
+        parent_id,
+        &file_name,
+        overwrite,
+        file_data
+    )
+}
+
+/// 系统中已存在相同内容时直接复用，否则把临时文件归档
+pub(crate) async fn archive_merged(stored: MergedFile) -> anyhow::Result<FileNodeMetaData> {
+    if let Some(file) = repo_user_file::get_filenode_data(&stored.hash).await? {
+        return Ok(file);
+    }
+    let mime = file_type::sniff_file(stored.tmp_file.path()).await?;
+    scan::scan_merged(&stored).await?;
+    let path = path_manager().archived_path(&stored.hash);
+    let mut file = FileNodeMetaData::new(stored.size, stored.hash.clone(), path);
+    file.mime = Some(mime.to_string());
+    file_sys::create_dir_all(&file.archived_path.parent().unwrap()).await?;
+    stored
+        .persist(&file.archived_path, path_manager().archive_root())
+        .await?;
+    Ok(file)
+}
+
+async fn upload_small_tx(
+    user_id: UserId,
+    member_id: UserId,
+    parent_id: UserFileId,
+    file_name: &str,
+    overwrite: bool,
+    file_data: FileNodeMetaData,
+    conn: &mut PgConn,
+) -> BizResult<UploadedUserFile, UploadSmallErr> {
+    let mut parent =
+        ensure_biz!(small_upload_target(user_id, parent_id, file_data.size, conn).await?);
+
+    let sys_file_id = file_data.id;
+    let file_data_path = file_data.archived_path.clone();
+    let hash = file_data.hash.clone();
+    let file = ensure_biz!(
+        version::place_file(
+            member_id,
+            &mut parent,
+            file_name,
+            file_data,
+            overwrite,
+            conn
+        )
+        .await?
+    );
+
+    transcode_rule::on_uploaded(&file, conn).await?;
+
+    let new_name = file.file_name() != file_name;
+    let new_name = new_name.then(|| file.file_name().to_string());
+
+    on_user_file_created(sys_file_id, &hash, file_data_path, file.path()).await?;
+
+    biz_ok!(UploadedUserFile {
+        new_name,
+        file_id: file.id().to_string()
+    })
+}
+
+/// 检查写权限和配额，返回可以写入的父目录
+async fn small_upload_target(
+    user_id: UserId,
+    parent_id: UserFileId,
+    size: u64,
+    conn: &mut PgConn,
+) -> BizResult<FileNode, UploadSmallErr> {
+    let owner = ensure_exist!(
+        share::owner_for(user_id, parent_id, FileOp::Write, conn).await?,
+        UploadSmallErr::NoParent
+    );
+    let parent = ensure_exist!(
+        repo_user_file::load_tree_dep2((owner, parent_id), conn).await?,
+        UploadSmallErr::NoParent
+    );
+    ensure_biz!(
+        not tier::exceeds_quota(owner, size, conn).await?,
+        UploadSmallErr::QuotaExceeded
+    );
+    biz_ok!(parent)
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct InstantUploadDto {
+    #[validate(custom = "sha256_hex")]
+    hash: String,
+    parent_id: UserFileId,
+    #[validate(length(min = 1, max = 255))]
+    file_name: String,
+    /// 覆盖同名文件，被覆盖的内容保存为历史版本。默认自动重命名
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(From, Debug)]
+pub enum InstantUploadErr {
+    FsDomain(FileOperateErr),
+    HashNotExisted,
+    NoParent,
+    /// 识别出的文件类型
+    TypeNotAllowed(String),
+    QuotaExceeded,
+    TooManyUploads,
+}
+
+/// 秒传：系统中已存在相同 hash 的文件时，直接为用户创建文件，不需要上传分片。
+/// hash 不存在时返回 `HashNotExisted`，客户端应改为注册上传任务
+pub async fn instant_upload(
+    user_id: UserId,
+    member_id: UserId,
+    params: InstantUploadDto,
+) -> BizResult<UploadedUserFile, InstantUploadErr> {
+    let file_data = ensure_exist!(
+        repo_user_file::get_filenode_data(&params.hash).await?,
+        InstantUploadErr::HashNotExisted
+    );
+    ensure_biz!(
+        tier::try_upload(user_id).await?,
+        InstantUploadErr::TooManyUploads
+    );
+    let mime = file_type::archived_mime(&file_data).await?;
+    ensure_biz!(
+        file_type::is_allowed(user_id, &mime).await?,
+        InstantUploadErr::TypeNotAllowed(mime)
+    );
+
+    let resp = pg_tx!(instant_upload_tx, user_id, member_id, &params, file_data);
+    if matches!(resp, Ok(Ok(_))) {
+        notification::wake(user_id);
+    }
+    resp
+}
+
+async fn instant_upload_tx(
+    user_id: UserId,
+    member_id: UserId,
+    params: &InstantUploadDto,
+    file_data: FileNodeMetaData,
+    conn: &mut PgConn,
+) -> BizResult<UploadedUserFile, InstantUploadErr> {
+    let owner = ensure_exist!(
+        share::owner_for(user_id, params.parent_id, FileOp::Write, conn).await?,
+        InstantUploadErr::NoParent
+    );
+    let mut parent = ensure_exist!(
+        repo_user_file::load_tree_dep2((owner, params.parent_id), conn).await?,
+        InstantUploadErr::NoParent
+    );
+    ensure_biz!(
+        not tier::exceeds_quota(owner, file_data.size, conn).await?,
+        InstantUploadErr::QuotaExceeded
+    );
+
+    let sys_file_id = file_data.id;
+    let file_data_path = file_data.archived_path.clone();
+    let hash = file_data.hash.clone();
+    let file = ensure_biz!(
+        version::place_file(
+            member_id,
+            &mut parent,
+            &params.file_name,
+            file_data,
+            params.overwrite,
+            conn
+        )
+        .await?
+    );
+
+    transcode_rule::on_uploaded(&file, conn).await?;
+
+    let new_name = file.file_name() != params.file_name;
+    let new_name = new_name.then(|| file.file_name().to_string());
+    let event = NotificationEvent::UploadFinished {
+        file_id: *file.id(),
+        file_name: file.file_name().to_string(),
+    };
+    notification::notify(user_id, event, conn).await?;
+
+    on_user_file_created(sys_file_id, &hash, file_data_path, file.path()).await?;
+
     biz_ok!(UploadedUserFile {
         new_name,
         file_id: file.id().to_string()
@@ -270,18 +747,50 @@ async fn load_sys_file(task: &UploadTask) -> BizResult<FileNodeMetaData, FinishU
 
     if let Some(file) = repo_user_file::get_filenode_data(task.hash()).await? {
         // founded in repository
+        let mime = file_type::archived_mime(&file).await?;
+        ensure_biz!(
+            file_type::is_allowed(*task.user_id(), &mime).await?,
+            TypeNotAllowed(mime)
+        );
         biz_ok!(file)
     } else {
         // merge slices
         let slice_dir = path_manager().upload_slice_dir(*task.id());
-        let merged = ensure_exist!(file_sys::merge_slices(&slice_dir).await?, NoSlice);
+        // 合并前按声明的分片方式检查，不必等到计算 hash 后才发现上传不完整
+        let size = file_sys::slices_size(&slice_dir).await?;
+        if let Some(layout) = task.layout() {
+            let missing = task.missing_slices().unwrap_or_default();
+            ensure_biz!(missing.is_empty(), MissingSlices(missing));
+            ensure_biz!(size == layout.file_size, SizeNotMatch);
+        }
+        // 直接合并到归档目录所在的文件系统，之后重命名到归档路径
+        let archive_root = path_manager().archive_root();
+        let _reservation = ensure_exist!(disk_space::reserve(&[archive_root], size)?, SysBusy);
+        let merged = ensure_exist!(
+            file_sys::merge_slices(&slice_dir, archive_root).await?,
+            NoSlice
+        );
         // check hash
+        ensure_biz!(
+            merged.size <= get_settings().file_system.max_file_size,
+            FileTooLarge
+        );
         ensure_biz!(&merged.hash == task.hash(), HashNotMatch);
+        // check type
+        let mime = file_type::sniff_file(merged.tmp_file.path()).await?;
+        ensure_biz!(
+            file_type::is_allowed(*task.user_id(), mime).await?,
+            TypeNotAllowed(mime.to_string())
+        );
+        scan::scan_merged(&merged).await?;
         // persist file
         let path = path_manager().archived_path(&merged.hash);
-        let file = FileNodeMetaData::new(merged.size, merged.hash.clone(), path);
+        let mut file = FileNodeMetaData::new(merged.size, merged.hash.clone(), path);
+        file.mime = Some(mime.to_string());
         file_sys::create_dir_all(&file.archived_path.parent().unwrap()).await?;
-        merged.persist(&file.archived_path).await?;
+        merged
+            .persist(&file.archived_path, path_manager().archive_root())
+            .await?;
 
         biz_ok!(file)
     }
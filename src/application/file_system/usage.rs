@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_dir_usage, repo_user_file},
+};
+
+/// 目录占用的空间，包含所有子目录
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DirUsageDto {
+    dir_id: UserFileId,
+    /// 文件总大小（byte）
+    size: i64,
+    /// 文件数，不含目录
+    file_count: i64,
+    /// 视频总时长（毫秒）
+    video_duration_ms: i64,
+    /// 各个直接子目录的占用，从大到小排列
+    sub_dirs: Vec<SubDirUsage>,
+}
+
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SubDirUsage {
+    dir_id: UserFileId,
+    name: String,
+    size: i64,
+    file_count: i64,
+    video_duration_ms: i64,
+}
+
+pub enum DirUsageErr {
+    NotFound,
+    NotDir,
+}
+
+/// 统计目录的占用，优先使用缓存，缺失的部分计算后写入缓存
+pub async fn dir_usage(user_id: UserId, dir_id: UserFileId) -> BizResult<DirUsageDto, DirUsageErr> {
+    let conn = &mut pg_conn().await?;
+    let dir = ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, dir_id), conn).await?,
+        DirUsageErr::NotFound
+    );
+    ensure_biz!(dir.is_dir(), DirUsageErr::NotDir);
+
+    let sub_dirs: Vec<_> = dir
+        .children()
+        .into_iter()
+        .flatten()
+        .filter(|child| child.is_dir())
+        .collect();
+    let mut ids = vec![dir_id];
+    ids.extend(sub_dirs.iter().map(|d| *d.id()));
+
+    let mut usages: HashMap<_, _> = repo_dir_usage::find(&ids, conn)
+        .await?
+        .into_iter()
+        .map(|u| (u.dir_id, u))
+        .collect();
+    let missing: Vec<_> = ids
+        .into_iter()
+        .filter(|id| !usages.contains_key(id))
+        .collect();
+    let computed = repo_dir_usage::compute(&missing, conn).await?;
+    repo_dir_usage::save(&computed, conn).await?;
+    usages.extend(computed.into_iter().map(|u| (u.dir_id, u)));
+
+    let usage_of = |id: &UserFileId| {
+        usages
+            .get(id)
+            .map(|u| (u.size, u.file_count, u.video_duration_ms))
+            .unwrap_or_default()
+    };
+    let mut sub_dirs: Vec<_> = sub_dirs
+        .into_iter()
+        .map(|d| {
+            let (size, file_count, video_duration_ms) = usage_of(d.id());
+            SubDirUsage {
+                dir_id: *d.id(),
+                name: d.file_name().to_string(),
+                size,
+                file_count,
+                video_duration_ms,
+            }
+        })
+        .collect();
+    sub_dirs.sort_by(|a, b| b.size.cmp(&a.size));
+
+    let (size, file_count, video_duration_ms) = usage_of(&dir_id);
+    biz_ok!(DirUsageDto {
+        dir_id,
+        size,
+        file_count,
+        video_duration_ms,
+        sub_dirs,
+    })
+}
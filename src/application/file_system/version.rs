@@ -0,0 +1,192 @@
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn_read, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::{
+            change::FileChange,
+            file::{FileNode, FileNodeMetaData, FileOperateErr, SysFileId, UserFileId},
+            service::PathManager,
+            version::{self, FileVersion, FileVersionId},
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{file_sys, repo_file_change, repo_file_version, repo_user, repo_user_file},
+    pg_tx,
+    settings::get_settings,
+};
+
+use super::{edit_lock, scan};
+
+/// 在目录下保存上传的文件。
+/// `overwrite` 为真且存在同名文件时覆盖它，原来的内容保存为历史版本，否则与同名文件共存（自动重命名）。
+/// 同名文件被 uploader 以外的用户锁定时不能覆盖
+pub(super) async fn place_file<'a>(
+    uploader: UserId,
+    parent: &'a mut FileNode,
+    name: &str,
+    file_data: FileNodeMetaData,
+    overwrite: bool,
+    conn: &mut PgConn,
+) -> BizResult<&'a FileNode, FileOperateErr> {
+    if !overwrite {
+        let file = ensure_biz!(parent.create_file(name, file_data));
+        repo_user_file::save_node(file, conn).await?;
+        repo_file_change::insert(&FileChange::created(file), conn).await?;
+        return biz_ok!(&*file);
+    }
+
+    let (file, replaced) = ensure_biz!(parent.overwrite_file(name, file_data));
+    let Some(replaced) = replaced else {
+        repo_user_file::save_node(file, conn).await?;
+        repo_file_change::insert(&FileChange::created(file), conn).await?;
+        return biz_ok!(&*file);
+    };
+    ensure_biz!(edit_lock::check(uploader, *file.id(), conn).await?);
+
+    repo_user_file::update_file_data(file, conn).await?;
+    repo_file_change::insert(&FileChange::modified(file), conn).await?;
+    keep_version(*file.user_id(), *file.id(), replaced, conn).await?;
+
+    biz_ok!(&*file)
+}
+
+/// 保存历史版本，并删除超出保留数量的旧版本
+async fn keep_version(
+    user_id: UserId,
+    user_file_id: UserFileId,
+    sys_file_id: SysFileId,
+    conn: &mut PgConn,
+) -> anyhow::Result<()> {
+    let keep = max_file_versions(user_id, conn).await?;
+    if keep > 0 {
+        let version = FileVersion::new(user_id, user_file_id, sys_file_id);
+        repo_file_version::save(&version, conn).await?;
+    }
+
+    let versions = repo_file_version::list_ids(user_file_id, conn).await?;
+    repo_file_version::delete(version::expired_versions(&versions, keep), conn).await?;
+
+    Ok(())
+}
+
+async fn max_file_versions(user_id: UserId, conn: &mut PgConn) -> anyhow::Result<u32> {
+    let user = repo_user::find(user_id, conn).await?;
+    let keep = user
+        .and_then(|u| *u.max_file_versions())
+        .unwrap_or(get_settings().file_system.max_file_versions);
+    Ok(keep)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileVersionDto {
+    id: FileVersionId,
+    size: u64,
+    hash: String,
+    /// 被覆盖的时间
+    create_at: MillionTimestamp,
+}
+
+pub enum ListVersionsErr {
+    NotFound,
+}
+
+/// 文件的历史版本，从新到旧排列
+pub async fn list_versions(
+    user_id: UserId,
+    file_id: UserFileId,
+) -> BizResult<Vec<FileVersionDto>, ListVersionsErr> {
+    let conn = &mut pg_conn_read().await?;
+    let file = ensure_exist!(
+        repo_user_file::find_node_for_read((user_id, file_id), conn).await?,
+        ListVersionsErr::NotFound
+    );
+    ensure_biz!(file.is_file(), ListVersionsErr::NotFound);
+
+    let versions = repo_file_version::list(file_id).await?;
+    let versions = versions
+        .into_iter()
+        .map(|v| FileVersionDto {
+            id: *v.version.id(),
+            size: v.data.size,
+            hash: v.data.hash,
+            create_at: v.create_at.into(),
+        })
+        .collect();
+
+    biz_ok!(versions)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreVersionDto {
+    file_id: UserFileId,
+    version_id: FileVersionId,
+}
+
+#[derive(From, Debug)]
+pub enum RestoreVersionErr {
+    FsDomain(FileOperateErr),
+    FileNotFound,
+    VersionNotFound,
+}
+
+/// 恢复到历史版本，当前的内容保存为一个新的历史版本
+/// member_id 为实际操作的用户，用于检查文件锁
+pub async fn restore_version(
+    user_id: UserId,
+    member_id: UserId,
+    params: RestoreVersionDto,
+) -> BizResult<(), RestoreVersionErr> {
+    pg_tx!(restore_version_tx, user_id, member_id, &params)
+}
+
+async fn restore_version_tx(
+    user_id: UserId,
+    member_id: UserId,
+    params: &RestoreVersionDto,
+    conn: &mut PgConn,
+) -> BizResult<(), RestoreVersionErr> {
+    use RestoreVersionErr::*;
+
+    let mut file = ensure_exist!(
+        repo_user_file::find_node((user_id, params.file_id), conn).await?,
+        FileNotFound
+    );
+    ensure_biz!(edit_lock::check(member_id, params.file_id, conn).await?);
+    let version = ensure_exist!(
+        repo_file_version::find(params.version_id, conn).await?,
+        VersionNotFound
+    );
+    ensure_biz!(*version.user_file_id() == *file.id(), VersionNotFound);
+    let file_data = ensure_exist!(
+        repo_user_file::find_sys_file(*version.sys_file_id()).await?,
+        VersionNotFound
+    );
+
+    let archived_path = file_data.archived_path.clone();
+    let quarantined = scan::is_quarantined(&file_data.hash).await?;
+    let replaced = ensure_biz!(file.replace_data(file_data));
+    repo_user_file::update_file_data(&file, conn).await?;
+    repo_file_change::insert(&FileChange::modified(&file), conn).await?;
+
+    // 恢复的版本变为当前内容，当前内容变为最新的历史版本
+    repo_file_version::delete(&[*version.id()], conn).await?;
+    keep_version(user_id, *file.id(), replaced, conn).await?;
+
+    // 被隔离的内容不能下载，删除当前内容的软链接后不再创建
+    if quarantined {
+        file_sys::delete(&PathManager::virtual_to_disk(file.path())).await?;
+    } else {
+        file_sys::create_user_link(&archived_path, file.path()).await?;
+    }
+
+    biz_ok!(())
+}
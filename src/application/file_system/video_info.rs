@@ -1,7 +1,15 @@
-use crate::{domain::file_system::file::SysFileId, infrastructure::repo_user_file};
+use std::sync::Mutex;
+
+use crate::{
+    application::transcode_rule,
+    domain::file_system::file::SysFileId,
+    infrastructure::{av1_factory, repo_user_file},
+    log_if_err,
+};
 use anyhow::Result;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 #[derive(Debug, Serialize, Clone, Deserialize)]
 pub struct MediaInfo {
@@ -147,5 +155,88 @@ pub async fn file_parsed(file_id: SysFileId, video_parsed: Option<String>) -> Re
     debug!(%file_id, "file parsed, updating");
     let video_parsed = video_parsed.map(|v| serde_json::from_str(&v)).transpose()?;
     repo_user_file::update_file_matedata(file_id, video_parsed).await?;
+    // 自动转码失败不影响视频信息的保存
+    log_if_err!(transcode_rule::on_file_parsed(file_id).await);
+    Ok(())
+}
+
+/// 补全视频信息时，每批从数据库中取出的文件数
+const BACKFILL_BATCH_SIZE: i64 = 100;
+/// 补全视频信息时，同时发送的解析请求数
+const BACKFILL_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct BackfillProgress {
+    pub running: bool,
+    /// 开始时未解析的文件总数
+    pub total: i64,
+    /// 已发送解析请求的文件数
+    pub submitted: i64,
+    /// 发送解析请求失败的文件数
+    pub failed: i64,
+}
+
+static BACKFILL_PROGRESS: Mutex<BackfillProgress> = Mutex::new(BackfillProgress {
+    running: false,
+    total: 0,
+    submitted: 0,
+    failed: 0,
+});
+
+pub fn backfill_progress() -> BackfillProgress {
+    BACKFILL_PROGRESS.lock().unwrap().clone()
+}
+
+/// 为未解析过的文件重新发送解析请求，已经在运行时返回 false
+pub fn start_backfill() -> bool {
+    {
+        let mut progress = BACKFILL_PROGRESS.lock().unwrap();
+        if progress.running {
+            return false;
+        }
+        *progress = BackfillProgress {
+            running: true,
+            ..Default::default()
+        };
+    }
+
+    tokio::spawn(async {
+        log_if_err!(backfill().await);
+        let progress = {
+            let mut progress = BACKFILL_PROGRESS.lock().unwrap();
+            progress.running = false;
+            progress.clone()
+        };
+        info!(?progress, "video info backfill finished");
+    });
+    true
+}
+
+async fn backfill() -> Result<()> {
+    let total = repo_user_file::count_unparsed_sys_files().await?;
+    BACKFILL_PROGRESS.lock().unwrap().total = total;
+    info!(total, "video info backfill started");
+
+    let mut cursor = None;
+    loop {
+        let files = repo_user_file::find_unparsed_sys_files(cursor, BACKFILL_BATCH_SIZE).await?;
+        let Some(last) = files.last() else {
+            break;
+        };
+        cursor = Some(last.id);
+
+        futures_util::stream::iter(files)
+            .for_each_concurrent(BACKFILL_CONCURRENCY, |file| async move {
+                let res = av1_factory::parse_file(file.id, &file.archived_path).await;
+                let mut progress = BACKFILL_PROGRESS.lock().unwrap();
+                progress.submitted += 1;
+                if let Err(err) = res {
+                    warn!(?err, file_id = %file.id, "resubmit parse task failed");
+                    progress.failed += 1;
+                }
+            })
+            .await;
+    }
+
     Ok(())
 }
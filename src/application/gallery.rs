@@ -0,0 +1,347 @@
+//! 公开画廊
+//!
+//! 发布后视频的公开页面和播放地址都以短链接访问，不需要登录。源文件被删除或视频被下架后不再公开。
+//! 访客的举报按 IP 限制频率，观看次数按访客去重
+
+use std::net::IpAddr;
+
+use anyhow::Result;
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, pg_conn_read, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::file::{FileNode, FileType, UserFileId},
+        gallery::{
+            Moderation, Publication, PublicationId, PublishErr, Report, ReportErr, ReportId,
+        },
+        user::{employee::EmployeeId, user::UserId},
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_gallery, repo_user_file},
+    pg_tx,
+    settings::get_settings,
+};
+
+use super::{
+    audit::{self, AuditAction},
+    file_system::playback::{self, PlaybackFile},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct GalleryCfg {
+    /// 同一 IP 在 report_window_secs 内对同一视频最多举报的次数
+    #[serde(default = "default_max_reports")]
+    pub max_reports: u64,
+    #[serde(default = "default_report_window_secs")]
+    pub report_window_secs: u64,
+    /// 同一观看者在这段时间内重复打开页面只计一次观看
+    #[serde(default = "default_view_window_secs")]
+    pub view_window_secs: u64,
+}
+
+impl Default for GalleryCfg {
+    fn default() -> Self {
+        Self {
+            max_reports: default_max_reports(),
+            report_window_secs: default_report_window_secs(),
+            view_window_secs: default_view_window_secs(),
+        }
+    }
+}
+
+fn default_max_reports() -> u64 {
+    3
+}
+
+fn default_report_window_secs() -> u64 {
+    60 * 60
+}
+
+fn default_view_window_secs() -> u64 {
+    30 * 60
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicationDto {
+    id: PublicationId,
+    slug: String,
+    title: String,
+    file_id: UserFileId,
+    view_count: i64,
+    /// 被管理员下架
+    taken_down: bool,
+    /// 公开页面的地址
+    page_url: String,
+    /// 不需要登录的播放地址
+    stream_url: String,
+}
+
+impl PublicationDto {
+    fn new(publication: Publication, view_count: i64) -> Self {
+        Self {
+            page_url: format!("/api/gallery/v/{}", publication.slug()),
+            stream_url: format!("/api/gallery/v/{}/stream", publication.slug()),
+            id: *publication.id(),
+            slug: publication.slug().clone(),
+            title: publication.title().clone(),
+            file_id: *publication.user_file_id(),
+            view_count,
+            taken_down: *publication.taken_down(),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishDto {
+    file_id: UserFileId,
+    /// 为空时使用文件名
+    title: Option<String>,
+}
+
+#[derive(From, Debug)]
+pub enum PublishVideoErr {
+    Publish(PublishErr),
+    FileNotFound,
+}
+
+/// 发布转码后的视频，已经发布时返回原来的短链接
+pub async fn publish(
+    user_id: UserId,
+    params: PublishDto,
+) -> BizResult<PublicationDto, PublishVideoErr> {
+    pg_tx!(publish_tx, user_id, &params)
+}
+
+async fn publish_tx(
+    user_id: UserId,
+    params: &PublishDto,
+    conn: &mut PgConn,
+) -> BizResult<PublicationDto, PublishVideoErr> {
+    use PublishVideoErr::*;
+
+    let file = ensure_exist!(
+        repo_user_file::find_node((user_id, params.file_id), conn).await?,
+        FileNotFound
+    );
+    ensure_biz!(!*file.deleted(), FileNotFound);
+
+    if let Some((existing, views)) = repo_gallery::find_by_file(params.file_id, conn).await? {
+        ensure_biz!(!*existing.taken_down(), PublishErr::TakenDown);
+        return biz_ok!(PublicationDto::new(existing, views));
+    }
+
+    let encoded = is_encoded(&file, conn).await?;
+    let publication = ensure_biz!(Publication::publish(&file, encoded, params.title.clone()));
+    if !repo_gallery::insert(&publication, conn).await? {
+        // 同一个文件被同时发布，返回先插入的
+        let existing = repo_gallery::find_by_file(params.file_id, conn).await?;
+        let (existing, views) = ensure_exist!(existing, FileNotFound);
+        ensure_biz!(!*existing.taken_down(), PublishErr::TakenDown);
+        return biz_ok!(PublicationDto::new(existing, views));
+    }
+    biz_ok!(PublicationDto::new(publication, 0))
+}
+
+/// 文件是否是转码的结果
+async fn is_encoded(file: &FileNode, conn: &mut PgConn) -> Result<bool> {
+    let sys_file_id = match file.file_type() {
+        FileType::File(data) => data.id,
+        FileType::LazyFile(id) => *id,
+        FileType::Dir(_) => return Ok(false),
+    };
+    let video = repo_user_file::find_sys_video(sys_file_id, conn).await?;
+    Ok(video.is_some_and(|video| video.transcode_from.is_some()))
+}
+
+/// 用户发布的所有视频，包括被下架的
+pub async fn list_mine(user_id: UserId) -> Result<Vec<PublicationDto>> {
+    let conn = &mut pg_conn_read().await?;
+    let publications = repo_gallery::list_for_user(user_id, conn).await?;
+    Ok(publications
+        .into_iter()
+        .map(|(publication, views)| PublicationDto::new(publication, views))
+        .collect())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnpublishDto {
+    publication_id: PublicationId,
+}
+
+#[derive(Debug)]
+pub enum UnpublishErr {
+    NotFound,
+}
+
+/// 取消发布，短链接随之失效。被下架的视频保留记录，防止再次发布
+pub async fn unpublish(user_id: UserId, params: UnpublishDto) -> BizResult<(), UnpublishErr> {
+    pg_tx!(unpublish_tx, user_id, params.publication_id)
+}
+
+async fn unpublish_tx(
+    user_id: UserId,
+    id: PublicationId,
+    conn: &mut PgConn,
+) -> BizResult<(), UnpublishErr> {
+    let publication = ensure_exist!(
+        repo_gallery::find_for_update(id, conn).await?,
+        UnpublishErr::NotFound
+    );
+    ensure_biz!(*publication.user_id() == user_id, UnpublishErr::NotFound);
+    if !*publication.taken_down() {
+        repo_gallery::delete(id, conn).await?;
+    }
+    biz_ok!(())
+}
+
+#[derive(Debug)]
+pub enum GalleryErr {
+    /// 短链接不存在、视频被下架或源文件已删除
+    NotFound,
+}
+
+/// 打开公开页面。同一观看者（登录用户或 IP）在 view_window_secs 内只计一次观看
+pub async fn view(
+    slug: &str,
+    viewer_id: Option<UserId>,
+    ip: Option<IpAddr>,
+) -> BizResult<PublicationDto, GalleryErr> {
+    let cfg = &get_settings().gallery;
+    let conn = &mut pg_conn().await?;
+    let (publication, _) = ensure_exist!(find_public(slug, conn).await?, GalleryErr::NotFound);
+    let id = *publication.id();
+    let first = match visitor(viewer_id, ip) {
+        Some(viewer) => repo_gallery::first_view(id, &viewer, cfg.view_window_secs).await?,
+        None => true,
+    };
+    let views = if first {
+        repo_gallery::incr_views(id, conn).await?
+    } else {
+        repo_gallery::find_views(id, conn).await?
+    };
+    biz_ok!(PublicationDto::new(publication, views))
+}
+
+/// 访客的标识，优先使用登录的用户
+fn visitor(user_id: Option<UserId>, ip: Option<IpAddr>) -> Option<String> {
+    match (user_id, ip) {
+        (Some(user_id), _) => Some(format!("user:{user_id}")),
+        (None, Some(ip)) => Some(format!("ip:{ip}")),
+        (None, None) => None,
+    }
+}
+
+/// 公开视频的存档文件，内容被隔离时视为不存在
+pub async fn stream(slug: &str) -> BizResult<PlaybackFile, GalleryErr> {
+    let conn = &mut pg_conn_read().await?;
+    let (_, file) = ensure_exist!(find_public(slug, conn).await?, GalleryErr::NotFound);
+    let file = ensure_exist!(playback::servable_file(&file).await?, GalleryErr::NotFound);
+    biz_ok!(file)
+}
+
+/// 未下架且源文件未删除的发布和它的源文件
+async fn find_public(slug: &str, conn: &mut PgConn) -> Result<Option<(Publication, FileNode)>> {
+    let Some(publication) = repo_gallery::find_by_slug(slug, conn).await? else {
+        return Ok(None);
+    };
+    if *publication.taken_down() {
+        return Ok(None);
+    }
+    let file = repo_user_file::find_node_for_read(*publication.user_file_id(), conn).await?;
+    Ok(file
+        .filter(|file| !*file.deleted())
+        .map(|file| (publication, file)))
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportVideoDto {
+    /// 举报原因，最长 500 个字符
+    reason: String,
+}
+
+#[derive(From, Debug)]
+pub enum ReportVideoErr {
+    Report(ReportErr),
+    Gallery(GalleryErr),
+    TooMany,
+}
+
+/// 举报公开的视频，访客也可以举报。同一 IP 对同一视频的举报次数受 max_reports 限制
+pub async fn report(
+    slug: &str,
+    reporter_id: Option<UserId>,
+    ip: Option<IpAddr>,
+    params: ReportVideoDto,
+) -> BizResult<(), ReportVideoErr> {
+    let cfg = &get_settings().gallery;
+    let conn = &mut pg_conn().await?;
+    let (publication, _) = ensure_exist!(find_public(slug, conn).await?, GalleryErr::NotFound);
+    // 取不到地址时所有这样的请求共用一个计数
+    let reporter = ip.map_or_else(|| "ip:unknown".to_string(), |ip| format!("ip:{ip}"));
+    let count =
+        repo_gallery::incr_report_count(*publication.id(), &reporter, cfg.report_window_secs)
+            .await?;
+    ensure_biz!(count <= cfg.max_reports, ReportVideoErr::TooMany);
+    let report = ensure_biz!(Report::create(&publication, reporter_id, params.reason));
+    repo_gallery::insert_report(&report, conn).await?;
+    biz_ok!(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolveReportDto {
+    report_id: ReportId,
+    moderation: Moderation,
+}
+
+#[derive(From, Debug)]
+pub enum ResolveReportErr {
+    Report(ReportErr),
+    NotFound,
+}
+
+/// 管理员处理举报。下架时同一视频的其他待处理举报一并处理
+pub async fn resolve_report(
+    employee_id: EmployeeId,
+    params: ResolveReportDto,
+) -> BizResult<(), ResolveReportErr> {
+    let res = pg_tx!(resolve_report_tx, employee_id, &params)?;
+    if res.is_ok() {
+        audit::record(employee_id, AuditAction::ModerateVideo, &params).await?;
+    }
+    Ok(res)
+}
+
+async fn resolve_report_tx(
+    employee_id: EmployeeId,
+    params: &ResolveReportDto,
+    conn: &mut PgConn,
+) -> BizResult<(), ResolveReportErr> {
+    use ResolveReportErr::*;
+
+    let mut report = ensure_exist!(
+        repo_gallery::find_report_for_update(params.report_id, conn).await?,
+        NotFound
+    );
+    let mut publication = ensure_exist!(
+        repo_gallery::find_for_update(*report.publication_id(), conn).await?,
+        NotFound
+    );
+    ensure_biz!(report.resolve(&mut publication, params.moderation));
+    repo_gallery::update_report(&report, employee_id, conn).await?;
+
+    if params.moderation == Moderation::TakeDown {
+        repo_gallery::set_taken_down(&publication, conn).await?;
+        repo_gallery::close_pending_reports(*publication.id(), employee_id, conn).await?;
+    }
+    biz_ok!(())
+}
@@ -0,0 +1,203 @@
+//! Google Drive 导入
+//!
+//! 使用 OAuth 授权码流程获取只读的访问令牌，通过 Drive v3 接口列出和下载文件。
+//! Google 文档、表格等在线文档不能直接下载，列出时会被过滤掉
+
+use anyhow::{ensure, Result};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::settings::Secret;
+
+use super::{ImportConnector, OAuthToken, RemoteFile};
+
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const FILES_URL: &str = "https://www.googleapis.com/drive/v3/files";
+const SCOPE: &str = "https://www.googleapis.com/auth/drive.readonly";
+const FOLDER_MIME: &str = "application/vnd.google-apps.folder";
+/// 在线文档的 mime 类型前缀
+const GOOGLE_APPS_MIME: &str = "application/vnd.google-apps.";
+const FILE_FIELDS: &str = "id,name,mimeType,size";
+
+#[derive(Debug, Deserialize)]
+pub struct GoogleDriveCfg {
+    pub client_id: String,
+    pub client_secret: Secret,
+    /// 在 Google 控制台登记的回调地址，前端在该地址取出 code 和 state 后调用 connect 接口
+    pub redirect_uri: String,
+}
+
+pub struct GoogleDrive {
+    cfg: &'static GoogleDriveCfg,
+    client: reqwest::Client,
+}
+
+impl GoogleDrive {
+    pub fn new(cfg: &'static GoogleDriveCfg) -> Self {
+        Self {
+            cfg,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DriveFile {
+    id: String,
+    name: String,
+    mime_type: String,
+    /// 数字以字符串返回，文件夹和在线文档没有大小
+    size: Option<String>,
+}
+
+impl DriveFile {
+    fn is_dir(&self) -> bool {
+        self.mime_type == FOLDER_MIME
+    }
+
+    fn is_online_doc(&self) -> bool {
+        !self.is_dir() && self.mime_type.starts_with(GOOGLE_APPS_MIME)
+    }
+
+    fn into_remote(self) -> RemoteFile {
+        RemoteFile {
+            is_dir: self.is_dir(),
+            size: self.size.and_then(|s| s.parse().ok()),
+            id: self.id,
+            name: self.name,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileList {
+    files: Vec<DriveFile>,
+    /// 还有下一页时不为空
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResp {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Drive 的文件 id 只包含字母、数字、- 和 _，拼接到查询语句前先检查
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[async_trait::async_trait]
+impl ImportConnector for GoogleDrive {
+    fn authorize_url(&self, state: &str) -> String {
+        let params = [
+            ("client_id", self.cfg.client_id.as_str()),
+            ("redirect_uri", self.cfg.redirect_uri.as_str()),
+            ("response_type", "code"),
+            ("scope", SCOPE),
+            ("access_type", "online"),
+            ("state", state),
+        ];
+        Url::parse_with_params(AUTH_URL, params)
+            .expect("auth url is valid")
+            .into()
+    }
+
+    async fn exchange_code(&self, code: &str) -> Result<OAuthToken> {
+        let params = [
+            ("code", code),
+            ("client_id", self.cfg.client_id.as_str()),
+            ("client_secret", self.cfg.client_secret.expose()),
+            ("redirect_uri", self.cfg.redirect_uri.as_str()),
+            ("grant_type", "authorization_code"),
+        ];
+        let resp: TokenResp = self
+            .client
+            .post(TOKEN_URL)
+            .form(&params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(OAuthToken {
+            access_token: resp.access_token,
+            expires_in: resp.expires_in,
+        })
+    }
+
+    async fn list(&self, token: &str, folder_id: Option<&str>) -> Result<Vec<RemoteFile>> {
+        let folder_id = folder_id.unwrap_or("root");
+        ensure!(is_valid_id(folder_id), "bad folder id: {}", folder_id);
+
+        let query = format!("'{}' in parents and trashed = false", folder_id);
+        let fields = format!("nextPageToken,files({})", FILE_FIELDS);
+        let mut files = vec![];
+        let mut page_token = None;
+        // 每页最多 1000 个，按 nextPageToken 依次加载直到最后一页
+        loop {
+            let mut request = self.client.get(FILES_URL).bearer_auth(token).query(&[
+                ("q", query.as_str()),
+                ("fields", fields.as_str()),
+                ("orderBy", "folder,name"),
+                ("pageSize", "1000"),
+            ]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let list: FileList = request.send().await?.error_for_status()?.json().await?;
+
+            files.extend(
+                list.files
+                    .into_iter()
+                    .filter(|f| !f.is_online_doc())
+                    .map(DriveFile::into_remote),
+            );
+            match list.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => break,
+            }
+        }
+        Ok(files)
+    }
+
+    async fn file(&self, token: &str, file_id: &str) -> Result<Option<RemoteFile>> {
+        ensure!(is_valid_id(file_id), "bad file id: {}", file_id);
+
+        let resp = self
+            .client
+            .get(format!("{}/{}", FILES_URL, file_id))
+            .bearer_auth(token)
+            .query(&[("fields", FILE_FIELDS)])
+            .send()
+            .await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let file: DriveFile = resp.error_for_status()?.json().await?;
+        if file.is_online_doc() {
+            return Ok(None);
+        }
+        Ok(Some(file.into_remote()))
+    }
+
+    async fn download(&self, token: &str, file_id: &str) -> Result<reqwest::Response> {
+        ensure!(is_valid_id(file_id), "bad file id: {}", file_id);
+
+        let resp = self
+            .client
+            .get(format!("{}/{}", FILES_URL, file_id))
+            .bearer_auth(token)
+            .query(&[("alt", "media")])
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(resp)
+    }
+}
@@ -0,0 +1,473 @@
+//! 从网盘等外部服务导入文件
+//!
+//! 每个外部服务实现 [`ImportConnector`]，这里负责 OAuth 授权、保存访问令牌和后台导入任务。
+//! 用户授权后可以浏览远程文件，选中的文件按顺序下载到 /源视频 或指定的目录，
+//! 下载的内容与上传的文件一样去重并发送解析和缩略图请求，每个文件的状态单独记录。
+//! 每个文件都检查上传频率、文件类型和配额，存储空间用完时结束整个任务
+
+use std::{collections::HashSet, fmt};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    application::{
+        file_system::{
+            import_url::{self, PlaceErr},
+            service, upload,
+        },
+        notification,
+        user::tier,
+    },
+    biz_ok,
+    domain::{
+        file_system::{
+            file::{UserFileId, VirtualPath},
+            import_job::{ImportFileState, ImportJob, ImportJobId},
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_import_task, repo_user_file},
+    pg_tx,
+    settings::get_settings,
+};
+
+pub mod google_drive;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportCfg {
+    /// 一个导入任务最多包含的文件数
+    #[serde(default = "default_max_job_files")]
+    pub max_job_files: usize,
+    /// 未配置时不能从 Google Drive 导入
+    pub google_drive: Option<google_drive::GoogleDriveCfg>,
+}
+
+impl Default for ImportCfg {
+    fn default() -> Self {
+        Self {
+            max_job_files: default_max_job_files(),
+            google_drive: None,
+        }
+    }
+}
+
+fn default_max_job_files() -> usize {
+    100
+}
+
+/// 外部服务
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    GoogleDrive,
+}
+
+impl ImportSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GoogleDrive => "google_drive",
+        }
+    }
+
+    /// 未配置的服务返回 None
+    fn connector(&self) -> Option<Box<dyn ImportConnector>> {
+        let cfg = &get_settings().import;
+        match self {
+            Self::GoogleDrive => {
+                let cfg = cfg.google_drive.as_ref()?;
+                Some(Box::new(google_drive::GoogleDrive::new(cfg)))
+            }
+        }
+    }
+}
+
+/// 外部服务中的文件
+#[derive(Serialize, ToSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteFile {
+    pub id: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+}
+
+pub struct OAuthToken {
+    pub access_token: String,
+    /// 有效期，秒
+    pub expires_in: u64,
+}
+
+#[async_trait::async_trait]
+pub trait ImportConnector: Send + Sync {
+    /// 引导用户授权的地址
+    fn authorize_url(&self, state: &str) -> String;
+
+    /// 用授权码换取访问令牌
+    async fn exchange_code(&self, code: &str) -> Result<OAuthToken>;
+
+    /// 列出目录下的文件，folder_id 为空时列出根目录
+    async fn list(&self, token: &str, folder_id: Option<&str>) -> Result<Vec<RemoteFile>>;
+
+    /// 文件不存在或不能下载时返回 None
+    async fn file(&self, token: &str, file_id: &str) -> Result<Option<RemoteFile>>;
+
+    /// 开始下载文件内容
+    async fn download(&self, token: &str, file_id: &str) -> Result<reqwest::Response>;
+}
+
+pub enum ConnectorErr {
+    NotConfigured,
+    NotConnected,
+}
+
+/// 生成授权地址，state 在 10 分钟内有效
+pub async fn authorize_url(
+    user_id: UserId,
+    source: ImportSource,
+) -> BizResult<String, ConnectorErr> {
+    let connector = ensure_exist!(source.connector(), ConnectorErr::NotConfigured);
+    let state = hex::encode(rand::random::<[u8; 16]>());
+    repo_import_task::save_oauth_state(&state, user_id, source.name()).await?;
+    biz_ok!(connector.authorize_url(&state))
+}
+
+pub enum ConnectErr {
+    Connector(ConnectorErr),
+    BadState,
+    ExchangeFailed,
+}
+
+impl From<ConnectorErr> for ConnectErr {
+    fn from(value: ConnectorErr) -> Self {
+        Self::Connector(value)
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectDto {
+    source: ImportSource,
+    /// 授权回调中的 code
+    code: String,
+    /// 授权回调中的 state
+    state: String,
+}
+
+/// 用授权回调中的 code 换取访问令牌并保存
+pub async fn connect(user_id: UserId, params: ConnectDto) -> BizResult<(), ConnectErr> {
+    let ConnectDto {
+        source,
+        code,
+        state,
+    } = params;
+    let connector = ensure_exist!(source.connector(), ConnectorErr::NotConfigured);
+    let (state_user, state_source) = ensure_exist!(
+        repo_import_task::take_oauth_state(&state).await?,
+        ConnectErr::BadState
+    );
+    ensure_biz!(
+        state_user == user_id && state_source == source.name(),
+        ConnectErr::BadState
+    );
+
+    let token = match connector.exchange_code(&code).await {
+        Ok(token) => token,
+        Err(err) => {
+            warn!(%user_id, source = source.name(), ?err, "exchange oauth code failed");
+            return Ok(Err(ConnectErr::ExchangeFailed));
+        }
+    };
+    // 提前一分钟过期，避免导入时令牌刚好失效
+    let ttl = token.expires_in.saturating_sub(60).max(1) as usize;
+    repo_import_task::save_token(user_id, source.name(), &token.access_token, ttl).await?;
+
+    biz_ok!(())
+}
+
+/// 已配置的服务和用户的访问令牌
+async fn connected(
+    user_id: UserId,
+    source: ImportSource,
+) -> BizResult<(Box<dyn ImportConnector>, String), ConnectorErr> {
+    let connector = ensure_exist!(source.connector(), ConnectorErr::NotConfigured);
+    let token = ensure_exist!(
+        repo_import_task::find_token(user_id, source.name()).await?,
+        ConnectorErr::NotConnected
+    );
+    biz_ok!((connector, token))
+}
+
+/// 列出远程目录下的文件
+pub async fn list(
+    user_id: UserId,
+    source: ImportSource,
+    folder_id: Option<&str>,
+) -> BizResult<Vec<RemoteFile>, ConnectorErr> {
+    let (connector, token) = ensure_biz!(connected(user_id, source).await?);
+    let files = connector.list(&token, folder_id).await?;
+    biz_ok!(files)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StartImportDto {
+    source: ImportSource,
+    /// 要导入的远程文件 id，不能包含目录
+    file_ids: Vec<String>,
+    /// 默认导入到 /源视频
+    parent_id: Option<UserFileId>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StartImportResp {
+    job_id: ImportJobId,
+}
+
+pub enum StartImportErr {
+    Connector(ConnectorErr),
+    NoFiles,
+    TooManyFiles,
+    NoParent,
+}
+
+impl From<ConnectorErr> for StartImportErr {
+    fn from(value: ConnectorErr) -> Self {
+        Self::Connector(value)
+    }
+}
+
+/// 创建后台导入任务，返回任务 id
+pub async fn start_job(
+    user_id: UserId,
+    params: StartImportDto,
+) -> BizResult<StartImportResp, StartImportErr> {
+    use StartImportErr::*;
+
+    let StartImportDto {
+        source,
+        mut file_ids,
+        parent_id,
+    } = params;
+    let mut seen = HashSet::new();
+    file_ids.retain(|id| seen.insert(id.clone()));
+    ensure_biz!(!file_ids.is_empty(), NoFiles);
+    ensure_biz!(
+        file_ids.len() <= get_settings().import.max_job_files,
+        TooManyFiles
+    );
+    let (connector, token) = ensure_biz!(connected(user_id, source).await?);
+
+    let parent_id = match parent_id {
+        Some(parent_id) => {
+            let conn = &mut pg_conn().await?;
+            let parent = repo_user_file::find_node_for_read((user_id, parent_id), conn).await?;
+            ensure_biz!(parent.is_some_and(|p| p.is_dir()), NoParent);
+            parent_id
+        }
+        None => {
+            // 确保用户的根目录已创建
+            service::load_home(user_id).await?;
+            let conn = &mut pg_conn().await?;
+            let resource_dir = VirtualPath::resource_dir(user_id);
+            let dir = ensure_exist!(
+                repo_user_file::find_node_for_read(&resource_dir, conn).await?,
+                NoParent
+            );
+            *dir.id()
+        }
+    };
+
+    let job = ImportJob::new(user_id, source.name().to_string(), parent_id, file_ids);
+    repo_import_task::save_job(&job).await?;
+    let job_id = *job.id();
+
+    info!(%user_id, %job_id, source = source.name(), files = job.files().len(), "import job started");
+    tokio::spawn(async move {
+        let mut job = job;
+        run_job(&mut job, connector.as_ref(), &token).await;
+        info!(%job_id, "import job finished");
+    });
+
+    biz_ok!(StartImportResp { job_id })
+}
+
+/// 按顺序导入每个文件，每个文件结束后保存任务状态。
+/// 存储空间用完或上传太频繁时后面的文件也不能导入，提前结束任务
+async fn run_job(job: &mut ImportJob, connector: &dyn ImportConnector, token: &str) {
+    let job_id = *job.id();
+    for index in 0..job.files().len() {
+        let result = import_one(job, index, connector, token).await;
+        let file = job.file_mut(index);
+        let mut abort = None;
+        match result {
+            Ok(Ok((file_id, new_name))) => file.completed(file_id, new_name),
+            Ok(Err(err)) => {
+                let reason = err.to_string();
+                if err.ends_job() {
+                    abort = Some(reason.clone());
+                }
+                file.failed(reason);
+            }
+            Err(err) => {
+                warn!(%job_id, remote_id = file.remote_id(), ?err, "import file failed");
+                file.failed(format!("{:#}", err));
+            }
+        }
+        if let Some(reason) = &abort {
+            info!(%job_id, %reason, "import job aborted");
+            job.abort(reason);
+        }
+        if let Err(err) = repo_import_task::save_job(job).await {
+            warn!(%job_id, ?err, "save import job failed");
+        }
+        if abort.is_some() {
+            break;
+        }
+    }
+}
+
+/// 一个文件不能导入的原因
+enum ImportFileErr {
+    NotFound,
+    IsDir,
+    TooManyUploads,
+    Place(PlaceErr),
+}
+
+impl ImportFileErr {
+    fn ends_job(&self) -> bool {
+        matches!(
+            self,
+            ImportFileErr::TooManyUploads | ImportFileErr::Place(PlaceErr::QuotaExceeded)
+        )
+    }
+}
+
+impl fmt::Display for ImportFileErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportFileErr::NotFound => write!(f, "file not found"),
+            ImportFileErr::IsDir => write!(f, "directory can not be imported"),
+            ImportFileErr::TooManyUploads => write!(f, "too many uploads"),
+            ImportFileErr::Place(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// 下载并创建一个文件，返回文件 id 和重命名后的文件名，不能导入时返回原因。
+/// 每个文件与上传一样检查上传频率、文件类型和配额
+async fn import_one(
+    job: &mut ImportJob,
+    index: usize,
+    connector: &dyn ImportConnector,
+    token: &str,
+) -> Result<Result<(UserFileId, Option<String>), ImportFileErr>> {
+    let user_id = *job.user_id();
+    let remote_id = job.files()[index].remote_id().clone();
+    let Some(remote) = connector.file(token, &remote_id).await? else {
+        return Ok(Err(ImportFileErr::NotFound));
+    };
+    if remote.is_dir {
+        return Ok(Err(ImportFileErr::IsDir));
+    }
+    if !tier::try_upload(user_id).await? {
+        return Ok(Err(ImportFileErr::TooManyUploads));
+    }
+    job.file_mut(index).start(remote.name.clone());
+    repo_import_task::save_job(job).await?;
+
+    let resp = connector.download(token, &remote_id).await?;
+    let stored = import_url::store_response(resp, None).await?;
+    if let Err(err) = import_url::check_stored(user_id, &stored).await? {
+        return Ok(Err(ImportFileErr::Place(err)));
+    }
+    let file_data = upload::archive_merged(stored).await?;
+
+    let placed = pg_tx!(
+        import_url::place_downloaded,
+        user_id,
+        *job.parent_id(),
+        &remote.name,
+        false,
+        file_data
+    )?;
+    if placed.is_ok() {
+        notification::wake(user_id);
+    }
+    Ok(placed.map_err(ImportFileErr::Place))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportJobDto {
+    job_id: ImportJobId,
+    source: String,
+    finished: bool,
+    files: Vec<ImportJobFileDto>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportJobFileDto {
+    remote_id: String,
+    /// 开始下载后才有值
+    name: Option<String>,
+    /// pending、downloading、completed 或 failed
+    state: &'static str,
+    /// 完成后创建的文件
+    file_id: Option<UserFileId>,
+    /// 与已有文件重名时自动重命名后的文件名
+    new_name: Option<String>,
+    /// 失败原因
+    error: Option<String>,
+}
+
+pub enum ImportJobErr {
+    NotFound,
+}
+
+/// 查询导入任务，任务结束 10 分钟后不能再查询
+pub async fn job(user_id: UserId, job_id: ImportJobId) -> BizResult<ImportJobDto, ImportJobErr> {
+    let job = ensure_exist!(
+        repo_import_task::find_job(job_id).await?,
+        ImportJobErr::NotFound
+    );
+    ensure_biz!(*job.user_id() == user_id, ImportJobErr::NotFound);
+
+    let files = job
+        .files()
+        .iter()
+        .map(|file| {
+            let (state, file_id, new_name, error) = match file.state().clone() {
+                ImportFileState::Pending => ("pending", None, None, None),
+                ImportFileState::Downloading => ("downloading", None, None, None),
+                ImportFileState::Completed { file_id, new_name } => {
+                    ("completed", Some(file_id), new_name, None)
+                }
+                ImportFileState::Failed(reason) => ("failed", None, None, Some(reason)),
+            };
+            ImportJobFileDto {
+                remote_id: file.remote_id().clone(),
+                name: file.name().clone(),
+                state,
+                file_id,
+                new_name,
+                error,
+            }
+        })
+        .collect();
+
+    biz_ok!(ImportJobDto {
+        job_id,
+        source: job.source().clone(),
+        finished: job.is_finished(),
+        files,
+    })
+}
@@ -1,7 +1,30 @@
+pub mod activity;
+pub mod audit;
+pub mod callback;
+pub mod captcha;
+pub mod credits;
 pub mod email;
+pub mod event_bus;
+pub mod feature_flag;
 pub mod file_system;
+pub mod gallery;
+pub mod import;
+pub mod notification;
+pub mod organization;
+pub mod outbox;
+pub mod schedule;
+pub mod task_log;
+pub mod task_result;
 pub mod transcode;
+pub mod transcode_rule;
 pub mod user;
+pub mod watchdog;
+pub mod webhook;
+pub mod worker;
+
+use std::time::Instant;
+
+use tracing::warn;
 
 #[macro_export]
 macro_rules! ensure_ok {
@@ -58,6 +81,23 @@ macro_rules! biz_err {
     };
 }
 
+/// 事务的执行时间超过 `postgres.slow_tx_ms` 时打印警告，在调用方的 span 中打印，
+/// http 请求中的慢事务会带有请求 id
+#[doc(hidden)]
+pub fn warn_if_slow(func: &str, started: Instant) {
+    let Some(threshold) = utils::db_pools::postgres::slow_tx_threshold() else {
+        return;
+    };
+    let elapsed = started.elapsed();
+    if elapsed >= threshold {
+        warn!(
+            func,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "slow transaction"
+        );
+    }
+}
+
 #[macro_export]
 macro_rules! pg_tx {
     ($func:path, $($params:expr),*) => {{
@@ -65,7 +105,8 @@ macro_rules! pg_tx {
         use diesel_async::scoped_futures::ScopedFutureExt;
 
         let mut conn = utils::db_pools::postgres::pg_conn().await?;
-        let res = conn
+        let started = std::time::Instant::now();
+        let tx = conn
             .transaction(|conn| {
                 async {
                     if false {
@@ -74,8 +115,9 @@ macro_rules! pg_tx {
                     $func($($params),*, conn).await
                 }
                 .scope_boxed()
-            })
-            .await;
+            });
+        let res = $crate::infrastructure::repo_dir_cache::after_commit(tx).await;
+        $crate::application::warn_if_slow(stringify!($func), started);
         res
     }};
 }
@@ -87,14 +129,17 @@ macro_rules! tx_func {
         use diesel_async::scoped_futures::ScopedFutureExt;
 
         let mut conn = utils::db_pools::postgres::pg_conn().await?;
-        conn
+        let started = std::time::Instant::now();
+        let tx = conn
            .transaction(|conn| {
                async {
                     let res = $func($($params),*, conn).await;
                     res
                }
                .scope_boxed()
-           })
-           .await
+           });
+        let res = $crate::infrastructure::repo_dir_cache::after_commit(tx).await;
+        $crate::application::warn_if_slow(stringify!($func), started);
+        res
     }};
 }
@@ -0,0 +1,45 @@
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use tokio::sync::broadcast;
+use utils::db_pools::postgres::{pg_conn, PgConn};
+
+use crate::{
+    domain::{
+        notification::{Notification, NotificationEvent, NotificationId},
+        user::user::UserId,
+    },
+    infrastructure::repo_notification,
+};
+
+/// 在业务事务中保存通知，事务回滚时通知也不会产生。
+/// 事务提交后调用 [`wake`] 立即推送
+pub(crate) async fn notify(
+    user_id: UserId,
+    event: NotificationEvent,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let notification = Notification::new(user_id, event);
+    repo_notification::save(&notification, conn).await
+}
+
+fn sender() -> &'static broadcast::Sender<UserId> {
+    static SENDER: OnceLock<broadcast::Sender<UserId>> = OnceLock::new();
+    SENDER.get_or_init(|| broadcast::channel(1024).0)
+}
+
+/// 唤醒该用户在本实例上的推送连接
+pub(crate) fn wake(user_id: UserId) {
+    let _ = sender().send(user_id);
+}
+
+/// 订阅有新通知的用户，推送连接收到自己的用户 id 后加载新通知
+pub fn watch() -> broadcast::Receiver<UserId> {
+    sender().subscribe()
+}
+
+/// 标记通知为已读，ids 为空时标记全部，返回实际标记的数量
+pub async fn mark_read(user_id: UserId, ids: Vec<NotificationId>) -> Result<usize> {
+    let conn = &mut pg_conn().await?;
+    repo_notification::mark_read(user_id, &ids, conn).await
+}
@@ -0,0 +1,258 @@
+//! 组织
+//!
+//! 创建组织时同时创建共享空间的账号，成员在请求头 `X-Org-Id` 中指定组织即可操作共享空间。
+//! 共享空间的文件、配额和积分都记在空间账号上，管理员通过空间账号的 id 为组织充值
+
+use anyhow::{bail, Result};
+use derive_more::From;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, pg_conn_read, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_err, biz_ok,
+    domain::{
+        organization::{OrgErr, OrgId, OrgRole, Organization},
+        user::{
+            employee::EmployeeId,
+            service,
+            user::{User, UserId},
+            Email, Password,
+        },
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{repo_organization, repo_user},
+    pg_tx,
+};
+
+use super::audit::{self, AuditAction};
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrgDto {
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrgResp {
+    org_id: OrgId,
+    space_id: UserId,
+}
+
+/// 创建组织，创建者成为所有者
+pub async fn create(user_id: UserId, params: CreateOrgDto) -> BizResult<CreateOrgResp, OrgErr> {
+    let space = User::create_org_space(Password::random().await?);
+    let org = ensure_biz!(Organization::create(params.name, *space.id()));
+    pg_tx!(create_tx, user_id, space, org)
+}
+
+async fn create_tx(
+    user_id: UserId,
+    space: User,
+    org: Organization,
+    conn: &mut PgConn,
+) -> BizResult<CreateOrgResp, OrgErr> {
+    let Ok(space_id) = service::register_tx(space, conn).await? else {
+        bail!("org space email already registered: {}", org.space_id());
+    };
+    repo_organization::save(&org, conn).await?;
+    repo_organization::add_member(*org.id(), user_id, OrgRole::Owner, conn).await?;
+    biz_ok!(CreateOrgResp {
+        org_id: *org.id(),
+        space_id,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrgDto {
+    id: OrgId,
+    name: String,
+    /// 共享空间的账号
+    space_id: UserId,
+    role: OrgRole,
+    /// 共享空间的存储配额，为空时按空间账号的等级
+    storage_quota: Option<u64>,
+}
+
+/// 用户加入的所有组织
+pub async fn list_mine(user_id: UserId) -> Result<Vec<OrgDto>> {
+    let conn = &mut pg_conn_read().await?;
+    let orgs = repo_organization::list_for_user(user_id, conn).await?;
+    let orgs = orgs
+        .into_iter()
+        .map(|(org, role)| OrgDto {
+            id: *org.id(),
+            name: org.name().clone(),
+            space_id: *org.space_id(),
+            role,
+            storage_quota: *org.storage_quota(),
+        })
+        .collect();
+    Ok(orgs)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberDto {
+    user_id: UserId,
+    email: String,
+    role: OrgRole,
+}
+
+/// 组织的所有成员，只有成员可以查看
+pub async fn members(user_id: UserId, org_id: OrgId) -> BizResult<Vec<MemberDto>, OrgErr> {
+    let conn = &mut pg_conn_read().await?;
+    let role = repo_organization::find_role(org_id, user_id, conn).await?;
+    ensure_biz!(role.is_some(), OrgErr::NotMember);
+
+    let members = repo_organization::members(org_id, conn).await?;
+    let members = members
+        .into_iter()
+        .map(|(user_id, email, role)| MemberDto {
+            user_id,
+            email,
+            role,
+        })
+        .collect();
+    biz_ok!(members)
+}
+
+/// 用户操作组织空间时使用的账号
+pub async fn resolve_space(user_id: UserId, org_id: OrgId) -> BizResult<UserId, OrgErr> {
+    let conn = &mut pg_conn_read().await?;
+    let role = repo_organization::find_role(org_id, user_id, conn).await?;
+    ensure_biz!(role.is_some(), OrgErr::NotMember);
+    let org = repo_organization::find(org_id, conn).await?;
+    let org = ensure_exist!(org, OrgErr::NotMember);
+    biz_ok!(*org.space_id())
+}
+
+#[derive(From, Debug)]
+pub enum ManageMemberErr {
+    Org(OrgErr),
+    UserNotFound,
+    AlreadyMember,
+    MemberNotFound,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddMemberDto {
+    org_id: OrgId,
+    email: String,
+    role: OrgRole,
+}
+
+/// 按邮箱邀请已注册的用户加入组织
+pub async fn add_member(operator: UserId, params: AddMemberDto) -> BizResult<(), ManageMemberErr> {
+    let conn = &mut pg_conn().await?;
+    let role = repo_organization::find_role(params.org_id, operator, conn).await?;
+    let role = ensure_exist!(role, OrgErr::NotMember);
+    ensure_biz!(role.can_invite(params.role), OrgErr::PermissionDenied);
+
+    let Ok(email) = Email::try_from(params.email) else {
+        return biz_err!(ManageMemberErr::UserNotFound);
+    };
+    let user = repo_user::find(&email, conn).await?;
+    let user = ensure_exist!(user, ManageMemberErr::UserNotFound);
+    ensure_biz!(not user.has_placeholder_email(), ManageMemberErr::UserNotFound);
+
+    let added = repo_organization::add_member(params.org_id, *user.id(), params.role, conn).await?;
+    ensure_biz!(added, ManageMemberErr::AlreadyMember);
+    biz_ok!(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetRoleDto {
+    org_id: OrgId,
+    user_id: UserId,
+    role: OrgRole,
+}
+
+pub async fn set_role(operator: UserId, params: SetRoleDto) -> BizResult<(), ManageMemberErr> {
+    pg_tx!(
+        change_member_tx,
+        operator,
+        params.org_id,
+        params.user_id,
+        Some(params.role)
+    )
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveMemberDto {
+    org_id: OrgId,
+    /// 为自己时表示退出组织
+    user_id: UserId,
+}
+
+pub async fn remove_member(
+    operator: UserId,
+    params: RemoveMemberDto,
+) -> BizResult<(), ManageMemberErr> {
+    pg_tx!(
+        change_member_tx,
+        operator,
+        params.org_id,
+        params.user_id,
+        None
+    )
+}
+
+/// 修改成员角色，to 为空时移出组织
+async fn change_member_tx(
+    operator: UserId,
+    org_id: OrgId,
+    target: UserId,
+    to: Option<OrgRole>,
+    conn: &mut PgConn,
+) -> BizResult<(), ManageMemberErr> {
+    // 先锁住所有者，并发修改时所有者数量保持准确
+    let owners = repo_organization::owner_count(org_id, conn).await?;
+    let operator_role = repo_organization::find_role(org_id, operator, conn).await?;
+    let operator_role = ensure_exist!(operator_role, OrgErr::NotMember);
+    let target_role = repo_organization::find_role(org_id, target, conn).await?;
+    let target_role = ensure_exist!(target_role, ManageMemberErr::MemberNotFound);
+    ensure_biz!(OrgRole::check_change(
+        (operator, operator_role),
+        (target, target_role),
+        to,
+        owners
+    ));
+
+    match to {
+        Some(role) => repo_organization::set_role(org_id, target, role, conn).await?,
+        None => repo_organization::remove_member(org_id, target, conn).await?,
+    }
+    biz_ok!(())
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetOrgQuotaDto {
+    org_id: OrgId,
+    /// 存储配额（byte），为空时按空间账号的等级
+    storage_quota: Option<u64>,
+}
+
+pub enum SetOrgQuotaErr {
+    OrgNotFound,
+}
+
+/// 管理员设置组织的存储配额
+pub async fn set_quota(
+    employee_id: EmployeeId,
+    params: SetOrgQuotaDto,
+) -> BizResult<(), SetOrgQuotaErr> {
+    let conn = &mut pg_conn().await?;
+    let found = repo_organization::set_quota(params.org_id, params.storage_quota, conn).await?;
+    ensure_biz!(found, SetOrgQuotaErr::OrgNotFound);
+
+    audit::record(employee_id, AuditAction::SetOrgQuota, &params).await?;
+    biz_ok!(())
+}
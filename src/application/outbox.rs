@@ -0,0 +1,305 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use chrono::Local;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+
+use crate::{
+    domain::{
+        transcode_order::{TranscodeTask, TranscodeTaskId},
+        user::{user::UserId, webhook},
+    },
+    infrastructure::{
+        av1_factory::{self, FactoryTask},
+        repo_outbox::{self, NewOutboxPo, OutboxPo, OutboxStatus},
+    },
+    log_if_err,
+    settings::get_settings,
+};
+
+use super::{
+    user::tier,
+    worker::{self, Route},
+};
+
+/// 发送 av1-factory 请求的配置
+#[derive(Debug, Deserialize)]
+pub struct OutboxCfg {
+    /// 没有新消息时轮询的间隔
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 超过此次数后放弃发送
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试的等待时间，之后每次翻倍
+    #[serde(default = "default_base_backoff_secs")]
+    pub base_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// 发送期间的租约，超时未更新状态时可以被重新发送
+    #[serde(default = "default_lease_secs")]
+    pub lease_secs: u64,
+}
+
+impl Default for OutboxCfg {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: default_poll_interval_secs(),
+            max_attempts: default_max_attempts(),
+            base_backoff_secs: default_base_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            lease_secs: default_lease_secs(),
+        }
+    }
+}
+
+fn default_poll_interval_secs() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> u32 {
+    10
+}
+
+fn default_base_backoff_secs() -> u64 {
+    2
+}
+
+fn default_max_backoff_secs() -> u64 {
+    300
+}
+
+fn default_lease_secs() -> u64 {
+    60
+}
+
+/// 有新消息时唤醒发送任务，不必等到下一次轮询
+static WAKE: Notify = Notify::const_new();
+
+/// 信息采集、缩略图等请求耗时很短，排在所有转码请求之前
+const SYSTEM_PRIORITY: i16 = i16::MAX;
+
+/// 将任务的转码请求写入 outbox，必须与任务状态的修改在同一个事务中调用。
+/// 事务提交后调用 [`wake`] 尽快发送。下发优先级由用户等级决定
+pub(crate) async fn enqueue_transcode<'a>(
+    user_id: UserId,
+    tasks: impl IntoIterator<Item = &'a TranscodeTask>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let priority = tier::limits(user_id).await?.priority;
+    let messages = tasks
+        .into_iter()
+        .map(|task| {
+            let payload =
+                av1_factory::transcode_payload(*task.id(), *task.sys_file_id(), task.params())?;
+            Ok(NewOutboxPo {
+                task_id: task.id().0,
+                payload,
+                priority,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    repo_outbox::save(&messages, conn).await
+}
+
+/// 将取消转码的请求写入 outbox，必须与取消任务在同一个事务中调用。
+/// 取消请求不受用户等级的限制，与信息采集等请求一起优先发送
+pub(crate) async fn enqueue_cancel<'a>(
+    tasks: impl IntoIterator<Item = &'a TranscodeTask>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let messages = tasks
+        .into_iter()
+        .map(|task| {
+            let request = av1_factory::cancel_task(*task.id(), *task.sys_file_id())?;
+            Ok(NewOutboxPo {
+                task_id: request.id,
+                payload: request.payload,
+                priority: SYSTEM_PRIORITY,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if messages.is_empty() {
+        return Ok(());
+    }
+    repo_outbox::save(&messages, conn).await
+}
+
+pub(crate) fn wake() {
+    WAKE.notify_one();
+}
+
+/// 立即发送信息采集、缩略图等请求，重试后仍失败或熔断时写入 outbox，由后台任务稍后发送
+pub(crate) async fn send_or_enqueue(task: FactoryTask) -> Result<()> {
+    let err = match av1_factory::send_with_retry(&task.payload).await {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    warn!(
+        ?err,
+        task_id = task.id,
+        "av1-factory unavailable, enqueue the request"
+    );
+    let message = NewOutboxPo {
+        task_id: task.id,
+        payload: task.payload,
+        priority: SYSTEM_PRIORITY,
+    };
+    let conn = &mut pg_conn().await?;
+    repo_outbox::save(&[message], conn).await
+}
+
+/// 启动后台发送任务
+pub fn spawn_dispatcher() {
+    let cfg = &get_settings().outbox;
+
+    info!(?cfg, "av1-factory outbox dispatcher started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(dispatch_due(cfg).await);
+            let _ =
+                tokio::time::timeout(Duration::from_secs(cfg.poll_interval_secs), WAKE.notified())
+                    .await;
+        }
+    });
+}
+
+async fn dispatch_due(cfg: &OutboxCfg) -> Result<()> {
+    // 熔断期间不发送，也不消耗重试次数。转码请求发给注册的节点时不受影响
+    if av1_factory::is_circuit_open() && !get_settings().worker.enable {
+        return Ok(());
+    }
+    let messages = {
+        let conn = &mut pg_conn().await?;
+        let messages = repo_outbox::due_messages(100, conn).await?;
+        let (messages, deferred) = throttle(messages, conn).await?;
+        if !deferred.is_empty() {
+            debug!(
+                count = deferred.len(),
+                "defer transcode requests over the tier limit"
+            );
+            let until = Local::now() + chrono::Duration::seconds(cfg.poll_interval_secs as i64);
+            repo_outbox::defer(&deferred, until, conn).await?;
+        }
+        messages
+    };
+
+    futures_util::stream::iter(messages)
+        .for_each_concurrent(8, |message| async move {
+            log_if_err!(dispatch(cfg, message).await);
+        })
+        .await;
+    Ok(())
+}
+
+/// 按用户等级限制同时转码的任务数，返回可以发送的消息和需要推迟的消息 id
+async fn throttle(
+    messages: Vec<(OutboxPo, Option<UserId>)>,
+    conn: &mut PgConn,
+) -> Result<(Vec<OutboxPo>, Vec<i64>)> {
+    let mut user_ids: Vec<_> = messages.iter().filter_map(|(_, user)| *user).collect();
+    user_ids.sort_by_key(|id| id.0);
+    user_ids.dedup();
+    let mut active = repo_outbox::active_transcodes(&user_ids, conn).await?;
+
+    let mut limits = HashMap::new();
+    let mut send = vec![];
+    let mut deferred = vec![];
+    for (message, user_id) in messages {
+        let Some(user_id) = user_id else {
+            send.push(message);
+            continue;
+        };
+        let max = match limits.get(&user_id) {
+            Some(max) => *max,
+            None => {
+                let max = tier::limits(user_id).await?.max_active_tasks;
+                limits.insert(user_id, max);
+                max
+            }
+        };
+        let count = active.entry(user_id).or_default();
+        if max.is_some_and(|max| *count >= max as i64) {
+            deferred.push(message.id);
+        } else {
+            *count += 1;
+            send.push(message);
+        }
+    }
+    Ok((send, deferred))
+}
+
+async fn dispatch(cfg: &OutboxCfg, message: OutboxPo) -> Result<()> {
+    let route = worker::route(&message.payload).await?;
+    match route {
+        Route::Wait => {
+            debug!(task_id = message.task_id, "no worker available");
+            return Ok(());
+        }
+        Route::Factory if av1_factory::is_circuit_open() => return Ok(()),
+        _ => {}
+    }
+
+    let conn = &mut pg_conn().await?;
+    let lease = Local::now() + chrono::Duration::seconds(cfg.lease_secs as i64);
+    if !repo_outbox::claim(&message, lease, conn).await? {
+        return Ok(());
+    }
+
+    let task_id = message.task_id;
+    let result = match &route {
+        Route::Worker(worker) => {
+            let cancel = av1_factory::cancel_target(&message.payload).is_some();
+            let sent = av1_factory::send_to_worker(&worker.endpoint, message.payload).await;
+            if sent.is_ok() && !cancel {
+                log_if_err!(worker::assigned(TranscodeTaskId(task_id), worker.id).await);
+            }
+            sent
+        }
+        _ => av1_factory::send_payload(message.payload).await,
+    };
+
+    let attempts = message.attempts + 1;
+    match result {
+        Ok(_) => {
+            debug!(task_id, "factory task request sent");
+            repo_outbox::update(
+                message.id,
+                OutboxStatus::Sent,
+                attempts,
+                Local::now(),
+                None,
+                conn,
+            )
+            .await?;
+        }
+        Err(err) => {
+            let err = format!("{:#}", err);
+            let status = if attempts as u32 >= cfg.max_attempts {
+                warn!(task_id, %err, "gave up sending factory task request");
+                OutboxStatus::GaveUp
+            } else {
+                OutboxStatus::Pending
+            };
+            let delay = webhook::retry_delay(
+                attempts as u32,
+                Duration::from_secs(cfg.base_backoff_secs),
+                Duration::from_secs(cfg.max_backoff_secs),
+            );
+            repo_outbox::update(
+                message.id,
+                status,
+                attempts,
+                Local::now() + chrono::Duration::seconds(delay.as_secs() as i64),
+                Some(&err),
+                conn,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+//! 预约转码
+//!
+//! 用户可以把订单预约到空闲时段，创建订单时照常扣除积分、锁定源文件，
+//! 但订单和任务处于预约状态，转码请求不会写入 outbox。后台任务定期检查预约时间已到的订单并下发。
+//! 下发之前可以修改预约时间或取消订单。
+//! 下发时同样检查未结束的任务数，超出上限的订单继续等待，下次检查时再下发
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use serde::Deserialize;
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_err, biz_ok,
+    domain::{
+        transcode_order::{TranscocdeOrder, TranscodeOrderId},
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::repo_order,
+    log_if_err, pg_tx,
+    settings::get_settings,
+    tx_func, LocalDataTime,
+};
+
+use super::{outbox, transcode::exceeds_task_limit};
+
+/// 每次检查时最多下发的订单数
+const DISPATCH_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleCfg {
+    /// 检查预约订单的间隔
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 最多可以预约多少天之后
+    #[serde(default = "default_max_days")]
+    pub max_days: i64,
+}
+
+impl Default for ScheduleCfg {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: default_poll_interval_secs(),
+            max_days: default_max_days(),
+        }
+    }
+}
+
+fn default_poll_interval_secs() -> u64 {
+    30
+}
+
+fn default_max_days() -> i64 {
+    30
+}
+
+pub(crate) enum StartTime {
+    /// 预约时间已经过去，立即开始
+    Now,
+    At(LocalDataTime),
+    Invalid,
+}
+
+/// 解析用户指定的预约时间（毫秒时间戳）
+pub(crate) fn start_time(ms: i64) -> StartTime {
+    let Some(time) = Local.timestamp_millis_opt(ms).single() else {
+        return StartTime::Invalid;
+    };
+    let now = Local::now();
+    if time <= now {
+        return StartTime::Now;
+    }
+    if time > now + chrono::Duration::days(get_settings().order_schedule.max_days) {
+        return StartTime::Invalid;
+    }
+    StartTime::At(time)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RescheduleDto {
+    order_id: TranscodeOrderId,
+    /// 新的预约时间，毫秒时间戳。早于当前时间时立即开始转码
+    start_after: i64,
+}
+
+pub enum RescheduleErr {
+    OrderNotFound,
+    /// 订单已经开始转码或已取消
+    NotScheduled,
+    InvalidStartTime,
+}
+
+/// 修改预约订单的开始时间
+pub async fn reschedule(user_id: UserId, params: RescheduleDto) -> BizResult<(), RescheduleErr> {
+    let resp = pg_tx!(reschedule_tx, user_id, params);
+    if matches!(resp, Ok(Ok(_))) {
+        outbox::wake();
+    }
+    resp
+}
+
+async fn reschedule_tx(
+    user_id: UserId,
+    params: RescheduleDto,
+    conn: &mut PgConn,
+) -> BizResult<(), RescheduleErr> {
+    use RescheduleErr::*;
+
+    let order = repo_order::find_order(params.order_id, conn).await?;
+    let mut order = ensure_exist!(order, OrderNotFound);
+    ensure_biz!(*order.user_id() == user_id, OrderNotFound);
+    ensure_biz!(order.is_scheduled(), NotScheduled);
+
+    match start_time(params.start_after) {
+        StartTime::Invalid => return biz_err!(InvalidStartTime),
+        StartTime::Now if exceeds_task_limit(&order, conn).await? => {
+            // 超出任务数上限时留在队列中，由后台任务在有空余时下发
+            order.reschedule(Local::now());
+            repo_order::update(&order, conn).await?;
+        }
+        StartTime::Now => {
+            order.dispatch();
+            start(&order, conn).await?;
+        }
+        StartTime::At(time) => {
+            order.reschedule(time);
+            repo_order::update(&order, conn).await?;
+        }
+    }
+    biz_ok!(())
+}
+
+/// 启动后台任务，下发预约时间已到的订单
+pub fn spawn() {
+    let cfg = &get_settings().order_schedule;
+
+    info!(?cfg, "scheduled order dispatcher started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(dispatch_due().await);
+            tokio::time::sleep(Duration::from_secs(cfg.poll_interval_secs)).await;
+        }
+    });
+}
+
+async fn dispatch_due() -> Result<()> {
+    let order_ids = {
+        let conn = &mut pg_conn().await?;
+        repo_order::find_due_scheduled(Local::now(), DISPATCH_BATCH_SIZE, conn).await?
+    };
+
+    let mut dispatched = false;
+    for order_id in order_ids {
+        match dispatch(order_id).await {
+            Ok(ok) => dispatched |= ok,
+            Err(err) => warn!(?err, %order_id, "dispatch scheduled order failed"),
+        }
+    }
+    if dispatched {
+        outbox::wake();
+    }
+    Ok(())
+}
+
+async fn dispatch(order_id: TranscodeOrderId) -> Result<bool> {
+    tx_func!(dispatch_tx, order_id)
+}
+
+/// 订单已被取消、修改了预约时间或已被其他实例下发时什么也不做，
+/// 用户未结束的任务数超出上限时订单留在队列中
+async fn dispatch_tx(order_id: TranscodeOrderId, conn: &mut PgConn) -> Result<bool> {
+    let Some(mut order) = repo_order::find_order(order_id, conn).await? else {
+        return Ok(false);
+    };
+    let due = order
+        .start_after()
+        .map_or(true, |time| time <= Local::now());
+    if !due || !order.is_scheduled() {
+        return Ok(false);
+    }
+    if exceeds_task_limit(&order, conn).await? {
+        // 移到队尾，避免等待中的订单占满每次检查的批次
+        info!(%order_id, "too many active tasks, scheduled order keeps waiting");
+        order.reschedule(Local::now());
+        repo_order::update(&order, conn).await?;
+        return Ok(false);
+    }
+    order.dispatch();
+    info!(%order_id, "scheduled order started");
+    start(&order, conn).await?;
+    Ok(true)
+}
+
+/// 保存订单状态并把转码请求写入 outbox
+async fn start(order: &TranscocdeOrder, conn: &mut PgConn) -> Result<()> {
+    repo_order::update(order, conn).await?;
+    repo_order::reset_started_at(*order.id(), conn).await?;
+    outbox::enqueue_transcode(
+        *order.user_id(),
+        order.tasks().iter().filter(|t| t.status().is_processing()),
+        conn,
+    )
+    .await
+}
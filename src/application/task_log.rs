@@ -0,0 +1,185 @@
+//! 转码任务日志
+//!
+//! 转码失败时 err_msg 只有一行原因，转码工厂在任务结束后上传完整的编码日志，
+//! 保存在源文件的转码工作目录中，重新下发的任务会覆盖之前的日志。
+//! 用户只能查看自己任务的日志末尾，管理员可以查看完整的日志和任务参数
+
+use anyhow::Result;
+use futures_util::Stream;
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::file::{SysFileId, UserFileId},
+        transcode_order::{
+            params::TranscodeTaskParams, worker::WorkerId, TranscodeOrderId, TranscodeTaskId,
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        file_sys,
+        repo_order::{self, TaskDetailPo},
+    },
+};
+
+/// 单个任务日志的最大字节数
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024 * 16;
+
+/// 返回给用户的日志末尾的字节数
+const USER_TAIL_SIZE: u64 = 1024 * 64;
+
+#[derive(Serialize, ToSchema, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskStatusDto {
+    Processing,
+    Ok,
+    Failed,
+    Cancelled,
+    Scheduled,
+}
+
+impl TaskStatusDto {
+    fn from_po(status: i16) -> Self {
+        match status {
+            0 => Self::Processing,
+            1 => Self::Ok,
+            2 => Self::Failed,
+            4 => Self::Scheduled,
+            _ => Self::Cancelled,
+        }
+    }
+}
+
+pub enum StoreTaskLogErr {
+    TaskNotFound,
+    TooLarge,
+}
+
+/// 保存转码工厂上传的日志
+pub async fn store<S, B>(task_id: TranscodeTaskId, data: S) -> BizResult<(), StoreTaskLogErr>
+where
+    S: Stream<Item = anyhow::Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    let task = ensure_exist!(find_task(task_id).await?, StoreTaskLogErr::TaskNotFound);
+    let path = task_params(&task)?.log_path(task_id);
+    ensure_biz!(
+        file_sys::store_file(data, &path, MAX_LOG_SIZE).await?,
+        StoreTaskLogErr::TooLarge
+    );
+    biz_ok!(())
+}
+
+pub enum TaskLogErr {
+    TaskNotFound,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLogDto {
+    task_id: TranscodeTaskId,
+    status: TaskStatusDto,
+    err_msg: Option<String>,
+    /// 日志的末尾部分，还没有上传日志时为空
+    log: Option<String>,
+    /// 完整日志的字节数
+    log_size: u64,
+    /// 日志是否只返回了末尾部分
+    truncated: bool,
+}
+
+/// 用户查看自己任务的日志
+pub async fn user_log(
+    user_id: UserId,
+    task_id: TranscodeTaskId,
+) -> BizResult<TaskLogDto, TaskLogErr> {
+    let task = ensure_exist!(find_task(task_id).await?, TaskLogErr::TaskNotFound);
+    ensure_biz!(task.user_id == user_id, TaskLogErr::TaskNotFound);
+
+    let path = task_params(&task)?.log_path(task_id);
+    let tail = file_sys::read_tail(&path, USER_TAIL_SIZE).await?;
+    let (log, log_size) = match tail {
+        Some((tail, size)) => (Some(String::from_utf8_lossy(&tail).into_owned()), size),
+        None => (None, 0),
+    };
+    biz_ok!(TaskLogDto {
+        task_id,
+        status: TaskStatusDto::from_po(task.status),
+        err_msg: task.err_msg,
+        log,
+        log_size,
+        truncated: log_size > USER_TAIL_SIZE,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLogDetailDto {
+    task_id: TranscodeTaskId,
+    order_id: TranscodeOrderId,
+    user_id: UserId,
+    user_file_id: UserFileId,
+    sys_file_id: SysFileId,
+    virtual_path: String,
+    status: TaskStatusDto,
+    err_msg: Option<String>,
+    /// 发给转码工厂的参数
+    #[schema(value_type = Object)]
+    params: serde_json::Value,
+    retries: i16,
+    /// 最近一次分配到的转码节点
+    worker_id: Option<WorkerId>,
+    started_at: MillionTimestamp,
+    create_at: MillionTimestamp,
+    updated_at: MillionTimestamp,
+    /// 日志在磁盘上的路径
+    log_path: String,
+    /// 完整的日志，还没有上传日志时为空
+    log: Option<String>,
+    log_size: u64,
+}
+
+/// 管理员查看任务的完整日志和参数
+pub async fn admin_log(task_id: TranscodeTaskId) -> BizResult<TaskLogDetailDto, TaskLogErr> {
+    let task = ensure_exist!(find_task(task_id).await?, TaskLogErr::TaskNotFound);
+    let path = task_params(&task)?.log_path(task_id);
+    let (log, log_size) = match file_sys::read_tail(&path, MAX_LOG_SIZE).await? {
+        Some((log, size)) => (Some(String::from_utf8_lossy(&log).into_owned()), size),
+        None => (None, 0),
+    };
+
+    biz_ok!(TaskLogDetailDto {
+        task_id,
+        order_id: task.order_id,
+        user_id: task.user_id,
+        user_file_id: task.user_file_id,
+        sys_file_id: task.sys_file_id,
+        status: TaskStatusDto::from_po(task.status),
+        err_msg: task.err_msg,
+        params: serde_json::from_str(&task.params)?,
+        retries: task.retries,
+        worker_id: task.worker_id,
+        started_at: task.started_at.into(),
+        create_at: task.create_at.into(),
+        updated_at: task.updated_at.into(),
+        log_path: path.to_string_lossy().into_owned(),
+        virtual_path: task.virtual_path,
+        log,
+        log_size,
+    })
+}
+
+async fn find_task(task_id: TranscodeTaskId) -> Result<Option<TaskDetailPo>> {
+    let conn = &mut pg_conn().await?;
+    repo_order::find_task(task_id, conn).await
+}
+
+fn task_params(task: &TaskDetailPo) -> Result<TranscodeTaskParams> {
+    Ok(serde_json::from_str(&task.params)?)
+}
@@ -0,0 +1,98 @@
+//! 转码结果对比
+//!
+//! 任务完成后对比源文件和转码结果的大小、码率、编码等信息，用于前端展示结果卡片。
+//! 转码结果的视频信息在文件解析完成后才有，解析完成之前只返回文件大小
+
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::{
+        transcode_order::{metrics::compression_ratio, TranscodeTaskId},
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        repo_order,
+        repo_user_file::{self, VideoPo},
+    },
+};
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaSummaryDto {
+    /// 文件大小，字节
+    size: i64,
+    /// 码率，bit/s
+    bit_rate: Option<i32>,
+    /// 视频编码，如 AVC、HEVC、AV1
+    codec: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+    duration_ms: Option<i32>,
+}
+
+impl MediaSummaryDto {
+    fn from_po(video: VideoPo) -> Self {
+        Self {
+            size: video.size,
+            bit_rate: video.bit_rate,
+            codec: video.video_info.and_then(|info| info.Format),
+            width: video.width,
+            height: video.height,
+            duration_ms: video.duration_ms,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskResultDto {
+    task_id: TranscodeTaskId,
+    source: MediaSummaryDto,
+    /// 转码结果已被删除或是升级前完成的任务时为空
+    output: Option<MediaSummaryDto>,
+    /// 源文件大小 / 转码结果大小
+    compression_ratio: Option<f64>,
+}
+
+pub enum TaskResultErr {
+    TaskNotFound,
+    /// 任务还没有成功完成
+    NotFinished,
+}
+
+/// 查看自己的转码任务的结果对比
+pub async fn task_result(
+    user_id: UserId,
+    task_id: TranscodeTaskId,
+) -> BizResult<TaskResultDto, TaskResultErr> {
+    let conn = &mut pg_conn().await?;
+    let task = repo_order::find_task_output(task_id, conn).await?;
+    let task = ensure_exist!(
+        task.filter(|task| task.user_id == user_id),
+        TaskResultErr::TaskNotFound
+    );
+    // 1 表示转码成功
+    ensure_biz!(task.status == 1, TaskResultErr::NotFinished);
+
+    let source = repo_user_file::find_sys_video(task.sys_file_id, conn).await?;
+    let source = ensure_exist!(source, TaskResultErr::TaskNotFound);
+    let output = match &task.output_hash {
+        Some(hash) => repo_user_file::find_sys_video(hash.as_str(), conn).await?,
+        None => None,
+    };
+
+    let ratio = output
+        .as_ref()
+        .and_then(|output| compression_ratio(source.size as u64, output.size as u64));
+    biz_ok!(TaskResultDto {
+        task_id,
+        source: MediaSummaryDto::from_po(source),
+        output: output.map(MediaSummaryDto::from_po),
+        compression_ratio: ratio,
+    })
+}
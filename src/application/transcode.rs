@@ -1,33 +1,77 @@
 use anyhow::{anyhow, Context};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
-use utils::db_pools::postgres::{pg_conn, PgConn};
+use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
+use validator::Validate;
 
-use crate::domain::file_system::file::{UserFileId, VideoInfo, VirtualPath};
+use crate::domain::event::{TranscodeOrderFinished, TranscodeTaskDone};
+use crate::domain::file_system::file::{FileNode, UserFileId, VideoInfo, VirtualPath};
 use crate::domain::file_system::service::path_manager;
-use crate::domain::transcode_order::params::audio::AudioProcessParameters;
+use crate::domain::file_system::subtitle::SubtitleFormat;
+use crate::domain::notification::NotificationEvent;
+use crate::domain::transcode_order::params::audio::{AudioFormat, AudioProcessParameters};
 use crate::domain::transcode_order::params::zcode::{
     OutputQuality, RayTracing, Resolution, VideoFormat, ZcodeProcessParams,
 };
-use crate::domain::transcode_order::params::{ContainerFormat, TranscodeTaskParams};
-use crate::domain::transcode_order::{service, TranscodeTaskId};
-use crate::infrastructure::{av1_factory, repo_order, repo_user_file};
-use crate::{biz_ok, ensure_biz, ensure_exist, tx_func};
+use crate::domain::transcode_order::params::{
+    ContainerFormat, SubtitleMode, SubtitleParams, TranscodeTaskParams,
+};
+use crate::domain::transcode_order::{
+    compat::{self, ParamsErr},
+    metrics::QualityMetrics,
+    preview::SampleRange,
+    recommend, service, TranscocdeOrder, TranscodeTaskId,
+};
+use crate::domain::user::webhook::{OrderCreated, OrderFinished, TaskCompleted, WebhookEvent};
+use crate::infrastructure::repo_credit::{self, CreditKind};
+use crate::infrastructure::{
+    repo_file_lock, repo_order, repo_outbox, repo_subtitle, repo_user, repo_user_file,
+};
+use crate::{biz_err, biz_ok, ensure_biz, ensure_exist, pg_tx, settings::get_settings, tx_func};
 use crate::{
     domain::{transcode_order::TranscodeOrderId, user::user::UserId},
     http::BizResult,
 };
 use anyhow::Result;
 
-use super::file_system;
+use super::schedule::{self, StartTime};
+use super::user::tier;
+use super::{credits, event_bus, file_system, notification, outbox, webhook};
 
+#[derive(Debug)]
 pub enum CreateOrderErr {
     FileNotFound,
     CannotTransDir,
     NotAVideo,
+    SubtitleNotAttached,
+    MultipleBurnedSubtitles,
+    DuplicateRendition,
+    InsufficientCredits,
+    /// 存放转码结果的磁盘空间不足
+    InsufficientStorage,
+    /// 预约时间超出允许的范围
+    InvalidStartTime,
+    /// 未结束的任务数超出用户等级的上限
+    TooManyActiveTasks,
+    /// 订单本身的任务数就超出用户等级的上限，永远不能开始
+    TooManyTasks,
+    /// 转码参数与源视频或封装格式不兼容
+    IncompatibleParams(ParamsErr),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+pub enum CancelOrderErr {
+    OrderNotFound,
+    AlreadyEnded,
+}
+
+pub enum RecommendErr {
+    FileNotFound,
+    NotAVideo,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct TranscodeParamsDto {
     pub file_id: UserFileId,
@@ -36,9 +80,56 @@ pub struct TranscodeParamsDto {
     pub video: ZcodeProcessParamsDto,
     #[serde(default)]
     pub audio: Option<AudioProcessParameters>,
+    /// 需要封装或烧录的字幕，必须已经关联到这个视频
+    #[serde(default)]
+    #[validate(length(max = 8))]
+    pub subtitles: Vec<SubtitleParamsDto>,
+    /// 同时输出多个清晰度，每个清晰度对应一个转码任务。为空时只按 video 输出一份
+    #[serde(default)]
+    #[validate(length(max = 8))]
+    pub renditions: Vec<RenditionDto>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenditionDto {
+    pub resolution: Option<Resolution>,
+    /// 为空时使用 video 中的 quality
+    #[serde(default)]
+    pub quality: Option<OutputQuality>,
+}
+
+impl TranscodeParamsDto {
+    /// 每个清晰度生成一份视频参数，清晰度重复时返回 None
+    fn video_renditions(&self) -> Option<Vec<ZcodeProcessParamsDto>> {
+        if self.renditions.is_empty() {
+            return Some(vec![self.video]);
+        }
+
+        let mut videos: Vec<ZcodeProcessParamsDto> = vec![];
+        for rendition in &self.renditions {
+            let video = ZcodeProcessParamsDto {
+                resolution: rendition.resolution,
+                quality: rendition.quality.unwrap_or(self.video.quality),
+                ..self.video
+            };
+            if videos.contains(&video) {
+                return None;
+            }
+            videos.push(video);
+        }
+        Some(videos)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleParamsDto {
+    pub file_id: UserFileId,
+    pub mode: SubtitleMode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ZcodeProcessParamsDto {
     pub format: VideoFormat,
@@ -47,7 +138,65 @@ pub struct ZcodeProcessParamsDto {
     pub quality: OutputQuality,
 }
 
-#[derive(Serialize)]
+/// 预览订单的配置
+#[derive(Debug, Deserialize)]
+pub struct PreviewCfg {
+    /// 没有指定时转码的时长
+    #[serde(default = "default_preview_secs")]
+    pub default_secs: u32,
+    #[serde(default = "default_max_preview_secs")]
+    pub max_secs: u32,
+}
+
+impl Default for PreviewCfg {
+    fn default() -> Self {
+        Self {
+            default_secs: default_preview_secs(),
+            max_secs: default_max_preview_secs(),
+        }
+    }
+}
+
+fn default_preview_secs() -> u32 {
+    30
+}
+
+fn default_max_preview_secs() -> u32 {
+    120
+}
+
+/// 预览订单只转码视频中的一段，转码结果与完整转码的结果分开保存
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewDto {
+    /// 片段的开始位置，默认从视频中间截取
+    #[serde(default)]
+    pub start_ms: Option<u32>,
+    /// 片段的时长，默认和最大值见配置
+    #[serde(default)]
+    #[validate(range(min = 1000))]
+    pub duration_ms: Option<u32>,
+}
+
+impl PreviewDto {
+    fn sample(&self, video: &VideoInfo) -> SampleRange {
+        let cfg = &get_settings().transcode_preview;
+        let duration_ms = self
+            .duration_ms
+            .unwrap_or(cfg.default_secs * 1000)
+            .min(cfg.max_secs * 1000);
+        SampleRange::new(self.start_ms, duration_ms, video_duration_ms(video))
+    }
+}
+
+/// 没有解析出时长时按 25 帧每秒估算
+fn video_duration_ms(video: &VideoInfo) -> u32 {
+    video
+        .duration_ms
+        .unwrap_or_else(|| (video.frame_count as u64 * 40).min(u32::MAX as u64) as u32)
+}
+
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateOrderResp {
     order_id: TranscodeOrderId,
@@ -57,33 +206,144 @@ pub struct CreateOrderResp {
 pub async fn create_order(
     user_id: UserId,
     params: Vec<TranscodeParamsDto>,
+    preview: Option<PreviewDto>,
+    start_after: Option<i64>,
 ) -> BizResult<CreateOrderResp, CreateOrderErr> {
     use CreateOrderErr::*;
 
+    let start_after = match start_after.map(schedule::start_time) {
+        None | Some(StartTime::Now) => None,
+        Some(StartTime::At(time)) => Some(time),
+        Some(StartTime::Invalid) => return biz_err!(InvalidStartTime),
+    };
+
+    // 并发加载各个文件，保持参数的顺序，出错时返回第一个错误
+    let sources: Vec<_> = futures_util::stream::iter(&params)
+        .map(load_source)
+        .buffered(LOAD_CONCURRENCY)
+        .collect()
+        .await;
+
+    let credits = credits::cfg();
     let mut transcode_params = vec![];
-    for param in params {
-        let file = ensure_exist!(
-            repo_user_file::find_video(param.file_id).await?,
-            FileNotFound
-        );
-        ensure_biz!(file.is_file(), CannotTransDir);
+    let mut source_files = vec![];
+    // 转码结果写在源文件的归档目录下，大小按源文件估算
+    let mut output_dirs = vec![];
+    let mut output_size = 0;
+    for (param, source) in params.iter().zip(sources) {
+        source_files.push(param.file_id);
+        let (file, subtitles) = ensure_biz!(source?);
         let meta = file.file_data().unwrap();
-        ensure_biz!(meta.video_info.is_some(), NotAVideo);
         let video = meta.video_info.as_ref().unwrap();
 
-        let task_params = to_task_params(meta, video, param);
-        transcode_params.push((file, task_params));
+        let renditions = ensure_exist!(param.video_renditions(), DuplicateRendition);
+        let sample = preview.map(|preview| preview.sample(video));
+        // 预览订单的积分和结果大小按片段的时长计算
+        let charged = match sample {
+            Some(sample) => VideoInfo {
+                duration_ms: Some(sample.duration_ms),
+                frame_count: sample.frame_count(video.frame_count, video_duration_ms(video)),
+                ..video.clone()
+            },
+            None => video.clone(),
+        };
+        let size = match sample {
+            Some(sample) => {
+                let total_ms = video_duration_ms(video).max(1) as u64;
+                meta.size * sample.duration_ms as u64 / total_ms
+            }
+            None => meta.size,
+        };
+        for rendition in renditions {
+            let task_params =
+                to_task_params(meta, video, param, rendition, subtitles.clone(), sample);
+            let audio = param.audio.as_ref().map(|audio| audio.format);
+            ensure_biz!(
+                compat::check(&task_params.video, param.container_format, audio)
+                    .map_err(IncompatibleParams)
+            );
+            let cost = if credits.enable {
+                credits.pricing.task_cost(&charged, &task_params.video)
+            } else {
+                0
+            };
+            transcode_params.push((file.clone(), task_params, cost));
+            output_size += size;
+        }
+        output_dirs.push(path_manager().transcode_work_dir(&meta.hash));
     }
+    let output_dirs: Vec<_> = output_dirs.iter().map(|dir| dir.as_path()).collect();
+    ensure_biz!(
+        file_system::disk_space::is_enough(&output_dirs, output_size)?,
+        InsufficientStorage
+    );
 
-    let order = service::create_order(user_id, transcode_params);
-    for task in order.tasks() {
-        av1_factory::transcode(*task.id(), *task.sys_file_id(), task.params())
-            .await
-            .context("send task request")?;
+    let order = service::create_order(user_id, transcode_params, start_after);
+    let resp = pg_tx!(create_order_tx, order);
+    if matches!(resp, Ok(Ok(_))) {
+        outbox::wake();
+        let touches = source_files
+            .into_iter()
+            .map(|file_id| file_system::recent::touch(user_id, file_id));
+        futures_util::future::join_all(touches).await;
     }
+    resp
+}
+
+/// 创建订单时同时加载的源文件数
+const LOAD_CONCURRENCY: usize = 8;
 
-    let conn = &mut pg_conn().await?;
+/// 加载并检查源视频和要使用的字幕
+async fn load_source(
+    param: &TranscodeParamsDto,
+) -> BizResult<(FileNode, Vec<SubtitleParams>), CreateOrderErr> {
+    use CreateOrderErr::*;
+
+    let file = ensure_exist!(
+        repo_user_file::find_video(param.file_id).await?,
+        FileNotFound
+    );
+    ensure_biz!(file.is_file(), CannotTransDir);
+    let meta = file.file_data().unwrap();
+    ensure_biz!(meta.video_info.is_some(), NotAVideo);
+    let subtitles = ensure_biz!(load_subtitles(param.file_id, &param.subtitles).await?);
+
+    biz_ok!((file, subtitles))
+}
+
+/// 扣除积分、保存订单，转码请求写入 outbox，事务提交后才会发送给 av1-factory。
+/// 预约订单的转码请求在预约时间到达后才写入
+async fn create_order_tx(
+    order: TranscocdeOrder,
+    conn: &mut PgConn,
+) -> BizResult<CreateOrderResp, CreateOrderErr> {
+    // 预约订单到时间后才检查未结束的任务数，但订单本身超出上限时无论何时都不能开始
+    ensure_biz!(
+        !over_task_limit_alone(&order).await?,
+        CreateOrderErr::TooManyTasks
+    );
+    if !order.is_scheduled() {
+        ensure_biz!(
+            !exceeds_task_limit(&order, conn).await?,
+            CreateOrderErr::TooManyActiveTasks
+        );
+    }
+    let cost = order.credits();
+    if cost > 0 {
+        let balance = repo_credit::consume(*order.user_id(), cost, *order.id(), conn).await?;
+        ensure_exist!(balance, CreateOrderErr::InsufficientCredits);
+    }
     let _ = repo_order::save(&order, conn).await?;
+    repo_file_lock::lock_order(&order, conn).await?;
+    let event = WebhookEvent::OrderCreated(OrderCreated {
+        order_id: *order.id(),
+        task_ids: order.tasks().iter().map(|t| *t.id()).collect(),
+    });
+    webhook::emit(*order.user_id(), &event, conn).await?;
+
+    if !order.is_scheduled() {
+        outbox::enqueue_transcode(*order.user_id(), order.tasks(), conn).await?;
+    }
 
     biz_ok!(CreateOrderResp {
         order_id: *order.id(),
@@ -91,10 +351,67 @@ pub async fn create_order(
     })
 }
 
+/// 加上订单中的任务后，未结束的任务数是否超出用户等级的上限。
+/// 先锁定用户，同一用户并发创建的订单依次检查
+pub(crate) async fn exceeds_task_limit(order: &TranscocdeOrder, conn: &mut PgConn) -> Result<bool> {
+    let user_id = *order.user_id();
+    let Some(max) = tier::limits(user_id).await?.max_processing_tasks else {
+        return Ok(false);
+    };
+    repo_user::lock(user_id, conn).await?;
+    let processing = repo_order::count_processing_tasks(user_id, conn).await?;
+    Ok(processing + order.tasks().len() as i64 > max as i64)
+}
+
+/// 订单的任务数是否超出用户等级的上限
+async fn over_task_limit_alone(order: &TranscocdeOrder) -> Result<bool> {
+    let limits = tier::limits(*order.user_id()).await?;
+    Ok(limits
+        .max_processing_tasks
+        .is_some_and(|max| order.tasks().len() > max as usize))
+}
+
+async fn load_subtitles(
+    video_file_id: UserFileId,
+    params: &[SubtitleParamsDto],
+) -> BizResult<Vec<SubtitleParams>, CreateOrderErr> {
+    use CreateOrderErr::*;
+
+    if params.is_empty() {
+        return Ok(Ok(vec![]));
+    }
+    let burned = params.iter().filter(|p| p.mode == SubtitleMode::Burn);
+    ensure_biz!(burned.count() <= 1, MultipleBurnedSubtitles);
+
+    let attached = repo_subtitle::find_attached(video_file_id).await?;
+    let mut subtitles = vec![];
+    for param in params {
+        let subtitle = attached
+            .iter()
+            .find(|s| s.subtitle_file_id == param.file_id);
+        let subtitle = ensure_exist!(subtitle, SubtitleNotAttached);
+        let format = ensure_exist!(
+            SubtitleFormat::from_file_name(&subtitle.file_name),
+            SubtitleNotAttached
+        );
+        subtitles.push(SubtitleParams {
+            path: subtitle.archived_path.clone(),
+            format,
+            language: subtitle.language.clone(),
+            mode: param.mode,
+        });
+    }
+
+    biz_ok!(subtitles)
+}
+
 fn to_task_params(
     meta: &crate::domain::file_system::file::FileNodeMetaData,
     video: &VideoInfo,
-    param: TranscodeParamsDto,
+    param: &TranscodeParamsDto,
+    rendition: ZcodeProcessParamsDto,
+    subtitles: Vec<SubtitleParams>,
+    sample: Option<SampleRange>,
 ) -> TranscodeTaskParams {
     let manager = path_manager();
     let work_dir = manager.transcode_work_dir(&meta.hash);
@@ -103,30 +420,105 @@ fn to_task_params(
         is_hdr: video.hdr_format.is_some(),
         width: video.width,
         height: video.height,
-        format: param.video.format,
-        resolution: param.video.resolution,
-        ray_tracing: param.video.ray_tracing,
-        quality: param.video.quality,
+        format: rendition.format,
+        resolution: rendition.resolution,
+        ray_tracing: rendition.ray_tracing,
+        quality: rendition.quality,
     };
     let dst_path = manager.transcode_dst_path(
         &meta.hash,
         param.container_format,
         &video_params,
         &param.audio,
+        sample,
     );
     let task_params = TranscodeTaskParams {
         work_dir,
         path: meta.archived_path.clone(),
         dst_path,
-        frame_count: video.frame_count,
+        frame_count: match sample {
+            Some(sample) => sample.frame_count(video.frame_count, video_duration_ms(video)),
+            None => video.frame_count,
+        },
         video: video_params,
-        audio: param.audio,
+        audio: param.audio.clone(),
         container: param.container_format,
         is_h264: video.is_h264,
+        subtitles,
+        sample,
     };
     task_params
 }
 
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationDto {
+    video: ZcodeProcessParams,
+    av1_beneficial: bool,
+    estimated_size: Option<u64>,
+    duration_ms: Option<u32>,
+    estimated_encode_secs: u64,
+}
+
+pub async fn recommend(
+    user_id: UserId,
+    file_id: UserFileId,
+) -> BizResult<RecommendationDto, RecommendErr> {
+    use RecommendErr::*;
+
+    let file = ensure_exist!(repo_user_file::find_video(file_id).await?, FileNotFound);
+    ensure_biz!(*file.user_id() == user_id && !*file.deleted(), FileNotFound);
+    let video = ensure_exist!(
+        file.file_data().and_then(|data| data.video_info.as_ref()),
+        NotAVideo
+    );
+
+    let recommendation = recommend::recommend(video);
+    biz_ok!(RecommendationDto {
+        video: recommendation.video,
+        av1_beneficial: recommendation.av1_beneficial,
+        estimated_size: recommendation.estimated_size,
+        duration_ms: recommendation.duration_ms,
+        estimated_encode_secs: recommendation.estimated_encode_secs,
+    })
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerCapabilityDto {
+    container: ContainerFormat,
+    video_formats: Vec<VideoFormat>,
+    audio_formats: Vec<AudioFormat>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesDto {
+    /// 每种封装格式支持的视频和音频编码
+    containers: Vec<ContainerCapabilityDto>,
+    /// 源视频为 HDR 时可以选择的视频编码
+    hdr_video_formats: Vec<VideoFormat>,
+    /// 源视频为 HDR 时可以选择的画质
+    hdr_qualities: Vec<OutputQuality>,
+}
+
+/// 可选的转码参数组合，与创建订单时的检查一致
+pub fn capabilities() -> CapabilitiesDto {
+    let containers = compat::CONTAINERS
+        .into_iter()
+        .map(|container| ContainerCapabilityDto {
+            container,
+            video_formats: compat::video_formats(container).to_vec(),
+            audio_formats: compat::audio_formats(container).to_vec(),
+        })
+        .collect();
+    CapabilitiesDto {
+        containers,
+        hdr_video_formats: compat::HDR_VIDEO_FORMATS.to_vec(),
+        hdr_qualities: compat::HDR_QUALITIES.to_vec(),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TaskResult<O> {
     pub task_id: TranscodeTaskId,
@@ -134,25 +526,74 @@ pub struct TaskResult<O> {
     pub result: Result<O, String>,
 }
 
-pub async fn task_done(result: TaskResult<()>) -> Result<()> {
-    tx_func!(task_done_tx, result)
+/// 转码结果，成功时可能带有质量指标
+pub type TranscodeResult = TaskResult<Option<QualityMetrics>>;
+
+pub async fn task_done(result: TranscodeResult) -> Result<()> {
+    let Some((done, finished)) = tx_func!(task_done_tx, result)? else {
+        return Ok(());
+    };
+    // 事务提交后再推送通知和发布事件
+    notification::wake(done.user_id);
+    event_bus::publish(done);
+    if let Some(finished) = finished {
+        event_bus::publish(finished);
+    }
+    Ok(())
 }
 
-pub async fn task_done_tx(result: TaskResult<()>, conn: &mut PgConn) -> Result<()> {
+type TaskDoneEvents = (TranscodeTaskDone, Option<TranscodeOrderFinished>);
+
+/// 处理转码结果，返回需要在事务提交后发布的事件，订单因此结束时带有订单结束事件
+pub async fn task_done_tx(
+    result: TranscodeResult,
+    conn: &mut PgConn,
+) -> Result<Option<TaskDoneEvents>> {
     debug!(?result, "transcode task done");
 
     let task_id = result.task_id;
     let Some(mut order) = repo_order::find(result.task_id, conn).await? else {
         warn!(%task_id, "order not found");
-        return Ok(());
+        return Ok(None);
     };
     let user_id = *order.user_id();
+    let order_id = *order.id();
+
+    let task = order.tasks().iter().find(|task| task.id() == &task_id);
+    let Some(task) = task.filter(|task| task.status().is_processing()) else {
+        info!(%task_id, "task already ended, ignore the result");
+        return Ok(None);
+    };
+    let credits = *task.credits();
+    let source_file_id = *task.user_file_id();
 
     if let Err(err) = &result.result {
         info!(%err, "task failed");
-        order.task_completed(task_id, result.result);
+        let file_name = VirtualPath::build(user_id, task.virtual_path())
+            .map(|path| path.file_name().to_string())
+            .unwrap_or_default();
+        order.task_completed(task_id, result.result.map(|_| ()));
         let _ = repo_order::update(&order, conn).await?;
-        return Ok(());
+        repo_file_lock::release(&[task_id], conn).await?;
+        let event = NotificationEvent::TranscodeFailed {
+            task_id,
+            file_id: source_file_id,
+            file_name,
+        };
+        notification::notify(user_id, event, conn).await?;
+        emit_task_events(&order, task_id, false, conn).await?;
+        if credits > 0 {
+            repo_credit::add(
+                user_id,
+                credits,
+                CreditKind::Refund,
+                Some(order_id),
+                Some(task_id),
+                conn,
+            )
+            .await?;
+        }
+        return Ok(Some(task_done_events(order, task_id, false)));
     }
 
     let task = order
@@ -162,12 +603,18 @@ pub async fn task_done_tx(result: TaskResult<()>, conn: &mut PgConn) -> Result<(
         .expect("task not found");
 
     let params = task.params();
+    let source_sys_file_id = *task.sys_file_id();
     let hash = repo_user_file::get_hash(*task.user_file_id())
         .await?
         .ok_or_else(|| anyhow!("file not found"))?;
 
-    let transcode_out_path =
-        path_manager().transcode_dst_path(&hash, params.container, &params.video, &params.audio);
+    let transcode_out_path = path_manager().transcode_dst_path(
+        &hash,
+        params.container,
+        &params.video,
+        &params.audio,
+        params.sample,
+    );
     let virtual_path = VirtualPath::build(user_id, task.virtual_path())
         .map_err(|_| anyhow!("invalid virtual path"))?;
     debug!("create transcoded file");
@@ -175,17 +622,131 @@ pub async fn task_done_tx(result: TaskResult<()>, conn: &mut PgConn) -> Result<(
     let out_name = transcode_out_path.file_name().unwrap().to_string_lossy();
     let new_name = format!("{}_{}", mirror_path.file_stem(), out_name);
     mirror_path.set_file_name(new_name).unwrap();
-    file_system::service::create_user_file(transcode_out_path, mirror_path, conn)
+    let file_name = mirror_path.file_name().to_string();
+    let output_hash = file_system::service::create_user_file(transcode_out_path, mirror_path, conn)
         .await
         .context("create user file")?;
 
-    order.task_completed(task_id, result.result);
+    let metrics = result.result.as_ref().ok().copied().flatten();
+    order.task_completed(task_id, result.result.map(|_| ()));
 
     let _ = repo_order::update(&order, conn).await?;
+    if let Some(metrics) = metrics {
+        repo_order::save_metrics(task_id, &metrics.sanitized(), conn).await?;
+    }
+    repo_order::save_output_hash(task_id, &output_hash, conn).await?;
+    repo_user_file::set_transcode_from(&output_hash, source_sys_file_id, conn).await?;
+    repo_file_lock::release(&[task_id], conn).await?;
+    let event = NotificationEvent::TranscodeSucceeded {
+        task_id,
+        file_id: source_file_id,
+        file_name,
+    };
+    notification::notify(user_id, event, conn).await?;
+    emit_task_events(&order, task_id, true, conn).await?;
 
+    Ok(Some(task_done_events(order, task_id, true)))
+}
+
+fn task_done_events(order: TranscocdeOrder, task_id: TranscodeTaskId, ok: bool) -> TaskDoneEvents {
+    let done = TranscodeTaskDone {
+        user_id: *order.user_id(),
+        order_id: *order.id(),
+        task_id,
+        ok,
+    };
+    let finished = order
+        .is_finished()
+        .then_some(TranscodeOrderFinished { order });
+    (done, finished)
+}
+
+async fn emit_task_events(
+    order: &TranscocdeOrder,
+    task_id: TranscodeTaskId,
+    ok: bool,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let order_id = *order.id();
+    let user_id = *order.user_id();
+    let event = WebhookEvent::TaskCompleted(TaskCompleted {
+        order_id,
+        task_id,
+        ok,
+    });
+    webhook::emit(user_id, &event, conn).await?;
+
+    if order.is_finished() {
+        let (ok_count, failed_count) = order.task_counts();
+        let event = WebhookEvent::OrderFinished(OrderFinished {
+            order_id,
+            ok_count,
+            failed_count,
+        });
+        webhook::emit(user_id, &event, conn).await?;
+    }
     Ok(())
 }
 
+/// 取消订单中未完成的任务并退还积分，已经发送给 av1-factory 的任务通过 outbox 发送取消请求
+pub async fn cancel_order(
+    user_id: UserId,
+    order_id: TranscodeOrderId,
+) -> BizResult<(), CancelOrderErr> {
+    let resp = pg_tx!(cancel_order_tx, user_id, order_id);
+    if matches!(resp, Ok(Ok(_))) {
+        outbox::wake();
+    }
+    resp
+}
+
+async fn cancel_order_tx(
+    user_id: UserId,
+    order_id: TranscodeOrderId,
+    conn: &mut PgConn,
+) -> BizResult<(), CancelOrderErr> {
+    use CancelOrderErr::*;
+
+    let order = repo_order::find_order(order_id, conn).await?;
+    let mut order = ensure_exist!(order, OrderNotFound);
+    ensure_biz!(*order.user_id() == user_id, OrderNotFound);
+    ensure_biz!(order.is_processing() || order.is_scheduled(), AlreadyEnded);
+
+    // 预约中的任务还没有写入 outbox，不需要通知 av1-factory
+    let processing: Vec<_> = order
+        .tasks()
+        .iter()
+        .filter(|t| t.status().is_processing())
+        .map(|t| *t.id())
+        .collect();
+    let refund = order.cancel();
+    repo_order::update(&order, conn).await?;
+    // 结果会被丢弃，不再需要锁定源文件
+    let task_ids: Vec<_> = order.tasks().iter().map(|t| *t.id()).collect();
+    repo_file_lock::release(&task_ids, conn).await?;
+    // 还未发送的请求不再发送，已经发送的请求通过 outbox 通知 av1-factory 取消
+    let raw_ids: Vec<_> = processing.iter().map(|id| id.0).collect();
+    let unsent = repo_outbox::cancel(&raw_ids, conn).await?;
+    let sent = order
+        .tasks()
+        .iter()
+        .filter(|t| processing.contains(t.id()) && !unsent.contains(&t.id().0));
+    outbox::enqueue_cancel(sent, conn).await?;
+    if refund > 0 {
+        repo_credit::add(
+            user_id,
+            refund,
+            CreditKind::Refund,
+            Some(order_id),
+            None,
+            conn,
+        )
+        .await?;
+    }
+
+    biz_ok!(())
+}
+
 #[cfg(test)]
 mod test {
     use crate::domain::transcode_order::params::audio::{
@@ -211,9 +772,46 @@ mod test {
                 track: AudioTrack::_51,
             }),
             include_audio: true,
+            subtitles: vec![SubtitleParamsDto {
+                file_id: 12840.into(),
+                mode: SubtitleMode::Mux,
+            }],
+            renditions: vec![
+                RenditionDto {
+                    resolution: Some(Resolution::_720P),
+                    quality: None,
+                },
+                RenditionDto {
+                    resolution: Some(Resolution::_480P),
+                    quality: Some(OutputQuality::Base),
+                },
+            ],
         };
 
         let b = serde_json::to_string_pretty(&a).unwrap();
         println!("{}", b);
+
+        let renditions = a.video_renditions().unwrap();
+        assert_eq!(renditions.len(), 2);
+        assert_eq!(renditions[0].quality, OutputQuality::High);
+        assert_eq!(renditions[1].resolution, Some(Resolution::_480P));
+    }
+
+    #[test]
+    fn duplicate_rendition() {
+        let mut a: TranscodeParamsDto = serde_json::from_str(
+            r#"{
+                "fileId": "1",
+                "includeAudio": false,
+                "containerFormat": "mp4",
+                "video": { "format": "av1", "resolution": null, "rayTracing": null, "quality": "high" },
+                "renditions": [{ "resolution": "_720p" }, { "resolution": "_720p" }]
+            }"#,
+        )
+        .unwrap();
+        assert!(a.video_renditions().is_none());
+
+        a.renditions.clear();
+        assert_eq!(a.video_renditions().unwrap(), vec![a.video]);
     }
 }
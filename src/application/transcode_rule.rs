@@ -0,0 +1,248 @@
+//! 自动转码规则
+//!
+//! 上传完成时检查文件是否在规则监听的目录中，匹配的文件先记录下来，
+//! 等视频信息解析完成后按规则的参数创建订单。订单与手动创建的一样扣除积分，
+//! 转码结果按镜像路径放在 /已转码视频 下
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    biz_ok,
+    domain::{
+        file_system::file::{FileNode, SysFileId, UserFileId},
+        transcode_order::{
+            params::{audio::AudioProcessParameters, ContainerFormat},
+            rule::{RuleErr, TranscodeRule, TranscodeRuleId, MAX_RULES_PER_USER},
+        },
+        user::user::UserId,
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::repo_transcode_rule::{self, RuleMatchPo},
+};
+
+use super::transcode::{self, RenditionDto, TranscodeParamsDto, ZcodeProcessParamsDto};
+
+/// 规则使用的转码参数，与创建订单时的参数相同，但不指定文件和字幕
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleParamsDto {
+    pub include_audio: bool,
+    pub container_format: ContainerFormat,
+    pub video: ZcodeProcessParamsDto,
+    #[serde(default)]
+    pub audio: Option<AudioProcessParameters>,
+    #[serde(default)]
+    #[validate(length(max = 8))]
+    pub renditions: Vec<RenditionDto>,
+}
+
+impl RuleParamsDto {
+    fn for_file(self, file_id: UserFileId) -> TranscodeParamsDto {
+        TranscodeParamsDto {
+            file_id,
+            include_audio: self.include_audio,
+            container_format: self.container_format,
+            video: self.video,
+            audio: self.audio,
+            subtitles: vec![],
+            renditions: self.renditions,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscodeRuleDto {
+    id: TranscodeRuleId,
+    name: String,
+    watch_dir: String,
+    recursive: bool,
+    enabled: bool,
+    params: RuleParamsDto,
+}
+
+impl TranscodeRuleDto {
+    fn from_rule(rule: &TranscodeRule) -> Result<Self> {
+        Ok(Self {
+            id: *rule.id(),
+            name: rule.name().clone(),
+            watch_dir: rule.watch_dir().to_str().into_owned(),
+            recursive: *rule.recursive(),
+            enabled: *rule.enabled(),
+            params: serde_json::from_str(rule.params())?,
+        })
+    }
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRuleDto {
+    name: String,
+    /// 监听的目录，必须在 /源视频 下，如 /源视频/incoming
+    watch_dir: String,
+    /// 是否包括子目录中的文件
+    #[serde(default)]
+    recursive: bool,
+    #[validate]
+    params: RuleParamsDto,
+}
+
+#[derive(derive_more::From)]
+pub enum CreateRuleErr {
+    Rule(RuleErr),
+    TooMany,
+}
+
+pub async fn list(user_id: UserId) -> Result<Vec<TranscodeRuleDto>> {
+    let conn = &mut pg_conn().await?;
+    let rules = repo_transcode_rule::find_by_user(user_id, conn).await?;
+    rules.iter().map(TranscodeRuleDto::from_rule).collect()
+}
+
+pub async fn create(
+    user_id: UserId,
+    params: CreateRuleDto,
+) -> BizResult<TranscodeRuleDto, CreateRuleErr> {
+    let rule = ensure_biz!(TranscodeRule::create(
+        user_id,
+        params.name,
+        &params.watch_dir,
+        params.recursive,
+        serde_json::to_string(&params.params)?,
+    ));
+
+    let conn = &mut pg_conn().await?;
+    let count = repo_transcode_rule::count(user_id, conn).await?;
+    ensure_biz!(count < MAX_RULES_PER_USER, CreateRuleErr::TooMany);
+    repo_transcode_rule::save(&rule, conn).await?;
+
+    biz_ok!(TranscodeRuleDto::from_rule(&rule)?)
+}
+
+/// 只修改指定的字段
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateRuleDto {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    watch_dir: Option<String>,
+    #[serde(default)]
+    recursive: Option<bool>,
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    #[validate]
+    params: Option<RuleParamsDto>,
+}
+
+#[derive(derive_more::From)]
+pub enum UpdateRuleErr {
+    Rule(RuleErr),
+    NotFound,
+}
+
+pub async fn update(
+    user_id: UserId,
+    id: TranscodeRuleId,
+    params: UpdateRuleDto,
+) -> BizResult<TranscodeRuleDto, UpdateRuleErr> {
+    let conn = &mut pg_conn().await?;
+    let rule = repo_transcode_rule::find(id, conn).await?;
+    let mut rule = ensure_exist!(
+        rule.filter(|rule| *rule.user_id() == user_id),
+        UpdateRuleErr::NotFound
+    );
+
+    if let Some(name) = params.name {
+        ensure_biz!(rule.rename(name));
+    }
+    if let Some(watch_dir) = params.watch_dir {
+        ensure_biz!(rule.watch(&watch_dir));
+    }
+    if let Some(recursive) = params.recursive {
+        rule.set_recursive(recursive);
+    }
+    if let Some(enabled) = params.enabled {
+        rule.set_enabled(enabled);
+    }
+    if let Some(rule_params) = params.params {
+        rule.set_params(serde_json::to_string(&rule_params)?);
+    }
+    repo_transcode_rule::update(&rule, conn).await?;
+
+    biz_ok!(TranscodeRuleDto::from_rule(&rule)?)
+}
+
+pub enum DeleteRuleErr {
+    NotFound,
+}
+
+/// 删除规则，已经匹配但还没有创建订单的文件不再转码
+pub async fn delete(user_id: UserId, id: TranscodeRuleId) -> BizResult<(), DeleteRuleErr> {
+    let conn = &mut pg_conn().await?;
+    let effected = repo_transcode_rule::delete(user_id, id, conn).await?;
+    ensure_biz!(effected.is_effected(), DeleteRuleErr::NotFound);
+    biz_ok!(())
+}
+
+/// 上传完成时记录匹配规则的文件，与上传在同一个事务中调用
+pub(crate) async fn on_uploaded(file: &FileNode, conn: &mut PgConn) -> Result<()> {
+    let Some(data) = file.file_data() else {
+        return Ok(());
+    };
+    let rules = repo_transcode_rule::find_by_user(*file.user_id(), conn).await?;
+    let matches: Vec<_> = rules
+        .iter()
+        .filter(|rule| rule.matches(file.path()))
+        .map(|rule| RuleMatchPo {
+            rule_id: *rule.id(),
+            user_file_id: *file.id(),
+            sys_file_id: data.id,
+        })
+        .collect();
+    repo_transcode_rule::save_matches(&matches, conn).await
+}
+
+/// 视频信息解析完成后，为等待中的文件创建订单
+pub(crate) async fn on_file_parsed(sys_file_id: SysFileId) -> Result<()> {
+    let matches = {
+        let conn = &mut pg_conn().await?;
+        repo_transcode_rule::take_matches(sys_file_id, conn).await?
+    };
+
+    for matched in matches {
+        let rule = {
+            let conn = &mut pg_conn().await?;
+            repo_transcode_rule::find(matched.rule_id, conn).await?
+        };
+        // 规则在等待期间被删除或停用
+        let Some(rule) = rule.filter(|rule| *rule.enabled()) else {
+            continue;
+        };
+        let params: RuleParamsDto = serde_json::from_str(rule.params())?;
+        let params = vec![params.for_file(matched.user_file_id)];
+        let user_id = *rule.user_id();
+        match transcode::create_order(user_id, params, None, None).await? {
+            Ok(_) => info!(
+                rule_id = %rule.id(),
+                file_id = %matched.user_file_id,
+                "order created by transcode rule"
+            ),
+            // 不是视频、积分不足等情况下不创建订单
+            Err(err) => warn!(
+                rule_id = %rule.id(),
+                file_id = %matched.user_file_id,
+                ?err,
+                "transcode rule skipped"
+            ),
+        }
+    }
+    Ok(())
+}
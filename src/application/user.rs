@@ -1,11 +1,16 @@
 use serde::Deserialize;
 use tracing::info;
 use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
 
+use crate::domain::event::UserRegistered;
 use crate::domain::user::SanityCheck;
 use crate::{
+    application::event_bus,
     biz_ok,
     domain::user::{
+        password::PasswordPolicy,
+        referral::ReferralCode,
         service::{self, login_tx, LoginErr, RegisterErr, ResetPasswordErr, UpdateProfileErr},
         user::{User, UserId},
         Email, Password, Phone, PhoneFormatErr, UserName,
@@ -13,12 +18,21 @@ use crate::{
     ensure_biz, ensure_exist,
     http::BizResult,
     infrastructure::{email::EmailCodeSender, repo_user, sms_code::SmsSender},
-    pg_tx, tx_func,
+    pg_tx,
+    settings::get_settings,
+    tx_func,
 };
 use anyhow::{bail, Result};
 use derive_more::From;
 
+pub mod avatar;
 pub mod employee;
+pub mod export;
+pub mod oauth;
+pub mod password;
+pub mod referral;
+pub mod sms_login;
+pub mod tier;
 
 pub async fn is_email_registerd(email: String) -> Result<bool> {
     let Ok(email) = Email::try_from(email) else {
@@ -29,30 +43,69 @@ pub async fn is_email_registerd(email: String) -> Result<bool> {
     Ok(exist)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserDto {
     email: String,
     email_code: String,
     password: String,
+    /// 邀请码，可以不填
+    #[serde(default)]
+    referral_code: Option<String>,
+}
+
+impl UserDto {
+    pub fn email(&self) -> &str {
+        &self.email
+    }
 }
 
 pub async fn register(user_dto: UserDto) -> BizResult<UserId, RegisterErr> {
+    let referral_code = match user_dto.referral_code.filter(|c| !c.trim().is_empty()) {
+        Some(code) => Some(ensure_exist!(
+            ReferralCode::parse(&code),
+            RegisterErr::InvalidReferralCode
+        )),
+        None => None,
+    };
     let email = ensure_biz!(Email::try_from(user_dto.email));
-    let password = ensure_biz!(Password::try_from_async(user_dto.password).await);
+    let password = ensure_biz!(password::parse(user_dto.password).await?);
     ensure_biz!(
         EmailCodeSender::verify_email_code(&email, &user_dto.email_code).await?,
         SanityCheck::EmailCodeNotMatch
     );
 
     let user = User::create(email, password);
-    service::register(user).await
+    let email = user.email().to_string();
+    let user_id = ensure_biz!(pg_tx!(register_tx, user, referral_code)?);
+    event_bus::publish(UserRegistered { user_id, email });
+    biz_ok!(user_id)
+}
+
+async fn register_tx(
+    user: User,
+    referral_code: Option<ReferralCode>,
+    conn: &mut PgConn,
+) -> BizResult<UserId, RegisterErr> {
+    // 先检查邀请码，避免邀请码无效时用户已经写入
+    let inviter = match &referral_code {
+        Some(code) => Some(ensure_exist!(
+            referral::find_inviter(code, conn).await?,
+            RegisterErr::InvalidReferralCode
+        )),
+        None => None,
+    };
+    let user_id = ensure_biz!(service::register_tx(user, conn).await?);
+    if let (Some(inviter), Some(code)) = (inviter, &referral_code) {
+        referral::reward_tx(inviter, user_id, code, conn).await?;
+    }
+    biz_ok!(user_id)
 }
 
 pub async fn register_test_user() -> Result<()> {
     for i in 1..=12 {
         let email = Email::try_from(format!("aa{}@cc.com", i)).unwrap();
-        let password = Password::try_from_async("aabbccdd".to_string())
+        let password = Password::try_from_async("aabbccdd".to_string(), &PasswordPolicy::default())
             .await
             .unwrap();
         info!(
@@ -65,7 +118,7 @@ pub async fn register_test_user() -> Result<()> {
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginDto {
     email: String,
@@ -93,7 +146,7 @@ pub async fn logout_tx(user_id: UserId, conn: &mut PgConn) -> anyhow::Result<()>
     Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResetPasswordDto {
     email: String,
@@ -103,29 +156,33 @@ pub struct ResetPasswordDto {
 
 pub async fn reset_password(params: ResetPasswordDto) -> BizResult<(), ResetPasswordErr> {
     let email = ensure_biz!(Email::try_from(params.email));
-    let new_password = ensure_biz!(Password::try_from_async(params.new_password).await);
+    let new_password = ensure_biz!(password::parse(params.new_password).await?);
     service::reset_password(email, new_password, params.email_code).await
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserUpdateDto {
     pub user_name: Option<String>,
     pub password: Option<UpdatePassword>,
     pub address: Option<Vec<String>>,
     pub mobile_number: Option<MobileNumber>,
+    /// 是否退订转码订单完成邮件
+    pub order_email_opt_out: Option<bool>,
+    /// 每个文件保留的历史版本数，最多 100
+    pub max_file_versions: Option<u32>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdatePassword {
     // 管理员可以不传这个参数
     #[serde(default)]
-    old_password: String,
-    new_password: String,
+    pub old_password: String,
+    pub new_password: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MobileNumber {
     // 管理员可以不传这个参数
@@ -139,7 +196,7 @@ pub async fn update_profile(
     update_info: UserUpdateDto,
 ) -> BizResult<(), UpdateProfileErr> {
     let phone = if let Some(phone_params) = update_info.mobile_number {
-        let phone = ensure_biz!(Phone::try_from(phone_params.tel));
+        let phone = ensure_biz!(Phone::try_from(phone_params.tel, &get_settings().phone));
         ensure_biz!(
             SmsSender::verify(&phone, phone_params.sms_code).await?,
             SanityCheck::SmsCodeNotMatch
@@ -157,7 +214,7 @@ pub async fn update_profile(
     let password = if let Some(password) = update_info.password {
         let p = service::UpdatePassword {
             old_password: password.old_password,
-            new_password: ensure_biz!(Password::try_from_async(password.new_password).await),
+            new_password: ensure_biz!(password::parse(password.new_password).await?),
         };
         Some(p)
     } else {
@@ -169,6 +226,8 @@ pub async fn update_profile(
         password,
         address: update_info.address.map(|a| a.join(",")),
         mobile_number: phone,
+        order_email_opt_out: update_info.order_email_opt_out,
+        max_file_versions: update_info.max_file_versions,
     };
 
     pg_tx!(service::update_profile, user_id, update_info)
@@ -179,7 +238,7 @@ pub async fn update_profile_uncheck(
     update_info: UserUpdateDto,
 ) -> BizResult<(), UpdateProfileErr> {
     let phone = if let Some(phone_params) = update_info.mobile_number {
-        let phone = ensure_biz!(Phone::try_from(phone_params.tel));
+        let phone = ensure_biz!(Phone::try_from(phone_params.tel, &get_settings().phone));
         Some(phone)
     } else {
         None
@@ -193,7 +252,7 @@ pub async fn update_profile_uncheck(
     let password = if let Some(password) = update_info.password {
         let p = service::UpdatePassword {
             old_password: password.old_password,
-            new_password: ensure_biz!(Password::try_from_async(password.new_password).await),
+            new_password: ensure_biz!(password::parse(password.new_password).await?),
         };
         Some(p)
     } else {
@@ -205,6 +264,8 @@ pub async fn update_profile_uncheck(
         password,
         address: update_info.address.map(|a| a.join(",")),
         mobile_number: phone,
+        order_email_opt_out: update_info.order_email_opt_out,
+        max_file_versions: update_info.max_file_versions,
     };
 
     pg_tx!(service::update_profile_uncheck, user_id, update_info)
@@ -217,7 +278,7 @@ pub enum SendSmsCodeErr {
 }
 
 pub async fn send_sms_code(tel: String, fake: bool) -> BizResult<(), SendSmsCodeErr> {
-    let tel = ensure_biz!(Phone::try_from(tel));
+    let tel = ensure_biz!(Phone::try_from(tel, &get_settings().phone));
     let sender = ensure_exist!(
         SmsSender::try_build(&tel, fake).await?,
         SendSmsCodeErr::TooFrequent
@@ -0,0 +1,89 @@
+//! 用户头像
+//!
+//! 头像与普通文件一样按内容 hash 保存在归档目录下，但不创建系统文件记录，
+//! 回收和孤儿扫描会跳过被用作头像的 hash。缩略图由转码工厂生成，生成之前返回原图的地址
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use futures_util::Stream;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    application::{
+        file_system::{file_type, upload::archive_merged},
+        outbox,
+    },
+    biz_ok,
+    domain::{
+        file_system::service::path_manager,
+        user::{
+            avatar::{self, AvatarErr},
+            user::UserId,
+        },
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{av1_factory, file_sys, repo_user},
+};
+
+#[derive(derive_more::From)]
+pub enum UploadAvatarErr {
+    TooLarge,
+    Avatar(AvatarErr),
+}
+
+/// 上传并设置头像，返回头像的地址
+pub async fn upload<S, B>(user_id: UserId, data: S) -> BizResult<String, UploadAvatarErr>
+where
+    S: Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    let stored = ensure_exist!(
+        file_sys::store_small_file(data, avatar::MAX_SIZE).await?,
+        UploadAvatarErr::TooLarge
+    );
+    let mime = file_type::sniff_file(stored.tmp_file.path()).await?;
+    let head = file_sys::read_head(stored.tmp_file.path(), avatar::HEAD_LEN).await?;
+    ensure_biz!(avatar::check(mime, &head));
+
+    let file = archive_merged(stored).await?;
+    let thumbnail_dir = path_manager().thumbnail_dir(&file.hash);
+    if file_sys::child_file_names(&thumbnail_dir).await?.is_empty() {
+        let task = av1_factory::thumbnail_task(file.id, &file.archived_path, &thumbnail_dir)?;
+        outbox::send_or_enqueue(task)
+            .await
+            .context("send thumbnail req")?;
+    }
+
+    let conn = &mut pg_conn().await?;
+    repo_user::set_avatar(user_id, Some(&file.hash), conn).await?;
+    biz_ok!(avatar_url(&file.hash).await?)
+}
+
+/// 恢复默认头像，头像文件由回收任务清理
+pub async fn remove(user_id: UserId) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    repo_user::set_avatar(user_id, None, conn).await?;
+    Ok(())
+}
+
+/// 有缩略图时返回缩略图的地址，否则返回原图的地址
+pub(crate) async fn avatar_url(hash: &str) -> Result<String> {
+    let mut names = file_sys::child_file_names(&path_manager().thumbnail_dir(hash)).await?;
+    names.sort();
+    let url = match names.first() {
+        Some(name) => format!("/api/fs/thumbnail/{}/{}", hash, name),
+        None => format!("/api/user/avatar/{}", hash),
+    };
+    Ok(url)
+}
+
+/// 头像原图的路径，hash 没有被用作头像时返回 None
+pub async fn original(hash: &str) -> Result<Option<PathBuf>> {
+    let conn = &mut pg_conn().await?;
+    if !repo_user::is_avatar(hash, conn).await? {
+        return Ok(None);
+    }
+    Ok(Some(path_manager().archived_path(hash)))
+}
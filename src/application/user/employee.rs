@@ -1,10 +1,12 @@
 use serde::Deserialize;
 use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
 
 use crate::{
     biz_ok,
     domain::user::{
         employee::{Employee, EmployeeId, InviteCode, Role},
+        password::PasswordPolicy,
         Email, EmailFormatErr, Password, PasswordFormatErr, SanityCheck,
     },
     ensure_biz, ensure_exist,
@@ -15,6 +17,8 @@ use crate::{
 use anyhow::Result;
 use derive_more::*;
 
+use super::password;
+
 pub async fn generate_invite_code(invitor: EmployeeId) -> Result<String> {
     let invite_code = InviteCode::generate();
     repo_employee::save_invite_code(invitor, &invite_code).await?;
@@ -29,7 +33,7 @@ pub enum RegisterErr {
     NoInvitor,
     AlreadyRegistered,
 }
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Clone, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct EmployeeRegisterDto {
     pub email: String,
@@ -44,7 +48,7 @@ pub async fn register(user_dto: EmployeeRegisterDto) -> BizResult<(EmployeeId, R
         EmailCodeSender::verify_email_code(&email, &user_dto.email_code).await?,
         SanityCheck::EmailCodeNotMatch
     );
-    let password = ensure_biz!(Password::try_from_async(user_dto.password).await);
+    let password = ensure_biz!(password::parse(user_dto.password).await?);
 
     pg_tx!(register_tx, email, password, user_dto.invitation_code)
 }
@@ -79,7 +83,7 @@ pub async fn register_root() -> anyhow::Result<()> {
     let conn = &mut pg_conn().await?;
 
     let email = Email::try_from("root@cc.com".to_string()).unwrap();
-    let password = Password::try_from_async("12341234".to_string())
+    let password = Password::try_from_async("12341234".to_string(), &PasswordPolicy::default())
         .await
         .unwrap();
     let mut root = Employee::create(email, password, EmployeeId(0));
@@ -88,7 +92,7 @@ pub async fn register_root() -> anyhow::Result<()> {
     let root_id = *repo_employee::find(root.email(), conn).await?.unwrap().id();
 
     let email = Email::try_from("manager@cc.com".to_string()).unwrap();
-    let password = Password::try_from_async("12341234".to_string())
+    let password = Password::try_from_async("12341234".to_string(), &PasswordPolicy::default())
         .await
         .unwrap();
     let mut manager = Employee::create(email, password, root_id);
@@ -97,7 +101,7 @@ pub async fn register_root() -> anyhow::Result<()> {
 
     for i in 1..=5 {
         let email = Email::try_from(format!("admin{}@cc.com", i)).unwrap();
-        let password = Password::try_from_async("aabbccdd".to_string())
+        let password = Password::try_from_async("aabbccdd".to_string(), &PasswordPolicy::default())
             .await
             .unwrap();
         let employee = Employee::create(email, password, root_id);
@@ -113,8 +117,9 @@ pub enum LoginErr {
     SanityCheck(SanityCheck),
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
+#[schema(as = EmployeeLoginDto)]
 pub struct LoginDto {
     pub email: String,
     pub password: String,
@@ -0,0 +1,271 @@
+//! 导出用户列表
+//!
+//! 符合条件的用户不超过 max_rows 时直接以 csv 流返回；更多的用户由后台任务写入存储根目录下的 exports，
+//! 完成后通过下载链接获取。导出的联系方式默认脱敏，与管理端的查询一致，每次导出都会记录审计日志
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use futures_util::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    application::audit::{self, AuditAction},
+    biz_ok,
+    cqrs::{
+        guard::UNMASK_ROLE,
+        user::{SearchBy, Sort, User},
+        MillionTimestamp,
+    },
+    domain::{
+        file_system::service::path_manager,
+        user::employee::{EmployeeId, Role},
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::repo_export::{self, ExportStatus, NewExportJobPo},
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct UserExportCfg {
+    /// 直接导出时的最大行数，超过时需要使用后台任务
+    #[serde(default = "default_max_rows")]
+    pub max_rows: i64,
+    /// 后台任务导出的最大行数
+    #[serde(default = "default_job_max_rows")]
+    pub job_max_rows: i64,
+    /// 每次从数据库读取的行数
+    #[serde(default = "default_batch_size")]
+    pub batch_size: i64,
+}
+
+impl Default for UserExportCfg {
+    fn default() -> Self {
+        Self {
+            max_rows: default_max_rows(),
+            job_max_rows: default_job_max_rows(),
+            batch_size: default_batch_size(),
+        }
+    }
+}
+
+fn default_max_rows() -> i64 {
+    5000
+}
+
+fn default_job_max_rows() -> i64 {
+    1_000_000
+}
+
+fn default_batch_size() -> i64 {
+    1000
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserExportParams {
+    /// 搜索条件，与 graphql 中 userList 的 searchBy 相同
+    #[schema(value_type = Option<Object>)]
+    pub search_by: Option<SearchBy>,
+    /// 排序条件，与 graphql 中 userList 的 sort 相同
+    #[schema(value_type = Object)]
+    pub sort: Sort,
+    /// 导出未脱敏的联系方式，需要经理及以上的角色。按邮箱或手机号过滤、排序同样需要
+    #[serde(default)]
+    pub unmask: bool,
+}
+
+pub enum ExportErr {
+    TooManyRows,
+    PermissionDenied,
+}
+
+/// 检查权限和行数，记录审计日志，返回要导出的行数
+async fn prepare(
+    employee_id: EmployeeId,
+    role: Role,
+    params: &UserExportParams,
+    max_rows: i64,
+) -> BizResult<i64, ExportErr> {
+    let by_contact =
+        params.search_by.as_ref().is_some_and(SearchBy::by_contact) || params.sort.by_contact();
+    ensure_biz!(
+        (!params.unmask && !by_contact) || role >= UNMASK_ROLE,
+        ExportErr::PermissionDenied
+    );
+    let rows = User::count(params.search_by.as_ref()).await?;
+    ensure_biz!(rows <= max_rows, ExportErr::TooManyRows);
+
+    let detail = serde_json::json!({ "rows": rows, "params": params });
+    audit::record(employee_id, AuditAction::ExportUsers, &detail).await?;
+    biz_ok!(rows)
+}
+
+/// 直接导出，返回 csv 内容的流
+pub async fn export_csv(
+    employee_id: EmployeeId,
+    role: Role,
+    params: UserExportParams,
+) -> BizResult<impl Stream<Item = Result<Vec<u8>>>, ExportErr> {
+    let cfg = &get_settings().user_export;
+    let rows = ensure_biz!(prepare(employee_id, role, &params, cfg.max_rows).await?);
+    biz_ok!(csv_chunks(Arc::new(params), rows, cfg.batch_size))
+}
+
+/// 分批读取用户并编码为 csv，最多读取 rows 行。第一批包含 BOM 和表头，方便用 Excel 打开
+fn csv_chunks(
+    params: Arc<UserExportParams>,
+    rows: i64,
+    batch_size: i64,
+) -> impl Stream<Item = Result<Vec<u8>>> {
+    futures_util::stream::try_unfold(Some(0), move |offset| {
+        let params = params.clone();
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let limit = batch_size.min(rows - offset);
+            let users =
+                User::search(params.search_by.as_ref(), &params.sort, offset, limit).await?;
+            let next = offset + users.len() as i64;
+            let has_more = users.len() as i64 == limit && next < rows;
+
+            let mut writer = csv::Writer::from_writer(vec![]);
+            if offset == 0 {
+                writer.get_mut().extend_from_slice("\u{feff}".as_bytes());
+                writer.write_record(User::CSV_HEADERS)?;
+            }
+            for mut user in users {
+                if !params.unmask {
+                    user.mask_contact();
+                }
+                writer.write_record(user.csv_record())?;
+            }
+            let chunk = writer.into_inner().map_err(|err| err.into_error())?;
+
+            Ok(Some((chunk, has_more.then_some(next))))
+        }
+    })
+}
+
+/// 创建后台导出任务，返回任务 id
+pub async fn start_job(
+    employee_id: EmployeeId,
+    role: Role,
+    params: UserExportParams,
+) -> BizResult<i64, ExportErr> {
+    let cfg = &get_settings().user_export;
+    let rows = ensure_biz!(prepare(employee_id, role, &params, cfg.job_max_rows).await?);
+
+    let job = NewExportJobPo {
+        employee_id,
+        params: serde_json::to_string(&params)?,
+    };
+    let id = {
+        let conn = &mut pg_conn().await?;
+        repo_export::save(&job, conn).await?
+    };
+
+    info!(id, %employee_id, rows, "user export job started");
+    tokio::spawn(async move {
+        let result = run_job(id, params, rows, cfg.batch_size).await;
+        let (status, err) = match &result {
+            Ok(()) => (ExportStatus::Done, None),
+            Err(err) => {
+                warn!(id, ?err, "user export job failed");
+                (ExportStatus::Failed, Some(format!("{:#}", err)))
+            }
+        };
+        let finish = async {
+            let conn = &mut pg_conn().await?;
+            repo_export::finish(id, status, rows, err.as_deref(), conn).await
+        };
+        if let Err(err) = finish.await {
+            warn!(id, ?err, "update user export job failed");
+        }
+    });
+
+    biz_ok!(id)
+}
+
+async fn run_job(id: i64, params: UserExportParams, rows: i64, batch_size: i64) -> Result<()> {
+    let path = job_file_path(id);
+    let mut file = tokio::fs::File::create(&path).await?;
+    let mut chunks = std::pin::pin!(csv_chunks(Arc::new(params), rows, batch_size));
+    while let Some(chunk) = chunks.try_next().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+fn job_file_name(id: i64) -> String {
+    format!("users-{}.csv", id)
+}
+
+fn job_file_path(id: i64) -> PathBuf {
+    path_manager().export_path(&job_file_name(id))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJobDto {
+    id: i64,
+    /// running、done 或 failed
+    status: &'static str,
+    /// 导出的行数
+    row_count: i64,
+    error: Option<String>,
+    /// 完成后的下载链接
+    download_url: Option<String>,
+    create_at: MillionTimestamp,
+    updated_at: MillionTimestamp,
+}
+
+pub enum ExportJobErr {
+    NotFound,
+    NotReady,
+}
+
+/// 查询导出任务，只能查询自己创建的任务
+pub async fn job(employee_id: EmployeeId, id: i64) -> BizResult<ExportJobDto, ExportJobErr> {
+    let conn = &mut pg_conn().await?;
+    let job = ensure_exist!(repo_export::find(id, conn).await?, ExportJobErr::NotFound);
+    ensure_biz!(job.employee_id == employee_id, ExportJobErr::NotFound);
+
+    let status = ExportStatus::from_i16(job.status);
+    biz_ok!(ExportJobDto {
+        id: job.id,
+        status: match status {
+            Some(ExportStatus::Running) => "running",
+            Some(ExportStatus::Done) => "done",
+            Some(ExportStatus::Failed) | None => "failed",
+        },
+        row_count: job.row_count,
+        error: job.error,
+        download_url: (status == Some(ExportStatus::Done))
+            .then(|| format!("/admin/user/export_jobs/{}/download", job.id)),
+        create_at: job.create_at.into(),
+        updated_at: job.updated_at.into(),
+    })
+}
+
+/// 已完成的导出任务的文件路径和下载时使用的文件名
+pub async fn job_file(
+    employee_id: EmployeeId,
+    id: i64,
+) -> BizResult<(PathBuf, String), ExportJobErr> {
+    let conn = &mut pg_conn().await?;
+    let job = ensure_exist!(repo_export::find(id, conn).await?, ExportJobErr::NotFound);
+    ensure_biz!(job.employee_id == employee_id, ExportJobErr::NotFound);
+    ensure_biz!(
+        job.status == ExportStatus::Done as i16,
+        ExportJobErr::NotReady
+    );
+    biz_ok!((job_file_path(id), job_file_name(id)))
+}
@@ -0,0 +1,172 @@
+//! 第三方登录
+//!
+//! 登录时按以下顺序确定用户：
+//! 1. 第三方账号已关联的用户
+//! 2. 已登录时关联到当前用户
+//! 3. 第三方账号的已验证邮箱与已有账号相同时，关联到该账号
+//! 4. 开启自动注册时用该邮箱注册新账号
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use tracing::warn;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    application::event_bus,
+    biz_err, biz_ok,
+    domain::{
+        event::UserRegistered,
+        user::{
+            service,
+            user::{User, UserId},
+            Email, Password,
+        },
+    },
+    ensure_biz, ensure_exist,
+    http::BizResult,
+    infrastructure::{
+        oauth::{OAuthProviderCfg, ProviderIdentity},
+        repo_provider_identity::{self, ProviderIdentityPo},
+        repo_user,
+    },
+    pg_tx,
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct OAuthCfg {
+    /// 没有可关联的账号时，是否用第三方账号的已验证邮箱注册
+    #[serde(default)]
+    pub auto_register: bool,
+    /// 第三方账号的已验证邮箱与已有账号相同时是否自动关联
+    #[serde(default = "default_link_by_email")]
+    pub link_by_email: bool,
+    /// key 为接口路径中的 provider，如 github
+    #[serde(default)]
+    pub providers: HashMap<String, OAuthProviderCfg>,
+}
+
+impl Default for OAuthCfg {
+    fn default() -> Self {
+        Self {
+            auto_register: false,
+            link_by_email: default_link_by_email(),
+            providers: HashMap::new(),
+        }
+    }
+}
+
+fn default_link_by_email() -> bool {
+    true
+}
+
+pub enum OAuthErr {
+    UnknownProvider,
+    InvalidState,
+    /// 授权码无效或第三方服务请求失败
+    ProviderFailed,
+    /// 第三方账号已关联其他用户
+    AlreadyLinked,
+    NoAccount,
+}
+
+fn cfg() -> &'static OAuthCfg {
+    &get_settings().oauth
+}
+
+/// 防止 CSRF 的随机 state，由调用方保存并在回调时比对
+pub fn new_state() -> String {
+    hex::encode(thread_rng().gen::<[u8; 16]>())
+}
+
+/// 第三方授权页面的地址
+pub fn authorize_url(provider: &str, state: &str) -> BizResult<String, OAuthErr> {
+    let provider = ensure_exist!(cfg().providers.get(provider), OAuthErr::UnknownProvider);
+    biz_ok!(provider.authorize_url(state)?)
+}
+
+/// 用授权码登录，`current` 为当前登录的用户，有值时把第三方账号关联到该用户
+pub async fn callback(
+    provider: String,
+    code: &str,
+    current: Option<UserId>,
+) -> BizResult<UserId, OAuthErr> {
+    let provider_cfg = ensure_exist!(cfg().providers.get(&provider), OAuthErr::UnknownProvider);
+    let identity = match provider_cfg.fetch_identity(code).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            warn!(?err, %provider, "fetch oauth identity failed");
+            return biz_err!(OAuthErr::ProviderFailed);
+        }
+    };
+
+    let (user_id, registered) = ensure_biz!(pg_tx!(login_tx, provider, identity, current)?);
+    if let Some(email) = registered {
+        event_bus::publish(UserRegistered { user_id, email });
+    }
+    biz_ok!(user_id)
+}
+
+/// 返回用户 id，注册了新账号时同时返回邮箱
+async fn login_tx(
+    provider: String,
+    identity: ProviderIdentity,
+    current: Option<UserId>,
+    conn: &mut PgConn,
+) -> BizResult<(UserId, Option<String>), OAuthErr> {
+    let cfg = cfg();
+    let linked = repo_provider_identity::find_user(&provider, &identity.subject, conn).await?;
+    if let Some(user_id) = linked {
+        ensure_biz!(
+            current.map_or(true, |c| c == user_id),
+            OAuthErr::AlreadyLinked
+        );
+        return login_existing(user_id, conn).await;
+    }
+
+    let link = |user_id| ProviderIdentityPo {
+        provider: &provider,
+        subject: &identity.subject,
+        user_id,
+        email: identity.email.as_deref(),
+    };
+
+    if let Some(user_id) = current {
+        repo_provider_identity::save(&link(user_id), conn).await?;
+        return biz_ok!((user_id, None));
+    }
+
+    let email = identity.email.clone().and_then(|e| Email::try_from(e).ok());
+    let email = ensure_exist!(email, OAuthErr::NoAccount);
+    if cfg.link_by_email {
+        if let Some(user) = repo_user::find(&email, conn).await? {
+            repo_provider_identity::save(&link(*user.id()), conn).await?;
+            return login_existing(*user.id(), conn).await;
+        }
+    }
+
+    ensure_biz!(cfg.auto_register, OAuthErr::NoAccount);
+    let user = User::create(email, Password::random().await?);
+    let email = user.email().to_string();
+    let Ok(user_id) = service::register_tx(user, conn).await? else {
+        // 邮箱已被注册但不允许按邮箱关联
+        return biz_err!(OAuthErr::NoAccount);
+    };
+    repo_provider_identity::save(&link(user_id), conn).await?;
+    biz_ok!((user_id, Some(email)))
+}
+
+async fn login_existing(
+    user_id: UserId,
+    conn: &mut PgConn,
+) -> BizResult<(UserId, Option<String>), OAuthErr> {
+    let Some(mut user) = repo_user::find(user_id, conn).await? else {
+        bail!("linked user not found: {}", user_id);
+    };
+    user.login_verified();
+    repo_user::update(&user, conn).await?;
+    biz_ok!((user_id, None))
+}
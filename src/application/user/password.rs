@@ -0,0 +1,149 @@
+//! 密码检查
+//!
+//! 除了格式规则，还会拒绝常见密码列表中的密码，并可选地检查密码是否已经泄露。
+//! 泄露检查只用 SHA-1 的前 5 位查询，剩下的部分在本地比对，明文和完整的 hash 都不会发出。
+//! 查询失败时不阻止注册和修改密码，只记录日志
+
+use std::{collections::HashSet, path::PathBuf, sync::OnceLock, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use crate::{
+    domain::user::{
+        password::{pwned_count, PasswordPolicy},
+        Password, PasswordFormatErr,
+    },
+    ensure_biz,
+    http::BizResult,
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize, Default)]
+pub struct PasswordCfg {
+    #[serde(default)]
+    pub policy: PasswordPolicy,
+    /// 常见密码列表，每行一个，比较时不区分大小写
+    #[serde(default)]
+    pub banned_list_file: Option<PathBuf>,
+    #[serde(default)]
+    pub pwned: PwnedCfg,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PwnedCfg {
+    #[serde(default)]
+    pub enable: bool,
+    /// 以 http 开头时为远程服务，请求 {source}{前缀}；否则为本地目录，读取 {source}/{前缀}.txt
+    #[serde(default = "default_source")]
+    pub source: String,
+    /// 泄露次数达到这个值时拒绝
+    #[serde(default = "default_min_count")]
+    pub min_count: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for PwnedCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            source: default_source(),
+            min_count: default_min_count(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_source() -> String {
+    "https://api.pwnedpasswords.com/range/".to_string()
+}
+
+fn default_min_count() -> u64 {
+    1
+}
+
+fn default_timeout_secs() -> u64 {
+    3
+}
+
+/// SHA-1 十六进制的前缀长度
+const PREFIX_LEN: usize = 5;
+
+static BANNED: OnceLock<HashSet<String>> = OnceLock::new();
+
+fn cfg() -> &'static PasswordCfg {
+    &get_settings().password
+}
+
+// 这个函数应该在服务初始化时被调用一次，以检测列表文件是否可以正常读取
+pub fn load_banned_list() -> Result<()> {
+    let mut banned = HashSet::new();
+    if let Some(path) = &cfg().banned_list_file {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("read banned password list: {}", path.display()))?;
+        banned.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_lowercase),
+        );
+    }
+    let _ = BANNED.set(banned);
+    Ok(())
+}
+
+/// 按配置的规则检查密码，通过后返回 hash 后的密码
+pub async fn parse(value: String) -> BizResult<Password, PasswordFormatErr> {
+    let cfg = cfg();
+    ensure_biz!(cfg.policy.check(&value));
+    ensure_biz!(not is_banned(&value), PasswordFormatErr::Banned);
+    if cfg.pwned.enable {
+        ensure_biz!(not is_pwned(&cfg.pwned, &value).await, PasswordFormatErr::Pwned);
+    }
+    Ok(Password::try_from_async(value, &cfg.policy).await)
+}
+
+fn is_banned(password: &str) -> bool {
+    BANNED
+        .get()
+        .is_some_and(|banned| banned.contains(&password.to_lowercase()))
+}
+
+async fn is_pwned(cfg: &PwnedCfg, password: &str) -> bool {
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+    match pwned_range(cfg, prefix).await {
+        Ok(range) => pwned_count(&range, suffix) >= cfg.min_count,
+        Err(err) => {
+            warn!(?err, "query pwned passwords failed");
+            false
+        }
+    }
+}
+
+/// 查询 SHA-1 前缀相同的所有泄露密码
+async fn pwned_range(cfg: &PwnedCfg, prefix: &str) -> Result<String> {
+    if !cfg.source.starts_with("http") {
+        let path = PathBuf::from(&cfg.source).join(format!("{}.txt", prefix));
+        return match tokio::fs::read_to_string(&path).await {
+            Ok(range) => Ok(range),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    let range = reqwest::Client::builder()
+        .timeout(Duration::from_secs(cfg.timeout_secs))
+        .build()?
+        .get(format!("{}{}", cfg.source, prefix))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(range)
+}
@@ -0,0 +1,171 @@
+//! 用户邀请
+//!
+//! 注册时填写邀请码，邀请人和被邀请人都获得积分和额外的存储配额。
+//! 奖励在注册的事务中发放，数量按当时的配置记录在邀请记录中。
+//! 邀请人超过配置的邀请数上限后，新的邀请照常记录，但双方都不再获得奖励
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::user::{
+        referral::{ReferralCode, MAX_CODES_PER_USER},
+        user::UserId,
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{
+        repo_credit::{self, CreditKind},
+        repo_referral::{self, ReferralPo},
+        repo_user,
+    },
+    settings::get_settings,
+    LocalDataTime,
+};
+
+/// 邀请奖励，为 0 时不发放
+#[derive(Debug, Deserialize, Default)]
+pub struct ReferralCfg {
+    /// 邀请人获得的积分
+    #[serde(default)]
+    pub inviter_credits: u64,
+    /// 被邀请人获得的积分
+    #[serde(default)]
+    pub invitee_credits: u64,
+    /// 双方各自获得的额外存储配额（byte）
+    #[serde(default)]
+    pub bonus_quota: u64,
+    /// 每个邀请人最多有多少次邀请获得奖励，为空时不限制
+    #[serde(default)]
+    pub max_rewards: Option<u32>,
+    /// 每个邀请人 24 小时内最多有多少次邀请获得奖励，为空时不限制
+    #[serde(default)]
+    pub daily_max_rewards: Option<u32>,
+}
+
+/// 生成邀请码时碰撞的重试次数
+const GENERATE_RETRIES: usize = 3;
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferralCodeDto {
+    code: String,
+    /// 通过这个邀请码注册的人数
+    invited: i64,
+    #[schema(value_type = String)]
+    create_at: LocalDataTime,
+}
+
+pub async fn list_codes(user_id: UserId) -> Result<Vec<ReferralCodeDto>> {
+    let conn = &mut pg_conn().await?;
+    let codes = repo_referral::find_codes(user_id, conn).await?;
+    let counts: HashMap<_, _> = repo_referral::count_by_code(user_id, conn)
+        .await?
+        .into_iter()
+        .collect();
+    let codes = codes
+        .into_iter()
+        .map(|po| ReferralCodeDto {
+            invited: counts.get(&po.code).copied().unwrap_or(0),
+            code: po.code,
+            create_at: po.create_at,
+        })
+        .collect();
+    Ok(codes)
+}
+
+pub enum CreateCodeErr {
+    TooMany,
+}
+
+pub async fn create_code(user_id: UserId) -> BizResult<ReferralCodeDto, CreateCodeErr> {
+    let conn = &mut pg_conn().await?;
+    let count = repo_referral::count_codes(user_id, conn).await?;
+    ensure_biz!(count < MAX_CODES_PER_USER, CreateCodeErr::TooMany);
+
+    for _ in 0..GENERATE_RETRIES {
+        let code = ReferralCode::generate();
+        if repo_referral::save_code(user_id, &code, conn)
+            .await?
+            .is_effected()
+        {
+            return biz_ok!(ReferralCodeDto {
+                code: code.to_string(),
+                invited: 0,
+                create_at: chrono::Local::now(),
+            });
+        }
+    }
+    anyhow::bail!("failed to generate unique referral code")
+}
+
+/// 查找邀请码的所有者
+pub(crate) async fn find_inviter(code: &ReferralCode, conn: &mut PgConn) -> Result<Option<UserId>> {
+    repo_referral::find_owner(code, conn).await
+}
+
+/// 记录邀请并为双方发放奖励，与注册在同一个事务中调用
+pub(crate) async fn reward_tx(
+    inviter: UserId,
+    invitee: UserId,
+    code: &ReferralCode,
+    conn: &mut PgConn,
+) -> Result<()> {
+    let cfg = &get_settings().referral;
+    let (inviter_credits, invitee_credits, bonus_quota) =
+        if within_limit(inviter, cfg, conn).await? {
+            (cfg.inviter_credits, cfg.invitee_credits, cfg.bonus_quota)
+        } else {
+            (0, 0, 0)
+        };
+    let referral = ReferralPo {
+        invitee_id: invitee,
+        inviter_id: inviter,
+        code: code.as_str(),
+        inviter_credits: inviter_credits as i64,
+        invitee_credits: invitee_credits as i64,
+        bonus_quota: bonus_quota as i64,
+    };
+    if !repo_referral::save(&referral, conn).await?.is_effected() {
+        return Ok(());
+    }
+
+    for (user_id, credits) in [(inviter, inviter_credits), (invitee, invitee_credits)] {
+        if credits > 0 {
+            repo_credit::add(user_id, credits, CreditKind::Referral, None, None, conn).await?;
+        }
+        if bonus_quota > 0 {
+            repo_user::add_bonus_quota(user_id, bonus_quota, conn).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 邀请人是否还能获得奖励。先锁住邀请人，避免并发注册时超过上限
+async fn within_limit(inviter: UserId, cfg: &ReferralCfg, conn: &mut PgConn) -> Result<bool> {
+    if cfg.max_rewards.is_none() && cfg.daily_max_rewards.is_none() {
+        return Ok(true);
+    }
+    repo_user::lock(inviter, conn).await?;
+    let limits = [
+        (cfg.max_rewards, None),
+        (
+            cfg.daily_max_rewards,
+            Some(chrono::Local::now() - chrono::Duration::days(1)),
+        ),
+    ];
+    for (max, since) in limits {
+        let Some(max) = max else {
+            continue;
+        };
+        if repo_referral::count_rewarded(inviter, since, conn).await? >= max as i64 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,116 @@
+//! 手机号和短信验证码登录
+//!
+//! 验证码通过 /api/user/sms_code 获取。除了每个验证码最多尝试 5 次，还按手机号限制一段时间内的登录次数。
+//! 开启自动注册时，没有绑定过的手机号直接创建账号，账号的邮箱为占位地址，不会收到订单邮件
+
+use anyhow::bail;
+use derive_more::From;
+use serde::Deserialize;
+use utils::db_pools::postgres::PgConn;
+use utoipa::ToSchema;
+
+use crate::{
+    application::event_bus,
+    biz_ok,
+    domain::{
+        event::UserRegistered,
+        user::{
+            service,
+            user::{User, UserId},
+            Password, Phone, PhoneFormatErr, SanityCheck,
+        },
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{
+        repo_user,
+        sms_code::{self, SmsSender},
+    },
+    pg_tx,
+    settings::get_settings,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SmsLoginCfg {
+    /// 手机号没有绑定账号时是否自动注册
+    #[serde(default)]
+    pub auto_register: bool,
+    /// 每个手机号在一个时间窗口内最多登录的次数，包括失败的尝试
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u64,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+}
+
+impl Default for SmsLoginCfg {
+    fn default() -> Self {
+        Self {
+            auto_register: false,
+            max_attempts: default_max_attempts(),
+            window_secs: default_window_secs(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u64 {
+    10
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SmsLoginDto {
+    tel: String,
+    sms_code: String,
+}
+
+#[derive(From)]
+pub enum SmsLoginErr {
+    Phone(PhoneFormatErr),
+    Sanity(SanityCheck),
+    TooManyAttempts,
+    NotRegistered,
+}
+
+pub async fn login(login: SmsLoginDto) -> BizResult<UserId, SmsLoginErr> {
+    let settings = get_settings();
+    let cfg = &settings.sms_login;
+    let phone = ensure_biz!(Phone::try_from(login.tel, &settings.phone));
+    let attempts = sms_code::incr_login_count(&phone, cfg.window_secs).await?;
+    ensure_biz!(attempts <= cfg.max_attempts, SmsLoginErr::TooManyAttempts);
+    ensure_biz!(
+        SmsSender::verify(&phone, login.sms_code).await?,
+        SanityCheck::SmsCodeNotMatch
+    );
+
+    let (user_id, registered) = ensure_biz!(pg_tx!(login_tx, phone, cfg.auto_register)?);
+    if let Some(email) = registered {
+        event_bus::publish(UserRegistered { user_id, email });
+    }
+    biz_ok!(user_id)
+}
+
+/// 返回用户 id，自动注册时同时返回占位邮箱
+async fn login_tx(
+    phone: Phone,
+    auto_register: bool,
+    conn: &mut PgConn,
+) -> BizResult<(UserId, Option<String>), SmsLoginErr> {
+    if let Some(mut user) = repo_user::find(&phone, conn).await? {
+        user.login_verified();
+        repo_user::update(&user, conn).await?;
+        return biz_ok!((*user.id(), None));
+    }
+
+    ensure_biz!(auto_register, SmsLoginErr::NotRegistered);
+    let user = User::create_by_phone(phone, Password::random().await?);
+    let email = user.email().to_string();
+    // 验证码验证成功后即被删除，同一个手机号不会同时走到这里，占位邮箱也不会被其他账号使用
+    let Ok(user_id) = service::register_tx(user, conn).await? else {
+        bail!("placeholder email already registered: {}", email);
+    };
+    biz_ok!((user_id, Some(email)))
+}
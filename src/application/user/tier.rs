@@ -0,0 +1,122 @@
+//! 用户等级的限制
+//!
+//! 存储配额、同时转码和未结束的任务数、上传频率和转码请求的下发优先级都按用户等级配置，
+//! 没有单独配置的等级使用 default。用户等级由管理员设置，修改后立即生效
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    application::audit::{self, AuditAction},
+    biz_ok,
+    cqrs::user::UserLevel,
+    domain::user::{employee::EmployeeId, user::UserId},
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{repo_organization, repo_user, repo_user_file},
+    settings::get_settings,
+};
+
+/// 上传频率的统计窗口
+const UPLOAD_WINDOW_SECS: u64 = 3600;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct TierCfg {
+    #[serde(default)]
+    pub default: TierLimits,
+    /// 键为用户等级，如 VIP、SVIP
+    #[serde(default)]
+    pub levels: HashMap<UserLevel, TierLimits>,
+}
+
+impl TierCfg {
+    pub fn limits(&self, level: UserLevel) -> &TierLimits {
+        self.levels.get(&level).unwrap_or(&self.default)
+    }
+}
+
+/// 各项为空时不限制
+#[derive(Debug, Deserialize, Default)]
+pub struct TierLimits {
+    /// 存储配额（byte）
+    #[serde(default)]
+    pub storage_quota: Option<u64>,
+    /// 同时转码的任务数，超出的任务在 outbox 中排队
+    #[serde(default)]
+    pub max_active_tasks: Option<u32>,
+    /// 未结束的任务数，包括排队中的，超出时拒绝创建订单
+    #[serde(default)]
+    pub max_processing_tasks: Option<u32>,
+    /// 每小时最多开始的上传次数，包括秒传
+    #[serde(default)]
+    pub uploads_per_hour: Option<u32>,
+    /// 转码请求的下发优先级，越大越先下发
+    #[serde(default)]
+    pub priority: i16,
+}
+
+pub(crate) async fn limits(user_id: UserId) -> Result<&'static TierLimits> {
+    let level = UserLevel::of(user_id).await?;
+    Ok(get_settings().user_tiers.limits(level))
+}
+
+/// 再保存 bytes 字节后是否超出存储配额，配额包括邀请奖励的部分。
+/// 组织空间单独设置了配额时使用组织的配额
+pub(crate) async fn exceeds_quota(user_id: UserId, bytes: u64, conn: &mut PgConn) -> Result<bool> {
+    let quota = match repo_organization::find_quota_by_space(user_id, conn).await? {
+        Some(Some(quota)) => quota,
+        _ => {
+            let Some(quota) = limits(user_id).await?.storage_quota else {
+                return Ok(false);
+            };
+            quota + repo_user::find_bonus_quota(user_id, conn).await?
+        }
+    };
+    let used = repo_user_file::used_space(user_id, conn).await?;
+    Ok(used + bytes > quota)
+}
+
+/// 记录一次上传，超出频率限制时返回 false
+pub(crate) async fn try_upload(user_id: UserId) -> Result<bool> {
+    let Some(max) = limits(user_id).await?.uploads_per_hour else {
+        return Ok(true);
+    };
+    let count = repo_user::incr_upload_count(user_id, UPLOAD_WINDOW_SECS).await?;
+    Ok(count <= max as u64)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetLevelDto {
+    user_id: UserId,
+    #[schema(value_type = String, example = "VIP")]
+    level: UserLevel,
+}
+
+#[derive(Serialize)]
+struct SetLevelDetail {
+    user_id: UserId,
+    level: UserLevel,
+}
+
+pub enum SetLevelErr {
+    UserNotFound,
+}
+
+/// 管理员设置用户等级
+pub async fn set_level(employee_id: EmployeeId, params: SetLevelDto) -> BizResult<(), SetLevelErr> {
+    let conn = &mut pg_conn().await?;
+    let found = repo_user::set_level(params.user_id, params.level as i16, conn).await?;
+    ensure_biz!(found, SetLevelErr::UserNotFound);
+
+    let detail = SetLevelDetail {
+        user_id: params.user_id,
+        level: params.level,
+    };
+    audit::record(employee_id, AuditAction::SetUserLevel, &detail).await?;
+    biz_ok!(())
+}
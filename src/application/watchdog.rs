@@ -0,0 +1,245 @@
+//! 转码任务超时检测
+//!
+//! 转码工厂可能在没有任何回调的情况下丢失任务，这些任务会一直处于转码中。
+//! 后台任务定期检查超过截止时间的任务：未重试过的任务重新下发一次（可配置），
+//! 否则按转码失败处理，与收到失败回调时一样通知用户并退还积分
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    cqrs::MillionTimestamp,
+    domain::{
+        file_system::file::UserFileId,
+        transcode_order::{
+            deadline::task_deadline, params::TranscodeTaskParams, TranscodeOrderId, TranscodeTaskId,
+        },
+        user::user::UserId,
+    },
+    infrastructure::repo_order::{self, RunningTaskPo},
+    log_if_err,
+    settings::get_settings,
+    tx_func,
+};
+
+use super::{
+    outbox,
+    transcode::{self, TaskResult},
+};
+
+/// 超时任务的失败原因
+pub const TIMEOUT_ERR: &str = "timeout";
+
+/// 每次查询的任务数
+const CHECK_BATCH_SIZE: i64 = 200;
+
+/// 转码任务超时检测配置
+#[derive(Debug, Deserialize)]
+pub struct WatchdogCfg {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+    /// 检查的间隔
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// 截止时间 = base_timeout_secs + 每千帧 secs_per_thousand_frames
+    #[serde(default = "default_base_timeout_secs")]
+    pub base_timeout_secs: u64,
+    #[serde(default = "default_secs_per_thousand_frames")]
+    pub secs_per_thousand_frames: u64,
+    /// 超时后是否重新下发一次
+    #[serde(default = "default_auto_retry")]
+    pub auto_retry: bool,
+}
+
+impl Default for WatchdogCfg {
+    fn default() -> Self {
+        Self {
+            enable: default_enable(),
+            interval_secs: default_interval_secs(),
+            base_timeout_secs: default_base_timeout_secs(),
+            secs_per_thousand_frames: default_secs_per_thousand_frames(),
+            auto_retry: default_auto_retry(),
+        }
+    }
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_base_timeout_secs() -> u64 {
+    1800
+}
+
+fn default_secs_per_thousand_frames() -> u64 {
+    60
+}
+
+fn default_auto_retry() -> bool {
+    true
+}
+
+impl WatchdogCfg {
+    fn base_timeout(&self) -> Duration {
+        Duration::from_secs(self.base_timeout_secs)
+    }
+
+    fn deadline(&self, frame_count: u32) -> Duration {
+        task_deadline(
+            frame_count,
+            self.base_timeout(),
+            Duration::from_secs(self.secs_per_thousand_frames),
+        )
+    }
+}
+
+/// 启动后台检测任务
+pub fn spawn() {
+    let cfg = &get_settings().watchdog;
+    if !cfg.enable {
+        return;
+    }
+
+    info!(?cfg, "transcode task watchdog started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(check(cfg).await);
+            tokio::time::sleep(Duration::from_secs(cfg.interval_secs)).await;
+        }
+    });
+}
+
+async fn check(cfg: &WatchdogCfg) -> Result<()> {
+    // 截止时间不会小于 base_timeout，更早开始的任务才需要检查
+    let started_before = Local::now() - chrono::Duration::seconds(cfg.base_timeout_secs as i64);
+    // 未超时的任务不会被处理，用游标翻页，避免一直查到同一批任务
+    let mut cursor = None;
+    loop {
+        let tasks = {
+            let conn = &mut pg_conn().await?;
+            repo_order::find_running_after(started_before, cursor, CHECK_BATCH_SIZE, conn).await?
+        };
+        let Some(last) = tasks.last() else {
+            break;
+        };
+        cursor = Some((last.started_at, last.id));
+        let is_last_page = (tasks.len() as i64) < CHECK_BATCH_SIZE;
+
+        for task in tasks {
+            check_task(cfg, task).await?;
+        }
+        if is_last_page {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn check_task(cfg: &WatchdogCfg, task: RunningTaskPo) -> Result<()> {
+    let params: TranscodeTaskParams = serde_json::from_str(&task.params)?;
+    if running_time(&task) < cfg.deadline(params.frame_count) {
+        return Ok(());
+    }
+
+    let task_id = task.id;
+    if cfg.auto_retry && task.retries == 0 {
+        warn!(%task_id, "transcode task timed out, retrying");
+        log_if_err!(retry(task_id).await);
+    } else {
+        warn!(%task_id, retries = task.retries, "transcode task timed out");
+        let result = TaskResult {
+            task_id,
+            file_id: task.sys_file_id.0,
+            result: Err(TIMEOUT_ERR.to_string()),
+        };
+        log_if_err!(transcode::task_done(result).await);
+    }
+    Ok(())
+}
+
+fn running_time(task: &RunningTaskPo) -> Duration {
+    (Local::now() - task.started_at)
+        .to_std()
+        .unwrap_or_default()
+}
+
+async fn retry(task_id: TranscodeTaskId) -> Result<()> {
+    if tx_func!(retry_tx, task_id)? {
+        outbox::wake();
+    }
+    Ok(())
+}
+
+/// 重新下发任务，任务已结束时什么也不做
+async fn retry_tx(task_id: TranscodeTaskId, conn: &mut PgConn) -> Result<bool> {
+    let Some(order) = repo_order::find(task_id, conn).await? else {
+        return Ok(false);
+    };
+    let Some(task) = order.tasks().iter().find(|t| t.id() == &task_id) else {
+        return Ok(false);
+    };
+    if !repo_order::restart_task(task_id, conn).await? {
+        return Ok(false);
+    }
+    outbox::enqueue_transcode(*order.user_id(), [task], conn).await?;
+    Ok(true)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LongRunningTaskDto {
+    task_id: TranscodeTaskId,
+    order_id: TranscodeOrderId,
+    user_id: UserId,
+    file_id: UserFileId,
+    virtual_path: String,
+    frame_count: u32,
+    /// 开始或最近一次重新下发的时间
+    started_at: MillionTimestamp,
+    running_secs: u64,
+    /// 超过这个时间会被视为超时
+    deadline_secs: u64,
+    retries: i16,
+}
+
+/// 已运行超过 min_secs 秒的任务，运行最久的排在前面
+pub async fn long_running(
+    min_secs: u64,
+    page: u32,
+    page_size: u32,
+) -> Result<Vec<LongRunningTaskDto>> {
+    let cfg = &get_settings().watchdog;
+    let started_before = Local::now() - chrono::Duration::seconds(min_secs as i64);
+    let offset = page.saturating_sub(1) as i64 * page_size as i64;
+    let conn = &mut pg_conn().await?;
+    let tasks = repo_order::find_running(started_before, offset, page_size as i64, conn).await?;
+
+    tasks
+        .into_iter()
+        .map(|task| {
+            let params: TranscodeTaskParams = serde_json::from_str(&task.params)?;
+            Ok(LongRunningTaskDto {
+                task_id: task.id,
+                order_id: task.order_id,
+                user_id: task.user_id,
+                file_id: task.user_file_id,
+                frame_count: params.frame_count,
+                running_secs: running_time(&task).as_secs(),
+                deadline_secs: cfg.deadline(params.frame_count).as_secs(),
+                started_at: task.started_at.into(),
+                virtual_path: task.virtual_path,
+                retries: task.retries,
+            })
+        })
+        .collect()
+}
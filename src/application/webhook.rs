@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+
+use crate::{
+    biz_ok,
+    domain::user::{
+        user::UserId,
+        webhook::{self, Webhook, WebhookEvent, WebhookId, WebhookUrlErr, MAX_WEBHOOKS_PER_USER},
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{
+        public_host::is_public_host,
+        repo_webhook::{self, DeliveryPo, DeliveryStatus, NewDeliveryPo},
+    },
+    log_if_err,
+    settings::get_settings,
+};
+
+/// webhook 投递配置
+#[derive(Debug, Deserialize)]
+pub struct WebhookCfg {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+    /// 轮询待投递事件的间隔
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 超过此次数后放弃投递
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试的等待时间，之后每次翻倍
+    #[serde(default = "default_base_backoff_secs")]
+    pub base_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookCfg {
+    fn default() -> Self {
+        Self {
+            enable: default_enable(),
+            poll_interval_secs: default_poll_interval_secs(),
+            max_attempts: default_max_attempts(),
+            base_backoff_secs: default_base_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_poll_interval_secs() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> u32 {
+    8
+}
+
+fn default_base_backoff_secs() -> u64 {
+    10
+}
+
+fn default_max_backoff_secs() -> u64 {
+    3600
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookDto {
+    id: WebhookId,
+    url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedWebhook {
+    id: WebhookId,
+    url: String,
+    /// 签名密钥，只在创建时返回
+    secret: String,
+}
+
+#[derive(derive_more::From)]
+pub enum CreateWebhookErr {
+    Url(WebhookUrlErr),
+    TooMany,
+}
+
+pub enum DeleteWebhookErr {
+    NotFound,
+}
+
+pub async fn list(user_id: UserId) -> Result<Vec<WebhookDto>> {
+    let conn = &mut pg_conn().await?;
+    let hooks = repo_webhook::find_by_user(user_id, conn).await?;
+    let hooks = hooks
+        .into_iter()
+        .map(|hook| WebhookDto {
+            id: *hook.id(),
+            url: hook.url().clone(),
+        })
+        .collect();
+    Ok(hooks)
+}
+
+pub async fn create(user_id: UserId, url: String) -> BizResult<CreatedWebhook, CreateWebhookErr> {
+    let hook = ensure_biz!(Webhook::create(user_id, url));
+    let url = reqwest::Url::parse(hook.url())?;
+    ensure_biz!(
+        is_public_host(&url).await,
+        CreateWebhookErr::Url(WebhookUrlErr::PrivateHost)
+    );
+
+    let conn = &mut pg_conn().await?;
+    let count = repo_webhook::count(user_id, conn).await?;
+    ensure_biz!(count < MAX_WEBHOOKS_PER_USER, CreateWebhookErr::TooMany);
+    repo_webhook::save(&hook, conn).await?;
+
+    biz_ok!(CreatedWebhook {
+        id: *hook.id(),
+        url: hook.url().clone(),
+        secret: hook.secret().clone(),
+    })
+}
+
+pub async fn delete(user_id: UserId, id: WebhookId) -> BizResult<(), DeleteWebhookErr> {
+    let conn = &mut pg_conn().await?;
+    let effected = repo_webhook::delete(user_id, id, conn).await?;
+    ensure_biz!(effected.is_effected(), DeleteWebhookErr::NotFound);
+    biz_ok!(())
+}
+
+/// 为用户的每个 webhook 生成一条投递记录，与业务数据在同一个事务中保存，由后台任务投递
+pub(crate) async fn emit(user_id: UserId, event: &WebhookEvent, conn: &mut PgConn) -> Result<()> {
+    let hooks = repo_webhook::find_by_user(user_id, conn).await?;
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let payload = serde_json::to_string(event)?;
+    let deliveries: Vec<_> = hooks
+        .iter()
+        .map(|hook| NewDeliveryPo {
+            webhook_id: *hook.id(),
+            event: event.name(),
+            payload: &payload,
+        })
+        .collect();
+    repo_webhook::save_deliveries(&deliveries, conn).await
+}
+
+/// 启动后台投递任务
+pub fn spawn_worker() {
+    let cfg = &get_settings().webhook;
+    if !cfg.enable {
+        return;
+    }
+
+    info!(?cfg, "webhook worker started");
+    tokio::spawn(async move {
+        // 重定向的目标没有经过内网地址检查，不跟随
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(cfg.timeout_secs))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("build http client");
+        loop {
+            log_if_err!(deliver_due(&client, cfg).await);
+            tokio::time::sleep(Duration::from_secs(cfg.poll_interval_secs)).await;
+        }
+    });
+}
+
+async fn deliver_due(client: &reqwest::Client, cfg: &WebhookCfg) -> Result<()> {
+    let deliveries = {
+        let conn = &mut pg_conn().await?;
+        repo_webhook::due_deliveries(100, conn).await?
+    };
+
+    futures_util::stream::iter(deliveries)
+        .for_each_concurrent(8, |delivery| async move {
+            log_if_err!(deliver(client, cfg, delivery).await);
+        })
+        .await;
+    Ok(())
+}
+
+async fn deliver(client: &reqwest::Client, cfg: &WebhookCfg, delivery: DeliveryPo) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    // 投递期间的租约，超时未更新状态时可以被重新投递
+    let lease = Local::now() + chrono::Duration::seconds(cfg.timeout_secs as i64 * 2);
+    if !repo_webhook::claim(&delivery, lease, conn).await? {
+        return Ok(());
+    }
+    let Some(hook) = repo_webhook::find(delivery.webhook_id, conn).await? else {
+        return Ok(());
+    };
+
+    let attempts = delivery.attempts + 1;
+    // 创建后域名可能被解析到内网地址，每次投递前重新检查
+    let url = reqwest::Url::parse(hook.url())?;
+    if !is_public_host(&url).await {
+        warn!(
+            id = delivery.id,
+            url = hook.url(),
+            "webhook host is private, gave up"
+        );
+        repo_webhook::update_delivery(
+            delivery.id,
+            DeliveryStatus::GaveUp,
+            attempts,
+            Local::now(),
+            Some("host not allowed"),
+            conn,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let signature = webhook::sign(hook.secret(), delivery.payload.as_bytes());
+    let result = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Av1-Event", &delivery.event)
+        .header("X-Av1-Delivery", delivery.id)
+        .header("X-Av1-Signature", format!("sha256={}", signature))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|resp| {
+            // 不跟随重定向，3xx 也视为失败
+            let status = resp.status();
+            status
+                .is_success()
+                .then_some(())
+                .ok_or_else(|| format!("unexpected status: {}", status))
+        });
+
+    match result {
+        Ok(_) => {
+            debug!(id = delivery.id, url = hook.url(), "webhook delivered");
+            repo_webhook::update_delivery(
+                delivery.id,
+                DeliveryStatus::Delivered,
+                attempts,
+                Local::now(),
+                None,
+                conn,
+            )
+            .await?;
+        }
+        Err(err) => {
+            let status = if attempts as u32 >= cfg.max_attempts {
+                warn!(id = delivery.id, url = hook.url(), %err, "webhook delivery gave up");
+                DeliveryStatus::GaveUp
+            } else {
+                DeliveryStatus::Pending
+            };
+            let delay = webhook::retry_delay(
+                attempts as u32,
+                Duration::from_secs(cfg.base_backoff_secs),
+                Duration::from_secs(cfg.max_backoff_secs),
+            );
+            repo_webhook::update_delivery(
+                delivery.id,
+                status,
+                attempts,
+                Local::now() + chrono::Duration::seconds(delay.as_secs() as i64),
+                Some(&err),
+                conn,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
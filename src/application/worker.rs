@@ -0,0 +1,248 @@
+//! 转码节点的注册、心跳和任务分配
+//!
+//! 开启后转码请求不再发往固定的 av1-factory 地址，而是由 outbox 挑选在线、支持该任务且负载最低的节点发送；
+//! 没有可用节点时消息留在 outbox 中等待。后台任务定期检查分配给已下线节点的任务并重新下发。
+//! 信息采集、缩略图等请求仍然发给 av1-factory
+
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use utils::db_pools::postgres::{pg_conn, PgConn};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::{
+    biz_ok,
+    domain::transcode_order::{
+        params::zcode::VideoFormat,
+        worker::{self, Worker, WorkerCapabilities, WorkerId},
+        TranscodeTaskId,
+    },
+    ensure_biz,
+    http::BizResult,
+    infrastructure::{av1_factory, repo_order, repo_worker},
+    log_if_err,
+    settings::get_settings,
+    tx_func, LocalDataTime,
+};
+
+use super::outbox;
+
+/// 每次检查时最多重新分配的任务数
+const REASSIGN_BATCH_SIZE: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct WorkerCfg {
+    /// 关闭时转码请求发往 av1_factory.endpoint
+    #[serde(default)]
+    pub enable: bool,
+    /// 节点发送心跳的间隔，注册时返回给节点
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// 超过这个时间没有心跳的节点视为下线
+    #[serde(default = "default_heartbeat_timeout_secs")]
+    pub heartbeat_timeout_secs: u64,
+}
+
+impl Default for WorkerCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            heartbeat_interval_secs: default_heartbeat_interval_secs(),
+            heartbeat_timeout_secs: default_heartbeat_timeout_secs(),
+        }
+    }
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    10
+}
+
+fn default_heartbeat_timeout_secs() -> u64 {
+    30
+}
+
+impl WorkerCfg {
+    fn alive_since(&self) -> LocalDataTime {
+        Local::now() - chrono::Duration::seconds(self.heartbeat_timeout_secs as i64)
+    }
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterWorkerDto {
+    /// 节点接收任务的地址，如 http://10.0.0.2:8993
+    #[validate(length(min = 1, max = 2048))]
+    endpoint: String,
+    #[validate(length(min = 1))]
+    codecs: Vec<VideoFormat>,
+    #[validate(range(min = 1))]
+    max_width: u32,
+    #[validate(range(min = 1))]
+    max_height: u32,
+    /// 同时处理的任务数
+    #[validate(range(min = 1, max = 1024))]
+    concurrency: u32,
+    /// 节点上正在处理的任务数，重启后注册时为 0
+    #[serde(default)]
+    running: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterWorkerResp {
+    worker_id: WorkerId,
+    heartbeat_interval_secs: u64,
+}
+
+/// 注册节点。同一个地址重新注册时分配新的 id，之前分配给它的任务会被重新下发
+pub async fn register(params: RegisterWorkerDto) -> Result<RegisterWorkerResp> {
+    let worker = Worker {
+        id: WorkerId::next_id(),
+        endpoint: params.endpoint.trim_end_matches('/').to_string(),
+        capabilities: WorkerCapabilities {
+            codecs: params.codecs,
+            max_width: params.max_width,
+            max_height: params.max_height,
+            concurrency: params.concurrency,
+        },
+        running: params.running,
+    };
+    let conn = &mut pg_conn().await?;
+    repo_worker::register(&worker, conn).await?;
+    info!(
+        worker_id = %worker.id,
+        endpoint = worker.endpoint,
+        capabilities = ?worker.capabilities,
+        "worker registered"
+    );
+
+    Ok(RegisterWorkerResp {
+        worker_id: worker.id,
+        heartbeat_interval_secs: get_settings().worker.heartbeat_interval_secs,
+    })
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HeartbeatDto {
+    worker_id: WorkerId,
+    /// 节点上正在处理的任务数
+    running: u32,
+}
+
+pub enum HeartbeatErr {
+    /// 节点需要重新注册
+    UnknownWorker,
+}
+
+pub async fn heartbeat(params: HeartbeatDto) -> BizResult<(), HeartbeatErr> {
+    let conn = &mut pg_conn().await?;
+    let found = repo_worker::heartbeat(params.worker_id, params.running, conn).await?;
+    ensure_biz!(found, HeartbeatErr::UnknownWorker);
+    biz_ok!(())
+}
+
+/// outbox 中一条消息的去向
+pub(crate) enum Route {
+    Factory,
+    Worker(Worker),
+    /// 暂时没有可用的节点，留在 outbox 中稍后再试
+    Wait,
+}
+
+pub(crate) async fn route(payload: &str) -> Result<Route> {
+    let cfg = &get_settings().worker;
+    if !cfg.enable {
+        return Ok(Route::Factory);
+    }
+    // 取消请求发给执行该任务的节点，节点已下线或任务不在节点上时发给 av1-factory
+    if let Some(task_id) = av1_factory::cancel_target(payload) {
+        let conn = &mut pg_conn().await?;
+        let Some(worker_id) = repo_order::worker_of(task_id, conn).await? else {
+            return Ok(Route::Factory);
+        };
+        let workers = repo_worker::find_alive(cfg.alive_since(), conn).await?;
+        let worker = workers.into_iter().find(|worker| worker.id == worker_id);
+        return Ok(worker.map_or(Route::Factory, Route::Worker));
+    }
+    let Some(params) = av1_factory::transcode_params(payload) else {
+        return Ok(Route::Factory);
+    };
+
+    let conn = &mut pg_conn().await?;
+    let workers = repo_worker::find_alive(cfg.alive_since(), conn).await?;
+    match worker::pick_worker(&workers, &params) {
+        Some(worker) => Ok(Route::Worker(worker.clone())),
+        None => Ok(Route::Wait),
+    }
+}
+
+/// 任务已发送给节点
+pub(crate) async fn assigned(task_id: TranscodeTaskId, worker_id: WorkerId) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    repo_order::assign_worker(task_id, worker_id, conn).await?;
+    repo_worker::take_slot(worker_id, conn).await
+}
+
+/// 启动后台任务，重新下发已下线节点上的任务
+pub fn spawn() {
+    let cfg = &get_settings().worker;
+    if !cfg.enable {
+        return;
+    }
+
+    info!(?cfg, "worker heartbeat checker started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(reassign_orphaned(cfg).await);
+            tokio::time::sleep(Duration::from_secs(cfg.heartbeat_interval_secs)).await;
+        }
+    });
+}
+
+async fn reassign_orphaned(cfg: &WorkerCfg) -> Result<()> {
+    let tasks = {
+        let conn = &mut pg_conn().await?;
+        repo_order::find_orphaned(cfg.alive_since(), REASSIGN_BATCH_SIZE, conn).await?
+    };
+
+    let mut reassigned = false;
+    for (task_id, worker_id) in tasks {
+        warn!(%task_id, %worker_id, "worker missed heartbeats, reassigning task");
+        match reassign(task_id, worker_id).await {
+            Ok(ok) => reassigned |= ok,
+            Err(err) => warn!(?err, %task_id, "reassign task failed"),
+        }
+    }
+    if reassigned {
+        outbox::wake();
+    }
+    Ok(())
+}
+
+async fn reassign(task_id: TranscodeTaskId, worker_id: WorkerId) -> Result<bool> {
+    tx_func!(reassign_tx, task_id, worker_id)
+}
+
+/// 重新写入 outbox，任务已结束或已被其他实例重新分配时什么也不做
+async fn reassign_tx(
+    task_id: TranscodeTaskId,
+    worker_id: WorkerId,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let Some(order) = repo_order::find(task_id, conn).await? else {
+        return Ok(false);
+    };
+    let Some(task) = order.tasks().iter().find(|t| t.id() == &task_id) else {
+        return Ok(false);
+    };
+    if !repo_order::reassign_task(task_id, worker_id, conn).await? {
+        return Ok(false);
+    }
+    outbox::enqueue_transcode(*order.user_id(), [task], conn).await?;
+    Ok(true)
+}
@@ -0,0 +1,106 @@
+use anyhow::bail;
+use async_graphql::{Enum, SimpleObject};
+use diesel::{prelude::Queryable, ExpressionMethods, QueryDsl, Selectable, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn_read;
+
+use crate::{
+    domain::{transcode_order::TranscodeOrderId, user::user::UserId},
+    schema::activities,
+};
+
+use super::{decode_cursor, encode_cursor, KeysetPaginate, MillionTimestamp};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = activities)]
+struct ActivityRow {
+    id: i64,
+    kind: i16,
+    message: String,
+    file_path: Option<String>,
+    order_id: Option<TranscodeOrderId>,
+    create_at: MillionTimestamp,
+}
+
+/// 用户动态
+#[derive(SimpleObject)]
+pub struct Activity {
+    kind: ActivityKind,
+    message: String,
+    /// 相关文件的路径，文件之后可能被移动或删除
+    file_path: Option<String>,
+    order_id: Option<TranscodeOrderId>,
+    create_at: MillionTimestamp,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    /// 上传文件
+    FileUploaded,
+    /// 转码订单结束
+    OrderFinished,
+    /// 其他用户分享了文件
+    FileShared,
+}
+
+impl TryFrom<ActivityRow> for Activity {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ActivityRow) -> anyhow::Result<Self> {
+        let kind = match row.kind {
+            0 => ActivityKind::FileUploaded,
+            1 => ActivityKind::OrderFinished,
+            2 => ActivityKind::FileShared,
+            kind => bail!("invalid activity kind: {}", kind),
+        };
+        Ok(Self {
+            kind,
+            message: row.message,
+            file_path: row.file_path,
+            order_id: row.order_id,
+            create_at: row.create_at,
+        })
+    }
+}
+
+#[derive(SimpleObject, Default)]
+pub struct ActivityPage {
+    activities: Vec<Activity>,
+    /// 下一页的游标，为空时没有更多数据
+    next_cursor: Option<String>,
+}
+
+impl ActivityPage {
+    /// 按时间倒序的游标分页，动态只追加，翻页时不会重复或遗漏
+    pub async fn load(user_id: UserId, page: KeysetPaginate) -> anyhow::Result<Self> {
+        let mut query = activities::table
+            .filter(activities::user_id.eq(user_id))
+            .into_boxed();
+        if let Some(cursor) = &page.after {
+            let last_id: i64 = decode_cursor(cursor)?;
+            query = query.filter(activities::id.lt(last_id));
+        }
+
+        let conn = &mut pg_conn_read().await?;
+        let page_size = page.page_size as usize;
+        let rows: Vec<ActivityRow> = query
+            .select(ActivityRow::as_select())
+            .order_by(activities::id.desc())
+            .limit(page_size as i64)
+            .load(conn)
+            .await?;
+
+        let next_cursor = match rows.last() {
+            Some(last) if rows.len() == page_size => Some(encode_cursor(&last.id)?),
+            _ => None,
+        };
+        let activities = rows
+            .into_iter()
+            .map(Activity::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            activities,
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use async_graphql::SimpleObject;
+use diesel::{prelude::Queryable, ExpressionMethods, QueryDsl, Selectable, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{
+        file_system::{comment::FileCommentId, file::UserFileId},
+        user::user::UserId,
+    },
+    schema::{file_comments, user_files},
+};
+
+use super::{MillionTimestamp, Paginate};
+
+/// 文件评论
+#[derive(SimpleObject, Serialize, ToSchema, Queryable, Selectable)]
+#[diesel(table_name = file_comments)]
+#[serde(rename_all = "camelCase")]
+pub struct FileComment {
+    id: FileCommentId,
+    user_file_id: UserFileId,
+    /// 评论者
+    #[diesel(column_name = user_id)]
+    author_id: UserId,
+    content: String,
+    /// 视频时间点（毫秒），为空表示针对整个文件
+    timecode_ms: Option<i32>,
+    create_at: MillionTimestamp,
+    /// 最后修改时间
+    updated_at: MillionTimestamp,
+}
+
+#[derive(SimpleObject, Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentList {
+    total: i64,
+    comments: Vec<FileComment>,
+}
+
+impl CommentList {
+    /// 按时间顺序加载文件的评论
+    pub async fn load(file_id: UserFileId, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let total: i64 = file_comments::table
+            .filter(file_comments::user_file_id.eq(file_id))
+            .count()
+            .get_result(conn)
+            .await?;
+        let comments = file_comments::table
+            .filter(file_comments::user_file_id.eq(file_id))
+            .select(FileComment::as_select())
+            .order_by((file_comments::create_at.asc(), file_comments::id.asc()))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        Ok(Self { total, comments })
+    }
+
+    /// 文件不存在或不属于该用户时返回 None
+    pub async fn load_owned(
+        user_id: UserId,
+        file_id: UserFileId,
+        page: Paginate,
+    ) -> anyhow::Result<Option<Self>> {
+        let conn = &mut pg_conn_read().await?;
+        let owned: bool = diesel::select(diesel::dsl::exists(
+            user_files::table
+                .filter(user_files::id.eq(file_id))
+                .filter(user_files::user_id.eq(user_id))
+                .filter(user_files::deleted.eq(false)),
+        ))
+        .get_result(conn)
+        .await?;
+        if !owned {
+            return Ok(None);
+        }
+        Ok(Some(Self::load(file_id, page).await?))
+    }
+}
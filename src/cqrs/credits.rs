@@ -0,0 +1,93 @@
+use async_graphql::SimpleObject;
+use diesel::{prelude::Queryable, ExpressionMethods, QueryDsl, Selectable, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn_read;
+
+use crate::{
+    domain::{
+        transcode_order::{TranscodeOrderId, TranscodeTaskId},
+        user::user::UserId,
+    },
+    schema::credit_transactions,
+};
+
+pub use crate::infrastructure::repo_credit::CreditKind;
+
+use super::{MillionTimestamp, Paginate};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = credit_transactions)]
+struct CreditTransactionRow {
+    amount: i64,
+    balance: i64,
+    kind: i16,
+    order_id: Option<TranscodeOrderId>,
+    task_id: Option<TranscodeTaskId>,
+    create_at: MillionTimestamp,
+}
+
+/// 积分变动记录
+#[derive(SimpleObject)]
+pub struct CreditTransaction {
+    /// 变动数量，正数为增加，负数为扣除
+    amount: i64,
+    /// 变动后的余额
+    balance: i64,
+    kind: CreditKind,
+    order_id: Option<TranscodeOrderId>,
+    task_id: Option<TranscodeTaskId>,
+    create_at: MillionTimestamp,
+}
+
+impl TryFrom<CreditTransactionRow> for CreditTransaction {
+    type Error = anyhow::Error;
+
+    fn try_from(row: CreditTransactionRow) -> anyhow::Result<Self> {
+        Ok(Self {
+            amount: row.amount,
+            balance: row.balance,
+            kind: CreditKind::from_i16(row.kind)?,
+            order_id: row.order_id,
+            task_id: row.task_id,
+            create_at: row.create_at,
+        })
+    }
+}
+
+#[derive(SimpleObject, Default)]
+pub struct CreditHistory {
+    total: i64,
+    transactions: Vec<CreditTransaction>,
+}
+
+impl CreditHistory {
+    pub async fn load(user_id: UserId, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let total: i64 = credit_transactions::table
+            .filter(credit_transactions::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .await?;
+        let rows: Vec<CreditTransactionRow> = credit_transactions::table
+            .filter(credit_transactions::user_id.eq(user_id))
+            .select(CreditTransactionRow::as_select())
+            .order_by(credit_transactions::id.desc())
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        let transactions = rows
+            .into_iter()
+            .map(CreditTransaction::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            total,
+            transactions,
+        })
+    }
+}
@@ -0,0 +1,115 @@
+use diesel::{
+    prelude::Queryable, ExpressionMethods, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{
+        file_system::{change::ChangeKind, file::UserFileId},
+        user::user::UserId,
+    },
+    schema::file_changes,
+};
+
+use super::{decode_cursor, encode_cursor, MillionTimestamp};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = file_changes)]
+struct ChangeRow {
+    seq: i64,
+    user_file_id: UserFileId,
+    kind: i16,
+    is_dir: bool,
+    path: String,
+    old_path: Option<String>,
+    create_at: MillionTimestamp,
+}
+
+/// 一次文件变更
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChange {
+    file_id: UserFileId,
+    kind: ChangeKind,
+    is_dir: bool,
+    /// 变更后的路径，删除时为删除前的路径
+    path: String,
+    /// 重命名和移动前的路径
+    old_path: Option<String>,
+    create_at: MillionTimestamp,
+}
+
+impl TryFrom<ChangeRow> for FileChange {
+    type Error = anyhow::Error;
+
+    fn try_from(row: ChangeRow) -> anyhow::Result<Self> {
+        Ok(Self {
+            file_id: row.user_file_id,
+            kind: row.kind.try_into()?,
+            is_dir: row.is_dir,
+            path: row.path,
+            old_path: row.old_path,
+            create_at: row.create_at,
+        })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChangeList {
+    /// 按发生顺序排列
+    changes: Vec<FileChange>,
+    /// 下次请求使用的游标
+    cursor: String,
+    /// 还有更多变更，应立即用新的游标继续读取
+    has_more: bool,
+}
+
+impl FileChangeList {
+    /// 游标之后的变更。没有游标时只返回当前的游标，客户端先取得游标，再读取目录清单作为同步的起点。
+    /// 从库的延迟会让游标和目录清单不一致，从主库读取
+    pub async fn load(user_id: UserId, since: Option<&str>, limit: u32) -> anyhow::Result<Self> {
+        let conn = &mut pg_conn().await?;
+        let Some(since) = since else {
+            let last_seq: Option<i64> = file_changes::table
+                .filter(file_changes::user_id.eq(user_id))
+                .select(file_changes::seq)
+                .order_by(file_changes::seq.desc())
+                .first(conn)
+                .await
+                .optional()?;
+            return Ok(Self {
+                changes: vec![],
+                cursor: encode_cursor(&last_seq.unwrap_or(0))?,
+                has_more: false,
+            });
+        };
+
+        let since_seq: i64 = decode_cursor(since)?;
+        let mut rows: Vec<ChangeRow> = file_changes::table
+            .filter(file_changes::user_id.eq(user_id))
+            .filter(file_changes::seq.gt(since_seq))
+            .select(ChangeRow::as_select())
+            .order_by(file_changes::seq.asc())
+            .limit(limit as i64 + 1)
+            .load(conn)
+            .await?;
+
+        let has_more = rows.len() > limit as usize;
+        rows.truncate(limit as usize);
+        let last_seq = rows.last().map_or(since_seq, |row| row.seq);
+        let changes = rows
+            .into_iter()
+            .map(FileChange::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            changes,
+            cursor: encode_cursor(&last_seq)?,
+            has_more,
+        })
+    }
+}
@@ -1,25 +1,54 @@
-use async_graphql::{ComplexObject, Enum, SimpleObject};
-use diesel::{prelude::Queryable, ExpressionMethods, QueryDsl, Selectable, SelectableHelper};
+use std::ops::RangeInclusive;
+
+use async_graphql::{ComplexObject, Context, Enum, InputObject, SimpleObject};
+use diesel::{
+    dsl::{not, sql},
+    expression::SqlLiteral,
+    pg::Pg,
+    prelude::Queryable,
+    sql_types::Bool,
+    BoolExpressionMethods, ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl,
+    Selectable, SelectableHelper,
+};
 use diesel_async::RunQueryDsl;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::warn;
-use utils::db_pools::postgres::pg_conn;
+use utils::db_pools::postgres::{pg_conn, pg_conn_read, PgConn};
 
 use crate::{
-    application::file_system::video_info::AudioInfo,
+    application::file_system::{
+        access_log::AccessKind,
+        dir_cache, share,
+        video_info::{self, AudioInfo},
+    },
     domain::{
-        file_system::file::{SysFileId, UserFileId},
+        file_system::{
+            file::{SysFileId, UserFileId},
+            share::{AccessLevel, SharePermission},
+            tag::TagId,
+        },
         user::user::UserId,
     },
-    schema::{sys_files, user_files},
+    schema::{
+        file_access_logs, file_accesses, file_tags, folder_shares, starred_files, subtitles,
+        sys_files, tags, transcode_tasks, user_files,
+    },
     LocalDataTime,
 };
 use async_graphql::Result;
 
-use super::{user::User, MillionTimestamp, Paginate};
+use super::{
+    comment::CommentList,
+    decode_cursor, encode_cursor,
+    loader::{
+        DbDataLoader, MediaInfo, MediaInfoKey, QuarantinedKey, StarredKey, TranscodedOutputsKey,
+    },
+    user::User,
+    KeysetOrder, KeysetPaginate, MillionTimestamp, Paginate,
+};
 
 /// 用户文件节点
-#[derive(SimpleObject, Debug, Queryable, Selectable)]
+#[derive(SimpleObject, Debug, Clone, Queryable, Selectable, Serialize, Deserialize)]
 #[graphql(complex)]
 #[diesel(table_name = user_files)]
 pub struct UserFile {
@@ -27,7 +56,7 @@ pub struct UserFile {
     pub user_id: UserId,
 
     #[graphql(skip)]
-    pub sys_file_id: Option<i64>,
+    pub sys_file_id: Option<SysFileId>,
 
     /// 文件文件所在的目录
     pub at_dir: String,
@@ -35,14 +64,19 @@ pub struct UserFile {
     pub file_name: String,
 
     pub is_dir: bool,
+
+    #[graphql(skip)]
+    pub create_at: LocalDataTime,
+    #[graphql(skip)]
+    pub updated_at: LocalDataTime,
 }
 
 /// 系统文件节点
-#[derive(SimpleObject, Debug, Queryable, Selectable)]
+#[derive(SimpleObject, Debug, Clone, Queryable, Selectable)]
 #[diesel(table_name = sys_files)]
 #[graphql(complex)]
 pub struct FileData {
-    id: SysFileId,
+    pub id: SysFileId,
     /// 文件哈希
     pub hash: String,
     /// 文件大小（byte)
@@ -50,7 +84,7 @@ pub struct FileData {
     /// 是否是视频文件
     pub is_video: Option<bool>,
     /// 转码自哪个文件
-    pub transcode_from: Option<i64>,
+    pub transcode_from: Option<SysFileId>,
     /// 是否可以转码
     pub can_be_encode: Option<bool>,
     /// 比特率
@@ -61,6 +95,8 @@ pub struct FileData {
     pub height: Option<i32>,
     /// 宽度
     pub width: Option<i32>,
+    /// 根据内容识别的 MIME 类型，旧文件为空
+    pub mime: Option<String>,
 }
 
 #[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
@@ -121,36 +157,89 @@ pub enum Channels {
     _71 = 8,
 }
 
+/// 各分辨率对应的（宽，高）范围，竖屏视频宽高互换
+const RESOLUTIONS: [(ResolutionQl, RangeInclusive<i32>, RangeInclusive<i32>); 8] = [
+    (ResolutionQl::_144P, 0..=256, 0..=144),
+    (ResolutionQl::_240P, 257..=426, 145..=240),
+    (ResolutionQl::_360P, 427..=640, 241..=360),
+    (ResolutionQl::_480P, 641..=768, 361..=480),
+    (ResolutionQl::_720P, 769..=1280, 481..=720),
+    (ResolutionQl::_1080P, 1281..=1920, 721..=1080),
+    (ResolutionQl::_1440P, 1921..=2560, 1081..=1440),
+    (ResolutionQl::_4K, 2561..=3840, 1441..=2160),
+];
+
 impl ResolutionQl {
     fn try_from(width: i32, height: i32) -> Option<Self> {
-        let res1 = match (width, height) {
-            (0..=256, 0..=144) => Some(Self::_144P),
-            (257..=426, 145..=240) => Some(Self::_240P),
-            (427..=640, 241..=360) => Some(Self::_360P),
-            (641..=768, 361..=480) => Some(Self::_480P),
-            (769..=1280, 481..=720) => Some(Self::_720P),
-            (1281..=1920, 721..=1080) => Some(Self::_1080P),
-            (1921..=2560, 1081..=1440) => Some(Self::_1440P),
-            (2561..=3840, 1441..=2160) => Some(Self::_4K),
-            _ => None,
+        let find = |w: i32, h: i32| {
+            RESOLUTIONS
+                .iter()
+                .find(|(_, ws, hs)| ws.contains(&w) && hs.contains(&h))
+                .map(|(res, ..)| *res)
         };
-        let res = res1.or_else(|| match (height, width) {
-            (0..=256, 0..=144) => Some(Self::_144P),
-            (257..=426, 145..=240) => Some(Self::_240P),
-            (427..=640, 241..=360) => Some(Self::_360P),
-            (641..=768, 361..=480) => Some(Self::_480P),
-            (769..=1280, 481..=720) => Some(Self::_720P),
-            (1281..=1920, 721..=1080) => Some(Self::_1080P),
-            (1921..=2560, 1081..=1440) => Some(Self::_1440P),
-            (2561..=3840, 1441..=2160) => Some(Self::_4K),
-            _ => None,
-        });
+        let res = find(width, height).or_else(|| find(height, width));
 
         if res.is_none() {
             warn!(width, height, "failed to convert resolution");
         }
         res
     }
+
+    fn ranges(self) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+        let (_, ws, hs) = RESOLUTIONS
+            .iter()
+            .find(|(res, ..)| *res == self)
+            .expect("every resolution has a range");
+        (ws.clone(), hs.clone())
+    }
+}
+
+impl CodecType {
+    const ALL_KNOWN: [Self; 5] = [Self::H264, Self::H265, Self::Av1, Self::Vp8, Self::Vp9];
+
+    /// mediainfo 中 Format 字段（小写）的取值
+    fn formats(self) -> &'static [&'static str] {
+        match self {
+            Self::H264 => &["h264", "avc"],
+            Self::H265 => &["h265", "hevc"],
+            Self::Av1 => &["av1"],
+            Self::Vp8 => &["vp8"],
+            Self::Vp9 => &["vp9"],
+            Self::UNSUPPORTED => &[],
+        }
+    }
+
+    fn from_format(format: &str) -> Self {
+        let format = format.to_lowercase();
+        Self::ALL_KNOWN
+            .into_iter()
+            .find(|codec| codec.formats().contains(&format.as_str()))
+            .unwrap_or(Self::UNSUPPORTED)
+    }
+
+    /// 按 sys_files.video_info 中的 Format 过滤
+    fn sql_filter(self) -> SqlLiteral<Bool> {
+        let quote = |formats: &[&str]| {
+            formats
+                .iter()
+                .map(|f| format!("'{f}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let format = "lower(sys_files.video_info::jsonb ->> 'Format')";
+        let clause = match self {
+            Self::UNSUPPORTED => {
+                let known: Vec<_> = Self::ALL_KNOWN
+                    .iter()
+                    .flat_map(|codec| codec.formats())
+                    .copied()
+                    .collect();
+                format!("{format} NOT IN ({})", quote(&known))
+            }
+            codec => format!("{format} IN ({})", quote(codec.formats())),
+        };
+        sql(&clause)
+    }
 }
 
 #[ComplexObject]
@@ -165,34 +254,31 @@ impl FileData {
     }
 
     /// 视频文件通用信息
-    async fn general_info(&self) -> Result<Option<serde_json::Value>> {
-        self.general_info_inner().await
+    async fn general_info(&self, ctx: &Context<'_>) -> Result<Option<serde_json::Value>> {
+        let info = self.media_info(ctx).await?;
+        parse_info(info.general_info)
     }
 
     /// 视频信息
-    async fn video_info(&self) -> Result<Option<serde_json::Value>> {
-        self.video_info_inner().await
+    async fn video_info(&self, ctx: &Context<'_>) -> Result<Option<serde_json::Value>> {
+        let info = self.media_info(ctx).await?;
+        parse_info(info.video_info)
     }
 
     /// 音频信息
-    async fn audio_info(&self) -> Result<Option<serde_json::Value>> {
-        self.audio_info_inner().await
+    async fn audio_info(&self, ctx: &Context<'_>) -> Result<Option<serde_json::Value>> {
+        let info = self.media_info(ctx).await?;
+        parse_info(info.audio_info)
     }
 
     /// 视频编码类型
-    async fn codec_type(&self) -> Result<Option<CodecType>> {
-        Ok(self.codec_type_inner().await?)
+    async fn codec_type(&self, ctx: &Context<'_>) -> Result<Option<CodecType>> {
+        self.codec_type_inner(ctx).await
     }
 
     /// 视频分辨率
     async fn resolution(&self) -> Result<Option<ResolutionQl>> {
-        let conn = &mut pg_conn().await?;
-        let (height, width) = sys_files::table
-            .filter(sys_files::id.eq(self.id))
-            .select((sys_files::height, sys_files::width))
-            .first::<(Option<i32>, Option<i32>)>(conn)
-            .await?;
-        let (Some(height), Some(width)) = (height, width) else {
+        let (Some(height), Some(width)) = (self.height, self.width) else {
             return Ok(None);
         };
 
@@ -201,47 +287,34 @@ impl FileData {
     }
 
     /// 音频通道数
-    async fn channels(&self) -> Result<Option<Channels>> {
-        Ok(self.channels_inner().await?)
+    async fn channels(&self, ctx: &Context<'_>) -> Result<Option<Channels>> {
+        self.channels_inner(ctx).await
     }
-}
 
-impl FileData {
-    async fn general_info_inner(&self) -> Result<Option<serde_json::Value>> {
-        let mut conn = pg_conn().await?;
-        let info: Option<String> = sys_files::table
-            .filter(sys_files::id.eq(self.id))
-            .select(sys_files::general_info)
-            .first(&mut conn)
-            .await?;
-        let info = info.map(|info| serde_json::from_str(&info)).transpose()?;
-        Ok(info)
+    /// 转码结果的源文件，不是转码得到的文件或源文件已被清理时为空
+    async fn transcode_source(&self, ctx: &Context<'_>) -> Result<Option<FileData>> {
+        let Some(source) = self.transcode_from else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        Ok(loader.load_one(source).await?)
     }
+}
 
-    async fn video_info_inner(&self) -> Result<Option<serde_json::Value>> {
-        let mut conn = pg_conn().await?;
-        let info: Option<String> = sys_files::table
-            .filter(sys_files::id.eq(self.id))
-            .select(sys_files::video_info)
-            .first(&mut conn)
-            .await?;
-        let info = info.map(|info| serde_json::from_str(&info)).transpose()?;
-        Ok(info)
-    }
+fn parse_info(info: Option<String>) -> Result<Option<serde_json::Value>> {
+    let info = info.map(|info| serde_json::from_str(&info)).transpose()?;
+    Ok(info)
+}
 
-    async fn audio_info_inner(&self) -> Result<Option<serde_json::Value>> {
-        let mut conn = pg_conn().await?;
-        let info: Option<String> = sys_files::table
-            .filter(sys_files::id.eq(self.id))
-            .select(sys_files::audio_info)
-            .first(&mut conn)
-            .await?;
-        let info = info.map(|info| serde_json::from_str(&info)).transpose()?;
-        Ok(info)
+impl FileData {
+    async fn media_info(&self, ctx: &Context<'_>) -> Result<MediaInfo> {
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        let info = loader.load_one(MediaInfoKey(self.id)).await?;
+        Ok(info.unwrap_or_default())
     }
 
-    async fn channels_inner(&self) -> Result<Option<Channels>> {
-        let audio = self.audio_info_inner().await?;
+    async fn channels_inner(&self, ctx: &Context<'_>) -> Result<Option<Channels>> {
+        let audio = parse_info(self.media_info(ctx).await?.audio_info)?;
         let audio: Option<AudioInfo> = audio.map(|v| serde_json::from_value(v)).transpose()?;
         let channels = audio.and_then(|v| v.Channels).and_then(|v| match v {
             1 => Some(Channels::_1),
@@ -253,26 +326,18 @@ impl FileData {
         Ok(channels)
     }
 
-    async fn codec_type_inner(&self) -> Result<Option<CodecType>> {
+    async fn codec_type_inner(&self, ctx: &Context<'_>) -> Result<Option<CodecType>> {
         #[allow(non_snake_case)]
         #[derive(Deserialize, Debug)]
         struct VideoInfo {
             #[serde(default)]
             Format: Option<String>,
         }
-        let v_info = self.video_info_inner().await?;
+        let v_info = parse_info(self.media_info(ctx).await?.video_info)?;
         let v_info: Option<VideoInfo> = v_info.map(|v| serde_json::from_value(v)).transpose()?;
-        let codec_type =
-            v_info
-                .and_then(|v| v.Format)
-                .map(|format| match format.to_lowercase().as_str() {
-                    "h264" | "avc" => CodecType::H264,
-                    "h265" | "hevc" => CodecType::H265,
-                    "av1" => CodecType::Av1,
-                    "vp8" => CodecType::Vp8,
-                    "vp9" => CodecType::Vp9,
-                    _ => CodecType::UNSUPPORTED,
-                });
+        let codec_type = v_info
+            .and_then(|v| v.Format)
+            .map(|format| CodecType::from_format(&format));
 
         Ok(codec_type)
     }
@@ -281,8 +346,12 @@ impl FileData {
 #[ComplexObject]
 impl UserFile {
     /// 用户文件详细信息
-    async fn detail(&self) -> Result<Option<FileData>> {
-        Ok(self.detail_inner().await?)
+    async fn detail(&self, ctx: &Context<'_>) -> Result<Option<FileData>> {
+        let Some(sys_file_id) = self.sys_file_id else {
+            return Ok(None);
+        };
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        Ok(loader.load_one(sys_file_id).await?)
     }
 
     /// 视频文件是否完成前期解析和切片工作，用以判断是否可以开始对这个视频转码
@@ -294,18 +363,81 @@ impl UserFile {
         Ok(User::load(self.user_id).await?)
     }
 
-    async fn create_at(&self) -> Result<MillionTimestamp> {
-        Ok(self.create_at_inner().await?)
+    async fn create_at(&self) -> MillionTimestamp {
+        self.create_at.into()
+    }
+
+    async fn last_modified(&self) -> MillionTimestamp {
+        self.updated_at.into()
+    }
+
+    /// 关联到这个视频的字幕
+    async fn subtitles(&self) -> Result<Vec<Subtitle>> {
+        Ok(self.subtitles_inner().await?)
+    }
+
+    /// 文件所有者从这个视频转码得到的文件，按文件名排列
+    async fn transcoded_outputs(&self, ctx: &Context<'_>) -> Result<Vec<UserFile>> {
+        let Some(sys_file_id) = self.sys_file_id else {
+            return Ok(vec![]);
+        };
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        let outputs = loader
+            .load_one(TranscodedOutputsKey(self.user_id, sys_file_id))
+            .await?;
+        Ok(outputs.unwrap_or_default())
+    }
+
+    /// 文件的评论，按时间顺序排列
+    async fn comments(&self, page: Paginate) -> Result<CommentList> {
+        Ok(CommentList::load(self.id, page).await?)
+    }
+
+    /// 文件的标签，按名称排列
+    async fn tags(&self) -> Result<Vec<Tag>> {
+        Ok(Tag::load_attached(self.id).await?)
+    }
+
+    /// 文件所有者是否收藏了这个文件
+    async fn starred(&self, ctx: &Context<'_>) -> Result<bool> {
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        let starred = loader.load_one(StarredKey(self.user_id, self.id)).await?;
+        Ok(starred.unwrap_or_default())
     }
 
-    async fn last_modified(&self) -> Result<MillionTimestamp> {
-        Ok(self.last_modified_inner().await?)
+    /// 文件内容是否被病毒扫描隔离，隔离的文件不能下载
+    async fn quarantined(&self, ctx: &Context<'_>) -> Result<bool> {
+        let Some(sys_file_id) = self.sys_file_id else {
+            return Ok(false);
+        };
+        let loader = ctx.data_unchecked::<DbDataLoader>();
+        let quarantined = loader.load_one(QuarantinedKey(sys_file_id)).await?;
+        Ok(quarantined.unwrap_or_default())
+    }
+
+    /// 文件的访问记录，按时间倒序排列，只有文件所有者可以查看
+    async fn access_log(&self, ctx: &Context<'_>, page: Paginate) -> Result<FileAccessLogList> {
+        if ctx.data_opt::<UserId>() != Some(&self.user_id) {
+            return Err("permission denied".into());
+        }
+        Ok(FileAccessLogList::load(self, page).await?)
+    }
+
+    /// 当前用户对文件的访问级别，通过共享文件夹访问时为 Read 或 Write
+    async fn access_level(&self, ctx: &Context<'_>) -> Result<Option<AccessLevel>> {
+        let Some(user_id) = ctx.data_opt::<UserId>() else {
+            return Ok(None);
+        };
+        if *user_id == self.user_id {
+            return Ok(Some(AccessLevel::Owner));
+        }
+        Ok(share::access_level(*user_id, self.id).await?)
     }
 }
 
 impl UserFile {
     pub async fn find(id: UserFileId) -> anyhow::Result<Option<Self>> {
-        let mut conn = pg_conn().await?;
+        let mut conn = pg_conn_read().await?;
         let file = user_files::table
             .filter(user_files::id.eq(id))
             .select(UserFile::as_select())
@@ -313,46 +445,102 @@ impl UserFile {
             .await?;
         Ok(Some(file))
     }
+}
 
-    async fn detail_inner(&self) -> anyhow::Result<Option<FileData>> {
-        if let Some(sys_file_id) = self.sys_file_id {
-            let mut conn = pg_conn().await?;
-            let file = sys_files::table
-                .filter(sys_files::id.eq(sys_file_id))
-                .select(FileData::as_select())
-                .first::<FileData>(&mut conn)
-                .await?;
-            Ok(Some(file))
-        } else {
-            Ok(None)
-        }
-    }
+/// 文件的一次访问
+#[derive(SimpleObject)]
+pub struct FileAccessLog {
+    kind: AccessKind,
+    /// 访问者，未登录时为空
+    accessor_id: Option<UserId>,
+    /// 是否是文件所有者自己的访问
+    by_owner: bool,
+    accessed_at: MillionTimestamp,
+}
 
-    async fn create_at_inner(&self) -> Result<MillionTimestamp> {
-        let mut conn = pg_conn().await?;
+#[derive(SimpleObject, Default)]
+pub struct FileAccessLogList {
+    total: i64,
+    logs: Vec<FileAccessLog>,
+}
 
-        let create_at: LocalDataTime = user_files::table
-            .filter(user_files::id.eq(self.id))
-            .select(user_files::create_at)
-            .first(&mut conn)
+impl FileAccessLogList {
+    async fn load(file: &UserFile, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let total: i64 = file_access_logs::table
+            .filter(file_access_logs::user_file_id.eq(file.id))
+            .count()
+            .get_result(conn)
             .await?;
-        Ok(create_at.into())
+        let rows: Vec<(String, Option<UserId>, LocalDataTime)> = file_access_logs::table
+            .filter(file_access_logs::user_file_id.eq(file.id))
+            .select((
+                file_access_logs::kind,
+                file_access_logs::accessor_id,
+                file_access_logs::accessed_at,
+            ))
+            .order_by((
+                file_access_logs::accessed_at.desc(),
+                file_access_logs::id.desc(),
+            ))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        let logs = rows
+            .into_iter()
+            .filter_map(|(kind, accessor_id, accessed_at)| {
+                let Some(kind) = AccessKind::parse(&kind) else {
+                    warn!(kind, "unknown file access kind");
+                    return None;
+                };
+                Some(FileAccessLog {
+                    kind,
+                    accessor_id,
+                    by_owner: accessor_id == Some(file.user_id),
+                    accessed_at: accessed_at.into(),
+                })
+            })
+            .collect();
+        Ok(Self { total, logs })
     }
+}
 
-    async fn last_modified_inner(&self) -> Result<MillionTimestamp> {
-        let mut conn = pg_conn().await?;
+/// 视频关联的字幕
+#[derive(SimpleObject, Debug)]
+pub struct Subtitle {
+    /// 字幕文件
+    pub file: UserFile,
+    /// 字幕语言
+    pub language: Option<String>,
+}
 
-        let updated_at: LocalDataTime = user_files::table
-            .filter(user_files::id.eq(self.id))
-            .select(user_files::updated_at)
-            .first(&mut conn)
+impl UserFile {
+    async fn subtitles_inner(&self) -> anyhow::Result<Vec<Subtitle>> {
+        let mut conn = pg_conn_read().await?;
+        let subtitles = subtitles::table
+            .inner_join(user_files::table)
+            .filter(subtitles::video_file_id.eq(self.id))
+            .filter(user_files::deleted.eq(false))
+            .select((UserFile::as_select(), subtitles::language))
+            .load::<(UserFile, Option<String>)>(&mut conn)
             .await?;
-        Ok(updated_at.into())
+
+        let subtitles = subtitles
+            .into_iter()
+            .map(|(file, language)| Subtitle { file, language })
+            .collect();
+        Ok(subtitles)
     }
 }
 
 /// 文件夹节点
-#[derive(SimpleObject, Default)]
+#[derive(SimpleObject, Default, Serialize, Deserialize)]
 pub struct DirContent {
     total: u64,
     dirs: Vec<UserFile>,
@@ -360,27 +548,50 @@ pub struct DirContent {
 }
 
 impl DirContent {
+    /// 不带过滤条件时优先读取缓存。写入缓存的数据从主库读取，避免把副本中的旧数据以新的代数缓存下来
     pub async fn load(
         user_id: UserId,
         dir_id: UserFileId,
         page: Paginate,
+        filter: Option<FileFilter>,
+    ) -> anyhow::Result<Option<Self>> {
+        if filter.is_some() {
+            let conn = pg_conn_read().await?;
+            return Self::load_from_db(user_id, dir_id, page, filter, conn).await;
+        }
+        let (page_no, page_size) = (page.page, page.page_size);
+        dir_cache::get_or_load(user_id, dir_id, page_no, page_size, || async move {
+            let conn = pg_conn().await?;
+            Self::load_from_db(user_id, dir_id, page, None, conn).await
+        })
+        .await
+    }
+
+    async fn load_from_db(
+        user_id: UserId,
+        dir_id: UserFileId,
+        page: Paginate,
+        filter: Option<FileFilter>,
+        mut conn: PgConn,
     ) -> anyhow::Result<Option<Self>> {
-        let mut conn = pg_conn().await?;
         let Some(offset) = page.cursor() else {
             return Ok(Default::default());
         };
-        let total: i64 = user_files::table
-            .filter(user_files::user_id.eq(user_id))
-            .filter(user_files::parent_id.eq(dir_id))
-            .filter(user_files::deleted.eq(false))
-            .count()
-            .get_result(&mut conn)
-            .await?;
+        let filter = filter.as_ref();
+        let children = || {
+            let query = user_files::table
+                .filter(user_files::user_id.eq(user_id))
+                .filter(user_files::parent_id.eq(dir_id))
+                .filter(user_files::deleted.eq(false))
+                .into_boxed();
+            match filter {
+                Some(filter) => filter.apply(user_id, query),
+                None => query,
+            }
+        };
+        let total: i64 = children().count().get_result(&mut conn).await?;
 
-        let mut dir_or_files: Vec<UserFile> = user_files::table
-            .filter(user_files::user_id.eq(user_id))
-            .filter(user_files::parent_id.eq(dir_id))
-            .filter(user_files::deleted.eq(false))
+        let mut dir_or_files: Vec<UserFile> = children()
             .select(UserFile::as_select())
             .limit(page.page_size as i64)
             .offset(offset as i64)
@@ -407,3 +618,386 @@ impl DirContent {
         self.files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
     }
 }
+
+/// 游标分页的文件夹内容，目录在前
+#[derive(SimpleObject, Default)]
+pub struct DirPage {
+    dirs: Vec<UserFile>,
+    files: Vec<UserFile>,
+    /// 下一页的游标，为空时没有更多数据
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DirCursor {
+    order: KeysetOrder,
+    is_dir: bool,
+    id: UserFileId,
+    file_name: String,
+}
+
+impl DirPage {
+    /// 不统计总数，也不经过缓存
+    pub async fn load(
+        user_id: UserId,
+        dir_id: UserFileId,
+        order: KeysetOrder,
+        page: KeysetPaginate,
+        filter: Option<FileFilter>,
+    ) -> anyhow::Result<Self> {
+        let mut query = user_files::table
+            .filter(user_files::user_id.eq(user_id))
+            .filter(user_files::parent_id.eq(dir_id))
+            .filter(user_files::deleted.eq(false))
+            .into_boxed();
+        if let Some(filter) = &filter {
+            query = filter.apply(user_id, query);
+        }
+
+        if let Some(cursor) = &page.after {
+            let last: DirCursor = decode_cursor(cursor)?;
+            anyhow::ensure!(last.order == order, "cursor does not match the sort order");
+            let DirCursor {
+                is_dir,
+                id,
+                file_name,
+                ..
+            } = last;
+            // 目录在前，同类型的按排序键
+            query = match order {
+                KeysetOrder::Id => query.filter(
+                    user_files::is_dir
+                        .lt(is_dir)
+                        .or(user_files::is_dir.eq(is_dir).and(user_files::id.gt(id))),
+                ),
+                KeysetOrder::Name => query.filter(
+                    user_files::is_dir
+                        .lt(is_dir)
+                        .or(user_files::is_dir.eq(is_dir).and(
+                            user_files::file_name
+                                .gt(file_name.clone())
+                                .or(user_files::file_name
+                                    .eq(file_name)
+                                    .and(user_files::id.gt(id))),
+                        )),
+                ),
+            };
+        }
+        query = match order {
+            KeysetOrder::Id => query.order_by((user_files::is_dir.desc(), user_files::id.asc())),
+            KeysetOrder::Name => query.order_by((
+                user_files::is_dir.desc(),
+                user_files::file_name.asc(),
+                user_files::id.asc(),
+            )),
+        };
+
+        let conn = &mut pg_conn_read().await?;
+        let page_size = page.page_size as usize;
+        let mut dir_or_files: Vec<UserFile> = query
+            .select(UserFile::as_select())
+            .limit(page_size as i64)
+            .load(conn)
+            .await?;
+
+        let next_cursor = match dir_or_files.last() {
+            Some(last) if dir_or_files.len() == page_size => Some(encode_cursor(&DirCursor {
+                order,
+                is_dir: last.is_dir,
+                id: last.id,
+                file_name: last.file_name.clone(),
+            })?),
+            _ => None,
+        };
+
+        let first_file_idx = dir_or_files.iter().position(|f| !f.is_dir);
+        let files: Vec<_> = dir_or_files
+            .drain(first_file_idx.unwrap_or(dir_or_files.len())..)
+            .collect();
+        Ok(Self {
+            dirs: dir_or_files,
+            files,
+            next_cursor,
+        })
+    }
+}
+
+/// 文件标签
+#[derive(SimpleObject, Debug, Queryable, Selectable)]
+#[diesel(table_name = tags)]
+pub struct Tag {
+    pub id: TagId,
+    /// 标签名
+    pub name: String,
+}
+
+impl Tag {
+    /// 用户的所有标签，按名称排列
+    pub async fn load_all(user_id: UserId) -> anyhow::Result<Vec<Self>> {
+        let conn = &mut pg_conn_read().await?;
+        let tags = tags::table
+            .filter(tags::user_id.eq(user_id))
+            .select(Tag::as_select())
+            .order_by(tags::name.asc())
+            .load(conn)
+            .await?;
+        Ok(tags)
+    }
+
+    async fn load_attached(file_id: UserFileId) -> anyhow::Result<Vec<Self>> {
+        let conn = &mut pg_conn_read().await?;
+        let tags = tags::table
+            .inner_join(file_tags::table)
+            .filter(file_tags::user_file_id.eq(file_id))
+            .select(Tag::as_select())
+            .order_by(tags::name.asc())
+            .load(conn)
+            .await?;
+        Ok(tags)
+    }
+}
+
+/// 其他用户分享给当前用户的文件夹
+#[derive(SimpleObject)]
+pub struct SharedFolder {
+    dir: UserFile,
+    access_level: AccessLevel,
+}
+
+impl SharedFolder {
+    /// 最近分享的在前，已删除的文件夹不再列出
+    pub async fn load_all(member_id: UserId) -> anyhow::Result<Vec<Self>> {
+        let conn = &mut pg_conn_read().await?;
+        let rows: Vec<(UserFile, i16)> = folder_shares::table
+            .inner_join(user_files::table.on(user_files::id.eq(folder_shares::dir_id)))
+            .filter(folder_shares::member_id.eq(member_id))
+            .filter(user_files::deleted.eq(false))
+            .select((UserFile::as_select(), folder_shares::permission))
+            .order_by(folder_shares::create_at.desc())
+            .load(conn)
+            .await?;
+        rows.into_iter()
+            .map(|(dir, permission)| {
+                let permission = SharePermission::try_from(permission)?;
+                Ok(Self {
+                    dir,
+                    access_level: permission.into(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// 文件过滤条件，为空的条件不参与过滤，多个条件需要同时满足。
+/// 除标签外的条件只会匹配到视频文件
+#[derive(InputObject, Default)]
+pub struct FileFilter {
+    /// 带有该标签
+    tag: Option<String>,
+    /// 视频编码
+    codec: Option<CodecType>,
+    /// 分辨率
+    resolution: Option<ResolutionQl>,
+    /// 最短时长（毫秒）
+    min_duration_ms: Option<i32>,
+    /// 最长时长（毫秒）
+    max_duration_ms: Option<i32>,
+    /// 是否是 HDR 视频
+    hdr: Option<bool>,
+    /// 是否有转码成功的任务
+    transcoded: Option<bool>,
+}
+
+/// transcode_tasks.status 中表示转码成功的值
+const TASK_STATUS_OK: i16 = 1;
+
+impl FileFilter {
+    fn has_video_filter(&self) -> bool {
+        self.codec.is_some()
+            || self.resolution.is_some()
+            || self.min_duration_ms.is_some()
+            || self.max_duration_ms.is_some()
+            || self.hdr.is_some()
+    }
+
+    fn apply<'a>(
+        &'a self,
+        user_id: UserId,
+        mut query: user_files::BoxedQuery<'a, Pg>,
+    ) -> user_files::BoxedQuery<'a, Pg> {
+        if let Some(tag) = &self.tag {
+            let tag_ids = tags::table
+                .filter(tags::user_id.eq(user_id))
+                .filter(tags::name.eq(tag.trim()))
+                .select(tags::id);
+            let file_ids = file_tags::table
+                .filter(file_tags::tag_id.eq_any(tag_ids))
+                .select(file_tags::user_file_id);
+            query = query.filter(user_files::id.eq_any(file_ids));
+        }
+
+        if self.has_video_filter() {
+            let mut videos = sys_files::table
+                .filter(sys_files::is_video.eq(true))
+                .select(sys_files::id.nullable())
+                .into_boxed();
+            if let Some(codec) = self.codec {
+                videos = videos.filter(codec.sql_filter());
+            }
+            if let Some(resolution) = self.resolution {
+                let (ws, hs) = resolution.ranges();
+                let landscape = sys_files::width
+                    .between(*ws.start(), *ws.end())
+                    .and(sys_files::height.between(*hs.start(), *hs.end()));
+                let portrait = sys_files::height
+                    .between(*ws.start(), *ws.end())
+                    .and(sys_files::width.between(*hs.start(), *hs.end()));
+                videos = videos.filter(landscape.or(portrait));
+            }
+            if let Some(min) = self.min_duration_ms {
+                videos = videos.filter(sys_files::duration_ms.ge(min));
+            }
+            if let Some(max) = self.max_duration_ms {
+                videos = videos.filter(sys_files::duration_ms.le(max));
+            }
+            if let Some(hdr) = self.hdr {
+                let is_null = if hdr { "IS NOT NULL" } else { "IS NULL" };
+                videos = videos.filter(sql::<Bool>(&format!(
+                    "(sys_files.video_info::jsonb ->> 'HDR_Format') {is_null}"
+                )));
+            }
+            query = query.filter(user_files::sys_file_id.eq_any(videos));
+        }
+
+        if let Some(transcoded) = self.transcoded {
+            let done = transcode_tasks::table
+                .filter(transcode_tasks::status.eq(TASK_STATUS_OK))
+                .select(transcode_tasks::user_file_id);
+            query = if transcoded {
+                query.filter(user_files::id.eq_any(done))
+            } else {
+                query.filter(not(user_files::id.eq_any(done)))
+            };
+        }
+
+        query
+    }
+}
+
+/// 按条件搜索的文件列表
+#[derive(SimpleObject, Default)]
+pub struct FileList {
+    total: i64,
+    files: Vec<UserFile>,
+}
+
+impl FileList {
+    /// 在用户的所有文件（不含目录）中搜索，最近修改的在前
+    pub async fn search(
+        user_id: UserId,
+        filter: FileFilter,
+        page: Paginate,
+    ) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+        let filter = &filter;
+        let files = || {
+            let query = user_files::table
+                .filter(user_files::user_id.eq(user_id))
+                .filter(user_files::is_dir.eq(false))
+                .filter(user_files::deleted.eq(false))
+                .into_boxed();
+            filter.apply(user_id, query)
+        };
+
+        let total: i64 = files().count().get_result(conn).await?;
+        let files = files()
+            .select(UserFile::as_select())
+            .order_by((user_files::updated_at.desc(), user_files::id.desc()))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        Ok(Self { total, files })
+    }
+
+    /// 用户收藏的文件，最近收藏的在前
+    pub async fn starred(user_id: UserId, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+        let starred = || {
+            starred_files::table
+                .inner_join(user_files::table.on(user_files::id.eq(starred_files::user_file_id)))
+                .filter(starred_files::user_id.eq(user_id))
+                .filter(user_files::deleted.eq(false))
+        };
+
+        let total: i64 = starred().count().get_result(conn).await?;
+        let files = starred()
+            .select(UserFile::as_select())
+            .order_by((starred_files::create_at.desc(), user_files::id.desc()))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        Ok(Self { total, files })
+    }
+
+    /// 用户最近访问（下载、预览、作为转码源）的文件，最近访问的在前。
+    /// 访问记录定期写入数据库，刚发生的访问可能稍后才会出现
+    pub async fn recent(user_id: UserId, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+        let accessed = || {
+            file_accesses::table
+                .inner_join(user_files::table.on(user_files::id.eq(file_accesses::user_file_id)))
+                .filter(file_accesses::user_id.eq(user_id))
+                .filter(user_files::user_id.eq(user_id))
+                .filter(user_files::deleted.eq(false))
+        };
+
+        let total: i64 = accessed().count().get_result(conn).await?;
+        let files = accessed()
+            .select(UserFile::as_select())
+            .order_by((file_accesses::accessed_at.desc(), user_files::id.desc()))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        Ok(Self { total, files })
+    }
+}
+
+/// 视频信息补全进度
+#[derive(SimpleObject, Debug)]
+pub struct VideoInfoBackfill {
+    /// 是否正在运行
+    pub running: bool,
+    /// 开始时未解析的文件总数
+    pub total: i64,
+    /// 已发送解析请求的文件数
+    pub submitted: i64,
+    /// 发送解析请求失败的文件数
+    pub failed: i64,
+}
+
+impl VideoInfoBackfill {
+    pub fn load() -> Self {
+        let progress = video_info::backfill_progress();
+        Self {
+            running: progress.running,
+            total: progress.total,
+            submitted: progress.submitted,
+            failed: progress.failed,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use async_graphql::SimpleObject;
+use diesel::{
+    prelude::Queryable, ExpressionMethods, JoinOnDsl, QueryDsl, Selectable, SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use utils::db_pools::postgres::pg_conn_read;
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{
+        file_system::file::UserFileId,
+        gallery::{PublicationId, ReportId, ReportState},
+        user::user::UserId,
+    },
+    schema::{publication_reports, publications},
+};
+
+use super::{MillionTimestamp, Paginate};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = publication_reports)]
+struct ReportRow {
+    id: ReportId,
+    publication_id: PublicationId,
+    reporter_id: Option<UserId>,
+    reason: String,
+    state: i16,
+    create_at: MillionTimestamp,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = publications)]
+struct PublicationRow {
+    slug: String,
+    title: String,
+    user_id: UserId,
+    user_file_id: UserFileId,
+}
+
+/// 公开视频的举报
+#[derive(SimpleObject, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoReport {
+    id: ReportId,
+    publication_id: PublicationId,
+    slug: String,
+    title: String,
+    /// 发布视频的用户
+    owner_id: UserId,
+    file_id: UserFileId,
+    /// 未登录的访客举报时为空
+    reporter_id: Option<UserId>,
+    reason: String,
+    state: ReportState,
+    create_at: MillionTimestamp,
+}
+
+impl VideoReport {
+    fn try_from_row((report, publication): (ReportRow, PublicationRow)) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: report.id,
+            publication_id: report.publication_id,
+            slug: publication.slug,
+            title: publication.title,
+            owner_id: publication.user_id,
+            file_id: publication.user_file_id,
+            reporter_id: report.reporter_id,
+            reason: report.reason,
+            state: report.state.try_into()?,
+            create_at: report.create_at,
+        })
+    }
+}
+
+#[derive(SimpleObject, Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoReportList {
+    total: i64,
+    reports: Vec<VideoReport>,
+}
+
+impl VideoReportList {
+    /// 按举报时间顺序加载指定状态的举报，先举报的先处理
+    pub async fn load(state: ReportState, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let total: i64 = publication_reports::table
+            .filter(publication_reports::state.eq(state as i16))
+            .count()
+            .get_result(conn)
+            .await?;
+        let rows: Vec<(ReportRow, PublicationRow)> = publication_reports::table
+            .inner_join(
+                publications::table.on(publications::id.eq(publication_reports::publication_id)),
+            )
+            .filter(publication_reports::state.eq(state as i16))
+            .select((ReportRow::as_select(), PublicationRow::as_select()))
+            .order_by((
+                publication_reports::create_at.asc(),
+                publication_reports::id.asc(),
+            ))
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        let reports = rows
+            .into_iter()
+            .map(VideoReport::try_from_row)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { total, reports })
+    }
+}
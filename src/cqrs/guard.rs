@@ -0,0 +1,52 @@
+//! 管理端权限
+//!
+//! /admin/query 会把员工的 EmployeeId 和 Role 放进上下文。联系方式默认脱敏，查看原文，
+//! 或按邮箱、手机号过滤和排序时检查角色。
+//! 用户通过 /api/query 查询自己的数据时上下文中只有 UserId，不受这些 guard 限制
+
+use async_graphql::{Context, Guard, Result};
+
+use crate::{
+    application::audit::{self, AuditAction},
+    domain::user::{
+        employee::{EmployeeId, Role},
+        user::UserId,
+    },
+};
+
+/// 可以查看未脱敏联系方式的最低角色
+pub const UNMASK_ROLE: Role = Role::Manager;
+
+/// 要求员工的角色不低于 min
+pub struct RoleGuard {
+    min: Role,
+}
+
+impl RoleGuard {
+    pub fn new(min: Role) -> Self {
+        Self { min }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for RoleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        match ctx.data_opt::<Role>() {
+            Some(role) if *role >= self.min => Ok(()),
+            Some(_) => Err("permission denied".into()),
+            None if ctx.data_opt::<UserId>().is_some() => Ok(()),
+            None => Err("not logged in".into()),
+        }
+    }
+}
+
+/// 管理端查询用户时是否需要脱敏。请求查看原文时检查角色，并记录查看了哪些用户
+pub async fn should_mask(ctx: &Context<'_>, unmask: bool, user_ids: &[UserId]) -> Result<bool> {
+    if !unmask {
+        return Ok(true);
+    }
+    RoleGuard::new(UNMASK_ROLE).check(ctx).await?;
+    let employee_id = *ctx.data::<EmployeeId>()?;
+    audit::record(employee_id, AuditAction::UnmaskUser, &user_ids).await?;
+    Ok(false)
+}
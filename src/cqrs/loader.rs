@@ -0,0 +1,204 @@
+//! 批量加载
+//!
+//! 列表中每一行的字段单独查询时，一页数据会产生大量查询。同一轮解析中对相同字段的请求
+//! 由 DataLoader 合并成一次 `id = ANY(..)` 查询
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn_read;
+
+use crate::{
+    domain::{
+        file_system::file::{SysFileId, UserFileId},
+        user::user::UserId,
+    },
+    schema::{file_quarantines, starred_files, sys_files, user_files},
+};
+
+use super::file_system::{FileData, UserFile};
+
+pub type DbDataLoader = DataLoader<DbLoader>;
+
+pub fn data_loader() -> DbDataLoader {
+    DataLoader::new(DbLoader, tokio::spawn)
+}
+
+pub struct DbLoader;
+
+type LoadResult<K, V> = Result<HashMap<K, V>, Arc<anyhow::Error>>;
+
+#[async_trait::async_trait]
+impl Loader<SysFileId> for DbLoader {
+    type Value = FileData;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[SysFileId]) -> LoadResult<SysFileId, FileData> {
+        let load = async {
+            let conn = &mut pg_conn_read().await?;
+            let files: Vec<FileData> = sys_files::table
+                .filter(sys_files::id.eq_any(keys))
+                .select(FileData::as_select())
+                .load(conn)
+                .await?;
+            anyhow::Ok(files.into_iter().map(|f| (f.id, f)).collect())
+        };
+        load.await.map_err(Arc::new)
+    }
+}
+
+/// 系统文件的 mediainfo 解析结果，内容较大，只在需要时加载
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MediaInfoKey(pub SysFileId);
+
+#[derive(Clone, Default)]
+pub struct MediaInfo {
+    pub general_info: Option<String>,
+    pub video_info: Option<String>,
+    pub audio_info: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl Loader<MediaInfoKey> for DbLoader {
+    type Value = MediaInfo;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[MediaInfoKey]) -> LoadResult<MediaInfoKey, MediaInfo> {
+        let ids: Vec<_> = keys.iter().map(|k| k.0).collect();
+        let load = async {
+            let conn = &mut pg_conn_read().await?;
+            let rows: Vec<(SysFileId, Option<String>, Option<String>, Option<String>)> =
+                sys_files::table
+                    .filter(sys_files::id.eq_any(&ids))
+                    .select((
+                        sys_files::id,
+                        sys_files::general_info,
+                        sys_files::video_info,
+                        sys_files::audio_info,
+                    ))
+                    .load(conn)
+                    .await?;
+            let infos = rows
+                .into_iter()
+                .map(|(id, general_info, video_info, audio_info)| {
+                    let info = MediaInfo {
+                        general_info,
+                        video_info,
+                        audio_info,
+                    };
+                    (MediaInfoKey(id), info)
+                })
+                .collect();
+            anyhow::Ok(infos)
+        };
+        load.await.map_err(Arc::new)
+    }
+}
+
+/// 用户是否收藏了自己的文件
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StarredKey(pub UserId, pub UserFileId);
+
+#[async_trait::async_trait]
+impl Loader<StarredKey> for DbLoader {
+    type Value = bool;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[StarredKey]) -> LoadResult<StarredKey, bool> {
+        let file_ids: Vec<_> = keys.iter().map(|k| k.1).collect();
+        let load = async {
+            let conn = &mut pg_conn_read().await?;
+            let starred: HashSet<(UserId, UserFileId)> = starred_files::table
+                .filter(starred_files::user_file_id.eq_any(&file_ids))
+                .select((starred_files::user_id, starred_files::user_file_id))
+                .load::<(UserId, UserFileId)>(conn)
+                .await?
+                .into_iter()
+                .collect();
+            let starred = keys
+                .iter()
+                .map(|k| (*k, starred.contains(&(k.0, k.1))))
+                .collect();
+            anyhow::Ok(starred)
+        };
+        load.await.map_err(Arc::new)
+    }
+}
+
+/// 用户从某个系统文件转码得到的文件
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TranscodedOutputsKey(pub UserId, pub SysFileId);
+
+#[async_trait::async_trait]
+impl Loader<TranscodedOutputsKey> for DbLoader {
+    type Value = Vec<UserFile>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(
+        &self,
+        keys: &[TranscodedOutputsKey],
+    ) -> LoadResult<TranscodedOutputsKey, Vec<UserFile>> {
+        let user_ids: Vec<_> = keys.iter().map(|k| k.0).collect();
+        let source_ids: Vec<_> = keys.iter().map(|k| k.1).collect();
+        let load = async {
+            let conn = &mut pg_conn_read().await?;
+            let rows: Vec<(Option<SysFileId>, UserFile)> = user_files::table
+                .inner_join(sys_files::table)
+                .filter(sys_files::transcode_from.eq_any(&source_ids))
+                .filter(user_files::user_id.eq_any(&user_ids))
+                .filter(user_files::deleted.eq(false))
+                .select((sys_files::transcode_from, UserFile::as_select()))
+                .order_by(user_files::file_name.asc())
+                .load(conn)
+                .await?;
+
+            let mut outputs: HashMap<TranscodedOutputsKey, Vec<UserFile>> = HashMap::new();
+            for (source, file) in rows {
+                let Some(source) = source else { continue };
+                let key = TranscodedOutputsKey(file.user_id, source);
+                // 批量查询时不同用户的 id 交叉匹配，只保留请求的组合
+                if keys.contains(&key) {
+                    outputs.entry(key).or_default().push(file);
+                }
+            }
+            anyhow::Ok(outputs)
+        };
+        load.await.map_err(Arc::new)
+    }
+}
+
+/// 系统文件的内容是否被隔离
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QuarantinedKey(pub SysFileId);
+
+#[async_trait::async_trait]
+impl Loader<QuarantinedKey> for DbLoader {
+    type Value = bool;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[QuarantinedKey]) -> LoadResult<QuarantinedKey, bool> {
+        let ids: Vec<_> = keys.iter().map(|k| k.0).collect();
+        let load = async {
+            let conn = &mut pg_conn_read().await?;
+            let quarantined: HashSet<SysFileId> = sys_files::table
+                .inner_join(file_quarantines::table.on(file_quarantines::hash.eq(sys_files::hash)))
+                .filter(sys_files::id.eq_any(&ids))
+                .select(sys_files::id)
+                .load::<SysFileId>(conn)
+                .await?
+                .into_iter()
+                .collect();
+            let quarantined = keys
+                .iter()
+                .map(|k| (*k, quarantined.contains(&k.0)))
+                .collect();
+            anyhow::Ok(quarantined)
+        };
+        load.await.map_err(Arc::new)
+    }
+}
@@ -1,20 +1,35 @@
 use ::diesel::{deserialize::FromSqlRow, expression::AsExpression};
 use actix_identity::Identity;
-use actix_web::{web, HttpResponse};
+use actix_session::SessionExt;
+use actix_web::{web, HttpRequest, HttpResponse};
 use async_graphql::{
-    http::GraphiQLSource, scalar, Context, EmptyMutation, EmptySubscription, InputObject, Object,
-    Schema,
+    http::GraphiQLSource, scalar, Context, EmptyMutation, EmptySubscription, Enum, Guard,
+    InputObject, MergedObject, Object, Schema,
 };
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+pub mod activity;
+pub mod comment;
+pub mod credits;
+pub mod file_change;
 pub mod file_system;
+pub mod gallery;
+pub mod guard;
+pub(crate) mod loader;
+pub mod notification;
+pub mod profile;
+pub mod referral;
 pub mod transcode;
 pub(crate) mod user;
 
 pub fn actix_config(cfg: &mut web::ServiceConfig) {
-    let schema = Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish();
-    let schema_dev = Schema::build(AdminQueryRoot, EmptyMutation, EmptySubscription).finish();
+    let schema = Schema::build(QueryRoot, MutationRoot::default(), EmptySubscription)
+        .data(loader::data_loader())
+        .finish();
+    let schema_dev = Schema::build(AdminQueryRoot, EmptyMutation, EmptySubscription)
+        .data(loader::data_loader())
+        .finish();
     cfg.app_data(actix_web::web::Data::new(schema))
         .app_data(actix_web::web::Data::new(schema_dev))
         .service(
@@ -29,7 +44,7 @@ pub fn actix_config(cfg: &mut web::ServiceConfig) {
         );
 }
 
-pub type Av1Schema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+pub type Av1Schema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
 pub struct QueryRoot;
 
@@ -48,6 +63,17 @@ impl QueryRoot {
     }
 }
 
+/// 用户端的修改操作，调用应用层的业务逻辑
+#[derive(MergedObject, Default)]
+pub struct MutationRoot(ProfileMutation);
+
+/// 业务错误转换为 GraphQL 错误，extensions 中的 code 与 REST 接口返回的 status 相同
+pub(crate) fn biz_err(err: impl Into<ApiError>) -> async_graphql::Error {
+    let err: ApiError = err.into();
+    let code = err.code();
+    async_graphql::Error::new(err.message()).extend_with(|_, ext| ext.set("code", code))
+}
+
 pub trait UserIdCtxExt {
     fn user_id_unchecked(&self) -> UserId;
 }
@@ -68,16 +94,67 @@ impl AdminQueryRoot {
         "pong"
     }
 
-    /// 获取用户
-    async fn user(&self, id: String) -> async_graphql::Result<User> {
-        let id = id.parse()?;
-        let id = User::load(id).await?;
-        Ok(id)
+    /// 获取用户，联系方式默认脱敏，unmask 为 true 时返回原文并记录审计日志
+    async fn user(
+        &self,
+        ctx: &Context<'_>,
+        id: UserId,
+        #[graphql(default)] unmask: bool,
+    ) -> async_graphql::Result<User> {
+        let mut user = User::load(id).await?;
+        if guard::should_mask(ctx, unmask, &[id]).await? {
+            user.mask_contact();
+        }
+        Ok(user)
+    }
+
+    /// 获取用户列表，联系方式默认脱敏，unmask 为 true 时返回原文并记录审计日志。
+    /// 按邮箱或手机号过滤、排序需要经理及以上的角色
+    async fn user_list(
+        &self,
+        ctx: &Context<'_>,
+        params: UserSearchParams,
+        #[graphql(default)] unmask: bool,
+    ) -> async_graphql::Result<UserList> {
+        if params.by_contact() {
+            RoleGuard::new(guard::UNMASK_ROLE).check(ctx).await?;
+        }
+        let mut list = User::list(params).await?;
+        if guard::should_mask(ctx, unmask, &list.user_ids()).await? {
+            list.mask_contact();
+        }
+        Ok(list)
     }
 
-    /// 获取用户列表
-    async fn user_list(&self, params: UserSearchParams) -> async_graphql::Result<UserList> {
-        Ok(User::list(params).await?)
+    /// 按游标分页获取用户列表，用户很多时代替 userList。联系方式默认脱敏，
+    /// unmask 为 true 时返回原文并记录审计日志。按邮箱或手机号过滤需要经理及以上的角色
+    async fn user_page(
+        &self,
+        ctx: &Context<'_>,
+        params: UserKeysetParams,
+        #[graphql(default)] unmask: bool,
+    ) -> async_graphql::Result<UserPage> {
+        if params.by_contact() {
+            RoleGuard::new(guard::UNMASK_ROLE).check(ctx).await?;
+        }
+        let mut page = User::list_after(params).await?;
+        if guard::should_mask(ctx, unmask, &page.user_ids()).await? {
+            page.mask_contact();
+        }
+        Ok(page)
+    }
+
+    /// 视频信息补全进度
+    async fn video_info_backfill(&self) -> VideoInfoBackfill {
+        VideoInfoBackfill::load()
+    }
+
+    /// 邀请注册的统计，top 为排行榜返回的用户数
+    async fn referral_stats(
+        &self,
+        #[graphql(default = 10)] top: i64,
+    ) -> async_graphql::Result<ReferralStats> {
+        Ok(ReferralStats::load(top).await?)
     }
 }
 
@@ -85,6 +162,7 @@ async fn index(
     schema: web::Data<Av1Schema>,
     req: GraphQLRequest,
     id: Option<Identity>,
+    http_req: HttpRequest,
 ) -> actix_web::Result<GraphQLResponse> {
     let mut req = req.into_inner();
     if id.is_none() {
@@ -97,7 +175,16 @@ async fn index(
         .map_err(|err| -> Box<dyn std::error::Error> { format!("{}", err).into() })?
         .parse()
         .map_err(|err| -> Box<dyn std::error::Error> { format!("{}", err).into() })?;
-    let req = req.data(UserId(id));
+    let mut user_id = UserId(id);
+    // 请求头指定了组织时，查询组织共享空间中的数据
+    if let Some(org_id) = http_req.headers().get(ORG_ID_HEADER) {
+        let org_id = org_id.to_str().unwrap_or_default().parse().map_err(ApiError::from)?;
+        user_id = organization::resolve_space(user_id, org_id)
+            .await
+            .map_err(ApiError::from)?
+            .map_err(ApiError::from)?;
+    }
+    let req = req.data(user_id);
     Ok(schema.execute(req).await.into())
 }
 
@@ -107,11 +194,23 @@ async fn playgroud() -> actix_web::Result<HttpResponse> {
         .body(GraphiQLSource::build().endpoint("/api/query").finish()))
 }
 
+/// 只有登录的员工可以调用，员工 id 和角色会放进上下文供 guard 使用
 async fn index_dev(
     schema: web::Data<AdminSchema>,
     req: GraphQLRequest,
+    id: Identity,
+    http_req: HttpRequest,
 ) -> actix_web::Result<GraphQLResponse> {
-    let req = req.into_inner();
+    let id: EmployeeId = id
+        .id()
+        .map_err(|err| -> Box<dyn std::error::Error> { format!("{}", err).into() })?
+        .parse()
+        .map_err(|err| -> Box<dyn std::error::Error> { format!("{}", err).into() })?;
+    let role = http_req
+        .get_session()
+        .get::<Role>("role")?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("employee role not found"))?;
+    let req = req.into_inner().data(id).data(role);
     Ok(schema.execute(req).await.into())
 }
 
@@ -123,15 +222,38 @@ async fn playgroud_dev() -> actix_web::Result<HttpResponse> {
 
 use derive_more::From;
 
+use crate::application::organization;
+use crate::domain::user::employee::{EmployeeId, Role};
 use crate::domain::user::user::UserId;
+use crate::http::ApiError;
+use crate::presentation::organization::ORG_ID_HEADER;
 
-use self::user::{User, UserList, UserSearchParams};
+use self::file_system::VideoInfoBackfill;
+use self::guard::RoleGuard;
+use self::profile::ProfileMutation;
+use self::referral::ReferralStats;
+use self::user::{User, UserKeysetParams, UserList, UserPage, UserSearchParams};
 
 #[derive(Deserialize, From, Debug, AsExpression, FromSqlRow)]
 #[diesel(sql_type = ::diesel::sql_types::Timestamptz)]
 pub struct MillionTimestamp(chrono::DateTime<chrono::Local>);
 scalar!(MillionTimestamp);
 
+impl<'__s> utoipa::ToSchema<'__s> for MillionTimestamp {
+    fn schema() -> (
+        &'__s str,
+        utoipa::openapi::RefOr<utoipa::openapi::schema::Schema>,
+    ) {
+        let schema = utoipa::openapi::ObjectBuilder::new()
+            .schema_type(utoipa::openapi::SchemaType::Integer)
+            .format(Some(utoipa::openapi::SchemaFormat::KnownFormat(
+                utoipa::openapi::KnownFormat::Int64,
+            )))
+            .description(Some("毫秒时间戳"));
+        ("MillionTimestamp", schema.into())
+    }
+}
+
 impl Serialize for MillionTimestamp {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
@@ -156,6 +278,36 @@ impl Paginate {
     }
 }
 
+/// 游标分页，数据量大时代替 Paginate，翻页的开销不随页数增长。
+/// 第一页不传 after，之后传入上一页返回的 nextCursor，nextCursor 为空时没有更多数据
+#[derive(Debug, InputObject)]
+pub struct KeysetPaginate {
+    /// 上一页返回的游标
+    pub after: Option<String>,
+    /// 每页大小
+    pub page_size: u32,
+}
+
+/// 游标分页的排序字段
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KeysetOrder {
+    /// 按 id 排序
+    Id,
+    /// 按名称排序，名称相同时按 id
+    Name,
+}
+
+/// 游标对客户端不透明，内容是序列化后再 hex 编码的最后一条记录的排序键
+pub(crate) fn encode_cursor<T: Serialize>(last: &T) -> anyhow::Result<String> {
+    Ok(hex::encode(serde_json::to_vec(last)?))
+}
+
+pub(crate) fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> anyhow::Result<T> {
+    let bytes = hex::decode(cursor).map_err(|_| anyhow::anyhow!("invalid cursor"))?;
+    serde_json::from_slice(&bytes).map_err(|_| anyhow::anyhow!("invalid cursor"))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Address(Vec<String>);
 scalar!(Address);
@@ -0,0 +1,180 @@
+use anyhow::bail;
+use async_graphql::{Enum, SimpleObject};
+use diesel::{
+    prelude::Queryable, ExpressionMethods, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use serde::Serialize;
+use utils::db_pools::postgres::{pg_conn, pg_conn_read};
+use utoipa::ToSchema;
+
+use crate::{
+    domain::{
+        file_system::file::UserFileId, notification::NotificationId,
+        transcode_order::TranscodeTaskId, user::user::UserId,
+    },
+    schema::notifications,
+};
+
+use super::{MillionTimestamp, Paginate};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = notifications)]
+struct NotificationRow {
+    id: NotificationId,
+    kind: i16,
+    message: String,
+    file_id: Option<UserFileId>,
+    task_id: Option<TranscodeTaskId>,
+    read: bool,
+    create_at: MillionTimestamp,
+    seq: i64,
+}
+
+/// 用户通知
+#[derive(SimpleObject, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Notification {
+    id: NotificationId,
+    kind: NotificationKind,
+    message: String,
+    /// 相关的文件，转码通知中为源文件
+    file_id: Option<UserFileId>,
+    task_id: Option<TranscodeTaskId>,
+    /// 是否已读
+    read: bool,
+    create_at: MillionTimestamp,
+    /// 用户内递增的序号，用作推送的事件 id
+    #[serde(skip)]
+    #[graphql(skip)]
+    seq: i64,
+}
+
+#[derive(Enum, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationKind {
+    /// 上传完成
+    UploadFinished,
+    /// 转码成功
+    TranscodeSucceeded,
+    /// 转码失败
+    TranscodeFailed,
+    /// 文件收到评论
+    FileCommented,
+}
+
+impl Notification {
+    pub fn seq(&self) -> i64 {
+        self.seq
+    }
+
+    /// 按 seq 顺序加载 after 之后的通知。
+    /// 从库的延迟会让推送跳过或延后通知，从主库读取
+    pub async fn load_after(user_id: UserId, after: i64, limit: u32) -> anyhow::Result<Vec<Self>> {
+        let conn = &mut pg_conn().await?;
+        let rows: Vec<NotificationRow> = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::seq.gt(after))
+            .select(NotificationRow::as_select())
+            .order_by(notifications::seq.asc())
+            .limit(limit as i64)
+            .load(conn)
+            .await?;
+        rows.into_iter().map(Self::try_from).collect()
+    }
+
+    /// 用户最新一条通知的 seq，没有通知时为 0
+    pub async fn latest_seq(user_id: UserId) -> anyhow::Result<i64> {
+        let conn = &mut pg_conn().await?;
+        let seq = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .select(notifications::seq)
+            .order_by(notifications::seq.desc())
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(seq.unwrap_or(0))
+    }
+}
+
+impl TryFrom<NotificationRow> for Notification {
+    type Error = anyhow::Error;
+
+    fn try_from(row: NotificationRow) -> anyhow::Result<Self> {
+        let kind = match row.kind {
+            0 => NotificationKind::UploadFinished,
+            1 => NotificationKind::TranscodeSucceeded,
+            2 => NotificationKind::TranscodeFailed,
+            3 => NotificationKind::FileCommented,
+            kind => bail!("invalid notification kind: {}", kind),
+        };
+        Ok(Self {
+            id: row.id,
+            kind,
+            message: row.message,
+            file_id: row.file_id,
+            task_id: row.task_id,
+            read: row.read,
+            create_at: row.create_at,
+            seq: row.seq,
+        })
+    }
+}
+
+#[derive(SimpleObject, Serialize, Default, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationList {
+    total: i64,
+    notifications: Vec<Notification>,
+}
+
+impl NotificationList {
+    /// 按时间倒序加载通知，unread_only 为 true 时只加载未读通知
+    pub async fn load(user_id: UserId, page: Paginate, unread_only: bool) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let mut count = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .into_boxed();
+        let mut query = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .into_boxed();
+        if unread_only {
+            count = count.filter(notifications::read.eq(false));
+            query = query.filter(notifications::read.eq(false));
+        }
+
+        let total: i64 = count.count().get_result(conn).await?;
+        let rows: Vec<NotificationRow> = query
+            .select(NotificationRow::as_select())
+            .order_by(notifications::create_at.desc())
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        let notifications = rows
+            .into_iter()
+            .map(Notification::try_from)
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            total,
+            notifications,
+        })
+    }
+
+    pub async fn unread_count(user_id: UserId) -> anyhow::Result<i64> {
+        let conn = &mut pg_conn_read().await?;
+        let count = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read.eq(false))
+            .count()
+            .get_result(conn)
+            .await?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,85 @@
+use async_graphql::{Context, InputObject, Object, Result, Upload};
+use futures_util::TryStreamExt;
+use tokio_util::{compat::FuturesAsyncReadCompatExt, io::ReaderStream};
+
+use crate::application::user::{self, avatar, MobileNumber, UpdatePassword, UserUpdateDto};
+
+use super::{biz_err, user::User, UserIdCtxExt};
+
+/// 个人资料，只修改传入的字段
+#[derive(InputObject)]
+pub struct ProfileInput {
+    user_name: Option<String>,
+    /// 修改密码需要提供原密码
+    password: Option<PasswordInput>,
+    address: Option<Vec<String>>,
+    /// 绑定手机号需要提供短信验证码
+    mobile_number: Option<MobileNumberInput>,
+    /// 是否退订转码订单完成邮件
+    order_email_opt_out: Option<bool>,
+    /// 每个文件保留的历史版本数，最多 100
+    max_file_versions: Option<u32>,
+}
+
+#[derive(InputObject)]
+pub struct PasswordInput {
+    old_password: String,
+    new_password: String,
+}
+
+#[derive(InputObject)]
+pub struct MobileNumberInput {
+    tel: String,
+    sms_code: String,
+}
+
+impl From<ProfileInput> for UserUpdateDto {
+    fn from(input: ProfileInput) -> Self {
+        Self {
+            user_name: input.user_name,
+            password: input.password.map(|p| UpdatePassword {
+                old_password: p.old_password,
+                new_password: p.new_password,
+            }),
+            address: input.address,
+            mobile_number: input.mobile_number.map(|m| MobileNumber {
+                sms_code: m.sms_code,
+                tel: m.tel,
+            }),
+            order_email_opt_out: input.order_email_opt_out,
+            max_file_versions: input.max_file_versions,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ProfileMutation;
+
+#[Object]
+/// 当前用户修改自己的资料，与 REST 接口 /api/user/modify_info 相同
+impl ProfileMutation {
+    /// 修改个人资料，返回修改后的用户
+    async fn update_profile(&self, ctx: &Context<'_>, input: ProfileInput) -> Result<User> {
+        let user_id = ctx.user_id_unchecked();
+        user::update_profile(user_id, input.into())
+            .await?
+            .map_err(biz_err)?;
+        Ok(User::load(user_id).await?)
+    }
+
+    /// 上传头像，支持 jpeg、png 和 webp，最大 2MB，宽和高在 32 到 4096 像素之间
+    async fn upload_avatar(&self, ctx: &Context<'_>, file: Upload) -> Result<User> {
+        let user_id = ctx.user_id_unchecked();
+        let content = file.value(ctx)?.into_async_read().compat();
+        let data = Box::pin(ReaderStream::new(content).map_err(anyhow::Error::from));
+        avatar::upload(user_id, data).await?.map_err(biz_err)?;
+        Ok(User::load(user_id).await?)
+    }
+
+    /// 恢复默认头像
+    async fn remove_avatar(&self, ctx: &Context<'_>) -> Result<User> {
+        let user_id = ctx.user_id_unchecked();
+        avatar::remove(user_id).await?;
+        Ok(User::load(user_id).await?)
+    }
+}
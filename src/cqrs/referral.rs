@@ -0,0 +1,69 @@
+use async_graphql::SimpleObject;
+use diesel::{
+    dsl::{count_distinct, count_star, sql},
+    sql_types::BigInt,
+    ExpressionMethods, QueryDsl,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn_read;
+
+use crate::{domain::user::user::UserId, schema::referrals};
+
+/// 排行榜最多返回的用户数
+const MAX_TOP: i64 = 100;
+
+/// 邀请注册的统计
+#[derive(SimpleObject)]
+pub struct ReferralStats {
+    /// 通过邀请码注册的用户数
+    total_referrals: i64,
+    /// 至少邀请了一个用户的邀请人数
+    total_inviters: i64,
+    /// 发放的积分总数，包括邀请人和被邀请人
+    credits_granted: i64,
+    /// 发放的额外存储配额总数（字节），包括邀请人和被邀请人
+    quota_granted: i64,
+    /// 邀请人数最多的用户
+    top_inviters: Vec<InviterStat>,
+}
+
+#[derive(SimpleObject)]
+pub struct InviterStat {
+    user_id: UserId,
+    /// 邀请的人数
+    invited: i64,
+}
+
+impl ReferralStats {
+    pub async fn load(top: i64) -> anyhow::Result<Self> {
+        let conn = &mut pg_conn_read().await?;
+        let (total_referrals, total_inviters, credits_granted, quota_granted) = referrals::table
+            .select((
+                count_star(),
+                count_distinct(referrals::inviter_id),
+                sql::<BigInt>("COALESCE(SUM(inviter_credits + invitee_credits), 0)::BIGINT"),
+                sql::<BigInt>("COALESCE(SUM(bonus_quota * 2), 0)::BIGINT"),
+            ))
+            .get_result::<(i64, i64, i64, i64)>(conn)
+            .await?;
+
+        let top_inviters = referrals::table
+            .group_by(referrals::inviter_id)
+            .select((referrals::inviter_id, count_star()))
+            .order(count_star().desc())
+            .limit(top.clamp(0, MAX_TOP))
+            .load::<(UserId, i64)>(conn)
+            .await?
+            .into_iter()
+            .map(|(user_id, invited)| InviterStat { user_id, invited })
+            .collect();
+
+        Ok(Self {
+            total_referrals,
+            total_inviters,
+            credits_granted,
+            quota_granted,
+            top_inviters,
+        })
+    }
+}
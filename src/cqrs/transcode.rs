@@ -1,17 +1,119 @@
-use diesel::{ExpressionMethods, QueryDsl};
+use async_graphql::{ComplexObject, Enum, SimpleObject};
+use diesel::{
+    prelude::Queryable, ExpressionMethods, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
 use diesel_async::RunQueryDsl;
-use utils::db_pools::postgres::pg_conn;
+use utils::db_pools::postgres::pg_conn_read;
 
 use crate::{
-    domain::{transcode_order::TranscodeTaskId, user::user::UserId},
+    domain::{
+        file_system::file::UserFileId,
+        transcode_order::{metrics::bitrate_savings, TranscodeOrderId, TranscodeTaskId},
+        user::user::UserId,
+    },
     schema::transcode_tasks,
 };
 
-pub struct TranscodeTask {}
+use super::{MillionTimestamp, Paginate};
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = transcode_tasks)]
+struct TranscodeTaskRow {
+    id: TranscodeTaskId,
+    order_id: TranscodeOrderId,
+    user_file_id: UserFileId,
+    virtual_path: String,
+    status: i16,
+    err_msg: Option<String>,
+    create_at: MillionTimestamp,
+    updated_at: MillionTimestamp,
+    vmaf: Option<f64>,
+    psnr: Option<f64>,
+    source_bitrate: Option<i64>,
+    output_bitrate: Option<i64>,
+}
+
+/// 转码任务
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct TranscodeTask {
+    id: TranscodeTaskId,
+    order_id: TranscodeOrderId,
+    /// 源视频
+    source_file_id: UserFileId,
+    /// 创建任务时源视频的路径
+    virtual_path: String,
+    status: TaskStatus,
+    err_msg: Option<String>,
+    create_at: MillionTimestamp,
+    updated_at: MillionTimestamp,
+    /// 转码结果的 VMAF 分数，0 ~ 100
+    vmaf: Option<f64>,
+    /// 转码结果的 PSNR，单位 dB
+    psnr: Option<f64>,
+    /// 源视频的码率，bit/s
+    source_bitrate: Option<i64>,
+    /// 转码结果的码率，bit/s
+    output_bitrate: Option<i64>,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Processing,
+    Ok,
+    Failed,
+    Cancelled,
+    /// 等待预约时间到达
+    Scheduled,
+}
+
+impl From<TranscodeTaskRow> for TranscodeTask {
+    fn from(row: TranscodeTaskRow) -> Self {
+        let status = match row.status {
+            0 => TaskStatus::Processing,
+            1 => TaskStatus::Ok,
+            2 => TaskStatus::Failed,
+            4 => TaskStatus::Scheduled,
+            _ => TaskStatus::Cancelled,
+        };
+        Self {
+            id: row.id,
+            order_id: row.order_id,
+            source_file_id: row.user_file_id,
+            virtual_path: row.virtual_path,
+            status,
+            err_msg: row.err_msg,
+            create_at: row.create_at,
+            updated_at: row.updated_at,
+            vmaf: row.vmaf,
+            psnr: row.psnr,
+            source_bitrate: row.source_bitrate,
+            output_bitrate: row.output_bitrate,
+        }
+    }
+}
+
+#[ComplexObject]
+impl TranscodeTask {
+    /// 相比源视频节省的码率百分比，转码结果更大时为负数
+    async fn bitrate_savings(&self) -> Option<f64> {
+        bitrate_savings(
+            self.source_bitrate.map(|b| b as u64),
+            self.output_bitrate.map(|b| b as u64),
+        )
+    }
+}
+
+#[derive(SimpleObject, Default)]
+pub struct TranscodeTaskList {
+    total: i64,
+    tasks: Vec<TranscodeTask>,
+}
 
 impl TranscodeTask {
     pub async fn running_tasks(user_id: UserId) -> anyhow::Result<Vec<TranscodeTaskId>> {
-        let conn = &mut pg_conn().await?;
+        let conn = &mut pg_conn_read().await?;
         let task_ids = transcode_tasks::table
             .filter(transcode_tasks::user_id.eq(user_id))
             .filter(transcode_tasks::status.eq(0))
@@ -20,4 +122,45 @@ impl TranscodeTask {
             .await?;
         Ok(task_ids)
     }
+
+    pub async fn find(user_id: UserId, id: TranscodeTaskId) -> anyhow::Result<Option<Self>> {
+        let conn = &mut pg_conn_read().await?;
+        let row: Option<TranscodeTaskRow> = transcode_tasks::table
+            .find(id)
+            .filter(transcode_tasks::user_id.eq(user_id))
+            .select(TranscodeTaskRow::as_select())
+            .first(conn)
+            .await
+            .optional()?;
+        Ok(row.map(Into::into))
+    }
+}
+
+impl TranscodeTaskList {
+    /// 用户的转码任务，最近创建的在前
+    pub async fn load(user_id: UserId, page: Paginate) -> anyhow::Result<Self> {
+        let Some(offset) = page.cursor() else {
+            return Ok(Default::default());
+        };
+        let conn = &mut pg_conn_read().await?;
+
+        let total: i64 = transcode_tasks::table
+            .filter(transcode_tasks::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .await?;
+        let rows: Vec<TranscodeTaskRow> = transcode_tasks::table
+            .filter(transcode_tasks::user_id.eq(user_id))
+            .select(TranscodeTaskRow::as_select())
+            .order_by(transcode_tasks::id.desc())
+            .offset(offset as i64)
+            .limit(page.page_size as i64)
+            .load(conn)
+            .await?;
+
+        Ok(Self {
+            total,
+            tasks: rows.into_iter().map(Into::into).collect(),
+        })
+    }
 }
@@ -2,20 +2,30 @@ use anyhow::{bail, ensure};
 use async_graphql::{ComplexObject, Enum, InputObject, Result, SimpleObject};
 use chrono::NaiveDateTime;
 use diesel::helper_types::IntoBoxed;
-use diesel::TextExpressionMethods;
 use diesel::{prelude::Queryable, QueryDsl, Selectable};
 use diesel::{result::OptionalExtension, ExpressionMethods, SelectableHelper};
+use diesel::{BoolExpressionMethods, TextExpressionMethods};
 use diesel_async::RunQueryDsl;
-use utils::db_pools::postgres::pg_conn;
+use serde::{Deserialize, Serialize};
+use utils::db_pools::postgres::{pg_conn, pg_conn_read};
 
+use crate::application::file_system::share;
+use crate::application::user::avatar;
 use crate::domain::file_system::file::UserFileId;
 use crate::domain::transcode_order::TranscodeTaskId;
+use crate::infrastructure::repo_user;
 use crate::schema::users;
 
-use super::file_system::{DirContent, UserFile};
-use super::transcode::TranscodeTask;
-use super::{MillionTimestamp, Paginate};
+use super::activity::ActivityPage;
+use super::credits::CreditHistory;
+use super::file_system::{DirContent, DirPage, FileFilter, FileList, SharedFolder, Tag, UserFile};
+use super::notification::NotificationList;
+use super::transcode::{TranscodeTask, TranscodeTaskList};
+use super::{
+    decode_cursor, encode_cursor, KeysetOrder, KeysetPaginate, MillionTimestamp, Paginate,
+};
 
+use crate::domain::user::mask::{mask_email, mask_phone};
 use crate::domain::user::user::UserId;
 
 #[derive(Queryable, Selectable, SimpleObject)]
@@ -25,9 +35,9 @@ pub struct User {
     pub id: UserId,
     /// 用户名
     pub name: String,
-    /// 手机号
+    /// 手机号，管理端默认脱敏
     pub mobile_number: Option<String>,
-    /// 邮箱
+    /// 邮箱，管理端默认脱敏
     pub email: String,
     /// 最近登录时间
     pub last_login: MillionTimestamp,
@@ -35,15 +45,25 @@ pub struct User {
     pub create_at: MillionTimestamp,
     /// 是否在线
     pub online: bool,
-    /// 地址
+    /// 地址，管理端默认为空
     pub address: Option<super::Address>,
+    /// 积分余额
+    pub credits: i64,
+    /// 是否退订转码订单完成邮件
+    pub order_email_opt_out: bool,
+    /// 每个文件保留的历史版本数，为空时使用系统默认值
+    pub max_file_versions: Option<i32>,
+    #[graphql(skip)]
+    pub level: i16,
+    #[graphql(skip)]
+    pub avatar: Option<String>,
 }
 
 #[ComplexObject]
 impl User {
     /// 用户等级
     pub async fn level(&self) -> Result<UserLevel> {
-        Ok(UserLevel::Normal)
+        Ok(UserLevel::from_i16(self.level)?)
     }
 
     /// 用户状态
@@ -51,12 +71,68 @@ impl User {
         Ok(UserStatus::Ok)
     }
 
-    /// 获取用户文件夹内容
-    async fn dir(&self, file_id: UserFileId, page: Paginate) -> Result<Option<DirContent>> {
-        let dir = DirContent::load(self.id, file_id, page).await?;
+    /// 头像地址，没有上传头像时为空
+    async fn avatar_url(&self) -> Result<Option<String>> {
+        let Some(hash) = &self.avatar else {
+            return Ok(None);
+        };
+        Ok(Some(avatar::avatar_url(hash).await?))
+    }
+
+    /// 获取用户文件夹内容，可以按条件过滤
+    async fn dir(
+        &self,
+        file_id: UserFileId,
+        page: Paginate,
+        filter: Option<FileFilter>,
+    ) -> Result<Option<DirContent>> {
+        // 共享文件夹中的内容属于文件夹的所有者
+        let owner = share::readable_owner(self.id, file_id).await?;
+        let dir = DirContent::load(owner.unwrap_or(self.id), file_id, page, filter).await?;
         Ok(dir)
     }
 
+    /// 按游标分页获取用户文件夹内容，文件很多时代替 dir。目录在前，默认按名称排序
+    async fn dir_page(
+        &self,
+        file_id: UserFileId,
+        #[graphql(default_with = "KeysetOrder::Name")] order: KeysetOrder,
+        page: KeysetPaginate,
+        filter: Option<FileFilter>,
+    ) -> Result<DirPage> {
+        let owner = share::readable_owner(self.id, file_id).await?;
+        Ok(DirPage::load(owner.unwrap_or(self.id), file_id, order, page, filter).await?)
+    }
+
+    /// 其他用户分享给我的文件夹
+    async fn shared_folders(&self) -> Result<Vec<SharedFolder>> {
+        Ok(SharedFolder::load_all(self.id).await?)
+    }
+
+    /// 按条件搜索用户的所有文件，最近修改的在前
+    async fn files(
+        &self,
+        #[graphql(default)] filter: FileFilter,
+        page: Paginate,
+    ) -> Result<FileList> {
+        Ok(FileList::search(self.id, filter, page).await?)
+    }
+
+    /// 用户的所有标签
+    async fn tags(&self) -> Result<Vec<Tag>> {
+        Ok(Tag::load_all(self.id).await?)
+    }
+
+    /// 收藏的文件，最近收藏的在前
+    async fn starred_files(&self, page: Paginate) -> Result<FileList> {
+        Ok(FileList::starred(self.id, page).await?)
+    }
+
+    /// 最近访问的文件，最近访问的在前
+    async fn recent_files(&self, page: Paginate) -> Result<FileList> {
+        Ok(FileList::recent(self.id, page).await?)
+    }
+
     /// 获取用户文件
     async fn file(&self, id: UserFileId) -> Result<Option<UserFile>> {
         Ok(UserFile::find(id).await?)
@@ -66,9 +142,87 @@ impl User {
     async fn running_tasks(&self) -> Result<Vec<TranscodeTaskId>> {
         Ok(TranscodeTask::running_tasks(self.id).await?)
     }
+
+    /// 转码任务，最近创建的在前，包含转码结果的质量指标
+    async fn transcode_tasks(&self, page: Paginate) -> Result<TranscodeTaskList> {
+        Ok(TranscodeTaskList::load(self.id, page).await?)
+    }
+
+    /// 获取转码任务
+    async fn transcode_task(&self, id: TranscodeTaskId) -> Result<Option<TranscodeTask>> {
+        Ok(TranscodeTask::find(self.id, id).await?)
+    }
+
+    /// 积分变动记录，按时间倒序
+    async fn credit_history(&self, page: Paginate) -> Result<CreditHistory> {
+        Ok(CreditHistory::load(self.id, page).await?)
+    }
+
+    /// 未读通知数量
+    async fn unread_notifications(&self) -> Result<i64> {
+        Ok(NotificationList::unread_count(self.id).await?)
+    }
+
+    /// 通知列表，按时间倒序
+    async fn notifications(
+        &self,
+        page: Paginate,
+        #[graphql(default)] unread_only: bool,
+    ) -> Result<NotificationList> {
+        Ok(NotificationList::load(self.id, page, unread_only).await?)
+    }
+
+    /// 动态时间线，最近的在前
+    async fn activities(&self, page: KeysetPaginate) -> Result<ActivityPage> {
+        Ok(ActivityPage::load(self.id, page).await?)
+    }
+}
+
+/// 以 = + - @ 制表符或回车开头的单元格加上 ' 前缀，+86 这样的手机号除外
+fn csv_cell(value: &str) -> String {
+    let is_phone = value
+        .strip_prefix('+')
+        .is_some_and(|rest| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit()));
+    if !is_phone && value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    }
 }
 
 impl User {
+    /// 管理端默认只展示脱敏后的联系方式，不返回地址
+    pub fn mask_contact(&mut self) {
+        self.email = mask_email(&self.email);
+        self.mobile_number = self.mobile_number.as_deref().map(mask_phone);
+        self.address = None;
+    }
+
+    /// 导出为 csv 时的列名，与 csv_record 的顺序一致
+    pub const CSV_HEADERS: [&'static str; 7] = [
+        "id",
+        "name",
+        "email",
+        "mobile_number",
+        "credits",
+        "create_at",
+        "last_login",
+    ];
+
+    /// 用户填写的字段经过 [`csv_cell`] 转义，避免在表格软件中被当作公式执行
+    pub fn csv_record(&self) -> [String; 7] {
+        let format_time = |t: &MillionTimestamp| t.0.format("%Y-%m-%d %H:%M:%S").to_string();
+        [
+            self.id.to_string(),
+            csv_cell(&self.name),
+            csv_cell(&self.email),
+            csv_cell(self.mobile_number.as_deref().unwrap_or_default()),
+            self.credits.to_string(),
+            format_time(&self.create_at),
+            format_time(&self.last_login),
+        ]
+    }
+
     pub async fn load(id: UserId) -> anyhow::Result<User> {
         let user = Self::load_may_none(id).await?;
 
@@ -80,11 +234,10 @@ impl User {
     }
 
     pub async fn load_may_none(id: UserId) -> anyhow::Result<Option<User>> {
-        let conn = &mut pg_conn().await?;
+        let conn = &mut pg_conn_read().await?;
         let user: Option<User> = users::table
             .filter(users::id.eq(id))
             .select(User::as_select())
-            .for_update()
             .get_result(conn)
             .await
             .optional()?;
@@ -96,28 +249,134 @@ impl User {
             return Ok(Default::default());
         };
 
-        let conn = &mut pg_conn().await?;
+        let conn = &mut pg_conn_read().await?;
 
         let total: i64 = users::table.count().get_result(conn).await?;
+        let Some(sql) = Self::search_sql(params.search_by.as_ref())? else {
+            return Ok(Default::default());
+        };
+
+        let users: Vec<User> = params
+            .sort
+            .set_order_by(sql)
+            .select(User::as_select())
+            .offset(offset as i64)
+            .limit(params.page.page_size as i64)
+            .get_results(conn)
+            .await?;
+        Ok(UserList { total, users })
+    }
+
+    /// 游标分页的用户列表，不统计总数
+    pub async fn list_after(params: UserKeysetParams) -> anyhow::Result<UserPage> {
+        let Some(mut sql) = Self::search_sql(params.search_by.as_ref())? else {
+            return Ok(Default::default());
+        };
+
+        let up = params.direction == Direction::Up;
+        if let Some(cursor) = &params.page.after {
+            let last: UserCursor = decode_cursor(cursor)?;
+            ensure!(
+                last.order == params.order && last.direction == params.direction,
+                "cursor does not match the sort order"
+            );
+            let UserCursor { id, name, .. } = last;
+            sql = match (params.order, up) {
+                (KeysetOrder::Id, true) => sql.filter(users::id.gt(id)),
+                (KeysetOrder::Id, false) => sql.filter(users::id.lt(id)),
+                (KeysetOrder::Name, true) => sql.filter(
+                    users::name
+                        .gt(name.clone())
+                        .or(users::name.eq(name).and(users::id.gt(id))),
+                ),
+                (KeysetOrder::Name, false) => sql.filter(
+                    users::name
+                        .lt(name.clone())
+                        .or(users::name.eq(name).and(users::id.lt(id))),
+                ),
+            };
+        }
+        sql = match (params.order, up) {
+            (KeysetOrder::Id, true) => sql.order_by(users::id.asc()),
+            (KeysetOrder::Id, false) => sql.order_by(users::id.desc()),
+            (KeysetOrder::Name, true) => sql.order_by((users::name.asc(), users::id.asc())),
+            (KeysetOrder::Name, false) => sql.order_by((users::name.desc(), users::id.desc())),
+        };
+
+        let conn = &mut pg_conn_read().await?;
+        let page_size = params.page.page_size as usize;
+        let users: Vec<User> = sql
+            .select(User::as_select())
+            .limit(page_size as i64)
+            .get_results(conn)
+            .await?;
+
+        // 不足一页时说明已经没有更多数据
+        let next_cursor = match users.last() {
+            Some(last) if users.len() == page_size => Some(encode_cursor(&UserCursor {
+                order: params.order,
+                direction: params.direction,
+                id: last.id,
+                name: last.name.clone(),
+            })?),
+            _ => None,
+        };
+        Ok(UserPage { users, next_cursor })
+    }
+
+    /// 符合条件的用户数
+    pub async fn count(search_by: Option<&SearchBy>) -> anyhow::Result<i64> {
+        let Some(sql) = Self::search_sql(search_by)? else {
+            return Ok(0);
+        };
+        let conn = &mut pg_conn_read().await?;
+        let count = sql.count().get_result(conn).await?;
+        Ok(count)
+    }
+
+    /// 按条件分批读取用户，用于导出
+    pub async fn search(
+        search_by: Option<&SearchBy>,
+        sort: &Sort,
+        offset: i64,
+        limit: i64,
+    ) -> anyhow::Result<Vec<User>> {
+        let Some(sql) = Self::search_sql(search_by)? else {
+            return Ok(vec![]);
+        };
+        let conn = &mut pg_conn_read().await?;
+        let users = sort
+            .set_order_by(sql)
+            .then_order_by(users::id.asc())
+            .select(User::as_select())
+            .offset(offset)
+            .limit(limit)
+            .get_results(conn)
+            .await?;
+        Ok(users)
+    }
+
+    /// 根据搜索条件构造查询，条件不可能满足时返回 None
+    fn search_sql<'a>(
+        search_by: Option<&SearchBy>,
+    ) -> anyhow::Result<Option<IntoBoxed<'a, users::table, diesel::pg::Pg>>> {
         let mut sql = users::table.into_boxed();
 
         // where clause
-        if let Some(search) = params.search_by {
-            // Fixme: 目前用户的等级和状态都是固定的，所以不需要过滤
+        if let Some(search) = search_by {
+            // Fixme: 目前用户的状态是固定的，所以不需要过滤
             if let Some(level) = search.level {
-                if !matches!(level, UserLevel::Normal) {
-                    return Ok(Default::default());
-                }
+                sql = sql.filter(users::level.eq(level as i16));
             }
             if let Some(status) = search.status {
                 if !matches!(status, UserStatus::Ok) {
-                    return Ok(Default::default());
+                    return Ok(None);
                 }
             }
 
             macro_rules! filter_if_not_empty {
                 ($field:tt $(,)?) => {{
-                    if let Some(field) = search.$field {
+                    if let Some(field) = &search.$field {
                         sql = sql.filter(users::$field.like(format!("%{}%", field)));
                     }
                 }};
@@ -128,7 +387,7 @@ impl User {
 
             macro_rules! interval_filter {
                 ($search_field:tt, $sql_field:tt) => {
-                    if let Some(interval) = search.$search_field {
+                    if let Some(interval) = &search.$search_field {
                         let Some(start) = NaiveDateTime::from_timestamp_millis(interval.start_ms) else {
                             bail!("invalid timestamp: {}",interval.start_ms);
                         };
@@ -143,16 +402,7 @@ impl User {
             interval_filter!(register_at, create_at);
         }
 
-        // order by
-        let sql = params.sort.set_order_by(sql);
-
-        let users: Vec<User> = sql
-            .select(User::as_select())
-            .offset(offset as i64)
-            .limit(params.page.page_size as i64)
-            .get_results(conn)
-            .await?;
-        Ok(UserList { total, users })
+        Ok(Some(sql))
     }
 }
 
@@ -162,6 +412,53 @@ pub struct UserList {
     users: Vec<User>,
 }
 
+impl UserList {
+    pub fn user_ids(&self) -> Vec<UserId> {
+        self.users.iter().map(|u| u.id).collect()
+    }
+
+    pub fn mask_contact(&mut self) {
+        self.users.iter_mut().for_each(User::mask_contact);
+    }
+}
+
+/// 游标分页的用户列表
+#[derive(Default, SimpleObject)]
+pub struct UserPage {
+    users: Vec<User>,
+    /// 下一页的游标，为空时没有更多数据
+    next_cursor: Option<String>,
+}
+
+impl UserPage {
+    pub fn user_ids(&self) -> Vec<UserId> {
+        self.users.iter().map(|u| u.id).collect()
+    }
+
+    pub fn mask_contact(&mut self) {
+        self.users.iter_mut().for_each(User::mask_contact);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct UserCursor {
+    order: KeysetOrder,
+    direction: Direction,
+    id: UserId,
+    name: String,
+}
+
+#[derive(InputObject)]
+pub struct UserKeysetParams {
+    /// 搜索条件，为空时不过滤
+    search_by: Option<SearchBy>,
+    /// 排序字段
+    order: KeysetOrder,
+    direction: Direction,
+    /// 分页条件，翻页时排序条件需要保持不变
+    page: KeysetPaginate,
+}
+
 #[derive(InputObject)]
 pub struct UserSearchParams {
     /// 搜索条件，为空时不过滤
@@ -172,14 +469,15 @@ pub struct UserSearchParams {
     page: Paginate,
 }
 
-#[derive(InputObject)]
+#[derive(InputObject, Serialize, Deserialize)]
 pub struct Sort {
     /// 排序字段
     by: SortBy,
     direction: Direction,
 }
 
-#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum SortBy {
     /// 按用户名排序
     Name,
@@ -193,7 +491,26 @@ pub enum SortBy {
     RegisterAt,
 }
 
+impl UserSearchParams {
+    /// 是否按联系方式过滤或排序
+    pub fn by_contact(&self) -> bool {
+        self.search_by.as_ref().is_some_and(SearchBy::by_contact) || self.sort.by_contact()
+    }
+}
+
+impl UserKeysetParams {
+    /// 是否按联系方式过滤
+    pub fn by_contact(&self) -> bool {
+        self.search_by.as_ref().is_some_and(SearchBy::by_contact)
+    }
+}
+
 impl Sort {
+    /// 按邮箱或手机号排序会暴露联系方式的顺序
+    pub fn by_contact(&self) -> bool {
+        matches!(self.by, SortBy::Email | SortBy::MobileNumber)
+    }
+
     fn set_order_by<'a>(
         &self,
         sql: IntoBoxed<'a, users::table, diesel::pg::Pg>,
@@ -217,7 +534,8 @@ impl Sort {
     }
 }
 
-#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Direction {
     /// 升序
     Up,
@@ -225,7 +543,8 @@ pub enum Direction {
     Down,
 }
 
-#[derive(InputObject, Default)]
+#[derive(InputObject, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SearchBy {
     name: Option<String>,
     email: Option<String>,
@@ -236,14 +555,24 @@ pub struct SearchBy {
     status: Option<UserStatus>,
 }
 
-#[derive(InputObject)]
+impl SearchBy {
+    /// 按邮箱或手机号搜索可以逐位试出脱敏的内容，空字符串匹配所有用户，不算在内
+    pub fn by_contact(&self) -> bool {
+        let not_empty = |s: &Option<String>| s.as_deref().is_some_and(|s| !s.is_empty());
+        not_empty(&self.email) || not_empty(&self.mobile_number)
+    }
+}
+
+#[derive(InputObject, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TimeInterval {
     start_ms: i64,
     end_ms: i64,
 }
 
 #[repr(i16)]
-#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum UserLevel {
     /// 普通用户
     Normal,
@@ -252,14 +581,26 @@ pub enum UserLevel {
 }
 
 impl UserLevel {
+    /// 读取主库，管理员修改等级后立即生效。用户不存在时视为普通用户
+    pub async fn of(user_id: UserId) -> anyhow::Result<Self> {
+        let conn = &mut pg_conn().await?;
+        let level = repo_user::find_level(user_id, conn).await?;
+        level.map_or(Ok(Self::Normal), Self::from_i16)
+    }
+
     pub fn from_i16(value: i16) -> anyhow::Result<Self> {
-        ensure!(value <= Self::Svip as i16, "invalid user level: {}", value);
+        ensure!(
+            (0..=Self::Svip as i16).contains(&value),
+            "invalid user level: {}",
+            value
+        );
         unsafe { Ok(std::mem::transmute(value)) }
     }
 }
 
 #[repr(i16)]
-#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum UserStatus {
     /// 正常
     Ok,
@@ -277,3 +618,20 @@ impl UserStatus {
         unsafe { Ok(std::mem::transmute(value)) }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_csv_cell() {
+        for value in ["=1+1", "+A1", "+1+1", "-1", "@SUM(A1)", "\tx", "\rx"] {
+            assert_eq!(csv_cell(value), format!("'{value}"));
+        }
+        assert_eq!(csv_cell("+8613800000000"), "+8613800000000");
+        assert_eq!(csv_cell("+"), "'+");
+        assert_eq!(csv_cell("alice"), "alice");
+        assert_eq!(csv_cell("a=b"), "a=b");
+        assert_eq!(csv_cell(""), "");
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::Path;
+
+use getset::Getters;
+
+use super::{transcode_order::TranscodeOrderId, user::user::UserId};
+
+/// 用户动态对应的事件，按时间线展示在用户的账号中
+pub enum ActivityEvent {
+    FileUploaded {
+        file_path: String,
+    },
+    /// 订单中所有任务都已结束
+    OrderFinished {
+        order_id: TranscodeOrderId,
+        /// 第一个任务的源文件路径
+        file_path: String,
+        ok_count: usize,
+        failed_count: usize,
+    },
+    /// 其他用户分享了文件
+    FileShared {
+        file_path: String,
+        owner_name: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i16)]
+pub enum ActivityKind {
+    FileUploaded = 0,
+    OrderFinished = 1,
+    FileShared = 2,
+}
+
+#[derive(Getters)]
+#[getset(get = "pub")]
+pub struct Activity {
+    user_id: UserId,
+    kind: ActivityKind,
+    message: String,
+    file_path: Option<String>,
+    order_id: Option<TranscodeOrderId>,
+}
+
+impl Activity {
+    pub fn new(user_id: UserId, event: ActivityEvent) -> Self {
+        let (kind, message, file_path, order_id) = match event {
+            ActivityEvent::FileUploaded { file_path } => (
+                ActivityKind::FileUploaded,
+                format!("上传了「{}」", file_name(&file_path)),
+                file_path,
+                None,
+            ),
+            ActivityEvent::OrderFinished {
+                order_id,
+                file_path,
+                ok_count,
+                failed_count,
+            } => {
+                let name = file_name(&file_path);
+                let message = match (ok_count + failed_count, failed_count) {
+                    (1, 0) => format!("「{}」转码完成", name),
+                    (1, _) => format!("「{}」转码失败", name),
+                    (total, 0) => format!("「{}」等 {} 个文件转码完成", name, total),
+                    (total, failed) => {
+                        format!("「{}」等 {} 个文件转码结束，{} 个失败", name, total, failed)
+                    }
+                };
+                (
+                    ActivityKind::OrderFinished,
+                    message,
+                    file_path,
+                    Some(order_id),
+                )
+            }
+            ActivityEvent::FileShared {
+                file_path,
+                owner_name,
+            } => (
+                ActivityKind::FileShared,
+                format!("{} 分享了「{}」", owner_name, file_name(&file_path)),
+                file_path,
+                None,
+            ),
+        };
+
+        Self {
+            user_id,
+            kind,
+            message,
+            file_path: Some(file_path),
+            order_id,
+        }
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_new_activity() {
+        let event = ActivityEvent::FileUploaded {
+            file_path: "/源视频/a.mp4".to_string(),
+        };
+        let a = Activity::new(UserId(1), event);
+        assert_eq!(a.kind(), &ActivityKind::FileUploaded);
+        assert_eq!(a.message(), "上传了「a.mp4」");
+
+        let finished = |ok_count, failed_count| {
+            let event = ActivityEvent::OrderFinished {
+                order_id: TranscodeOrderId(2),
+                file_path: "/源视频/b.mkv".to_string(),
+                ok_count,
+                failed_count,
+            };
+            Activity::new(UserId(1), event).message().clone()
+        };
+        assert_eq!(finished(1, 0), "「b.mkv」转码完成");
+        assert_eq!(finished(0, 1), "「b.mkv」转码失败");
+        assert_eq!(finished(3, 0), "「b.mkv」等 3 个文件转码完成");
+        assert_eq!(finished(2, 1), "「b.mkv」等 3 个文件转码结束，1 个失败");
+    }
+}
@@ -0,0 +1,59 @@
+//! 领域事件
+//!
+//! 核心业务完成后由应用层通过 `application::event_bus` 发布，附带的反应由订阅者处理
+
+use std::path::PathBuf;
+
+use super::{
+    file_system::file::{SysFileId, VirtualPath},
+    transcode_order::{TranscocdeOrder, TranscodeOrderId, TranscodeTaskId},
+    user::user::UserId,
+};
+
+pub trait DomainEvent: Send + Sync + 'static {
+    /// 用于日志
+    const NAME: &'static str;
+}
+
+/// 新用户注册成功
+pub struct UserRegistered {
+    pub user_id: UserId,
+    pub email: String,
+}
+
+impl DomainEvent for UserRegistered {
+    const NAME: &'static str = "user-registered";
+}
+
+/// 用户文件已创建并链接到归档文件，来自上传、秒传、链接导入和目录导入
+pub struct UserFileCreated {
+    pub sys_file_id: SysFileId,
+    pub hash: String,
+    pub archived_path: PathBuf,
+    pub user_path: VirtualPath,
+}
+
+impl DomainEvent for UserFileCreated {
+    const NAME: &'static str = "user-file-created";
+}
+
+/// 转码任务结束，ok 为 false 时表示失败
+pub struct TranscodeTaskDone {
+    pub user_id: UserId,
+    pub order_id: TranscodeOrderId,
+    pub task_id: TranscodeTaskId,
+    pub ok: bool,
+}
+
+impl DomainEvent for TranscodeTaskDone {
+    const NAME: &'static str = "transcode-task-done";
+}
+
+/// 订单中所有任务都已结束
+pub struct TranscodeOrderFinished {
+    pub order: TranscocdeOrder,
+}
+
+impl DomainEvent for TranscodeOrderFinished {
+    const NAME: &'static str = "transcode-order-finished";
+}
@@ -0,0 +1,141 @@
+//! 功能开关
+//!
+//! 开关打开后，按灰度比例对部分用户生效，名单中的用户总是生效。
+//! 同一个用户在同一个开关中的分桶是固定的，提高比例时已经生效的用户不会失效
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::user::user::UserId;
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Getters, Serialize, Deserialize, Debug, Clone)]
+#[getset(get = "pub")]
+pub struct FeatureFlag {
+    name: String,
+    description: String,
+    enabled: bool,
+    /// 灰度比例，0 到 100
+    rollout_percent: u8,
+    /// 总是生效的用户
+    user_ids: Vec<UserId>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FeatureFlagErr {
+    /// 名称只能包含小写字母、数字、`-`、`_` 和 `.`
+    InvalidName,
+    InvalidPercent,
+}
+
+impl FeatureFlag {
+    pub fn new(
+        name: String,
+        description: String,
+        enabled: bool,
+        rollout_percent: u8,
+        user_ids: Vec<UserId>,
+    ) -> Result<Self, FeatureFlagErr> {
+        let valid_name = !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name.bytes().all(|b| {
+                b.is_ascii_lowercase() || b.is_ascii_digit() || matches!(b, b'-' | b'_' | b'.')
+            });
+        if !valid_name {
+            return Err(FeatureFlagErr::InvalidName);
+        }
+        if rollout_percent > 100 {
+            return Err(FeatureFlagErr::InvalidPercent);
+        }
+
+        Ok(Self {
+            name,
+            description,
+            enabled,
+            rollout_percent,
+            user_ids,
+        })
+    }
+
+    /// 开关对用户是否生效，user_id 为空（未登录或后台任务）时只看开关是否全量打开
+    pub fn is_enabled_for(&self, user_id: Option<UserId>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let Some(user_id) = user_id else {
+            return self.rollout_percent == 100;
+        };
+        if self.user_ids.contains(&user_id) {
+            return true;
+        }
+        self.bucket(user_id) < self.rollout_percent
+    }
+
+    /// 用户在这个开关中的分桶，0 到 99。
+    /// 使用开关名参与哈希，不同开关的灰度用户不会总是同一批
+    fn bucket(&self, user_id: UserId) -> u8 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.name.as_bytes());
+        hasher.update(user_id.0.to_be_bytes());
+        let hash = hasher.finalize();
+        let n = u64::from_be_bytes(hash[..8].try_into().unwrap());
+        (n % 100) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn flag(enabled: bool, rollout_percent: u8, user_ids: Vec<UserId>) -> FeatureFlag {
+        FeatureFlag::new(
+            "new-scheduler".to_string(),
+            String::new(),
+            enabled,
+            rollout_percent,
+            user_ids,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn t_new_flag() {
+        let new = |name: &str, percent| {
+            FeatureFlag::new(name.to_string(), String::new(), true, percent, vec![]).map(|_| ())
+        };
+        assert_eq!(new("s3.backend", 10), Ok(()));
+        assert_eq!(new("", 10), Err(FeatureFlagErr::InvalidName));
+        assert_eq!(new("S3", 10), Err(FeatureFlagErr::InvalidName));
+        assert_eq!(new("a b", 10), Err(FeatureFlagErr::InvalidName));
+        assert_eq!(new("s3", 101), Err(FeatureFlagErr::InvalidPercent));
+    }
+
+    #[test]
+    fn t_is_enabled_for() {
+        let users: Vec<_> = (1..=1000).map(UserId).collect();
+
+        let disabled = flag(false, 100, vec![UserId(1)]);
+        assert!(users.iter().all(|u| !disabled.is_enabled_for(Some(*u))));
+
+        let full = flag(true, 100, vec![]);
+        assert!(full.is_enabled_for(None));
+        assert!(users.iter().all(|u| full.is_enabled_for(Some(*u))));
+
+        let listed = flag(true, 0, vec![UserId(7)]);
+        assert!(!listed.is_enabled_for(None));
+        assert!(listed.is_enabled_for(Some(UserId(7))));
+        assert!(!listed.is_enabled_for(Some(UserId(8))));
+
+        // 提高比例时，已经生效的用户保持生效
+        let partial = flag(true, 30, vec![]);
+        let more = flag(true, 60, vec![]);
+        let enabled = users
+            .iter()
+            .filter(|u| partial.is_enabled_for(Some(**u)))
+            .collect::<Vec<_>>();
+        assert!((200..400).contains(&enabled.len()));
+        assert!(enabled.iter().all(|u| more.is_enabled_for(Some(**u))));
+    }
+}
@@ -0,0 +1,91 @@
+//! 文件变更记录
+//!
+//! 增量同步的客户端按记录的顺序重放变更。目录的创建和删除只记录目录本身，
+//! 新建的目录（如复制得到的目录）中的内容需要客户端再读取目录清单
+
+use getset::Getters;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::domain::user::user::UserId;
+
+use super::file::{FileNode, UserFileId, VirtualPath};
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[repr(i16)]
+pub enum ChangeKind {
+    Created = 0,
+    /// 文件内容被覆盖或恢复为历史版本
+    Modified = 1,
+    Renamed = 2,
+    Moved = 3,
+    Deleted = 4,
+}
+
+impl TryFrom<i16> for ChangeKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i16) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(Self::Created),
+            1 => Ok(Self::Modified),
+            2 => Ok(Self::Renamed),
+            3 => Ok(Self::Moved),
+            4 => Ok(Self::Deleted),
+            other => anyhow::bail!("invalid file change kind: {}", other),
+        }
+    }
+}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct FileChange {
+    /// 文件所有者
+    user_id: UserId,
+    file_id: UserFileId,
+    kind: ChangeKind,
+    is_dir: bool,
+    path: String,
+    /// 重命名和移动前的路径
+    old_path: Option<String>,
+}
+
+impl FileChange {
+    pub fn created(node: &FileNode) -> Self {
+        Self::new(node, ChangeKind::Created, node.path(), None)
+    }
+
+    pub fn modified(node: &FileNode) -> Self {
+        Self::new(node, ChangeKind::Modified, node.path(), None)
+    }
+
+    pub fn renamed(node: &FileNode, old_path: &VirtualPath) -> Self {
+        Self::new(node, ChangeKind::Renamed, node.path(), Some(old_path))
+    }
+
+    pub fn moved(node: &FileNode, old_path: &VirtualPath) -> Self {
+        Self::new(node, ChangeKind::Moved, node.path(), Some(old_path))
+    }
+
+    /// 删除后节点的路径已经改变，记录删除前的路径
+    pub fn deleted(node: &FileNode, old_path: &VirtualPath) -> Self {
+        Self::new(node, ChangeKind::Deleted, old_path, None)
+    }
+
+    fn new(
+        node: &FileNode,
+        kind: ChangeKind,
+        path: &VirtualPath,
+        old_path: Option<&VirtualPath>,
+    ) -> Self {
+        Self {
+            user_id: *node.user_id(),
+            file_id: *node.id(),
+            kind,
+            is_dir: node.is_dir(),
+            path: path.to_str().into_owned(),
+            old_path: old_path.map(|path| path.to_str().into_owned()),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use getset::Getters;
+
+use super::file::UserFileId;
+use crate::{domain::user::user::UserId, ensure_ok, id_wraper};
+
+id_wraper!(FileCommentId);
+
+/// 评论内容的最大字符数
+const MAX_CONTENT_LEN: usize = 2000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CommentErr {
+    BadContent,
+    NotAuthor,
+}
+
+/// 文件评论，可以针对视频的某个时间点
+#[derive(Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct FileComment {
+    id: FileCommentId,
+    user_file_id: UserFileId,
+    author_id: UserId,
+    content: String,
+    /// 视频时间点（毫秒）
+    timecode_ms: Option<u32>,
+}
+
+impl FileComment {
+    pub fn create(
+        user_file_id: UserFileId,
+        author_id: UserId,
+        content: String,
+        timecode_ms: Option<u32>,
+    ) -> Result<Self, CommentErr> {
+        let content = check_content(content)?;
+        Ok(Self {
+            id: FileCommentId::next_id(),
+            user_file_id,
+            author_id,
+            content,
+            timecode_ms,
+        })
+    }
+
+    pub fn from_raw(
+        id: FileCommentId,
+        user_file_id: UserFileId,
+        author_id: UserId,
+        content: String,
+        timecode_ms: Option<u32>,
+    ) -> Self {
+        Self {
+            id,
+            user_file_id,
+            author_id,
+            content,
+            timecode_ms,
+        }
+    }
+
+    /// 只有作者可以修改评论
+    pub fn edit(&mut self, editor: UserId, content: String) -> Result<(), CommentErr> {
+        ensure_ok!(self.author_id == editor, CommentErr::NotAuthor);
+        self.content = check_content(content)?;
+        Ok(())
+    }
+}
+
+fn check_content(content: String) -> Result<String, CommentErr> {
+    let content = content.trim();
+    ensure_ok!(!content.is_empty(), CommentErr::BadContent);
+    ensure_ok!(
+        content.chars().count() <= MAX_CONTENT_LEN,
+        CommentErr::BadContent
+    );
+    Ok(content.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_comment() {
+        let comment = FileComment::create(1.into(), 2.into(), "  ".to_string(), None);
+        assert_eq!(comment.unwrap_err(), CommentErr::BadContent);
+
+        let long = "长".repeat(MAX_CONTENT_LEN + 1);
+        let comment = FileComment::create(1.into(), 2.into(), long, None);
+        assert_eq!(comment.unwrap_err(), CommentErr::BadContent);
+
+        let mut comment =
+            FileComment::create(1.into(), 2.into(), " 第 3 秒偏暗 ".to_string(), Some(3000))
+                .unwrap();
+        assert_eq!(comment.content(), "第 3 秒偏暗");
+
+        let err = comment.edit(3.into(), "x".to_string()).unwrap_err();
+        assert_eq!(err, CommentErr::NotAuthor);
+        comment.edit(2.into(), "已修正".to_string()).unwrap();
+        assert_eq!(comment.content(), "已修正");
+    }
+}
@@ -0,0 +1,184 @@
+//! 协作编辑时的文件锁
+//!
+//! 锁是建议性的：其他用户仍然可以浏览和下载被锁定的文件，但不能覆盖上传、重命名、移动或删除它。
+//! 锁到期后自动失效，持有者可以在到期前续期，有写权限的其他用户可以强制抢占。
+//! 持有者是实际操作的用户，组织空间中的成员各自持有锁
+
+use chrono::Duration;
+use getset::Getters;
+
+use crate::{domain::user::user::UserId, LocalDataTime};
+
+use super::file::UserFileId;
+
+/// 锁的最短有效期（秒）
+pub const MIN_TTL_SECS: u32 = 60;
+/// 锁的最长有效期（秒）
+pub const MAX_TTL_SECS: u32 = 60 * 60 * 8;
+
+#[derive(Getters, Debug, Clone)]
+#[getset(get = "pub")]
+pub struct EditLock {
+    user_file_id: UserFileId,
+    holder_id: UserId,
+    expire_at: LocalDataTime,
+}
+
+/// 文件被其他用户锁定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLocked {
+    pub file_id: UserFileId,
+    pub holder_id: UserId,
+    /// 由应用层填入，用于提示是哪个成员锁定了文件
+    pub holder_name: Option<String>,
+    pub expire_at: LocalDataTime,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EditLockErr {
+    Locked(FileLocked),
+    /// 有效期超出允许的范围
+    InvalidTtl,
+    /// 文件没有被锁定，或锁已过期
+    NotLocked,
+}
+
+impl From<FileLocked> for EditLockErr {
+    fn from(value: FileLocked) -> Self {
+        Self::Locked(value)
+    }
+}
+
+impl EditLock {
+    pub fn from_po(user_file_id: UserFileId, holder_id: UserId, expire_at: LocalDataTime) -> Self {
+        Self {
+            user_file_id,
+            holder_id,
+            expire_at,
+        }
+    }
+
+    pub fn is_active(&self, now: LocalDataTime) -> bool {
+        self.expire_at > now
+    }
+
+    /// 锁被其他用户持有且未过期时，user_id 不能修改该文件
+    pub fn check(&self, user_id: UserId, now: LocalDataTime) -> Result<(), FileLocked> {
+        if self.holder_id == user_id || !self.is_active(now) {
+            return Ok(());
+        }
+        Err(FileLocked {
+            file_id: self.user_file_id,
+            holder_id: self.holder_id,
+            holder_name: None,
+            expire_at: self.expire_at,
+        })
+    }
+}
+
+/// 检查一组文件（通常是一棵目录树）中是否有被其他用户锁定的
+pub fn check_all(
+    locks: &[EditLock],
+    user_id: UserId,
+    now: LocalDataTime,
+) -> Result<(), FileLocked> {
+    locks.iter().try_for_each(|lock| lock.check(user_id, now))
+}
+
+/// 加锁，已经持有时续期。steal 为 true 时抢占其他用户持有的锁
+pub fn acquire(
+    current: Option<&EditLock>,
+    file_id: UserFileId,
+    user_id: UserId,
+    ttl_secs: u32,
+    steal: bool,
+    now: LocalDataTime,
+) -> Result<EditLock, EditLockErr> {
+    if !(MIN_TTL_SECS..=MAX_TTL_SECS).contains(&ttl_secs) {
+        return Err(EditLockErr::InvalidTtl);
+    }
+    if let (Some(current), false) = (current, steal) {
+        current.check(user_id, now)?;
+    }
+    Ok(EditLock {
+        user_file_id: file_id,
+        holder_id: user_id,
+        expire_at: now + Duration::seconds(ttl_secs as i64),
+    })
+}
+
+/// 释放锁，只有持有者可以释放
+pub fn release(
+    current: Option<&EditLock>,
+    user_id: UserId,
+    now: LocalDataTime,
+) -> Result<(), EditLockErr> {
+    let Some(current) = current.filter(|lock| lock.is_active(now)) else {
+        return Err(EditLockErr::NotLocked);
+    };
+    current.check(user_id, now)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Local;
+
+    use super::*;
+
+    #[test]
+    fn t_acquire() {
+        let now = Local::now();
+        let file_id = UserFileId(1);
+        let (alice, bob) = (UserId(1), UserId(2));
+
+        assert_eq!(
+            acquire(None, file_id, alice, 1, false, now).unwrap_err(),
+            EditLockErr::InvalidTtl
+        );
+        let lock = acquire(None, file_id, alice, 600, false, now).unwrap();
+        assert_eq!(*lock.expire_at(), now + Duration::seconds(600));
+
+        // 其他用户不能加锁，持有者可以续期
+        let err = acquire(Some(&lock), file_id, bob, 600, false, now).unwrap_err();
+        assert_eq!(
+            err,
+            EditLockErr::Locked(FileLocked {
+                file_id,
+                holder_id: alice,
+                holder_name: None,
+                expire_at: *lock.expire_at(),
+            })
+        );
+        let renewed = acquire(Some(&lock), file_id, alice, 900, false, now).unwrap();
+        assert_eq!(*renewed.holder_id(), alice);
+
+        // 抢占或过期后其他用户可以加锁
+        let stolen = acquire(Some(&lock), file_id, bob, 600, true, now).unwrap();
+        assert_eq!(*stolen.holder_id(), bob);
+        let later = now + Duration::seconds(601);
+        let expired = acquire(Some(&lock), file_id, bob, 600, false, later).unwrap();
+        assert_eq!(*expired.holder_id(), bob);
+    }
+
+    #[test]
+    fn t_release() {
+        let now = Local::now();
+        let (alice, bob) = (UserId(1), UserId(2));
+        let lock = acquire(None, UserFileId(1), alice, 600, false, now).unwrap();
+
+        assert_eq!(release(None, alice, now), Err(EditLockErr::NotLocked));
+        assert!(matches!(
+            release(Some(&lock), bob, now),
+            Err(EditLockErr::Locked(_))
+        ));
+        assert_eq!(release(Some(&lock), alice, now), Ok(()));
+
+        let later = now + Duration::seconds(601);
+        assert_eq!(
+            release(Some(&lock), alice, later),
+            Err(EditLockErr::NotLocked)
+        );
+        assert_eq!(check_all(&[lock], bob, later), Ok(()));
+    }
+}
@@ -4,14 +4,21 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use chrono::Local;
 use derive_more::From;
 use derive_more::IsVariant;
 use getset::Getters;
 use path_slash::PathExt;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
-use crate::{domain::user::user::UserId, ensure_ok, id_wraper};
+use crate::{domain::user::user::UserId, ensure_ok, id_wraper, LocalDataTime};
+
+use super::{
+    edit_lock::FileLocked,
+    home::{home_layout, HomeDirCfg, HomeDirRole},
+};
 
 id_wraper!(UserFileId);
 id_wraper!(SysFileId);
@@ -26,6 +33,9 @@ pub struct FileNode {
     path: VirtualPath,
     deleted: bool,
     file_type: FileType,
+    create_at: LocalDataTime,
+    /// 从数据库读出时的值，保存时由数据库更新，内存中的修改不会改变它
+    updated_at: LocalDataTime,
 }
 
 #[derive(IsVariant, Debug, Clone)]
@@ -43,6 +53,8 @@ pub struct FileNodeMetaData {
     pub hash: String,
     pub archived_path: PathBuf,
     pub video_info: Option<VideoInfo>,
+    /// 根据内容识别的 MIME 类型，旧数据为空
+    pub mime: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -52,15 +64,20 @@ pub struct VideoInfo {
     pub height: u32,
     pub hdr_format: Option<String>,
     pub is_h264: bool,
+    /// 视频编码格式，如 AVC、HEVC、AV1
+    pub format: Option<String>,
+    /// 视频码率（bps）
+    pub bit_rate: Option<u32>,
+    pub duration_ms: Option<u32>,
 }
 
 /// VirtualPath 是一个虚拟的路径，用以控制用户的文件访问权限
 /// 它有如下性质：
-/// - 除了 "/"，所有路径都以 "/源视频" 或 "/已转码视频" 或 "/deleted" 开头
+/// - 除了 "/"，所有路径都以固定目录（默认为 "/源视频" 和 "/已转码视频"，见 [`super::home`]）或 "/deleted" 开头
 /// - 不包含 ".." 或可以解释为当前目录的 "."
 /// - 不包含多余的 "/"
 ///
-/// "/源视频" 或 "/已转码视频" 只能从数据库中读取，没有任何公开的方法可以创建
+/// 固定目录只能从数据库中读取，没有任何公开的方法可以创建
 /// "/" 可以通过 VirtualPath::root() 创建，但无法通过 join 方法产生子路径，所以只能在初始化或加载用户文件系统时使用
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VirtualPath {
@@ -76,6 +93,7 @@ impl FileNodeMetaData {
             hash,
             archived_path,
             video_info: None,
+            mime: None,
         }
     }
 }
@@ -88,7 +106,14 @@ pub enum FileOperateErr {
     AlreadyExist,
     ParentNotDir,
     NoParent,
+    NotFile,
+    /// 文件（或目录下的文件）正在被转码任务使用
+    FileBusy,
+    /// 目标磁盘的空间不足
+    InsufficientStorage,
     Path(VirtualPathErr),
+    /// 文件（或目录下的文件）被其他用户锁定
+    Locked(FileLocked),
 }
 
 use FileOperateErr::*;
@@ -96,28 +121,42 @@ use FileOperateErr::*;
 impl FileNode {
     pub fn user_home(user_id: UserId) -> Self {
         let mut root = Self::new_dir(user_id, VirtualPath::root(user_id));
-        let mut resource = Self::new_dir(user_id, VirtualPath::resource_dir(user_id));
-        resource.parent_id = Some(root.id);
-        let mut encoded = Self::new_dir(user_id, VirtualPath::encode_dir(user_id));
-        encoded.parent_id = Some(root.id);
+        root.add_missing_home_dirs();
+        root
+    }
 
-        let children = root.children_mut().unwrap();
-        children.push(resource);
-        children.push(encoded);
+    /// 在根目录下创建缺少的固定目录，返回新建的目录。配置中新增的固定目录在加载已有的家目录时补全
+    pub fn add_missing_home_dirs(&mut self) -> Vec<FileNode> {
+        if self.path.parent().is_some() {
+            return vec![];
+        }
+        let (user_id, root_id) = (self.user_id, self.id);
+        let Some(children) = self.children_mut() else {
+            return vec![];
+        };
 
-        root
+        let mut created = vec![];
+        for path in VirtualPath::home_dirs(user_id) {
+            if children.iter().any(|c| c.path == path) {
+                continue;
+            }
+            let mut dir = Self::new_dir(user_id, path);
+            dir.parent_id = Some(root_id);
+            created.push(dir.clone());
+            children.push(dir);
+        }
+        created
     }
 
+    /// 固定目录按配置的顺序排在最前面，其他按名字排列
     pub fn sort_by_name(&mut self) {
         if let FileType::Dir(dir) = &mut self.file_type {
             dir.sort_by(|a, b| {
-                if a.path().is_source() {
-                    return std::cmp::Ordering::Less;
-                }
-                if a.path().is_encoded() {
-                    return std::cmp::Ordering::Greater;
-                }
-                a.file_name().cmp(b.file_name())
+                let a_pos = a.path().home_dir_position().unwrap_or(usize::MAX);
+                let b_pos = b.path().home_dir_position().unwrap_or(usize::MAX);
+                a_pos
+                    .cmp(&b_pos)
+                    .then_with(|| a.file_name().cmp(b.file_name()))
             });
             for node in dir {
                 node.sort_by_name();
@@ -126,6 +165,7 @@ impl FileNode {
     }
 
     fn new_dir(user_id: UserId, path: VirtualPath) -> Self {
+        let now = Local::now();
         Self {
             id: UserFileId::next_id(),
             parent_id: None,
@@ -133,6 +173,8 @@ impl FileNode {
             path,
             deleted: false,
             file_type: FileType::Dir(vec![]),
+            create_at: now,
+            updated_at: now,
         }
     }
 
@@ -148,7 +190,7 @@ impl FileNode {
         self.path.file_name()
     }
 
-    // 只能在 /源视频 或 /已转码视频 下创建文件夹
+    // 只能在固定目录下创建文件夹
     pub fn create_dir(&mut self, name: &str) -> Result<&mut Self, FileOperateErr> {
         self.create_child(name, None)
     }
@@ -162,13 +204,14 @@ impl FileNode {
         let mut root = self;
         for name in new.components() {
             let name = name.as_os_str().to_str().unwrap();
-            root = root.create_dir(name).unwrap();
+            let path = root.path.join_child_raw(name).unwrap();
+            root = root.push_child(path, None).unwrap();
         }
 
         root
     }
 
-    // 只能在 /源视频 或 /已转码视频 下创建文件
+    // 只能在固定目录下创建文件
     pub fn create_file(
         &mut self,
         name: &str,
@@ -177,18 +220,58 @@ impl FileNode {
         self.create_child(name, Some(metadata))
     }
 
+    /// 同名文件存在时覆盖它的内容，并返回被覆盖的系统文件，否则创建新文件
+    pub fn overwrite_file(
+        &mut self,
+        name: &str,
+        metadata: FileNodeMetaData,
+    ) -> Result<(&mut Self, Option<SysFileId>), FileOperateErr> {
+        let path = self.path.join_child(name)?;
+        let existed = self.children_inner()?.iter().position(|ch| ch.path == path);
+        let Some(idx) = existed else {
+            return Ok((self.create_file(name, metadata)?, None));
+        };
+
+        let file = &mut self.children_mut_inner()?[idx];
+        let replaced = file.replace_data(metadata)?;
+        Ok((file, Some(replaced)))
+    }
+
+    /// 替换文件的内容，返回原来的系统文件
+    pub fn replace_data(
+        &mut self,
+        metadata: FileNodeMetaData,
+    ) -> Result<SysFileId, FileOperateErr> {
+        let replaced = match &self.file_type {
+            FileType::File(meta) => meta.id,
+            FileType::LazyFile(id) => *id,
+            FileType::Dir(_) => return Err(NotFile),
+        };
+        self.file_type = FileType::File(metadata);
+        Ok(replaced)
+    }
+
     pub fn create_child(
         &mut self,
         name: &str,
         metadata: Option<FileNodeMetaData>,
     ) -> Result<&mut Self, FileOperateErr> {
         let path = self.path.join_child(name)?;
+        self.push_child(path, metadata)
+    }
+
+    fn push_child(
+        &mut self,
+        path: VirtualPath,
+        metadata: Option<FileNodeMetaData>,
+    ) -> Result<&mut Self, FileOperateErr> {
         let file_type = if let Some(meta) = metadata {
             FileType::File(meta)
         } else {
             FileType::Dir(vec![])
         };
 
+        let now = Local::now();
         let mut child = Self {
             id: UserFileId::next_id(),
             parent_id: Some(self.id),
@@ -196,6 +279,8 @@ impl FileNode {
             path,
             deleted: false,
             file_type,
+            create_at: now,
+            updated_at: now,
         };
 
         let children = self.children_mut_inner()?;
@@ -222,6 +307,17 @@ impl FileNode {
         paths
     }
 
+    /// 递归获取所有未删除的文件，不包括目录
+    pub fn all_files(&self) -> Vec<&FileNode> {
+        if self.deleted {
+            return vec![];
+        }
+        match &self.file_type {
+            FileType::Dir(dir) => dir.iter().flat_map(|node| node.all_files()).collect(),
+            _ => vec![self],
+        }
+    }
+
     pub fn copy_to<'a>(&self, new_parent: &'a mut Self) -> Result<&'a mut Self, FileOperateErr> {
         let copyed = self.copy(new_parent.id);
         let copyed = copyed.move_to(new_parent)?;
@@ -232,6 +328,8 @@ impl FileNode {
         let mut copyed = self.clone();
         copyed.id = UserFileId::next_id();
         copyed.parent_id = Some(parent_id);
+        copyed.create_at = Local::now();
+        copyed.updated_at = copyed.create_at;
 
         if let FileType::Dir(dir) = &mut copyed.file_type {
             for node in dir {
@@ -248,7 +346,7 @@ impl FileNode {
         };
         ensure_ok!(!self.path.is_ancestor_of(&new_parent.path), Recursived);
 
-        let new_path = new_parent.path.join_child(self.file_name())?;
+        let new_path = new_parent.path.join_child_raw(self.file_name())?;
         let existed = children.iter().any(|ch| ch.path == new_path);
         ensure_ok!(!existed, AlreadyExist);
 
@@ -277,7 +375,7 @@ impl FileNode {
         self.path = new_path;
         if let FileType::Dir(dir) = &mut self.file_type {
             for node in dir {
-                let new_path = self.path.join_child(node.file_name())?;
+                let new_path = self.path.join_child_raw(node.file_name())?;
                 node.move_inner(new_path)?;
             }
         }
@@ -339,14 +437,16 @@ pub enum VirtualPathErr {
     BadFileName,
     TooLong,
     MustAbsolute,
+    /// 去掉控制字符和首尾空白后为空
+    EmptyName,
+    /// Windows 保留的设备名，如 CON、NUL、COM1
+    ReservedName,
 }
 use VirtualPathErr::*;
 
 impl std::error::Error for VirtualPathErr {}
 
 impl VirtualPath {
-    const SOURCE_DIR_PATH: &'static str = "/源视频";
-    const ENCODED_DIR_PATH: &'static str = "/已转码视频";
     const DELETED_DIR_PATH: &'static str = "/deleted";
 
     pub fn root(user_id: UserId) -> Self {
@@ -356,38 +456,41 @@ impl VirtualPath {
         }
     }
 
-    pub fn resource_dir(user_id: UserId) -> Self {
+    fn home_dir(user_id: UserId, dir: &HomeDirCfg) -> Self {
         Self {
             user_id,
-            path: Path::new(Self::SOURCE_DIR_PATH).to_owned(),
+            path: Path::new("/").join(&dir.name),
         }
     }
 
+    pub fn resource_dir(user_id: UserId) -> Self {
+        Self::home_dir(user_id, home_layout().source())
+    }
+
     pub fn encode_dir(user_id: UserId) -> Self {
-        Self {
-            user_id,
-            path: Path::new(Self::ENCODED_DIR_PATH).to_owned(),
-        }
+        Self::home_dir(user_id, home_layout().encoded())
     }
 
+    /// 所有固定目录，按配置的顺序排列
+    pub fn home_dirs(user_id: UserId) -> impl Iterator<Item = Self> {
+        home_layout()
+            .dirs
+            .iter()
+            .map(move |dir| Self::home_dir(user_id, dir))
+    }
+
+    /// 转码结果的路径：encoded 目录下的文件对应到 source 目录，其他固定目录下的文件对应到 encoded 目录
     pub fn mirror_path(&self) -> Self {
-        dbg!(&self.path.to_str());
-        if self.path.starts_with(Self::SOURCE_DIR_PATH) {
-            let path = self.path.strip_prefix(Self::SOURCE_DIR_PATH).unwrap();
-            let prefix = Self::encode_dir(self.user_id);
-            let path = prefix.path.join(path);
-            Self {
-                user_id: self.user_id,
-                path,
-            }
-        } else {
-            let path = self.path.strip_prefix(Self::ENCODED_DIR_PATH).unwrap();
-            let prefix = Self::resource_dir(self.user_id);
-            let path = prefix.path.join(path);
-            Self {
-                user_id: self.user_id,
-                path,
-            }
+        let layout = home_layout();
+        let target = match Self::home_dir_of(&self.path).map(|dir| dir.role) {
+            Some(HomeDirRole::Encoded) => layout.source(),
+            _ => layout.encoded(),
+        };
+        // 跳过 "/" 和固定目录
+        let rest: PathBuf = self.path.iter().skip(2).collect();
+        Self {
+            user_id: self.user_id,
+            path: Self::home_dir(self.user_id, target).path.join(rest),
         }
     }
 
@@ -397,20 +500,31 @@ impl VirtualPath {
         Ok(())
     }
 
-    fn is_source(&self) -> bool {
-        self.path == Path::new(Self::SOURCE_DIR_PATH)
+    /// 路径所在的固定目录，如 "/源视频/a/b.mp4" 在 "源视频" 下
+    fn home_dir_of(path: &Path) -> Option<&'static HomeDirCfg> {
+        let name = path.strip_prefix("/").ok()?.iter().next()?.to_str()?;
+        home_layout().get(name)
+    }
+
+    /// 路径是固定目录时，返回它的配置
+    pub fn as_home_dir(&self) -> Option<&'static HomeDirCfg> {
+        if self.path.parent() != Some(Path::new("/")) {
+            return None;
+        }
+        Self::home_dir_of(&self.path)
     }
 
-    fn is_encoded(&self) -> bool {
-        self.path == Path::new(Self::ENCODED_DIR_PATH)
+    fn home_dir_position(&self) -> Option<usize> {
+        let dir = self.as_home_dir()?;
+        home_layout().position(&dir.name)
     }
 
     fn is_fix_path(path: &Path) -> bool {
         let is_root = path == Path::new("/");
-        let is_source = path == Path::new(Self::SOURCE_DIR_PATH);
-        let is_encoded = path == Path::new(Self::ENCODED_DIR_PATH);
+        let is_home_dir =
+            path.parent() == Some(Path::new("/")) && Self::home_dir_of(path).is_some();
         let is_deleted = path == Path::new(Self::DELETED_DIR_PATH);
-        is_root || is_source || is_encoded || is_deleted
+        is_root || is_home_dir || is_deleted
     }
 
     fn allow_modified(&self) -> bool {
@@ -418,9 +532,7 @@ impl VirtualPath {
     }
 
     fn allow_add_child(&self) -> bool {
-        let is_decendant_of_source = self.path.starts_with(Self::SOURCE_DIR_PATH);
-        let is_decendant_of_encoded = self.path.starts_with(Self::ENCODED_DIR_PATH);
-        is_decendant_of_source || is_decendant_of_encoded
+        Self::home_dir_of(&self.path).is_some()
     }
 
     fn is_ancestor_of(&self, other: &Self) -> bool {
@@ -429,7 +541,7 @@ impl VirtualPath {
 }
 
 impl VirtualPath {
-    // 只能创建 "/源视频" 或 "/已转码视频" 的子路径
+    // 只能创建固定目录的子路径
     // 如："/源视频/aa" 是合法的，而 "/源视频" 无法通过这个方法创建
     pub fn build<Id, P>(user_id: Id, path: P) -> Result<Self, VirtualPathErr>
     where
@@ -443,10 +555,7 @@ impl VirtualPath {
         let path = clean_path::clean(&*path);
 
         ensure_ok!(!Self::is_fix_path(&path), NotAllowed);
-
-        let decendant_of_source = path.starts_with(Self::SOURCE_DIR_PATH);
-        let decendant_of_encoded = path.starts_with(Self::ENCODED_DIR_PATH);
-        ensure_ok!(decendant_of_source || decendant_of_encoded, NotAllowed);
+        ensure_ok!(Self::home_dir_of(&path).is_some(), NotAllowed);
 
         Self::build_permissive(user_id, path)
     }
@@ -473,8 +582,16 @@ impl VirtualPath {
         Ok(())
     }
 
+    /// 用户输入的文件名会先被规范化，见 normalize_file_name
     pub fn join_child(&self, name: &str) -> Result<Self, VirtualPathErr> {
         ensure_ok!(self.allow_add_child(), NotAllowed);
+        let name = Self::normalize_file_name(name)?;
+        self.join_child_raw(&name)
+    }
+
+    /// 不规范化文件名，用于移动已存在的文件，避免旧数据中不规范的文件名导致移动失败
+    fn join_child_raw(&self, name: &str) -> Result<Self, VirtualPathErr> {
+        ensure_ok!(self.allow_add_child(), NotAllowed);
 
         ensure_ok!(!name.contains(".."), NotAllowed);
         ensure_ok!(!name.contains("/"), NotAllowed);
@@ -487,7 +604,7 @@ impl VirtualPath {
         Self::build(self.user_id, child)
     }
 
-    // 只允许在 /源视频 或 /已转码视频 下的文件修改文件名
+    // 只允许在固定目录下的文件修改文件名
     fn increase_file_name(&mut self) -> Result<(), VirtualPathErr> {
         ensure_ok!(self.allow_modified(), NotAllowed);
 
@@ -528,12 +645,35 @@ impl VirtualPath {
     }
 
     fn rename(&self, new_name: &str) -> Result<Self, VirtualPathErr> {
+        let new_name = Self::normalize_file_name(new_name)?;
         let mut path = self.path.clone();
         path.set_file_name(new_name);
 
         Self::build(self.user_id, path)
     }
 
+    /// 规范化用户输入的文件名：转为 NFC 形式，去掉控制字符和首尾空白，拒绝 Windows 保留的设备名。
+    /// 不同系统上输入的同一个文件名（如 macOS 输入的 NFD 形式）会得到相同的结果
+    fn normalize_file_name(name: &str) -> Result<String, VirtualPathErr> {
+        const WINDOWS_RESERVED: &[&str] = &[
+            "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7",
+            "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+        ];
+
+        let name: String = name.nfc().filter(|c| !c.is_control()).collect();
+        let name = name.trim();
+        ensure_ok!(!name.is_empty(), EmptyName);
+
+        // 带扩展名时同样是保留名，如 con.txt
+        let stem = name.split('.').next().unwrap_or_default().trim_end();
+        let reserved = WINDOWS_RESERVED
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(stem));
+        ensure_ok!(!reserved, ReservedName);
+
+        Ok(name.to_string())
+    }
+
     pub fn parent(&self) -> Option<Self> {
         let parent = self.path.parent()?;
         Some(Self {
@@ -554,6 +694,15 @@ impl VirtualPath {
         self.path.to_slash_lossy()
     }
 
+    /// 相对于祖先路径的路径，如 "/源视频/a/b.mp4" 相对于 "/源视频" 为 "a/b.mp4"
+    pub fn relative_to(&self, ancestor: &VirtualPath) -> Option<Cow<str>> {
+        if self.user_id != ancestor.user_id {
+            return None;
+        }
+        let path = self.path.strip_prefix(&ancestor.path).ok()?;
+        Some(path.to_slash_lossy())
+    }
+
     pub fn user_id(&self) -> UserId {
         self.user_id
     }
@@ -599,6 +748,8 @@ pub mod convert {
                 file_name: Cow::Borrowed(file.path.file_name()),
                 is_dir: false,
                 deleted: file.deleted,
+                create_at: file.create_at,
+                updated_at: file.updated_at,
             };
 
             match &file.file_type {
@@ -611,6 +762,7 @@ pub mod convert {
                         size: meta.size as i64,
                         hash: Cow::Borrowed(&meta.hash),
                         path: meta.archived_path.to_string_lossy(),
+                        mime: meta.mime.as_deref().map(Cow::Borrowed),
                     };
 
                     v.push((default, Some(s)));
@@ -648,6 +800,8 @@ pub mod convert {
                 user_id,
                 parent_id,
                 deleted,
+                create_at,
+                updated_at,
                 ..
             } = user_file;
 
@@ -659,6 +813,7 @@ pub mod convert {
                         hash: meta.hash.into_owned(),
                         archived_path: Path::new(&*meta.path).to_path_buf(),
                         video_info: None,
+                        mime: meta.mime.map(Cow::into_owned),
                     };
                     crate::domain::file_system::file::FileType::File(meta)
                 }
@@ -682,6 +837,9 @@ pub mod convert {
                                     height: height as u32,
                                     hdr_format: info.HDR_Format,
                                     is_h264: video.is_h264,
+                                    format: info.Format,
+                                    bit_rate: video.bit_rate.map(|b| b as u32),
+                                    duration_ms: video.duration_ms.map(|d| d as u32),
                                 })
                             })
                         })
@@ -692,6 +850,7 @@ pub mod convert {
                         hash: video.hash,
                         archived_path: video.path.into(),
                         video_info: v_info,
+                        mime: video.mime,
                     };
                     crate::domain::file_system::file::FileType::File(meta)
                 }
@@ -703,6 +862,8 @@ pub mod convert {
                 path,
                 deleted,
                 file_type,
+                create_at,
+                updated_at,
             };
             Ok(node)
         }
@@ -713,7 +874,7 @@ pub mod convert {
                 size,
                 hash,
                 path,
-                ..
+                mime,
             } = po;
             FileNodeMetaData {
                 id,
@@ -721,6 +882,7 @@ pub mod convert {
                 hash: hash.into_owned(),
                 archived_path: Path::new(&*path).to_path_buf(),
                 video_info: None,
+                mime: mime.map(Cow::into_owned),
             }
         }
 
@@ -836,6 +998,51 @@ mod test {
         assert!(aabb.allow_modified());
     }
 
+    #[test]
+    fn t_mirror_path() {
+        let source = VirtualPath::build(1, "/源视频/a/b.mp4").unwrap();
+        assert_eq!(source.mirror_path().to_str(), "/已转码视频/a/b.mp4");
+        let encoded = VirtualPath::build(1, "/已转码视频/a/b.mp4").unwrap();
+        assert_eq!(encoded.mirror_path().to_str(), "/源视频/a/b.mp4");
+
+        let resource = VirtualPath::resource_dir(1.into());
+        assert_eq!(resource.as_home_dir().unwrap().name, "源视频");
+        let child = resource.join_child("源视频").unwrap();
+        assert!(child.as_home_dir().is_none());
+        assert!(child.allow_modified());
+    }
+
+    #[test]
+    fn t_normalize_file_name() {
+        let resource = VirtualPath::build_permissive(1.into(), "/源视频").unwrap();
+
+        // NFD 形式的 é 转为 NFC
+        let path = resource.join_child("cafe\u{301}.mp4").unwrap();
+        assert_eq!(path.file_name(), "caf\u{e9}.mp4");
+
+        let path = resource.join_child(" a\u{7}b\t.mp4 \n").unwrap();
+        assert_eq!(path.file_name(), "ab.mp4");
+
+        assert_eq!(
+            resource.join_child(" \r\n").unwrap_err(),
+            VirtualPathErr::EmptyName
+        );
+        assert_eq!(
+            resource.join_child("con").unwrap_err(),
+            VirtualPathErr::ReservedName
+        );
+        assert_eq!(
+            resource.join_child("Lpt1.tar.gz").unwrap_err(),
+            VirtualPathErr::ReservedName
+        );
+        assert!(resource.join_child("console.mp4").is_ok());
+
+        let mut home = FileNode::user_home(1.into());
+        let resource = &mut home.children_mut().unwrap()[0];
+        let dir = resource.create_dir("a ").unwrap();
+        assert_eq!(dir.file_name(), "a");
+    }
+
     #[test]
     fn t_create_child() {
         let mut home = FileNode::user_home(1.into());
@@ -906,6 +1113,28 @@ mod test {
         assert_eq!(aa.id, aabb.parent_id.unwrap());
     }
 
+    #[test]
+    fn t_overwrite_file() {
+        let home = &mut FileNode::user_home(1.into());
+        let (resource, _) = test_user_home(home);
+        let meta = || FileNodeMetaData::new(1, "hash".to_string(), "/archived".into());
+
+        let first = meta();
+        let first_id = first.id;
+        let (file, replaced) = resource.overwrite_file("a.mp4", first).unwrap();
+        assert!(replaced.is_none());
+        let file_id = *file.id();
+
+        let (file, replaced) = resource.overwrite_file("a.mp4", meta()).unwrap();
+        assert_eq!(replaced, Some(first_id));
+        assert_eq!(*file.id(), file_id);
+        assert_eq!(resource.children().unwrap().len(), 1);
+
+        resource.create_dir("dir").unwrap();
+        let err = resource.overwrite_file("dir", meta()).unwrap_err();
+        assert_eq!(err, NotFile);
+    }
+
     #[test]
     fn t_copy() {
         let mut home = FileNode::user_home(1.into());
@@ -1004,4 +1233,22 @@ mod test {
         let mirror = mirror.mirror_path();
         assert_eq!(mirror.to_str(), "/源视频/aa");
     }
+
+    #[test]
+    fn t_all_files() {
+        let mut home = FileNode::user_home(1.into());
+        let (aa, _bb) = test_user_home(&mut home);
+        let meta = FileNodeMetaData::new(1, "hash".to_string(), PathBuf::new());
+        aa.create_file("a.mp4", meta.clone()).unwrap();
+        let cc = aa.create_dir("cc").unwrap();
+        cc.create_file("c.mp4", meta.clone()).unwrap();
+        cc.create_file("d.mp4", meta).unwrap().delete().unwrap();
+
+        let files = aa.all_files();
+        let paths: Vec<_> = files
+            .iter()
+            .map(|f| f.path().relative_to(aa.path()).unwrap())
+            .collect();
+        assert_eq!(paths, ["a.mp4", "cc/c.mp4"]);
+    }
 }
@@ -0,0 +1,148 @@
+//! 用户家目录下的固定目录
+//!
+//! 根目录下只有这些目录和存放已删除文件的 /deleted，用户的文件都在固定目录下。
+//! 目录名和各语言的显示名由配置决定，默认为 /源视频 和 /已转码视频
+
+use std::{collections::HashMap, sync::OnceLock};
+
+use anyhow::{ensure, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HomeDirRole {
+    /// 上传的视频，转码结果放在 encoded 目录的镜像路径下
+    Source,
+    /// 转码结果
+    Encoded,
+    #[default]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HomeDirCfg {
+    /// 路径中使用的目录名
+    pub name: String,
+    /// 各语言的显示名，如 { en = "Source" }，没有对应语言时显示目录名
+    #[serde(default)]
+    pub display_names: HashMap<String, String>,
+    #[serde(default)]
+    pub role: HomeDirRole,
+}
+
+impl HomeDirCfg {
+    fn new(name: &str, role: HomeDirRole) -> Self {
+        Self {
+            name: name.to_string(),
+            display_names: HashMap::new(),
+            role,
+        }
+    }
+}
+
+/// 固定目录，按显示顺序排列。source 和 encoded 目录各有一个
+#[derive(Debug, Clone, Deserialize)]
+pub struct HomeLayout {
+    pub dirs: Vec<HomeDirCfg>,
+}
+
+impl Default for HomeLayout {
+    fn default() -> Self {
+        Self {
+            dirs: vec![
+                HomeDirCfg::new("源视频", HomeDirRole::Source),
+                HomeDirCfg::new("已转码视频", HomeDirRole::Encoded),
+            ],
+        }
+    }
+}
+
+static HOME_LAYOUT: OnceLock<HomeLayout> = OnceLock::new();
+
+/// 未初始化时（如单元测试中）使用默认的目录
+pub fn home_layout() -> &'static HomeLayout {
+    HOME_LAYOUT.get_or_init(HomeLayout::default)
+}
+
+impl HomeLayout {
+    pub fn init(layout: HomeLayout) -> Result<&'static Self> {
+        layout.check()?;
+        Ok(HOME_LAYOUT.get_or_init(|| layout))
+    }
+
+    fn check(&self) -> Result<()> {
+        for (i, dir) in self.dirs.iter().enumerate() {
+            let name = dir.name.as_str();
+            ensure!(
+                !name.is_empty() && !name.contains(['/', '\\']) && name != "." && name != "..",
+                "invalid home dir name: {name:?}"
+            );
+            ensure!(name != "deleted", "home dir name is reserved: {name:?}");
+            ensure!(
+                self.dirs[..i].iter().all(|d| d.name != name),
+                "duplicate home dir: {name:?}"
+            );
+        }
+        for role in [HomeDirRole::Source, HomeDirRole::Encoded] {
+            let count = self.dirs.iter().filter(|d| d.role == role).count();
+            ensure!(count == 1, "home dirs should have exactly one {role:?} dir");
+        }
+        Ok(())
+    }
+
+    pub fn source(&self) -> &HomeDirCfg {
+        self.by_role(HomeDirRole::Source)
+    }
+
+    pub fn encoded(&self) -> &HomeDirCfg {
+        self.by_role(HomeDirRole::Encoded)
+    }
+
+    fn by_role(&self, role: HomeDirRole) -> &HomeDirCfg {
+        self.dirs
+            .iter()
+            .find(|d| d.role == role)
+            .expect("checked when init")
+    }
+
+    pub fn get(&self, name: &str) -> Option<&HomeDirCfg> {
+        self.dirs.iter().find(|d| d.name == name)
+    }
+
+    /// 固定目录的显示顺序
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.dirs.iter().position(|d| d.name == name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_check() {
+        assert!(HomeLayout::default().check().is_ok());
+
+        let mut layout = HomeLayout::default();
+        layout
+            .dirs
+            .push(HomeDirCfg::new("字幕", HomeDirRole::Other));
+        assert!(layout.check().is_ok());
+
+        for name in ["", "a/b", "..", "deleted", "源视频"] {
+            let mut layout = HomeLayout::default();
+            layout.dirs.push(HomeDirCfg::new(name, HomeDirRole::Other));
+            assert!(layout.check().is_err(), "{name}");
+        }
+
+        let mut layout = HomeLayout::default();
+        layout
+            .dirs
+            .push(HomeDirCfg::new("Source", HomeDirRole::Source));
+        assert!(layout.check().is_err());
+
+        let mut layout = HomeLayout::default();
+        layout.dirs.remove(1);
+        assert!(layout.check().is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use super::file::UserFileId;
+use crate::{domain::user::user::UserId, id_wraper};
+
+id_wraper!(ImportJobId);
+
+/// 从网盘等外部服务批量导入文件的任务，文件按顺序逐个下载
+#[derive(Serialize, Deserialize, Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct ImportJob {
+    id: ImportJobId,
+    user_id: UserId,
+    /// 导入来源的名字，如 google_drive
+    source: String,
+    parent_id: UserFileId,
+    files: Vec<ImportJobFile>,
+}
+
+#[derive(Serialize, Deserialize, Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct ImportJobFile {
+    /// 文件在外部服务中的 id
+    remote_id: String,
+    /// 开始下载前从外部服务获取
+    name: Option<String>,
+    state: ImportFileState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ImportFileState {
+    Pending,
+    Downloading,
+    Completed {
+        file_id: UserFileId,
+        new_name: Option<String>,
+    },
+    Failed(String),
+}
+
+impl ImportJob {
+    pub fn new(
+        user_id: UserId,
+        source: String,
+        parent_id: UserFileId,
+        remote_ids: Vec<String>,
+    ) -> Self {
+        let files = remote_ids
+            .into_iter()
+            .map(|remote_id| ImportJobFile {
+                remote_id,
+                name: None,
+                state: ImportFileState::Pending,
+            })
+            .collect();
+        Self {
+            id: ImportJobId::next_id(),
+            user_id,
+            source,
+            parent_id,
+            files,
+        }
+    }
+
+    pub(crate) fn file_mut(&mut self, index: usize) -> &mut ImportJobFile {
+        &mut self.files[index]
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.files.iter().all(ImportJobFile::is_finished)
+    }
+
+    /// 提前结束任务，还没有结束的文件都以同样的原因失败
+    pub(crate) fn abort(&mut self, reason: &str) {
+        for file in self.files.iter_mut().filter(|f| !f.is_finished()) {
+            file.failed(reason.to_string());
+        }
+    }
+}
+
+impl ImportJobFile {
+    pub(crate) fn start(&mut self, name: String) {
+        self.name = Some(name);
+        self.state = ImportFileState::Downloading;
+    }
+
+    pub(crate) fn completed(&mut self, file_id: UserFileId, new_name: Option<String>) {
+        self.state = ImportFileState::Completed { file_id, new_name };
+    }
+
+    pub(crate) fn failed(&mut self, reason: String) {
+        self.state = ImportFileState::Failed(reason);
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        matches!(
+            self.state,
+            ImportFileState::Completed { .. } | ImportFileState::Failed(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_job_finished() {
+        let ids = vec!["a".to_string(), "b".to_string()];
+        let mut job = ImportJob::new(UserId(1), "test".into(), UserFileId(1), ids);
+        assert!(!job.is_finished());
+
+        job.file_mut(0).start("a.mp4".into());
+        job.file_mut(0).completed(UserFileId(2), None);
+        assert!(!job.is_finished());
+
+        job.file_mut(1).failed("not found".into());
+        assert!(job.is_finished());
+    }
+
+    #[test]
+    fn t_job_abort() {
+        let ids = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let mut job = ImportJob::new(UserId(1), "test".into(), UserFileId(1), ids);
+        job.file_mut(0).completed(UserFileId(2), None);
+        job.file_mut(1).start("b.mp4".into());
+
+        job.abort("quota exceeded");
+        assert!(job.is_finished());
+        assert!(matches!(
+            job.files()[0].state(),
+            ImportFileState::Completed { .. }
+        ));
+        assert!(
+            matches!(job.files()[2].state(), ImportFileState::Failed(r) if r == "quota exceeded")
+        );
+    }
+}
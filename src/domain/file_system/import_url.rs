@@ -0,0 +1,130 @@
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use super::file::UserFileId;
+use crate::{domain::user::user::UserId, id_wraper};
+
+id_wraper!(ImportTaskId);
+
+/// 从远程地址导入文件的任务，下载在后台进行
+#[derive(Serialize, Deserialize, Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct ImportTask {
+    id: ImportTaskId,
+    user_id: UserId,
+    url: String,
+    parent_id: UserFileId,
+    file_name: String,
+    overwrite: bool,
+    /// 远程服务器声明的大小，未声明时为空
+    total_bytes: Option<u64>,
+    downloaded_bytes: u64,
+    state: ImportTaskState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ImportTaskState {
+    Downloading,
+    Completed {
+        file_id: UserFileId,
+        new_name: Option<String>,
+    },
+    Failed(String),
+}
+
+impl ImportTask {
+    pub fn new(
+        user_id: UserId,
+        url: String,
+        parent_id: UserFileId,
+        file_name: String,
+        overwrite: bool,
+        total_bytes: Option<u64>,
+    ) -> Self {
+        Self {
+            id: ImportTaskId::next_id(),
+            user_id,
+            url,
+            parent_id,
+            file_name,
+            overwrite,
+            total_bytes,
+            downloaded_bytes: 0,
+            state: ImportTaskState::Downloading,
+        }
+    }
+
+    pub(crate) fn downloaded(&mut self, bytes: u64) {
+        self.downloaded_bytes = bytes;
+    }
+
+    pub(crate) fn completed(&mut self, file_id: UserFileId, new_name: Option<String>) {
+        self.state = ImportTaskState::Completed { file_id, new_name };
+    }
+
+    pub(crate) fn failed(&mut self, reason: String) {
+        self.state = ImportTaskState::Failed(reason);
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        !matches!(self.state, ImportTaskState::Downloading)
+    }
+}
+
+/// 从 url 的路径中取文件名，取不到时返回 None
+pub fn file_name_from_url_path(path: &str) -> Option<String> {
+    let name = path.rsplit('/').find(|seg| !seg.is_empty())?;
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// 检查远程服务器返回的 Content-Type。allowed 为空或服务器未返回时不限制
+pub fn is_allowed_content_type(content_type: Option<&str>, allowed: &[String]) -> bool {
+    let Some(content_type) = content_type else {
+        return true;
+    };
+    if allowed.is_empty() {
+        return true;
+    }
+    let content_type = content_type.trim().to_ascii_lowercase();
+    allowed
+        .iter()
+        .any(|prefix| content_type.starts_with(&prefix.to_ascii_lowercase()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_file_name_from_url_path() {
+        assert_eq!(
+            file_name_from_url_path("/samples/a.mp4").as_deref(),
+            Some("a.mp4")
+        );
+        assert_eq!(
+            file_name_from_url_path("/samples/dir/").as_deref(),
+            Some("dir")
+        );
+        assert_eq!(file_name_from_url_path("/"), None);
+        assert_eq!(file_name_from_url_path(""), None);
+        assert_eq!(file_name_from_url_path("/a/.."), None);
+    }
+
+    #[test]
+    fn t_allowed_content_type() {
+        let allowed = vec!["video/".to_string(), "application/octet-stream".to_string()];
+        assert!(is_allowed_content_type(Some("video/mp4"), &allowed));
+        assert!(is_allowed_content_type(Some("Video/MP4"), &allowed));
+        assert!(is_allowed_content_type(
+            Some("application/octet-stream"),
+            &allowed
+        ));
+        assert!(!is_allowed_content_type(Some("text/html"), &allowed));
+        assert!(is_allowed_content_type(None, &allowed));
+        assert!(is_allowed_content_type(Some("text/html"), &[]));
+    }
+}
@@ -0,0 +1,191 @@
+use serde::Deserialize;
+
+/// 识别文件类型需要读取的开头字节数，MPEG-TS 需要检查前三个 188 字节的包
+pub const SNIFF_LEN: usize = 512;
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// 根据文件开头的魔数识别 MIME 类型，不能识别时返回 application/octet-stream
+pub fn sniff(head: &[u8]) -> &'static str {
+    if let Some(mime) = sniff_container(head) {
+        return mime;
+    }
+    if is_text(head) {
+        return "text/plain";
+    }
+    OCTET_STREAM
+}
+
+fn sniff_container(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x1A\x45\xDF\xA3", "video/x-matroska"),
+        (b"FLV\x01", "video/x-flv"),
+        (b"\x30\x26\xB2\x75\x8E\x66\xCF\x11", "video/x-ms-asf"),
+        (b"\x00\x00\x01\xBA", "video/mpeg"),
+        (b"\x00\x00\x01\xB3", "video/mpeg"),
+        (b"OggS", "application/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"\x89PNG\r\n\x1A\n", "image/png"),
+        (b"\xFF\xD8\xFF", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1F\x8B", "application/gzip"),
+        (b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+        (b"Rar!\x1A\x07", "application/vnd.rar"),
+        (b"MZ", "application/x-msdownload"),
+        (b"\x7FELF", "application/x-executable"),
+    ];
+
+    if head.get(4..8) == Some(b"ftyp") {
+        return Some(sniff_ftyp(head.get(8..12).unwrap_or_default()));
+    }
+    if head.starts_with(b"RIFF") {
+        return match head.get(8..12) {
+            Some(b"AVI ") => Some("video/x-msvideo"),
+            Some(b"WAVE") => Some("audio/wav"),
+            Some(b"WEBP") => Some("image/webp"),
+            _ => None,
+        };
+    }
+    if is_mpeg_ts(head) {
+        return Some("video/mp2t");
+    }
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(sig, _)| head.starts_with(sig)) {
+        if *mime == "video/x-matroska" && contains(head, b"webm") {
+            return Some("video/webm");
+        }
+        return Some(mime);
+    }
+    // MP3 没有 ID3 标签时以帧同步字开头
+    is_mpeg_audio(head).then_some("audio/mpeg")
+}
+
+/// ISO 基础媒体文件（mp4、mov 等）根据主品牌区分
+fn sniff_ftyp(brand: &[u8]) -> &'static str {
+    match brand {
+        b"qt  " => "video/quicktime",
+        b"M4A " => "audio/mp4",
+        b"heic" | b"heix" | b"mif1" => "image/heic",
+        b"avif" => "image/avif",
+        _ if brand.starts_with(b"3g") => "video/3gpp",
+        _ => "video/mp4",
+    }
+}
+
+fn is_mpeg_ts(head: &[u8]) -> bool {
+    const PACKET: usize = 188;
+    head.len() > PACKET * 2 && (0..3).all(|i| head[i * PACKET] == 0x47)
+}
+
+fn is_mpeg_audio(head: &[u8]) -> bool {
+    matches!(head, [0xFF, b, ..] if b & 0xE0 == 0xE0)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// 开头的内容都是可打印的 UTF-8 字符。截断可能把最后一个多字节字符切开，忽略最后 3 个字节的错误
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(s) => s,
+        Err(err) if head.len() - err.valid_up_to() < 4 && err.error_len().is_none() => {
+            std::str::from_utf8(&head[..err.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return false,
+    };
+    !valid.is_empty()
+        && valid
+            .chars()
+            .all(|c| !c.is_control() || c.is_ascii_whitespace())
+}
+
+/// 允许上传的文件类型。规则可以是完整的类型（video/mp4）、大类（video/*）或 *
+#[derive(Debug, Deserialize, Default, Clone)]
+pub struct TypePolicy {
+    /// 为空时不限制
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// 优先于 allow
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl TypePolicy {
+    pub fn allows(&self, mime: &str) -> bool {
+        if self.deny.iter().any(|rule| type_matches(rule, mime)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|rule| type_matches(rule, mime))
+    }
+}
+
+fn type_matches(rule: &str, mime: &str) -> bool {
+    if rule == "*" {
+        return true;
+    }
+    match rule.strip_suffix("/*") {
+        Some(top) => mime
+            .split_once('/')
+            .is_some_and(|(t, _)| t.eq_ignore_ascii_case(top)),
+        None => rule.eq_ignore_ascii_case(mime),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_sniff() {
+        assert_eq!(sniff(b"\x00\x00\x00\x20ftypisom\x00\x00"), "video/mp4");
+        assert_eq!(
+            sniff(b"\x00\x00\x00\x14ftypqt  \x00\x00"),
+            "video/quicktime"
+        );
+        assert_eq!(sniff(b"RIFF\x00\x00\x00\x00AVI LIST"), "video/x-msvideo");
+        assert_eq!(sniff(b"\x1A\x45\xDF\xA3\x9F\x42\x82\x84webm"), "video/webm");
+        assert_eq!(
+            sniff(b"\x1A\x45\xDF\xA3\x9F\x42\x82\x88matroska"),
+            "video/x-matroska"
+        );
+        assert_eq!(sniff(b"\xFF\xFB\x90\x00"), "audio/mpeg");
+        assert_eq!(sniff(b"MZ\x90\x00\x03\x00"), "application/x-msdownload");
+        assert_eq!(sniff("字幕 hello\n".as_bytes()), "text/plain");
+        assert_eq!(sniff(&"中".as_bytes()[..2]), OCTET_STREAM);
+        assert_eq!(sniff(b"\x00\x01\x02\x03"), OCTET_STREAM);
+        assert_eq!(sniff(b""), OCTET_STREAM);
+
+        let mut ts = vec![0u8; SNIFF_LEN];
+        ts[0] = 0x47;
+        ts[188] = 0x47;
+        ts[376] = 0x47;
+        assert_eq!(sniff(&ts), "video/mp2t");
+    }
+
+    #[test]
+    fn t_policy() {
+        let policy = TypePolicy::default();
+        assert!(policy.allows("application/x-msdownload"));
+
+        let policy = TypePolicy {
+            allow: vec!["video/*".into(), "text/plain".into()],
+            deny: vec!["video/x-flv".into()],
+        };
+        assert!(policy.allows("video/mp4"));
+        assert!(policy.allows("text/plain"));
+        assert!(!policy.allows("video/x-flv"));
+        assert!(!policy.allows("image/png"));
+
+        let policy = TypePolicy {
+            allow: vec![],
+            deny: vec!["application/*".into()],
+        };
+        assert!(policy.allows("video/mp4"));
+        assert!(!policy.allows("application/zip"));
+    }
+}
@@ -1,3 +1,14 @@
+pub mod change;
+pub mod comment;
+pub mod edit_lock;
 pub mod file;
+pub mod home;
+pub mod import_job;
+pub mod import_url;
+pub mod mime;
 pub mod service;
 pub mod service_upload;
+pub mod share;
+pub mod subtitle;
+pub mod tag;
+pub mod version;
@@ -1,10 +1,14 @@
-use std::{path::PathBuf, sync::OnceLock};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::ensure;
 
 use crate::domain::{
-    transcode_order::params::{
-        audio::AudioProcessParameters, zcode::ZcodeProcessParams, ContainerFormat,
+    transcode_order::{
+        params::{audio::AudioProcessParameters, zcode::ZcodeProcessParams, ContainerFormat},
+        preview::SampleRange,
     },
     user::user::UserId,
 };
@@ -17,6 +21,7 @@ pub struct PathManager {
     repo_root: PathBuf,
     uploading_dir: PathBuf,
     user_space: PathBuf,
+    exports_dir: PathBuf,
 }
 
 static PATH_MANAGER: OnceLock<PathManager> = OnceLock::new();
@@ -32,23 +37,40 @@ impl PathManager {
             repo_root: root.join("archived"),
             uploading_dir: root.join("uploading"),
             user_space: root.join("user-space"),
+            exports_dir: root.join("exports"),
             root,
         };
         std::fs::create_dir_all(&manager.repo_root)?;
         std::fs::create_dir_all(&manager.uploading_dir)?;
         std::fs::create_dir_all(&manager.user_space)?;
+        std::fs::create_dir_all(&manager.exports_dir)?;
 
         Ok(PATH_MANAGER.get_or_init(|| manager))
     }
 
+    /// 所有用户的家目录的上级目录
+    pub fn user_space(&self) -> &Path {
+        &self.user_space
+    }
+
     pub fn user_home(&self, user_id: UserId) -> PathBuf {
         self.user_space.join(user_id.to_string())
     }
 
+    /// 后台导出任务生成的文件
+    pub fn export_path(&self, file_name: &str) -> PathBuf {
+        self.exports_dir.join(file_name)
+    }
+
     pub fn upload_slice_dir(&self, task_id: UploadTaskId) -> PathBuf {
         self.uploading_dir.join(task_id.to_string())
     }
 
+    /// 所有归档目录的上级目录，其中的每个目录以内容的 hash 命名
+    pub fn archive_root(&self) -> &Path {
+        &self.repo_root
+    }
+
     pub fn archived_dir(&self, hash: &str) -> PathBuf {
         self.repo_root.join(&hash)
     }
@@ -69,6 +91,7 @@ impl PathManager {
         container: ContainerFormat,
         v_params: &ZcodeProcessParams,
         a_params: &Option<AudioProcessParameters>,
+        sample: Option<SampleRange>,
     ) -> String {
         let mut v_path = String::from("v_");
         v_path += match v_params.format {
@@ -110,7 +133,11 @@ impl PathManager {
             })
             .unwrap_or_default();
 
-        format!("{}{}.{}", v_path, a_path, container.to_str())
+        let sample = sample
+            .map(|sample| sample.file_name_suffix())
+            .unwrap_or_default();
+
+        format!("{}{}{}.{}", v_path, a_path, sample, container.to_str())
     }
 
     pub fn transcode_dst_path(
@@ -119,8 +146,9 @@ impl PathManager {
         container: ContainerFormat,
         v_params: &ZcodeProcessParams,
         a_params: &Option<AudioProcessParameters>,
+        sample: Option<SampleRange>,
     ) -> PathBuf {
-        let out_name = Self::transcode_out_name(container, v_params, a_params);
+        let out_name = Self::transcode_out_name(container, v_params, a_params, sample);
         self.archived_dir(hash).join(out_name)
     }
 }
@@ -1,13 +1,17 @@
 use std::collections::HashSet;
 
 use super::file::{FileNode, UserFileId, VirtualPath};
-use crate::{domain::user::user::UserId, ensure_ok, id_wraper};
+use crate::{domain::user::user::UserId, ensure_ok, id_wraper, LocalDataTime};
 
+use chrono::Local;
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
 id_wraper!(UploadTaskId);
 
+/// 一个上传任务最多的分片数
+const MAX_SLICE_COUNT: u64 = 100_000;
+
 #[derive(Serialize, Deserialize, Getters, Debug)]
 #[getset(get = "pub(crate)")]
 pub struct UploadTask {
@@ -18,6 +22,37 @@ pub struct UploadTask {
     state: UploadTaskState,
     uploaded_slices: HashSet<u32>,
     path: VirtualPath,
+    /// 注册时客户端声明的分片方式，只有升级前注册的任务没有
+    #[serde(default)]
+    layout: Option<SliceLayout>,
+    /// 是否覆盖同名文件，被覆盖的内容保存为历史版本
+    #[serde(default)]
+    overwrite: bool,
+    /// 升级前注册的任务没有
+    #[serde(default)]
+    create_at: Option<LocalDataTime>,
+    /// 注册任务的用户，在共享文件夹中上传时与文件的所有者不同。升级前注册的任务没有
+    #[serde(default)]
+    uploader_id: Option<UserId>,
+}
+
+/// 文件按固定大小切片，最后一个分片可能较小
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SliceLayout {
+    pub file_size: u64,
+    pub slice_size: u64,
+}
+
+impl SliceLayout {
+    /// slice_size 不能为 0
+    pub fn slice_count(&self) -> u64 {
+        self.file_size.div_ceil(self.slice_size)
+    }
+
+    pub fn slice_len(&self, index: u32) -> u64 {
+        let start = (index as u64).saturating_mul(self.slice_size);
+        self.file_size.saturating_sub(start).min(self.slice_size)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,7 +62,14 @@ pub enum UploadTaskState {
 }
 
 impl UploadTask {
-    pub fn new(user_id: UserId, hash: String, parent_dir: UserFileId, path: VirtualPath) -> Self {
+    pub fn new(
+        user_id: UserId,
+        hash: String,
+        parent_dir: UserFileId,
+        path: VirtualPath,
+        layout: Option<SliceLayout>,
+        overwrite: bool,
+    ) -> Self {
         Self {
             id: UploadTaskId::next_id(),
             user_id,
@@ -36,6 +78,10 @@ impl UploadTask {
             state: UploadTaskState::Pending,
             uploaded_slices: Default::default(),
             path,
+            layout,
+            overwrite,
+            create_at: Some(Local::now()),
+            uploader_id: None,
         }
     }
 
@@ -50,6 +96,35 @@ impl UploadTask {
     pub(crate) fn is_completed(&self) -> bool {
         matches!(self.state, UploadTaskState::Completed(_))
     }
+
+    /// 未声明分片方式时不限制序号
+    pub(crate) fn is_valid_slice(&self, index: u32) -> bool {
+        match &self.layout {
+            Some(layout) => (index as u64) < layout.slice_count(),
+            None => true,
+        }
+    }
+
+    /// 尚未上传的分片序号，未声明分片方式时为空
+    pub(crate) fn missing_slices(&self) -> Option<Vec<u32>> {
+        let layout = self.layout.as_ref()?;
+        // 创建任务时已检查分片数不超过 MAX_SLICE_COUNT
+        let missing = (0..layout.slice_count() as u32)
+            .filter(|idx| !self.uploaded_slices.contains(idx))
+            .collect();
+        Some(missing)
+    }
+
+    /// 尚未上传的字节数，未声明分片方式时为空
+    pub(crate) fn bytes_remaining(&self) -> Option<u64> {
+        let layout = self.layout.as_ref()?;
+        let remaining = self
+            .missing_slices()?
+            .into_iter()
+            .map(|idx| layout.slice_len(idx))
+            .sum();
+        Some(remaining)
+    }
 }
 
 pub enum FinishUploadTaskErr {
@@ -61,23 +136,82 @@ pub enum FinishUploadTaskErr {
 pub enum CreateTaskErr {
     ParentNotDir,
     BadFileName,
+    BadLayout,
 }
 
 pub fn create_task(
+    uploader: UserId,
     target_dir: &FileNode,
     file_name: &str,
     hash: String,
+    layout: SliceLayout,
+    overwrite: bool,
 ) -> Result<UploadTask, CreateTaskErr> {
     use CreateTaskErr::*;
 
     ensure_ok!(target_dir.is_dir(), ParentNotDir);
+    ensure_ok!(layout.file_size > 0 && layout.slice_size > 0, BadLayout);
+    ensure_ok!(layout.slice_count() <= MAX_SLICE_COUNT, BadLayout);
 
     let path = target_dir
         .path()
         .join_child(file_name)
         .map_err(|_| BadFileName)?;
 
-    let task = UploadTask::new(*target_dir.user_id(), hash, *target_dir.id(), path);
+    let mut task = UploadTask::new(
+        *target_dir.user_id(),
+        hash,
+        *target_dir.id(),
+        path,
+        Some(layout),
+        overwrite,
+    );
+    task.uploader_id = Some(uploader);
 
     Ok(task)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_slice_layout() {
+        let layout = SliceLayout {
+            file_size: 10,
+            slice_size: 4,
+        };
+        assert_eq!(layout.slice_count(), 3);
+        assert_eq!(layout.slice_len(0), 4);
+        assert_eq!(layout.slice_len(2), 2);
+        assert_eq!(layout.slice_len(3), 0);
+
+        // 分片数超出 u32 或加法溢出时不会截断
+        let huge = SliceLayout {
+            file_size: u64::MAX,
+            slice_size: 1,
+        };
+        assert_eq!(huge.slice_count(), u64::MAX);
+        let single = SliceLayout {
+            file_size: u64::MAX,
+            slice_size: u64::MAX,
+        };
+        assert_eq!(single.slice_count(), 1);
+
+        let path = VirtualPath::build(1, "/源视频/a.mp4").unwrap();
+        let mut task = UploadTask::new(
+            1.into(),
+            "hash".to_string(),
+            1.into(),
+            path,
+            Some(layout),
+            false,
+        );
+        task.slice_done(0);
+        task.slice_done(2);
+        assert!(task.is_valid_slice(2));
+        assert!(!task.is_valid_slice(3));
+        assert_eq!(task.missing_slices(), Some(vec![1]));
+        assert_eq!(task.bytes_remaining(), Some(4));
+    }
+}
@@ -0,0 +1,177 @@
+//! 共享文件夹
+//!
+//! 文件夹的所有者可以把文件夹分享给其他用户，并为每个成员授予只读或读写权限。
+//! 权限对文件夹中的所有文件生效，成员不能删除、移动或重命名被分享的文件夹本身
+
+use async_graphql::Enum;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::domain::user::user::UserId;
+
+use super::file::VirtualPath;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[repr(i16)]
+pub enum SharePermission {
+    /// 可以浏览和下载
+    Read = 0,
+    /// 可以上传、新建、删除、移动和重命名
+    Write = 1,
+}
+
+impl TryFrom<i16> for SharePermission {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i16) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(Self::Read),
+            1 => Ok(Self::Write),
+            other => anyhow::bail!("invalid share permission: {}", other),
+        }
+    }
+}
+
+/// 用户对一个文件的访问级别
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccessLevel {
+    Read,
+    Write,
+    /// 文件所有者
+    Owner,
+}
+
+impl From<SharePermission> for AccessLevel {
+    fn from(value: SharePermission) -> Self {
+        match value {
+            SharePermission::Read => Self::Read,
+            SharePermission::Write => Self::Write,
+        }
+    }
+}
+
+/// 文件操作需要的权限
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileOp {
+    /// 浏览、下载
+    Read,
+    /// 在目录中新建或上传文件，作为移动和复制的目标目录
+    Write,
+    /// 删除、移动、重命名文件本身
+    Modify,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShareErr {
+    NotDir,
+    ShareWithSelf,
+}
+
+impl AccessLevel {
+    /// 用户对 path 的访问级别，shares 为分享给该用户的目录（属于 path 的所有者）及权限。
+    /// strict 为 true 时只考虑 path 的上级目录，不考虑 path 本身被分享
+    pub fn of(
+        user_id: UserId,
+        path: &VirtualPath,
+        shares: &[(VirtualPath, SharePermission)],
+        strict: bool,
+    ) -> Option<Self> {
+        if path.user_id() == user_id {
+            return Some(Self::Owner);
+        }
+        shares
+            .iter()
+            .filter(|(dir, _)| match path.relative_to(dir) {
+                Some(relative) => !strict || !relative.is_empty(),
+                None => false,
+            })
+            .map(|(_, permission)| Self::from(*permission))
+            .max()
+    }
+
+    /// 用户能否对 path 执行 op
+    pub fn permits(
+        user_id: UserId,
+        path: &VirtualPath,
+        shares: &[(VirtualPath, SharePermission)],
+        op: FileOp,
+    ) -> bool {
+        let level = Self::of(user_id, path, shares, op == FileOp::Modify);
+        match op {
+            FileOp::Read => level.is_some(),
+            FileOp::Write | FileOp::Modify => level >= Some(Self::Write),
+        }
+    }
+}
+
+/// 检查分享是否合法
+pub fn check_share(owner: UserId, member: UserId, is_dir: bool) -> Result<(), ShareErr> {
+    if !is_dir {
+        return Err(ShareErr::NotDir);
+    }
+    if owner == member {
+        return Err(ShareErr::ShareWithSelf);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_access_level() {
+        let (owner, member) = (UserId(1), UserId(2));
+        let path = |p: &str| VirtualPath::build(owner, p).unwrap();
+        let shared = path("/源视频/team");
+        let inner = path("/源视频/team/a/b.mp4");
+        let other = path("/源视频/private.mp4");
+
+        assert_eq!(
+            AccessLevel::of(owner, &other, &[], false),
+            Some(AccessLevel::Owner)
+        );
+        assert_eq!(AccessLevel::of(member, &inner, &[], false), None);
+
+        let read = [(shared.clone(), SharePermission::Read)];
+        assert_eq!(
+            AccessLevel::of(member, &inner, &read, false),
+            Some(AccessLevel::Read)
+        );
+        assert_eq!(AccessLevel::of(member, &other, &read, false), None);
+        assert!(AccessLevel::permits(member, &inner, &read, FileOp::Read));
+        assert!(!AccessLevel::permits(member, &inner, &read, FileOp::Write));
+
+        // 同时被分享了上级目录和下级目录时取较高的权限
+        let write = [
+            (shared.clone(), SharePermission::Read),
+            (path("/源视频/team/a"), SharePermission::Write),
+        ];
+        assert!(AccessLevel::permits(member, &inner, &write, FileOp::Modify));
+
+        // 可以在被分享的目录中新建文件，但不能修改被分享的目录本身
+        let write = [(shared.clone(), SharePermission::Write)];
+        assert!(AccessLevel::permits(member, &shared, &write, FileOp::Write));
+        assert!(!AccessLevel::permits(
+            member,
+            &shared,
+            &write,
+            FileOp::Modify
+        ));
+        assert!(AccessLevel::permits(member, &inner, &write, FileOp::Modify));
+    }
+
+    #[test]
+    fn t_check_share() {
+        assert_eq!(check_share(UserId(1), UserId(2), true), Ok(()));
+        assert_eq!(
+            check_share(UserId(1), UserId(2), false),
+            Err(ShareErr::NotDir)
+        );
+        assert_eq!(
+            check_share(UserId(1), UserId(1), true),
+            Err(ShareErr::ShareWithSelf)
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use super::file::FileNode;
+
+/// 字幕格式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    Srt,
+    Ass,
+}
+
+impl SubtitleFormat {
+    /// 根据扩展名判断字幕格式，不支持的格式返回 None
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let (_, ext) = name.rsplit_once('.')?;
+        match &*ext.to_lowercase() {
+            "srt" => Some(Self::Srt),
+            "ass" => Some(Self::Ass),
+            _ => None,
+        }
+    }
+
+    /// 目录或不支持的格式返回 None
+    pub fn from_file(file: &FileNode) -> Option<Self> {
+        if !file.is_file() {
+            return None;
+        }
+        Self::from_file_name(file.file_name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_from_file_name() {
+        assert_eq!(
+            SubtitleFormat::from_file_name("a.srt"),
+            Some(SubtitleFormat::Srt)
+        );
+        assert_eq!(
+            SubtitleFormat::from_file_name("a.b.ASS"),
+            Some(SubtitleFormat::Ass)
+        );
+        assert_eq!(SubtitleFormat::from_file_name("a.mp4"), None);
+        assert_eq!(SubtitleFormat::from_file_name("srt"), None);
+    }
+}
@@ -0,0 +1,74 @@
+use getset::Getters;
+
+use crate::{domain::user::user::UserId, ensure_ok, id_wraper};
+
+id_wraper!(TagId);
+
+/// 标签名的最大字符数
+const MAX_NAME_LEN: usize = 32;
+/// 每个文件最多可以添加的标签数
+pub const MAX_TAGS_PER_FILE: usize = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TagErr {
+    BadName,
+    TooManyTags,
+}
+
+/// 用户自定义的文件标签，同一用户下标签名唯一
+#[derive(Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct Tag {
+    id: TagId,
+    user_id: UserId,
+    name: String,
+}
+
+impl Tag {
+    pub fn create(user_id: UserId, name: &str) -> Result<Self, TagErr> {
+        Ok(Self {
+            id: TagId::next_id(),
+            user_id,
+            name: normalize_name(name)?,
+        })
+    }
+
+    pub fn from_raw(id: TagId, user_id: UserId, name: String) -> Self {
+        Self { id, user_id, name }
+    }
+}
+
+/// 去掉首尾空白，标签名不能为空、不能过长、不能包含控制字符
+pub fn normalize_name(name: &str) -> Result<String, TagErr> {
+    let name = name.trim();
+    ensure_ok!(!name.is_empty(), TagErr::BadName);
+    ensure_ok!(name.chars().count() <= MAX_NAME_LEN, TagErr::BadName);
+    ensure_ok!(!name.chars().any(char::is_control), TagErr::BadName);
+    Ok(name.to_string())
+}
+
+/// 文件已有 `current` 个标签时，是否还能再添加一个
+pub fn ensure_can_attach(current: usize) -> Result<(), TagErr> {
+    ensure_ok!(current < MAX_TAGS_PER_FILE, TagErr::TooManyTags);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_tag_name() {
+        assert_eq!(normalize_name(" 4K HDR ").unwrap(), "4K HDR");
+        assert_eq!(normalize_name("  ").unwrap_err(), TagErr::BadName);
+        assert_eq!(normalize_name("a\tb").unwrap_err(), TagErr::BadName);
+        let long = "标".repeat(MAX_NAME_LEN + 1);
+        assert_eq!(normalize_name(&long).unwrap_err(), TagErr::BadName);
+
+        assert!(ensure_can_attach(MAX_TAGS_PER_FILE - 1).is_ok());
+        assert_eq!(
+            ensure_can_attach(MAX_TAGS_PER_FILE).unwrap_err(),
+            TagErr::TooManyTags
+        );
+    }
+}
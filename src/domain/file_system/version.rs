@@ -0,0 +1,63 @@
+use getset::Getters;
+
+use super::file::{SysFileId, UserFileId};
+use crate::{domain::user::user::UserId, id_wraper};
+
+id_wraper!(FileVersionId);
+
+/// 用户可以设置的每个文件保留的最多历史版本数
+pub const MAX_FILE_VERSIONS: u32 = 100;
+
+/// 文件被覆盖前的内容
+#[derive(Getters, Debug)]
+#[getset(get = "pub(crate)")]
+pub struct FileVersion {
+    id: FileVersionId,
+    user_file_id: UserFileId,
+    user_id: UserId,
+    sys_file_id: SysFileId,
+}
+
+impl FileVersion {
+    pub fn new(user_id: UserId, user_file_id: UserFileId, sys_file_id: SysFileId) -> Self {
+        Self {
+            id: FileVersionId::next_id(),
+            user_file_id,
+            user_id,
+            sys_file_id,
+        }
+    }
+
+    pub fn from_raw(
+        id: FileVersionId,
+        user_id: UserId,
+        user_file_id: UserFileId,
+        sys_file_id: SysFileId,
+    ) -> Self {
+        Self {
+            id,
+            user_file_id,
+            user_id,
+            sys_file_id,
+        }
+    }
+}
+
+/// 超出保留数量的旧版本，`versions` 按从新到旧排列
+pub fn expired_versions(versions: &[FileVersionId], keep: u32) -> &[FileVersionId] {
+    versions.get(keep as usize..).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_expired_versions() {
+        let versions: Vec<FileVersionId> = vec![3.into(), 2.into(), 1.into()];
+        assert_eq!(expired_versions(&versions, 2), &[FileVersionId::from(1)]);
+        assert!(expired_versions(&versions, 3).is_empty());
+        assert!(expired_versions(&versions, 5).is_empty());
+        assert_eq!(expired_versions(&versions, 0).len(), 3);
+    }
+}
@@ -0,0 +1,278 @@
+//! 公开画廊
+//!
+//! 用户可以把转码后的视频公开发布，发布后生成固定的短链接，未登录的访客也可以观看。
+//! 访客可以举报公开的视频，管理员处理举报时可以下架视频，下架后用户不能再次发布
+
+use async_graphql::Enum;
+use getset::Getters;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{ensure_ok, id_wraper};
+
+use super::{
+    file_system::file::{FileNode, UserFileId},
+    user::user::UserId,
+};
+
+id_wraper!(PublicationId);
+id_wraper!(ReportId);
+
+const SLUG_LEN: usize = 12;
+const MAX_TITLE_CHARS: usize = 100;
+const MAX_REASON_CHARS: usize = 500;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PublishErr {
+    NotFile,
+    /// 只能发布转码后的视频
+    NotEncoded,
+    InvalidTitle,
+    /// 被管理员下架的视频不能再次发布
+    TakenDown,
+}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct Publication {
+    id: PublicationId,
+    /// 公开页面和播放地址中使用的短链接
+    slug: String,
+    user_id: UserId,
+    user_file_id: UserFileId,
+    title: String,
+    /// 被管理员下架
+    taken_down: bool,
+}
+
+impl Publication {
+    /// 发布视频，标题为空时使用文件名
+    pub fn publish(
+        file: &FileNode,
+        encoded: bool,
+        title: Option<String>,
+    ) -> Result<Self, PublishErr> {
+        ensure_ok!(file.is_file(), PublishErr::NotFile);
+        ensure_ok!(encoded, PublishErr::NotEncoded);
+
+        let title = match title {
+            Some(title) => title.trim().to_string(),
+            None => file.path().file_stem().to_string(),
+        };
+        let chars = title.chars().count();
+        ensure_ok!(
+            chars > 0 && chars <= MAX_TITLE_CHARS && !title.chars().any(char::is_control),
+            PublishErr::InvalidTitle
+        );
+
+        let slug = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SLUG_LEN)
+            .map(char::from)
+            .collect();
+        Ok(Self {
+            id: PublicationId::next_id(),
+            slug,
+            user_id: *file.user_id(),
+            user_file_id: *file.id(),
+            title,
+            taken_down: false,
+        })
+    }
+
+    pub fn from_po(
+        id: PublicationId,
+        slug: String,
+        user_id: UserId,
+        user_file_id: UserFileId,
+        title: String,
+        taken_down: bool,
+    ) -> Self {
+        Self {
+            id,
+            slug,
+            user_id,
+            user_file_id,
+            title,
+            taken_down,
+        }
+    }
+}
+
+#[derive(Enum, Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[repr(i16)]
+pub enum ReportState {
+    /// 等待管理员处理
+    Pending = 0,
+    /// 举报不成立
+    Dismissed = 1,
+    /// 视频已下架
+    TakenDown = 2,
+}
+
+impl TryFrom<i16> for ReportState {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i16) -> anyhow::Result<Self> {
+        let state = match value {
+            0 => Self::Pending,
+            1 => Self::Dismissed,
+            2 => Self::TakenDown,
+            other => anyhow::bail!("invalid report state: {}", other),
+        };
+        Ok(state)
+    }
+}
+
+/// 管理员对举报的处理
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Moderation {
+    /// 驳回举报
+    Dismiss,
+    /// 下架视频，同一视频的其他待处理举报一并处理
+    TakeDown,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReportErr {
+    InvalidReason,
+    AlreadyHandled,
+}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct Report {
+    id: ReportId,
+    publication_id: PublicationId,
+    /// 未登录的访客举报时为空
+    reporter_id: Option<UserId>,
+    reason: String,
+    state: ReportState,
+}
+
+impl Report {
+    pub fn create(
+        publication: &Publication,
+        reporter_id: Option<UserId>,
+        reason: String,
+    ) -> Result<Self, ReportErr> {
+        let reason = reason.trim().to_string();
+        let chars = reason.chars().count();
+        ensure_ok!(
+            chars > 0 && chars <= MAX_REASON_CHARS,
+            ReportErr::InvalidReason
+        );
+        Ok(Self {
+            id: ReportId::next_id(),
+            publication_id: *publication.id(),
+            reporter_id,
+            reason,
+            state: ReportState::Pending,
+        })
+    }
+
+    pub fn from_po(
+        id: ReportId,
+        publication_id: PublicationId,
+        reporter_id: Option<UserId>,
+        reason: String,
+        state: ReportState,
+    ) -> Self {
+        Self {
+            id,
+            publication_id,
+            reporter_id,
+            reason,
+            state,
+        }
+    }
+
+    /// 处理举报，下架时同时下架视频
+    pub fn resolve(
+        &mut self,
+        publication: &mut Publication,
+        moderation: Moderation,
+    ) -> Result<(), ReportErr> {
+        ensure_ok!(
+            self.state == ReportState::Pending,
+            ReportErr::AlreadyHandled
+        );
+        self.state = match moderation {
+            Moderation::Dismiss => ReportState::Dismissed,
+            Moderation::TakeDown => {
+                publication.taken_down = true;
+                ReportState::TakenDown
+            }
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::domain::file_system::file::FileNodeMetaData;
+
+    use super::*;
+
+    fn video(user_id: UserId) -> FileNode {
+        let mut home = FileNode::user_home(user_id);
+        let encoded = &mut home.children_mut().unwrap()[1];
+        let data = FileNodeMetaData::new(1, "hash".into(), "/archived".into());
+        encoded.create_file("猫.mp4", data).unwrap().clone()
+    }
+
+    #[test]
+    fn t_publish() {
+        let user_id = UserId(1);
+        let file = video(user_id);
+
+        let publication = Publication::publish(&file, true, None).unwrap();
+        assert_eq!(publication.title(), "猫");
+        assert_eq!(publication.slug().len(), SLUG_LEN);
+        assert_eq!(*publication.user_id(), user_id);
+
+        assert_eq!(
+            Publication::publish(&file, false, None).unwrap_err(),
+            PublishErr::NotEncoded
+        );
+        assert_eq!(
+            Publication::publish(&file, true, Some("  ".into())).unwrap_err(),
+            PublishErr::InvalidTitle
+        );
+        let home = FileNode::user_home(user_id);
+        assert_eq!(
+            Publication::publish(&home, true, None).unwrap_err(),
+            PublishErr::NotFile
+        );
+    }
+
+    #[test]
+    fn t_resolve_report() {
+        let mut publication = Publication::publish(&video(UserId(1)), true, None).unwrap();
+        assert_eq!(
+            Report::create(&publication, None, "".into()).unwrap_err(),
+            ReportErr::InvalidReason
+        );
+
+        let mut report = Report::create(&publication, None, "侵权".into()).unwrap();
+        report
+            .resolve(&mut publication, Moderation::Dismiss)
+            .unwrap();
+        assert_eq!(*report.state(), ReportState::Dismissed);
+        assert!(!publication.taken_down());
+        assert_eq!(
+            report.resolve(&mut publication, Moderation::TakeDown),
+            Err(ReportErr::AlreadyHandled)
+        );
+
+        let mut report = Report::create(&publication, Some(UserId(2)), "侵权".into()).unwrap();
+        report
+            .resolve(&mut publication, Moderation::TakeDown)
+            .unwrap();
+        assert_eq!(*report.state(), ReportState::TakenDown);
+        assert!(publication.taken_down());
+    }
+}
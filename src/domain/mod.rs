@@ -1,4 +1,10 @@
+pub mod activity;
+pub mod event;
+pub mod feature_flag;
 pub mod file_system;
+pub mod gallery;
+pub mod notification;
+pub mod organization;
 pub mod transcode_order;
 pub mod user;
 
@@ -21,7 +27,32 @@ macro_rules! id_wraper {
         )]
         #[diesel(sql_type = ::diesel::sql_types::BigInt)]
         pub struct $type_name(pub i64);
-        async_graphql::scalar!($type_name);
+
+        /// 64 位整数 id，以字符串输出，避免 js 客户端丢失精度。输入兼容字符串和整数
+        #[async_graphql::Scalar]
+        impl async_graphql::ScalarType for $type_name {
+            fn parse(value: async_graphql::Value) -> async_graphql::InputValueResult<Self> {
+                let id = match &value {
+                    async_graphql::Value::String(id) => id.parse().ok(),
+                    async_graphql::Value::Number(id) => id.as_i64(),
+                    _ => None,
+                };
+                id.map(Self)
+                    .ok_or_else(|| async_graphql::InputValueError::expected_type(value))
+            }
+
+            fn is_valid(value: &async_graphql::Value) -> bool {
+                matches!(
+                    value,
+                    async_graphql::Value::String(_) | async_graphql::Value::Number(_)
+                )
+            }
+
+            fn to_value(&self) -> async_graphql::Value {
+                async_graphql::Value::String(self.0.to_string())
+            }
+        }
+
         $crate::diesel_new_type!($type_name, ::diesel::sql_types::Bigint);
 
         impl $type_name {
@@ -39,6 +70,19 @@ macro_rules! id_wraper {
                 $type_name(lock.next() as i64)
             }
         }
+        impl<'__s> ::utoipa::ToSchema<'__s> for $type_name {
+            fn schema() -> (
+                &'__s str,
+                ::utoipa::openapi::RefOr<::utoipa::openapi::schema::Schema>,
+            ) {
+                let schema = ::utoipa::openapi::ObjectBuilder::new()
+                    .schema_type(::utoipa::openapi::SchemaType::String)
+                    .description(Some("64 位整数 id，以字符串传输"))
+                    .example(Some(::serde_json::json!("7113742358126088192")));
+                (stringify!($type_name), schema.into())
+            }
+        }
+
         impl ::redis::ToRedisArgs for $type_name {
             fn write_redis_args<W: ?Sized>(&self, out: &mut W)
             where
@@ -130,5 +174,15 @@ mod test {
         let json_int = format!(r#"{{"id":{}}}"#, next.0);
         let user_d: User = serde_json::from_str(&json_int).unwrap();
         assert_eq!(user_s, user_d);
+
+        use async_graphql::{ScalarType, Value};
+        assert_eq!(next.to_value(), Value::String(next.to_string()));
+        assert_eq!(
+            UserId::parse(Value::String(next.to_string())).unwrap(),
+            next
+        );
+        assert_eq!(UserId::parse(Value::from(next.0)).unwrap(), next);
+        assert!(UserId::parse(Value::String("abc".into())).is_err());
+        assert!(UserId::parse(Value::from(1.5)).is_err());
     }
 }
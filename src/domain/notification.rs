@@ -0,0 +1,121 @@
+use getset::Getters;
+
+use crate::id_wraper;
+
+use super::{file_system::file::UserFileId, transcode_order::TranscodeTaskId, user::user::UserId};
+
+id_wraper!(NotificationId);
+
+/// 需要通知用户的事件，用户离线时也能在通知中心看到
+pub enum NotificationEvent {
+    UploadFinished {
+        file_id: UserFileId,
+        file_name: String,
+    },
+    TranscodeSucceeded {
+        task_id: TranscodeTaskId,
+        file_id: UserFileId,
+        file_name: String,
+    },
+    TranscodeFailed {
+        task_id: TranscodeTaskId,
+        file_id: UserFileId,
+        file_name: String,
+    },
+    FileCommented {
+        file_id: UserFileId,
+        file_name: String,
+        author_name: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i16)]
+pub enum NotificationKind {
+    UploadFinished = 0,
+    TranscodeSucceeded = 1,
+    TranscodeFailed = 2,
+    FileCommented = 3,
+}
+
+#[derive(Getters)]
+#[getset(get = "pub")]
+pub struct Notification {
+    id: NotificationId,
+    user_id: UserId,
+    kind: NotificationKind,
+    message: String,
+    file_id: Option<UserFileId>,
+    task_id: Option<TranscodeTaskId>,
+}
+
+impl Notification {
+    pub fn new(user_id: UserId, event: NotificationEvent) -> Self {
+        let (kind, message, file_id, task_id) = match event {
+            NotificationEvent::UploadFinished { file_id, file_name } => (
+                NotificationKind::UploadFinished,
+                format!("「{}」上传完成", file_name),
+                file_id,
+                None,
+            ),
+            NotificationEvent::TranscodeSucceeded {
+                task_id,
+                file_id,
+                file_name,
+            } => (
+                NotificationKind::TranscodeSucceeded,
+                format!("「{}」转码完成", file_name),
+                file_id,
+                Some(task_id),
+            ),
+            NotificationEvent::TranscodeFailed {
+                task_id,
+                file_id,
+                file_name,
+            } => (
+                NotificationKind::TranscodeFailed,
+                format!("「{}」转码失败", file_name),
+                file_id,
+                Some(task_id),
+            ),
+            NotificationEvent::FileCommented {
+                file_id,
+                file_name,
+                author_name,
+            } => (
+                NotificationKind::FileCommented,
+                format!("{} 评论了「{}」", author_name, file_name),
+                file_id,
+                None,
+            ),
+        };
+
+        Self {
+            id: NotificationId::next_id(),
+            user_id,
+            kind,
+            message,
+            file_id: Some(file_id),
+            task_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_new_notification() {
+        let event = NotificationEvent::TranscodeFailed {
+            task_id: TranscodeTaskId(2),
+            file_id: UserFileId(3),
+            file_name: "a.mp4".to_string(),
+        };
+        let n = Notification::new(UserId(1), event);
+        assert_eq!(n.kind(), &NotificationKind::TranscodeFailed);
+        assert_eq!(n.message(), "「a.mp4」转码失败");
+        assert_eq!(n.task_id(), &Some(TranscodeTaskId(2)));
+        assert_eq!(n.file_id(), &Some(UserFileId(3)));
+    }
+}
@@ -0,0 +1,189 @@
+//! 组织
+//!
+//! 组织的共享空间由一个不能登录的空间账号持有，文件、存储配额和积分都记在这个账号上，
+//! 成员操作共享空间时以空间账号的身份进行，文件系统和计费的逻辑与个人空间相同
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::id_wraper;
+
+use super::user::user::UserId;
+
+id_wraper!(OrgId);
+
+const MAX_NAME_CHARS: usize = 32;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+#[repr(i16)]
+pub enum OrgRole {
+    /// 所有者，可以管理所有成员
+    Owner = 0,
+    /// 管理员，可以管理普通成员
+    Admin = 1,
+    /// 普通成员，可以读写共享空间
+    Member = 2,
+}
+
+impl TryFrom<i16> for OrgRole {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i16) -> anyhow::Result<Self> {
+        let role = match value {
+            0 => Self::Owner,
+            1 => Self::Admin,
+            2 => Self::Member,
+            other => anyhow::bail!("invalid org role: {}", other),
+        };
+        Ok(role)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OrgErr {
+    InvalidName,
+    NotMember,
+    /// 没有管理这个成员的权限
+    PermissionDenied,
+    /// 组织至少要有一个所有者
+    LastOwner,
+}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct Organization {
+    id: OrgId,
+    name: String,
+    /// 共享空间的账号
+    space_id: UserId,
+    /// 共享空间的存储配额，为空时按空间账号的等级
+    storage_quota: Option<u64>,
+}
+
+impl Organization {
+    pub fn create(name: String, space_id: UserId) -> Result<Self, OrgErr> {
+        let name = name.trim().to_string();
+        let chars = name.chars().count();
+        if chars == 0 || chars > MAX_NAME_CHARS || name.chars().any(char::is_control) {
+            return Err(OrgErr::InvalidName);
+        }
+        Ok(Self {
+            id: OrgId::next_id(),
+            name,
+            space_id,
+            storage_quota: None,
+        })
+    }
+
+    pub fn from_po(id: OrgId, name: String, space_id: UserId, storage_quota: Option<u64>) -> Self {
+        Self {
+            id,
+            name,
+            space_id,
+            storage_quota,
+        }
+    }
+}
+
+impl OrgRole {
+    /// operator 能否把 target 的角色从 from 修改为 to，to 为空表示移出组织。
+    /// 所有者可以管理任何人，管理员只能管理普通成员，任何人都可以退出组织。
+    /// owners 为组织当前的所有者数量
+    pub fn check_change(
+        operator: (UserId, OrgRole),
+        target: (UserId, OrgRole),
+        to: Option<OrgRole>,
+        owners: usize,
+    ) -> Result<(), OrgErr> {
+        let (operator_id, operator_role) = operator;
+        let (target_id, from) = target;
+
+        let leaving = operator_id == target_id && to.is_none();
+        let allowed = match operator_role {
+            OrgRole::Owner => true,
+            OrgRole::Admin => leaving || (from == OrgRole::Member && to != Some(OrgRole::Owner)),
+            OrgRole::Member => leaving,
+        };
+        if !allowed {
+            return Err(OrgErr::PermissionDenied);
+        }
+        if from == OrgRole::Owner && to != Some(OrgRole::Owner) && owners <= 1 {
+            return Err(OrgErr::LastOwner);
+        }
+        Ok(())
+    }
+
+    /// 能否邀请新成员，以及邀请时可以授予的角色
+    pub fn can_invite(&self, role: OrgRole) -> bool {
+        match self {
+            OrgRole::Owner => true,
+            OrgRole::Admin => role != OrgRole::Owner,
+            OrgRole::Member => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_create_org() {
+        assert!(Organization::create(" 工作室 ".to_string(), UserId(1)).is_ok());
+        assert_eq!(
+            Organization::create("  ".to_string(), UserId(1)).map(|_| ()),
+            Err(OrgErr::InvalidName)
+        );
+        assert_eq!(
+            Organization::create("a".repeat(33), UserId(1)).map(|_| ()),
+            Err(OrgErr::InvalidName)
+        );
+    }
+
+    #[test]
+    fn t_check_change() {
+        use OrgRole::*;
+        let (a, b) = (UserId(1), UserId(2));
+        let check = OrgRole::check_change;
+
+        // 所有者可以修改任何人
+        assert_eq!(check((a, Owner), (b, Admin), Some(Owner), 1), Ok(()));
+        assert_eq!(check((a, Owner), (b, Owner), None, 2), Ok(()));
+        // 唯一的所有者不能降级或退出
+        assert_eq!(
+            check((a, Owner), (a, Owner), None, 1),
+            Err(OrgErr::LastOwner)
+        );
+        assert_eq!(
+            check((a, Owner), (a, Owner), Some(Admin), 1),
+            Err(OrgErr::LastOwner)
+        );
+
+        // 管理员只能管理普通成员，不能授予所有者
+        assert_eq!(check((a, Admin), (b, Member), None, 1), Ok(()));
+        assert_eq!(check((a, Admin), (b, Member), Some(Admin), 1), Ok(()));
+        assert_eq!(
+            check((a, Admin), (b, Member), Some(Owner), 1),
+            Err(OrgErr::PermissionDenied)
+        );
+        assert_eq!(
+            check((a, Admin), (b, Admin), None, 1),
+            Err(OrgErr::PermissionDenied)
+        );
+        assert_eq!(check((a, Admin), (a, Admin), None, 1), Ok(()));
+
+        // 普通成员只能退出
+        assert_eq!(check((b, Member), (b, Member), None, 1), Ok(()));
+        assert_eq!(
+            check((b, Member), (a, Member), None, 1),
+            Err(OrgErr::PermissionDenied)
+        );
+
+        assert!(Owner.can_invite(Owner));
+        assert!(Admin.can_invite(Member));
+        assert!(!Admin.can_invite(Owner));
+        assert!(!Member.can_invite(Member));
+    }
+}
@@ -0,0 +1,177 @@
+//! 转码参数的兼容性检查
+//!
+//! 转码工厂会尽量执行收到的任何参数，不合理的组合（如 HDR 源视频输出 8bit 的 H264）
+//! 只会在转码结束后得到失真的结果，所以在创建订单前拒绝。
+//! 封装格式支持的编码同时通过接口返回给前端，用于过滤下拉选项
+
+use super::params::{
+    audio::AudioFormat,
+    zcode::{OutputQuality, Resolution, VideoFormat, ZcodeProcessParams},
+    ContainerFormat,
+};
+
+pub const CONTAINERS: [ContainerFormat; 3] = [
+    ContainerFormat::Mp4,
+    ContainerFormat::Mkv,
+    ContainerFormat::Webm,
+];
+
+/// 可以输出 HDR 的视频编码
+pub const HDR_VIDEO_FORMATS: [VideoFormat; 2] = [VideoFormat::H265, VideoFormat::Av1];
+
+/// HDR 源视频可以选择的画质
+pub const HDR_QUALITIES: [OutputQuality; 2] = [OutputQuality::High, OutputQuality::Top];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamsErr {
+    /// H264 编码器只支持 8bit，HDR 源视频需要输出 H265 或 AV1
+    HdrCodec,
+    /// HDR 源视频使用 base 质量时色带明显
+    HdrQuality,
+    /// 输出分辨率高于源视频
+    Upscale(Resolution),
+    /// 封装格式不支持该视频编码，如 webm 只能封装 AV1
+    Container(ContainerFormat, VideoFormat),
+    /// 封装格式不支持该音频编码，如 webm 只能封装 Opus
+    AudioContainer(ContainerFormat, AudioFormat),
+}
+
+/// 封装格式支持的视频编码
+pub fn video_formats(container: ContainerFormat) -> &'static [VideoFormat] {
+    match container {
+        ContainerFormat::Mp4 | ContainerFormat::Mkv => {
+            &[VideoFormat::H264, VideoFormat::H265, VideoFormat::Av1]
+        }
+        ContainerFormat::Webm => &[VideoFormat::Av1],
+    }
+}
+
+/// 封装格式支持的音频编码
+pub fn audio_formats(container: ContainerFormat) -> &'static [AudioFormat] {
+    match container {
+        ContainerFormat::Mp4 | ContainerFormat::Mkv => &[AudioFormat::AAC, AudioFormat::OPUS],
+        ContainerFormat::Webm => &[AudioFormat::OPUS],
+    }
+}
+
+/// 检查一份视频参数，width、height 和 is_hdr 来自源视频。不转码音频时 audio 为空
+pub fn check(
+    params: &ZcodeProcessParams,
+    container: ContainerFormat,
+    audio: Option<AudioFormat>,
+) -> Result<(), ParamsErr> {
+    if !video_formats(container).contains(&params.format) {
+        return Err(ParamsErr::Container(container, params.format));
+    }
+    if let Some(audio) = audio {
+        if !audio_formats(container).contains(&audio) {
+            return Err(ParamsErr::AudioContainer(container, audio));
+        }
+    }
+
+    if params.is_hdr {
+        if !HDR_VIDEO_FORMATS.contains(&params.format) {
+            return Err(ParamsErr::HdrCodec);
+        }
+        if !HDR_QUALITIES.contains(&params.quality) {
+            return Err(ParamsErr::HdrQuality);
+        }
+    }
+
+    // 没有解析出源视频尺寸时不检查
+    let source_short_side = params.width.min(params.height);
+    if let Some(resolution) = params.resolution {
+        if source_short_side > 0 && resolution.short_side() > source_short_side {
+            return Err(ParamsErr::Upscale(resolution));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params(format: VideoFormat, is_hdr: bool, quality: OutputQuality) -> ZcodeProcessParams {
+        ZcodeProcessParams {
+            is_hdr,
+            width: 1920,
+            height: 1080,
+            format,
+            resolution: None,
+            ray_tracing: None,
+            quality,
+        }
+    }
+
+    #[test]
+    fn t_hdr() {
+        use OutputQuality::*;
+        use VideoFormat::*;
+
+        let mp4 = ContainerFormat::Mp4;
+        assert_eq!(check(&params(H264, false, Base), mp4, None), Ok(()));
+        assert_eq!(
+            check(&params(H264, true, High), mp4, None),
+            Err(ParamsErr::HdrCodec)
+        );
+        assert_eq!(
+            check(&params(H265, true, Base), mp4, None),
+            Err(ParamsErr::HdrQuality)
+        );
+        assert_eq!(check(&params(H265, true, High), mp4, None), Ok(()));
+        assert_eq!(check(&params(Av1, true, Top), mp4, None), Ok(()));
+    }
+
+    #[test]
+    fn t_resolution() {
+        let mut p = params(VideoFormat::Av1, false, OutputQuality::High);
+        p.resolution = Some(Resolution::_1080P);
+        assert_eq!(check(&p, ContainerFormat::Mkv, None), Ok(()));
+        p.resolution = Some(Resolution::_4K);
+        assert_eq!(
+            check(&p, ContainerFormat::Mkv, None),
+            Err(ParamsErr::Upscale(Resolution::_4K))
+        );
+
+        // 竖屏视频按短边计算
+        p.width = 1080;
+        p.height = 1920;
+        p.resolution = Some(Resolution::_1080P);
+        assert_eq!(check(&p, ContainerFormat::Mkv, None), Ok(()));
+
+        p.width = 0;
+        p.height = 0;
+        p.resolution = Some(Resolution::_4K);
+        assert_eq!(check(&p, ContainerFormat::Mkv, None), Ok(()));
+    }
+
+    #[test]
+    fn t_container() {
+        let p = params(VideoFormat::H265, false, OutputQuality::High);
+        assert_eq!(
+            check(&p, ContainerFormat::Webm, None),
+            Err(ParamsErr::Container(
+                ContainerFormat::Webm,
+                VideoFormat::H265
+            ))
+        );
+        let p = params(VideoFormat::Av1, false, OutputQuality::High);
+        assert_eq!(check(&p, ContainerFormat::Webm, None), Ok(()));
+        assert_eq!(
+            check(&p, ContainerFormat::Webm, Some(AudioFormat::AAC)),
+            Err(ParamsErr::AudioContainer(
+                ContainerFormat::Webm,
+                AudioFormat::AAC
+            ))
+        );
+        assert_eq!(
+            check(&p, ContainerFormat::Webm, Some(AudioFormat::OPUS)),
+            Ok(())
+        );
+        assert_eq!(
+            check(&p, ContainerFormat::Mp4, Some(AudioFormat::AAC)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! 转码任务的超时时间，在固定时间的基础上按帧数线性增长
+
+use std::time::Duration;
+
+/// 超过这个时间仍未收到结果的任务视为已丢失
+pub fn task_deadline(frame_count: u32, base: Duration, per_thousand_frames: Duration) -> Duration {
+    let thousands = frame_count / 1000 + u32::from(frame_count % 1000 != 0);
+    base.saturating_add(per_thousand_frames.saturating_mul(thousands))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_task_deadline() {
+        let base = Duration::from_secs(600);
+        let per = Duration::from_secs(30);
+
+        assert_eq!(task_deadline(0, base, per), base);
+        // 不足一千帧按一千帧计算
+        assert_eq!(task_deadline(1, base, per), Duration::from_secs(630));
+        assert_eq!(task_deadline(1000, base, per), Duration::from_secs(630));
+        assert_eq!(
+            task_deadline(25 * 3600, base, per),
+            Duration::from_secs(600 + 90 * 30)
+        );
+    }
+}
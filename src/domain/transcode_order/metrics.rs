@@ -0,0 +1,85 @@
+//! 转码结果的质量指标，由转码工厂在任务完成时计算并上报
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QualityMetrics {
+    /// 0 ~ 100，越高越接近源视频
+    #[serde(default)]
+    pub vmaf: Option<f64>,
+    /// 单位 dB
+    #[serde(default)]
+    pub psnr: Option<f64>,
+    /// 源视频的码率，bit/s
+    #[serde(default)]
+    pub source_bitrate: Option<u64>,
+    /// 转码结果的码率，bit/s
+    #[serde(default)]
+    pub output_bitrate: Option<u64>,
+}
+
+impl QualityMetrics {
+    /// 丢弃明显错误的值，避免影响展示和比较
+    pub fn sanitized(self) -> Self {
+        Self {
+            vmaf: self.vmaf.filter(|v| (0.0..=100.0).contains(v)),
+            psnr: self.psnr.filter(|v| v.is_finite() && *v >= 0.0),
+            source_bitrate: self.source_bitrate.filter(|b| *b > 0),
+            output_bitrate: self.output_bitrate,
+        }
+    }
+}
+
+/// 相比源视频节省的码率百分比，转码结果更大时为负数
+pub fn bitrate_savings(source_bitrate: Option<u64>, output_bitrate: Option<u64>) -> Option<f64> {
+    let source = source_bitrate.filter(|b| *b > 0)? as f64;
+    let output = output_bitrate? as f64;
+    Some((source - output) / source * 100.0)
+}
+
+/// 压缩比，源文件大小 / 转码结果大小，保留两位小数
+pub fn compression_ratio(source_size: u64, output_size: u64) -> Option<f64> {
+    if source_size == 0 || output_size == 0 {
+        return None;
+    }
+    let ratio = source_size as f64 / output_size as f64;
+    Some((ratio * 100.0).round() / 100.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_bitrate_savings() {
+        assert_eq!(bitrate_savings(Some(8000), Some(2000)), Some(75.0));
+        assert_eq!(bitrate_savings(Some(1000), Some(1500)), Some(-50.0));
+        assert_eq!(bitrate_savings(Some(0), Some(1500)), None);
+        assert_eq!(bitrate_savings(None, Some(1500)), None);
+        assert_eq!(bitrate_savings(Some(1000), None), None);
+    }
+
+    #[test]
+    fn t_compression_ratio() {
+        assert_eq!(compression_ratio(8000, 2000), Some(4.0));
+        assert_eq!(compression_ratio(1000, 3000), Some(0.33));
+        assert_eq!(compression_ratio(0, 3000), None);
+        assert_eq!(compression_ratio(1000, 0), None);
+    }
+
+    #[test]
+    fn t_deserialize() {
+        // 旧版本的工厂不上报指标
+        let metrics: Option<QualityMetrics> = serde_json::from_str("null").unwrap();
+        assert!(metrics.is_none());
+
+        let metrics: QualityMetrics =
+            serde_json::from_str(r#"{"vmaf": 120.0, "psnr": 42.5, "outputBitrate": 2000}"#)
+                .unwrap();
+        let metrics = metrics.sanitized();
+        assert_eq!(metrics.vmaf, None);
+        assert_eq!(metrics.psnr, Some(42.5));
+        assert_eq!(metrics.output_bitrate, Some(2000));
+    }
+}
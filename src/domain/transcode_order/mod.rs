@@ -5,10 +5,18 @@ use super::{
     file_system::file::{SysFileId, UserFileId},
     user::user::UserId,
 };
-use crate::id_wraper;
+use crate::{id_wraper, LocalDataTime};
 
+pub mod compat;
+pub mod deadline;
+pub mod metrics;
 pub mod params;
+pub mod preview;
+pub mod pricing;
+pub mod recommend;
+pub mod rule;
 pub mod service;
+pub mod worker;
 
 id_wraper!(TranscodeOrderId);
 id_wraper!(TranscodeTaskId);
@@ -19,6 +27,8 @@ pub struct TranscocdeOrder {
     id: TranscodeOrderId,
     user_id: UserId,
     status: OrderStatus,
+    /// 预约的开始时间，到达之前任务不会下发
+    start_after: Option<LocalDataTime>,
     #[getset(skip)]
     tasks: Vec<TranscodeTask>,
 }
@@ -30,6 +40,7 @@ pub enum OrderStatus {
     Ok,
     Failed,
     Cancelled,
+    Scheduled,
 }
 
 #[derive(Getters)]
@@ -42,6 +53,8 @@ pub struct TranscodeTask {
     order_id: TranscodeOrderId,
     params: TranscodeTaskParams,
     status: TaskStatus,
+    /// 创建任务时扣除的积分
+    credits: u64,
 }
 
 #[derive(derive_more::IsVariant)]
@@ -51,12 +64,15 @@ pub enum TaskStatus {
     Ok,
     Failed(String),
     Cancelled,
+    /// 等待预约时间到达
+    Scheduled,
 }
 
 impl TaskStatus {
     fn is_end(&self) -> bool {
         match self {
             TaskStatus::Processing => false,
+            TaskStatus::Scheduled => false,
             TaskStatus::Ok => true,
             TaskStatus::Failed(_) => true,
             TaskStatus::Cancelled => true,
@@ -72,10 +88,19 @@ impl TranscocdeOrder {
         &mut self.tasks
     }
 
+    /// 订单消耗的总积分
+    pub fn credits(&self) -> u64 {
+        self.tasks.iter().map(|task| task.credits).sum()
+    }
+
     pub fn task_completed(&mut self, task_id: TranscodeTaskId, result: Result<(), String>) {
         let Some(task) = self.tasks.iter_mut().find(|task| task.id() == &task_id) else {
             return;
         };
+        // 已取消的任务不再接收转码结果
+        if task.status.is_end() {
+            return;
+        }
         if let Err(err) = result {
             task.status = TaskStatus::Failed(err);
         } else {
@@ -90,6 +115,62 @@ impl TranscocdeOrder {
             }
         }
     }
+
+    /// 取消订单中还未完成的任务，返回需要退还的积分
+    pub fn cancel(&mut self) -> u64 {
+        let mut refund = 0;
+        for task in &mut self.tasks {
+            if !task.status.is_end() {
+                task.status = TaskStatus::Cancelled;
+                refund += task.credits;
+            }
+        }
+        self.status = OrderStatus::Cancelled;
+        refund
+    }
+
+    pub fn is_processing(&self) -> bool {
+        matches!(self.status, OrderStatus::Processing)
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        matches!(self.status, OrderStatus::Scheduled)
+    }
+
+    /// 预约时间已到，开始转码。订单不是预约状态时返回 false
+    pub fn dispatch(&mut self) -> bool {
+        if !self.is_scheduled() {
+            return false;
+        }
+        for task in &mut self.tasks {
+            if task.status.is_scheduled() {
+                task.status = TaskStatus::Processing;
+            }
+        }
+        self.status = OrderStatus::Processing;
+        true
+    }
+
+    /// 修改预约时间，订单已经开始转码时返回 false
+    pub fn reschedule(&mut self, start_after: LocalDataTime) -> bool {
+        if !self.is_scheduled() {
+            return false;
+        }
+        self.start_after = Some(start_after);
+        true
+    }
+
+    /// 所有任务都已转码结束（不包括用户取消的订单）
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, OrderStatus::Ok | OrderStatus::Failed)
+    }
+
+    /// 转码成功和失败的任务数
+    pub fn task_counts(&self) -> (usize, usize) {
+        let ok = self.tasks.iter().filter(|t| t.status.is_ok()).count();
+        let failed = self.tasks.iter().filter(|t| t.status.is_failed()).count();
+        (ok, failed)
+    }
 }
 
 mod convert {
@@ -112,6 +193,7 @@ mod convert {
                     id: *self.id(),
                     user_id: *self.user_id(),
                     status: self.status as i16,
+                    start_after: self.start_after,
                 },
                 tasks,
             }
@@ -131,8 +213,10 @@ mod convert {
                     1 => OrderStatus::Ok,
                     2 => OrderStatus::Failed,
                     3 => OrderStatus::Cancelled,
+                    4 => OrderStatus::Scheduled,
                     _ => bail!("invalid order status"),
                 },
+                start_after: order.order.start_after,
                 tasks,
             };
 
@@ -155,11 +239,13 @@ mod convert {
                     TaskStatus::Ok => 1,
                     TaskStatus::Failed(_) => 2,
                     TaskStatus::Cancelled => 3,
+                    TaskStatus::Scheduled => 4,
                 },
                 err_msg: match &self.status {
                     TaskStatus::Failed(err) => Some(Cow::Borrowed(err)),
                     _ => None,
                 },
+                credits: self.credits as i64,
             }
         }
 
@@ -170,6 +256,7 @@ mod convert {
                 1 => TaskStatus::Ok,
                 2 => TaskStatus::Failed(po.err_msg.unwrap().into_owned()),
                 3 => TaskStatus::Cancelled,
+                4 => TaskStatus::Scheduled,
                 _ => bail!("invalid task status"),
             };
             Ok(Self {
@@ -180,6 +267,7 @@ mod convert {
                 order_id: po.order_id,
                 params,
                 status,
+                credits: po.credits as u64,
             })
         }
     }
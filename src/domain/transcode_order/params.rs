@@ -1,8 +1,13 @@
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use self::{audio::AudioProcessParameters, zcode::ZcodeProcessParams};
+use crate::domain::{
+    file_system::subtitle::SubtitleFormat,
+    transcode_order::{preview::SampleRange, TranscodeTaskId},
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TranscodeTaskParams {
@@ -15,9 +20,39 @@ pub struct TranscodeTaskParams {
     pub container: ContainerFormat,
     pub video: ZcodeProcessParams,
     pub audio: Option<AudioProcessParameters>,
+    #[serde(default)]
+    pub subtitles: Vec<SubtitleParams>,
+    /// 预览订单只转码这一段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleRange>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+impl TranscodeTaskParams {
+    /// 转码工厂上传的任务日志，同一个源文件的所有任务共用工作目录
+    pub fn log_path(&self, task_id: TranscodeTaskId) -> PathBuf {
+        self.work_dir.join("logs").join(format!("{}.log", task_id))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleParams {
+    pub path: PathBuf,
+    pub format: SubtitleFormat,
+    pub language: Option<String>,
+    pub mode: SubtitleMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum SubtitleMode {
+    /// 封装为独立的字幕轨道
+    Mux,
+    /// 烧录到画面中，最多只能有一个
+    Burn,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
 pub enum ContainerFormat {
     #[serde(rename = "mp4")]
     Mp4,
@@ -39,8 +74,9 @@ impl ContainerFormat {
 
 pub mod zcode {
     use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
 
-    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy)]
+    #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy, ToSchema)]
     #[serde(rename_all = "camelCase")]
     pub struct ZcodeProcessParams {
         pub is_hdr: bool,
@@ -52,7 +88,7 @@ pub mod zcode {
         pub quality: OutputQuality,
     }
 
-    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
     #[serde(rename_all = "lowercase")]
     pub enum VideoFormat {
         Av1,
@@ -60,7 +96,7 @@ pub mod zcode {
         H265,
     }
 
-    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
     pub enum Resolution {
         #[serde(rename = "_144p")]
         _144P,
@@ -80,7 +116,7 @@ pub mod zcode {
         _4K,
     }
 
-    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
     #[repr(i16)]
     #[serde(rename_all = "camelCase")]
     pub enum RayTracing {
@@ -90,7 +126,7 @@ pub mod zcode {
         Lagacy = 25,
     }
 
-    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
     #[repr(i16)]
     #[serde(rename_all = "camelCase")]
     pub enum OutputQuality {
@@ -121,6 +157,34 @@ pub mod zcode {
     }
 
     impl Resolution {
+        /// 按短边选择不超过原视频的标准分辨率
+        pub fn fit(width: u32, height: u32) -> Self {
+            match width.min(height) {
+                2160.. => Self::_4K,
+                1440.. => Self::_1440P,
+                1080.. => Self::_1080P,
+                720.. => Self::_720P,
+                480.. => Self::_480P,
+                360.. => Self::_360P,
+                240.. => Self::_240P,
+                _ => Self::_144P,
+            }
+        }
+
+        /// 短边的像素数
+        pub fn short_side(&self) -> u32 {
+            match self {
+                Resolution::_144P => 144,
+                Resolution::_240P => 240,
+                Resolution::_360P => 360,
+                Resolution::_480P => 480,
+                Resolution::_720P => 720,
+                Resolution::_1080P => 1080,
+                Resolution::_1440P => 1440,
+                Resolution::_4K => 2160,
+            }
+        }
+
         pub fn to_str(&self) -> &'static str {
             match self {
                 Resolution::_144P => "144p",
@@ -138,8 +202,9 @@ pub mod zcode {
 
 pub mod audio {
     use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
 
-    #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+    #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, ToSchema)]
     #[serde(rename_all = "camelCase")]
     pub struct AudioProcessParameters {
         pub format: AudioFormat,
@@ -148,7 +213,7 @@ pub mod audio {
         pub track: AudioTrack,
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
     #[serde(rename_all = "lowercase")]
     pub enum AudioFormat {
         AAC,
@@ -164,7 +229,7 @@ pub mod audio {
         }
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
     #[repr(u32)]
     pub enum AudioResampleRate {
         _22050 = 22050,
@@ -172,7 +237,7 @@ pub mod audio {
         _48000 = 48000,
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
     #[repr(u32)]
     pub enum AudioBitRate {
         #[serde(rename = "_16")]
@@ -193,7 +258,7 @@ pub mod audio {
         _640 = 640,
     }
 
-    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
     pub enum AudioTrack {
         #[serde(rename = "_1")]
         _1 = 1,
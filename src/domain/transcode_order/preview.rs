@@ -0,0 +1,72 @@
+//! 预览转码，只转码视频中的一段，用于在正式转码前快速比较参数
+
+use serde::{Deserialize, Serialize};
+
+/// 转码的片段，单位毫秒
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleRange {
+    pub start_ms: u32,
+    pub duration_ms: u32,
+}
+
+impl SampleRange {
+    /// 从 start_ms 开始截取 duration_ms，超出视频的部分截掉。
+    /// 没有指定开始位置时从视频中间截取，视频不够长时转码整个视频
+    pub fn new(start_ms: Option<u32>, duration_ms: u32, total_ms: u32) -> Self {
+        let duration_ms = duration_ms.min(total_ms);
+        let start_ms = match start_ms {
+            Some(start_ms) => start_ms.min(total_ms - duration_ms),
+            None => (total_ms - duration_ms) / 2,
+        };
+        Self {
+            start_ms,
+            duration_ms,
+        }
+    }
+
+    /// 按比例估算片段的帧数，至少为 1
+    pub fn frame_count(&self, total_frames: u32, total_ms: u32) -> u32 {
+        if total_ms == 0 {
+            return total_frames;
+        }
+        let frames = total_frames as u64 * self.duration_ms as u64 / total_ms as u64;
+        (frames as u32).max(1)
+    }
+
+    /// 加在转码结果文件名中，区分同一参数的不同片段和完整转码的结果
+    pub fn file_name_suffix(&self) -> String {
+        format!("_preview_{}_{}", self.start_ms, self.duration_ms)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_sample_range() {
+        let range = SampleRange::new(None, 30_000, 600_000);
+        assert_eq!(range.start_ms, 285_000);
+        assert_eq!(range.duration_ms, 30_000);
+
+        // 视频不够长
+        let range = SampleRange::new(None, 30_000, 10_000);
+        assert_eq!((range.start_ms, range.duration_ms), (0, 10_000));
+
+        // 指定的开始位置超出视频
+        let range = SampleRange::new(Some(590_000), 30_000, 600_000);
+        assert_eq!(range.start_ms, 570_000);
+        let range = SampleRange::new(Some(1_000), 30_000, 600_000);
+        assert_eq!(range.start_ms, 1_000);
+    }
+
+    #[test]
+    fn t_frame_count() {
+        let range = SampleRange::new(None, 30_000, 600_000);
+        assert_eq!(range.frame_count(15_000, 600_000), 750);
+        let range = SampleRange::new(None, 1, 600_000);
+        assert_eq!(range.frame_count(15_000, 600_000), 1);
+        assert_eq!(range.frame_count(15_000, 0), 15_000);
+    }
+}
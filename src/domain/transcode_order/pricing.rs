@@ -0,0 +1,119 @@
+//! 按转码后的视频时长计费，价格由编码格式和分辨率决定
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::domain::file_system::file::VideoInfo;
+
+use super::params::zcode::{Resolution, VideoFormat, ZcodeProcessParams};
+
+/// 无法获取视频时长时，按此帧率估算
+const FALLBACK_FPS: u64 = 25;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Pricing {
+    /// 各编码格式下，每分钟 1080P 视频的价格
+    pub codecs: HashMap<VideoFormat, u64>,
+    /// 各分辨率相对于 1080P 的价格比例（百分比），未配置的分辨率按 100 计算
+    #[serde(default)]
+    pub resolutions: HashMap<Resolution, u64>,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            codecs: HashMap::from([
+                (VideoFormat::Av1, 10),
+                (VideoFormat::H265, 6),
+                (VideoFormat::H264, 4),
+            ]),
+            resolutions: HashMap::from([
+                (Resolution::_144P, 20),
+                (Resolution::_240P, 25),
+                (Resolution::_360P, 30),
+                (Resolution::_480P, 40),
+                (Resolution::_720P, 60),
+                (Resolution::_1080P, 100),
+                (Resolution::_1440P, 200),
+                (Resolution::_4K, 400),
+            ]),
+        }
+    }
+}
+
+impl Pricing {
+    /// 计算一个转码任务消耗的积分，不足一分钟的部分按一分钟计算
+    pub fn task_cost(&self, video: &VideoInfo, params: &ZcodeProcessParams) -> u64 {
+        let duration_ms = match video.duration_ms {
+            Some(duration_ms) => duration_ms as u64,
+            None => video.frame_count as u64 * 1000 / FALLBACK_FPS,
+        };
+        let minutes = ((duration_ms + 59_999) / 60_000).max(1);
+
+        let resolution = params
+            .resolution
+            .unwrap_or_else(|| Resolution::fit(video.width, video.height));
+        let per_minute = self.codecs.get(&params.format).copied().unwrap_or_default();
+        let percent = self.resolutions.get(&resolution).copied().unwrap_or(100);
+
+        (minutes * per_minute * percent + 99) / 100
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::domain::transcode_order::params::zcode::OutputQuality;
+
+    use super::*;
+
+    fn video(duration_ms: Option<u32>) -> VideoInfo {
+        VideoInfo {
+            frame_count: 25 * 90,
+            width: 1920,
+            height: 1080,
+            hdr_format: None,
+            is_h264: true,
+            format: None,
+            bit_rate: None,
+            duration_ms,
+        }
+    }
+
+    fn params(format: VideoFormat, resolution: Option<Resolution>) -> ZcodeProcessParams {
+        ZcodeProcessParams {
+            is_hdr: false,
+            width: 1920,
+            height: 1080,
+            format,
+            resolution,
+            ray_tracing: None,
+            quality: OutputQuality::High,
+        }
+    }
+
+    #[test]
+    fn t_task_cost() {
+        let pricing = Pricing::default();
+
+        // 90 秒按 2 分钟计算，未指定分辨率时使用原视频分辨率
+        let cost = pricing.task_cost(&video(Some(90 * 1000)), &params(VideoFormat::Av1, None));
+        assert_eq!(cost, 20);
+
+        // 缺少时长时按帧数估算
+        let cost = pricing.task_cost(&video(None), &params(VideoFormat::Av1, None));
+        assert_eq!(cost, 20);
+
+        let cost = pricing.task_cost(
+            &video(Some(60 * 1000)),
+            &params(VideoFormat::H264, Some(Resolution::_720P)),
+        );
+        assert_eq!(cost, 3);
+
+        let cost = pricing.task_cost(
+            &video(Some(10 * 60 * 1000)),
+            &params(VideoFormat::Av1, Some(Resolution::_4K)),
+        );
+        assert_eq!(cost, 400);
+    }
+}
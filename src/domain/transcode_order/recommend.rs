@@ -0,0 +1,156 @@
+//! 根据视频元数据推荐转码参数，结果只用于前端预填，估算值都是经验值
+
+use crate::domain::file_system::file::VideoInfo;
+
+use super::params::zcode::{OutputQuality, Resolution, VideoFormat, ZcodeProcessParams};
+
+#[derive(Debug)]
+pub struct Recommendation {
+    pub video: ZcodeProcessParams,
+    /// 转为 AV1 是否能明显减小体积
+    pub av1_beneficial: bool,
+    /// 预计输出文件大小（byte）
+    pub estimated_size: Option<u64>,
+    /// 输出视频的时长（毫秒），与原视频相同
+    pub duration_ms: Option<u32>,
+    /// 预计转码耗时（秒）
+    pub estimated_encode_secs: u64,
+}
+
+pub fn recommend(video: &VideoInfo) -> Recommendation {
+    let resolution = Resolution::fit(video.width, video.height);
+    let is_hdr = video.hdr_format.is_some();
+    // HDR 视频不能使用 base 质量，见 compat::check
+    let quality = match recommend_quality(video) {
+        OutputQuality::Base if is_hdr => OutputQuality::High,
+        quality => quality,
+    };
+    let av1_beneficial = !video
+        .format
+        .as_ref()
+        .is_some_and(|format| format.eq_ignore_ascii_case("av1"));
+
+    let params = ZcodeProcessParams {
+        is_hdr,
+        width: video.width,
+        height: video.height,
+        format: VideoFormat::Av1,
+        resolution: Some(resolution),
+        ray_tracing: None,
+        quality,
+    };
+
+    let estimated_size = video.duration_ms.map(|duration_ms| {
+        let bit_rate = estimate_bit_rate(resolution, quality, video.bit_rate);
+        bit_rate * duration_ms as u64 / 1000 / 8
+    });
+
+    Recommendation {
+        video: params,
+        av1_beneficial,
+        estimated_size,
+        duration_ms: video.duration_ms,
+        estimated_encode_secs: video.frame_count as u64 / encode_fps(resolution),
+    }
+}
+
+/// 根据原视频每个像素分到的码率选择输出质量，原视频质量越高，推荐的输出质量越高
+fn recommend_quality(video: &VideoInfo) -> OutputQuality {
+    let (Some(bit_rate), Some(duration_ms)) = (video.bit_rate, video.duration_ms) else {
+        return OutputQuality::High;
+    };
+    if duration_ms == 0 || video.width == 0 || video.height == 0 {
+        return OutputQuality::High;
+    }
+
+    let fps = video.frame_count as f64 * 1000.0 / duration_ms as f64;
+    let bits_per_pixel = bit_rate as f64 / (video.width as f64 * video.height as f64 * fps);
+    if bits_per_pixel > 0.1 {
+        OutputQuality::Top
+    } else if bits_per_pixel > 0.05 {
+        OutputQuality::High
+    } else {
+        OutputQuality::Base
+    }
+}
+
+/// AV1 输出码率（bps），不会超过原视频码率的 60%
+fn estimate_bit_rate(resolution: Resolution, quality: OutputQuality, source: Option<u32>) -> u64 {
+    let base: u64 = match resolution {
+        Resolution::_144P => 150_000,
+        Resolution::_240P => 300_000,
+        Resolution::_360P => 500_000,
+        Resolution::_480P => 800_000,
+        Resolution::_720P => 1_500_000,
+        Resolution::_1080P => 3_000_000,
+        Resolution::_1440P => 6_000_000,
+        Resolution::_4K => 12_000_000,
+    };
+    let bit_rate = match quality {
+        OutputQuality::Base => base * 7 / 10,
+        OutputQuality::High => base,
+        OutputQuality::Top => base * 3 / 2,
+    };
+
+    match source {
+        Some(source) => bit_rate.min(source as u64 * 6 / 10),
+        None => bit_rate,
+    }
+}
+
+/// 单个节点的转码速度（帧/秒）
+fn encode_fps(resolution: Resolution) -> u64 {
+    match resolution.short_side() {
+        2160.. => 8,
+        1440.. => 15,
+        1080.. => 25,
+        720.. => 50,
+        _ => 80,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn video(width: u32, height: u32, bit_rate: Option<u32>) -> VideoInfo {
+        VideoInfo {
+            frame_count: 25 * 60,
+            width,
+            height,
+            hdr_format: None,
+            is_h264: true,
+            format: Some("AVC".to_string()),
+            bit_rate,
+            duration_ms: Some(60 * 1000),
+        }
+    }
+
+    #[test]
+    fn t_recommend() {
+        let r = recommend(&video(1920, 1080, Some(8_000_000)));
+        assert_eq!(r.video.resolution, Some(Resolution::_1080P));
+        assert_eq!(r.video.quality, OutputQuality::High);
+        assert!(r.av1_beneficial);
+        assert_eq!(r.estimated_size, Some(3_000_000 * 60 / 8));
+        assert_eq!(r.estimated_encode_secs, 60);
+
+        // 竖屏视频按短边计算
+        let r = recommend(&video(720, 1280, None));
+        assert_eq!(r.video.resolution, Some(Resolution::_720P));
+        assert_eq!(r.video.quality, OutputQuality::High);
+
+        // 低码率视频
+        let r = recommend(&video(1280, 720, Some(500_000)));
+        assert_eq!(r.video.quality, OutputQuality::Base);
+        assert_eq!(r.estimated_size, Some(300_000 * 60 / 8));
+
+        let mut hdr = video(1280, 720, Some(500_000));
+        hdr.hdr_format = Some("SMPTE ST 2086".to_string());
+        assert_eq!(recommend(&hdr).video.quality, OutputQuality::High);
+
+        let mut av1 = video(1920, 1080, None);
+        av1.format = Some("AV1".to_string());
+        assert!(!recommend(&av1).av1_beneficial);
+    }
+}
@@ -0,0 +1,183 @@
+//! 自动转码规则
+//!
+//! 用户为 /源视频 下的目录设置转码参数，之后上传到这个目录的视频自动创建转码订单。
+//! 规则按路径匹配，目录被重命名或移动后规则不再生效
+
+use getset::Getters;
+
+use crate::{
+    domain::{file_system::file::VirtualPath, user::user::UserId},
+    ensure_ok, id_wraper,
+};
+
+id_wraper!(TranscodeRuleId);
+
+/// 每个用户最多创建的规则数量
+pub const MAX_RULES_PER_USER: i64 = 20;
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug)]
+pub enum RuleErr {
+    InvalidName,
+    /// 只能监听 /源视频 下的目录
+    InvalidDir,
+}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct TranscodeRule {
+    id: TranscodeRuleId,
+    user_id: UserId,
+    name: String,
+    watch_dir: VirtualPath,
+    /// 是否包括子目录中的文件
+    recursive: bool,
+    enabled: bool,
+    /// 转码参数的 json，由应用层解析
+    params: String,
+}
+
+impl TranscodeRule {
+    pub fn create(
+        user_id: UserId,
+        name: String,
+        watch_dir: &str,
+        recursive: bool,
+        params: String,
+    ) -> Result<Self, RuleErr> {
+        let mut rule = Self {
+            id: TranscodeRuleId::next_id(),
+            user_id,
+            name: String::new(),
+            watch_dir: VirtualPath::resource_dir(user_id),
+            recursive,
+            enabled: true,
+            params,
+        };
+        rule.rename(name)?;
+        rule.watch(watch_dir)?;
+        Ok(rule)
+    }
+
+    pub fn from_raw(
+        id: TranscodeRuleId,
+        user_id: UserId,
+        name: String,
+        watch_dir: &str,
+        recursive: bool,
+        enabled: bool,
+        params: String,
+    ) -> Self {
+        Self {
+            id,
+            user_id,
+            name,
+            watch_dir: parse_dir(user_id, watch_dir)
+                .unwrap_or_else(|_| VirtualPath::resource_dir(user_id)),
+            recursive,
+            enabled,
+            params,
+        }
+    }
+
+    pub fn rename(&mut self, name: String) -> Result<(), RuleErr> {
+        let name = name.trim();
+        ensure_ok!(
+            !name.is_empty() && name.chars().count() <= MAX_NAME_LEN,
+            RuleErr::InvalidName
+        );
+        self.name = name.to_string();
+        Ok(())
+    }
+
+    pub fn watch(&mut self, watch_dir: &str) -> Result<(), RuleErr> {
+        self.watch_dir = parse_dir(self.user_id, watch_dir)?;
+        Ok(())
+    }
+
+    pub fn set_recursive(&mut self, recursive: bool) {
+        self.recursive = recursive;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_params(&mut self, params: String) {
+        self.params = params;
+    }
+
+    /// 文件是否在监听的目录中
+    pub fn matches(&self, file_path: &VirtualPath) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if file_path.parent().as_ref() == Some(&self.watch_dir) {
+            return true;
+        }
+        self.recursive && file_path.relative_to(&self.watch_dir).is_some()
+    }
+}
+
+fn parse_dir(user_id: UserId, dir: &str) -> Result<VirtualPath, RuleErr> {
+    let source = VirtualPath::resource_dir(user_id);
+    if dir.trim_end_matches('/') == source.to_str() {
+        return Ok(source);
+    }
+    let dir = VirtualPath::build(user_id, dir).map_err(|_| RuleErr::InvalidDir)?;
+    // 转码结果在 /已转码视频 下，监听这个目录会重复转码
+    ensure_ok!(dir.relative_to(&source).is_some(), RuleErr::InvalidDir);
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(dir: &str, recursive: bool) -> TranscodeRule {
+        TranscodeRule::create(UserId(1), "incoming".into(), dir, recursive, "{}".into()).unwrap()
+    }
+
+    fn path(path: &str) -> VirtualPath {
+        VirtualPath::build(UserId(1), path).unwrap()
+    }
+
+    #[test]
+    fn t_matches() {
+        let r = rule("/源视频/incoming", false);
+        assert!(r.matches(&path("/源视频/incoming/a.mp4")));
+        assert!(!r.matches(&path("/源视频/incoming/sub/a.mp4")));
+        assert!(!r.matches(&path("/源视频/other/a.mp4")));
+        assert!(!r.matches(&VirtualPath::build(UserId(2), "/源视频/incoming/a.mp4").unwrap()));
+
+        let mut r = rule("/源视频/incoming", true);
+        assert!(r.matches(&path("/源视频/incoming/sub/a.mp4")));
+        assert!(!r.matches(&path("/源视频/incoming2/a.mp4")));
+        r.set_enabled(false);
+        assert!(!r.matches(&path("/源视频/incoming/a.mp4")));
+
+        let r = rule("/源视频/", false);
+        assert!(r.matches(&path("/源视频/a.mp4")));
+    }
+
+    #[test]
+    fn t_invalid() {
+        let create = |name: &str, dir: &str| {
+            TranscodeRule::create(UserId(1), name.into(), dir, false, "{}".into()).map(|_| ())
+        };
+        assert!(matches!(
+            create("a", "/已转码视频/a"),
+            Err(RuleErr::InvalidDir)
+        ));
+        assert!(matches!(
+            create("a", "/源视频/../a"),
+            Err(RuleErr::InvalidDir)
+        ));
+        assert!(matches!(create("a", "/"), Err(RuleErr::InvalidDir)));
+        assert!(matches!(
+            create(" ", "/源视频/a"),
+            Err(RuleErr::InvalidName)
+        ));
+    }
+}
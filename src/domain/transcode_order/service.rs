@@ -1,32 +1,47 @@
-use crate::domain::{
-    file_system::file::FileNode,
-    transcode_order::{OrderStatus, TaskStatus, TranscodeOrderId, TranscodeTask, TranscodeTaskId},
-    user::user::UserId,
+use crate::{
+    domain::{
+        file_system::file::FileNode,
+        transcode_order::{
+            OrderStatus, TaskStatus, TranscodeOrderId, TranscodeTask, TranscodeTaskId,
+        },
+        user::user::UserId,
+    },
+    LocalDataTime,
 };
 
 use super::{params::TranscodeTaskParams, TranscocdeOrder};
 
+/// 指定 start_after 时创建预约订单，任务在预约时间到达后才下发
 pub fn create_order(
     user_id: UserId,
-    params: Vec<(FileNode, TranscodeTaskParams)>,
+    params: Vec<(FileNode, TranscodeTaskParams, u64)>,
+    start_after: Option<LocalDataTime>,
 ) -> TranscocdeOrder {
     let order_id = TranscodeOrderId::next_id();
     let tasks = params
         .into_iter()
-        .map(|(file, params)| TranscodeTask {
+        .map(|(file, params, credits)| TranscodeTask {
             id: TranscodeTaskId::next_id(),
             virtual_path: file.path().to_str().to_string(),
             sys_file_id: file.file_data().unwrap().id,
             user_file_id: *file.id(),
             order_id,
             params,
-            status: TaskStatus::Processing,
+            status: match start_after {
+                Some(_) => TaskStatus::Scheduled,
+                None => TaskStatus::Processing,
+            },
+            credits,
         })
         .collect();
     let order = TranscocdeOrder {
         id: order_id,
         user_id,
-        status: OrderStatus::Processing,
+        status: match start_after {
+            Some(_) => OrderStatus::Scheduled,
+            None => OrderStatus::Processing,
+        },
+        start_after,
         tasks,
     };
     order
@@ -0,0 +1,125 @@
+//! 转码节点
+//!
+//! 节点启动时注册自己支持的编码格式、最大分辨率和并发数，之后定期发送心跳并上报正在处理的任务数。
+//! 超过一定时间没有心跳的节点视为下线，分配给它的任务需要重新下发
+
+use super::params::{zcode::VideoFormat, TranscodeTaskParams};
+use crate::id_wraper;
+
+id_wraper!(WorkerId);
+
+/// 节点注册时声明的能力
+#[derive(Debug, Clone)]
+pub struct WorkerCapabilities {
+    pub codecs: Vec<VideoFormat>,
+    pub max_width: u32,
+    pub max_height: u32,
+    /// 同时处理的任务数
+    pub concurrency: u32,
+}
+
+impl WorkerCapabilities {
+    /// 能否处理这个任务，竖屏视频按旋转后的尺寸比较
+    pub fn supports(&self, params: &TranscodeTaskParams) -> bool {
+        let video = &params.video;
+        let (long, short) = (video.width.max(video.height), video.width.min(video.height));
+        self.codecs.contains(&video.format)
+            && long <= self.max_width.max(self.max_height)
+            && short <= self.max_width.min(self.max_height)
+    }
+}
+
+/// 在线的节点
+#[derive(Debug, Clone)]
+pub struct Worker {
+    pub id: WorkerId,
+    pub endpoint: String,
+    pub capabilities: WorkerCapabilities,
+    /// 正在处理的任务数，心跳时更新，分配任务时加一
+    pub running: u32,
+}
+
+impl Worker {
+    fn has_slot(&self) -> bool {
+        self.running < self.capabilities.concurrency
+    }
+
+    /// 已使用的并发比例，用于挑选最空闲的节点
+    fn load(&self) -> f64 {
+        self.running as f64 / self.capabilities.concurrency.max(1) as f64
+    }
+}
+
+/// 选出能处理任务且负载最低的节点，所有节点都已满载或不支持时返回 None
+pub fn pick_worker<'a>(workers: &'a [Worker], params: &TranscodeTaskParams) -> Option<&'a Worker> {
+    workers
+        .iter()
+        .filter(|w| w.has_slot() && w.capabilities.supports(params))
+        .min_by(|a, b| a.load().total_cmp(&b.load()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params(format: VideoFormat, width: u32, height: u32) -> TranscodeTaskParams {
+        serde_json::from_value(serde_json::json!({
+            "work_dir": "/tmp",
+            "path": "/tmp/a.mp4",
+            "dst_path": "/tmp/b.mp4",
+            "frame_count": 250,
+            "is_h264": true,
+            "container": "mp4",
+            "video": {
+                "isHdr": false,
+                "width": width,
+                "height": height,
+                "format": format,
+                "resolution": null,
+                "rayTracing": null,
+                "quality": "base",
+            },
+            "audio": null,
+        }))
+        .unwrap()
+    }
+
+    fn worker(id: i64, codecs: Vec<VideoFormat>, running: u32, concurrency: u32) -> Worker {
+        Worker {
+            id: WorkerId(id),
+            endpoint: format!("http://worker-{id}"),
+            capabilities: WorkerCapabilities {
+                codecs,
+                max_width: 1920,
+                max_height: 1080,
+                concurrency,
+            },
+            running,
+        }
+    }
+
+    #[test]
+    fn t_supports() {
+        let w = worker(1, vec![VideoFormat::Av1], 0, 1);
+        let caps = &w.capabilities;
+        assert!(caps.supports(&params(VideoFormat::Av1, 1920, 1080)));
+        // 竖屏视频
+        assert!(caps.supports(&params(VideoFormat::Av1, 1080, 1920)));
+        assert!(!caps.supports(&params(VideoFormat::Av1, 3840, 2160)));
+        assert!(!caps.supports(&params(VideoFormat::H265, 1280, 720)));
+    }
+
+    #[test]
+    fn t_pick_worker() {
+        let task = params(VideoFormat::Av1, 1280, 720);
+        let workers = vec![
+            worker(1, vec![VideoFormat::Av1], 2, 2),
+            worker(2, vec![VideoFormat::Av1, VideoFormat::H265], 1, 4),
+            worker(3, vec![VideoFormat::Av1], 1, 2),
+            worker(4, vec![VideoFormat::H264], 0, 4),
+        ];
+        assert_eq!(pick_worker(&workers, &task).unwrap().id, WorkerId(2));
+        assert!(pick_worker(&workers[..1], &task).is_none());
+        assert!(pick_worker(&workers[3..], &task).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! 用户头像
+//!
+//! 只接受 jpeg、png 和 webp，类型根据文件内容识别。
+//! 图片尺寸从文件头解析，不解码整张图片
+
+/// 头像文件的最大字节数
+pub const MAX_SIZE: u64 = 2 * 1024 * 1024;
+
+/// 解析尺寸需要读取的开头字节数，jpeg 的 EXIF 等信息可能在尺寸之前
+pub const HEAD_LEN: usize = 64 * 1024;
+
+pub const ALLOWED_MIMES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+
+const MIN_SIDE: u32 = 32;
+const MAX_SIDE: u32 = 4096;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AvatarErr {
+    /// 不是支持的图片格式，或文件头损坏
+    InvalidImage,
+    /// 宽或高不在 32 到 4096 像素之间
+    InvalidSize(u32, u32),
+}
+
+/// 检查图片的类型和尺寸，mime 为识别出的类型，head 为文件开头的内容
+pub fn check(mime: &str, head: &[u8]) -> Result<(u32, u32), AvatarErr> {
+    if !ALLOWED_MIMES.contains(&mime) {
+        return Err(AvatarErr::InvalidImage);
+    }
+    let (width, height) = image_size(head).ok_or(AvatarErr::InvalidImage)?;
+    let valid = |side| (MIN_SIDE..=MAX_SIDE).contains(&side);
+    if !valid(width) || !valid(height) {
+        return Err(AvatarErr::InvalidSize(width, height));
+    }
+    Ok((width, height))
+}
+
+/// 从文件头解析图片的宽和高
+pub fn image_size(head: &[u8]) -> Option<(u32, u32)> {
+    if head.starts_with(b"\x89PNG\r\n\x1A\n") {
+        return png_size(head);
+    }
+    if head.starts_with(b"\xFF\xD8") {
+        return jpeg_size(head);
+    }
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WEBP") {
+        return webp_size(head);
+    }
+    None
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_be_bytes([b[0], b[1]]) as u32)
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]) as u32)
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 3)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+}
+
+/// 第一个块必须是 IHDR，宽高为其中的两个大端 u32
+fn png_size(head: &[u8]) -> Option<(u32, u32)> {
+    if head.get(12..16) != Some(b"IHDR") {
+        return None;
+    }
+    let width = u32::from_be_bytes(head.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(head.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+/// 依次跳过各个段，直到遇到 SOF 段
+fn jpeg_size(head: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+    loop {
+        if *head.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *head.get(pos + 1)?;
+        // 填充字节
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        // SOF0 ~ SOF15，不包括 DHT(C4)、JPG(C8) 和 DAC(CC)
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            let height = be_u16(head, pos + 5)?;
+            let width = be_u16(head, pos + 7)?;
+            return Some((width, height));
+        }
+        // 图像数据开始或文件结束之前没有 SOF
+        if matches!(marker, 0xD9 | 0xDA) {
+            return None;
+        }
+        pos += 2 + be_u16(head, pos + 2)? as usize;
+    }
+}
+
+fn webp_size(head: &[u8]) -> Option<(u32, u32)> {
+    match head.get(12..16)? {
+        // 有损格式，关键帧头之后是 14 位的宽和高
+        b"VP8 " => {
+            if head.get(23..26) != Some(b"\x9D\x01\x2A") {
+                return None;
+            }
+            Some((le_u16(head, 26)? & 0x3FFF, le_u16(head, 28)? & 0x3FFF))
+        }
+        // 无损格式，宽和高各 14 位，存储的值比实际小 1
+        b"VP8L" => {
+            if *head.get(20)? != 0x2F {
+                return None;
+            }
+            let bits = u32::from_le_bytes(head.get(21..25)?.try_into().ok()?);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        // 扩展格式，宽和高各 24 位，存储的值比实际小 1
+        b"VP8X" => Some((le_u24(head, 24)? + 1, le_u24(head, 27)? + 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut head = b"\x89PNG\r\n\x1A\n\x00\x00\x00\x0DIHDR".to_vec();
+        head.extend(width.to_be_bytes());
+        head.extend(height.to_be_bytes());
+        head
+    }
+
+    #[test]
+    fn t_image_size() {
+        assert_eq!(image_size(&png(640, 480)), Some((640, 480)));
+
+        // APP0 段之后是 SOF0
+        let mut jpeg =
+            b"\xFF\xD8\xFF\xE0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00".to_vec();
+        jpeg.extend(b"\xFF\xC0\x00\x11\x08\x01\xE0\x02\x80");
+        assert_eq!(image_size(&jpeg), Some((640, 480)));
+        assert_eq!(image_size(&jpeg[..20]), None);
+
+        let mut webp = b"RIFF\x00\x00\x00\x00WEBPVP8X\x0A\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        webp.extend(b"\x7F\x02\x00\xDF\x01\x00");
+        assert_eq!(image_size(&webp), Some((640, 480)));
+
+        let mut webp = b"RIFF\x00\x00\x00\x00WEBPVP8 \x00\x00\x00\x00\x00\x00\x00".to_vec();
+        webp.extend(b"\x9D\x01\x2A\x80\x02\xE0\x01");
+        assert_eq!(image_size(&webp), Some((640, 480)));
+
+        assert_eq!(image_size(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn t_check() {
+        assert_eq!(check("image/png", &png(256, 256)), Ok((256, 256)));
+        assert_eq!(
+            check("image/png", &png(16, 256)),
+            Err(AvatarErr::InvalidSize(16, 256))
+        );
+        assert_eq!(
+            check("image/png", &png(8000, 256)),
+            Err(AvatarErr::InvalidSize(8000, 256))
+        );
+        assert_eq!(
+            check("image/gif", &png(256, 256)),
+            Err(AvatarErr::InvalidImage)
+        );
+        assert_eq!(check("image/png", b"\x89PNG"), Err(AvatarErr::InvalidImage));
+    }
+}
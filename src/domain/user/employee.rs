@@ -28,8 +28,9 @@ pub struct Employee {
     login_at: NaiveDateTime,
 }
 
+/// 员工角色，权限从低到高排列
 #[repr(i16)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Role {
     Employee,
@@ -96,10 +97,7 @@ impl Employee {
             email: Email::try_from(user.email.into_owned())?,
             password: Password(user.password.into_owned()),
             login_at: user.last_login,
-            mobile_number: user
-                .mobile_number
-                .map(|n| Phone::try_from(n.into_owned()))
-                .transpose()?,
+            mobile_number: user.mobile_number.map(|n| Phone(n.into_owned())),
             role: Role::try_from(user.role)?,
             invited_by: user.invited_by,
         })
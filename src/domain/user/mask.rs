@@ -0,0 +1,48 @@
+//! 管理端展示用户联系方式时的脱敏规则
+
+/// 只保留用户名的第一个字符和完整的域名，如 a***@domain.com
+pub fn mask_email(email: &str) -> String {
+    let Some((name, domain)) = email.split_once('@') else {
+        return "***".to_string();
+    };
+    match name.chars().next() {
+        Some(first) => format!("{}***@{}", first, domain),
+        None => format!("***@{}", domain),
+    }
+}
+
+/// 保留前 3 位和后 4 位，如 138****1234。位数不足时只保留后 2 位
+pub fn mask_phone(phone: &str) -> String {
+    let chars: Vec<char> = phone.chars().collect();
+    let len = chars.len();
+    let (head, tail) = if len >= 11 { (3, 4) } else { (0, len.min(2)) };
+    let masked = len - head - tail;
+
+    let mut s = String::with_capacity(len);
+    s.extend(&chars[..head]);
+    s.extend(std::iter::repeat('*').take(masked));
+    s.extend(&chars[len - tail..]);
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_mask_email() {
+        assert_eq!(mask_email("alice@domain.com"), "a***@domain.com");
+        assert_eq!(mask_email("@domain.com"), "***@domain.com");
+        assert_eq!(mask_email("张三@domain.com"), "张***@domain.com");
+        assert_eq!(mask_email("no-at-sign"), "***");
+    }
+
+    #[test]
+    fn t_mask_phone() {
+        assert_eq!(mask_phone("13812341234"), "138****1234");
+        assert_eq!(mask_phone("+8613812341234"), "+86*******1234");
+        assert_eq!(mask_phone("12345"), "***45");
+        assert_eq!(mask_phone("1"), "1");
+        assert_eq!(mask_phone(""), "");
+    }
+}
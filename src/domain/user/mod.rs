@@ -1,11 +1,20 @@
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use tracing::warn;
 
 use crate::ensure_ok;
 
+use self::{password::PasswordPolicy, phone::PhonePolicy};
+
+pub mod avatar;
 pub mod employee;
+pub mod mask;
+pub mod password;
+pub mod phone;
+pub mod referral;
 pub mod service;
 pub mod user;
+pub mod webhook;
 
 pub use common_err::*;
 
@@ -30,14 +39,21 @@ pub struct Password(String);
 pub struct Phone(String);
 
 #[derive(Display, Debug)]
-pub struct PhoneFormatErr;
+pub enum PhoneFormatErr {
+    /// 为空或包含数字和分隔符以外的字符
+    Invalid,
+    /// 无法识别国家码，或不在允许的地区内
+    UnsupportedRegion,
+    WrongLength,
+    /// 不是手机号段
+    NotMobile,
+}
 
 impl std::error::Error for PhoneFormatErr {}
 
 impl Phone {
-    pub fn try_from(phone: String) -> Result<Self, PhoneFormatErr> {
-        // TODO: check phone format
-        Ok(Self(phone))
+    pub fn try_from(phone: String, policy: &PhonePolicy) -> Result<Self, PhoneFormatErr> {
+        Ok(Self(policy.parse(&phone)?))
     }
 }
 
@@ -88,14 +104,20 @@ pub enum PasswordFormatErr {
     TooShort,
     NotAllowedChar,
     TooSimple,
+    /// 字符种类少于规则要求
+    TooFewCharClasses,
+    /// 在常见密码列表中
+    Banned,
+    /// 出现在已泄露的密码中
+    Pwned,
 }
 
 impl Password {
-    pub async fn try_from_async(value: String) -> Result<Self, PasswordFormatErr> {
-        ensure_ok!(value.len() >= 8, PasswordFormatErr::TooShort);
-        ensure_ok!(value.len() <= 20, PasswordFormatErr::TooLong);
-        ensure_ok!(value.is_ascii(), PasswordFormatErr::NotAllowedChar);
-        ensure_ok!(!Self::is_monotonic(&value, 5), PasswordFormatErr::TooSimple);
+    pub async fn try_from_async(
+        value: String,
+        policy: &PasswordPolicy,
+    ) -> Result<Self, PasswordFormatErr> {
+        policy.check(&value)?;
 
         let value = tokio::task::spawn_blocking(|| Self::encrypt_password(value))
             .await
@@ -107,6 +129,16 @@ impl Password {
         Ok(Self(value))
     }
 
+    /// 随机密码，用于没有设置过密码的账号，如短信登录时自动注册的账号
+    pub async fn random() -> anyhow::Result<Self> {
+        let value: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        Ok(Self(Self::encrypt_password_async(value).await?))
+    }
+
     pub fn hashed_str(&self) -> &str {
         &self.0
     }
@@ -0,0 +1,157 @@
+//! 密码强度规则
+//!
+//! 默认规则与之前写死的规则相同：8 到 20 个 ascii 字符，不能有超过 5 个连续单调的字符。
+//! 常见密码列表和泄露密码的检查需要读取文件或请求外部服务，由应用层完成
+
+use serde::Deserialize;
+
+use crate::ensure_ok;
+
+use super::{Password, PasswordFormatErr};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PasswordPolicy {
+    #[serde(default = "default_min_len")]
+    pub min_len: usize,
+    #[serde(default = "default_max_len")]
+    pub max_len: usize,
+    /// 是否只允许 ascii 字符
+    #[serde(default = "default_ascii_only")]
+    pub ascii_only: bool,
+    /// 至少包含的字符种类数，种类为小写字母、大写字母、数字和其他符号
+    #[serde(default)]
+    pub min_char_classes: usize,
+    /// 连续单调字符（如 abcdef、111111）的最大长度，为 0 时不检查
+    #[serde(default = "default_max_monotonic")]
+    pub max_monotonic: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_len: default_min_len(),
+            max_len: default_max_len(),
+            ascii_only: default_ascii_only(),
+            min_char_classes: 0,
+            max_monotonic: default_max_monotonic(),
+        }
+    }
+}
+
+fn default_min_len() -> usize {
+    8
+}
+
+fn default_max_len() -> usize {
+    20
+}
+
+fn default_ascii_only() -> bool {
+    true
+}
+
+fn default_max_monotonic() -> usize {
+    5
+}
+
+impl PasswordPolicy {
+    pub fn check(&self, password: &str) -> Result<(), PasswordFormatErr> {
+        let len = password.chars().count();
+        ensure_ok!(len >= self.min_len, PasswordFormatErr::TooShort);
+        ensure_ok!(len <= self.max_len, PasswordFormatErr::TooLong);
+        ensure_ok!(
+            !self.ascii_only || password.is_ascii(),
+            PasswordFormatErr::NotAllowedChar
+        );
+        ensure_ok!(
+            char_classes(password) >= self.min_char_classes,
+            PasswordFormatErr::TooFewCharClasses
+        );
+        ensure_ok!(
+            self.max_monotonic == 0 || !Password::is_monotonic(password, self.max_monotonic),
+            PasswordFormatErr::TooSimple
+        );
+        Ok(())
+    }
+}
+
+fn char_classes(password: &str) -> usize {
+    let has = |pred: fn(&char) -> bool| password.chars().any(|c| pred(&c)) as usize;
+    has(char::is_ascii_lowercase)
+        + has(char::is_ascii_uppercase)
+        + has(char::is_ascii_digit)
+        + has(|c| !c.is_ascii_alphanumeric())
+}
+
+/// 从泄露密码的查询结果中找出 hash 后缀出现的次数
+///
+/// 结果每行为 `SUFFIX:COUNT`，SUFFIX 为 SHA-1 去掉前 5 位后的 35 位大写十六进制
+pub fn pwned_count(range: &str, suffix: &str) -> u64 {
+    range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(s, _)| s.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("aabbccdd").is_ok());
+        assert!(matches!(
+            policy.check("aabbcc"),
+            Err(PasswordFormatErr::TooShort)
+        ));
+        assert!(matches!(
+            policy.check(&"ab".repeat(11)),
+            Err(PasswordFormatErr::TooLong)
+        ));
+        assert!(matches!(
+            policy.check("密码密码密码密码"),
+            Err(PasswordFormatErr::NotAllowedChar)
+        ));
+        assert!(matches!(
+            policy.check("x123456y"),
+            Err(PasswordFormatErr::TooSimple)
+        ));
+    }
+
+    #[test]
+    fn t_char_classes() {
+        let policy = PasswordPolicy {
+            min_char_classes: 3,
+            max_monotonic: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            policy.check("aabbccdd"),
+            Err(PasswordFormatErr::TooFewCharClasses)
+        ));
+        assert!(matches!(
+            policy.check("aabbcc12"),
+            Err(PasswordFormatErr::TooFewCharClasses)
+        ));
+        assert!(policy.check("Aabbcc12").is_ok());
+        assert!(policy.check("aabb_c12").is_ok());
+        assert!(policy.check("12345678_a").is_ok());
+    }
+
+    #[test]
+    fn t_pwned_count() {
+        let range = "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n\
+                     00D4F6E8FA6EECAD2A3AA415EEC418D38EC:2\r\n\
+                     011053FD0102E94D6AE2F8B83D76FAF94F6:13\r\n";
+        assert_eq!(
+            pwned_count(range, "011053FD0102E94D6AE2F8B83D76FAF94F6"),
+            13
+        );
+        assert_eq!(pwned_count(range, "00d4f6e8fa6eecad2a3aa415eec418d38ec"), 2);
+        assert_eq!(pwned_count(range, "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF"), 0);
+        assert_eq!(pwned_count("", "0018A45C4D1DEF81644B54AB7F969B88D65"), 0);
+    }
+}
@@ -0,0 +1,222 @@
+//! 手机号解析
+//!
+//! 接受 `+86 138-0013-8000`、`008613800138000` 和 `13800138000` 等写法，
+//! 没有国家码时按默认地区解析。保存时统一为 E.164 格式，如 `+8613800138000`
+
+use serde::Deserialize;
+
+use crate::ensure_ok;
+
+use super::PhoneFormatErr;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PhonePolicy {
+    /// 没有国家码时使用的地区
+    #[serde(default = "default_region")]
+    pub default_region: String,
+    /// 允许的地区，为 ISO 3166 两位代码
+    #[serde(default = "default_allowed_regions")]
+    pub allowed_regions: Vec<String>,
+}
+
+impl Default for PhonePolicy {
+    fn default() -> Self {
+        Self {
+            default_region: default_region(),
+            allowed_regions: default_allowed_regions(),
+        }
+    }
+}
+
+fn default_region() -> String {
+    "CN".to_string()
+}
+
+fn default_allowed_regions() -> Vec<String> {
+    vec![default_region()]
+}
+
+/// 各地区的手机号规则
+struct Region {
+    code: &'static str,
+    calling_code: &'static str,
+    /// 国内号码的长度，不包括长途前缀 0
+    len: usize,
+    /// 手机号段，为国内号码的开头
+    mobile_prefixes: &'static [&'static str],
+}
+
+const REGIONS: &[Region] = &[
+    Region {
+        code: "CN",
+        calling_code: "86",
+        len: 11,
+        mobile_prefixes: &["13", "14", "15", "16", "17", "18", "19"],
+    },
+    Region {
+        code: "HK",
+        calling_code: "852",
+        len: 8,
+        mobile_prefixes: &["4", "5", "6", "7", "8", "9"],
+    },
+    Region {
+        code: "MO",
+        calling_code: "853",
+        len: 8,
+        mobile_prefixes: &["6"],
+    },
+    Region {
+        code: "TW",
+        calling_code: "886",
+        len: 9,
+        mobile_prefixes: &["9"],
+    },
+    Region {
+        code: "SG",
+        calling_code: "65",
+        len: 8,
+        mobile_prefixes: &["8", "9"],
+    },
+    Region {
+        code: "JP",
+        calling_code: "81",
+        len: 10,
+        mobile_prefixes: &["70", "80", "90"],
+    },
+    Region {
+        code: "GB",
+        calling_code: "44",
+        len: 10,
+        mobile_prefixes: &["7"],
+    },
+    Region {
+        code: "US",
+        calling_code: "1",
+        len: 10,
+        mobile_prefixes: &["2", "3", "4", "5", "6", "7", "8", "9"],
+    },
+];
+
+impl PhonePolicy {
+    fn is_allowed(&self, region: &Region) -> bool {
+        self.allowed_regions
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(region.code))
+    }
+
+    /// 解析手机号，返回 E.164 格式的号码
+    pub fn parse(&self, phone: &str) -> Result<String, PhoneFormatErr> {
+        let phone = phone.trim();
+        let (international, rest) = match phone.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => match phone.strip_prefix("00") {
+                Some(rest) => (true, rest),
+                None => (false, phone),
+            },
+        };
+        let digits: String = rest
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+            .collect();
+        ensure_ok!(
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()),
+            PhoneFormatErr::Invalid
+        );
+
+        let (region, national) = if international {
+            let region = REGIONS
+                .iter()
+                .find(|r| digits.starts_with(r.calling_code))
+                .ok_or(PhoneFormatErr::UnsupportedRegion)?;
+            (region, &digits[region.calling_code.len()..])
+        } else {
+            let region = REGIONS
+                .iter()
+                .find(|r| r.code.eq_ignore_ascii_case(&self.default_region))
+                .ok_or(PhoneFormatErr::UnsupportedRegion)?;
+            (region, digits.as_str())
+        };
+        ensure_ok!(self.is_allowed(region), PhoneFormatErr::UnsupportedRegion);
+
+        // 部分地区国内拨号时带长途前缀 0，如台湾的 0912345678
+        let national = match national.strip_prefix('0') {
+            Some(n) if n.len() == region.len => n,
+            _ => national,
+        };
+        ensure_ok!(national.len() == region.len, PhoneFormatErr::WrongLength);
+        ensure_ok!(
+            region
+                .mobile_prefixes
+                .iter()
+                .any(|p| national.starts_with(p)),
+            PhoneFormatErr::NotMobile
+        );
+
+        Ok(format!("+{}{}", region.calling_code, national))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_parse_default() {
+        let policy = PhonePolicy::default();
+        for phone in [
+            "13800138000",
+            "138 0013 8000",
+            "138-0013-8000",
+            "+8613800138000",
+            "+86 (138) 0013-8000",
+            "008613800138000",
+        ] {
+            assert_eq!(policy.parse(phone).unwrap(), "+8613800138000", "{}", phone);
+        }
+
+        assert!(matches!(
+            policy.parse("1380013800a"),
+            Err(PhoneFormatErr::Invalid)
+        ));
+        assert!(matches!(policy.parse(""), Err(PhoneFormatErr::Invalid)));
+        assert!(matches!(
+            policy.parse("1380013800"),
+            Err(PhoneFormatErr::WrongLength)
+        ));
+        assert!(matches!(
+            policy.parse("12800138000"),
+            Err(PhoneFormatErr::NotMobile)
+        ));
+        assert!(matches!(
+            policy.parse("01088888888"),
+            Err(PhoneFormatErr::NotMobile)
+        ));
+        assert!(matches!(
+            policy.parse("+85291234567"),
+            Err(PhoneFormatErr::UnsupportedRegion)
+        ));
+        assert!(matches!(
+            policy.parse("+99912345678"),
+            Err(PhoneFormatErr::UnsupportedRegion)
+        ));
+    }
+
+    #[test]
+    fn t_parse_regions() {
+        let policy = PhonePolicy {
+            default_region: "HK".to_string(),
+            allowed_regions: vec!["cn".to_string(), "HK".to_string(), "TW".to_string()],
+        };
+        assert_eq!(policy.parse("9123 4567").unwrap(), "+85291234567");
+        assert_eq!(policy.parse("+86 13800138000").unwrap(), "+8613800138000");
+        assert_eq!(policy.parse("+886 0912 345 678").unwrap(), "+886912345678");
+        assert!(matches!(
+            policy.parse("+88691234567"),
+            Err(PhoneFormatErr::WrongLength)
+        ));
+        assert!(matches!(
+            policy.parse("+14155552671"),
+            Err(PhoneFormatErr::UnsupportedRegion)
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+//! 用户邀请码
+//!
+//! 用户生成邀请码分享给他人，注册时填写邀请码后双方都获得奖励。
+//! 邀请码不区分大小写，不包含 0、O、1、I、L 等容易看错的字符
+
+use rand::{thread_rng, Rng};
+
+/// 每个用户最多生成的邀请码数量
+pub const MAX_CODES_PER_USER: i64 = 10;
+
+const CODE_LEN: usize = 8;
+const ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+
+#[derive(derive_more::Deref, derive_more::Display, Debug, Clone, PartialEq, Eq)]
+pub struct ReferralCode(String);
+
+impl ReferralCode {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let code = (0..CODE_LEN)
+            .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+            .collect();
+        Self(code)
+    }
+
+    /// 解析用户填写的邀请码，格式不对时返回 None
+    pub fn parse(code: &str) -> Option<Self> {
+        let code = code.trim().to_ascii_uppercase();
+        if code.len() != CODE_LEN || !code.bytes().all(|c| ALPHABET.contains(&c)) {
+            return None;
+        }
+        Some(Self(code))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_parse() {
+        let code = ReferralCode::generate();
+        assert_eq!(code.len(), CODE_LEN);
+        assert_eq!(ReferralCode::parse(&code), Some(code.clone()));
+        assert_eq!(
+            ReferralCode::parse(&format!(" {} ", code.to_lowercase())),
+            Some(code)
+        );
+
+        assert_eq!(
+            ReferralCode::parse("ABCD2345"),
+            Some(ReferralCode("ABCD2345".into()))
+        );
+        assert_eq!(ReferralCode::parse("ABCD234"), None);
+        assert_eq!(ReferralCode::parse("ABCD2340"), None);
+        assert_eq!(ReferralCode::parse("ABCD234O"), None);
+        assert_eq!(ReferralCode::parse(""), None);
+    }
+}
@@ -25,6 +25,8 @@ pub enum RegisterErr {
     Sanity(SanityCheck),
 
     AlreadyRegister,
+    /// 邀请码格式错误或不存在
+    InvalidReferralCode,
 }
 
 pub async fn register(user: User) -> BizResult<UserId, RegisterErr> {
@@ -103,6 +105,8 @@ pub struct UserUpdate {
     pub password: Option<UpdatePassword>,
     pub address: Option<String>,
     pub mobile_number: Option<Phone>,
+    pub order_email_opt_out: Option<bool>,
+    pub max_file_versions: Option<u32>,
 }
 
 pub struct UpdatePassword {
@@ -3,8 +3,9 @@ use super::{
     Email, Password, Phone, UserName,
 };
 use crate::{
-    biz_ok, domain::user::common_err::SanityCheck, ensure_biz, ensure_ok, http::BizResult,
-    id_wraper, infrastructure::repo_user::UserPo, LocalDataTime,
+    biz_ok, domain::file_system::version::MAX_FILE_VERSIONS, domain::user::common_err::SanityCheck,
+    ensure_biz, ensure_ok, http::BizResult, id_wraper, infrastructure::repo_user::UserPo,
+    LocalDataTime,
 };
 
 use chrono::Local;
@@ -12,6 +13,11 @@ use getset::Getters;
 
 id_wraper!(UserId);
 
+/// 短信自动注册的用户使用的占位邮箱后缀
+const PLACEHOLDER_EMAIL_DOMAIN: &str = "@phone.invalid";
+/// 组织空间账号使用的占位邮箱后缀
+const ORG_SPACE_EMAIL_DOMAIN: &str = "@org.invalid";
+
 #[derive(Getters, Debug)]
 #[getset(get = "pub(crate)")]
 pub struct User {
@@ -23,6 +29,10 @@ pub struct User {
     mobile_number: Option<Phone>,
     address: Option<String>,
     online: bool,
+    /// 是否退订转码订单完成邮件
+    order_email_opt_out: bool,
+    /// 每个文件保留的历史版本数，为空时使用系统默认值
+    max_file_versions: Option<u32>,
 
     login_at: LocalDataTime,
 }
@@ -38,9 +48,44 @@ impl User {
             mobile_number: None,
             address: None,
             online: true,
+            order_email_opt_out: false,
+            max_file_versions: None,
+        }
+    }
+
+    /// 短信登录时自动注册，邮箱为占位地址，并退订订单邮件
+    pub fn create_by_phone(phone: Phone, password: Password) -> Self {
+        let email = Email(format!(
+            "{}{}",
+            phone.trim_start_matches('+'),
+            PLACEHOLDER_EMAIL_DOMAIN
+        ));
+        Self {
+            mobile_number: Some(phone),
+            order_email_opt_out: true,
+            ..Self::create(email, password)
+        }
+    }
+
+    /// 组织共享空间的账号，邮箱为占位地址，密码随机，不能登录
+    pub fn create_org_space(password: Password) -> Self {
+        let id = UserId::next_id();
+        let email = Email(format!("org-{}{}", id, ORG_SPACE_EMAIL_DOMAIN));
+        Self {
+            id,
+            name: UserName::try_from("org-space".to_string()).unwrap(),
+            online: false,
+            order_email_opt_out: true,
+            ..Self::create(email, password)
         }
     }
 
+    /// 短信自动注册的用户和组织空间账号没有真实的邮箱
+    pub fn has_placeholder_email(&self) -> bool {
+        self.email.ends_with(PLACEHOLDER_EMAIL_DOMAIN)
+            || self.email.ends_with(ORG_SPACE_EMAIL_DOMAIN)
+    }
+
     pub async fn login(&mut self, password: &str) -> BizResult<(), SanityCheck> {
         ensure_biz!(
             self.password.verify(password).await,
@@ -51,6 +96,12 @@ impl User {
         biz_ok!(())
     }
 
+    /// 不使用密码登录，身份由调用方验证，如短信验证码和第三方登录
+    pub fn login_verified(&mut self) {
+        self.login_at = Local::now();
+        self.online = true;
+    }
+
     pub fn logout(&mut self) {
         self.online = false
     }
@@ -86,6 +137,14 @@ impl User {
             self.mobile_number = Some(mobile_number)
         }
 
+        if let Some(opt_out) = update.order_email_opt_out {
+            self.order_email_opt_out = opt_out
+        }
+
+        if let Some(max) = update.max_file_versions {
+            self.max_file_versions = Some(max.min(MAX_FILE_VERSIONS))
+        }
+
         biz_ok!(())
     }
 
@@ -107,6 +166,14 @@ impl User {
             self.mobile_number = Some(mobile_number)
         }
 
+        if let Some(opt_out) = update.order_email_opt_out {
+            self.order_email_opt_out = opt_out
+        }
+
+        if let Some(max) = update.max_file_versions {
+            self.max_file_versions = Some(max.min(MAX_FILE_VERSIONS))
+        }
+
         biz_ok!(())
     }
 
@@ -117,12 +184,11 @@ impl User {
             email: Email::try_from(user.email.into_owned())?,
             password: Password(user.password.into_owned()),
             login_at: user.last_login,
-            mobile_number: user
-                .mobile_number
-                .map(|n| Phone::try_from(n.into_owned()))
-                .transpose()?,
+            mobile_number: user.mobile_number.map(|n| Phone(n.into_owned())),
             address: user.address.map(|a| a.into_owned()),
             online: user.online,
+            order_email_opt_out: user.order_email_opt_out,
+            max_file_versions: user.max_file_versions.map(|m| m as u32),
         })
     }
 }
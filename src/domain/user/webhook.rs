@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use getset::Getters;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    domain::transcode_order::{TranscodeOrderId, TranscodeTaskId},
+    ensure_ok, id_wraper,
+    infrastructure::public_host::is_private_literal,
+};
+
+use super::user::UserId;
+
+id_wraper!(WebhookId);
+
+/// 每个用户最多注册的 webhook 数量
+pub const MAX_WEBHOOKS_PER_USER: i64 = 10;
+
+const SECRET_LEN: usize = 32;
+const MAX_URL_LEN: usize = 512;
+
+#[derive(Debug, derive_more::Display)]
+pub enum WebhookUrlErr {
+    Invalid,
+    /// 内网、回环或链路本地地址
+    PrivateHost,
+}
+
+impl std::error::Error for WebhookUrlErr {}
+
+#[derive(Getters, Debug)]
+#[getset(get = "pub")]
+pub struct Webhook {
+    id: WebhookId,
+    user_id: UserId,
+    url: String,
+    /// 用于签名请求体的密钥，只在创建时返回给用户
+    secret: String,
+}
+
+impl Webhook {
+    pub fn create(user_id: UserId, url: String) -> Result<Self, WebhookUrlErr> {
+        ensure_ok!(url.len() <= MAX_URL_LEN, WebhookUrlErr::Invalid);
+        let parsed = reqwest::Url::parse(&url).map_err(|_| WebhookUrlErr::Invalid)?;
+        ensure_ok!(
+            matches!(parsed.scheme(), "http" | "https"),
+            WebhookUrlErr::Invalid
+        );
+        let host = parsed.host_str().ok_or(WebhookUrlErr::Invalid)?;
+        ensure_ok!(!is_private_literal(host), WebhookUrlErr::PrivateHost);
+
+        let secret = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(SECRET_LEN)
+            .map(char::from)
+            .collect();
+        Ok(Self {
+            id: WebhookId::next_id(),
+            user_id,
+            url,
+            secret,
+        })
+    }
+
+    pub fn from_raw(id: WebhookId, user_id: UserId, url: String, secret: String) -> Self {
+        Self {
+            id,
+            user_id,
+            url,
+            secret,
+        }
+    }
+}
+
+/// 计算请求体的签名，结果为 HMAC-SHA256 的十六进制小写字符串
+pub fn sign(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(payload);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 第 attempts 次投递失败后，下次重试前需要等待的时间，按指数增长且不超过 max
+pub fn retry_delay(attempts: u32, base: Duration, max: Duration) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    base.saturating_mul(factor).min(max)
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+pub enum WebhookEvent {
+    #[serde(rename = "order.created")]
+    OrderCreated(OrderCreated),
+    #[serde(rename = "task.completed")]
+    TaskCompleted(TaskCompleted),
+    #[serde(rename = "order.finished")]
+    OrderFinished(OrderFinished),
+}
+
+impl WebhookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::OrderCreated(_) => "order.created",
+            WebhookEvent::TaskCompleted(_) => "task.completed",
+            WebhookEvent::OrderFinished(_) => "order.finished",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderCreated {
+    pub order_id: TranscodeOrderId,
+    pub task_ids: Vec<TranscodeTaskId>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCompleted {
+    pub order_id: TranscodeOrderId,
+    pub task_id: TranscodeTaskId,
+    pub ok: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderFinished {
+    pub order_id: TranscodeOrderId,
+    pub ok_count: usize,
+    pub failed_count: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_create() {
+        let user_id = UserId(1);
+        assert!(Webhook::create(user_id, "https://example.com/hook".to_string()).is_ok());
+        assert!(Webhook::create(user_id, "ftp://example.com/hook".to_string()).is_err());
+        assert!(Webhook::create(user_id, "example.com/hook".to_string()).is_err());
+
+        let hook = Webhook::create(user_id, "http://example.com:8080".to_string()).unwrap();
+        assert_eq!(hook.secret().len(), SECRET_LEN);
+
+        for url in [
+            "http://127.0.0.1:8080",
+            "http://localhost/hook",
+            "http://10.0.10.59:6379",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]/hook",
+        ] {
+            assert!(
+                matches!(
+                    Webhook::create(user_id, url.to_string()),
+                    Err(WebhookUrlErr::PrivateHost)
+                ),
+                "{url}"
+            );
+        }
+    }
+
+    #[test]
+    fn t_sign() {
+        // 与 `echo -n "hello" | openssl dgst -sha256 -hmac "key"` 的结果一致
+        assert_eq!(
+            sign("key", b"hello"),
+            "9307b3b915efb5171ff14d8cb55fbcc798c6c0ef1456d66ded1a6aa723a58b7b"
+        );
+    }
+
+    #[test]
+    fn t_retry_delay() {
+        let base = Duration::from_secs(10);
+        let max = Duration::from_secs(3600);
+        assert_eq!(retry_delay(1, base, max), Duration::from_secs(10));
+        assert_eq!(retry_delay(2, base, max), Duration::from_secs(20));
+        assert_eq!(retry_delay(4, base, max), Duration::from_secs(80));
+        assert_eq!(retry_delay(20, base, max), max);
+    }
+
+    #[test]
+    fn t_event_json() {
+        let event = WebhookEvent::OrderFinished(OrderFinished {
+            order_id: TranscodeOrderId(1),
+            ok_count: 2,
+            failed_count: 0,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "order.finished");
+        assert_eq!(json["data"]["orderId"], "1");
+        assert_eq!(json["data"]["okCount"], 2);
+    }
+}
@@ -4,6 +4,17 @@ use actix_session::{SessionGetError, SessionInsertError};
 use actix_web::{body::BoxBody, http::StatusCode, web::Json, HttpResponse, ResponseError};
 use serde::Serialize;
 
+use self::validation::FieldError;
+
+pub mod client_ip;
+pub mod csrf;
+pub mod idempotency;
+pub mod internal_auth;
+pub mod playback_token;
+pub mod request_id;
+pub mod slow_log;
+pub mod validation;
+
 type Result<T, E = ApiError> = std::result::Result<T, E>;
 pub type ApiResult<T> = Result<Json<ApiResponse<T>>>;
 
@@ -18,6 +29,12 @@ pub struct ApiResponse<T: Serialize> {
     pub err_msg: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<T>,
+    /// 参数校验失败时各个字段的错误
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+    /// 出错时返回请求 id，便于按 id 查找日志
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl<T: Serialize> ApiResponse<T> {
@@ -27,6 +44,8 @@ impl<T: Serialize> ApiResponse<T> {
             status: 0,
             err_msg: None,
             data: Some(data),
+            errors: None,
+            request_id: None,
         }))
     }
 }
@@ -41,12 +60,26 @@ impl ApiError {
     pub fn code(&self) -> u32 {
         self.msg.code()
     }
+
+    /// 不带前缀的错误信息
+    pub fn message(&self) -> String {
+        self.msg.to_string()
+    }
+
+    pub fn data(&self) -> Option<serde_json::Value> {
+        self.msg.data()
+    }
 }
 
 pub trait HttpBizError: Display + Debug + Send + Sync + 'static {
     fn code(&self) -> u32 {
         1
     }
+
+    /// 随错误一起返回给客户端的数据，例如文件被锁定时的持有者
+    fn data(&self) -> Option<serde_json::Value> {
+        None
+    }
 }
 
 impl HttpBizError for std::num::ParseIntError {}
@@ -71,10 +104,12 @@ impl ResponseError for ApiError {
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
-        let resp = ApiResponse::<()> {
+        let resp = ApiResponse {
             status: self.code(),
             err_msg: Some(self.to_string()),
-            data: None,
+            data: self.data(),
+            errors: None,
+            request_id: request_id::current(),
         };
         HttpResponse::build(self.status_code()).json(resp)
     }
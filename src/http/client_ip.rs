@@ -0,0 +1,50 @@
+//! 请求方的 IP
+//!
+//! Forwarded 和 X-Forwarded-For 请求头可以由客户端任意设置，只有直连的地址是配置的可信代理时才使用，
+//! 否则使用 TCP 连接的对端地址。可信代理需要覆盖（而不是追加）这两个请求头
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::HttpRequest;
+
+use crate::settings::get_settings;
+
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = &get_settings().http_server.trusted_proxies;
+    let info = req.connection_info();
+    Some(resolve(peer, info.realip_remote_addr(), trusted))
+}
+
+fn resolve(peer: IpAddr, forwarded: Option<&str>, trusted: &[IpAddr]) -> IpAddr {
+    if !trusted.contains(&peer) {
+        return peer;
+    }
+    forwarded.and_then(parse_ip).unwrap_or(peer)
+}
+
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_resolve() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "1.2.3.4".parse().unwrap();
+        let trusted = [proxy];
+
+        // 不是可信代理时忽略转发的地址
+        assert_eq!(resolve(client, Some("5.6.7.8"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("1.2.3.4"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("1.2.3.4:5000"), &trusted), client);
+        assert_eq!(resolve(proxy, Some("unknown"), &trusted), proxy);
+        assert_eq!(resolve(proxy, None, &trusted), proxy);
+        assert_eq!(resolve(proxy, Some("1.2.3.4"), &[]), proxy);
+    }
+}
@@ -0,0 +1,208 @@
+//! CSRF 防护（double-submit cookie）
+//!
+//! 登录时生成一个随机 token 保存在 session 中，并通过可被 js 读取的 cookie `XSRF-TOKEN` 下发，
+//! 已登录用户发起的 /api 下非 GET 请求必须在请求头 `X-CSRF-Token` 中带上相同的 token。
+//!
+//! 以下请求不做校验：
+//! - 未登录的请求，它们不携带可被利用的身份
+//! - 配置中 `exempt_paths` 前缀下的请求
+//! - 携带 `token_client_header` 请求头的请求，它们是使用 token 鉴权的 API 客户端
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_identity::IdentityExt;
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{Error, HttpResponse, ResponseError};
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+
+use super::{request_id, ApiResponse};
+use crate::log_if_err;
+use crate::settings::get_settings;
+
+pub const CSRF_COOKIE: &str = "XSRF-TOKEN";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+const SESSION_KEY: &str = "csrf_token";
+
+/// CSRF token 缺失或不匹配
+pub const TOKEN_MISMATCH: u32 = 920;
+
+#[derive(Deserialize, Debug)]
+pub struct CsrfCfg {
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+    /// 不做校验的路径前缀
+    #[serde(default = "default_exempt_paths")]
+    pub exempt_paths: Vec<String>,
+    /// 携带这个请求头的请求视为 token 鉴权的客户端，不做校验
+    #[serde(default)]
+    pub token_client_header: Option<String>,
+}
+
+impl Default for CsrfCfg {
+    fn default() -> Self {
+        Self {
+            enable: default_enable(),
+            exempt_paths: default_exempt_paths(),
+            token_client_header: None,
+        }
+    }
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_exempt_paths() -> Vec<String> {
+    // 转码工厂的回调在 /internal 下，使用签名鉴权
+    vec![]
+}
+
+#[derive(derive_more::Display, Debug)]
+#[display(fmt = "csrf token missing or mismatched")]
+pub struct CsrfErr;
+
+impl ResponseError for CsrfErr {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let resp = ApiResponse::<()> {
+            status: TOKEN_MISMATCH,
+            err_msg: Some(self.to_string()),
+            data: None,
+            errors: None,
+            request_id: request_id::current(),
+        };
+        HttpResponse::build(self.status_code()).json(resp)
+    }
+}
+
+/// 生成新的 token 保存到 session 中，cookie 由 [`CsrfGuard`] 在响应时下发
+pub fn rotate_token(session: &Session) -> Result<String, SessionInsertError> {
+    let token = hex::encode(thread_rng().gen::<[u8; 32]>());
+    session.insert(SESSION_KEY, &token)?;
+    Ok(token)
+}
+
+/// session 中已有的 token，没有时生成一个
+pub fn ensure_token(session: &Session) -> anyhow::Result<String> {
+    match session.get::<String>(SESSION_KEY)? {
+        Some(token) => Ok(token),
+        None => Ok(rotate_token(session)?),
+    }
+}
+
+pub struct CsrfGuard;
+
+impl<S, B> Transform<S, ServiceRequest> for CsrfGuard
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfGuardMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CsrfGuardMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct CsrfGuardMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CsrfGuardMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let cfg = &get_settings().http_server.csrf;
+            if !cfg.enable {
+                return srv.call(req).await;
+            }
+
+            if needs_check(&req, cfg) {
+                let expected = req.get_session().get::<String>(SESSION_KEY)?;
+                let cookie = req.cookie(CSRF_COOKIE);
+                let header = req.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+                let matched = match (expected, cookie, header) {
+                    (Some(expected), Some(cookie), Some(header)) => {
+                        expected == header && cookie.value() == header
+                    }
+                    _ => false,
+                };
+                if !matched {
+                    return Err(CsrfErr.into());
+                }
+            }
+
+            let mut res = srv.call(req).await?;
+            log_if_err!(sync_cookie(&mut res));
+            Ok(res)
+        })
+    }
+}
+
+fn needs_check(req: &ServiceRequest, cfg: &CsrfCfg) -> bool {
+    let safe = matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    if safe || !req.path().starts_with("/api/") {
+        return false;
+    }
+    if cfg.exempt_paths.iter().any(|p| req.path().starts_with(p)) {
+        return false;
+    }
+    if let Some(header) = &cfg.token_client_header {
+        if req.headers().contains_key(header.as_str()) {
+            return false;
+        }
+    }
+    req.get_identity().is_ok()
+}
+
+/// session 中的 token 与请求携带的 cookie 不一致时（如刚登录），在响应中下发新的 cookie
+fn sync_cookie<B>(res: &mut ServiceResponse<B>) -> anyhow::Result<()> {
+    let token = res.request().get_session().get::<String>(SESSION_KEY)?;
+    let Some(token) = token else {
+        return Ok(());
+    };
+    let current = res.request().cookie(CSRF_COOKIE);
+    if current.is_some_and(|c| c.value() == token) {
+        return Ok(());
+    }
+
+    let cookie = Cookie::build(CSRF_COOKIE, token)
+        .path("/")
+        .http_only(false)
+        .secure(get_settings().http_server.session.secure)
+        .same_site(SameSite::Strict)
+        .finish();
+    res.response_mut().add_cookie(&cookie)?;
+    Ok(())
+}
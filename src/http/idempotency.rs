@@ -0,0 +1,244 @@
+//! 幂等键
+//!
+//! 客户端在请求头 `Idempotency-Key` 中携带一个唯一的键，同一个用户使用相同的键重复请求时，
+//! 直接返回第一次请求的响应，而不会重复执行。只对通过 `.wrap(Idempotent)` 开启的接口生效，
+//! 不带这个请求头的请求不受影响。
+//!
+//! 第一次请求返回 5xx 时不会保存响应，客户端可以使用相同的键重试
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_identity::IdentityExt;
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpResponse, ResponseError};
+use anyhow::anyhow;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{request_id, ApiError, ApiResponse};
+use crate::domain::user::user::UserId;
+use crate::infrastructure::repo_idempotency::{self, IdempotentRecord, StoredResponse};
+use crate::log_if_err;
+use crate::settings::get_settings;
+
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+/// 重放的响应会带上这个响应头
+pub const IDEMPOTENT_REPLAYED: &str = "Idempotent-Replayed";
+
+const MAX_KEY_LEN: usize = 128;
+
+/// 幂等键格式不正确
+pub const INVALID_KEY: u32 = 910;
+/// 相同幂等键的请求正在处理中
+pub const IN_PROGRESS: u32 = 911;
+/// 幂等键已经用于其他请求
+pub const KEY_REUSED: u32 = 912;
+
+#[derive(Deserialize, Debug)]
+pub struct IdempotencyCfg {
+    /// 响应保存的时长
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for IdempotencyCfg {
+    fn default() -> Self {
+        Self {
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    60 * 60 * 24
+}
+
+#[derive(derive_more::Display, Debug)]
+pub enum IdempotencyErr {
+    #[display(fmt = "invalid idempotency key")]
+    InvalidKey,
+    #[display(fmt = "a request with the same idempotency key is in progress")]
+    InProgress,
+    #[display(fmt = "idempotency key was used by another request")]
+    KeyReused,
+}
+
+impl ResponseError for IdempotencyErr {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            IdempotencyErr::InProgress => StatusCode::CONFLICT,
+            IdempotencyErr::InvalidKey | IdempotencyErr::KeyReused => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let status = match self {
+            IdempotencyErr::InvalidKey => INVALID_KEY,
+            IdempotencyErr::InProgress => IN_PROGRESS,
+            IdempotencyErr::KeyReused => KEY_REUSED,
+        };
+        let resp = ApiResponse::<()> {
+            status,
+            err_msg: Some(self.to_string()),
+            data: None,
+            errors: None,
+            request_id: request_id::current(),
+        };
+        HttpResponse::build(self.status_code()).json(resp)
+    }
+}
+
+pub struct Idempotent;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotent
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IdempotentMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotentMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct IdempotentMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotentMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let Some(key) = req.headers().get(IDEMPOTENCY_KEY) else {
+                return Ok(srv.call(req).await?.map_into_boxed_body());
+            };
+            let key = match key.to_str() {
+                Ok(key) if is_valid_key(key) => key.to_string(),
+                _ => return Err(IdempotencyErr::InvalidKey.into()),
+            };
+            // 未登录的请求交给后续的处理函数拒绝
+            let user_id = req
+                .get_identity()
+                .ok()
+                .and_then(|id| id.id().ok())
+                .and_then(|id| id.parse::<UserId>().ok());
+            let Some(user_id) = user_id else {
+                return Ok(srv.call(req).await?.map_into_boxed_body());
+            };
+
+            let body = req.extract::<Bytes>().await?;
+            let fingerprint = fingerprint(req.method(), req.path(), &body);
+            req.set_payload(Payload::from(body));
+
+            let ttl_secs = get_settings().http_server.idempotency.ttl_secs;
+            let existed = repo_idempotency::reserve(user_id, &key, &fingerprint, ttl_secs)
+                .await
+                .map_err(ApiError::from)?;
+            if let Some(record) = existed {
+                if record.fingerprint != fingerprint {
+                    return Err(IdempotencyErr::KeyReused.into());
+                }
+                let Some(stored) = record.response else {
+                    return Err(IdempotencyErr::InProgress.into());
+                };
+                return Ok(req.into_response(replay(stored)));
+            }
+
+            let res = match srv.call(req).await {
+                Ok(res) => res,
+                Err(err) => {
+                    log_if_err!(repo_idempotency::release(user_id, &key).await);
+                    return Err(err);
+                }
+            };
+
+            let (http_req, res) = res.into_parts();
+            let status = res.status();
+            let content_type = res
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from);
+            let (res, body) = res.into_parts();
+            let body = match to_bytes(body).await {
+                Ok(body) => body,
+                Err(err) => {
+                    log_if_err!(repo_idempotency::release(user_id, &key).await);
+                    let err: Box<dyn std::error::Error> = err.into();
+                    return Err(ApiError::from(anyhow!("read response body: {err}")).into());
+                }
+            };
+
+            if status.is_server_error() {
+                log_if_err!(repo_idempotency::release(user_id, &key).await);
+            } else {
+                let record = IdempotentRecord {
+                    fingerprint,
+                    response: Some(StoredResponse {
+                        status: status.as_u16(),
+                        content_type,
+                        body: body.to_vec(),
+                    }),
+                };
+                log_if_err!(repo_idempotency::complete(user_id, &key, &record, ttl_secs).await);
+            }
+
+            let res = res.set_body(body).map_into_boxed_body();
+            Ok(ServiceResponse::new(http_req, res))
+        })
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// 相同的幂等键只能用于方法、路径、请求体都相同的请求
+fn fingerprint(method: &Method, path: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b" ");
+    hasher.update(path);
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(stored: StoredResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut builder = HttpResponse::build(status);
+    if let Some(content_type) = stored.content_type {
+        builder.insert_header((CONTENT_TYPE, content_type));
+    }
+    builder
+        .insert_header((IDEMPOTENT_REPLAYED, "true"))
+        .body(stored.body)
+}
@@ -0,0 +1,201 @@
+//! 内部接口签名校验
+//!
+//! /internal 下的接口只允许转码工厂调用。调用方在请求头 `X-Av1-Timestamp` 中携带当前的 unix 时间戳（秒），
+//! 在 `X-Av1-Signature` 中携带 `sha256=<hex>`，其中 hex 是使用共享密钥对 `{timestamp}.{body}` 计算的 HMAC-SHA256。
+//!
+//! 配置中可以同时存在多个密钥，任意一个校验通过即可，轮换密钥时先加入新密钥，
+//! 调用方切换完成后再删除旧密钥
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpResponse, ResponseError};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tracing::warn;
+
+use super::{request_id, ApiResponse};
+use crate::settings::{get_settings, Secret};
+
+pub const TIMESTAMP_HEADER: &str = "X-Av1-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Av1-Signature";
+
+/// 签名缺失或不正确
+pub const BAD_SIGNATURE: u32 = 930;
+
+#[derive(Deserialize, Debug)]
+pub struct InternalAuthCfg {
+    /// 关闭后不校验签名，只应在开发环境中使用
+    #[serde(default = "default_enable")]
+    pub enable: bool,
+    /// 共享密钥，轮换期间可以同时配置新旧两个
+    #[serde(default)]
+    pub keys: Vec<Secret>,
+    /// 允许的时间戳误差，超出的请求视为重放
+    #[serde(default = "default_max_skew_secs")]
+    pub max_skew_secs: u64,
+}
+
+impl Default for InternalAuthCfg {
+    fn default() -> Self {
+        Self {
+            enable: default_enable(),
+            keys: vec![],
+            max_skew_secs: default_max_skew_secs(),
+        }
+    }
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_max_skew_secs() -> u64 {
+    300
+}
+
+#[derive(derive_more::Display, Debug)]
+#[display(fmt = "signature missing or invalid")]
+pub struct SignatureErr;
+
+impl ResponseError for SignatureErr {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNAUTHORIZED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let resp = ApiResponse::<()> {
+            status: BAD_SIGNATURE,
+            err_msg: Some(self.to_string()),
+            data: None,
+            errors: None,
+            request_id: request_id::current(),
+        };
+        HttpResponse::build(self.status_code()).json(resp)
+    }
+}
+
+fn mac(key: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+pub fn sign(key: &str, timestamp: i64, body: &[u8]) -> String {
+    hex::encode(mac(key, timestamp, body).finalize().into_bytes())
+}
+
+/// 使用任意一个密钥校验通过即可，比较时间恒定
+fn verify<'a>(
+    keys: impl IntoIterator<Item = &'a str>,
+    timestamp: i64,
+    body: &[u8],
+    signature: &str,
+) -> bool {
+    let Some(signature) = signature.strip_prefix("sha256=") else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    keys.into_iter()
+        .any(|key| mac(key, timestamp, body).verify_slice(&signature).is_ok())
+}
+
+pub struct InternalAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for InternalAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = InternalAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(InternalAuthMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct InternalAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for InternalAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let cfg = &get_settings().http_server.internal_auth;
+            if !cfg.enable {
+                return srv.call(req).await;
+            }
+
+            let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+            let timestamp = header(TIMESTAMP_HEADER).and_then(|t| t.parse::<i64>().ok());
+            let (Some(timestamp), Some(signature)) = (timestamp, header(SIGNATURE_HEADER)) else {
+                return Err(SignatureErr.into());
+            };
+            let signature = signature.to_string();
+            let skew = (chrono::Local::now().timestamp() - timestamp).unsigned_abs();
+            if skew > cfg.max_skew_secs {
+                warn!(
+                    path = req.path(),
+                    skew, "internal request timestamp out of range"
+                );
+                return Err(SignatureErr.into());
+            }
+
+            let body = req.extract::<Bytes>().await?;
+            let keys = cfg.keys.iter().map(|key| key.expose());
+            if !verify(keys, timestamp, &body, &signature) {
+                warn!(path = req.path(), "internal request signature mismatch");
+                return Err(SignatureErr.into());
+            }
+            req.set_payload(Payload::from(body));
+
+            srv.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_verify() {
+        let body = br#"{"taskId":1}"#;
+        let signature = format!("sha256={}", sign("new-key", 1697000000, body));
+
+        assert!(verify(["old-key", "new-key"], 1697000000, body, &signature));
+        assert!(!verify(["old-key"], 1697000000, body, &signature));
+        assert!(!verify(["new-key"], 1697000001, body, &signature));
+        assert!(!verify(["new-key"], 1697000000, b"{}", &signature));
+        assert!(!verify(["new-key"], 1697000000, body, &signature[7..]));
+    }
+}
@@ -0,0 +1,239 @@
+//! 播放令牌
+//!
+//! 直接播放和下载文件的地址带有短期有效的令牌，令牌格式为 `{key_id}.{file_id}.{expire}.{bind}.{signature}`：
+//! - `expire` 是过期时间的 unix 时间戳（秒）
+//! - `bind` 为 `i` 时令牌只能在签发时的 IP 使用，为 `a` 时不限 IP
+//! - `signature` 是用 `key_id` 对应的密钥对前面的内容（绑定 IP 时再加上 IP）计算的 HMAC-SHA256 的前 16 字节，hex 编码
+//!
+//! 校验只需要配置中的密钥，不查询数据库。配置中第一个密钥用于签发，其余密钥只用于校验，
+//! 轮换时把新密钥放在最前面，等旧令牌全部过期后再删除旧密钥
+
+use std::net::IpAddr;
+
+use chrono::{Duration, Local};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    domain::file_system::file::UserFileId,
+    settings::{get_settings, Secret},
+    LocalDataTime,
+};
+
+/// 签名保留的字节数
+const SIGNATURE_LEN: usize = 16;
+
+#[derive(Deserialize, Debug)]
+pub struct PlaybackTokenCfg {
+    /// 签名密钥，第一个用于签发
+    #[serde(default)]
+    pub keys: Vec<PlaybackKey>,
+    /// 令牌有效期
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+impl Default for PlaybackTokenCfg {
+    fn default() -> Self {
+        Self {
+            keys: vec![],
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PlaybackKey {
+    /// 写在令牌中，用于找到校验的密钥，不能包含 `.`
+    pub id: String,
+    pub secret: Secret,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenErr {
+    /// 没有配置签名密钥
+    NotConfigured,
+    Malformed,
+    /// 签名不正确、密钥已经删除或 IP 不匹配
+    BadSignature,
+    Expired,
+    /// 令牌不是为这个文件签发的
+    WrongFile,
+}
+
+pub struct IssuedToken {
+    pub token: String,
+    pub expire_at: LocalDataTime,
+}
+
+/// 使用配置中的第一个密钥签发令牌，ip 不为空时令牌只能在该 IP 使用
+pub fn issue(file_id: UserFileId, ip: Option<IpAddr>) -> Result<IssuedToken, TokenErr> {
+    let cfg = &get_settings().http_server.playback_token;
+    let key = cfg.keys.first().ok_or(TokenErr::NotConfigured)?;
+    let expire_at = Local::now() + Duration::seconds(cfg.ttl_secs as i64);
+    let token = sign(
+        &key.id,
+        key.secret.expose(),
+        file_id,
+        expire_at.timestamp(),
+        ip,
+    );
+    Ok(IssuedToken { token, expire_at })
+}
+
+/// 使用配置中的任意密钥校验令牌，ip 为请求方的地址
+pub fn verify(token: &str, file_id: UserFileId, ip: Option<IpAddr>) -> Result<(), TokenErr> {
+    let cfg = &get_settings().http_server.playback_token;
+    let keys = cfg
+        .keys
+        .iter()
+        .map(|key| (key.id.as_str(), key.secret.expose()));
+    verify_with(keys, token, file_id, ip, Local::now().timestamp())
+}
+
+fn mac(key: &str, payload: &str, ip: Option<IpAddr>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("any key length");
+    mac.update(payload.as_bytes());
+    if let Some(ip) = ip {
+        mac.update(b".");
+        mac.update(ip.to_string().as_bytes());
+    }
+    mac
+}
+
+fn sign(
+    key_id: &str,
+    key: &str,
+    file_id: UserFileId,
+    expire_at: i64,
+    ip: Option<IpAddr>,
+) -> String {
+    let bind = if ip.is_some() { "i" } else { "a" };
+    let payload = format!("{}.{}.{}.{}", key_id, file_id, expire_at, bind);
+    let signature = mac(key, &payload, ip).finalize().into_bytes();
+    format!("{}.{}", payload, hex::encode(&signature[..SIGNATURE_LEN]))
+}
+
+fn verify_with<'a>(
+    keys: impl IntoIterator<Item = (&'a str, &'a str)>,
+    token: &str,
+    file_id: UserFileId,
+    ip: Option<IpAddr>,
+    now: i64,
+) -> Result<(), TokenErr> {
+    use TokenErr::*;
+
+    let (payload, signature) = token.rsplit_once('.').ok_or(Malformed)?;
+    let [key_id, token_file_id, expire_at, bind] = payload.split('.').collect::<Vec<_>>()[..]
+    else {
+        return Err(Malformed);
+    };
+    let expire_at: i64 = expire_at.parse().map_err(|_| Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| Malformed)?;
+    // verify_truncated_left 接受任意长度的前缀，过短的签名可以被穷举
+    if signature.len() != SIGNATURE_LEN {
+        return Err(Malformed);
+    }
+    let ip = match bind {
+        "i" => Some(ip.ok_or(BadSignature)?),
+        "a" => None,
+        _ => return Err(Malformed),
+    };
+
+    let (_, key) = keys
+        .into_iter()
+        .find(|(id, _)| *id == key_id)
+        .ok_or(BadSignature)?;
+    mac(key, payload, ip)
+        .verify_truncated_left(&signature)
+        .map_err(|_| BadSignature)?;
+
+    if token_file_id != file_id.to_string() {
+        return Err(WrongFile);
+    }
+    if expire_at <= now {
+        return Err(Expired);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_verify() {
+        let file_id = UserFileId(42);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let keys = [("k2", "new-key"), ("k1", "old-key")];
+
+        let token = sign("k1", "old-key", file_id, 1000, None);
+        assert_eq!(verify_with(keys, &token, file_id, Some(ip), 999), Ok(()));
+        assert_eq!(
+            verify_with(keys, &token, file_id, None, 1000),
+            Err(TokenErr::Expired)
+        );
+        assert_eq!(
+            verify_with(keys, &token, UserFileId(43), None, 999),
+            Err(TokenErr::WrongFile)
+        );
+        assert_eq!(
+            verify_with([("k2", "new-key")], &token, file_id, None, 999),
+            Err(TokenErr::BadSignature)
+        );
+        let forged = token.replace(".1000.", ".9000.");
+        assert_eq!(
+            verify_with(keys, &forged, file_id, None, 999),
+            Err(TokenErr::BadSignature)
+        );
+        assert_eq!(
+            verify_with(keys, "k1.42", file_id, None, 999),
+            Err(TokenErr::Malformed)
+        );
+    }
+
+    #[test]
+    fn t_short_signature() {
+        let file_id = UserFileId(42);
+        let keys = [("k1", "key")];
+        let token = sign("k1", "key", file_id, 1000, None);
+        let (payload, signature) = token.rsplit_once('.').unwrap();
+
+        for len in [2, 16, signature.len() - 2] {
+            let short = format!("{}.{}", payload, &signature[..len]);
+            assert_eq!(
+                verify_with(keys, &short, file_id, None, 999),
+                Err(TokenErr::Malformed)
+            );
+        }
+        let empty = format!("{}.", payload);
+        assert_eq!(
+            verify_with(keys, &empty, file_id, None, 999),
+            Err(TokenErr::Malformed)
+        );
+    }
+
+    #[test]
+    fn t_ip_binding() {
+        let file_id = UserFileId(42);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let keys = [("k1", "key")];
+
+        let token = sign("k1", "key", file_id, 1000, Some(ip));
+        assert_eq!(verify_with(keys, &token, file_id, Some(ip), 999), Ok(()));
+        assert_eq!(
+            verify_with(keys, &token, file_id, Some(other), 999),
+            Err(TokenErr::BadSignature)
+        );
+        assert_eq!(
+            verify_with(keys, &token, file_id, None, 999),
+            Err(TokenErr::BadSignature)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! 请求 id
+//!
+//! 使用请求头 `X-Request-Id` 中的 id，没有或格式不正确时生成一个。
+//! 请求在带有这个 id 的 span 中处理，期间的日志都可以按 id 关联；
+//! 响应头中返回同样的 id，错误响应的 body 中也会带上。
+//! 需要作为最外层的中间件，使其他中间件产生的日志和错误也能带上 id
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use rand::{thread_rng, Rng};
+use tracing::{info_span, Instrument};
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+const MAX_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// 当前正在处理的请求的 id，不在请求中（如后台任务）时返回 None
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| is_valid(id))
+            .map(ToOwned::to_owned)
+            .unwrap_or_else(generate);
+        let span = info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.path(),
+        );
+        let http_req = req.request().clone();
+
+        let handle = async move {
+            match srv.call(req).await {
+                Ok(res) => res.map_into_boxed_body(),
+                // 在 id 的作用域内生成内层中间件的错误响应，使其中也带有 id
+                Err(err) => ServiceResponse::new(http_req, err.error_response()),
+            }
+        };
+        let handle = REQUEST_ID.scope(id.clone(), handle.instrument(span));
+
+        Box::pin(async move {
+            let mut res = handle.await;
+            // id 只包含可见的 ascii 字符，一定是合法的响应头
+            let value = HeaderValue::from_str(&id).unwrap();
+            res.headers_mut()
+                .insert(HeaderName::from_static("x-request-id"), value);
+            Ok(res)
+        })
+    }
+}
+
+/// 客户端传入的 id 会写入日志，只接受长度有限的可见 ascii 字符
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+fn generate() -> String {
+    hex::encode(thread_rng().gen::<[u8; 16]>())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_is_valid() {
+        assert!(is_valid("a1b2-c3d4"));
+        assert!(is_valid(&generate()));
+        assert!(!is_valid(""));
+        assert!(!is_valid("a b"));
+        assert!(!is_valid("a\nb"));
+        assert!(!is_valid(&"a".repeat(MAX_ID_LEN + 1)));
+    }
+}
@@ -0,0 +1,88 @@
+//! 慢请求日志
+//!
+//! 处理时间超过 `http_server.slow_request_ms` 的请求打印一条警告，包含路由、用户 id 和耗时。
+//! 放在 `IdentityMiddleware` 之内才能取到用户 id，在请求 id 的 span 中打印，可以关联同一请求的慢事务日志
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use actix_identity::IdentityExt;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use tracing::warn;
+
+use crate::settings::get_settings;
+
+pub struct SlowRequestLog;
+
+impl<S, B> Transform<S, ServiceRequest> for SlowRequestLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = SlowRequestLogMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SlowRequestLogMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct SlowRequestLogMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for SlowRequestLogMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let srv = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let threshold = get_settings().http_server.slow_request_ms;
+            if threshold == 0 {
+                return srv.call(req).await;
+            }
+
+            // 路由模板，如 /api/fs/files/{id}，同一接口的请求可以聚合统计
+            let route = req
+                .match_pattern()
+                .unwrap_or_else(|| req.path().to_string());
+            let method = req.method().clone();
+            let user_id = req.get_identity().ok().and_then(|id| id.id().ok());
+
+            let start = Instant::now();
+            let res = srv.call(req).await;
+            let elapsed = start.elapsed();
+            if elapsed >= Duration::from_millis(threshold) {
+                let status = res.as_ref().map(|r| r.status().as_u16()).ok();
+                warn!(
+                    %method,
+                    %route,
+                    user_id = user_id.as_deref(),
+                    status,
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    "slow request"
+                );
+            }
+            res
+        })
+    }
+}
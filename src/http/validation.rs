@@ -0,0 +1,281 @@
+//! 请求参数校验
+//!
+//! 请求参数无法解析或者校验失败时返回 422，`status` 位于 900 ~ 909，
+//! `errors` 中列出每个字段的错误，字段名与请求中的一致（camelCase，嵌套字段以 `.` 和 `[i]` 连接）
+
+use std::ops::Deref;
+
+use actix_web::{
+    dev::Payload,
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    web::{Json, Query},
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use futures_util::future::LocalBoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use super::{request_id, ApiResponse};
+
+/// 请求体无法解析
+pub const INVALID_BODY: u32 = 900;
+/// 查询参数无法解析
+pub const INVALID_QUERY: u32 = 901;
+/// 路径参数无法解析
+pub const INVALID_PATH: u32 = 902;
+/// 字段校验失败
+pub const INVALID_FIELD: u32 = 903;
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    /// 出错的字段，无法确定字段时为空
+    pub field: Option<String>,
+    /// 校验规则，如 length、range
+    pub code: String,
+    pub message: Option<String>,
+}
+
+#[derive(derive_more::Display, Debug)]
+#[display(fmt = "{msg}")]
+pub struct ValidationErr {
+    status: u32,
+    msg: String,
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErr {
+    fn unparsable(status: u32, err: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            msg: "invalid request".to_string(),
+            errors: vec![FieldError {
+                field: None,
+                code: "parse".to_string(),
+                message: Some(err.to_string()),
+            }],
+        }
+    }
+}
+
+impl ResponseError for ValidationErr {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let resp = ApiResponse::<()> {
+            status: self.status,
+            err_msg: Some(self.msg.clone()),
+            data: None,
+            errors: Some(self.errors.clone()),
+            request_id: request_id::current(),
+        };
+        HttpResponse::build(self.status_code()).json(resp)
+    }
+}
+
+impl From<ValidationErrors> for ValidationErr {
+    fn from(value: ValidationErrors) -> Self {
+        let mut errors = vec![];
+        flatten(None, &value, &mut errors);
+        errors.sort_by(|a, b| a.field.cmp(&b.field));
+        Self {
+            status: INVALID_FIELD,
+            msg: "validation failed".to_string(),
+            errors,
+        }
+    }
+}
+
+fn flatten(prefix: Option<&str>, errors: &ValidationErrors, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let field = camel_case(field);
+        let path = match prefix {
+            Some(prefix) => format!("{prefix}.{field}"),
+            None => field,
+        };
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                out.extend(errs.iter().map(|e| FieldError {
+                    field: Some(path.clone()),
+                    code: e.code.to_string(),
+                    message: e.message.as_ref().map(|m| m.to_string()),
+                }));
+            }
+            ValidationErrorsKind::Struct(inner) => flatten(Some(&path), inner, out),
+            ValidationErrorsKind::List(items) => {
+                for (idx, inner) in items {
+                    flatten(Some(&format!("{path}[{idx}]")), inner, out);
+                }
+            }
+        }
+    }
+}
+
+fn camel_case(field: &str) -> String {
+    let mut camel = String::with_capacity(field.len());
+    let mut upper = false;
+    for c in field.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            camel.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+/// 十六进制的 sha256 摘要
+pub fn sha256_hex(value: &str) -> Result<(), ValidationError> {
+    if value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("sha256_hex"))
+    }
+}
+
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(err) => ValidationErr::unparsable(INVALID_BODY, err).into(),
+        err => err.into(),
+    }
+}
+
+pub fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        QueryPayloadError::Deserialize(err) => ValidationErr::unparsable(INVALID_QUERY, err).into(),
+        err => err.into(),
+    }
+}
+
+pub fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        PathError::Deserialize(err) => ValidationErr::unparsable(INVALID_PATH, err).into(),
+        err => err.into(),
+    }
+}
+
+/// 解析 json 请求体后再执行 [`Validate`] 校验
+pub struct ValidJson<T>(pub T);
+
+impl<T> ValidJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for ValidJson<T>
+where
+    T: DeserializeOwned + Validate + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            value.validate().map_err(ValidationErr::from)?;
+            Ok(Self(value))
+        })
+    }
+}
+
+/// 解析查询参数后再执行 [`Validate`] 校验
+pub struct ValidQuery<T>(pub T);
+
+impl<T> ValidQuery<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidQuery<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> FromRequest for ValidQuery<T>
+where
+    T: DeserializeOwned + Validate + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let query = Query::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = query.await?.into_inner();
+            value.validate().map_err(ValidationErr::from)?;
+            Ok(Self(value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Validate)]
+    struct Item {
+        #[validate(length(min = 1))]
+        file_name: String,
+    }
+
+    #[derive(Validate)]
+    struct Params {
+        #[validate(range(min = 1))]
+        page_size: u32,
+        #[validate]
+        items: Vec<Item>,
+    }
+
+    #[test]
+    fn t_field_paths() {
+        let params = Params {
+            page_size: 0,
+            items: vec![
+                Item {
+                    file_name: "a".to_string(),
+                },
+                Item {
+                    file_name: String::new(),
+                },
+            ],
+        };
+        let err = ValidationErr::from(params.validate().unwrap_err());
+        assert_eq!(err.status, INVALID_FIELD);
+
+        let fields: Vec<_> = err
+            .errors
+            .iter()
+            .map(|e| e.field.clone().unwrap())
+            .collect();
+        assert_eq!(fields, ["items[1].fileName", "pageSize"]);
+        assert_eq!(err.errors[1].code, "range");
+    }
+
+    #[test]
+    fn t_sha256_hex() {
+        assert!(sha256_hex(&"a".repeat(64)).is_ok());
+        assert!(sha256_hex(&"g".repeat(64)).is_err());
+        assert!(sha256_hex("abc").is_err());
+    }
+}
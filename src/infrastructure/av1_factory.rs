@@ -1,7 +1,18 @@
+//! av1-factory 的请求
+//!
+//! 请求失败时按带随机抖动的指数退避重试；连续失败达到阈值后熔断，熔断期间直接返回 [`CircuitOpen`]，
+//! 不再等待超时，由调用方决定是否写入 outbox 稍后发送
+
 use anyhow::{ensure, Result};
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{borrow::Cow, path::Path};
-use tracing::debug;
+use std::{
+    borrow::Cow,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
 
 #[cfg(not(test))]
 use crate::settings::get_settings;
@@ -9,6 +20,7 @@ use crate::{
     domain::{
         file_system::file::SysFileId,
         transcode_order::{params::TranscodeTaskParams, TranscodeTaskId},
+        user::webhook::retry_delay,
     },
     id_wraper, post,
 };
@@ -29,6 +41,14 @@ pub enum VideoTaskType<'a> {
     Parse(Parse<'a>),
     Thumbnail(Thumbnail<'a>),
     Transcode(&'a TranscodeTaskParams),
+    Cancel(Cancel),
+}
+
+/// 取消转码任务，任务已结束或不存在时由 av1-factory 忽略
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Cancel {
+    task_id: i64,
 }
 
 #[derive(Serialize, Debug)]
@@ -41,6 +61,136 @@ pub struct Parse<'a> {
 #[derive(Deserialize, Debug)]
 pub struct Av1FactoryCfg {
     endpoint: String,
+    #[serde(default)]
+    retry: RetryCfg,
+    #[serde(default)]
+    breaker: BreakerCfg,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct RetryCfg {
+    /// 首次请求失败后的重试次数
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// 首次重试前等待时间的上限，之后每次翻倍，实际等待时间在上限的一半到上限之间随机
+    #[serde(default = "default_base_backoff_ms")]
+    pub base_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryCfg {
+    fn default() -> Self {
+        Self {
+            max_retries: default_max_retries(),
+            base_backoff_ms: default_base_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+fn default_max_retries() -> u32 {
+    2
+}
+
+fn default_base_backoff_ms() -> u64 {
+    200
+}
+
+fn default_max_backoff_ms() -> u64 {
+    2000
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BreakerCfg {
+    /// 连续失败多少次后熔断
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// 熔断的持续时间，之后放行请求试探，成功后恢复，失败则再次熔断
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64,
+}
+
+impl Default for BreakerCfg {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            open_secs: default_open_secs(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_secs() -> u64 {
+    30
+}
+
+#[cfg(not(test))]
+fn cfg() -> (RetryCfg, BreakerCfg) {
+    let cfg = &get_settings().av1_factory;
+    (cfg.retry, cfg.breaker)
+}
+
+#[cfg(test)]
+fn cfg() -> (RetryCfg, BreakerCfg) {
+    Default::default()
+}
+
+/// 熔断期间不发送请求
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "av1-factory circuit open")]
+pub struct CircuitOpen;
+
+impl std::error::Error for CircuitOpen {}
+
+struct Breaker {
+    /// 连续失败的次数
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl Breaker {
+    fn allows(&self, now: Instant) -> bool {
+        self.open_until.map_or(true, |until| now >= until)
+    }
+
+    fn record(&mut self, ok: bool, cfg: &BreakerCfg, now: Instant) {
+        if ok {
+            if self.open_until.take().is_some() {
+                info!("av1-factory recovered, circuit closed");
+            }
+            self.failures = 0;
+            return;
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        if self.failures >= cfg.failure_threshold && self.allows(now) {
+            warn!(
+                failures = self.failures,
+                "av1-factory unavailable, circuit opened"
+            );
+            self.open_until = Some(now + Duration::from_secs(cfg.open_secs));
+        }
+    }
+}
+
+static BREAKER: Mutex<Breaker> = Mutex::new(Breaker {
+    failures: 0,
+    open_until: None,
+});
+
+/// 是否处于熔断期间
+pub(crate) fn is_circuit_open() -> bool {
+    !BREAKER.lock().unwrap().allows(Instant::now())
+}
+
+/// 可以直接发送，也可以写入 outbox 的请求
+pub(crate) struct FactoryTask {
+    pub id: i64,
+    pub payload: String,
 }
 
 #[allow(unused)]
@@ -51,8 +201,14 @@ struct Av1FactoryResp<T> {
     data: Option<T>,
 }
 
+/// 发送信息采集请求，失败时重试，不写入 outbox
 pub(crate) async fn parse_file(file_id: SysFileId, path: &Path) -> Result<()> {
     debug!(%file_id, "sending parse task request");
+    let task = parse_task(file_id, path)?;
+    send_with_retry(&task.payload).await
+}
+
+pub(crate) fn parse_task(file_id: SysFileId, path: &Path) -> Result<FactoryTask> {
     let task = VideoTask {
         id: TaskId::next_id().0,
         file_id: file_id.0,
@@ -60,9 +216,7 @@ pub(crate) async fn parse_file(file_id: SysFileId, path: &Path) -> Result<()> {
             path: Cow::Borrowed(path),
         }),
     };
-    send_task(task).await?;
-
-    Ok(())
+    task.into_factory_task()
 }
 
 #[derive(Serialize, Debug)]
@@ -71,46 +225,169 @@ pub struct Thumbnail<'a> {
     out_dir: &'a Path,
 }
 
-pub(crate) async fn generate_thumbnail(
+pub(crate) fn thumbnail_task(
     file_id: SysFileId,
     path: &Path,
     out_dir: &Path,
-) -> Result<()> {
-    debug!(%file_id, "sending parse task request");
+) -> Result<FactoryTask> {
     let task = VideoTask {
         id: TaskId::next_id().0,
         file_id: file_id.0,
         task: VideoTaskType::Thumbnail(Thumbnail { path, out_dir }),
     };
-    send_task(task).await?;
+    task.into_factory_task()
+}
 
-    Ok(())
+impl VideoTask<'_> {
+    fn into_factory_task(self) -> Result<FactoryTask> {
+        Ok(FactoryTask {
+            id: self.id,
+            payload: serde_json::to_string(&self)?,
+        })
+    }
 }
 
-pub(crate) async fn transcode(
+/// 转码任务的请求体，写入 outbox 后由后台任务通过 [`send_payload`] 发送
+pub(crate) fn transcode_payload(
     task_id: TranscodeTaskId,
     file_id: SysFileId,
     params: &TranscodeTaskParams,
-) -> Result<()> {
-    debug!(%file_id, "sending transcode task request");
+) -> Result<String> {
     let task = VideoTask {
         id: task_id.0,
         file_id: file_id.0,
         task: VideoTaskType::Transcode(params),
     };
-    send_task(task).await?;
+    Ok(serde_json::to_string(&task)?)
+}
 
-    Ok(())
+/// 取消转码任务的请求，写入 outbox 后与转码请求一样发送。请求本身使用新的 id
+pub(crate) fn cancel_task(task_id: TranscodeTaskId, file_id: SysFileId) -> Result<FactoryTask> {
+    let task = VideoTask {
+        id: TaskId::next_id().0,
+        file_id: file_id.0,
+        task: VideoTaskType::Cancel(Cancel { task_id: task_id.0 }),
+    };
+    task.into_factory_task()
+}
+
+/// 从请求体中取出要取消的转码任务，不是取消请求时返回 None
+pub(crate) fn cancel_target(payload: &str) -> Option<TranscodeTaskId> {
+    #[derive(Deserialize)]
+    struct Payload {
+        task: Task,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Task {
+        Cancel(Cancel),
+        #[serde(other)]
+        Other,
+    }
+
+    match serde_json::from_str(payload).ok()? {
+        Payload {
+            task: Task::Cancel(cancel),
+        } => Some(TranscodeTaskId(cancel.task_id)),
+        _ => None,
+    }
+}
+
+/// 从请求体中取出转码参数，不是转码请求时返回 None
+pub(crate) fn transcode_params(payload: &str) -> Option<TranscodeTaskParams> {
+    #[derive(Deserialize)]
+    struct Payload {
+        task: Task,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    enum Task {
+        Transcode(TranscodeTaskParams),
+        #[serde(other)]
+        Other,
+    }
+
+    match serde_json::from_str(payload).ok()? {
+        Payload {
+            task: Task::Transcode(params),
+        } => Some(params),
+        _ => None,
+    }
+}
+
+/// 发送请求，失败时按退避时间重试。熔断时立即返回 [`CircuitOpen`]
+pub(crate) async fn send_with_retry(payload: &str) -> Result<()> {
+    let (retry, _) = cfg();
+    let mut attempts = 0;
+    loop {
+        let res = send_payload(payload.to_string()).await;
+        attempts += 1;
+        match res {
+            Ok(()) => return Ok(()),
+            Err(err) if err.is::<CircuitOpen>() || attempts > retry.max_retries => return Err(err),
+            Err(err) => {
+                let delay = backoff(attempts, &retry);
+                debug!(
+                    ?err,
+                    attempts,
+                    ?delay,
+                    "av1-factory request failed, retrying"
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// 第 attempts 次失败后的等待时间，在指数退避的上限的一半到上限之间随机，避免多个请求同时重试
+fn backoff(attempts: u32, cfg: &RetryCfg) -> Duration {
+    let max = retry_delay(
+        attempts,
+        Duration::from_millis(cfg.base_backoff_ms),
+        Duration::from_millis(cfg.max_backoff_ms),
+    );
+    let max = max.as_millis() as u64;
+    Duration::from_millis(thread_rng().gen_range(max / 2..=max))
 }
 
-async fn send_task(task: VideoTask<'_>) -> Result<()> {
+/// 发送一次请求并记录结果，熔断时不发送
+pub(crate) async fn send_payload(payload: String) -> Result<()> {
+    let (_, breaker) = cfg();
+    if is_circuit_open() {
+        return Err(CircuitOpen.into());
+    }
+
+    let res = send_payload_inner(payload).await;
+    BREAKER
+        .lock()
+        .unwrap()
+        .record(res.is_ok(), &breaker, Instant::now());
+    res
+}
+
+async fn send_payload_inner(payload: String) -> Result<()> {
     #[cfg(not(test))]
     let endpoint = &get_settings().av1_factory.endpoint;
+    // 集成测试通过环境变量指向模拟的转码工厂
     #[cfg(test)]
-    let endpoint = "http://127.0.0.1:8993";
+    let endpoint = &std::env::var("AV1__AV1_FACTORY__ENDPOINT")
+        .unwrap_or_else(|_| "http://127.0.0.1:8993".to_string());
+
+    send_to(endpoint, payload).await
+}
 
+/// 发送转码请求给注册的节点，接口与 av1-factory 相同。
+/// 单个节点失败不影响其他节点，不计入熔断
+pub(crate) async fn send_to_worker(endpoint: &str, payload: String) -> Result<()> {
+    debug!(endpoint, "sending transcode task to worker");
+    send_to(endpoint, payload).await
+}
+
+async fn send_to(endpoint: &str, payload: String) -> Result<()> {
     let url = format!("{}/api/video/task", endpoint);
-    let resp: Av1FactoryResp<()> = post!(url, body: serde_json::to_string(&task).unwrap());
+    let resp: Av1FactoryResp<()> = post!(url, body: payload);
     ensure!(resp.status == 0, "parse req error: {:?}", resp.msg);
 
     Ok(())
@@ -120,6 +397,78 @@ async fn send_task(task: VideoTask<'_>) -> Result<()> {
 mod test {
     use super::*;
 
+    #[test]
+    fn t_breaker() {
+        let cfg = BreakerCfg {
+            failure_threshold: 2,
+            open_secs: 10,
+        };
+        let now = Instant::now();
+        let mut breaker = Breaker {
+            failures: 0,
+            open_until: None,
+        };
+
+        breaker.record(false, &cfg, now);
+        assert!(breaker.allows(now));
+        breaker.record(false, &cfg, now);
+        assert!(!breaker.allows(now));
+        assert!(!breaker.allows(now + Duration::from_secs(9)));
+
+        // 熔断结束后放行试探，失败时再次熔断
+        let later = now + Duration::from_secs(10);
+        assert!(breaker.allows(later));
+        breaker.record(false, &cfg, later);
+        assert!(!breaker.allows(later));
+
+        let later = later + Duration::from_secs(10);
+        breaker.record(true, &cfg, later);
+        assert!(breaker.allows(later));
+        assert_eq!(breaker.failures, 0);
+    }
+
+    #[test]
+    fn t_backoff() {
+        let cfg = RetryCfg {
+            max_retries: 3,
+            base_backoff_ms: 100,
+            max_backoff_ms: 300,
+        };
+        for _ in 0..100 {
+            let first = backoff(1, &cfg).as_millis();
+            assert!((50..=100).contains(&first));
+            let third = backoff(3, &cfg).as_millis();
+            assert!((150..=300).contains(&third));
+        }
+    }
+
+    #[test]
+    fn t_transcode_params() {
+        let parse = parse_task(1.into(), Path::new("/aa/bb")).unwrap();
+        assert!(transcode_params(&parse.payload).is_none());
+
+        let params: TranscodeTaskParams = serde_json::from_value(serde_json::json!({
+            "work_dir": "/tmp",
+            "path": "/tmp/a.mp4",
+            "dst_path": "/tmp/b.mp4",
+            "frame_count": 250,
+            "is_h264": true,
+            "container": "mp4",
+            "video": {
+                "isHdr": false,
+                "width": 1280,
+                "height": 720,
+                "format": "av1",
+                "quality": "base",
+            },
+            "audio": null,
+        }))
+        .unwrap();
+        let payload = transcode_payload(TranscodeTaskId(2), SysFileId(1), &params).unwrap();
+        let parsed = transcode_params(&payload).unwrap();
+        assert_eq!(parsed.video, params.video);
+    }
+
     #[tokio::test]
     #[tracing_test::traced_test]
     async fn aa() {
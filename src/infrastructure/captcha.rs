@@ -0,0 +1,108 @@
+//! 人机验证服务
+//!
+//! hCaptcha、Turnstile 和滑块验证使用相同的校验方式：把前端拿到的 token 和密钥提交到校验地址，
+//! 返回的 json 中 `success` 为 true 时通过。滑块验证没有默认地址，需要配置 `verify_url`
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use redis::AsyncCommands;
+use serde::Deserialize;
+
+use crate::{redis_conn_switch::redis_conn, settings::Secret};
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptchaProvider {
+    #[default]
+    Hcaptcha,
+    Turnstile,
+    Slider,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct CaptchaCfg {
+    #[serde(default)]
+    pub enable: bool,
+    #[serde(default)]
+    pub provider: CaptchaProvider,
+    #[serde(default)]
+    pub secret: Secret,
+    /// 为空时使用服务商的默认地址
+    #[serde(default)]
+    pub verify_url: Option<String>,
+    /// 每个 ip 和每个目标邮箱、手机号在一个时间窗口内免验证的请求次数，为 0 时每次都需要验证
+    #[serde(default = "default_free_attempts")]
+    pub free_attempts: u64,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// 为 true 时不请求校验服务，任意非空的 token 都通过，只用于测试环境
+    #[serde(default)]
+    pub bypass: bool,
+}
+
+fn default_free_attempts() -> u64 {
+    3
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+#[derive(Deserialize)]
+struct VerifyResp {
+    success: bool,
+}
+
+impl CaptchaCfg {
+    fn verify_url(&self) -> Result<&str> {
+        let url = match (&self.verify_url, self.provider) {
+            (Some(url), _) => url,
+            (None, CaptchaProvider::Hcaptcha) => "https://hcaptcha.com/siteverify",
+            (None, CaptchaProvider::Turnstile) => {
+                "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+            }
+            (None, CaptchaProvider::Slider) => bail!("verify_url of slider captcha not configured"),
+        };
+        Ok(url)
+    }
+
+    /// 校验前端提交的 token，`remote_ip` 为用户的 ip
+    pub async fn verify(&self, token: &str, remote_ip: Option<&str>) -> Result<bool> {
+        if self.bypass {
+            return Ok(!token.is_empty());
+        }
+
+        let mut form = vec![("secret", self.secret.expose()), ("response", token)];
+        if let Some(ip) = remote_ip {
+            form.push(("remoteip", ip));
+        }
+        let resp: VerifyResp = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()?
+            .post(self.verify_url()?)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp.success)
+    }
+}
+
+fn count_key(scene: &str, subject: &str, window: i64) -> String {
+    format!("captcha:{}:{}:{}", scene, subject, window)
+}
+
+/// 在当前的时间窗口内记录 `subject`（ip 或目标邮箱、手机号）的一次请求，返回窗口内的请求次数
+pub(crate) async fn incr_count(scene: &str, subject: &str, window_secs: u64) -> Result<u64> {
+    let window = chrono::Local::now().timestamp() / window_secs as i64;
+    let key = count_key(scene, subject, window);
+    let conn = &mut redis_conn().await?;
+    let count: u64 = conn.incr(&key, 1).await?;
+    if count == 1 {
+        let _: () = conn.expire(&key, window_secs as usize).await?;
+    }
+    Ok(count)
+}
@@ -1,6 +1,8 @@
-use std::{fs::File, io::Read, path::PathBuf, sync::OnceLock};
+use std::{sync::OnceLock, time::Duration};
 
 use anyhow::Result;
+use chrono::Local;
+use futures_util::StreamExt;
 use lettre::{
     message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
     AsyncTransport, Message, Tokio1Executor,
@@ -8,37 +10,128 @@ use lettre::{
 use rand::{thread_rng, Rng};
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
-
-use crate::{redis_conn_switch::redis_conn, settings::get_settings};
+use tokio::sync::{mpsc, Notify};
+use tracing::{debug, info, warn};
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    domain::user::webhook,
+    infrastructure::{
+        email_template::{self, TemplatedEmail},
+        repo_email_outbox::{self, EmailPo, EmailStatus, NewEmailPo},
+    },
+    log_if_err,
+    redis_conn_switch::redis_conn,
+    settings::{get_settings, Secret},
+    LocalDataTime,
+};
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct EmailCodeCfg {
     pub from_full: String,
     pub from_addr: String,
-    pub password: String,
+    pub password: Secret,
     pub server: String,
     pub port: u16,
-    pub subject: String,
+    /// 备用的发件账号，主账号发送失败时使用
+    #[serde(default)]
+    pub fallback: Option<SmtpCfg>,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct SmtpCfg {
+    pub from_full: String,
+    pub from_addr: String,
+    pub password: Secret,
+    pub server: String,
+    pub port: u16,
+}
+
+/// 邮件发送队列的配置
+#[derive(Debug, Deserialize)]
+pub struct EmailQueueCfg {
+    /// 没有新邮件时轮询的间隔
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// 超过此次数后放弃发送
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// 首次重试的等待时间，之后每次翻倍
+    #[serde(default = "default_base_backoff_secs")]
+    pub base_backoff_secs: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    /// 发送期间的租约，超时未更新状态时可以被重新发送
+    #[serde(default = "default_lease_secs")]
+    pub lease_secs: u64,
+}
+
+impl Default for EmailQueueCfg {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: default_poll_interval_secs(),
+            max_attempts: default_max_attempts(),
+            base_backoff_secs: default_base_backoff_secs(),
+            max_backoff_secs: default_max_backoff_secs(),
+            lease_secs: default_lease_secs(),
+        }
+    }
+}
 
-    pub template_file: PathBuf,
+fn default_poll_interval_secs() -> u64 {
+    5
 }
 
-static EAMIL_CODE_TEMPLATE: OnceLock<String> = OnceLock::new();
+fn default_max_attempts() -> u32 {
+    8
+}
 
-//  这个函数应该在服务初始化时被调用一次，以检测模板文件是否可以正常读取
-pub fn load_email_code_template() -> Result<&'static String> {
-    let path = &get_settings().email_code.template_file;
-    // 约定文件名后尽量少更改，所以直接在代码中写死路径
-    let mut file = File::open(path)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content).unwrap();
+fn default_base_backoff_secs() -> u64 {
+    5
+}
+
+fn default_max_backoff_secs() -> u64 {
+    600
+}
+
+fn default_lease_secs() -> u64 {
+    60
+}
+
+/// 验证码的有效期，过期之前没有发送成功的验证码邮件不再发送
+const CODE_TTL_SECS: u64 = 300;
+
+/// 服务启动时检查这些邮件都有模板
+pub const BUILTIN_TEMPLATES: &[&str] = &[
+    VerifyCodeEmail::TEMPLATE,
+    OrderEmail::TEMPLATE,
+    WelcomeEmail::TEMPLATE,
+];
+
+#[derive(Debug, Serialize)]
+pub struct VerifyCodeEmail<'a> {
+    pub email_code: String,
+    pub email_target: &'a str,
+}
 
-    Ok(EAMIL_CODE_TEMPLATE.get_or_init(|| content))
+impl TemplatedEmail for VerifyCodeEmail<'_> {
+    const TEMPLATE: &'static str = "email_code";
 }
 
-pub fn get_email_code_template() -> &'static String {
-    EAMIL_CODE_TEMPLATE.get().unwrap()
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WelcomeEmailCfg {
+    #[serde(default)]
+    pub enable: bool,
+}
+
+/// 注册成功后的欢迎邮件
+#[derive(Debug, Serialize)]
+pub struct WelcomeEmail<'a> {
+    pub user_name: &'a str,
+}
+
+impl TemplatedEmail for WelcomeEmail<'_> {
+    const TEMPLATE: &'static str = "welcome";
 }
 
 pub struct EmailCodeSender<'a> {
@@ -70,19 +163,24 @@ impl<'a> EmailCodeSender<'a> {
         // 生成验证码
         let code: i64 = thread_rng().gen_range(100000..999999);
 
+        // 5 分钟有效期，在验证码加一个计数器
+        conn.set_ex(
+            Self::key(&self.email),
+            code * 10 + 5,
+            CODE_TTL_SECS as usize,
+        )
+        .await?;
+
         if !self.fake {
-            // 发送验证码
-            let config = &get_settings().email_code;
-            let template = get_email_code_template();
-            let body = template.replace("{{email_code}}", code.to_string().as_str());
-            let body = body.replace("{{email_target}}", self.email);
-            send_email(&config.from_full, self.email, &config.subject, body).await?;
+            // 放入发送队列后立即返回，不等待 SMTP 服务器
+            let email = VerifyCodeEmail {
+                email_code: code.to_string(),
+                email_target: self.email,
+            };
+            let expire_at = Local::now() + chrono::Duration::seconds(CODE_TTL_SECS as i64);
+            send(self.email, &email, Some(expire_at)).await?;
         }
-
-        debug!(code, "email code sent");
-        // 5 分钟有效期，在验证码加一个计数器
-        conn.set_ex(Self::key(&self.email), code * 10 + 5, 300)
-            .await?;
+        debug!(code, "email code enqueued");
 
         Ok(())
     }
@@ -140,34 +238,302 @@ impl<'a> EmailCodeSender<'a> {
     }
 }
 
-pub async fn send_email(
+/// 有新邮件时唤醒发送任务，不必等到下一次轮询
+static WAKE: Notify = Notify::const_new();
+
+/// 用模板渲染邮件后放入发送队列
+pub async fn send<T: TemplatedEmail>(
+    to: &str,
+    email: &T,
+    expire_at: Option<LocalDataTime>,
+) -> Result<()> {
+    let email = email_template::render(email)?;
+    enqueue(to, &email.subject, &email.body, expire_at).await
+}
+
+/// 把邮件放入发送队列，由后台任务发送。`expire_at` 之前没有发送成功时放弃
+pub async fn enqueue(
+    to: &str,
+    subject: &str,
+    body: &str,
+    expire_at: Option<LocalDataTime>,
+) -> Result<()> {
+    let email = NewEmailPo {
+        to_addr: to,
+        subject,
+        body,
+        expire_at,
+    };
+    let conn = &mut pg_conn().await?;
+    repo_email_outbox::save(&email, conn).await?;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// 启动后台发送邮件的任务
+pub fn spawn_queue_worker() {
+    let cfg = &get_settings().email_queue;
+
+    info!(?cfg, "email queue worker started");
+    tokio::spawn(async move {
+        loop {
+            log_if_err!(dispatch_due(cfg).await);
+            let _ =
+                tokio::time::timeout(Duration::from_secs(cfg.poll_interval_secs), WAKE.notified())
+                    .await;
+        }
+    });
+}
+
+async fn dispatch_due(cfg: &EmailQueueCfg) -> Result<()> {
+    let emails = {
+        let conn = &mut pg_conn().await?;
+        repo_email_outbox::due_emails(50, conn).await?
+    };
+    futures_util::stream::iter(emails)
+        .for_each_concurrent(4, |email| async move {
+            log_if_err!(dispatch(cfg, email).await);
+        })
+        .await;
+    Ok(())
+}
+
+async fn dispatch(cfg: &EmailQueueCfg, email: EmailPo) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    if email.expire_at.is_some_and(|at| at <= Local::now()) {
+        warn!(
+            id = email.id,
+            to = %email.to_addr,
+            "email expired before sent"
+        );
+        repo_email_outbox::failed(
+            email.id,
+            EmailStatus::Dead,
+            email.attempts,
+            email.next_attempt_at,
+            "expired",
+            conn,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let lease = Local::now() + chrono::Duration::seconds(cfg.lease_secs as i64);
+    if !repo_email_outbox::claim(&email, lease, conn).await? {
+        return Ok(());
+    }
+
+    let attempts = email.attempts + 1;
+    match send_with_fallback(&email.to_addr, &email.subject, email.body).await {
+        Ok(()) => repo_email_outbox::sent(email.id, attempts, conn).await?,
+        Err(err) => {
+            let err = format!("{:#}", err);
+            let status = if attempts as u32 >= cfg.max_attempts {
+                warn!(id = email.id, to = %email.to_addr, %err, "gave up sending email");
+                EmailStatus::Dead
+            } else {
+                EmailStatus::Pending
+            };
+            let delay = webhook::retry_delay(
+                attempts as u32,
+                Duration::from_secs(cfg.base_backoff_secs),
+                Duration::from_secs(cfg.max_backoff_secs),
+            );
+            repo_email_outbox::failed(
+                email.id,
+                status,
+                attempts,
+                Local::now() + chrono::Duration::seconds(delay.as_secs() as i64),
+                &err,
+                conn,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// 使用主发件账号发送，失败时使用备用账号
+async fn send_with_fallback(to: &str, subject: &str, body: String) -> Result<()> {
+    let config = &get_settings().email_code;
+    let message = build_message(&config.from_full, to, subject, body.clone())?;
+    let err = match build_mailer().send(message).await {
+        Ok(response) => {
+            debug!(?response, "sent email successfully");
+            return Ok(());
+        }
+        Err(err) => err,
+    };
+    let Some(fallback) = &config.fallback else {
+        return Err(err.into());
+    };
+
+    warn!(?err, "send email failed, try the fallback smtp server");
+    let message = build_message(&fallback.from_full, to, subject, body)?;
+    let mailer = transport(
+        &fallback.server,
+        &fallback.from_addr,
+        &fallback.password,
+        fallback.port,
+    );
+    let response = mailer.send(message).await?;
+    debug!(?response, "sent email by the fallback smtp server");
+    Ok(())
+}
+
+fn build_message(
     from: &str,
     to: &str,
     subject: impl Into<String>,
     body: String,
-) -> Result<()> {
-    let config = &get_settings().email_code;
+) -> Result<Message> {
     let email = Message::builder()
         .from(from.parse()?)
         .to(to.parse()?)
         .subject(subject)
         .header(ContentType::TEXT_HTML)
-        .body(body.to_string())?;
+        .body(body)?;
+    Ok(email)
+}
+
+fn build_mailer() -> AsyncSmtpTransport<Tokio1Executor> {
+    let config = &get_settings().email_code;
+    transport(
+        &config.server,
+        &config.from_addr,
+        &config.password,
+        config.port,
+    )
+}
+
+fn transport(
+    server: &str,
+    from_addr: &str,
+    password: &Secret,
+    port: u16,
+) -> AsyncSmtpTransport<Tokio1Executor> {
+    let creds = Credentials::new(from_addr.to_string(), password.expose().to_string());
+
+    AsyncSmtpTransport::<Tokio1Executor>::relay(server)
+        .unwrap()
+        .credentials(creds)
+        .port(port)
+        .build()
+}
+
+/// 转码订单完成邮件的配置，使用 email_code 中的发件账号
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderEmailCfg {
+    #[serde(default)]
+    pub enable: bool,
+    /// 每批最多发送的邮件数，同一批邮件复用一个 SMTP 连接
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// 两批邮件之间的间隔，避免触发邮件服务器的频率限制
+    #[serde(default = "default_batch_interval_secs")]
+    pub batch_interval_secs: u64,
+}
 
-    let creds = Credentials::new(config.from_addr.to_string(), config.password.to_string());
+impl Default for OrderEmailCfg {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            batch_size: default_batch_size(),
+            batch_interval_secs: default_batch_interval_secs(),
+        }
+    }
+}
 
-    let mailer: AsyncSmtpTransport<Tokio1Executor> =
-        AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)
-            .unwrap()
-            .credentials(creds)
-            .port(config.port)
-            .build();
+fn default_batch_size() -> usize {
+    20
+}
 
-    let response = mailer.send(email).await?;
-    debug!(?response, "sent email successfully");
+fn default_batch_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderEmail {
+    #[serde(skip)]
+    pub to: String,
+    pub user_name: String,
+    pub order_id: String,
+    pub ok_count: usize,
+    pub failed_count: usize,
+}
+
+impl TemplatedEmail for OrderEmail {
+    const TEMPLATE: &'static str = "order_finished";
+}
+
+static ORDER_EMAIL_QUEUE: OnceLock<mpsc::UnboundedSender<OrderEmail>> = OnceLock::new();
+
+/// 启动后台发送订单邮件的任务，未开启时不做任何事
+pub fn start_order_mailer() -> Result<()> {
+    let config = &get_settings().order_email;
+    if !config.enable {
+        return Ok(());
+    }
+    let (tx, rx) = mpsc::unbounded_channel();
+    if ORDER_EMAIL_QUEUE.set(tx).is_err() {
+        warn!("order mailer already started");
+        return Ok(());
+    }
+    tokio::spawn(order_mailer(rx));
     Ok(())
 }
 
+pub fn order_mailer_enabled() -> bool {
+    ORDER_EMAIL_QUEUE.get().is_some()
+}
+
+/// 将邮件放入发送队列，返回是否成功入队
+pub fn enqueue_order_email(email: OrderEmail) -> bool {
+    let Some(queue) = ORDER_EMAIL_QUEUE.get() else {
+        return false;
+    };
+    queue.send(email).is_ok()
+}
+
+async fn order_mailer(mut rx: mpsc::UnboundedReceiver<OrderEmail>) {
+    let config = &get_settings().order_email;
+    let from = &get_settings().email_code.from_full;
+    let batch_size = config.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while let Some(first) = rx.recv().await {
+        batch.push(first);
+        while batch.len() < batch_size {
+            let Ok(email) = rx.try_recv() else {
+                break;
+            };
+            batch.push(email);
+        }
+
+        let mailer = build_mailer();
+        let total = batch.len();
+        let mut sent = 0;
+        for email in batch.drain(..) {
+            let message = email_template::render(&email)
+                .and_then(|body| build_message(from, &email.to, body.subject, body.body));
+            let message = match message {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!(?err, ?email, "invalid order email");
+                    continue;
+                }
+            };
+            match mailer.send(message).await {
+                Ok(_) => sent += 1,
+                Err(err) => warn!(?err, ?email, "send order email failed"),
+            }
+        }
+        info!(total, sent, "order emails sent");
+
+        tokio::time::sleep(Duration::from_secs(config.batch_interval_secs)).await;
+    }
+}
+
 #[cfg(test)]
 mod test {
 
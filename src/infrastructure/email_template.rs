@@ -0,0 +1,357 @@
+//! 邮件模板
+//!
+//! 模板目录下的每个 `<name>.html` 是一个模板，`<title>` 中的内容作为邮件标题。
+//! 标题和正文中的 `{{变量}}` 在发送时替换为参数，正文中的参数会转义 HTML 字符。
+//! 后台任务定期检查文件的修改时间，修改文案后不需要重启服务
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::settings::get_settings;
+
+#[derive(Debug, Deserialize)]
+pub struct EmailTemplateCfg {
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    /// 检查模板文件是否修改的间隔，为 0 时不重新加载
+    #[serde(default = "default_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+}
+
+impl Default for EmailTemplateCfg {
+    fn default() -> Self {
+        Self {
+            dir: default_dir(),
+            reload_interval_secs: default_reload_interval_secs(),
+        }
+    }
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("./configs/email_templates")
+}
+
+fn default_reload_interval_secs() -> u64 {
+    10
+}
+
+/// 有类型的邮件参数，序列化后的字段名对应模板中的变量
+pub trait TemplatedEmail: Serialize {
+    /// 模板名，即模板目录下不带扩展名的文件名
+    const TEMPLATE: &'static str;
+}
+
+#[derive(Debug)]
+pub struct EmailTemplate {
+    pub name: String,
+    subject: String,
+    body: String,
+    /// 标题和正文中用到的变量
+    pub variables: BTreeSet<String>,
+    modified: SystemTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub body: String,
+}
+
+impl EmailTemplate {
+    fn parse(name: &str, content: String, modified: SystemTime) -> Result<Self> {
+        let start = content.find("<title>").context("no <title> in template")? + "<title>".len();
+        let end = start
+            + content[start..]
+                .find("</title>")
+                .context("<title> not closed")?;
+        let subject = content[start..end].trim().to_string();
+        ensure!(!subject.is_empty(), "empty <title> in template");
+
+        let mut variables = BTreeSet::new();
+        for part in [&subject, &content] {
+            for segment in split(part)? {
+                if let Segment::Var(var) = segment {
+                    variables.insert(var.to_string());
+                }
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            subject,
+            body: content,
+            variables,
+            modified,
+        })
+    }
+
+    /// 缺少任何变量时返回错误，多余的参数忽略
+    pub fn render(&self, params: &HashMap<String, String>) -> Result<RenderedEmail> {
+        let missing = self
+            .variables
+            .iter()
+            .filter(|v| !params.contains_key(*v))
+            .collect::<Vec<_>>();
+        ensure!(
+            missing.is_empty(),
+            "missing variables of template {}: {:?}",
+            self.name,
+            missing
+        );
+        Ok(RenderedEmail {
+            subject: fill(&self.subject, params, false)?,
+            body: fill(&self.body, params, true)?,
+        })
+    }
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    Var(&'a str),
+}
+
+fn split(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Text(&rest[..start]));
+        let Some(len) = rest[start + 2..].find("}}") else {
+            bail!("unclosed {{{{ in template");
+        };
+        let var = rest[start + 2..start + 2 + len].trim();
+        ensure!(
+            !var.is_empty() && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "invalid variable name: {:?}",
+            var
+        );
+        segments.push(Segment::Var(var));
+        rest = &rest[start + 2 + len + 2..];
+    }
+    segments.push(Segment::Text(rest));
+    Ok(segments)
+}
+
+fn fill(template: &str, params: &HashMap<String, String>, escape: bool) -> Result<String> {
+    let mut output = String::with_capacity(template.len());
+    for segment in split(template)? {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Var(var) if escape => escape_html(&params[var], &mut output),
+            Segment::Var(var) => output.push_str(&params[var]),
+        }
+    }
+    Ok(output)
+}
+
+fn escape_html(value: &str, output: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+}
+
+/// 把有类型的参数转为模板变量，字符串以外的值使用 json 格式
+pub fn to_params<T: Serialize>(email: &T) -> Result<HashMap<String, String>> {
+    let Value::Object(fields) = serde_json::to_value(email)? else {
+        bail!("email params must be a struct");
+    };
+    let params = fields
+        .into_iter()
+        .map(|(k, v)| {
+            let v = match v {
+                Value::String(s) => s,
+                Value::Null => String::new(),
+                v => v.to_string(),
+            };
+            (k, v)
+        })
+        .collect();
+    Ok(params)
+}
+
+static TEMPLATES: RwLock<BTreeMap<String, Arc<EmailTemplate>>> = RwLock::new(BTreeMap::new());
+
+pub fn get(name: &str) -> Option<Arc<EmailTemplate>> {
+    TEMPLATES.read().unwrap().get(name).cloned()
+}
+
+pub fn list() -> Vec<Arc<EmailTemplate>> {
+    TEMPLATES.read().unwrap().values().cloned().collect()
+}
+
+pub fn render<T: TemplatedEmail>(email: &T) -> Result<RenderedEmail> {
+    let template = get(T::TEMPLATE).with_context(|| format!("no template: {}", T::TEMPLATE))?;
+    template.render(&to_params(email)?)
+}
+
+/// 加载模板目录，并检查内置的邮件都有模板
+///
+/// 这个函数应该在服务初始化时被调用一次，以检测模板文件是否可以正常读取
+pub fn load(required: &[&str]) -> Result<()> {
+    let dir = &get_settings().email_template.dir;
+    let templates = read_dir(dir, &BTreeMap::new())?;
+    for name in required {
+        ensure!(
+            templates.contains_key(*name),
+            "template {} not found in {}",
+            name,
+            dir.display()
+        );
+    }
+    info!(count = templates.len(), "email templates loaded");
+    *TEMPLATES.write().unwrap() = templates;
+    Ok(())
+}
+
+/// 启动后台重新加载模板的任务
+pub fn spawn_reloader() {
+    let cfg = &get_settings().email_template;
+    if cfg.reload_interval_secs == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(cfg.reload_interval_secs)).await;
+            let current = TEMPLATES.read().unwrap().clone();
+            match read_dir(&cfg.dir, &current) {
+                Ok(templates) => *TEMPLATES.write().unwrap() = templates,
+                Err(err) => warn!(?err, "reload email templates failed"),
+            }
+        }
+    });
+}
+
+/// 读取目录下的模板，修改时间未变的模板沿用 `current` 中的。
+/// 单个模板解析失败时保留旧版本，避免改错文案后邮件无法发送
+fn read_dir(
+    dir: &Path,
+    current: &BTreeMap<String, Arc<EmailTemplate>>,
+) -> Result<BTreeMap<String, Arc<EmailTemplate>>> {
+    let mut templates = BTreeMap::new();
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("read email template dir: {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "html") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let modified = std::fs::metadata(&path)?.modified()?;
+        let old = current.get(name);
+        if let Some(old) = old.filter(|t| t.modified == modified) {
+            templates.insert(name.to_string(), old.clone());
+            continue;
+        }
+
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| EmailTemplate::parse(name, content, modified));
+        match (parsed, old) {
+            (Ok(template), _) => {
+                if old.is_some() {
+                    info!(name, "email template reloaded");
+                }
+                templates.insert(name.to_string(), Arc::new(template));
+            }
+            (Err(err), Some(old)) => {
+                warn!(?err, name, "invalid email template, keep the old one");
+                templates.insert(name.to_string(), old.clone());
+            }
+            (Err(err), None) => {
+                return Err(err.context(format!("invalid email template: {}", path.display())))
+            }
+        }
+    }
+    Ok(templates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn template(content: &str) -> Result<EmailTemplate> {
+        EmailTemplate::parse("test", content.to_string(), SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn t_render() {
+        let t =
+            template("<title>{{ name }} 的订单</title><p>{{name}}：{{count}} 个文件</p>").unwrap();
+        assert_eq!(
+            t.variables.iter().collect::<Vec<_>>(),
+            vec!["count", "name"]
+        );
+
+        let params = HashMap::from([
+            ("name".to_string(), "<b>".to_string()),
+            ("count".to_string(), "3".to_string()),
+        ]);
+        let email = t.render(&params).unwrap();
+        assert_eq!(email.subject, "<b> 的订单");
+        assert!(email.body.ends_with("<p>&lt;b&gt;：3 个文件</p>"));
+
+        let params = HashMap::from([("name".to_string(), "a".to_string())]);
+        assert!(t.render(&params).is_err());
+    }
+
+    #[test]
+    fn t_order_finished_escaped() {
+        let t = template(include_str!(
+            "../../configs/email_templates/order_finished.html"
+        ))
+        .unwrap();
+        let params = to_params(&crate::infrastructure::email::OrderEmail {
+            to: "a@b.c".to_string(),
+            user_name: "<a href=\"x\">".to_string(),
+            order_id: "1".to_string(),
+            ok_count: 1,
+            failed_count: 0,
+        })
+        .unwrap();
+        let email = t.render(&params).unwrap();
+        assert!(email.body.contains("&lt;a href=&quot;x&quot;&gt;，您好"));
+        assert!(!email.body.contains("<a href"));
+    }
+
+    #[test]
+    fn t_parse_err() {
+        assert!(template("<p>{{name}}</p>").is_err());
+        assert!(template("<title>a</title>{{name").is_err());
+        assert!(template("<title>a</title>{{a b}}").is_err());
+    }
+
+    #[test]
+    fn t_to_params() {
+        #[derive(Serialize)]
+        struct Email {
+            name: &'static str,
+            count: usize,
+            note: Option<String>,
+        }
+        let params = to_params(&Email {
+            name: "a",
+            count: 2,
+            note: None,
+        })
+        .unwrap();
+        assert_eq!(params["name"], "a");
+        assert_eq!(params["count"], "2");
+        assert_eq!(params["note"], "");
+    }
+}
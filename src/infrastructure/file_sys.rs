@@ -1,21 +1,51 @@
-use anyhow::Result;
+use actix_web::web::Bytes;
+use anyhow::{Context, Result};
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use futures_util::{Stream, StreamExt};
 use sha2::Digest;
 use std::{
-    io::{Read, Write},
+    io::{self, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
 };
 use tempfile::NamedTempFile;
-use tokio::{fs, io::AsyncWriteExt, task::spawn_blocking};
+use tokio::{
+    fs,
+    io::{AsyncWriteExt, DuplexStream},
+    sync::oneshot,
+    task::spawn_blocking,
+};
+use tokio_util::{compat::TokioAsyncReadCompatExt, io::ReaderStream};
 use tracing::{debug, info, warn};
 
-use crate::domain::file_system::{file::VirtualPath, service::PathManager};
+use crate::{
+    domain::file_system::{file::VirtualPath, service::PathManager},
+    log_if_err,
+};
+
+use super::slice_hash::{self, HashState};
 
-pub struct UploadFileSlice<'a> {
+pub struct UploadFileSlice<'a, S> {
     pub index: u32,
-    pub data: &'a [u8],
+    pub data: S,
     pub dir: &'a Path,
+    /// 分片的最大字节数
+    pub max_size: u64,
+    /// 按声明的分片方式，这个分片应有的字节数
+    pub expected_size: Option<u64>,
 }
 
+/// 写入片段的结果
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoredSlice {
+    Stored,
+    TooLarge,
+    /// 与声明的分片大小不一致
+    WrongSize,
+}
+
+/// 正在写入的片段的扩展名，写入完成后才会重命名为正式的片段文件
+const WRITING_SLICE_EXT: &str = "writing";
+
 fn slice_file_name(idx: u32) -> String {
     format!("part-{}", idx)
 }
@@ -24,16 +54,103 @@ fn slice_file_path(dir: &Path, idx: u32) -> PathBuf {
     dir.join(slice_file_name(idx))
 }
 
-pub async fn store_slice(slice: UploadFileSlice<'_>) -> Result<()> {
-    let path = slice_file_path(&slice.dir, slice.index);
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(&path)
-        .await?;
-    file.write_all(slice.data).await?;
+/// 以流的方式将片段写入磁盘，避免将整个片段缓存在内存中
+///
+/// 先写入临时文件，完成后再重命名，防止合并时读到不完整的片段。
+/// 超过大小限制或与声明的大小不一致时不保存，已写入的部分会被删除。
+/// 轮到这个片段计算 hash 时边写边计算，见 slice_hash
+pub async fn store_slice<S, B>(slice: UploadFileSlice<'_, S>) -> Result<StoredSlice>
+where
+    S: Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    let dir = slice.dir;
+    let path = slice_file_path(dir, slice.index);
+    let writing_path = path.with_extension(WRITING_SLICE_EXT);
+
+    if entry_exists(&path).await? {
+        slice_hash::invalidate(dir);
+    }
+    let origin = slice_hash::take(dir, slice.index);
+    let mut state = origin.as_ref().map(|s| HashState {
+        next: s.next,
+        size: s.size,
+        hasher: s.hasher.clone(),
+    });
 
-    Ok(())
+    let res = async {
+        let mut file = fs::File::create(&writing_path).await?;
+        let mut data = slice.data;
+        let mut size = 0;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            size += chunk.as_ref().len() as u64;
+            if size > slice.max_size {
+                return Ok(StoredSlice::TooLarge);
+            }
+            if slice.expected_size.is_some_and(|expected| size > expected) {
+                return Ok(StoredSlice::WrongSize);
+            }
+            if let Some(state) = &mut state {
+                state.hasher.update(chunk.as_ref());
+            }
+            file.write_all(chunk.as_ref()).await?;
+        }
+        if slice.expected_size.is_some_and(|expected| size != expected) {
+            return Ok(StoredSlice::WrongSize);
+        }
+        file.flush().await?;
+        if let Some(state) = &mut state {
+            state.size += size;
+            state.next += 1;
+        }
+        anyhow::Ok(StoredSlice::Stored)
+    }
+    .await;
+
+    let stored = match res {
+        Ok(StoredSlice::Stored) => fs::rename(&writing_path, &path)
+            .await
+            .map(|_| StoredSlice::Stored)
+            .map_err(Into::into),
+        res => res,
+    };
+    if !matches!(stored, Ok(StoredSlice::Stored)) {
+        let _ = fs::remove_file(&writing_path).await;
+        // 片段没有写入，计算过的内容仍然有效
+        if let Some(origin) = origin {
+            slice_hash::put_back(dir, origin);
+        }
+        return stored;
+    }
+
+    if let Some(state) = state {
+        let owned = dir.to_owned();
+        match spawn_blocking(move || hash_stored_slices(&owned, state)).await? {
+            Ok(state) => slice_hash::put_back(dir, state),
+            Err(err) => {
+                warn!(?err, "hash stored slices failed");
+                slice_hash::clear(dir);
+            }
+        }
+    }
+
+    Ok(StoredSlice::Stored)
+}
+
+/// 继续计算已经在磁盘上的后续片段
+fn hash_stored_slices(dir: &Path, mut state: HashState) -> Result<HashState> {
+    loop {
+        let path = slice_file_path(dir, state.next);
+        let mut file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => break,
+            Err(err) => return Err(err.into()),
+        };
+        state.size += std::io::copy(&mut file, &mut state.hasher)?;
+        state.next += 1;
+    }
+    Ok(state)
 }
 
 pub struct MergedFile {
@@ -43,14 +160,30 @@ pub struct MergedFile {
 }
 
 impl MergedFile {
-    pub async fn persist(self, path: &Path) -> Result<()> {
+    /// 移动到归档路径。跨文件系统时先复制到 staging_dir 下的临时文件再重命名，
+    /// staging_dir 应当与 path 在同一个文件系统，并由 [`clean_merging_files`] 清理
+    pub async fn persist(self, path: &Path, staging_dir: &Path) -> Result<()> {
         let path = path.to_owned();
+        let staging_dir = staging_dir.to_owned();
         spawn_blocking(move || -> Result<()> {
-            let tmp_file = self.tmp_file.path();
-
-            // NamedTempFile 和 path 不在同一个文件系统，不能直接 rename
-            // NamedTempFile 会在 drop 时自动删除
-            std::fs::copy(tmp_file, path)?;
+            // 合并的文件与归档目录在同一个文件系统，直接重命名
+            let err = match self.tmp_file.persist(&path) {
+                Ok(_) => return Ok(()),
+                Err(err) => err,
+            };
+            if err.error.raw_os_error() != Some(CROSS_DEVICE_ERR) {
+                return Err(err.error.into());
+            }
+
+            // 小文件写在系统的临时目录，可能不在同一个文件系统，只能复制
+            // 先复制到临时文件再重命名，进程中途退出时不会留下不完整的归档文件
+            // NamedTempFile 会在 drop 时自动删除，进程退出时留下的由启动时的清理删除
+            let mut copied = tempfile::Builder::new()
+                .prefix(MERGING_FILE_PREFIX)
+                .tempfile_in(&staging_dir)?;
+            std::fs::copy(err.file.path(), copied.path())?;
+            copied.as_file_mut().sync_all()?;
+            copied.persist(&path)?;
 
             Ok(())
         })
@@ -59,45 +192,261 @@ impl MergedFile {
     }
 }
 
-pub async fn merge_slices(slice_dir: &Path) -> Result<Option<MergedFile>> {
+/// 合并中的临时文件的前缀，便于与归档目录区分
+const MERGING_FILE_PREFIX: &str = ".merging-";
+
+/// 按序号将片段合并到 dst_dir 下的临时文件中。dst_dir 应当与最终的归档路径在同一个文件系统，
+/// 之后 persist 时只需要重命名。
+/// 上传时已经计算过 hash 的片段直接复制，在 linux 上由 copy_file_range 完成，不经过用户态
+pub async fn merge_slices(slice_dir: &Path, dst_dir: &Path) -> Result<Option<MergedFile>> {
     debug!("merging slices");
-    let mut hasher = sha2::Sha256::new();
-    let mut size = 0;
+    let state = slice_hash::finish(slice_dir);
     let slices = load_slices_sorted(&slice_dir).await?;
     if slices.is_empty() {
         return Ok(None);
     }
+    let dst_dir = dst_dir.to_owned();
 
     spawn_blocking(move || {
-        let mut dst_file = NamedTempFile::new()?;
-        for slice in slices {
-            let mut data = vec![];
+        let (hashed, mut hasher) = match state {
+            Some(state) if is_hashed_prefix(&slices, &state)? => {
+                (state.next as usize, state.hasher)
+            }
+            _ => (0, sha2::Sha256::new()),
+        };
+        debug!(hashed, total = slices.len(), "slices hashed on upload");
+
+        let mut dst_file = tempfile::Builder::new()
+            .prefix(MERGING_FILE_PREFIX)
+            .tempfile_in(dst_dir)?;
+        let mut size = 0;
+        let mut buf = vec![0; MERGE_BUF_SIZE];
+        for (idx, slice) in slices.iter().enumerate() {
             let mut slice = std::fs::File::open(slice)?;
-            slice.read_to_end(&mut data)?;
-
-            size += data.len();
-            hasher.update(&data);
-
-            dst_file.write_all(&data)?;
+            if idx < hashed {
+                size += std::io::copy(&mut slice, dst_file.as_file_mut())?;
+                continue;
+            }
+            loop {
+                let len = slice.read(&mut buf)?;
+                if len == 0 {
+                    break;
+                }
+                hasher.update(&buf[..len]);
+                dst_file.write_all(&buf[..len])?;
+                size += len as u64;
+            }
         }
         let hash = hex::encode(hasher.finalize());
         Ok(Some(MergedFile {
             hash,
-            size: size as u64,
+            size,
             tmp_file: dst_file,
         }))
     })
     .await?
 }
 
+/// 需要计算 hash 的片段每次读取的字节数
+const MERGE_BUF_SIZE: usize = 8 * 1024 * 1024;
+
+/// 计算过的正是排在最前面的片段，且文件大小没有变化
+fn is_hashed_prefix(slices: &[PathBuf], state: &HashState) -> Result<bool> {
+    let hashed = state.next as usize;
+    if slices.len() < hashed {
+        return Ok(false);
+    }
+    let mut size = 0;
+    for (idx, slice) in slices[..hashed].iter().enumerate() {
+        if slice_index(slice) != Some(idx as u32) {
+            return Ok(false);
+        }
+        size += std::fs::metadata(slice)?.len();
+    }
+    Ok(size == state.size)
+}
+
+fn slice_index(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix("part-")?.parse().ok()
+}
+
+/// 以流的方式将小文件写入临时文件，同时计算 hash。文件超过 `max_size` 时返回 None
+pub async fn store_small_file<S, B>(data: S, max_size: u64) -> Result<Option<MergedFile>>
+where
+    S: Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    let mut hasher = sha2::Sha256::new();
+    let mut size = 0;
+    let tmp_file = NamedTempFile::new()?;
+    let mut file = fs::File::from_std(tmp_file.reopen()?);
+
+    let mut data = data;
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        let chunk = chunk.as_ref();
+        size += chunk.len() as u64;
+        if size > max_size {
+            return Ok(None);
+        }
+        hasher.update(chunk);
+        file.write_all(chunk).await?;
+    }
+    file.flush().await?;
+
+    let hash = hex::encode(hasher.finalize());
+    Ok(Some(MergedFile {
+        hash,
+        size,
+        tmp_file,
+    }))
+}
+
+/// 重新计算文件的 sha256，文件不存在时返回 None
+pub async fn file_hash(path: &Path) -> Result<Option<String>> {
+    let path = path.to_owned();
+    spawn_blocking(move || {
+        let mut file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut hasher = sha2::Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(Some(hex::encode(hasher.finalize())))
+    })
+    .await?
+}
+
+/// 读取文件开头最多 len 个字节
+pub async fn read_head(path: &Path, len: usize) -> Result<Vec<u8>> {
+    let path = path.to_owned();
+    spawn_blocking(move || {
+        let file = std::fs::File::open(&path)?;
+        let mut head = Vec::with_capacity(len);
+        file.take(len as u64).read_to_end(&mut head)?;
+        Ok(head)
+    })
+    .await?
+}
+
+/// 读取文件末尾最多 len 个字节，同时返回文件大小。文件不存在时返回 None
+pub async fn read_tail(path: &Path, len: u64) -> Result<Option<(Vec<u8>, u64)>> {
+    let path = path.to_owned();
+    spawn_blocking(move || {
+        let mut file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let size = file.metadata()?.len();
+        file.seek(SeekFrom::Start(size.saturating_sub(len)))?;
+        let mut tail = Vec::with_capacity(len.min(size) as usize);
+        file.take(len).read_to_end(&mut tail)?;
+        Ok(Some((tail, size)))
+    })
+    .await?
+}
+
+/// 以流的方式写入文件，先写入临时文件，完成后替换已有的文件。
+/// 超过 `max_size` 时返回 false，已写入的部分会被删除
+pub async fn store_file<S, B>(data: S, path: &Path, max_size: u64) -> Result<bool>
+where
+    S: Stream<Item = Result<B>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).await?;
+    }
+    let writing_path = path.with_extension(WRITING_SLICE_EXT);
+    let res = async {
+        let mut file = fs::File::create(&writing_path).await?;
+        let mut data = data;
+        let mut size = 0;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            size += chunk.as_ref().len() as u64;
+            if size > max_size {
+                return Ok(false);
+            }
+            file.write_all(chunk.as_ref()).await?;
+        }
+        file.flush().await?;
+        fs::rename(&writing_path, path).await?;
+        anyhow::Ok(true)
+    }
+    .await;
+
+    if !matches!(res, Ok(true)) {
+        let _ = fs::remove_file(&writing_path).await;
+    }
+    res
+}
+
+/// 边打包边输出 zip 压缩包，不会在磁盘或内存中生成完整的压缩包
+///
+/// entries 为 (压缩包内的路径, 磁盘路径)。视频文件几乎无法再压缩，所以只存储不压缩。
+/// 文件超过 4 GiB 或文件过多时 async_zip 会自动写入 zip64 字段。
+/// 打包出错时流以错误结束，让连接中断，避免客户端收到不完整的压缩包
+pub fn zip_stream(entries: Vec<(String, PathBuf)>) -> impl Stream<Item = io::Result<Bytes>> {
+    let (writer, reader) = tokio::io::duplex(1024 * 64);
+    let (done_tx, done_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let res = write_zip(entries, writer).await;
+        log_if_err!(&res, "write zip stream");
+        let _ = done_tx.send(res.is_ok());
+    });
+    // writer 在 write_zip 结束时被释放，读完后再检查是否成功
+    let tail = futures_util::stream::once(async move {
+        match done_rx.await {
+            Ok(true) => None,
+            _ => Some(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "write zip stream failed",
+            ))),
+        }
+    })
+    .filter_map(std::future::ready);
+    ReaderStream::new(reader).chain(tail)
+}
+
+async fn write_zip(entries: Vec<(String, PathBuf)>, writer: DuplexStream) -> Result<()> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    for (name, path) in entries {
+        // 用户文件是指向归档文件的软链接，打开时会自动解析
+        let file = fs::File::open(&path).await?;
+        let entry = ZipEntryBuilder::new(name.into(), Compression::Stored);
+        let mut entry_writer = zip.write_entry_stream(entry).await?;
+        futures_util::io::copy(&mut file.compat(), &mut entry_writer).await?;
+        entry_writer.close().await?;
+    }
+    zip.close().await?;
+
+    Ok(())
+}
+
+/// 已写入完成的片段的总字节数，不包括正在写入的片段
+pub async fn slices_size(dir: &Path) -> Result<u64> {
+    let mut size = 0;
+    for slice in load_slices_sorted(dir).await? {
+        size += fs::metadata(slice).await?.len();
+    }
+    Ok(size)
+}
+
 async fn load_slices_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
     debug!(?dir, "reading slices");
     let mut dir = fs::read_dir(&dir).await?;
     let mut paths = vec![];
     while let Some(entry) = dir.next_entry().await? {
-        if !entry.metadata().await?.is_dir() {
-            paths.push(entry.path())
+        let path = entry.path();
+        if entry.metadata().await?.is_dir()
+            || path.extension().is_some_and(|ext| ext == WRITING_SLICE_EXT)
+        {
+            continue;
         }
+        paths.push(path)
     }
     paths.sort_by(|a, b| {
         let index_a: u32 = a
@@ -148,12 +497,15 @@ impl IgnoreNotExist for std::io::Result<()> {
 }
 
 macro_rules! nx_is_ok {
-    ($fs_op:expr) => {{
+    ($fs_op:expr) => {
+        nx_is_ok!($fs_op, ())
+    };
+    ($fs_op:expr, $nx:expr) => {{
         match $fs_op {
             Ok(res) => res,
             Err(err) => match err.kind() {
                 std::io::ErrorKind::NotFound => {
-                    return Ok(());
+                    return Ok($nx);
                 }
                 _ => return { Err(err.into()) },
             },
@@ -162,6 +514,12 @@ macro_rules! nx_is_ok {
 }
 
 /// delete a file or directory if exists
+/// 删除上传任务的片段目录
+pub async fn clear_slices(slice_dir: &Path) -> Result<()> {
+    slice_hash::clear(slice_dir);
+    delete(slice_dir).await
+}
+
 pub async fn delete(path: &Path) -> Result<()> {
     let path = path.to_owned();
     spawn_blocking(move || delete_inner(&path)).await??;
@@ -222,7 +580,119 @@ pub(crate) async fn create_dir_all(dir: &Path) -> Result<()> {
 pub(crate) async fn virtual_move(from: &VirtualPath, to: &VirtualPath) -> Result<()> {
     let from = PathManager::virtual_to_disk(from);
     let to = PathManager::virtual_to_disk(to);
-    fs::rename(from, to).await?;
+    rename_or_copy(&from, &to).await
+}
+
+/// 跨文件系统的 rename 返回的错误码（EXDEV / ERROR_NOT_SAME_DEVICE）
+#[cfg(target_family = "unix")]
+const CROSS_DEVICE_ERR: i32 = 18;
+#[cfg(target_family = "windows")]
+const CROSS_DEVICE_ERR: i32 = 17;
+
+/// 复制过程中每复制这么多字节输出一次进度
+const MOVE_PROGRESS_STEP: u64 = 1024 * 1024 * 1024;
+
+/// 优先使用 rename，源和目标不在同一个文件系统时改为复制、校验后再删除源文件。
+///
+/// 复制先写到目标旁边的临时路径，校验通过后再 rename 到目标位置，
+/// 中途失败只会留下源文件，不会出现只复制了一部分的目标
+async fn rename_or_copy(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to).await {
+        Ok(()) => return Ok(()),
+        Err(err) if err.raw_os_error() == Some(CROSS_DEVICE_ERR) => {
+            info!(?from, ?to, "crossing devices, fallback to copy");
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    let from = from.to_owned();
+    let to = to.to_owned();
+    spawn_blocking(move || move_across_devices(&from, &to)).await?
+}
+
+fn move_across_devices(from: &Path, to: &Path) -> Result<()> {
+    let Some(name) = to.file_name() else {
+        anyhow::bail!("invalid move target: {:?}", to);
+    };
+    let mut tmp_name = name.to_owned();
+    tmp_name.push(".moving");
+    let tmp = to.with_file_name(tmp_name);
+
+    delete_inner(&tmp)?;
+    let mut progress = MoveProgress::default();
+    let copied = copy_verified(from, &tmp, &mut progress)
+        .and_then(|()| std::fs::rename(&tmp, to).map_err(Into::into));
+    if let Err(err) = copied {
+        log_if_err!(delete_inner(&tmp));
+        return Err(err);
+    }
+
+    delete_inner(from)?;
+    info!(
+        ?from,
+        ?to,
+        entries = progress.entries,
+        bytes = progress.bytes,
+        "moved across devices"
+    );
+    Ok(())
+}
+
+#[derive(Default)]
+struct MoveProgress {
+    entries: u64,
+    bytes: u64,
+    reported: u64,
+}
+
+impl MoveProgress {
+    fn add(&mut self, bytes: u64) {
+        self.entries += 1;
+        self.bytes += bytes;
+        if self.bytes - self.reported >= MOVE_PROGRESS_STEP {
+            self.reported = self.bytes;
+            info!(
+                entries = self.entries,
+                bytes = self.bytes,
+                "moving across devices"
+            );
+        }
+    }
+}
+
+/// 与 copy_dir_all 相同，但每一项复制后都与源文件比较：软链接比较指向，普通文件比较大小
+fn copy_verified(src: &Path, dst: &Path, progress: &mut MoveProgress) -> Result<()> {
+    use std::fs;
+
+    let meta = fs::symlink_metadata(src)?;
+    if meta.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_verified(&entry.path(), &dst.join(entry.file_name()), progress)?;
+        }
+        progress.add(0);
+    } else if meta.is_symlink() {
+        let target = fs::read_link(src)?;
+        create_file_link(&target, dst)?;
+        anyhow::ensure!(
+            fs::read_link(dst)? == target,
+            "link target mismatch after copy: {:?}",
+            dst
+        );
+        progress.add(0);
+    } else {
+        let copied = fs::copy(src, dst)?;
+        let written = fs::metadata(dst)?.len();
+        anyhow::ensure!(
+            copied == meta.len() && written == meta.len(),
+            "size mismatch after copy: {:?}, expect {}, got {}",
+            dst,
+            meta.len(),
+            written
+        );
+        progress.add(copied);
+    }
     Ok(())
 }
 
@@ -257,6 +727,163 @@ fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
     Ok(())
 }
 
+/// 磁盘的标识，用于判断两个路径是否在同一个文件系统上
+#[cfg(target_family = "unix")]
+pub type DeviceId = u64;
+#[cfg(target_family = "windows")]
+pub type DeviceId = std::ffi::OsString;
+
+/// 路径所在磁盘的标识和可用字节数，路径不存在时使用最近的已存在的上级目录
+pub(crate) fn disk_space(path: &Path) -> Result<(DeviceId, u64)> {
+    let mut path = path;
+    while !path.exists() {
+        path = path
+            .parent()
+            .with_context(|| format!("no existing ancestor: {:?}", path))?;
+    }
+    let available = fs2::available_space(path)?;
+    Ok((device_id(path)?, available))
+}
+
+#[cfg(target_family = "unix")]
+fn device_id(path: &Path) -> Result<DeviceId> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(path)?.dev())
+}
+
+/// windows 上以盘符区分
+#[cfg(target_family = "windows")]
+fn device_id(path: &Path) -> Result<DeviceId> {
+    let path = std::fs::canonicalize(path)?;
+    let prefix = path.components().next().map(|c| c.as_os_str().to_owned());
+    Ok(prefix.unwrap_or_default())
+}
+
+/// 路径下普通文件的总字节数，软链接不计入
+pub(crate) async fn disk_usage(path: &Path) -> Result<u64> {
+    let path = path.to_owned();
+    spawn_blocking(move || disk_usage_inner(&path)).await?
+}
+
+fn disk_usage_inner(path: &Path) -> Result<u64> {
+    use std::fs;
+
+    let meta = nx_is_ok!(fs::symlink_metadata(path), 0);
+    if meta.is_dir() {
+        let mut total = 0;
+        for entry in fs::read_dir(path)? {
+            total += disk_usage_inner(&entry?.path())?;
+        }
+        Ok(total)
+    } else if meta.is_file() {
+        Ok(meta.len())
+    } else {
+        Ok(0)
+    }
+}
+
+pub(crate) enum DiskEntryKind {
+    Dir,
+    File,
+    /// 软链接及其指向
+    Link(PathBuf),
+}
+
+pub(crate) struct DiskEntry {
+    pub path: PathBuf,
+    pub kind: DiskEntryKind,
+}
+
+/// 递归列出目录下的所有条目，不跟随软链接，不包括目录本身。目录不存在时返回空
+pub(crate) async fn walk_dir(dir: &Path) -> Result<Vec<DiskEntry>> {
+    let dir = dir.to_owned();
+    spawn_blocking(move || {
+        let mut entries = vec![];
+        walk_dir_inner(&dir, &mut entries)?;
+        Ok(entries)
+    })
+    .await?
+}
+
+fn walk_dir_inner(dir: &Path, entries: &mut Vec<DiskEntry>) -> Result<()> {
+    use std::fs;
+
+    let read_dir = nx_is_ok!(fs::read_dir(dir));
+    for entry in read_dir {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            let target = fs::read_link(&path)?;
+            entries.push(DiskEntry {
+                path,
+                kind: DiskEntryKind::Link(target),
+            });
+        } else if file_type.is_dir() {
+            walk_dir_inner(&path, entries)?;
+            entries.push(DiskEntry {
+                path,
+                kind: DiskEntryKind::Dir,
+            });
+        } else {
+            entries.push(DiskEntry {
+                path,
+                kind: DiskEntryKind::File,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// 路径本身是否存在，不跟随软链接
+pub(crate) async fn entry_exists(path: &Path) -> Result<bool> {
+    match fs::symlink_metadata(path).await {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// 删除 dir 下修改时间早于 before 的合并临时文件，它们是进程在合并中退出时留下的。
+/// 正在合并的文件一直在写入，修改时间不会早于 before。返回删除的文件数和字节数
+pub(crate) async fn clean_merging_files(
+    dir: &Path,
+    before: std::time::SystemTime,
+) -> Result<(usize, u64)> {
+    let mut count = 0;
+    let mut size = 0;
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if !name.to_string_lossy().starts_with(MERGING_FILE_PREFIX) {
+            continue;
+        }
+        let meta = entry.metadata().await?;
+        if meta.is_file() && meta.modified()? < before {
+            fs::remove_file(entry.path()).await?;
+            count += 1;
+            size += meta.len();
+        }
+    }
+    Ok((count, size))
+}
+
+/// 修改时间早于 before 的子目录名
+pub(crate) async fn child_dirs_modified_before(
+    dir: &Path,
+    before: std::time::SystemTime,
+) -> Result<Vec<String>> {
+    let mut names = vec![];
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let meta = entry.metadata().await?;
+        if meta.is_dir() && meta.modified()? < before {
+            names.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Ok(names)
+}
+
 pub(crate) async fn child_file_names(dir: &Path) -> Result<Vec<String>> {
     if !fs::try_exists(dir).await? {
         return Ok(Default::default());
@@ -308,6 +935,5 @@ pub(crate) async fn move_to(from: &Path, to: &Path) -> Result<()> {
     if let Some(parent) = to.parent() {
         create_dir_all(parent).await?;
     }
-    fs::rename(from, to).await?;
-    Ok(())
+    rename_or_copy(from, to).await
 }
@@ -1,13 +1,52 @@
 use derive_more::Deref;
 
 pub mod av1_factory;
+pub mod captcha;
 pub mod email;
+pub mod email_template;
 pub mod file_sys;
+pub mod oauth;
+pub(crate) mod public_host;
+pub mod repo_activity;
+pub mod repo_audit;
+pub mod repo_callback;
+pub mod repo_comment;
+pub mod repo_credit;
+pub mod repo_dir_cache;
+pub mod repo_dir_usage;
+pub mod repo_edit_lock;
+pub mod repo_email_outbox;
 pub mod repo_employee;
+pub mod repo_export;
+pub mod repo_feature_flag;
+pub mod repo_file_access;
+pub mod repo_file_change;
+pub mod repo_file_lock;
+pub mod repo_file_version;
+pub mod repo_folder_share;
+pub mod repo_gallery;
+pub mod repo_idempotency;
+pub mod repo_import_task;
+pub mod repo_integrity;
+pub mod repo_notification;
 pub mod repo_order;
+pub mod repo_organization;
+pub mod repo_path_history;
+pub mod repo_outbox;
+pub mod repo_provider_identity;
+pub mod repo_quarantine;
+pub mod repo_referral;
+pub mod repo_star;
+pub mod repo_subtitle;
+pub mod repo_tag;
+pub mod repo_transcode_rule;
 pub mod repo_upload_task;
 pub mod repo_user;
 pub mod repo_user_file;
+pub mod repo_user_sequence;
+pub mod repo_webhook;
+pub mod repo_worker;
+pub(crate) mod slice_hash;
 pub mod sms_code;
 
 #[must_use]
@@ -0,0 +1,264 @@
+//! 第三方登录的 OAuth 2.0 客户端
+//!
+//! 支持 GitHub、Google、微信和标准的 OIDC 服务，企业 SSO 一般按 OIDC 配置。
+//! 只返回第三方账号的唯一标识和已验证的邮箱，账号的关联和注册由应用层处理
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::{header, Client, Url};
+use serde::Deserialize;
+
+use crate::settings::Secret;
+
+const TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Github,
+    Google,
+    Wechat,
+    Oidc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OAuthProviderCfg {
+    pub kind: ProviderKind,
+    /// 微信为 appid
+    pub client_id: String,
+    /// 微信为 appsecret
+    pub client_secret: Secret,
+    /// 授权后跳转的地址，一般为前端页面，由前端把 code 和 state 转发到回调接口
+    pub redirect_uri: String,
+    /// 为空时使用各类型的默认值
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// OIDC 必须配置以下地址，其他类型可以用来覆盖默认地址
+    #[serde(default)]
+    pub authorize_url: Option<String>,
+    #[serde(default)]
+    pub token_url: Option<String>,
+    #[serde(default)]
+    pub userinfo_url: Option<String>,
+}
+
+/// 第三方账号
+#[derive(Debug)]
+pub struct ProviderIdentity {
+    /// 第三方账号在该服务中的唯一标识，微信优先使用 unionid
+    pub subject: String,
+    /// 已验证的邮箱，未验证的邮箱不返回
+    pub email: Option<String>,
+}
+
+struct Endpoints {
+    authorize: &'static str,
+    token: &'static str,
+    userinfo: &'static str,
+    scopes: &'static str,
+}
+
+impl ProviderKind {
+    fn endpoints(self) -> Endpoints {
+        match self {
+            ProviderKind::Github => Endpoints {
+                authorize: "https://github.com/login/oauth/authorize",
+                token: "https://github.com/login/oauth/access_token",
+                userinfo: "https://api.github.com/user",
+                scopes: "read:user user:email",
+            },
+            ProviderKind::Google => Endpoints {
+                authorize: "https://accounts.google.com/o/oauth2/v2/auth",
+                token: "https://oauth2.googleapis.com/token",
+                userinfo: "https://openidconnect.googleapis.com/v1/userinfo",
+                scopes: "openid email profile",
+            },
+            ProviderKind::Wechat => Endpoints {
+                authorize: "https://open.weixin.qq.com/connect/qrconnect",
+                token: "https://api.weixin.qq.com/sns/oauth2/access_token",
+                userinfo: "https://api.weixin.qq.com/sns/userinfo",
+                scopes: "snsapi_login",
+            },
+            ProviderKind::Oidc => Endpoints {
+                authorize: "",
+                token: "",
+                userinfo: "",
+                scopes: "openid email profile",
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResp {
+    access_token: Option<String>,
+    /// 微信在获取 token 时同时返回用户标识
+    openid: Option<String>,
+    unionid: Option<String>,
+    /// 微信的错误码，github 等服务的错误也以 200 状态码返回
+    errcode: Option<i64>,
+    #[serde(alias = "errmsg")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct GithubEmail {
+    email: String,
+    primary: bool,
+    verified: bool,
+}
+
+#[derive(Deserialize)]
+struct OidcUser {
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+impl OAuthProviderCfg {
+    fn url<'a>(&'a self, custom: &'a Option<String>, default: &'a str) -> Result<&'a str> {
+        match custom.as_deref() {
+            Some(url) => Ok(url),
+            None if !default.is_empty() => Ok(default),
+            None => bail!("oauth endpoint not configured for {:?}", self.kind),
+        }
+    }
+
+    fn scopes(&self) -> String {
+        if self.scopes.is_empty() {
+            self.kind.endpoints().scopes.to_string()
+        } else {
+            self.scopes.join(" ")
+        }
+    }
+
+    /// 跳转到第三方授权页面的地址
+    pub fn authorize_url(&self, state: &str) -> Result<String> {
+        let endpoints = self.kind.endpoints();
+        let base = self.url(&self.authorize_url, endpoints.authorize)?;
+        let scopes = self.scopes();
+        let client_key = match self.kind {
+            ProviderKind::Wechat => "appid",
+            _ => "client_id",
+        };
+        let mut url = Url::parse_with_params(
+            base,
+            [
+                (client_key, self.client_id.as_str()),
+                ("redirect_uri", &self.redirect_uri),
+                ("response_type", "code"),
+                ("scope", &scopes),
+                ("state", state),
+            ],
+        )?;
+        if self.kind == ProviderKind::Wechat {
+            url.set_fragment(Some("wechat_redirect"));
+        }
+        Ok(url.into())
+    }
+
+    /// 用授权码换取 token，再查询第三方账号
+    pub async fn fetch_identity(&self, code: &str) -> Result<ProviderIdentity> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(TIMEOUT_SECS))
+            .user_agent("av1-cloud")
+            .build()?;
+        let token = self.exchange_code(&client, code).await?;
+        let access_token = token.access_token.context("no access token")?;
+
+        let endpoints = self.kind.endpoints();
+        let userinfo_url = self.url(&self.userinfo_url, endpoints.userinfo)?;
+        match self.kind {
+            ProviderKind::Github => {
+                let user: GithubUser = client
+                    .get(userinfo_url)
+                    .bearer_auth(&access_token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let emails: Vec<GithubEmail> = client
+                    .get(format!("{}/emails", userinfo_url))
+                    .bearer_auth(&access_token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let email = emails
+                    .into_iter()
+                    .find(|e| e.primary && e.verified)
+                    .map(|e| e.email);
+                Ok(ProviderIdentity {
+                    subject: user.id.to_string(),
+                    email,
+                })
+            }
+            ProviderKind::Google | ProviderKind::Oidc => {
+                let user: OidcUser = client
+                    .get(userinfo_url)
+                    .bearer_auth(&access_token)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(ProviderIdentity {
+                    subject: user.sub,
+                    email: user.email.filter(|_| user.email_verified),
+                })
+            }
+            // 获取 token 时已经返回了 openid 和 unionid，微信不提供邮箱
+            ProviderKind::Wechat => {
+                let subject = token.unionid.or(token.openid).context("no openid")?;
+                Ok(ProviderIdentity {
+                    subject,
+                    email: None,
+                })
+            }
+        }
+    }
+
+    async fn exchange_code(&self, client: &Client, code: &str) -> Result<TokenResp> {
+        let endpoints = self.kind.endpoints();
+        let token_url = self.url(&self.token_url, endpoints.token)?;
+        let req = match self.kind {
+            ProviderKind::Wechat => client.get(token_url).query(&[
+                ("appid", self.client_id.as_str()),
+                ("secret", self.client_secret.expose()),
+                ("code", code),
+                ("grant_type", "authorization_code"),
+            ]),
+            _ => client.post(token_url).form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.expose()),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("grant_type", "authorization_code"),
+            ]),
+        };
+        let token: TokenResp = req
+            .header(header::ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if token.errcode.is_some_and(|c| c != 0) || token.access_token.is_none() {
+            bail!(
+                "exchange oauth code failed: {:?} {:?}",
+                token.errcode,
+                token.error
+            );
+        }
+        Ok(token)
+    }
+}
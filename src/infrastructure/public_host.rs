@@ -0,0 +1,104 @@
+//! 内网地址检查
+//!
+//! 服务器按用户提供的地址发起请求时（导入远程文件、投递 webhook），拒绝内网、回环和链路本地地址，
+//! 避免用户借此访问内部服务或云服务器的元数据接口
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    Url,
+};
+
+/// 拒绝解析到内网地址的域名。每次建立连接（包括重定向后的连接）都经过这里，
+/// 检查的就是实际连接的地址，域名重新绑定到内网地址也会被拒绝。
+/// IP 字面量不经过解析，需要另外用 [`is_private_literal`] 检查
+pub(crate) struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| is_private_ip(addr.ip())) {
+                let err = io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{host} resolves to a private address"),
+                );
+                return Err(err.into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 域名解析出的所有地址都不是内网地址
+pub(crate) async fn is_public_host(url: &Url) -> bool {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return false;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let Ok(addrs) = tokio::net::lookup_host((host, port)).await else {
+        return false;
+    };
+    let addrs: Vec<SocketAddr> = addrs.collect();
+    !addrs.is_empty() && addrs.iter().all(|addr| !is_private_ip(addr.ip()))
+}
+
+/// 不解析域名，只检查 localhost 和 IP 字面量
+pub(crate) fn is_private_literal(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost") || host.parse().is_ok_and(is_private_ip)
+}
+
+pub(crate) fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // 100.64.0.0/10
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => {
+            let seg = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // fc00::/7 和 fe80::/10
+                || seg & 0xfe00 == 0xfc00
+                || seg & 0xffc0 == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|v4| is_private_ip(v4.into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn t_private_literal() {
+        for host in [
+            "localhost",
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "[::1]",
+            "[fe80::1]",
+            "[::ffff:127.0.0.1]",
+        ] {
+            assert!(is_private_literal(host), "{host}");
+        }
+        for host in ["example.com", "8.8.8.8", "[2001:4860::8888]"] {
+            assert!(!is_private_literal(host), "{host}");
+        }
+    }
+}
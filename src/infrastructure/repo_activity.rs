@@ -0,0 +1,34 @@
+use anyhow::Result;
+use diesel::Insertable;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{activity::Activity, transcode_order::TranscodeOrderId, user::user::UserId},
+    schema::activities,
+};
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = activities)]
+pub struct ActivityPo<'a> {
+    pub user_id: UserId,
+    pub kind: i16,
+    pub message: &'a str,
+    pub file_path: Option<&'a str>,
+    pub order_id: Option<TranscodeOrderId>,
+}
+
+pub async fn save(activity: &Activity, conn: &mut PgConn) -> Result<()> {
+    let po = ActivityPo {
+        user_id: *activity.user_id(),
+        kind: *activity.kind() as i16,
+        message: activity.message(),
+        file_path: activity.file_path().as_deref(),
+        order_id: *activity.order_id(),
+    };
+    diesel::insert_into(activities::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use anyhow::Result;
+use diesel::Insertable;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{domain::user::employee::EmployeeId, schema::audit_logs};
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = audit_logs)]
+pub struct NewAuditLogPo<'a> {
+    pub employee_id: EmployeeId,
+    pub action: &'a str,
+    pub detail: String,
+}
+
+pub async fn save(log: &NewAuditLogPo<'_>, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(audit_logs::table)
+        .values(log)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
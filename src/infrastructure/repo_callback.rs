@@ -0,0 +1,170 @@
+use anyhow::Result;
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{schema::factory_callbacks, LocalDataTime};
+
+use super::EffectedRow;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i16)]
+pub enum CallbackKind {
+    TranscodeDone = 0,
+    FileParsed = 1,
+}
+
+impl CallbackKind {
+    pub fn from_i16(kind: i16) -> Option<Self> {
+        match kind {
+            0 => Some(Self::TranscodeDone),
+            1 => Some(Self::FileParsed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(i16)]
+pub enum CallbackStatus {
+    Pending = 0,
+    Processed = 1,
+    Dead = 2,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = factory_callbacks)]
+pub struct NewCallbackPo {
+    pub kind: i16,
+    pub task_id: i64,
+    pub payload: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = factory_callbacks)]
+pub struct CallbackPo {
+    pub id: i64,
+    pub kind: i16,
+    pub task_id: i64,
+    pub payload: String,
+    pub attempts: i32,
+    pub next_attempt_at: LocalDataTime,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = factory_callbacks)]
+pub struct StuckCallbackPo {
+    pub id: i64,
+    pub kind: i16,
+    pub task_id: i64,
+    pub status: i16,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub create_at: LocalDataTime,
+}
+
+pub async fn save(callback: &NewCallbackPo, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(factory_callbacks::table)
+        .values(callback)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 查询已到处理时间的回调，先收到的先处理
+pub async fn due_callbacks(limit: i64, conn: &mut PgConn) -> Result<Vec<CallbackPo>> {
+    let callbacks = factory_callbacks::table
+        .filter(factory_callbacks::status.eq(CallbackStatus::Pending as i16))
+        .filter(factory_callbacks::next_attempt_at.le(diesel::dsl::now))
+        .select(CallbackPo::as_select())
+        .order_by(factory_callbacks::id.asc())
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(callbacks)
+}
+
+/// 将回调的下次处理时间推迟到 lease_until，防止多个实例重复处理。
+/// 返回 false 表示已被其他实例认领
+pub async fn claim(
+    callback: &CallbackPo,
+    lease_until: LocalDataTime,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let effected = diesel::update(
+        factory_callbacks::table
+            .filter(factory_callbacks::id.eq(callback.id))
+            .filter(factory_callbacks::status.eq(CallbackStatus::Pending as i16))
+            .filter(factory_callbacks::next_attempt_at.eq(callback.next_attempt_at)),
+    )
+    .set(factory_callbacks::next_attempt_at.eq(lease_until))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
+
+pub async fn update(
+    id: i64,
+    status: CallbackStatus,
+    attempts: i32,
+    next_attempt_at: LocalDataTime,
+    last_error: Option<&str>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(factory_callbacks::table.filter(factory_callbacks::id.eq(id)))
+        .set((
+            factory_callbacks::status.eq(status as i16),
+            factory_callbacks::attempts.eq(attempts),
+            factory_callbacks::next_attempt_at.eq(next_attempt_at),
+            factory_callbacks::last_error.eq(last_error),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 已放弃或仍在重试中的回调，从新到旧排列
+pub async fn find_stuck(
+    offset: i64,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<StuckCallbackPo>> {
+    let callbacks = factory_callbacks::table
+        .filter(
+            factory_callbacks::status
+                .eq(CallbackStatus::Dead as i16)
+                .or(factory_callbacks::status
+                    .eq(CallbackStatus::Pending as i16)
+                    .and(factory_callbacks::attempts.gt(0))),
+        )
+        .select(StuckCallbackPo::as_select())
+        .order_by(factory_callbacks::id.desc())
+        .offset(offset)
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(callbacks)
+}
+
+/// 重新放入处理队列，重试次数清零
+pub async fn replay(id: i64, conn: &mut PgConn) -> Result<EffectedRow> {
+    let effected = diesel::update(
+        factory_callbacks::table
+            .filter(factory_callbacks::id.eq(id))
+            .filter(factory_callbacks::status.ne(CallbackStatus::Processed as i16)),
+    )
+    .set((
+        factory_callbacks::status.eq(CallbackStatus::Pending as i16),
+        factory_callbacks::attempts.eq(0),
+        factory_callbacks::next_attempt_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)
+    .await?;
+    Ok(EffectedRow {
+        expect_row: 1,
+        effected_row: effected,
+    })
+}
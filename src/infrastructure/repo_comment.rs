@@ -0,0 +1,88 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{
+            comment::{FileComment, FileCommentId},
+            file::UserFileId,
+        },
+        user::user::UserId,
+    },
+    schema::file_comments,
+};
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = file_comments)]
+struct FileCommentPo<'a> {
+    id: FileCommentId,
+    user_file_id: UserFileId,
+    user_id: UserId,
+    content: Cow<'a, str>,
+    timecode_ms: Option<i32>,
+}
+
+impl<'a> FileCommentPo<'a> {
+    fn from_do(comment: &'a FileComment) -> Self {
+        Self {
+            id: *comment.id(),
+            user_file_id: *comment.user_file_id(),
+            user_id: *comment.author_id(),
+            content: Cow::Borrowed(comment.content()),
+            timecode_ms: comment.timecode_ms().as_ref().map(|t| *t as i32),
+        }
+    }
+
+    fn into_do(self) -> FileComment {
+        FileComment::from_raw(
+            self.id,
+            self.user_file_id,
+            self.user_id,
+            self.content.into_owned(),
+            self.timecode_ms.map(|t| t as u32),
+        )
+    }
+}
+
+pub async fn save(comment: &FileComment, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(file_comments::table)
+        .values(FileCommentPo::from_do(comment))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn find(id: FileCommentId, conn: &mut PgConn) -> Result<Option<FileComment>> {
+    let comment = file_comments::table
+        .find(id)
+        .select(FileCommentPo::as_select())
+        .for_update()
+        .get_result::<FileCommentPo>(conn)
+        .await
+        .optional()?;
+    Ok(comment.map(FileCommentPo::into_do))
+}
+
+pub async fn update(comment: &FileComment, conn: &mut PgConn) -> Result<()> {
+    diesel::update(file_comments::table)
+        .filter(file_comments::id.eq(*comment.id()))
+        .set(file_comments::content.eq(comment.content()))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete(id: FileCommentId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(file_comments::table)
+        .filter(file_comments::id.eq(id))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Result};
+use async_graphql::Enum;
+use diesel::{ExpressionMethods, Insertable, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        transcode_order::{TranscodeOrderId, TranscodeTaskId},
+        user::user::UserId,
+    },
+    schema::{credit_transactions, users},
+};
+
+/// 积分变动的类型，也用于查询变动记录
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i16)]
+pub enum CreditKind {
+    /// 创建订单时扣除
+    Consume = 0,
+    /// 任务失败或取消时退还
+    Refund = 1,
+    /// 管理员充值
+    Recharge = 2,
+    /// 邀请注册的奖励
+    Referral = 3,
+}
+
+impl CreditKind {
+    pub fn from_i16(value: i16) -> Result<Self> {
+        let kind = match value {
+            0 => Self::Consume,
+            1 => Self::Refund,
+            2 => Self::Recharge,
+            3 => Self::Referral,
+            kind => bail!("invalid credit kind: {}", kind),
+        };
+        Ok(kind)
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = credit_transactions)]
+pub struct CreditTransactionPo {
+    pub user_id: UserId,
+    pub amount: i64,
+    pub balance: i64,
+    pub kind: i16,
+    pub order_id: Option<TranscodeOrderId>,
+    pub task_id: Option<TranscodeTaskId>,
+}
+
+/// 扣除积分，余额不足时不做任何修改并返回 None
+pub async fn consume(
+    user_id: UserId,
+    amount: u64,
+    order_id: TranscodeOrderId,
+    conn: &mut PgConn,
+) -> Result<Option<i64>> {
+    let amount = amount as i64;
+    let balance: Option<i64> = diesel::update(users::table)
+        .filter(users::id.eq(user_id))
+        .filter(users::credits.ge(amount))
+        .set(users::credits.eq(users::credits - amount))
+        .returning(users::credits)
+        .get_result(conn)
+        .await
+        .optional()?;
+    let Some(balance) = balance else {
+        return Ok(None);
+    };
+
+    save_transaction(
+        CreditTransactionPo {
+            user_id,
+            amount: -amount,
+            balance,
+            kind: CreditKind::Consume as i16,
+            order_id: Some(order_id),
+            task_id: None,
+        },
+        conn,
+    )
+    .await?;
+    Ok(Some(balance))
+}
+
+/// 增加积分，用户不存在时返回 None
+pub async fn add(
+    user_id: UserId,
+    amount: u64,
+    kind: CreditKind,
+    order_id: Option<TranscodeOrderId>,
+    task_id: Option<TranscodeTaskId>,
+    conn: &mut PgConn,
+) -> Result<Option<i64>> {
+    let amount = amount as i64;
+    let balance: Option<i64> = diesel::update(users::table)
+        .filter(users::id.eq(user_id))
+        .set(users::credits.eq(users::credits + amount))
+        .returning(users::credits)
+        .get_result(conn)
+        .await
+        .optional()?;
+    let Some(balance) = balance else {
+        return Ok(None);
+    };
+
+    save_transaction(
+        CreditTransactionPo {
+            user_id,
+            amount,
+            balance,
+            kind: kind as i16,
+            order_id,
+            task_id,
+        },
+        conn,
+    )
+    .await?;
+    Ok(Some(balance))
+}
+
+async fn save_transaction(po: CreditTransactionPo, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(credit_transactions::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
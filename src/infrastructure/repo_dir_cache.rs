@@ -0,0 +1,97 @@
+//! 目录列表的 redis 缓存
+//!
+//! 缓存键中带有用户的代数，用户的文件有任何变化时代数加一，旧的缓存不会再被读到，由过期时间清理。
+//! 事务中的变化在提交之后才使缓存失效，否则并发的读取可能把提交前的数据以新的代数缓存下来
+
+use std::{cell::RefCell, future::Future};
+
+use anyhow::Result;
+use redis::AsyncCommands;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    log_if_err,
+    redis_conn_switch::redis_conn,
+    settings::get_settings,
+};
+
+tokio::task_local! {
+    /// 当前事务中文件有变化的用户
+    static PENDING: RefCell<Vec<UserId>>;
+}
+
+use super::RedisKey;
+
+fn generation_key(user_id: UserId) -> String {
+    RedisKey::new("dir-list")
+        .add_field(user_id.to_string())
+        .add_field("gen")
+        .into_inner()
+}
+
+/// 某一页目录列表的缓存键
+pub async fn page_key(
+    user_id: UserId,
+    dir_id: UserFileId,
+    page: u32,
+    page_size: u32,
+) -> Result<String> {
+    let conn = &mut redis_conn().await?;
+    let generation: Option<u64> = conn.get(generation_key(user_id)).await?;
+    let key = RedisKey::new("dir-list")
+        .add_field(user_id.to_string())
+        .add_field(generation.unwrap_or_default().to_string())
+        .add_field(dir_id.to_string())
+        .add_field(format!("{page}-{page_size}"))
+        .into_inner();
+    Ok(key)
+}
+
+pub async fn get(key: &str) -> Result<Option<String>> {
+    let conn = &mut redis_conn().await?;
+    let cached = conn.get(key).await?;
+    Ok(cached)
+}
+
+pub async fn set(key: &str, value: &str, ttl_secs: u64) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let _: () = conn.set_ex(key, value, ttl_secs as usize).await?;
+    Ok(())
+}
+
+/// 用户的文件有变化时调用，使该用户所有目录的缓存失效。
+/// 在 [`after_commit`] 中调用时推迟到事务提交之后
+pub async fn invalidate(user_id: UserId) -> Result<()> {
+    if !get_settings().file_system.dir_cache.enable {
+        return Ok(());
+    }
+    let deferred = PENDING.try_with(|pending| pending.borrow_mut().push(user_id));
+    if deferred.is_ok() {
+        return Ok(());
+    }
+    incr_generation(user_id).await
+}
+
+/// 执行事务，其中调用的 [`invalidate`] 在事务提交（tx 返回 Ok）之后才执行
+pub async fn after_commit<T, E>(tx: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let (res, mut user_ids) = PENDING
+        .scope(RefCell::new(vec![]), async {
+            let res = tx.await;
+            (res, PENDING.with(|pending| pending.take()))
+        })
+        .await;
+    if res.is_ok() {
+        user_ids.sort_by_key(|id| id.0);
+        user_ids.dedup();
+        for user_id in user_ids {
+            log_if_err!(incr_generation(user_id).await);
+        }
+    }
+    res
+}
+
+async fn incr_generation(user_id: UserId) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let _: () = conn.incr(generation_key(user_id), 1).await?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use chrono::Local;
+use diesel::{
+    sql_types::{Array, BigInt},
+    upsert::excluded,
+    ExpressionMethods, Insertable, QueryDsl, Queryable, QueryableByName, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::{SysFileId, UserFileId},
+        user::user::UserId,
+    },
+    schema::dir_usages,
+};
+
+/// 缓存的有效期，兜底与写入并发时缓存了旧数据的情况
+const CACHE_TTL_HOURS: i64 = 1;
+
+/// 目录及其所有子目录下文件的统计
+#[derive(Queryable, Selectable, Insertable, QueryableByName, Debug, Clone)]
+#[diesel(table_name = dir_usages)]
+pub struct DirUsagePo {
+    pub dir_id: UserFileId,
+    pub user_id: UserId,
+    pub size: i64,
+    pub file_count: i64,
+    pub video_duration_ms: i64,
+}
+
+pub async fn find(dir_ids: &[UserFileId], conn: &mut PgConn) -> Result<Vec<DirUsagePo>> {
+    let expired_before = Local::now() - chrono::Duration::hours(CACHE_TTL_HOURS);
+    let usages = dir_usages::table
+        .filter(dir_usages::dir_id.eq_any(dir_ids))
+        .filter(dir_usages::computed_at.gt(expired_before))
+        .select(DirUsagePo::as_select())
+        .load(conn)
+        .await?;
+    Ok(usages)
+}
+
+/// 递归统计各个目录，已删除的文件不计入
+pub async fn compute(dir_ids: &[UserFileId], conn: &mut PgConn) -> Result<Vec<DirUsagePo>> {
+    if dir_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let usages = diesel::sql_query(
+        r#"
+        WITH RECURSIVE tree(root, user_id, id) AS (
+            SELECT id, user_id, id FROM user_files WHERE id = ANY($1) AND NOT deleted
+            UNION ALL
+            SELECT t.root, t.user_id, f.id FROM user_files f
+            JOIN tree t ON f.parent_id = t.id
+            WHERE NOT f.deleted
+        )
+        SELECT t.root AS dir_id,
+            t.user_id AS user_id,
+            COALESCE(SUM(s.size), 0)::BIGINT AS size,
+            COUNT(s.id) AS file_count,
+            COALESCE(SUM(s.duration_ms), 0)::BIGINT AS video_duration_ms
+        FROM tree t
+        JOIN user_files f ON f.id = t.id
+        LEFT JOIN sys_files s ON s.id = f.sys_file_id
+        GROUP BY t.root, t.user_id
+        "#,
+    )
+    .bind::<Array<BigInt>, _>(dir_ids.iter().map(|id| id.0).collect::<Vec<_>>())
+    .load::<DirUsagePo>(conn)
+    .await?;
+    Ok(usages)
+}
+
+pub async fn save(usages: &[DirUsagePo], conn: &mut PgConn) -> Result<()> {
+    if usages.is_empty() {
+        return Ok(());
+    }
+    diesel::insert_into(dir_usages::table)
+        .values(usages)
+        .on_conflict(dir_usages::dir_id)
+        .do_update()
+        .set((
+            dir_usages::size.eq(excluded(dir_usages::size)),
+            dir_usages::file_count.eq(excluded(dir_usages::file_count)),
+            dir_usages::video_duration_ms.eq(excluded(dir_usages::video_duration_ms)),
+            dir_usages::computed_at.eq(Local::now()),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 文件有变动后，删除它自身及所有上级目录的缓存
+pub async fn invalidate_ancestors(file_ids: &[UserFileId], conn: &mut PgConn) -> Result<()> {
+    if file_ids.is_empty() {
+        return Ok(());
+    }
+    diesel::sql_query(invalidate_sql("id = ANY($1)"))
+        .bind::<Array<BigInt>, _>(file_ids.iter().map(|id| id.0).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 系统文件的元数据有变动后，删除所有引用它的用户文件的上级目录的缓存
+pub async fn invalidate_by_sys_file(sys_file_id: SysFileId, conn: &mut PgConn) -> Result<()> {
+    diesel::sql_query(invalidate_sql("sys_file_id = $1"))
+        .bind::<BigInt, _>(sys_file_id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+fn invalidate_sql(seed: &str) -> String {
+    format!(
+        r#"
+        WITH RECURSIVE ancestors(id, parent_id) AS (
+            SELECT id, parent_id FROM user_files WHERE {seed}
+            UNION
+            SELECT f.id, f.parent_id FROM user_files f
+            JOIN ancestors a ON f.id = a.parent_id
+        )
+        DELETE FROM dir_usages WHERE dir_id IN (SELECT id FROM ancestors)
+        "#
+    )
+}
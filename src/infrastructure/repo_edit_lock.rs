@@ -0,0 +1,97 @@
+use anyhow::Result;
+use diesel::{
+    prelude::Queryable, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{edit_lock::EditLock, file::UserFileId},
+        user::user::UserId,
+    },
+    schema::file_edit_locks,
+    LocalDataTime,
+};
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = file_edit_locks)]
+struct EditLockPo {
+    user_file_id: UserFileId,
+    holder_id: UserId,
+    expire_at: LocalDataTime,
+}
+
+impl EditLockPo {
+    fn from_lock(lock: &EditLock) -> Self {
+        Self {
+            user_file_id: *lock.user_file_id(),
+            holder_id: *lock.holder_id(),
+            expire_at: *lock.expire_at(),
+        }
+    }
+
+    fn into_lock(self) -> EditLock {
+        EditLock::from_po(self.user_file_id, self.holder_id, self.expire_at)
+    }
+}
+
+/// 包括已过期的锁
+pub async fn find_for_update(file_id: UserFileId, conn: &mut PgConn) -> Result<Option<EditLock>> {
+    let po: Option<EditLockPo> = file_edit_locks::table
+        .find(file_id)
+        .select(EditLockPo::as_select())
+        .for_update()
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(po.map(EditLockPo::into_lock))
+}
+
+/// 这些文件上未过期的锁
+pub async fn find_active(
+    file_ids: &[UserFileId],
+    now: LocalDataTime,
+    conn: &mut PgConn,
+) -> Result<Vec<EditLock>> {
+    if file_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let pos: Vec<EditLockPo> = file_edit_locks::table
+        .filter(file_edit_locks::user_file_id.eq_any(file_ids))
+        .filter(file_edit_locks::expire_at.gt(now))
+        .select(EditLockPo::as_select())
+        .load(conn)
+        .await?;
+    Ok(pos.into_iter().map(EditLockPo::into_lock).collect())
+}
+
+/// 文件上还没有锁时插入，返回是否插入成功。并发加锁时只有一个会成功
+pub async fn insert(lock: &EditLock, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::insert_into(file_edit_locks::table)
+        .values(EditLockPo::from_lock(lock))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
+
+/// 覆盖已有的锁，调用前应当用 [`find_for_update`] 锁住这一行
+pub async fn update(lock: &EditLock, conn: &mut PgConn) -> Result<()> {
+    diesel::update(file_edit_locks::table.find(*lock.user_file_id()))
+        .set((
+            file_edit_locks::holder_id.eq(*lock.holder_id()),
+            file_edit_locks::expire_at.eq(*lock.expire_at()),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete(file_id: UserFileId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(file_edit_locks::table.find(file_id))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
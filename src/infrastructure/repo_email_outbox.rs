@@ -0,0 +1,106 @@
+use anyhow::Result;
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{schema::email_outbox, LocalDataTime};
+
+#[derive(Clone, Copy, Debug)]
+#[repr(i16)]
+pub enum EmailStatus {
+    Pending = 0,
+    Sent = 1,
+    /// 多次重试或过期后放弃，保留以便排查
+    Dead = 2,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = email_outbox)]
+pub struct NewEmailPo<'a> {
+    pub to_addr: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub expire_at: Option<LocalDataTime>,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = email_outbox)]
+pub struct EmailPo {
+    pub id: i64,
+    pub to_addr: String,
+    pub subject: String,
+    pub body: String,
+    pub attempts: i32,
+    pub next_attempt_at: LocalDataTime,
+    pub expire_at: Option<LocalDataTime>,
+}
+
+pub async fn save(email: &NewEmailPo<'_>, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(email_outbox::table)
+        .values(email)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 查询已到发送时间的邮件，先写入的先发送
+pub async fn due_emails(limit: i64, conn: &mut PgConn) -> Result<Vec<EmailPo>> {
+    let emails = email_outbox::table
+        .filter(email_outbox::status.eq(EmailStatus::Pending as i16))
+        .filter(email_outbox::next_attempt_at.le(diesel::dsl::now))
+        .select(EmailPo::as_select())
+        .order_by(email_outbox::id.asc())
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(emails)
+}
+
+/// 将邮件的下次发送时间推迟到 lease_until，防止多个实例重复发送。
+/// 返回 false 表示已被其他实例认领
+pub async fn claim(email: &EmailPo, lease_until: LocalDataTime, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(
+        email_outbox::table
+            .filter(email_outbox::id.eq(email.id))
+            .filter(email_outbox::status.eq(EmailStatus::Pending as i16))
+            .filter(email_outbox::next_attempt_at.eq(email.next_attempt_at)),
+    )
+    .set(email_outbox::next_attempt_at.eq(lease_until))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
+
+/// 发送成功，同时清空邮件内容
+pub async fn sent(id: i64, attempts: i32, conn: &mut PgConn) -> Result<()> {
+    diesel::update(email_outbox::table.filter(email_outbox::id.eq(id)))
+        .set((
+            email_outbox::status.eq(EmailStatus::Sent as i16),
+            email_outbox::attempts.eq(attempts),
+            email_outbox::body.eq(""),
+            email_outbox::last_error.eq(None::<String>),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn failed(
+    id: i64,
+    status: EmailStatus,
+    attempts: i32,
+    next_attempt_at: LocalDataTime,
+    last_error: &str,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(email_outbox::table.filter(email_outbox::id.eq(id)))
+        .set((
+            email_outbox::status.eq(status as i16),
+            email_outbox::attempts.eq(attempts),
+            email_outbox::next_attempt_at.eq(next_attempt_at),
+            email_outbox::last_error.eq(last_error),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
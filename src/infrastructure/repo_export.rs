@@ -0,0 +1,84 @@
+use anyhow::Result;
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{domain::user::employee::EmployeeId, schema::user_export_jobs, LocalDataTime};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(i16)]
+pub enum ExportStatus {
+    Running = 0,
+    Done = 1,
+    Failed = 2,
+}
+
+impl ExportStatus {
+    pub fn from_i16(status: i16) -> Option<Self> {
+        match status {
+            0 => Some(Self::Running),
+            1 => Some(Self::Done),
+            2 => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = user_export_jobs)]
+pub struct NewExportJobPo {
+    pub employee_id: EmployeeId,
+    pub params: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = user_export_jobs)]
+pub struct ExportJobPo {
+    pub id: i64,
+    pub employee_id: EmployeeId,
+    pub status: i16,
+    pub row_count: i64,
+    pub error: Option<String>,
+    pub create_at: LocalDataTime,
+    pub updated_at: LocalDataTime,
+}
+
+pub async fn save(job: &NewExportJobPo, conn: &mut PgConn) -> Result<i64> {
+    let id = diesel::insert_into(user_export_jobs::table)
+        .values(job)
+        .returning(user_export_jobs::id)
+        .get_result(conn)
+        .await?;
+    Ok(id)
+}
+
+pub async fn find(id: i64, conn: &mut PgConn) -> Result<Option<ExportJobPo>> {
+    let job = user_export_jobs::table
+        .find(id)
+        .select(ExportJobPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(job)
+}
+
+pub async fn finish(
+    id: i64,
+    status: ExportStatus,
+    row_count: i64,
+    error: Option<&str>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(user_export_jobs::table.find(id))
+        .set((
+            user_export_jobs::status.eq(status as i16),
+            user_export_jobs::row_count.eq(row_count),
+            user_export_jobs::error.eq(error),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use diesel::{
+    prelude::Queryable, upsert::excluded, ExpressionMethods, Insertable, OptionalExtension,
+    QueryDsl, Selectable, SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use redis::AsyncCommands;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{feature_flag::FeatureFlag, user::user::UserId},
+    redis_conn_switch::redis_conn,
+    schema::feature_flags,
+};
+
+use super::RedisKey;
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = feature_flags)]
+pub struct FeatureFlagPo {
+    pub name: String,
+    pub description: String,
+    pub enabled: bool,
+    pub rollout_percent: i16,
+    pub user_ids: Vec<UserId>,
+}
+
+impl FeatureFlagPo {
+    fn from_flag(flag: &FeatureFlag) -> Self {
+        Self {
+            name: flag.name().clone(),
+            description: flag.description().clone(),
+            enabled: *flag.enabled(),
+            rollout_percent: *flag.rollout_percent() as i16,
+            user_ids: flag.user_ids().clone(),
+        }
+    }
+
+    fn into_flag(self) -> Result<FeatureFlag> {
+        let flag = FeatureFlag::new(
+            self.name,
+            self.description,
+            self.enabled,
+            self.rollout_percent.try_into()?,
+            self.user_ids,
+        )
+        .map_err(|err| anyhow::anyhow!("invalid feature flag in db: {:?}", err))?;
+        Ok(flag)
+    }
+}
+
+pub async fn find(name: &str, conn: &mut PgConn) -> Result<Option<FeatureFlag>> {
+    let po: Option<FeatureFlagPo> = feature_flags::table
+        .filter(feature_flags::name.eq(name))
+        .select(FeatureFlagPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    po.map(FeatureFlagPo::into_flag).transpose()
+}
+
+pub async fn list(conn: &mut PgConn) -> Result<Vec<FeatureFlag>> {
+    let pos: Vec<FeatureFlagPo> = feature_flags::table
+        .select(FeatureFlagPo::as_select())
+        .order_by(feature_flags::name)
+        .load(conn)
+        .await?;
+    pos.into_iter().map(FeatureFlagPo::into_flag).collect()
+}
+
+/// 不存在时创建，存在时覆盖
+pub async fn save(flag: &FeatureFlag, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(feature_flags::table)
+        .values(FeatureFlagPo::from_flag(flag))
+        .on_conflict(feature_flags::name)
+        .do_update()
+        .set((
+            feature_flags::description.eq(excluded(feature_flags::description)),
+            feature_flags::enabled.eq(excluded(feature_flags::enabled)),
+            feature_flags::rollout_percent.eq(excluded(feature_flags::rollout_percent)),
+            feature_flags::user_ids.eq(excluded(feature_flags::user_ids)),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete(name: &str, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::delete(feature_flags::table)
+        .filter(feature_flags::name.eq(name))
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
+
+fn cache_key(name: &str) -> String {
+    RedisKey::new("feature-flag").add_field(name).into_inner()
+}
+
+/// 外层的 None 表示没有缓存，内层的 None 表示开关不存在
+pub async fn cached(name: &str) -> Result<Option<Option<FeatureFlag>>> {
+    let conn = &mut redis_conn().await?;
+    let cached: Option<String> = conn.get(cache_key(name)).await?;
+    let Some(cached) = cached else {
+        return Ok(None);
+    };
+    Ok(Some(serde_json::from_str(&cached)?))
+}
+
+/// 不存在的开关也缓存，避免未配置的开关每次都查询数据库
+pub async fn cache(name: &str, flag: Option<&FeatureFlag>, ttl_secs: u64) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let value = serde_json::to_string(&flag)?;
+    let _: () = conn
+        .set_ex(cache_key(name), value, ttl_secs as usize)
+        .await?;
+    Ok(())
+}
+
+pub async fn uncache(name: &str) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let _: () = conn.del(cache_key(name)).await?;
+    Ok(())
+}
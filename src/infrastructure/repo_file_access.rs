@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use chrono::{Local, TimeZone};
+use diesel::{upsert::excluded, ExpressionMethods, Insertable};
+use diesel_async::RunQueryDsl;
+use redis::AsyncCommands;
+use tracing::warn;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    redis_conn_switch::redis_conn,
+    schema::{file_access_logs, file_accesses},
+    LocalDataTime,
+};
+
+use super::RedisKey;
+
+/// 用户对文件的一次访问
+#[derive(Insertable, Debug)]
+#[diesel(table_name = file_accesses)]
+pub struct FileAccess {
+    pub user_id: UserId,
+    pub user_file_id: UserFileId,
+    pub accessed_at: LocalDataTime,
+}
+
+/// 记录到 redis 中，同一文件只保留最近一次访问时间
+pub async fn record(user_id: UserId, file_id: UserFileId, at: LocalDataTime) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let field = format!("{user_id}:{file_id}");
+    let _: () = conn
+        .hset(pending_key(), field, at.timestamp_millis())
+        .await?;
+    Ok(())
+}
+
+/// 取出 redis 中还未写入数据库的访问记录
+pub async fn take_pending() -> Result<Vec<FileAccess>> {
+    let conn = &mut redis_conn().await?;
+    let pending = pending_key();
+    let exists: bool = conn.exists(&pending).await?;
+    if !exists {
+        return Ok(vec![]);
+    }
+
+    // 先改名再读取，避免丢失读取期间新增的记录
+    let flushing = RedisKey::new("file-access")
+        .add_field("flushing")
+        .add_field(rand::random::<u64>().to_string())
+        .into_inner();
+    let _: () = conn.rename(&pending, &flushing).await?;
+    let records: HashMap<String, i64> = conn.hgetall(&flushing).await?;
+    let _: () = conn.del(&flushing).await?;
+
+    let accesses = records
+        .into_iter()
+        .filter_map(|(field, ms)| {
+            let access = parse_record(&field, ms);
+            if access.is_none() {
+                warn!(field, ms, "invalid file access record");
+            }
+            access
+        })
+        .collect();
+    Ok(accesses)
+}
+
+fn parse_record(field: &str, ms: i64) -> Option<FileAccess> {
+    let (user_id, file_id) = field.split_once(':')?;
+    Some(FileAccess {
+        user_id: user_id.parse().ok()?,
+        user_file_id: file_id.parse().ok()?,
+        accessed_at: Local.timestamp_millis_opt(ms).single()?,
+    })
+}
+
+pub async fn save(accesses: &[FileAccess], conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(file_accesses::table)
+        .values(accesses)
+        .on_conflict((file_accesses::user_id, file_accesses::user_file_id))
+        .do_update()
+        .set(file_accesses::accessed_at.eq(excluded(file_accesses::accessed_at)))
+        .execute(conn)
+        .await
+        .context("save file accesses")?;
+    Ok(())
+}
+
+fn pending_key() -> String {
+    RedisKey::new("file-access")
+        .add_field("pending")
+        .into_inner()
+}
+
+/// 文件的一条访问记录
+#[derive(Insertable, Debug)]
+#[diesel(table_name = file_access_logs)]
+pub struct NewAccessLogPo<'a> {
+    pub user_file_id: UserFileId,
+    pub kind: &'a str,
+    pub accessor_id: Option<UserId>,
+}
+
+pub async fn save_log(log: &NewAccessLogPo<'_>, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(file_access_logs::table)
+        .values(log)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 删除 before 之前的访问记录，返回删除的行数
+pub async fn delete_logs_before(before: LocalDataTime, conn: &mut PgConn) -> Result<usize> {
+    let deleted = diesel::delete(file_access_logs::table)
+        .filter(file_access_logs::accessed_at.lt(before))
+        .execute(conn)
+        .await?;
+    Ok(deleted)
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use diesel::Insertable;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{change::FileChange, file::UserFileId},
+        user::user::UserId,
+    },
+    schema::file_changes,
+};
+
+use super::repo_user_sequence;
+
+/// 变更记录在 user_sequences 中的序列名
+const SEQUENCE: &str = "file_changes";
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = file_changes)]
+struct FileChangePo<'a> {
+    user_id: UserId,
+    user_file_id: UserFileId,
+    kind: i16,
+    is_dir: bool,
+    path: &'a str,
+    old_path: Option<&'a str>,
+    seq: i64,
+}
+
+/// 按所有者取得递增的 seq，同一所有者的 seq 与提交顺序一致，
+/// 客户端按 seq 增量读取时不会漏掉提交较晚的记录
+pub async fn insert(change: &FileChange, conn: &mut PgConn) -> Result<()> {
+    let seq = repo_user_sequence::next(*change.user_id(), SEQUENCE, conn).await?;
+    let po = FileChangePo {
+        user_id: *change.user_id(),
+        user_file_id: *change.file_id(),
+        kind: *change.kind() as i16,
+        is_dir: *change.is_dir(),
+        path: change.path(),
+        old_path: change.old_path().as_deref(),
+        seq,
+    };
+    diesel::insert_into(file_changes::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use diesel::{dsl::exists, ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::UserFileId,
+        transcode_order::{TranscocdeOrder, TranscodeTaskId},
+    },
+    schema::file_locks,
+};
+
+/// 为订单中的每个任务锁定它的源文件
+pub async fn lock_order(order: &TranscocdeOrder, conn: &mut PgConn) -> Result<()> {
+    let values: Vec<_> = order
+        .tasks()
+        .iter()
+        .map(|task| {
+            (
+                file_locks::task_id.eq(*task.id()),
+                file_locks::user_file_id.eq(*task.user_file_id()),
+                file_locks::user_id.eq(*order.user_id()),
+            )
+        })
+        .collect();
+    diesel::insert_into(file_locks::table)
+        .values(values)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn release(task_ids: &[TranscodeTaskId], conn: &mut PgConn) -> Result<()> {
+    diesel::delete(file_locks::table)
+        .filter(file_locks::task_id.eq_any(task_ids))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 这些文件中是否有正在被转码任务使用的
+pub async fn any_locked(file_ids: &[UserFileId], conn: &mut PgConn) -> Result<bool> {
+    if file_ids.is_empty() {
+        return Ok(false);
+    }
+    let locked = diesel::select(exists(
+        file_locks::table.filter(file_locks::user_file_id.eq_any(file_ids)),
+    ))
+    .get_result(conn)
+    .await?;
+    Ok(locked)
+}
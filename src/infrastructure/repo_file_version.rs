@@ -0,0 +1,128 @@
+use anyhow::Result;
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::{pg_conn, PgConn};
+
+use crate::{
+    domain::{
+        file_system::{
+            file::{convert::FileNodeConverter, FileNodeMetaData, SysFileId, UserFileId},
+            version::{FileVersion, FileVersionId},
+        },
+        user::user::UserId,
+    },
+    schema::{sys_files, user_file_versions},
+    LocalDataTime,
+};
+
+use super::repo_user_file::SysFilePo;
+
+diesel::joinable!(user_file_versions -> sys_files (sys_file_id));
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = user_file_versions)]
+struct FileVersionPo {
+    id: FileVersionId,
+    user_file_id: UserFileId,
+    user_id: UserId,
+    sys_file_id: SysFileId,
+}
+
+impl FileVersionPo {
+    fn from_do(version: &FileVersion) -> Self {
+        Self {
+            id: *version.id(),
+            user_file_id: *version.user_file_id(),
+            user_id: *version.user_id(),
+            sys_file_id: *version.sys_file_id(),
+        }
+    }
+
+    fn into_do(self) -> FileVersion {
+        FileVersion::from_raw(self.id, self.user_id, self.user_file_id, self.sys_file_id)
+    }
+}
+
+/// 历史版本及其文件内容
+#[derive(Debug)]
+pub struct VersionWithData {
+    pub version: FileVersion,
+    pub data: FileNodeMetaData,
+    pub create_at: LocalDataTime,
+}
+
+pub async fn save(version: &FileVersion, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(user_file_versions::table)
+        .values(FileVersionPo::from_do(version))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn find(id: FileVersionId, conn: &mut PgConn) -> Result<Option<FileVersion>> {
+    let version = user_file_versions::table
+        .find(id)
+        .select(FileVersionPo::as_select())
+        .for_update()
+        .get_result::<FileVersionPo>(conn)
+        .await
+        .optional()?;
+    Ok(version.map(FileVersionPo::into_do))
+}
+
+/// 文件的所有历史版本 id，从新到旧排列
+pub async fn list_ids(user_file_id: UserFileId, conn: &mut PgConn) -> Result<Vec<FileVersionId>> {
+    let ids = user_file_versions::table
+        .filter(user_file_versions::user_file_id.eq(user_file_id))
+        .order((
+            user_file_versions::create_at.desc(),
+            user_file_versions::id.desc(),
+        ))
+        .select(user_file_versions::id)
+        .load(conn)
+        .await?;
+    Ok(ids)
+}
+
+/// 文件的所有历史版本，从新到旧排列
+pub async fn list(user_file_id: UserFileId) -> Result<Vec<VersionWithData>> {
+    let conn = &mut pg_conn().await?;
+    let versions = user_file_versions::table
+        .inner_join(sys_files::table)
+        .filter(user_file_versions::user_file_id.eq(user_file_id))
+        .order((
+            user_file_versions::create_at.desc(),
+            user_file_versions::id.desc(),
+        ))
+        .select((
+            FileVersionPo::as_select(),
+            SysFilePo::as_select(),
+            user_file_versions::create_at,
+        ))
+        .load::<(FileVersionPo, SysFilePo, LocalDataTime)>(conn)
+        .await?;
+
+    let versions = versions
+        .into_iter()
+        .map(|(version, data, create_at)| VersionWithData {
+            version: version.into_do(),
+            data: FileNodeConverter::sys_file_po_to_do(data),
+            create_at,
+        })
+        .collect();
+    Ok(versions)
+}
+
+pub async fn delete(ids: &[FileVersionId], conn: &mut PgConn) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    diesel::delete(user_file_versions::table)
+        .filter(user_file_versions::id.eq_any(ids))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
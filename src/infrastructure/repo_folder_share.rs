@@ -0,0 +1,82 @@
+use anyhow::Result;
+use diesel::{upsert::excluded, ExpressionMethods, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{file::UserFileId, share::SharePermission},
+        user::user::UserId,
+    },
+    schema::folder_shares,
+};
+
+/// 已分享给该成员时修改权限
+pub async fn save(
+    dir_id: UserFileId,
+    owner_id: UserId,
+    member_id: UserId,
+    permission: SharePermission,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::insert_into(folder_shares::table)
+        .values((
+            folder_shares::dir_id.eq(dir_id),
+            folder_shares::owner_id.eq(owner_id),
+            folder_shares::member_id.eq(member_id),
+            folder_shares::permission.eq(permission as i16),
+        ))
+        .on_conflict((folder_shares::dir_id, folder_shares::member_id))
+        .do_update()
+        .set(folder_shares::permission.eq(excluded(folder_shares::permission)))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 返回是否存在这条分享
+pub async fn delete(
+    owner_id: UserId,
+    dir_id: UserFileId,
+    member_id: UserId,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let effected = diesel::delete(folder_shares::table.find((dir_id, member_id)))
+        .filter(folder_shares::owner_id.eq(owner_id))
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
+
+/// 文件夹的所有成员及权限
+pub async fn members(
+    dir_id: UserFileId,
+    conn: &mut PgConn,
+) -> Result<Vec<(UserId, SharePermission)>> {
+    let rows: Vec<(UserId, i16)> = folder_shares::table
+        .filter(folder_shares::dir_id.eq(dir_id))
+        .select((folder_shares::member_id, folder_shares::permission))
+        .order_by(folder_shares::create_at)
+        .load(conn)
+        .await?;
+    rows.into_iter()
+        .map(|(member_id, permission)| Ok((member_id, permission.try_into()?)))
+        .collect()
+}
+
+/// owner 分享给 member 的所有文件夹
+pub async fn granted(
+    owner_id: UserId,
+    member_id: UserId,
+    conn: &mut PgConn,
+) -> Result<Vec<(UserFileId, SharePermission)>> {
+    let rows: Vec<(UserFileId, i16)> = folder_shares::table
+        .filter(folder_shares::member_id.eq(member_id))
+        .filter(folder_shares::owner_id.eq(owner_id))
+        .select((folder_shares::dir_id, folder_shares::permission))
+        .load(conn)
+        .await?;
+    rows.into_iter()
+        .map(|(dir_id, permission)| Ok((dir_id, permission.try_into()?)))
+        .collect()
+}
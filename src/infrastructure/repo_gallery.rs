@@ -0,0 +1,270 @@
+use anyhow::Result;
+use diesel::{
+    prelude::Queryable, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use redis::AsyncCommands;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::UserFileId,
+        gallery::{Publication, PublicationId, Report, ReportId, ReportState},
+        user::{employee::EmployeeId, user::UserId},
+    },
+    redis_conn_switch::redis_conn,
+    schema::{publication_reports, publications},
+};
+
+use super::RedisKey;
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = publications)]
+struct PublicationPo {
+    id: PublicationId,
+    slug: String,
+    user_id: UserId,
+    user_file_id: UserFileId,
+    title: String,
+    taken_down: bool,
+}
+
+impl PublicationPo {
+    fn from_do(publication: &Publication) -> Self {
+        Self {
+            id: *publication.id(),
+            slug: publication.slug().clone(),
+            user_id: *publication.user_id(),
+            user_file_id: *publication.user_file_id(),
+            title: publication.title().clone(),
+            taken_down: *publication.taken_down(),
+        }
+    }
+
+    fn into_do(self) -> Publication {
+        Publication::from_po(
+            self.id,
+            self.slug,
+            self.user_id,
+            self.user_file_id,
+            self.title,
+            self.taken_down,
+        )
+    }
+}
+
+/// 文件已经发布时返回 false
+pub async fn insert(publication: &Publication, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::insert_into(publications::table)
+        .values(PublicationPo::from_do(publication))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
+
+pub async fn find_for_update(id: PublicationId, conn: &mut PgConn) -> Result<Option<Publication>> {
+    let po: Option<PublicationPo> = publications::table
+        .find(id)
+        .select(PublicationPo::as_select())
+        .for_update()
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(po.map(PublicationPo::into_do))
+}
+
+pub async fn find_by_slug(slug: &str, conn: &mut PgConn) -> Result<Option<Publication>> {
+    let po: Option<PublicationPo> = publications::table
+        .filter(publications::slug.eq(slug))
+        .select(PublicationPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(po.map(PublicationPo::into_do))
+}
+
+/// 文件的发布记录和观看次数
+pub async fn find_by_file(
+    file_id: UserFileId,
+    conn: &mut PgConn,
+) -> Result<Option<(Publication, i64)>> {
+    let po: Option<(PublicationPo, i64)> = publications::table
+        .filter(publications::user_file_id.eq(file_id))
+        .select((PublicationPo::as_select(), publications::view_count))
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(po.map(|(po, views)| (po.into_do(), views)))
+}
+
+/// 用户发布的视频和观看次数，包括被下架的，按发布时间倒序
+pub async fn list_for_user(user_id: UserId, conn: &mut PgConn) -> Result<Vec<(Publication, i64)>> {
+    let pos: Vec<(PublicationPo, i64)> = publications::table
+        .filter(publications::user_id.eq(user_id))
+        .order_by(publications::create_at.desc())
+        .select((PublicationPo::as_select(), publications::view_count))
+        .load(conn)
+        .await?;
+    Ok(pos
+        .into_iter()
+        .map(|(po, views)| (po.into_do(), views))
+        .collect())
+}
+
+/// 增加观看次数，返回增加后的次数
+pub async fn incr_views(id: PublicationId, conn: &mut PgConn) -> Result<i64> {
+    let views = diesel::update(publications::table.find(id))
+        .set(publications::view_count.eq(publications::view_count + 1))
+        .returning(publications::view_count)
+        .get_result(conn)
+        .await?;
+    Ok(views)
+}
+
+pub async fn find_views(id: PublicationId, conn: &mut PgConn) -> Result<i64> {
+    let views = publications::table
+        .find(id)
+        .select(publications::view_count)
+        .get_result(conn)
+        .await?;
+    Ok(views)
+}
+
+/// 观看者在 window_secs 内第一次观看时返回 true，之后的访问不重复计数
+pub async fn first_view(id: PublicationId, viewer: &str, window_secs: u64) -> Result<bool> {
+    let key = RedisKey::new("gallery-view")
+        .add_field(id.to_string())
+        .add_field(viewer)
+        .into_inner();
+    let conn = &mut redis_conn().await?;
+    let first: bool = redis::cmd("set")
+        .arg(&key)
+        .arg(1)
+        .arg(&["EX", &window_secs.to_string(), "NX"])
+        .query_async(conn)
+        .await?;
+    Ok(first)
+}
+
+/// 在当前的时间窗口内记录 reporter 对视频的一次举报，返回窗口内的举报次数
+pub async fn incr_report_count(id: PublicationId, reporter: &str, window_secs: u64) -> Result<u64> {
+    let window = chrono::Local::now().timestamp() / window_secs as i64;
+    let key = RedisKey::new("gallery-report")
+        .add_field(id.to_string())
+        .add_field(reporter)
+        .add_field(window.to_string())
+        .into_inner();
+    let conn = &mut redis_conn().await?;
+    let count: u64 = conn.incr(&key, 1).await?;
+    if count == 1 {
+        let _: () = conn.expire(&key, window_secs as usize).await?;
+    }
+    Ok(count)
+}
+
+pub async fn set_taken_down(publication: &Publication, conn: &mut PgConn) -> Result<()> {
+    diesel::update(publications::table.find(*publication.id()))
+        .set(publications::taken_down.eq(*publication.taken_down()))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 删除发布记录和它的举报
+pub async fn delete(id: PublicationId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(publication_reports::table.filter(publication_reports::publication_id.eq(id)))
+        .execute(conn)
+        .await?;
+    diesel::delete(publications::table.find(id))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = publication_reports)]
+struct ReportPo {
+    id: ReportId,
+    publication_id: PublicationId,
+    reporter_id: Option<UserId>,
+    reason: String,
+    state: i16,
+}
+
+impl ReportPo {
+    fn from_do(report: &Report) -> Self {
+        Self {
+            id: *report.id(),
+            publication_id: *report.publication_id(),
+            reporter_id: *report.reporter_id(),
+            reason: report.reason().clone(),
+            state: *report.state() as i16,
+        }
+    }
+
+    fn into_do(self) -> Result<Report> {
+        Ok(Report::from_po(
+            self.id,
+            self.publication_id,
+            self.reporter_id,
+            self.reason,
+            ReportState::try_from(self.state)?,
+        ))
+    }
+}
+
+pub async fn insert_report(report: &Report, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(publication_reports::table)
+        .values(ReportPo::from_do(report))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn find_report_for_update(id: ReportId, conn: &mut PgConn) -> Result<Option<Report>> {
+    let po: Option<ReportPo> = publication_reports::table
+        .find(id)
+        .select(ReportPo::as_select())
+        .for_update()
+        .first(conn)
+        .await
+        .optional()?;
+    po.map(ReportPo::into_do).transpose()
+}
+
+pub async fn update_report(
+    report: &Report,
+    handled_by: EmployeeId,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(publication_reports::table.find(*report.id()))
+        .set((
+            publication_reports::state.eq(*report.state() as i16),
+            publication_reports::handled_by.eq(handled_by),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 视频下架后，把它其他待处理的举报一并标记为已下架
+pub async fn close_pending_reports(
+    publication_id: PublicationId,
+    handled_by: EmployeeId,
+    conn: &mut PgConn,
+) -> Result<usize> {
+    let effected = diesel::update(
+        publication_reports::table
+            .filter(publication_reports::publication_id.eq(publication_id))
+            .filter(publication_reports::state.eq(ReportState::Pending as i16)),
+    )
+    .set((
+        publication_reports::state.eq(ReportState::TakenDown as i16),
+        publication_reports::handled_by.eq(handled_by),
+    ))
+    .execute(conn)
+    .await?;
+    Ok(effected)
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{domain::user::user::UserId, redis_conn_switch::redis_conn};
+
+use super::RedisKey;
+
+/// 同一个幂等键的第一次请求及其响应
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IdempotentRecord {
+    /// 请求的指纹，相同的键只能用于相同的请求
+    pub fingerprint: String,
+    /// 为空表示第一次请求还在处理中
+    pub response: Option<StoredResponse>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// 占用幂等键，键已存在时返回已有的记录
+pub async fn reserve(
+    user_id: UserId,
+    key: &str,
+    fingerprint: &str,
+    ttl_secs: u64,
+) -> Result<Option<IdempotentRecord>> {
+    let redis_key = record_key(user_id, key);
+    let pending = IdempotentRecord {
+        fingerprint: fingerprint.to_string(),
+        response: None,
+    };
+    let conn = &mut redis_conn().await?;
+    let reserved: bool = redis::cmd("set")
+        .arg(&redis_key)
+        .arg(serde_json::to_string(&pending)?)
+        .arg("EX")
+        .arg(ttl_secs)
+        .arg("NX")
+        .query_async(conn)
+        .await?;
+    if reserved {
+        return Ok(None);
+    }
+
+    let existed: Option<String> = conn.get(&redis_key).await?;
+    match existed {
+        Some(existed) => Ok(Some(serde_json::from_str(&existed)?)),
+        // 刚好过期，视为处理中，由客户端重试
+        None => Ok(Some(pending)),
+    }
+}
+
+pub async fn complete(
+    user_id: UserId,
+    key: &str,
+    record: &IdempotentRecord,
+    ttl_secs: u64,
+) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let value = serde_json::to_string(record)?;
+    let _: () = conn
+        .set_ex(record_key(user_id, key), value, ttl_secs as usize)
+        .await?;
+    Ok(())
+}
+
+/// 释放幂等键，之后相同的键可以重新发起请求
+pub async fn release(user_id: UserId, key: &str) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let _: () = conn.del(record_key(user_id, key)).await?;
+    Ok(())
+}
+
+fn record_key(user_id: UserId, key: &str) -> String {
+    RedisKey::new("idempotency")
+        .add_field(user_id.to_string())
+        .add_field(key)
+        .into_inner()
+}
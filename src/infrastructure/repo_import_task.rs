@@ -0,0 +1,119 @@
+use crate::{
+    domain::{
+        file_system::{
+            import_job::{ImportJob, ImportJobId},
+            import_url::{ImportTask, ImportTaskId},
+        },
+        user::user::UserId,
+    },
+    redis_conn_switch::redis_conn,
+};
+use anyhow::Result;
+use redis::AsyncCommands;
+
+use super::RedisKey;
+
+pub async fn find(id: ImportTaskId) -> Result<Option<ImportTask>> {
+    let conn = &mut redis_conn().await?;
+    let task: Option<String> = conn.get(task_key(id)).await?;
+    let task = task.map(|t| serde_json::from_str(&t)).transpose()?;
+    Ok(task)
+}
+
+/// 下载中的任务保留 1 天，结束的任务保留 10 分钟供客户端查询结果
+pub async fn save(task: &ImportTask) -> Result<()> {
+    let ttl = if task.is_finished() {
+        60 * 10
+    } else {
+        60 * 60 * 24
+    };
+    let conn = &mut redis_conn().await?;
+    let value = serde_json::to_string(task)?;
+    let _: () = conn.set_ex(task_key(*task.id()), value, ttl).await?;
+    Ok(())
+}
+
+fn task_key(task_id: ImportTaskId) -> String {
+    let key = RedisKey::new("import-url-task");
+    key.add_field(task_id.to_string()).into_inner()
+}
+
+pub async fn find_job(id: ImportJobId) -> Result<Option<ImportJob>> {
+    let conn = &mut redis_conn().await?;
+    let job: Option<String> = conn.get(job_key(id)).await?;
+    let job = job.map(|j| serde_json::from_str(&j)).transpose()?;
+    Ok(job)
+}
+
+/// 与导入任务相同，结束后保留 10 分钟
+pub async fn save_job(job: &ImportJob) -> Result<()> {
+    let ttl = if job.is_finished() {
+        60 * 10
+    } else {
+        60 * 60 * 24
+    };
+    let conn = &mut redis_conn().await?;
+    let value = serde_json::to_string(job)?;
+    let _: () = conn.set_ex(job_key(*job.id()), value, ttl).await?;
+    Ok(())
+}
+
+fn job_key(job_id: ImportJobId) -> String {
+    let key = RedisKey::new("import-job");
+    key.add_field(job_id.to_string()).into_inner()
+}
+
+/// 保存 OAuth 授权时的 state，值为发起授权的用户和导入来源
+pub async fn save_oauth_state(state: &str, user_id: UserId, source: &str) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let value = format!("{}:{}", user_id, source);
+    let _: () = conn.set_ex(oauth_state_key(state), value, 60 * 10).await?;
+    Ok(())
+}
+
+/// 取出并删除 state，每个 state 只能使用一次
+pub async fn take_oauth_state(state: &str) -> Result<Option<(UserId, String)>> {
+    let conn = &mut redis_conn().await?;
+    let key = oauth_state_key(state);
+    let (value, _): (Option<String>, i64) = redis::pipe()
+        .atomic()
+        .get(&key)
+        .del(&key)
+        .query_async(conn)
+        .await?;
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let (user_id, source) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("bad oauth state: {}", value))?;
+    Ok(Some((user_id.parse()?, source.to_string())))
+}
+
+fn oauth_state_key(state: &str) -> String {
+    RedisKey::new("import-oauth-state")
+        .add_field(state)
+        .into_inner()
+}
+
+/// 保存外部服务的访问令牌，过期后需要用户重新授权
+pub async fn save_token(user_id: UserId, source: &str, token: &str, ttl_secs: usize) -> Result<()> {
+    let conn = &mut redis_conn().await?;
+    let _: () = conn
+        .set_ex(token_key(user_id, source), token, ttl_secs)
+        .await?;
+    Ok(())
+}
+
+pub async fn find_token(user_id: UserId, source: &str) -> Result<Option<String>> {
+    let conn = &mut redis_conn().await?;
+    let token = conn.get(token_key(user_id, source)).await?;
+    Ok(token)
+}
+
+fn token_key(user_id: UserId, source: &str) -> String {
+    RedisKey::new("import-token")
+        .add_field(user_id.to_string())
+        .add_field(source)
+        .into_inner()
+}
@@ -0,0 +1,26 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{domain::file_system::file::SysFileId, schema::file_integrity_mismatches};
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = file_integrity_mismatches)]
+pub struct MismatchPo<'a> {
+    pub sys_file_id: SysFileId,
+    pub expected_hash: Cow<'a, str>,
+    /// 为空表示文件已丢失
+    pub actual_hash: Option<Cow<'a, str>>,
+}
+
+pub async fn save_mismatch(mismatch: &MismatchPo<'_>) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    diesel::insert_into(file_integrity_mismatches::table)
+        .values(mismatch)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use diesel::{ExpressionMethods, Insertable, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::UserFileId,
+        notification::{Notification, NotificationId},
+        transcode_order::TranscodeTaskId,
+        user::user::UserId,
+    },
+    schema::notifications,
+};
+
+use super::repo_user_sequence;
+
+/// 通知在 user_sequences 中的序列名
+const SEQUENCE: &str = "notifications";
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = notifications)]
+pub struct NotificationPo<'a> {
+    pub id: NotificationId,
+    pub user_id: UserId,
+    pub kind: i16,
+    pub message: &'a str,
+    pub file_id: Option<UserFileId>,
+    pub task_id: Option<TranscodeTaskId>,
+    pub seq: i64,
+}
+
+/// 按用户取得递增的 seq，推送时按 seq 断点续传
+pub async fn save(notification: &Notification, conn: &mut PgConn) -> Result<()> {
+    let seq = repo_user_sequence::next(*notification.user_id(), SEQUENCE, conn).await?;
+    let po = NotificationPo {
+        id: *notification.id(),
+        user_id: *notification.user_id(),
+        kind: *notification.kind() as i16,
+        message: notification.message(),
+        file_id: *notification.file_id(),
+        task_id: *notification.task_id(),
+        seq,
+    };
+    diesel::insert_into(notifications::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 将通知标记为已读，ids 为空时标记该用户所有的通知
+pub async fn mark_read(
+    user_id: UserId,
+    ids: &[NotificationId],
+    conn: &mut PgConn,
+) -> Result<usize> {
+    let unread = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::read.eq(false));
+    let effected = if ids.is_empty() {
+        diesel::update(unread)
+            .set(notifications::read.eq(true))
+            .execute(conn)
+            .await?
+    } else {
+        diesel::update(unread.filter(notifications::id.eq_any(ids)))
+            .set(notifications::read.eq(true))
+            .execute(conn)
+            .await?
+    };
+    Ok(effected)
+}
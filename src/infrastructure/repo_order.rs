@@ -1,11 +1,14 @@
 use std::borrow::Cow;
 
 use crate::domain::file_system::file::{SysFileId, UserFileId};
-use crate::domain::transcode_order::{TranscocdeOrder, TranscodeOrderId, TranscodeTaskId};
+use crate::domain::transcode_order::{
+    metrics::QualityMetrics, worker::WorkerId, TranscocdeOrder, TranscodeOrderId, TranscodeTaskId,
+};
 use crate::domain::user::user::UserId;
-use crate::schema::{orders, transcode_tasks};
+use crate::schema::{orders, transcode_tasks, workers};
 
 use super::EffectedRow;
+use crate::LocalDataTime;
 use anyhow::Result;
 use diesel::prelude::Queryable;
 use diesel::prelude::*;
@@ -19,10 +22,12 @@ pub struct OrderPoWraper<'a> {
 
 #[derive(Queryable, Selectable, Insertable, AsChangeset, Identifiable, Debug)]
 #[diesel(table_name = orders)]
+#[diesel(treat_none_as_null = true)]
 pub struct OrderPo {
     pub id: TranscodeOrderId,
     pub user_id: UserId,
     pub status: i16,
+    pub start_after: Option<LocalDataTime>,
 }
 
 #[derive(Queryable, Selectable, Insertable, AsChangeset, Identifiable, Debug)]
@@ -37,6 +42,42 @@ pub struct TranscodeTaskPo<'a> {
     pub params: String,
     pub status: i16,
     pub err_msg: Option<Cow<'a, str>>,
+    pub credits: i64,
+}
+
+/// 超时检测时使用的任务信息
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = transcode_tasks)]
+pub struct RunningTaskPo {
+    pub id: TranscodeTaskId,
+    pub order_id: TranscodeOrderId,
+    pub user_id: UserId,
+    pub user_file_id: UserFileId,
+    pub sys_file_id: SysFileId,
+    pub virtual_path: String,
+    pub params: String,
+    pub started_at: LocalDataTime,
+    pub retries: i16,
+}
+
+/// 查看任务详情和日志时使用的任务信息
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = transcode_tasks)]
+pub struct TaskDetailPo {
+    pub id: TranscodeTaskId,
+    pub order_id: TranscodeOrderId,
+    pub user_id: UserId,
+    pub user_file_id: UserFileId,
+    pub sys_file_id: SysFileId,
+    pub virtual_path: String,
+    pub params: String,
+    pub status: i16,
+    pub err_msg: Option<String>,
+    pub retries: i16,
+    pub worker_id: Option<WorkerId>,
+    pub started_at: LocalDataTime,
+    pub create_at: LocalDataTime,
+    pub updated_at: LocalDataTime,
 }
 
 pub enum OrderStatus {
@@ -113,3 +154,280 @@ pub async fn update(order: &TranscocdeOrder, conn: &mut PgConn) -> Result<()> {
     }
     Ok(())
 }
+
+pub async fn find_order(
+    order_id: TranscodeOrderId,
+    conn: &mut PgConn,
+) -> Result<Option<TranscocdeOrder>> {
+    let order: Option<OrderPo> = orders::table
+        .find(order_id)
+        .select(OrderPo::as_select())
+        .for_update()
+        .first::<OrderPo>(conn)
+        .await
+        .optional()?;
+    let Some(order) = order else {
+        return Ok(None);
+    };
+
+    let tasks: Vec<TranscodeTaskPo> = transcode_tasks::table
+        .filter(transcode_tasks::order_id.eq(order_id))
+        .select(TranscodeTaskPo::as_select())
+        .load::<TranscodeTaskPo>(conn)
+        .await?;
+
+    let order = TranscocdeOrder::try_from_po(OrderPoWraper { order, tasks })?;
+    Ok(Some(order))
+}
+
+pub async fn find_task(
+    task_id: TranscodeTaskId,
+    conn: &mut PgConn,
+) -> Result<Option<TaskDetailPo>> {
+    let task = transcode_tasks::table
+        .find(task_id)
+        .select(TaskDetailPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(task)
+}
+
+/// 开始时间早于 started_before 且仍在转码中的任务，最早开始的排在前面
+pub async fn find_running(
+    started_before: LocalDataTime,
+    offset: i64,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<RunningTaskPo>> {
+    let tasks = transcode_tasks::table
+        // 0 表示转码中
+        .filter(transcode_tasks::status.eq(0))
+        .filter(transcode_tasks::started_at.lt(started_before))
+        .select(RunningTaskPo::as_select())
+        .order_by(transcode_tasks::started_at.asc())
+        .then_order_by(transcode_tasks::id.asc())
+        .offset(offset)
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(tasks)
+}
+
+/// 按 (started_at, id) 排序，返回 after 之后的转码中任务，用于遍历所有任务
+pub async fn find_running_after(
+    started_before: LocalDataTime,
+    after: Option<(LocalDataTime, TranscodeTaskId)>,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<RunningTaskPo>> {
+    let mut query = transcode_tasks::table
+        // 0 表示转码中
+        .filter(transcode_tasks::status.eq(0))
+        .filter(transcode_tasks::started_at.lt(started_before))
+        .select(RunningTaskPo::as_select())
+        .order_by(transcode_tasks::started_at.asc())
+        .then_order_by(transcode_tasks::id.asc())
+        .limit(limit)
+        .into_boxed();
+    if let Some((started_at, id)) = after {
+        query = query.filter(
+            transcode_tasks::started_at
+                .gt(started_at)
+                .or(transcode_tasks::started_at
+                    .eq(started_at)
+                    .and(transcode_tasks::id.gt(id))),
+        );
+    }
+    let tasks = query.load(conn).await?;
+    Ok(tasks)
+}
+
+/// 重新下发任务时重置开始时间并增加重试次数，任务已结束时返回 false
+pub async fn restart_task(task_id: TranscodeTaskId, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(
+        transcode_tasks::table
+            .filter(transcode_tasks::id.eq(task_id))
+            .filter(transcode_tasks::status.eq(0)),
+    )
+    .set((
+        transcode_tasks::started_at.eq(diesel::dsl::now),
+        transcode_tasks::retries.eq(transcode_tasks::retries + 1),
+        transcode_tasks::worker_id.eq(None::<WorkerId>),
+    ))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
+
+/// 用户未结束的任务数，包括排队中的，不包括未到预约时间的订单
+pub async fn count_processing_tasks(user_id: UserId, conn: &mut PgConn) -> Result<i64> {
+    let count = transcode_tasks::table
+        .inner_join(orders::table)
+        .filter(transcode_tasks::user_id.eq(user_id))
+        // 0 表示转码中
+        .filter(transcode_tasks::status.eq(0))
+        .filter(orders::status.eq(0))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+/// 预约时间已到的订单，最早到达的排在前面
+pub async fn find_due_scheduled(
+    now: LocalDataTime,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<TranscodeOrderId>> {
+    let order_ids = orders::table
+        // 4 表示已预约
+        .filter(orders::status.eq(4))
+        .filter(orders::start_after.le(now))
+        .select(orders::id)
+        .order_by(orders::start_after.asc())
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(order_ids)
+}
+
+/// 预约订单开始转码时重置任务的开始时间，避免等待的时间被计入超时
+pub async fn reset_started_at(order_id: TranscodeOrderId, conn: &mut PgConn) -> Result<()> {
+    diesel::update(
+        transcode_tasks::table
+            .filter(transcode_tasks::order_id.eq(order_id))
+            .filter(transcode_tasks::status.eq(0)),
+    )
+    .set(transcode_tasks::started_at.eq(diesel::dsl::now))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 保存转码结果的质量指标
+pub async fn save_metrics(
+    task_id: TranscodeTaskId,
+    metrics: &QualityMetrics,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(transcode_tasks::table.filter(transcode_tasks::id.eq(task_id)))
+        .set((
+            transcode_tasks::vmaf.eq(metrics.vmaf),
+            transcode_tasks::psnr.eq(metrics.psnr),
+            transcode_tasks::source_bitrate.eq(metrics.source_bitrate.map(|b| b as i64)),
+            transcode_tasks::output_bitrate.eq(metrics.output_bitrate.map(|b| b as i64)),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 记录转码结果的文件 hash
+pub async fn save_output_hash(
+    task_id: TranscodeTaskId,
+    hash: &str,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(transcode_tasks::table.filter(transcode_tasks::id.eq(task_id)))
+        .set(transcode_tasks::output_hash.eq(hash))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = transcode_tasks)]
+pub struct TaskOutputPo {
+    pub id: TranscodeTaskId,
+    pub user_id: UserId,
+    pub sys_file_id: SysFileId,
+    pub status: i16,
+    pub output_hash: Option<String>,
+}
+
+pub async fn find_task_output(
+    task_id: TranscodeTaskId,
+    conn: &mut PgConn,
+) -> Result<Option<TaskOutputPo>> {
+    let task = transcode_tasks::table
+        .find(task_id)
+        .select(TaskOutputPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(task)
+}
+
+/// 记录任务被分配到的节点，任务已结束时什么也不做
+pub async fn assign_worker(
+    task_id: TranscodeTaskId,
+    worker_id: WorkerId,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(
+        transcode_tasks::table
+            .filter(transcode_tasks::id.eq(task_id))
+            .filter(transcode_tasks::status.eq(0)),
+    )
+    .set(transcode_tasks::worker_id.eq(worker_id))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 任务被分配到的节点，由 av1-factory 执行时为空
+pub async fn worker_of(task_id: TranscodeTaskId, conn: &mut PgConn) -> Result<Option<WorkerId>> {
+    let worker_id = transcode_tasks::table
+        .find(task_id)
+        .select(transcode_tasks::worker_id)
+        .first::<Option<WorkerId>>(conn)
+        .await
+        .optional()?;
+    Ok(worker_id.flatten())
+}
+
+/// 仍在转码中，但所在节点在 alive_since 之后没有心跳或已重新注册的任务
+pub async fn find_orphaned(
+    alive_since: LocalDataTime,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<(TranscodeTaskId, WorkerId)>> {
+    let alive = workers::table
+        .filter(workers::last_heartbeat_at.ge(alive_since))
+        .select(workers::id.nullable());
+    let tasks = transcode_tasks::table
+        .filter(transcode_tasks::status.eq(0))
+        .filter(transcode_tasks::worker_id.is_not_null())
+        .filter(transcode_tasks::worker_id.ne_all(alive))
+        .select((
+            transcode_tasks::id,
+            transcode_tasks::worker_id.assume_not_null(),
+        ))
+        .order_by(transcode_tasks::started_at.asc())
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(tasks)
+}
+
+/// 节点下线后重新分配任务，重置开始时间但不计入重试次数，任务已结束时返回 false
+pub async fn reassign_task(
+    task_id: TranscodeTaskId,
+    worker_id: WorkerId,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let effected = diesel::update(
+        transcode_tasks::table
+            .filter(transcode_tasks::id.eq(task_id))
+            .filter(transcode_tasks::status.eq(0))
+            .filter(transcode_tasks::worker_id.eq(worker_id)),
+    )
+    .set((
+        transcode_tasks::started_at.eq(diesel::dsl::now),
+        transcode_tasks::worker_id.eq(None::<WorkerId>),
+    ))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
@@ -0,0 +1,185 @@
+use anyhow::Result;
+use diesel::{
+    prelude::Queryable, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        organization::{OrgId, OrgRole, Organization},
+        user::user::UserId,
+    },
+    schema::{org_members, organizations, users},
+};
+
+diesel::joinable!(org_members -> organizations (org_id));
+diesel::joinable!(org_members -> users (user_id));
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = organizations)]
+pub struct OrganizationPo {
+    pub id: OrgId,
+    pub name: String,
+    pub space_id: UserId,
+    pub storage_quota: Option<i64>,
+}
+
+impl OrganizationPo {
+    fn from_org(org: &Organization) -> Self {
+        Self {
+            id: *org.id(),
+            name: org.name().clone(),
+            space_id: *org.space_id(),
+            storage_quota: org.storage_quota().map(|q| q as i64),
+        }
+    }
+
+    fn into_org(self) -> Organization {
+        Organization::from_po(
+            self.id,
+            self.name,
+            self.space_id,
+            self.storage_quota.map(|q| q as u64),
+        )
+    }
+}
+
+pub async fn save(org: &Organization, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(organizations::table)
+        .values(OrganizationPo::from_org(org))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn find(org_id: OrgId, conn: &mut PgConn) -> Result<Option<Organization>> {
+    let po: Option<OrganizationPo> = organizations::table
+        .find(org_id)
+        .select(OrganizationPo::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(po.map(OrganizationPo::into_org))
+}
+
+/// 用户加入的所有组织及其角色
+pub async fn list_for_user(
+    user_id: UserId,
+    conn: &mut PgConn,
+) -> Result<Vec<(Organization, OrgRole)>> {
+    let rows: Vec<(OrganizationPo, i16)> = org_members::table
+        .inner_join(organizations::table)
+        .filter(org_members::user_id.eq(user_id))
+        .select((OrganizationPo::as_select(), org_members::role))
+        .order_by(org_members::create_at)
+        .load(conn)
+        .await?;
+    rows.into_iter()
+        .map(|(po, role)| Ok((po.into_org(), role.try_into()?)))
+        .collect()
+}
+
+/// 用户在组织中的角色，不是成员时为空
+pub async fn find_role(
+    org_id: OrgId,
+    user_id: UserId,
+    conn: &mut PgConn,
+) -> Result<Option<OrgRole>> {
+    let role: Option<i16> = org_members::table
+        .find((org_id, user_id))
+        .select(org_members::role)
+        .first(conn)
+        .await
+        .optional()?;
+    role.map(TryInto::try_into).transpose()
+}
+
+/// 组织的所有成员，返回用户 id、邮箱和角色
+pub async fn members(org_id: OrgId, conn: &mut PgConn) -> Result<Vec<(UserId, String, OrgRole)>> {
+    let rows: Vec<(UserId, String, i16)> = org_members::table
+        .inner_join(users::table)
+        .filter(org_members::org_id.eq(org_id))
+        .select((org_members::user_id, users::email, org_members::role))
+        .order_by((org_members::role, org_members::create_at))
+        .load(conn)
+        .await?;
+    rows.into_iter()
+        .map(|(user_id, email, role)| Ok((user_id, email, role.try_into()?)))
+        .collect()
+}
+
+/// 已是成员时什么也不做，返回是否新增
+pub async fn add_member(
+    org_id: OrgId,
+    user_id: UserId,
+    role: OrgRole,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let effected = diesel::insert_into(org_members::table)
+        .values((
+            org_members::org_id.eq(org_id),
+            org_members::user_id.eq(user_id),
+            org_members::role.eq(role as i16),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
+
+pub async fn set_role(
+    org_id: OrgId,
+    user_id: UserId,
+    role: OrgRole,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(org_members::table.find((org_id, user_id)))
+        .set(org_members::role.eq(role as i16))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn remove_member(org_id: OrgId, user_id: UserId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(org_members::table.find((org_id, user_id)))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 组织当前的所有者数量，修改成员时加锁，避免并发修改后组织没有所有者
+pub async fn owner_count(org_id: OrgId, conn: &mut PgConn) -> Result<usize> {
+    let owners: Vec<UserId> = org_members::table
+        .filter(org_members::org_id.eq(org_id))
+        .filter(org_members::role.eq(OrgRole::Owner as i16))
+        .select(org_members::user_id)
+        .for_update()
+        .load(conn)
+        .await?;
+    Ok(owners.len())
+}
+
+/// 空间账号所属组织的存储配额，外层的 None 表示不是组织空间
+pub async fn find_quota_by_space(
+    space_id: UserId,
+    conn: &mut PgConn,
+) -> Result<Option<Option<u64>>> {
+    let quota: Option<Option<i64>> = organizations::table
+        .filter(organizations::space_id.eq(space_id))
+        .select(organizations::storage_quota)
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(quota.map(|q| q.map(|q| q as u64)))
+}
+
+/// 返回组织是否存在
+pub async fn set_quota(org_id: OrgId, quota: Option<u64>, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(organizations::table.find(org_id))
+        .set(organizations::storage_quota.eq(quota.map(|q| q as i64)))
+        .execute(conn)
+        .await?;
+    Ok(effected > 0)
+}
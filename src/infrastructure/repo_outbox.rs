@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use diesel::{
+    BoolExpressionMethods, ExpressionMethods, Insertable, JoinOnDsl, NullableExpressionMethods,
+    QueryDsl, Queryable, Selectable, SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::user::user::UserId,
+    schema::{factory_outbox, transcode_tasks},
+    LocalDataTime,
+};
+
+#[derive(Clone, Copy, Debug)]
+#[repr(i16)]
+pub enum OutboxStatus {
+    Pending = 0,
+    Sent = 1,
+    GaveUp = 2,
+    /// 发送前任务已取消
+    Cancelled = 3,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = factory_outbox)]
+pub struct NewOutboxPo {
+    /// 转码任务或信息采集等请求的 id
+    pub task_id: i64,
+    pub payload: String,
+    /// 越大越先发送
+    pub priority: i16,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = factory_outbox)]
+pub struct OutboxPo {
+    pub id: i64,
+    pub task_id: i64,
+    pub payload: String,
+    pub attempts: i32,
+    pub next_attempt_at: LocalDataTime,
+}
+
+pub async fn save(messages: &[NewOutboxPo], conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(factory_outbox::table)
+        .values(messages)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 查询已到发送时间的消息，优先级高的先发送，同一优先级先写入的先发送。
+/// 转码请求同时返回任务所属的用户，任务已不在转码中的请求不再发送
+pub async fn due_messages(
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<(OutboxPo, Option<UserId>)>> {
+    let messages = factory_outbox::table
+        .left_join(transcode_tasks::table.on(transcode_tasks::id.eq(factory_outbox::task_id)))
+        .filter(factory_outbox::status.eq(OutboxStatus::Pending as i16))
+        .filter(factory_outbox::next_attempt_at.le(diesel::dsl::now))
+        // 0 表示转码中，信息采集等请求没有对应的任务
+        .filter(
+            transcode_tasks::status
+                .nullable()
+                .is_null()
+                .or(transcode_tasks::status.nullable().eq(0)),
+        )
+        .select((OutboxPo::as_select(), transcode_tasks::user_id.nullable()))
+        .order_by((factory_outbox::priority.desc(), factory_outbox::id.asc()))
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(messages)
+}
+
+/// 各用户已发送且仍在转码中的任务数
+pub async fn active_transcodes(
+    user_ids: &[UserId],
+    conn: &mut PgConn,
+) -> Result<HashMap<UserId, i64>> {
+    if user_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let counts: Vec<(UserId, i64)> = transcode_tasks::table
+        .inner_join(factory_outbox::table.on(factory_outbox::task_id.eq(transcode_tasks::id)))
+        .filter(transcode_tasks::user_id.eq_any(user_ids))
+        // 0 表示转码中
+        .filter(transcode_tasks::status.eq(0))
+        .filter(factory_outbox::status.eq(OutboxStatus::Sent as i16))
+        .group_by(transcode_tasks::user_id)
+        // 重新下发的任务有多条消息
+        .select((
+            transcode_tasks::user_id,
+            diesel::dsl::count_distinct(transcode_tasks::id),
+        ))
+        .load(conn)
+        .await?;
+    Ok(counts.into_iter().collect())
+}
+
+/// 取消任务还未发送的请求，返回被取消的任务 id。
+/// 正在发送的消息会被行锁阻塞到事务提交，之后认领失败，不会再发送
+pub async fn cancel(task_ids: &[i64], conn: &mut PgConn) -> Result<Vec<i64>> {
+    let cancelled = diesel::update(
+        factory_outbox::table
+            .filter(factory_outbox::task_id.eq_any(task_ids))
+            .filter(factory_outbox::status.eq(OutboxStatus::Pending as i16)),
+    )
+    .set(factory_outbox::status.eq(OutboxStatus::Cancelled as i16))
+    .returning(factory_outbox::task_id)
+    .get_results(conn)
+    .await?;
+    Ok(cancelled)
+}
+
+/// 推迟发送，不计入重试次数
+pub async fn defer(ids: &[i64], until: LocalDataTime, conn: &mut PgConn) -> Result<()> {
+    diesel::update(
+        factory_outbox::table
+            .filter(factory_outbox::id.eq_any(ids))
+            .filter(factory_outbox::status.eq(OutboxStatus::Pending as i16)),
+    )
+    .set(factory_outbox::next_attempt_at.eq(until))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 将消息的下次发送时间推迟到 lease_until，防止多个实例重复发送。
+/// 返回 false 表示已被其他实例认领，或任务已不在转码中
+pub async fn claim(
+    message: &OutboxPo,
+    lease_until: LocalDataTime,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let ended_task = transcode_tasks::table
+        .filter(transcode_tasks::id.eq(factory_outbox::task_id))
+        .filter(transcode_tasks::status.ne(0));
+    let effected = diesel::update(
+        factory_outbox::table
+            .filter(factory_outbox::id.eq(message.id))
+            .filter(factory_outbox::status.eq(OutboxStatus::Pending as i16))
+            .filter(factory_outbox::next_attempt_at.eq(message.next_attempt_at))
+            .filter(diesel::dsl::not(diesel::dsl::exists(ended_task))),
+    )
+    .set(factory_outbox::next_attempt_at.eq(lease_until))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
+
+pub async fn update(
+    id: i64,
+    status: OutboxStatus,
+    attempts: i32,
+    next_attempt_at: LocalDataTime,
+    last_error: Option<&str>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(factory_outbox::table.filter(factory_outbox::id.eq(id)))
+        .set((
+            factory_outbox::status.eq(status as i16),
+            factory_outbox::attempts.eq(attempts),
+            factory_outbox::next_attempt_at.eq(next_attempt_at),
+            factory_outbox::last_error.eq(last_error),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use diesel::{ExpressionMethods, Insertable, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::{FileNode, UserFileId, VirtualPath},
+        user::user::UserId,
+    },
+    schema::path_history,
+};
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = path_history)]
+struct PathHistoryPo<'a> {
+    user_id: UserId,
+    user_file_id: UserFileId,
+    old_path: &'a str,
+    new_path: &'a str,
+}
+
+/// 记录文件重命名或移动前的路径，node 为变更后的节点
+pub async fn insert(node: &FileNode, old_path: &VirtualPath, conn: &mut PgConn) -> Result<()> {
+    let po = PathHistoryPo {
+        user_id: *node.user_id(),
+        user_file_id: *node.id(),
+        old_path: &old_path.to_str(),
+        new_path: &node.path().to_str(),
+    };
+    diesel::insert_into(path_history::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 在 paths 中找到最长的、曾经被使用过的路径，返回它和最近一次使用它的文件
+pub async fn find_latest(
+    user_id: UserId,
+    paths: &[String],
+    conn: &mut PgConn,
+) -> Result<Option<(String, UserFileId)>> {
+    let found: Vec<(String, UserFileId)> = path_history::table
+        .filter(path_history::user_id.eq(user_id))
+        .filter(path_history::old_path.eq_any(paths))
+        .select((path_history::old_path, path_history::user_file_id))
+        .order_by(path_history::id.asc())
+        .load(conn)
+        .await?;
+    // 长度相同时 max_by_key 返回最后一个，即最近的记录
+    let longest = found.into_iter().max_by_key(|(path, _)| path.len());
+    Ok(longest)
+}
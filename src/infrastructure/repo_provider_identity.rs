@@ -0,0 +1,42 @@
+use anyhow::Result;
+use diesel::{ExpressionMethods, Insertable, OptionalExtension, QueryDsl};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{domain::user::user::UserId, schema::provider_identities};
+
+use super::EffectedRow;
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = provider_identities)]
+pub struct ProviderIdentityPo<'a> {
+    pub provider: &'a str,
+    pub subject: &'a str,
+    pub user_id: UserId,
+    pub email: Option<&'a str>,
+}
+
+/// 第三方账号关联的用户
+pub async fn find_user(provider: &str, subject: &str, conn: &mut PgConn) -> Result<Option<UserId>> {
+    let user_id = provider_identities::table
+        .filter(provider_identities::provider.eq(provider))
+        .filter(provider_identities::subject.eq(subject))
+        .select(provider_identities::user_id)
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(user_id)
+}
+
+/// 第三方账号已关联时不做修改
+pub async fn save(identity: &ProviderIdentityPo<'_>, conn: &mut PgConn) -> Result<EffectedRow> {
+    let effected = diesel::insert_into(provider_identities::table)
+        .values(identity)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(EffectedRow {
+        effected_row: effected,
+        expect_row: 1,
+    })
+}
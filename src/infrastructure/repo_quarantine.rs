@@ -0,0 +1,56 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use anyhow::Result;
+use diesel::{prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::pg_conn;
+
+use crate::schema::file_quarantines;
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = file_quarantines)]
+pub struct QuarantinePo<'a> {
+    pub hash: Cow<'a, str>,
+    pub scanner: Cow<'a, str>,
+    pub signature: Cow<'a, str>,
+}
+
+/// 相同内容重复扫描出问题时，更新为最近一次的结果
+pub async fn save(po: &QuarantinePo<'_>) -> Result<()> {
+    let conn = &mut pg_conn().await?;
+    diesel::insert_into(file_quarantines::table)
+        .values(po)
+        .on_conflict(file_quarantines::hash)
+        .do_update()
+        .set((
+            file_quarantines::scanner.eq(excluded(file_quarantines::scanner)),
+            file_quarantines::signature.eq(excluded(file_quarantines::signature)),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn is_quarantined(hash: &str) -> Result<bool> {
+    let conn = &mut pg_conn().await?;
+    let exists = diesel::select(diesel::dsl::exists(
+        file_quarantines::table.filter(file_quarantines::hash.eq(hash)),
+    ))
+    .get_result(conn)
+    .await?;
+    Ok(exists)
+}
+
+/// 返回给定 hash 中被隔离的部分
+pub async fn filter_quarantined(hashes: &[&str]) -> Result<HashSet<String>> {
+    if hashes.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let conn = &mut pg_conn().await?;
+    let found = file_quarantines::table
+        .filter(file_quarantines::hash.eq_any(hashes))
+        .select(file_quarantines::hash)
+        .load::<String>(conn)
+        .await?;
+    Ok(found.into_iter().collect())
+}
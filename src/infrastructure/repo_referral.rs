@@ -0,0 +1,128 @@
+use anyhow::Result;
+use diesel::{
+    dsl::count_star, BoolExpressionMethods, ExpressionMethods, Insertable, OptionalExtension,
+    QueryDsl, Queryable, Selectable, SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::user::{referral::ReferralCode, user::UserId},
+    schema::{referral_codes, referrals},
+    LocalDataTime,
+};
+
+use super::EffectedRow;
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = referral_codes)]
+pub struct ReferralCodePo {
+    pub code: String,
+    pub create_at: LocalDataTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = referrals)]
+pub struct ReferralPo<'a> {
+    pub invitee_id: UserId,
+    pub inviter_id: UserId,
+    pub code: &'a str,
+    pub inviter_credits: i64,
+    pub invitee_credits: i64,
+    pub bonus_quota: i64,
+}
+
+/// 邀请码已存在时不做修改
+pub async fn save_code(
+    user_id: UserId,
+    code: &ReferralCode,
+    conn: &mut PgConn,
+) -> Result<EffectedRow> {
+    let effected = diesel::insert_into(referral_codes::table)
+        .values((
+            referral_codes::code.eq(code.as_str()),
+            referral_codes::user_id.eq(user_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(EffectedRow {
+        effected_row: effected,
+        expect_row: 1,
+    })
+}
+
+pub async fn count_codes(user_id: UserId, conn: &mut PgConn) -> Result<i64> {
+    let count = referral_codes::table
+        .filter(referral_codes::user_id.eq(user_id))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+pub async fn find_codes(user_id: UserId, conn: &mut PgConn) -> Result<Vec<ReferralCodePo>> {
+    let codes = referral_codes::table
+        .filter(referral_codes::user_id.eq(user_id))
+        .order(referral_codes::create_at.desc())
+        .select(ReferralCodePo::as_select())
+        .load(conn)
+        .await?;
+    Ok(codes)
+}
+
+/// 用户的各个邀请码邀请的人数
+pub async fn count_by_code(user_id: UserId, conn: &mut PgConn) -> Result<Vec<(String, i64)>> {
+    let counts = referrals::table
+        .filter(referrals::inviter_id.eq(user_id))
+        .group_by(referrals::code)
+        .select((referrals::code, count_star()))
+        .load(conn)
+        .await?;
+    Ok(counts)
+}
+
+/// 邀请人在 since 之后获得了奖励的邀请数，since 为空时统计全部
+pub async fn count_rewarded(
+    inviter: UserId,
+    since: Option<LocalDataTime>,
+    conn: &mut PgConn,
+) -> Result<i64> {
+    let mut query = referrals::table
+        .filter(referrals::inviter_id.eq(inviter))
+        .filter(
+            referrals::inviter_credits
+                .gt(0)
+                .or(referrals::invitee_credits.gt(0))
+                .or(referrals::bonus_quota.gt(0)),
+        )
+        .into_boxed();
+    if let Some(since) = since {
+        query = query.filter(referrals::create_at.ge(since));
+    }
+    let count = query.count().get_result(conn).await?;
+    Ok(count)
+}
+
+pub async fn find_owner(code: &ReferralCode, conn: &mut PgConn) -> Result<Option<UserId>> {
+    let owner = referral_codes::table
+        .find(code.as_str())
+        .select(referral_codes::user_id)
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(owner)
+}
+
+/// 被邀请人已有邀请记录时不做修改
+pub async fn save(referral: &ReferralPo<'_>, conn: &mut PgConn) -> Result<EffectedRow> {
+    let effected = diesel::insert_into(referrals::table)
+        .values(referral)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(EffectedRow {
+        effected_row: effected,
+        expect_row: 1,
+    })
+}
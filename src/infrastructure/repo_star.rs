@@ -0,0 +1,31 @@
+use anyhow::Result;
+use diesel::ExpressionMethods;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    schema::starred_files,
+};
+
+/// 已收藏时什么也不做
+pub async fn star(user_id: UserId, file_id: UserFileId, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(starred_files::table)
+        .values((
+            starred_files::user_id.eq(user_id),
+            starred_files::user_file_id.eq(file_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn unstar(user_id: UserId, file_id: UserFileId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(starred_files::table)
+        .filter(starred_files::user_id.eq(user_id))
+        .filter(starred_files::user_file_id.eq(file_id))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use anyhow::Result;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::{pg_conn, PgConn};
+
+use crate::{
+    domain::{file_system::file::UserFileId, user::user::UserId},
+    schema::{subtitles, sys_files, user_files},
+};
+
+use super::EffectedRow;
+
+diesel::joinable!(subtitles -> user_files (subtitle_file_id));
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = subtitles)]
+pub struct SubtitlePo<'a> {
+    pub video_file_id: UserFileId,
+    pub subtitle_file_id: UserFileId,
+    pub user_id: UserId,
+    pub language: Option<Cow<'a, str>>,
+}
+
+/// 已关联到视频的字幕
+#[derive(Debug)]
+pub struct AttachedSubtitle {
+    pub subtitle_file_id: UserFileId,
+    pub file_name: String,
+    pub language: Option<String>,
+    pub archived_path: PathBuf,
+}
+
+/// 重复关联时更新字幕语言
+pub async fn save(subtitle: &SubtitlePo<'_>, conn: &mut PgConn) -> Result<EffectedRow> {
+    let effected = diesel::insert_into(subtitles::table)
+        .values(subtitle)
+        .on_conflict((subtitles::video_file_id, subtitles::subtitle_file_id))
+        .do_update()
+        .set(subtitles::language.eq(&subtitle.language))
+        .execute(conn)
+        .await?;
+
+    Ok(EffectedRow {
+        effected_row: effected,
+        expect_row: 1,
+    })
+}
+
+/// 获取视频关联的所有未删除的字幕
+pub async fn find_attached(video_file_id: UserFileId) -> Result<Vec<AttachedSubtitle>> {
+    let conn = &mut pg_conn().await?;
+    let subtitles = subtitles::table
+        .inner_join(user_files::table.inner_join(sys_files::table))
+        .filter(subtitles::video_file_id.eq(video_file_id))
+        .filter(user_files::deleted.eq(false))
+        .select((
+            subtitles::subtitle_file_id,
+            user_files::file_name,
+            subtitles::language,
+            sys_files::path,
+        ))
+        .load::<(UserFileId, String, Option<String>, String)>(conn)
+        .await?;
+
+    let subtitles = subtitles
+        .into_iter()
+        .map(
+            |(subtitle_file_id, file_name, language, path)| AttachedSubtitle {
+                subtitle_file_id,
+                file_name,
+                language,
+                archived_path: PathBuf::from(path),
+            },
+        )
+        .collect();
+    Ok(subtitles)
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use diesel::{
+    dsl::{exists, not},
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::{
+            file::UserFileId,
+            tag::{Tag, TagId},
+        },
+        user::user::UserId,
+    },
+    schema::{file_tags, tags},
+};
+
+diesel::joinable!(file_tags -> tags (tag_id));
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = tags)]
+struct TagPo<'a> {
+    id: TagId,
+    user_id: UserId,
+    name: &'a str,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = tags)]
+struct TagOwnedPo {
+    id: TagId,
+    user_id: UserId,
+    name: String,
+}
+
+impl TagOwnedPo {
+    fn into_do(self) -> Tag {
+        Tag::from_raw(self.id, self.user_id, self.name)
+    }
+}
+
+pub async fn find_by_name(user_id: UserId, name: &str, conn: &mut PgConn) -> Result<Option<Tag>> {
+    let tag = tags::table
+        .filter(tags::user_id.eq(user_id))
+        .filter(tags::name.eq(name))
+        .select(TagOwnedPo::as_select())
+        .get_result::<TagOwnedPo>(conn)
+        .await
+        .optional()?;
+    Ok(tag.map(TagOwnedPo::into_do))
+}
+
+/// 同名标签已存在时什么也不做，返回是否新建了标签
+pub async fn save(tag: &Tag, conn: &mut PgConn) -> Result<bool> {
+    let po = TagPo {
+        id: *tag.id(),
+        user_id: *tag.user_id(),
+        name: tag.name(),
+    };
+    let effected = diesel::insert_into(tags::table)
+        .values(po)
+        .on_conflict((tags::user_id, tags::name))
+        .do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+pub async fn count_attached(file_id: UserFileId, conn: &mut PgConn) -> Result<usize> {
+    let count: i64 = file_tags::table
+        .filter(file_tags::user_file_id.eq(file_id))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count as usize)
+}
+
+/// 返回是否新添加了标签，已添加过时返回 false
+pub async fn attach(file_id: UserFileId, tag_id: TagId, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::insert_into(file_tags::table)
+        .values((
+            file_tags::user_file_id.eq(file_id),
+            file_tags::tag_id.eq(tag_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+pub async fn detach(file_id: UserFileId, tag_id: TagId, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::delete(file_tags::table)
+        .filter(file_tags::user_file_id.eq(file_id))
+        .filter(file_tags::tag_id.eq(tag_id))
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+/// 标签不再被任何文件使用时删除它
+pub async fn delete_if_unused(tag_id: TagId, conn: &mut PgConn) -> Result<()> {
+    diesel::delete(tags::table)
+        .filter(tags::id.eq(tag_id))
+        .filter(not(exists(
+            file_tags::table.filter(file_tags::tag_id.eq(tag_id)),
+        )))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
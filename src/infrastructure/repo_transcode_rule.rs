@@ -0,0 +1,155 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use diesel::{
+    AsChangeset, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::{
+        file_system::file::{SysFileId, UserFileId},
+        transcode_order::rule::{TranscodeRule, TranscodeRuleId},
+        user::user::UserId,
+    },
+    schema::{transcode_rule_matches, transcode_rules},
+};
+
+use super::EffectedRow;
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Debug)]
+#[diesel(table_name = transcode_rules)]
+pub struct TranscodeRulePo<'a> {
+    pub id: TranscodeRuleId,
+    pub user_id: UserId,
+    pub name: Cow<'a, str>,
+    pub watch_dir: Cow<'a, str>,
+    pub recursive: bool,
+    pub enabled: bool,
+    pub params: Cow<'a, str>,
+}
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = transcode_rule_matches)]
+pub struct RuleMatchPo {
+    pub rule_id: TranscodeRuleId,
+    pub user_file_id: UserFileId,
+    pub sys_file_id: SysFileId,
+}
+
+fn to_po(rule: &TranscodeRule) -> TranscodeRulePo {
+    TranscodeRulePo {
+        id: *rule.id(),
+        user_id: *rule.user_id(),
+        name: Cow::Borrowed(rule.name()),
+        watch_dir: rule.watch_dir().to_str(),
+        recursive: *rule.recursive(),
+        enabled: *rule.enabled(),
+        params: Cow::Borrowed(rule.params()),
+    }
+}
+
+fn from_po(po: TranscodeRulePo) -> TranscodeRule {
+    TranscodeRule::from_raw(
+        po.id,
+        po.user_id,
+        po.name.into_owned(),
+        &po.watch_dir,
+        po.recursive,
+        po.enabled,
+        po.params.into_owned(),
+    )
+}
+
+pub async fn save(rule: &TranscodeRule, conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(transcode_rules::table)
+        .values(&to_po(rule))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn update(rule: &TranscodeRule, conn: &mut PgConn) -> Result<()> {
+    let po = to_po(rule);
+    diesel::update(transcode_rules::table.find(po.id))
+        .set(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn count(user_id: UserId, conn: &mut PgConn) -> Result<i64> {
+    let count = transcode_rules::table
+        .filter(transcode_rules::user_id.eq(user_id))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+pub async fn find_by_user(user_id: UserId, conn: &mut PgConn) -> Result<Vec<TranscodeRule>> {
+    let rules: Vec<TranscodeRulePo> = transcode_rules::table
+        .filter(transcode_rules::user_id.eq(user_id))
+        .select(TranscodeRulePo::as_select())
+        .order_by(transcode_rules::create_at.asc())
+        .load(conn)
+        .await?;
+    Ok(rules.into_iter().map(from_po).collect())
+}
+
+pub async fn find(id: TranscodeRuleId, conn: &mut PgConn) -> Result<Option<TranscodeRule>> {
+    let po: Option<TranscodeRulePo> = transcode_rules::table
+        .find(id)
+        .select(TranscodeRulePo::as_select())
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(po.map(from_po))
+}
+
+pub async fn delete(
+    user_id: UserId,
+    id: TranscodeRuleId,
+    conn: &mut PgConn,
+) -> Result<EffectedRow> {
+    let effected = diesel::delete(
+        transcode_rules::table
+            .filter(transcode_rules::id.eq(id))
+            .filter(transcode_rules::user_id.eq(user_id)),
+    )
+    .execute(conn)
+    .await?;
+    diesel::delete(transcode_rule_matches::table.filter(transcode_rule_matches::rule_id.eq(id)))
+        .execute(conn)
+        .await?;
+    Ok(EffectedRow {
+        expect_row: 1,
+        effected_row: effected,
+    })
+}
+
+/// 记录匹配了规则的文件，重复上传时忽略
+pub async fn save_matches(matches: &[RuleMatchPo], conn: &mut PgConn) -> Result<()> {
+    if matches.is_empty() {
+        return Ok(());
+    }
+    diesel::insert_into(transcode_rule_matches::table)
+        .values(matches)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 取出并删除等待这个文件解析完成的匹配记录
+pub async fn take_matches(sys_file_id: SysFileId, conn: &mut PgConn) -> Result<Vec<RuleMatchPo>> {
+    let matches = diesel::delete(
+        transcode_rule_matches::table.filter(transcode_rule_matches::sys_file_id.eq(sys_file_id)),
+    )
+    .returning(RuleMatchPo::as_returning())
+    .get_results(conn)
+    .await?;
+    Ok(matches)
+}
@@ -1,9 +1,15 @@
+use std::time::Duration;
+
 use crate::{
     domain::file_system::service_upload::{UploadTask, UploadTaskId},
+    log_if_err,
     redis_conn_switch::redis_conn,
 };
 use anyhow::Result;
+use rand::{thread_rng, Rng};
 use redis::AsyncCommands;
+use tokio::task::JoinHandle;
+use tracing::warn;
 
 use super::RedisKey;
 
@@ -21,6 +27,30 @@ pub async fn save(task: &UploadTask) -> Result<()> {
     Ok(())
 }
 
+/// 所有未过期的任务，包括已完成的，最多返回 limit 个
+pub(crate) async fn list_all(limit: usize) -> Result<Vec<UploadTask>> {
+    let conn = &mut redis_conn().await?;
+    let pattern = RedisKey::new("uploading-task").add_field("*").into_inner();
+    let mut keys = vec![];
+    {
+        let mut iter: redis::AsyncIter<String> = conn.scan_match(&pattern).await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+            if keys.len() >= limit {
+                break;
+            }
+        }
+    }
+
+    let mut tasks = vec![];
+    for key in keys {
+        // 扫描后可能已过期
+        let task: Option<UploadTask> = conn.get(&key).await?;
+        tasks.extend(task);
+    }
+    Ok(tasks)
+}
+
 pub(crate) async fn update(task: &UploadTask) -> Result<()> {
     if task.is_completed() {
         // set ttl for task
@@ -45,6 +75,109 @@ fn task_key(task_id: UploadTaskId) -> String {
     key.add_field(task_id.to_string()).into_inner()
 }
 
+/// 正在完成上传的标记，防止客户端重试时重复合并。
+/// 持有期间定时续期，drop 时删除；进程退出后在租约到期时失效，客户端重试即可重新合并
+pub struct MergingMarker {
+    key: String,
+    token: String,
+    keepalive: JoinHandle<()>,
+}
+
+/// 标记任务正在合并，已被标记时返回 None
+pub(crate) async fn mark_merging(
+    task_id: UploadTaskId,
+    lease_secs: u64,
+) -> Result<Option<MergingMarker>> {
+    let key = merging_key(task_id);
+    let token = hex::encode(thread_rng().gen::<[u8; 16]>());
+    let conn = &mut redis_conn().await?;
+    let set_ok: bool = redis::cmd("set")
+        .arg(&key)
+        .arg(&token)
+        .arg(&["EX", &lease_secs.to_string(), "NX"])
+        .query_async(conn)
+        .await?;
+    if !set_ok {
+        return Ok(None);
+    }
+
+    let keepalive = tokio::spawn({
+        let key = key.clone();
+        let token = token.clone();
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs((lease_secs / 3).max(1)));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match refresh_merging(&key, &token, lease_secs).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!(key, "merging marker lost, stop refreshing");
+                        break;
+                    }
+                    Err(err) => warn!(?err, "refresh merging marker failed"),
+                }
+            }
+        }
+    });
+    Ok(Some(MergingMarker {
+        key,
+        token,
+        keepalive,
+    }))
+}
+
+/// 标记仍属于自己时续期，否则返回 false
+async fn refresh_merging(key: &str, token: &str, lease_secs: u64) -> Result<bool> {
+    const SCRIPT: &str = r#"
+        if redis.call("get", KEYS[1]) == ARGV[1] then
+            return redis.call("expire", KEYS[1], ARGV[2])
+        end
+        return 0
+    "#;
+    let conn = &mut redis_conn().await?;
+    let refreshed: bool = redis::Script::new(SCRIPT)
+        .key(key)
+        .arg(token)
+        .arg(lease_secs)
+        .invoke_async(conn)
+        .await?;
+    Ok(refreshed)
+}
+
+impl Drop for MergingMarker {
+    fn drop(&mut self) {
+        self.keepalive.abort();
+        let key = std::mem::take(&mut self.key);
+        let token = std::mem::take(&mut self.token);
+        tokio::spawn(async move {
+            log_if_err!(unmark_merging(&key, &token).await);
+        });
+    }
+}
+
+/// 只删除自己的标记，租约到期后标记可能已属于其他请求
+async fn unmark_merging(key: &str, token: &str) -> Result<()> {
+    const SCRIPT: &str = r#"
+        if redis.call("get", KEYS[1]) == ARGV[1] then
+            return redis.call("del", KEYS[1])
+        end
+        return 0
+    "#;
+    let conn = &mut redis_conn().await?;
+    let _: i64 = redis::Script::new(SCRIPT)
+        .key(key)
+        .arg(token)
+        .invoke_async(conn)
+        .await?;
+    Ok(())
+}
+
+fn merging_key(task_id: UploadTaskId) -> String {
+    let key = RedisKey::new("merging-upload-task");
+    key.add_field(task_id.to_string()).into_inner()
+}
+
 mod impl_ {
     use redis::{FromRedisValue, RedisError, RedisWrite, ToRedisArgs};
 
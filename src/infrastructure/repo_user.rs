@@ -32,6 +32,10 @@ pub struct UserPo<'a> {
     pub address: Option<Cow<'a, str>>,
     pub last_login: LocalDataTime,
     pub online: bool,
+    #[serde(default)]
+    pub order_email_opt_out: bool,
+    #[serde(default)]
+    pub max_file_versions: Option<i32>,
 }
 
 pub(crate) async fn save(user: &User, conn: &mut PgConn) -> Result<EffectedRow> {
@@ -78,6 +82,8 @@ impl<'a> UserPo<'a> {
             address: user.address().as_ref().map(|a| Cow::Borrowed(&**a)),
             last_login: *user.login_at(),
             online: *user.online(),
+            order_email_opt_out: *user.order_email_opt_out(),
+            max_file_versions: user.max_file_versions().as_ref().map(|m| *m as i32),
         }
     }
 }
@@ -186,3 +192,100 @@ where
         }
     }
 }
+
+/// 用户等级的原始值，用户不存在时为空
+pub(crate) async fn find_level(id: UserId, conn: &mut PgConn) -> Result<Option<i16>> {
+    let level = users::table
+        .find(id)
+        .select(users::level)
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(level)
+}
+
+/// 返回 false 表示用户不存在
+pub(crate) async fn set_level(id: UserId, level: i16, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(users::table.find(id))
+        .set(users::level.eq(level))
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+/// 邀请奖励的存储配额，用户不存在时为 0
+pub(crate) async fn find_bonus_quota(id: UserId, conn: &mut PgConn) -> Result<u64> {
+    let quota: Option<i64> = users::table
+        .find(id)
+        .select(users::bonus_quota)
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(quota.unwrap_or(0) as u64)
+}
+
+/// 锁住用户这一行，用于串行化同一用户相关的计数和写入
+pub(crate) async fn lock(id: UserId, conn: &mut PgConn) -> Result<()> {
+    users::table
+        .find(id)
+        .select(users::id)
+        .for_update()
+        .get_result::<UserId>(conn)
+        .await
+        .optional()?;
+    Ok(())
+}
+
+pub(crate) async fn add_bonus_quota(id: UserId, bytes: u64, conn: &mut PgConn) -> Result<()> {
+    diesel::update(users::table.find(id))
+        .set(users::bonus_quota.eq(users::bonus_quota + bytes as i64))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 头像文件的 hash，用户不存在或没有头像时为空
+pub(crate) async fn find_avatar(id: UserId, conn: &mut PgConn) -> Result<Option<String>> {
+    let avatar = users::table
+        .find(id)
+        .select(users::avatar)
+        .get_result::<Option<String>>(conn)
+        .await
+        .optional()?;
+    Ok(avatar.flatten())
+}
+
+/// 返回 false 表示用户不存在
+pub(crate) async fn set_avatar(id: UserId, hash: Option<&str>, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(users::table.find(id))
+        .set(users::avatar.eq(hash))
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+/// 是否有用户使用这个文件作为头像
+pub(crate) async fn is_avatar(hash: &str, conn: &mut PgConn) -> Result<bool> {
+    let exist = diesel::select(diesel::dsl::exists(
+        users::table.filter(users::avatar.eq(hash)),
+    ))
+    .get_result(conn)
+    .await?;
+    Ok(exist)
+}
+
+fn upload_count_key(id: UserId, window: i64) -> String {
+    format!("user:{}:uploads:{}", id, window)
+}
+
+/// 在当前的时间窗口内记录一次上传，返回窗口内的上传次数
+pub(crate) async fn incr_upload_count(id: UserId, window_secs: u64) -> Result<u64> {
+    let window = chrono::Local::now().timestamp() / window_secs as i64;
+    let key = upload_count_key(id, window);
+    let conn = &mut redis_conn().await?;
+    let count: u64 = conn.incr(&key, 1).await?;
+    if count == 1 {
+        let _: () = conn.expire(&key, window_secs as usize).await?;
+    }
+    Ok(count)
+}
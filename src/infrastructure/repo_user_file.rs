@@ -1,4 +1,4 @@
-use std::{borrow::Cow, time::Duration};
+use std::{borrow::Cow, collections::HashSet, time::Duration};
 
 use crate::{
     application::file_system::video_info::{AudioInfo, MediaInfo, VideoInfo},
@@ -9,21 +9,24 @@ use crate::{
         },
         user::user::UserId,
     },
-    pg_exist,
-    schema::{sys_files, user_files},
+    log_if_err, pg_exist,
+    schema::{file_integrity_mismatches, sys_files, user_files, users},
+    LocalDataTime,
 };
 use anyhow::{ensure, Result};
+use chrono::Local;
 use derive_more::From;
 use diesel::{
     prelude::{Identifiable, Insertable, Queryable},
     result::OptionalExtension,
-    AsChangeset, ExpressionMethods, QueryDsl, Selectable, SelectableHelper,
+    sql_types::BigInt,
+    AsChangeset, ExpressionMethods, QueryDsl, QueryableByName, Selectable, SelectableHelper,
 };
 use diesel_async::RunQueryDsl;
 use serde::{Deserialize, Serialize};
-use utils::db_pools::postgres::{pg_conn, PgConn};
+use utils::db_pools::postgres::{pg_conn, pg_conn_read, PgConn};
 
-use super::EffectedRow;
+use super::{repo_dir_cache, repo_dir_usage, EffectedRow};
 
 diesel::joinable!(user_files -> sys_files (sys_file_id));
 
@@ -38,6 +41,9 @@ pub struct UserFilePo<'a> {
     pub file_name: Cow<'a, str>,
     pub is_dir: bool,
     pub deleted: bool,
+    pub create_at: LocalDataTime,
+    /// 更新时写回读出的值，由触发器设置为当前时间
+    pub updated_at: LocalDataTime,
 }
 
 #[derive(
@@ -49,6 +55,7 @@ pub struct SysFilePo<'a> {
     pub size: i64,
     pub hash: Cow<'a, str>,
     pub path: Cow<'a, str>,
+    pub mime: Option<Cow<'a, str>>,
 }
 
 pub struct FileNodePo<'a> {
@@ -76,6 +83,7 @@ pub struct UserDirPo<'a> {
     pub children: Vec<UserDirPo<'a>>,
 }
 
+/// 查找并锁定节点，只在修改数据的事务中使用。只读的查询使用 find_node_for_read
 pub async fn find_node<'a, T>(id: T, conn: &mut PgConn) -> Result<Option<FileNode>>
 where
     PgUserFileId<'a>: From<T>,
@@ -83,8 +91,18 @@ where
     load_tree(id, 1, conn).await
 }
 
+/// 不加锁，可以使用只读连接
+pub async fn find_node_for_read<'a, T>(id: T, conn: &mut PgConn) -> Result<Option<FileNode>>
+where
+    PgUserFileId<'a>: From<T>,
+{
+    load_tree_for_read(id, 1, conn).await
+}
+
+/// lock 为 true 时用 FOR UPDATE 锁定根节点
 async fn find_node_inner<'a, 'b, 'c, T>(
     id: T,
+    lock: bool,
     conn: &'b mut PgConn,
 ) -> Result<Option<FileNodePo<'c>>>
 where
@@ -92,14 +110,15 @@ where
 {
     macro_rules! get_result {
         ($($filter:expr),+ $(,)?) => {{
-            let file = user_files::table
+            let query = user_files::table
                     $(.filter($filter))+
                     .filter(user_files::deleted.eq(false))
-                    .select(UserFilePo::as_select())
-                    .for_update()
-                    .get_result::<UserFilePo>(conn)
-                    .await
-                    .optional()?;
+                    .select(UserFilePo::as_select());
+            let file = if lock {
+                query.for_update().get_result::<UserFilePo>(conn).await.optional()?
+            } else {
+                query.get_result::<UserFilePo>(conn).await.optional()?
+            };
             let Some(file) = file else {
                 return Ok(None);
             };
@@ -137,12 +156,53 @@ where
     }
 }
 
+#[derive(QueryableByName, Debug)]
+#[diesel(table_name = user_files)]
+pub struct AncestorPo {
+    pub id: UserFileId,
+    pub file_name: String,
+}
+
+/// 文件的所有上级目录，从根目录开始排列，不包括文件自身
+pub async fn ancestors(file_id: UserFileId, conn: &mut PgConn) -> Result<Vec<AncestorPo>> {
+    let ancestors = diesel::sql_query(
+        r#"
+        WITH RECURSIVE ancestors(id, parent_id, file_name, depth) AS (
+            SELECT p.id, p.parent_id, p.file_name, 1 FROM user_files f
+            JOIN user_files p ON p.id = f.parent_id
+            WHERE f.id = $1
+            UNION ALL
+            SELECT p.id, p.parent_id, p.file_name, a.depth + 1 FROM user_files p
+            JOIN ancestors a ON p.id = a.parent_id
+        )
+        SELECT id, file_name FROM ancestors ORDER BY depth DESC
+        "#,
+    )
+    .bind::<BigInt, _>(file_id)
+    .load::<AncestorPo>(conn)
+    .await?;
+    Ok(ancestors)
+}
+
+/// 按 hash 查找可以复用的系统文件，同时更新它的 updated_at，回收任务在宽限期内不会删除刚被复用的文件。
+/// 回收任务正在删除这个文件时会等待删除完成，之后返回空，调用方重新归档
 pub async fn get_filenode_data(hash: &str) -> Result<Option<FileNodeMetaData>> {
+    let conn = &mut pg_conn().await?;
+    let file = diesel::update(sys_files::table.filter(sys_files::hash.eq(hash)))
+        .set(sys_files::updated_at.eq(Local::now()))
+        .returning(SysFilePo::as_returning())
+        .get_result::<SysFilePo>(conn)
+        .await
+        .optional()?;
+    let file = file.map(FileNodeConverter::sys_file_po_to_do);
+    Ok(file)
+}
+
+pub async fn find_sys_file(id: SysFileId) -> Result<Option<FileNodeMetaData>> {
     let conn = &mut pg_conn().await?;
     let file = sys_files::table
-        .filter(sys_files::hash.eq(hash))
+        .find(id)
         .select(SysFilePo::as_select())
-        .for_update()
         .get_result::<SysFilePo>(conn)
         .await
         .optional()?;
@@ -150,6 +210,110 @@ pub async fn get_filenode_data(hash: &str) -> Result<Option<FileNodeMetaData>> {
     Ok(file)
 }
 
+/// 随机抽取一批系统文件
+/// 按 id 批量获取系统文件
+pub(crate) async fn find_sys_files(
+    ids: &[SysFileId],
+    conn: &mut PgConn,
+) -> Result<Vec<FileNodeMetaData>> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let files = sys_files::table
+        .filter(sys_files::id.eq_any(ids))
+        .select(SysFilePo::as_select())
+        .load::<SysFilePo>(conn)
+        .await?;
+    let files = files
+        .into_iter()
+        .map(FileNodeConverter::sys_file_po_to_do)
+        .collect();
+    Ok(files)
+}
+
+/// 有家目录记录的用户
+pub(crate) async fn home_owners(conn: &mut PgConn) -> Result<Vec<UserId>> {
+    let owners = user_files::table
+        .filter(user_files::parent_id.is_null())
+        .filter(user_files::deleted.eq(false))
+        .select(user_files::user_id)
+        .distinct()
+        .load(conn)
+        .await?;
+    Ok(owners)
+}
+
+/// 用户所有未删除的文件和目录，不组成树，目录的 children 为空
+pub(crate) async fn load_user_entries(user_id: UserId, conn: &mut PgConn) -> Result<Vec<FileNode>> {
+    let files: Vec<UserFilePo> = user_files::table
+        .filter(user_files::user_id.eq(user_id))
+        .filter(user_files::deleted.eq(false))
+        .select(UserFilePo::as_select())
+        .load(conn)
+        .await?;
+    files
+        .into_iter()
+        .map(|user_file| {
+            let file_type = match user_file.sys_file_id {
+                Some(id) if !user_file.is_dir => FileTypePo::LazyFile(id),
+                _ => FileTypePo::Dir(vec![]),
+            };
+            FileNodeConverter::po_to_do(FileNodePo {
+                user_file,
+                file_type,
+            })
+        })
+        .collect()
+}
+
+pub async fn sample_sys_files(count: i64) -> Result<Vec<FileNodeMetaData>> {
+    let conn = &mut pg_conn().await?;
+    let files = sys_files::table
+        .select(SysFilePo::as_select())
+        .order(diesel::dsl::sql::<diesel::sql_types::Double>("RANDOM()"))
+        .limit(count)
+        .load::<SysFilePo>(conn)
+        .await?;
+    let files = files
+        .into_iter()
+        .map(FileNodeConverter::sys_file_po_to_do)
+        .collect();
+    Ok(files)
+}
+
+/// 按 id 顺序分批获取未解析过视频信息的系统文件
+pub async fn find_unparsed_sys_files(
+    after: Option<SysFileId>,
+    limit: i64,
+) -> Result<Vec<FileNodeMetaData>> {
+    let conn = &mut pg_conn().await?;
+    let mut sql = sys_files::table
+        .filter(sys_files::is_video.is_null())
+        .select(SysFilePo::as_select())
+        .order(sys_files::id)
+        .limit(limit)
+        .into_boxed();
+    if let Some(after) = after {
+        sql = sql.filter(sys_files::id.gt(after));
+    }
+    let files = sql.load::<SysFilePo>(conn).await?;
+    let files = files
+        .into_iter()
+        .map(FileNodeConverter::sys_file_po_to_do)
+        .collect();
+    Ok(files)
+}
+
+pub async fn count_unparsed_sys_files() -> Result<i64> {
+    let conn = &mut pg_conn().await?;
+    let count = sys_files::table
+        .filter(sys_files::is_video.is_null())
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
 pub async fn save_node(node: &FileNode, conn: &mut PgConn) -> Result<EffectedRow> {
     let file_po = FileNodeConverter::do_to_po(node);
     let (u_files, s_files) = file_po.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
@@ -170,6 +334,8 @@ pub async fn save_node(node: &FileNode, conn: &mut PgConn) -> Result<EffectedRow
         .do_nothing()
         .execute(conn)
         .await?;
+    repo_dir_usage::invalidate_ancestors(&[*node.id()], conn).await?;
+    log_if_err!(repo_dir_cache::invalidate(*node.user_id()).await);
 
     Ok(EffectedRow {
         effected_row: effected,
@@ -177,6 +343,28 @@ pub async fn save_node(node: &FileNode, conn: &mut PgConn) -> Result<EffectedRow
     })
 }
 
+/// 文件内容被覆盖后，保存新的系统文件并更新用户文件的引用
+pub(crate) async fn update_file_data(node: &FileNode, conn: &mut PgConn) -> Result<()> {
+    repo_dir_usage::invalidate_ancestors(&[*node.id()], conn).await?;
+    log_if_err!(repo_dir_cache::invalidate(*node.user_id()).await);
+    for (u_file, s_file) in FileNodeConverter::do_to_po(node) {
+        if let Some(s_file) = s_file {
+            diesel::insert_into(sys_files::table)
+                .values(&s_file)
+                .on_conflict(sys_files::hash)
+                .do_nothing()
+                .execute(conn)
+                .await?;
+        }
+        diesel::update(user_files::table)
+            .filter(user_files::id.eq(u_file.id))
+            .set(user_files::sys_file_id.eq(u_file.sys_file_id))
+            .execute(conn)
+            .await?;
+    }
+    Ok(())
+}
+
 #[derive(From, Debug)]
 pub enum ExistedId<'a> {
     Id(UserFileId),
@@ -214,6 +402,16 @@ where
     load_tree(root_id, u32::MAX, conn).await
 }
 
+pub async fn load_tree_all_for_read<'a, T>(
+    root_id: T,
+    conn: &mut PgConn,
+) -> Result<Option<FileNode>>
+where
+    PgUserFileId<'a>: From<T>,
+{
+    load_tree_for_read(root_id, u32::MAX, conn).await
+}
+
 pub async fn load_tree_dep2<'a, T>(root_id: T, conn: &mut PgConn) -> Result<Option<FileNode>>
 where
     PgUserFileId<'a>: From<T>,
@@ -221,7 +419,42 @@ where
     load_tree(root_id, 2, conn).await
 }
 
+pub async fn load_tree_dep2_for_read<'a, T>(
+    root_id: T,
+    conn: &mut PgConn,
+) -> Result<Option<FileNode>>
+where
+    PgUserFileId<'a>: From<T>,
+{
+    load_tree_for_read(root_id, 2, conn).await
+}
+
+/// 加载并锁定根节点，只在修改数据的事务中使用
 pub async fn load_tree<'a, T>(root_id: T, depth: u32, conn: &mut PgConn) -> Result<Option<FileNode>>
+where
+    PgUserFileId<'a>: From<T>,
+{
+    load_tree_inner(root_id, depth, true, conn).await
+}
+
+/// 不加锁，可以使用只读连接
+pub async fn load_tree_for_read<'a, T>(
+    root_id: T,
+    depth: u32,
+    conn: &mut PgConn,
+) -> Result<Option<FileNode>>
+where
+    PgUserFileId<'a>: From<T>,
+{
+    load_tree_inner(root_id, depth, false, conn).await
+}
+
+async fn load_tree_inner<'a, T>(
+    root_id: T,
+    depth: u32,
+    lock: bool,
+    conn: &mut PgConn,
+) -> Result<Option<FileNode>>
 where
     PgUserFileId<'a>: From<T>,
 {
@@ -229,7 +462,7 @@ where
         return Ok(None);
     }
 
-    let Some(root) = find_node_inner(root_id, conn).await? else {
+    let Some(root) = find_node_inner(root_id, lock, conn).await? else {
         return Ok(None);
     };
 
@@ -261,17 +494,14 @@ pub async fn load_tree_struct<'a, T>(root_id: T) -> Result<Option<FileNode>>
 where
     PgUserFileId<'a>: From<T>,
 {
-    let mut conn = pg_conn().await?;
-    let Some(root) = find_node_inner(root_id, &mut conn).await? else {
+    let mut conn = pg_conn_read().await?;
+    let Some(root) = find_node_inner(root_id, false, &mut conn).await? else {
         return Ok(None);
     };
-    // release for_update lock
-    drop(conn);
 
     ensure!(root.user_file.is_dir, "root should be dir");
 
     let mut children = vec![];
-    let mut conn = pg_conn().await?;
     load_tree_recursive(root.user_file.id, u32::MAX, true, &mut children, &mut conn).await?;
     let root = FileNodePo {
         user_file: root.user_file,
@@ -335,6 +565,9 @@ pub(crate) async fn update(node: &FileNode, conn: &mut PgConn) -> Result<Effecte
         .collect();
     ensure!(s_files.is_empty(), "sys_files should not be updated");
 
+    // 移动文件时原来和现在的上级目录都需要失效
+    let ids: Vec<_> = u_files.iter().map(|f| f.id).collect();
+    repo_dir_usage::invalidate_ancestors(&ids, conn).await?;
     let mut effected_total = 0;
     for u_file in &u_files {
         let effected = diesel::update(user_files::table)
@@ -344,6 +577,8 @@ pub(crate) async fn update(node: &FileNode, conn: &mut PgConn) -> Result<Effecte
             .await?;
         effected_total += effected;
     }
+    repo_dir_usage::invalidate_ancestors(&ids, conn).await?;
+    log_if_err!(repo_dir_cache::invalidate(*node.user_id()).await);
 
     Ok(EffectedRow {
         effected_row: effected_total,
@@ -359,11 +594,9 @@ pub async fn update_file_matedata(
         let conn = &mut pg_conn().await?;
         diesel::update(dsl::sys_files)
             .filter(dsl::id.eq(file_id))
-            .set((
-                dsl::is_video.eq(false),
-                dsl::can_be_encode.eq(false),
-            ))
-            .execute(conn);
+            .set((dsl::is_video.eq(false), dsl::can_be_encode.eq(false)))
+            .execute(conn)
+            .await?;
         return Ok(());
     };
 
@@ -409,9 +642,112 @@ pub async fn update_file_matedata(
         ))
         .execute(conn)
         .await?;
+    repo_dir_usage::invalidate_by_sys_file(file_id, conn).await?;
     Ok(())
 }
 
+/// 没有被未删除的用户文件、历史版本和进行中的转码任务引用的系统文件
+macro_rules! unreferenced {
+    () => {{
+        use crate::schema::{transcode_tasks, user_file_versions};
+        use diesel::{
+            dsl::{exists, not},
+            BoolExpressionMethods, NullableExpressionMethods,
+        };
+
+        not(exists(
+            user_files::table
+                .filter(user_files::sys_file_id.eq(sys_files::id.nullable()))
+                .filter(user_files::deleted.eq(false)),
+        ))
+        .and(not(exists(
+            user_file_versions::table.filter(user_file_versions::sys_file_id.eq(sys_files::id)),
+        )))
+        .and(not(exists(
+            transcode_tasks::table
+                .filter(transcode_tasks::sys_file_id.eq(sys_files::id))
+                .filter(transcode_tasks::status.eq(0)),
+        )))
+    }};
+}
+
+/// 更新时间早于 before 且不再被引用的系统文件的 id 和 hash
+pub(crate) async fn find_unreferenced_sys_files(
+    before: LocalDataTime,
+    limit: i64,
+    conn: &mut PgConn,
+) -> Result<Vec<(SysFileId, String)>> {
+    let files = sys_files::table
+        .filter(sys_files::updated_at.lt(before))
+        .filter(unreferenced!())
+        .select((sys_files::id, sys_files::hash))
+        .order(sys_files::id)
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(files)
+}
+
+/// 删除前重新检查引用和更新时间，期间被重新引用或复用时不删除，返回是否已删除
+pub(crate) async fn delete_unreferenced_sys_file(
+    id: SysFileId,
+    before: LocalDataTime,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let deleted = diesel::delete(
+        sys_files::table
+            .find(id)
+            .filter(sys_files::updated_at.lt(before))
+            .filter(unreferenced!()),
+    )
+    .execute(conn)
+    .await?;
+    if deleted == 0 {
+        return Ok(false);
+    }
+    diesel::delete(
+        file_integrity_mismatches::table.filter(file_integrity_mismatches::sys_file_id.eq(id)),
+    )
+    .execute(conn)
+    .await?;
+    Ok(true)
+}
+
+/// 返回给定 hash 中存在系统文件记录或被用作头像的部分
+pub(crate) async fn existing_hashes(
+    hashes: &[String],
+    conn: &mut PgConn,
+) -> Result<HashSet<String>> {
+    if hashes.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let found = sys_files::table
+        .filter(sys_files::hash.eq_any(hashes))
+        .select(sys_files::hash)
+        .load::<String>(conn)
+        .await?;
+    let avatars = users::table
+        .filter(users::avatar.eq_any(hashes))
+        .select(users::avatar.assume_not_null())
+        .load::<String>(conn)
+        .await?;
+    Ok(found.into_iter().chain(avatars).collect())
+}
+
+/// 用户所有未删除文件的总大小，内容相同的文件分别计算
+pub(crate) async fn used_space(user_id: UserId, conn: &mut PgConn) -> Result<u64> {
+    let size: i64 = user_files::table
+        .inner_join(sys_files::table)
+        .filter(user_files::user_id.eq(user_id))
+        .filter(user_files::deleted.eq(false))
+        .select(diesel::dsl::sql::<diesel::sql_types::BigInt>(
+            "COALESCE(SUM(sys_files.size), 0)::BIGINT",
+        ))
+        .get_result(conn)
+        .await?;
+    Ok(size as u64)
+}
+
 pub(crate) async fn get_hash(id: UserFileId) -> Result<Option<String>> {
     let conn = &mut pg_conn().await?;
     let hash = user_files::table
@@ -440,6 +776,7 @@ struct VideoPoInner {
     width: Option<i32>,
     video_info: Option<String>,
     audio_info: Option<String>,
+    mime: Option<String>,
 }
 
 pub struct VideoPo {
@@ -457,6 +794,7 @@ pub struct VideoPo {
     pub is_h264: bool,
     pub video_info: Option<VideoInfo>,
     pub audio_info: Option<AudioInfo>,
+    pub mime: Option<String>,
 }
 
 impl VideoPo {
@@ -489,10 +827,56 @@ impl VideoPo {
             video_info,
             audio_info,
             is_h264,
+            mime: video.mime,
         })
     }
 }
 
+/// 记录转码结果的源文件。内容相同的文件已经记录了来源时保留原来的
+pub(crate) async fn set_transcode_from(
+    hash: &str,
+    source: SysFileId,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(
+        sys_files::table
+            .filter(sys_files::hash.eq(hash))
+            .filter(sys_files::id.ne(source))
+            .filter(sys_files::transcode_from.is_null()),
+    )
+    .set(sys_files::transcode_from.eq(source))
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// 按 id 或 hash 查询系统文件的视频信息
+pub(crate) async fn find_sys_video<'a>(
+    key: impl Into<SysVideoKey<'a>>,
+    conn: &mut PgConn,
+) -> Result<Option<VideoPo>> {
+    let query = sys_files::table.select(VideoPoInner::as_select());
+    let video = match key.into() {
+        SysVideoKey::Id(id) => query
+            .filter(sys_files::id.eq(id))
+            .get_result::<VideoPoInner>(conn)
+            .await
+            .optional()?,
+        SysVideoKey::Hash(hash) => query
+            .filter(sys_files::hash.eq(hash))
+            .get_result::<VideoPoInner>(conn)
+            .await
+            .optional()?,
+    };
+    video.map(VideoPo::try_from_raw).transpose()
+}
+
+#[derive(From, Debug)]
+pub enum SysVideoKey<'a> {
+    Id(SysFileId),
+    Hash(&'a str),
+}
+
 pub(crate) async fn find_video(id: UserFileId) -> Result<Option<FileNode>> {
     let conn = &mut pg_conn().await?;
     let res: Option<(UserFilePo, VideoPoInner)> = user_files::table
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use diesel::ExpressionMethods;
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{domain::user::user::UserId, schema::user_sequences};
+
+/// 取得用户在 name 序列中的下一个序号，从 1 开始。
+/// 序号所在的行被锁到事务提交，同一用户的取号按提交顺序排队，按序号增量读取时不会漏掉提交较晚的记录
+pub async fn next(user_id: UserId, name: &str, conn: &mut PgConn) -> Result<i64> {
+    let value = diesel::insert_into(user_sequences::table)
+        .values((
+            user_sequences::user_id.eq(user_id),
+            user_sequences::name.eq(name),
+            user_sequences::value.eq(1),
+        ))
+        .on_conflict((user_sequences::user_id, user_sequences::name))
+        .do_update()
+        .set(user_sequences::value.eq(user_sequences::value + 1))
+        .returning(user_sequences::value)
+        .get_result(conn)
+        .await?;
+    Ok(value)
+}
@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+use diesel::{
+    ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, Selectable,
+    SelectableHelper,
+};
+use diesel_async::RunQueryDsl;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::user::{
+        user::UserId,
+        webhook::{Webhook, WebhookId},
+    },
+    schema::{webhook_deliveries, webhooks},
+    LocalDataTime,
+};
+
+use super::EffectedRow;
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = webhooks)]
+pub struct WebhookPo<'a> {
+    pub id: WebhookId,
+    pub user_id: UserId,
+    pub url: Cow<'a, str>,
+    pub secret: Cow<'a, str>,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(i16)]
+pub enum DeliveryStatus {
+    Pending = 0,
+    Delivered = 1,
+    GaveUp = 2,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewDeliveryPo<'a> {
+    pub webhook_id: WebhookId,
+    pub event: &'a str,
+    pub payload: &'a str,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct DeliveryPo {
+    pub id: i64,
+    pub webhook_id: WebhookId,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub next_attempt_at: LocalDataTime,
+}
+
+pub async fn save(webhook: &Webhook, conn: &mut PgConn) -> Result<()> {
+    let po = WebhookPo {
+        id: *webhook.id(),
+        user_id: *webhook.user_id(),
+        url: Cow::Borrowed(webhook.url()),
+        secret: Cow::Borrowed(webhook.secret()),
+    };
+    diesel::insert_into(webhooks::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn count(user_id: UserId, conn: &mut PgConn) -> Result<i64> {
+    let count = webhooks::table
+        .filter(webhooks::user_id.eq(user_id))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+pub async fn find_by_user(user_id: UserId, conn: &mut PgConn) -> Result<Vec<Webhook>> {
+    let hooks: Vec<WebhookPo> = webhooks::table
+        .filter(webhooks::user_id.eq(user_id))
+        .select(WebhookPo::as_select())
+        .order_by(webhooks::create_at.asc())
+        .load(conn)
+        .await?;
+    let hooks = hooks
+        .into_iter()
+        .map(|po| {
+            Webhook::from_raw(
+                po.id,
+                po.user_id,
+                po.url.into_owned(),
+                po.secret.into_owned(),
+            )
+        })
+        .collect();
+    Ok(hooks)
+}
+
+pub async fn find(id: WebhookId, conn: &mut PgConn) -> Result<Option<Webhook>> {
+    let po: Option<WebhookPo> = webhooks::table
+        .find(id)
+        .select(WebhookPo::as_select())
+        .get_result(conn)
+        .await
+        .optional()?;
+    let hook = po.map(|po| {
+        Webhook::from_raw(
+            po.id,
+            po.user_id,
+            po.url.into_owned(),
+            po.secret.into_owned(),
+        )
+    });
+    Ok(hook)
+}
+
+pub async fn delete(user_id: UserId, id: WebhookId, conn: &mut PgConn) -> Result<EffectedRow> {
+    let effected = diesel::delete(
+        webhooks::table
+            .filter(webhooks::id.eq(id))
+            .filter(webhooks::user_id.eq(user_id)),
+    )
+    .execute(conn)
+    .await?;
+    diesel::delete(webhook_deliveries::table.filter(webhook_deliveries::webhook_id.eq(id)))
+        .execute(conn)
+        .await?;
+    Ok(EffectedRow {
+        expect_row: 1,
+        effected_row: effected,
+    })
+}
+
+pub async fn save_deliveries(deliveries: &[NewDeliveryPo<'_>], conn: &mut PgConn) -> Result<()> {
+    diesel::insert_into(webhook_deliveries::table)
+        .values(deliveries)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 查询已到重试时间的投递记录
+pub async fn due_deliveries(limit: i64, conn: &mut PgConn) -> Result<Vec<DeliveryPo>> {
+    let deliveries = webhook_deliveries::table
+        .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending as i16))
+        .filter(webhook_deliveries::next_attempt_at.le(diesel::dsl::now))
+        .select(DeliveryPo::as_select())
+        .order_by(webhook_deliveries::next_attempt_at.asc())
+        .limit(limit)
+        .load(conn)
+        .await?;
+    Ok(deliveries)
+}
+
+/// 将投递记录的下次投递时间推迟到 lease_until，防止多个实例重复投递。
+/// 返回 false 表示已被其他实例认领
+pub async fn claim(
+    delivery: &DeliveryPo,
+    lease_until: LocalDataTime,
+    conn: &mut PgConn,
+) -> Result<bool> {
+    let effected = diesel::update(
+        webhook_deliveries::table
+            .filter(webhook_deliveries::id.eq(delivery.id))
+            .filter(webhook_deliveries::status.eq(DeliveryStatus::Pending as i16))
+            .filter(webhook_deliveries::next_attempt_at.eq(delivery.next_attempt_at)),
+    )
+    .set(webhook_deliveries::next_attempt_at.eq(lease_until))
+    .execute(conn)
+    .await?;
+    Ok(effected == 1)
+}
+
+pub async fn update_delivery(
+    id: i64,
+    status: DeliveryStatus,
+    attempts: i32,
+    next_attempt_at: LocalDataTime,
+    last_error: Option<&str>,
+    conn: &mut PgConn,
+) -> Result<()> {
+    diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq(id)))
+        .set((
+            webhook_deliveries::status.eq(status as i16),
+            webhook_deliveries::attempts.eq(attempts),
+            webhook_deliveries::next_attempt_at.eq(next_attempt_at),
+            webhook_deliveries::last_error.eq(last_error),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use diesel::{ExpressionMethods, Insertable, QueryDsl, Queryable, Selectable, SelectableHelper};
+use diesel_async::RunQueryDsl;
+use serde_json::Value;
+use utils::db_pools::postgres::PgConn;
+
+use crate::{
+    domain::transcode_order::worker::{Worker, WorkerCapabilities, WorkerId},
+    schema::workers,
+    LocalDataTime,
+};
+
+#[derive(Queryable, Selectable, Insertable, Debug)]
+#[diesel(table_name = workers)]
+pub struct WorkerPo {
+    pub id: WorkerId,
+    pub endpoint: String,
+    /// 与 VideoFormat 序列化后的名字相同，如 av1
+    pub codecs: Vec<String>,
+    pub max_width: i32,
+    pub max_height: i32,
+    pub concurrency: i32,
+    pub running: i32,
+}
+
+impl WorkerPo {
+    pub fn from_do(worker: &Worker) -> Result<Self> {
+        let caps = &worker.capabilities;
+        let codecs = caps
+            .codecs
+            .iter()
+            .map(|codec| match serde_json::to_value(codec)? {
+                Value::String(codec) => Ok(codec),
+                other => anyhow::bail!("unexpected codec: {other}"),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            id: worker.id,
+            endpoint: worker.endpoint.clone(),
+            codecs,
+            max_width: caps.max_width as i32,
+            max_height: caps.max_height as i32,
+            concurrency: caps.concurrency as i32,
+            running: worker.running as i32,
+        })
+    }
+
+    pub fn into_do(self) -> Result<Worker> {
+        let codecs = self
+            .codecs
+            .into_iter()
+            .map(|codec| serde_json::from_value(Value::String(codec)))
+            .collect::<Result<_, _>>()?;
+        Ok(Worker {
+            id: self.id,
+            endpoint: self.endpoint,
+            capabilities: WorkerCapabilities {
+                codecs,
+                max_width: self.max_width as u32,
+                max_height: self.max_height as u32,
+                concurrency: self.concurrency as u32,
+            },
+            running: self.running as u32,
+        })
+    }
+}
+
+/// 注册节点，同一个地址已有记录时替换。
+/// 旧记录的 id 随之失效，分配给它的任务会被当作下线节点的任务重新下发
+pub async fn register(worker: &Worker, conn: &mut PgConn) -> Result<()> {
+    let po = WorkerPo::from_do(worker)?;
+    diesel::delete(workers::table.filter(workers::endpoint.eq(&po.endpoint)))
+        .execute(conn)
+        .await?;
+    diesel::insert_into(workers::table)
+        .values(&po)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// 更新心跳时间和正在处理的任务数，节点不存在时返回 false
+pub async fn heartbeat(id: WorkerId, running: u32, conn: &mut PgConn) -> Result<bool> {
+    let effected = diesel::update(workers::table.find(id))
+        .set((
+            workers::running.eq(running as i32),
+            workers::last_heartbeat_at.eq(diesel::dsl::now),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(effected == 1)
+}
+
+/// 在 alive_since 之后有过心跳的节点
+pub async fn find_alive(alive_since: LocalDataTime, conn: &mut PgConn) -> Result<Vec<Worker>> {
+    let workers: Vec<WorkerPo> = workers::table
+        .filter(workers::last_heartbeat_at.ge(alive_since))
+        .select(WorkerPo::as_select())
+        .order_by(workers::id.asc())
+        .load(conn)
+        .await?;
+    workers.into_iter().map(WorkerPo::into_do).collect()
+}
+
+/// 分配任务后占用一个并发，下次心跳时以节点上报的数量为准
+pub async fn take_slot(id: WorkerId, conn: &mut PgConn) -> Result<()> {
+    diesel::update(workers::table.find(id))
+        .set(workers::running.eq(workers::running + 1))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
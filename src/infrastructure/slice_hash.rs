@@ -0,0 +1,164 @@
+//! 分片上传时增量计算 hash
+//!
+//! 按序号从 0 开始连续到达的分片在写入时顺便计算 hash，先到达的靠后的分片在前面的分片
+//! 补齐后从磁盘读一次。合并时只需要计算剩余的分片。
+//! 状态只保存在内存中，服务重启、多实例部署或分片被重写时丢弃，合并时退回到完整计算
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use sha2::Sha256;
+
+/// 已经计算过的前缀
+pub(crate) struct HashState {
+    /// 下一个要计算的分片序号，之前的分片都已经计算过
+    pub next: u32,
+    /// 已计算的字节数
+    pub size: u64,
+    pub hasher: Sha256,
+}
+
+struct Entry {
+    next: u32,
+    size: u64,
+    /// 正在被某个上传请求使用时为 None
+    hasher: Option<Sha256>,
+    /// 使用期间有分片被重写，归还时丢弃
+    stale: bool,
+}
+
+/// 以分片目录为键
+static STATES: Mutex<BTreeMap<PathBuf, Entry>> = Mutex::new(BTreeMap::new());
+
+/// 正好轮到 index 时取出状态，用完后需要调用 put_back。
+/// 第一个分片到达时创建状态
+pub(crate) fn take(dir: &Path, index: u32) -> Option<HashState> {
+    let mut states = STATES.lock().unwrap();
+    let Some(entry) = states.get_mut(dir) else {
+        if index != 0 {
+            return None;
+        }
+        states.insert(
+            dir.to_owned(),
+            Entry {
+                next: 0,
+                size: 0,
+                hasher: None,
+                stale: false,
+            },
+        );
+        return Some(HashState {
+            next: 0,
+            size: 0,
+            hasher: Default::default(),
+        });
+    };
+
+    if entry.stale || entry.next != index {
+        return None;
+    }
+    let hasher = entry.hasher.take()?;
+    Some(HashState {
+        next: entry.next,
+        size: entry.size,
+        hasher,
+    })
+}
+
+pub(crate) fn put_back(dir: &Path, state: HashState) {
+    let mut states = STATES.lock().unwrap();
+    match states.get_mut(dir) {
+        Some(entry) if !entry.stale => {
+            entry.next = state.next;
+            entry.size = state.size;
+            entry.hasher = Some(state.hasher);
+        }
+        _ => {
+            states.remove(dir);
+        }
+    }
+}
+
+/// 已存在的分片被重写，计算过的内容可能已经失效
+pub(crate) fn invalidate(dir: &Path) {
+    let mut states = STATES.lock().unwrap();
+    match states.get_mut(dir) {
+        Some(entry) if entry.hasher.is_none() => entry.stale = true,
+        Some(_) => {
+            states.remove(dir);
+        }
+        None => {}
+    }
+}
+
+/// 合并时取出状态并删除记录，正在使用或已失效时返回 None
+pub(crate) fn finish(dir: &Path) -> Option<HashState> {
+    let mut states = STATES.lock().unwrap();
+    let entry = states.remove(dir)?;
+    if entry.stale {
+        return None;
+    }
+    Some(HashState {
+        next: entry.next,
+        size: entry.size,
+        hasher: entry.hasher?,
+    })
+}
+
+/// 上传任务被删除时调用
+pub(crate) fn clear(dir: &Path) {
+    STATES.lock().unwrap().remove(dir);
+}
+
+#[cfg(test)]
+mod test {
+    use sha2::Digest;
+
+    use super::*;
+
+    #[test]
+    fn t_take_in_order() {
+        let dir = Path::new("/tmp/slice-hash/in-order");
+        assert!(take(dir, 1).is_none());
+
+        let mut state = take(dir, 0).unwrap();
+        assert!(take(dir, 0).is_none());
+        state.hasher.update(b"abc");
+        state.size += 3;
+        state.next += 1;
+        put_back(dir, state);
+
+        assert!(take(dir, 0).is_none());
+        assert!(take(dir, 2).is_none());
+        let state = take(dir, 1).unwrap();
+        assert_eq!(state.size, 3);
+        put_back(dir, state);
+
+        let state = finish(dir).unwrap();
+        assert_eq!(state.next, 1);
+        assert_eq!(
+            hex::encode(state.hasher.finalize()),
+            hex::encode(Sha256::digest(b"abc"))
+        );
+        assert!(finish(dir).is_none());
+    }
+
+    #[test]
+    fn t_invalidate() {
+        let dir = Path::new("/tmp/slice-hash/invalidate");
+        let state = take(dir, 0).unwrap();
+        // 使用中被重写，归还后丢弃
+        invalidate(dir);
+        assert!(take(dir, 0).is_none());
+        put_back(dir, state);
+        assert!(finish(dir).is_none());
+
+        let state = take(dir, 0).unwrap();
+        put_back(dir, state);
+        invalidate(dir);
+        assert!(finish(dir).is_none());
+    }
+}
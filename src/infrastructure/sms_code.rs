@@ -12,6 +12,22 @@ use sha2::{Digest, Sha256};
 
 use tracing::{debug, error, warn};
 
+use crate::settings::{get_settings, Secret};
+
+/// 腾讯云短信配置
+#[derive(Default, Debug, Deserialize)]
+pub struct SmsCfg {
+    /// 应用 ID
+    pub app_id: String,
+    /// API 密钥，见 https://console.cloud.tencent.com/cam/capi
+    pub secret_id: Secret,
+    pub secret_key: Secret,
+    /// 短信模板 ID
+    pub template_id: String,
+    /// 短信签名
+    pub sign_name: String,
+}
+
 pub fn get_user_key(tel: &str) -> String {
     format!("user:{}:smsCode", tel)
 }
@@ -23,14 +39,6 @@ pub fn get_user_key(tel: &str) -> String {
  */
 static HOST: &str = "sms.ap-guangzhou.tencentcloudapi.com"; // 华南地区(广州)
 
-// 应用相关
-static APP_ID: &str = "1400796999";
-// API密钥管理 https://console.cloud.tencent.com/cam/capi
-static SECRET_ID: &str = "AKIDVr0v2xgBRSCqHUTGp9E6mGWyGbibGhux";
-static SECRET_KEY: &str = "tXAjFlIjz0rWIo8GBrKYZN0QefS0KSZl";
-//
-static SMS_TEMPLATE_ID: &str = "1707793";
-static SIGN_CONTENT: &str = "东方凤鸣科技";
 static CONTENT_TYPE: &str = "application/json";
 static REGION: &str = "ap-guangzhou";
 static SERVICE: &str = "sms";
@@ -117,8 +125,8 @@ impl<'a> SmsApi<'a> {
             //
             method: "POST",
             query: "",
-            template_id: SMS_TEMPLATE_ID,
-            sign_name: SIGN_CONTENT,
+            template_id: &get_settings().sms.template_id,
+            sign_name: &get_settings().sms.sign_name,
             // 私有参数
             phone_number_set: tel_list,
             template_param_set: template_param,
@@ -158,6 +166,7 @@ impl<'a> SmsApi<'a> {
     pub async fn send(&self) -> Result<SMSResponse, reqwest::Error> {
         // 定义当前时间戳
         let timestamp = Utc::now().timestamp();
+        let config = &get_settings().sms;
 
         // 添加Header
         let mut headers = self.add_headers(timestamp);
@@ -166,9 +175,9 @@ impl<'a> SmsApi<'a> {
         let body = if self.sign_name.is_empty() {
             json!({
                 "PhoneNumberSet": self.phone_number_set,
-                "SmsSdkAppId": APP_ID,
-                "TemplateId": SMS_TEMPLATE_ID,
-                "SignName": SIGN_CONTENT,
+                "SmsSdkAppId": config.app_id,
+                "TemplateId": config.template_id,
+                "SignName": config.sign_name,
                 "TemplateParamSet": self.template_param_set
             })
             .to_string()
@@ -178,7 +187,7 @@ impl<'a> SmsApi<'a> {
             // 若无模板参数，则设置为空。
             json!({
                 "PhoneNumberSet": self.phone_number_set,
-                "SmsSdkAppId": APP_ID,
+                "SmsSdkAppId": config.app_id,
                 "TemplateId": self.template_id,
                 "SignName": self.sign_name
             })
@@ -186,7 +195,7 @@ impl<'a> SmsApi<'a> {
         } else {
             json!({
                 "PhoneNumberSet": self.phone_number_set,
-                "SmsSdkAppId": APP_ID,
+                "SmsSdkAppId": config.app_id,
                 "TemplateId": self.template_id,
                 "SignName": self.sign_name,
                 "TemplateParamSet": self.template_param_set
@@ -269,8 +278,11 @@ impl<'a> SmsApi<'a> {
         );
 
         // Signature 计算签名
-        let secret_date =
-            self.hmac_sha256(format!("TC3{}", SECRET_KEY).as_bytes(), date.as_bytes());
+        let config = &get_settings().sms;
+        let secret_date = self.hmac_sha256(
+            format!("TC3{}", config.secret_key.expose()).as_bytes(),
+            date.as_bytes(),
+        );
         let secret_service =
             self.hmac_sha256(secret_date.into_bytes().as_slice(), SERVICE.as_bytes());
         let secret_signing = self.hmac_sha256(
@@ -288,7 +300,10 @@ impl<'a> SmsApi<'a> {
 
         let authorization = format!(
             "TC3-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
-            SECRET_ID, credential_scope, signed_headers, signature
+            config.secret_id.expose(),
+            credential_scope,
+            signed_headers,
+            signature
         );
 
         authorization
@@ -469,6 +484,22 @@ impl<'a> SmsSender<'a> {
     }
 }
 
+fn login_count_key(tel: &str, window: i64) -> String {
+    format!("user:sms_login:{}:{}", tel, window)
+}
+
+/// 在当前的时间窗口内记录一次短信登录，返回窗口内的次数
+pub(crate) async fn incr_login_count(tel: &str, window_secs: u64) -> Result<u64> {
+    let window = Utc::now().timestamp() / window_secs as i64;
+    let key = login_count_key(tel, window);
+    let conn = &mut redis_conn().await?;
+    let count: u64 = conn.incr(&key, 1).await?;
+    if count == 1 {
+        let _: () = conn.expire(&key, window_secs as usize).await?;
+    }
+    Ok(count)
+}
+
 /// 查询数据库中是否有短信验证码
 pub async fn get_sent_sms_code(tel: &str) -> Result<Option<String>> {
     let mut conn = redis_conn().await?;
@@ -484,6 +515,7 @@ mod test {
     // 发送单个手机验证码
     #[tokio::test]
     async fn send_sms() {
+        crate::settings::load_settings().unwrap();
         let tel = ["13129387413"].to_vec();
         let param = ["123456", "5"].to_vec();
 
@@ -496,6 +528,7 @@ mod test {
     // 发送多个手机号验证码
     #[tokio::test]
     async fn send_sms_multi() {
+        crate::settings::load_settings().unwrap();
         let tel = ["18645959590", "14707649560"].to_vec();
         let param = ["123456", "5"].to_vec();
 
@@ -10,13 +10,14 @@ use actix_web::{
     dev::Server,
     web, App, HttpServer,
 };
-use anyhow::{Context, Result};
-use settings::get_settings;
-use tracing::info;
+use anyhow::{ensure, Context, Result};
+use settings::{get_settings, CorsCfg};
+use tracing::{info, warn};
 use utils::logger;
 
 use crate::{
     application::file_system,
+    http::{csrf, request_id, slow_log, validation},
     presentation::{employee, user},
     settings::load_settings,
 };
@@ -31,6 +32,8 @@ mod cqrs;
 pub mod http;
 mod schema;
 pub mod settings;
+#[cfg(all(test, feature = "redis"))]
+mod test_support;
 
 pub use redis_conn_switch::*;
 pub mod redis_conn_switch {
@@ -48,23 +51,36 @@ pub async fn build_http_server() -> Result<Server> {
 
     let casbin_middleware = build_casbin_mw().await?;
 
+    let session_key = build_session_key()?;
+    check_cors(&settings.cors)?;
     let store = RedisSessionStore::new(&settings.session.url).await?;
     let server: Server = HttpServer::new(move || {
-        let session = build_session_mw(store.clone());
-        let cors = Cors::permissive();
+        let session = build_session_mw(store.clone(), session_key.clone());
+        let cors = build_cors(&settings.cors);
         App::new()
+            .app_data(web::JsonConfig::default().error_handler(validation::json_error))
+            .app_data(web::QueryConfig::default().error_handler(validation::query_error))
+            .app_data(web::PathConfig::default().error_handler(validation::path_error))
             .configure(presentation::config)
             .configure(user::config)
             .configure(employee::config)
             .configure(cqrs::actix_config)
             .configure(presentation::file_system::actix_config)
             .configure(presentation::transcode::config)
+            .configure(presentation::notification::config)
+            .configure(presentation::email::config)
+            .configure(presentation::feature_flag::config)
+            .configure(presentation::organization::config)
+            .configure(presentation::gallery::config)
             .route("/ping", web::get().to(http_ping))
+            .wrap(csrf::CsrfGuard)
             .wrap(casbin_middleware.clone())
             .wrap(auth::RoleExtractor)
+            .wrap(slow_log::SlowRequestLog)
             .wrap(IdentityMiddleware::default())
             .wrap(session)
             .wrap(cors)
+            .wrap(request_id::RequestId)
     })
     .bind((&*settings.bind, settings.port))?
     .run();
@@ -93,14 +109,61 @@ pub async fn t_admin() -> &'static str {
     "t_admin"
 }
 
-fn build_session_mw(store: RedisSessionStore) -> SessionMiddleware<RedisSessionStore> {
+fn build_session_key() -> Result<Key> {
+    let config = &get_settings().http_server.session;
+    let Some(key) = &config.key else {
+        warn!("session key is not configured, fallback to the insecure development key");
+        let key: Vec<u8> = (0..64).collect();
+        return Ok(Key::from(&key));
+    };
+
+    let key = key.expose().as_bytes();
+    ensure!(key.len() >= 64, "session key must be at least 64 bytes");
+    Ok(Key::from(key))
+}
+
+/// 允许任意来源时不能同时允许携带 cookie，否则任意网站都可以以登录用户的身份调用接口
+fn check_cors(config: &CorsCfg) -> Result<()> {
+    if config.permissive {
+        warn!("cors is permissive, any origin is allowed with credentials");
+        return Ok(());
+    }
+    let any_origin = config.allowed_origins.iter().any(|o| o == "*");
+    ensure!(
+        !(any_origin && config.supports_credentials),
+        "cors allowed_origins \"*\" can not be used with supports_credentials"
+    );
+    Ok(())
+}
+
+fn build_cors(config: &CorsCfg) -> Cors {
+    if config.permissive {
+        return Cors::permissive();
+    }
+
+    let mut cors = Cors::default()
+        .allowed_methods(config.allowed_methods.iter().map(|m| m.as_str()))
+        .allowed_headers(config.allowed_headers.iter().map(|h| h.as_str()))
+        .expose_headers([request_id::REQUEST_ID_HEADER])
+        .max_age(config.max_age_secs);
+    for origin in &config.allowed_origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    if config.supports_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+fn build_session_mw(store: RedisSessionStore, key: Key) -> SessionMiddleware<RedisSessionStore> {
     let config = &get_settings().http_server.session;
-    // TODO: load from configure or env
-    let key: Vec<u8> = (0..64).collect();
     let life =
         PersistentSession::default().session_ttl(Duration::seconds(config.max_age_secs as i64));
-    let key = Key::try_from(&*key).unwrap();
-    let session = SessionMiddleware::builder(store.clone(), key.clone())
+    let session = SessionMiddleware::builder(store.clone(), key)
         .cookie_secure(config.secure)
         .cookie_http_only(config.http_only)
         .session_lifecycle(life)
@@ -116,7 +179,9 @@ pub async fn init_global() -> Result<()> {
     let settings = load_settings().context("load settings")?;
     logger::init(&settings.log)?;
 
-    infrastructure::email::load_email_code_template().context("load email-code-template")?;
+    infrastructure::email_template::load(infrastructure::email::BUILTIN_TEMPLATES)
+        .context("load email templates")?;
+    infrastructure::email::start_order_mailer().context("start order mailer")?;
 
     utils::db_pools::postgres::init(&settings.postgres)
         .await
@@ -132,12 +197,27 @@ pub async fn init_global() -> Result<()> {
             .context("init redis pool")?;
     }
 
+    application::event_bus::init();
+    application::user::password::load_banned_list().context("load banned password list")?;
+
     if settings.init_system.register_test_user {
         application::user::employee::register_root().await?;
         application::user::register_test_user().await?;
     }
 
     file_system::init().await.context("init file-system")?;
+    application::webhook::spawn_worker();
+    application::outbox::spawn_dispatcher();
+    infrastructure::email::spawn_queue_worker();
+    infrastructure::email_template::spawn_reloader();
+    application::callback::spawn_processor();
+    application::watchdog::spawn();
+    application::worker::spawn();
+    application::schedule::spawn();
+
+    if settings.init_system.backfill_video_info {
+        file_system::video_info::start_backfill();
+    }
 
     info!("global environment loaded");
     Ok(())
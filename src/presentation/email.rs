@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use actix_identity::Identity;
+use actix_web::web::{self, Json};
+use utils::code;
+use utoipa::OpenApi;
+
+use crate::{
+    application::email::{self, EmailTemplateDto, PreviewTemplateErr, RenderedEmailDto},
+    http::{ApiError, ApiResponse, ApiResult},
+    status_doc,
+};
+
+code! {
+    mod = "email";
+    index = 14;
+    err_trait = crate::http::HttpBizError;
+
+    ---
+
+    PreviewEmailTemplate {
+        template_not_found = "邮件模板不存在",
+        missing_variables = "缺少模板中的变量"
+    }
+}
+
+impl From<PreviewTemplateErr> for ApiError {
+    fn from(value: PreviewTemplateErr) -> Self {
+        match value {
+            PreviewTemplateErr::NotFound => PREVIEW_EMAIL_TEMPLATE.template_not_found.into(),
+            PreviewTemplateErr::MissingVariables(_) => {
+                PREVIEW_EMAIL_TEMPLATE.missing_variables.into()
+            }
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/email")
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
+            .service(web::resource("/templates").route(web::get().to(list_templates)))
+            .service(
+                web::resource("/templates/{name}/preview").route(web::post().to(preview_template)),
+            ),
+    );
+}
+
+status_doc!();
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list_templates, preview_template),
+    components(schemas(EmailTemplateDto, RenderedEmailDto))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[("preview_template", "PreviewEmailTemplate")];
+
+/// 已加载的邮件模板
+#[utoipa::path(
+    get,
+    path = "/admin/email/templates",
+    responses((status = 200, body = Vec<EmailTemplateDto>)),
+    tag = "email"
+)]
+async fn list_templates(_id: Identity) -> ApiResult<Vec<EmailTemplateDto>> {
+    ApiResponse::Ok(email::list_templates())
+}
+
+/// 用给定的变量渲染邮件模板，不发送
+#[utoipa::path(
+    post,
+    path = "/admin/email/templates/{name}/preview",
+    params(("name" = String, Path, description = "模板名")),
+    request_body = HashMap<String, String>,
+    responses((status = 200, body = RenderedEmailDto)),
+    tag = "email"
+)]
+async fn preview_template(
+    _id: Identity,
+    name: web::Path<String>,
+    params: Json<HashMap<String, String>>,
+) -> ApiResult<RenderedEmailDto> {
+    let email = email::preview_template(&name, params.into_inner())??;
+    ApiResponse::Ok(email)
+}
@@ -3,11 +3,12 @@ use actix_session::SessionExt;
 use actix_web::web::{self, Json};
 use actix_web::{HttpMessage, HttpRequest};
 use utils::code;
+use utoipa::OpenApi;
 
 use crate::application::user::employee::{
     self, EmployeeRegisterDto, LoginDto, LoginErr, RegisterErr,
 };
-use crate::http::{ApiError, ApiResponse};
+use crate::http::{csrf, ApiError, ApiResponse};
 use crate::log_if_err;
 use crate::{http::ApiResult, status_doc};
 
@@ -36,6 +37,11 @@ macro_rules! password_err {
                 PASSWORD_FORMAT.not_allowed_char.into()
             }
             crate::domain::user::PasswordFormatErr::TooSimple => PASSWORD_FORMAT.too_simple.into(),
+            crate::domain::user::PasswordFormatErr::TooFewCharClasses => {
+                PASSWORD_FORMAT.too_few_char_classes.into()
+            }
+            crate::domain::user::PasswordFormatErr::Banned => PASSWORD_FORMAT.banned.into(),
+            crate::domain::user::PasswordFormatErr::Pwned => PASSWORD_FORMAT.pwned.into(),
         }
     };
 }
@@ -90,12 +96,38 @@ pub fn config(cfg: &mut web::ServiceConfig) {
 
 status_doc!();
 
+#[derive(OpenApi)]
+#[openapi(
+    paths(generate_invite_code, register, login, logout),
+    components(schemas(EmployeeRegisterDto, LoginDto))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[("employee_register", "Register")];
+
+/// 生成员工邀请码
+#[utoipa::path(
+    get,
+    path = "/admin/employee/invite_code",
+    responses((status = 200, body = String)),
+    tag = "employee"
+)]
 pub async fn generate_invite_code(id: Identity) -> ApiResult<String> {
     let id = id.id()?.parse()?;
     let code = employee::generate_invite_code(id).await?;
     ApiResponse::Ok(code)
 }
 
+/// 员工注册，成功后自动登录
+#[utoipa::path(
+    post,
+    path = "/admin/employee/register",
+    operation_id = "employee_register",
+    request_body = EmployeeRegisterDto,
+    responses((status = 200, description = "成功")),
+    tag = "employee"
+)]
 pub async fn register(params: Json<EmployeeRegisterDto>, req: HttpRequest) -> ApiResult<()> {
     let params = params.into_inner();
     let (id, role) = employee::register(params.clone()).await??;
@@ -103,21 +135,40 @@ pub async fn register(params: Json<EmployeeRegisterDto>, req: HttpRequest) -> Ap
     Identity::login(&req.extensions(), id.to_string())?;
     let session = req.get_session();
     session.insert("role", role)?;
+    csrf::rotate_token(&session)?;
 
     ApiResponse::Ok(())
 }
 
+/// 员工登录
+#[utoipa::path(
+    post,
+    path = "/admin/employee/login",
+    operation_id = "employee_login",
+    request_body = EmployeeLoginDto,
+    responses((status = 200, description = "成功")),
+    tag = "employee"
+)]
 pub async fn login(params: Json<LoginDto>, req: HttpRequest) -> ApiResult<()> {
     let (id, role) = employee::login(params.into_inner()).await??;
 
     Identity::login(&req.extensions(), id.to_string())?;
     let session = req.get_session();
     session.insert("role", role)?;
+    csrf::rotate_token(&session)?;
 
     // session
     ApiResponse::Ok(())
 }
 
+/// 员工退出登录
+#[utoipa::path(
+    post,
+    path = "/admin/employee/logout",
+    operation_id = "employee_logout",
+    responses((status = 200, description = "成功")),
+    tag = "employee"
+)]
 pub async fn logout(id: Identity) -> ApiResult<()> {
     let user_id = id.id()?.parse()?;
     // 不返回错误，只记录日志
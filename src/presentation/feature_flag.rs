@@ -0,0 +1,118 @@
+use actix_identity::Identity;
+use actix_web::web::{self, Json};
+use utils::code;
+use utoipa::OpenApi;
+
+use crate::{
+    application::feature_flag::{self, DeleteFeatureFlagErr, FeatureFlagDto, SaveFeatureFlagDto},
+    domain::feature_flag::FeatureFlagErr,
+    http::{ApiError, ApiResponse, ApiResult},
+    status_doc,
+};
+
+code! {
+    mod = "feature_flag";
+    index = 15;
+    err_trait = crate::http::HttpBizError;
+
+    ---
+
+    SaveFeatureFlag {
+        invalid_name = "名称只能包含小写字母、数字、-、_ 和 .，最长 64 个字符",
+        invalid_percent = "灰度比例必须在 0 到 100 之间"
+    }
+
+    DeleteFeatureFlag {
+        not_found = "功能开关不存在"
+    }
+}
+
+impl From<FeatureFlagErr> for ApiError {
+    fn from(value: FeatureFlagErr) -> Self {
+        match value {
+            FeatureFlagErr::InvalidName => SAVE_FEATURE_FLAG.invalid_name.into(),
+            FeatureFlagErr::InvalidPercent => SAVE_FEATURE_FLAG.invalid_percent.into(),
+        }
+    }
+}
+
+impl From<DeleteFeatureFlagErr> for ApiError {
+    fn from(value: DeleteFeatureFlagErr) -> Self {
+        match value {
+            DeleteFeatureFlagErr::NotFound => DELETE_FEATURE_FLAG.not_found.into(),
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin/feature_flags")
+            .service(web::resource("").route(web::get().to(list_feature_flags)))
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
+            .service(
+                web::resource("/{name}")
+                    .route(web::post().to(save_feature_flag))
+                    .route(web::delete().to(delete_feature_flag)),
+            ),
+    );
+}
+
+status_doc!();
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list_feature_flags, save_feature_flag, delete_feature_flag),
+    components(schemas(FeatureFlagDto, SaveFeatureFlagDto))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("save_feature_flag", "SaveFeatureFlag"),
+    ("delete_feature_flag", "DeleteFeatureFlag"),
+];
+
+/// 所有功能开关
+#[utoipa::path(
+    get,
+    path = "/admin/feature_flags",
+    responses((status = 200, body = Vec<FeatureFlagDto>)),
+    tag = "feature_flag"
+)]
+async fn list_feature_flags(_id: Identity) -> ApiResult<Vec<FeatureFlagDto>> {
+    let flags = feature_flag::list().await?;
+    ApiResponse::Ok(flags)
+}
+
+/// 创建或修改功能开关，修改立即生效
+#[utoipa::path(
+    post,
+    path = "/admin/feature_flags/{name}",
+    params(("name" = String, Path, description = "开关名称")),
+    request_body = SaveFeatureFlagDto,
+    responses((status = 200, description = "成功")),
+    tag = "feature_flag"
+)]
+async fn save_feature_flag(
+    id: Identity,
+    name: web::Path<String>,
+    params: Json<SaveFeatureFlagDto>,
+) -> ApiResult<()> {
+    let employee_id = id.id()?.parse()?;
+    feature_flag::save(employee_id, name.into_inner(), params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 删除功能开关，删除后视为关闭
+#[utoipa::path(
+    delete,
+    path = "/admin/feature_flags/{name}",
+    params(("name" = String, Path, description = "开关名称")),
+    responses((status = 200, description = "成功")),
+    tag = "feature_flag"
+)]
+async fn delete_feature_flag(id: Identity, name: web::Path<String>) -> ApiResult<()> {
+    let employee_id = id.id()?.parse()?;
+    feature_flag::delete(employee_id, name.into_inner()).await??;
+    ApiResponse::Ok(())
+}
@@ -2,27 +2,93 @@ use std::collections::HashSet;
 
 use actix_files::NamedFile;
 use actix_identity::Identity;
-use actix_multipart::form::bytes::Bytes;
-use actix_multipart::form::text::Text;
-use actix_multipart::form::{MultipartForm, MultipartFormConfig};
+use actix_multipart::{Field, Multipart};
 use actix_session::SessionExt;
+use actix_web::http::header::{
+    Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, HeaderValue,
+    CONTENT_TYPE,
+};
 use actix_web::web::{self, Json, Query};
-use actix_web::HttpRequest;
+use actix_web::{HttpRequest, HttpResponse};
+use anyhow::{anyhow, ensure};
+use futures_util::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 use utils::code;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use validator::Validate;
 
-use crate::application::file_system::service::{self, DirTree};
+use crate::application::callback;
+use crate::application::file_system::access_log::{self, AccessKind};
+use crate::application::file_system::admin::{
+    self, AdminFileEntry, AdminFsErr, AdminUploadErr, AdminUploadTaskDto, BrowseErr, FsCapability,
+};
+use crate::application::file_system::breadcrumb::{self, Ancestor, AncestorsErr};
+use crate::application::file_system::bulk::{self, BulkMoveErr, BulkResult};
+use crate::application::file_system::comment::{
+    self, CreateCommentDto, CreateCommentErr, CreateCommentResp, DeleteCommentErr,
+    UpdateCommentDto, UpdateCommentErr,
+};
+use crate::application::file_system::consistency::{self, ConsistencyReport, Inconsistency};
+use crate::application::file_system::dir_cache::{self, DirCacheStats};
+use crate::application::file_system::edit_lock::{
+    self, AcquireLockDto, AcquireLockErr, FileLockDto, ReleaseLockDto,
+};
+use crate::application::file_system::import_url::{
+    self, ImportStatusErr, ImportTaskDto, ImportUrlDto, ImportUrlErr, ImportUrlResp,
+};
+use crate::application::file_system::ingest::{self, IngestDto, IngestErr, IngestProgress};
+use crate::application::file_system::integrity::{self, VerifyErr, VerifyResult};
+use crate::application::file_system::manifest::{self, ManifestEntry, ManifestErr};
+use crate::application::file_system::path_history::{self, ResolvePathErr, ResolvedPath};
+use crate::application::file_system::playback::{
+    self, PlaybackFile, PlaybackUrl, PlaybackUrlDto, PlaybackUrlErr,
+};
+use crate::application::file_system::recent;
+use crate::application::file_system::reclaim::{self, OrphanDir, OrphanScanResult};
+use crate::application::file_system::service::{self, DirTree, DownloadDirErr};
+use crate::application::file_system::share::{
+    self, ShareFolderDto, ShareFolderErr, ShareMemberDto, UnshareFolderDto, UnshareFolderErr,
+};
+use crate::application::file_system::star::{self, StarDto, StarErr};
+use crate::application::file_system::subtitle::{self, AttachSubtitleDto, AttachSubtitleErr};
+use crate::application::file_system::tag::{self, AddTagErr, FileTagDto, RemoveTagErr};
 use crate::application::file_system::upload::{
-    self, FinishUploadTaskErr, RegisterUploadTaskDto, RegisterUploadTaskErr,
-    RegisterUploadTaskResp, StoreSliceErr, UploadTaskDto, UploadedUserFile,
+    self, FinishUploadTaskErr, InstantUploadDto, InstantUploadErr, RegisterUploadTaskDto,
+    RegisterUploadTaskErr, RegisterUploadTaskResp, StoreSliceErr, UploadLimits, UploadSmallErr,
+    UploadStatusDto, UploadStatusErr, UploadTaskDto, UploadedUserFile,
+};
+use crate::application::file_system::usage::{self, DirUsageDto, DirUsageErr, SubDirUsage};
+use crate::application::file_system::version::{
+    self, FileVersionDto, ListVersionsErr, RestoreVersionDto, RestoreVersionErr,
 };
 use crate::application::file_system::video_info;
+use crate::application::import::{
+    self, ConnectDto, ConnectErr, ConnectorErr, ImportJobDto, ImportJobErr, ImportJobFileDto,
+    ImportSource, RemoteFile, StartImportDto, StartImportErr, StartImportResp,
+};
 use crate::application::transcode::TaskResult;
-use crate::domain::file_system::file::{FileOperateErr, UserFileId, VirtualPathErr};
+use crate::cqrs::comment::{CommentList, FileComment};
+use crate::cqrs::file_change::{FileChange, FileChangeList};
+use crate::cqrs::Paginate;
+use crate::domain::file_system::change::ChangeKind;
+use crate::domain::file_system::comment::{CommentErr, FileCommentId};
+use crate::domain::file_system::edit_lock::{EditLockErr, FileLocked};
+use crate::domain::file_system::file::{FileOperateErr, SysFileId, UserFileId, VirtualPathErr};
+use crate::domain::file_system::import_job::ImportJobId;
+use crate::domain::file_system::import_url::ImportTaskId;
 use crate::domain::file_system::service_upload::UploadTaskId;
+use crate::domain::file_system::share::{ShareErr, SharePermission};
+use crate::domain::file_system::tag::TagErr;
+use crate::domain::user::employee::Role;
 use crate::domain::user::user::UserId;
+use crate::http::client_ip::client_ip;
+use crate::http::idempotency::Idempotent;
+use crate::http::internal_auth::InternalAuth;
+use crate::http::playback_token::{self, TokenErr};
+use crate::http::validation::{ValidJson, ValidQuery};
 use crate::http::{ApiError, ApiResponse};
+use crate::presentation::organization::SpaceOwner;
 use crate::{http::ApiResult, status_doc};
 
 code! {
@@ -38,6 +104,9 @@ code! {
         parent_not_found = "父文件不存在",
         parent_not_dir = "父文件不是目录",
         recursived = "不能移动或复制到子目录",
+        not_file = "不是文件",
+        file_busy = "文件正在转码中",
+        locked = "文件被其他用户锁定",
     }
 
     pub PathFormat = 210 {
@@ -45,26 +114,246 @@ code! {
         bad_file_name = "文件名不合法",
         too_long = "路径过长",
         must_absolute = "必须是绝对路径",
+        empty_name = "文件名不能为空",
+        reserved_name = "文件名是系统保留的名称",
+    }
+
+    pub AdminFs = 220 {
+        read_only = "只有查看权限，不能修改用户的文件",
+    }
+
+    pub ImportSource = 230 {
+        not_configured = "不支持从该服务导入",
+        not_connected = "未授权或授权已过期，请重新授权",
+    }
+
+    pub Storage = 240 {
+        insufficient = "存储空间不足，请稍后重试",
+    }
+
+    pub UploadLimit = 250 {
+        quota_exceeded = "存储空间已用完，请升级会员或清理文件",
+        too_many_uploads = "上传太频繁了，请稍后再试",
     }
 
     ---
 
     RegisterUploadTask {
+        use UploadLimit,
         no_parent = "父目录不存在",
         parent_not_dir = "父级文件不是目录",
         bad_file_name = "文件名不合法",
+        bad_layout = "分片方式不合法",
+        file_too_large = "文件大小超过限制",
+        slice_too_large = "分片大小超过限制",
     }
 
     UploadSlice {
         no_task = "任务不存在",
+        index_out_of_range = "分片序号超出范围",
+        too_large = "分片大小超过限制",
+        wrong_size = "分片大小与注册时声明的不一致",
+    }
+
+    UploadStatus {
+        no_task = "任务不存在",
+    }
+
+    DownloadDir {
+        not_found = "目录不存在",
+        not_dir = "不是目录",
+    }
+
+    Manifest {
+        not_found = "目录不存在",
+        not_dir = "不是目录",
+    }
+
+    ResolvePath {
+        bad_path = "路径格式不正确",
+        not_found = "文件不存在或已删除",
+    }
+
+    AdminBrowse {
+        not_found = "目录不存在",
+        not_dir = "不是目录",
+    }
+
+    AttachSubtitle {
+        video_not_found = "视频不存在",
+        subtitle_not_found = "字幕文件不存在",
+        not_a_video = "不是视频文件",
+        not_subtitle = "不支持的字幕格式",
+    }
+
+    Verify {
+        not_found = "文件不存在",
+    }
+
+    BackfillVideoInfo {
+        already_running = "正在补全中",
+    }
+
+    Ingest {
+        use AdminFs,
+        already_running = "已有导入任务在运行",
+        user_not_found = "用户不存在",
+        source_not_found = "目录不存在",
+        source_not_dir = "不是目录",
+        source_not_allowed = "不允许导入该目录",
+    }
+
+    AdminUploadTask {
+        use AdminFs,
+        no_task = "任务不存在",
+    }
+
+    UploadSmall {
+        use UploadLimit,
+        no_parent = "父目录不存在",
+        too_large = "文件过大，请使用分片上传",
+        type_not_allowed = "不允许上传该类型的文件",
+    }
+
+    ImportUrl {
+        bad_url = "只支持 http 和 https 地址",
+        host_not_allowed = "不允许访问该地址",
+        no_parent = "父目录不存在",
+        no_file_name = "无法从地址中获取文件名，请指定文件名",
+        unreachable = "无法连接到远程服务器",
+        bad_status = "远程服务器返回错误",
+        unsupported_type = "不支持的文件类型",
+        too_large = "文件过大",
+    }
+
+    ImportStatus {
+        no_task = "任务不存在",
+    }
+
+    ConnectImportSource {
+        use ImportSource,
+        bad_state = "授权已失效，请重新授权",
+        exchange_failed = "授权失败",
+    }
+
+    StartImportJob {
+        use ImportSource,
+        no_files = "请选择要导入的文件",
+        too_many_files = "一次导入的文件过多",
+        no_parent = "目标目录不存在",
+    }
+
+    ImportJob {
+        not_found = "任务不存在",
+    }
+
+    InstantUpload {
+        use UploadLimit,
+        hash_not_existed = "文件不存在，请使用分片上传",
+        no_parent = "父目录不存在",
+        type_not_allowed = "不允许上传该类型的文件",
+    }
+
+    ListVersions {
+        not_found = "文件不存在",
+    }
+
+    RestoreVersion {
+        file_not_found = "文件不存在",
+        version_not_found = "版本不存在",
+    }
+
+    CreateComment {
+        file_not_found = "文件不存在",
+        bad_content = "评论内容不合法",
+    }
+
+    UpdateComment {
+        not_found = "评论不存在",
+        bad_content = "评论内容不合法",
+        not_author = "只有作者可以修改评论",
+    }
+
+    DeleteComment {
+        not_found = "评论不存在",
+        not_allowed = "没有权限删除该评论",
+    }
+
+    ListComments {
+        not_found = "文件不存在",
+    }
+
+    AddTag {
+        file_not_found = "文件不存在",
+        bad_name = "标签名不合法",
+        too_many_tags = "文件的标签数量已达上限",
+    }
+
+    RemoveTag {
+        file_not_found = "文件不存在",
+    }
+
+    Star {
+        file_not_found = "文件不存在",
+    }
+
+    DirUsage {
+        not_found = "目录不存在",
+        not_dir = "不是目录",
+    }
+
+    BulkMove {
+        parent_not_found = "目标目录不存在",
+    }
+
+    ShareFolder {
+        dir_not_found = "目录不存在",
+        not_dir = "只能分享目录",
+        share_with_self = "不能分享给自己",
+        user_not_found = "用户不存在",
+    }
+
+    UnshareFolder {
+        not_shared = "没有分享给该用户",
+    }
+
+    EditLock {
+        use FileOperate,
+        file_not_found = "文件不存在",
+        not_file = "只能锁定文件",
+        invalid_ttl = "锁的有效期超出范围",
+        not_locked = "文件没有被锁定或锁已过期",
+    }
+
+    PlaybackUrl {
+        file_not_found = "文件不存在",
+        not_file = "只能播放文件",
+        not_configured = "未配置播放地址的签名密钥",
+        unknown_ip = "无法获取客户端 IP，不能绑定",
+    }
+
+    Play {
+        invalid_token = "播放地址无效",
+        expired = "播放地址已过期",
+        file_not_found = "文件不存在",
     }
 
     FinishUpload {
+        use UploadLimit,
         no_task = "任务不存在",
         hash_not_match = "文件hash不匹配",
         sys_busy = "系统繁忙",
         no_parent = "父目录不存在",
         no_slice = "文件片段不存在",
+        type_not_allowed = "不允许上传该类型的文件",
+        file_too_large = "文件大小超过限制",
+        merging = "文件合并中，请稍后重试",
+        missing_slices = "还有分片没有上传",
+        size_not_match = "文件大小与注册时声明的不一致",
+    }
+
+    Ancestors {
+        not_found = "文件不存在",
     }
 }
 
@@ -72,6 +361,10 @@ impl From<RegisterUploadTaskErr> for ApiError {
     fn from(value: RegisterUploadTaskErr) -> Self {
         match value {
             RegisterUploadTaskErr::NoParent => REGISTER_UPLOAD_TASK.no_parent.into(),
+            RegisterUploadTaskErr::FileTooLarge => REGISTER_UPLOAD_TASK.file_too_large.into(),
+            RegisterUploadTaskErr::SliceTooLarge => REGISTER_UPLOAD_TASK.slice_too_large.into(),
+            RegisterUploadTaskErr::QuotaExceeded => UPLOAD_LIMIT.quota_exceeded.into(),
+            RegisterUploadTaskErr::TooManyUploads => UPLOAD_LIMIT.too_many_uploads.into(),
             RegisterUploadTaskErr::Create(c) => match c {
                 crate::domain::file_system::service_upload::CreateTaskErr::ParentNotDir => {
                     REGISTER_UPLOAD_TASK.parent_not_dir.into()
@@ -79,6 +372,9 @@ impl From<RegisterUploadTaskErr> for ApiError {
                 crate::domain::file_system::service_upload::CreateTaskErr::BadFileName => {
                     REGISTER_UPLOAD_TASK.bad_file_name.into()
                 }
+                crate::domain::file_system::service_upload::CreateTaskErr::BadLayout => {
+                    REGISTER_UPLOAD_TASK.bad_layout.into()
+                }
             },
         }
     }
@@ -88,6 +384,309 @@ impl From<StoreSliceErr> for ApiError {
     fn from(value: StoreSliceErr) -> Self {
         match value {
             StoreSliceErr::NoTask => UPLOAD_SLICE.no_task.into(),
+            StoreSliceErr::IndexOutOfRange => UPLOAD_SLICE.index_out_of_range.into(),
+            StoreSliceErr::TooLarge => UPLOAD_SLICE.too_large.into(),
+            StoreSliceErr::WrongSize => UPLOAD_SLICE.wrong_size.into(),
+        }
+    }
+}
+
+impl From<UploadStatusErr> for ApiError {
+    fn from(value: UploadStatusErr) -> Self {
+        match value {
+            UploadStatusErr::NoTask => UPLOAD_STATUS.no_task.into(),
+        }
+    }
+}
+
+impl From<DownloadDirErr> for ApiError {
+    fn from(value: DownloadDirErr) -> Self {
+        match value {
+            DownloadDirErr::NotFound => DOWNLOAD_DIR.not_found.into(),
+            DownloadDirErr::NotDir => DOWNLOAD_DIR.not_dir.into(),
+        }
+    }
+}
+
+impl From<ManifestErr> for ApiError {
+    fn from(value: ManifestErr) -> Self {
+        match value {
+            ManifestErr::NotFound => MANIFEST.not_found.into(),
+            ManifestErr::NotDir => MANIFEST.not_dir.into(),
+        }
+    }
+}
+
+impl From<ResolvePathErr> for ApiError {
+    fn from(value: ResolvePathErr) -> Self {
+        match value {
+            ResolvePathErr::BadPath => RESOLVE_PATH.bad_path.into(),
+            ResolvePathErr::NotFound => RESOLVE_PATH.not_found.into(),
+        }
+    }
+}
+
+impl From<AncestorsErr> for ApiError {
+    fn from(value: AncestorsErr) -> Self {
+        match value {
+            AncestorsErr::NotFound => ANCESTORS.not_found.into(),
+        }
+    }
+}
+
+impl From<AdminUploadErr> for ApiError {
+    fn from(value: AdminUploadErr) -> Self {
+        match value {
+            AdminUploadErr::ReadOnly => ADMIN_FS.read_only.into(),
+            AdminUploadErr::NoTask => ADMIN_UPLOAD_TASK.no_task.into(),
+            AdminUploadErr::Finish(err) => err.into(),
+        }
+    }
+}
+
+impl From<AdminFsErr> for ApiError {
+    fn from(value: AdminFsErr) -> Self {
+        match value {
+            AdminFsErr::ReadOnly => ADMIN_FS.read_only.into(),
+            AdminFsErr::Operate(err) => err.into(),
+        }
+    }
+}
+
+impl From<IngestErr> for ApiError {
+    fn from(value: IngestErr) -> Self {
+        match value {
+            IngestErr::ReadOnly => ADMIN_FS.read_only.into(),
+            IngestErr::AlreadyRunning => INGEST.already_running.into(),
+            IngestErr::UserNotFound => INGEST.user_not_found.into(),
+            IngestErr::SourceNotFound => INGEST.source_not_found.into(),
+            IngestErr::SourceNotDir => INGEST.source_not_dir.into(),
+            IngestErr::SourceNotAllowed => INGEST.source_not_allowed.into(),
+        }
+    }
+}
+
+impl From<BrowseErr> for ApiError {
+    fn from(value: BrowseErr) -> Self {
+        match value {
+            BrowseErr::NotFound => ADMIN_BROWSE.not_found.into(),
+            BrowseErr::NotDir => ADMIN_BROWSE.not_dir.into(),
+        }
+    }
+}
+
+impl From<AttachSubtitleErr> for ApiError {
+    fn from(value: AttachSubtitleErr) -> Self {
+        match value {
+            AttachSubtitleErr::VideoNotFound => ATTACH_SUBTITLE.video_not_found.into(),
+            AttachSubtitleErr::SubtitleNotFound => ATTACH_SUBTITLE.subtitle_not_found.into(),
+            AttachSubtitleErr::NotAVideo => ATTACH_SUBTITLE.not_a_video.into(),
+            AttachSubtitleErr::NotSubtitle => ATTACH_SUBTITLE.not_subtitle.into(),
+        }
+    }
+}
+
+impl From<VerifyErr> for ApiError {
+    fn from(value: VerifyErr) -> Self {
+        match value {
+            VerifyErr::NotFound => VERIFY.not_found.into(),
+        }
+    }
+}
+
+impl From<ImportUrlErr> for ApiError {
+    fn from(value: ImportUrlErr) -> Self {
+        match value {
+            ImportUrlErr::BadUrl => IMPORT_URL.bad_url.into(),
+            ImportUrlErr::HostNotAllowed => IMPORT_URL.host_not_allowed.into(),
+            ImportUrlErr::NoParent => IMPORT_URL.no_parent.into(),
+            ImportUrlErr::NoFileName => IMPORT_URL.no_file_name.into(),
+            ImportUrlErr::Unreachable => IMPORT_URL.unreachable.into(),
+            ImportUrlErr::BadStatus => IMPORT_URL.bad_status.into(),
+            ImportUrlErr::UnsupportedType => IMPORT_URL.unsupported_type.into(),
+            ImportUrlErr::TooLarge => IMPORT_URL.too_large.into(),
+            ImportUrlErr::QuotaExceeded => UPLOAD_LIMIT.quota_exceeded.into(),
+            ImportUrlErr::TooManyUploads => UPLOAD_LIMIT.too_many_uploads.into(),
+        }
+    }
+}
+
+impl From<ConnectorErr> for ApiError {
+    fn from(value: ConnectorErr) -> Self {
+        match value {
+            ConnectorErr::NotConfigured => IMPORT_SOURCE.not_configured.into(),
+            ConnectorErr::NotConnected => IMPORT_SOURCE.not_connected.into(),
+        }
+    }
+}
+
+impl From<ConnectErr> for ApiError {
+    fn from(value: ConnectErr) -> Self {
+        match value {
+            ConnectErr::Connector(err) => err.into(),
+            ConnectErr::BadState => CONNECT_IMPORT_SOURCE.bad_state.into(),
+            ConnectErr::ExchangeFailed => CONNECT_IMPORT_SOURCE.exchange_failed.into(),
+        }
+    }
+}
+
+impl From<StartImportErr> for ApiError {
+    fn from(value: StartImportErr) -> Self {
+        match value {
+            StartImportErr::Connector(err) => err.into(),
+            StartImportErr::NoFiles => START_IMPORT_JOB.no_files.into(),
+            StartImportErr::TooManyFiles => START_IMPORT_JOB.too_many_files.into(),
+            StartImportErr::NoParent => START_IMPORT_JOB.no_parent.into(),
+        }
+    }
+}
+
+impl From<ImportJobErr> for ApiError {
+    fn from(value: ImportJobErr) -> Self {
+        match value {
+            ImportJobErr::NotFound => IMPORT_JOB.not_found.into(),
+        }
+    }
+}
+
+impl From<ImportStatusErr> for ApiError {
+    fn from(value: ImportStatusErr) -> Self {
+        match value {
+            ImportStatusErr::NoTask => IMPORT_STATUS.no_task.into(),
+        }
+    }
+}
+
+impl From<UploadSmallErr> for ApiError {
+    fn from(value: UploadSmallErr) -> Self {
+        match value {
+            UploadSmallErr::NoParent => UPLOAD_SMALL.no_parent.into(),
+            UploadSmallErr::TooLarge => UPLOAD_SMALL.too_large.into(),
+            UploadSmallErr::TypeNotAllowed(_) => UPLOAD_SMALL.type_not_allowed.into(),
+            UploadSmallErr::QuotaExceeded => UPLOAD_LIMIT.quota_exceeded.into(),
+            UploadSmallErr::TooManyUploads => UPLOAD_LIMIT.too_many_uploads.into(),
+            UploadSmallErr::FsDomain(f) => f.into(),
+        }
+    }
+}
+
+impl From<InstantUploadErr> for ApiError {
+    fn from(value: InstantUploadErr) -> Self {
+        match value {
+            InstantUploadErr::HashNotExisted => INSTANT_UPLOAD.hash_not_existed.into(),
+            InstantUploadErr::NoParent => INSTANT_UPLOAD.no_parent.into(),
+            InstantUploadErr::TypeNotAllowed(_) => INSTANT_UPLOAD.type_not_allowed.into(),
+            InstantUploadErr::QuotaExceeded => UPLOAD_LIMIT.quota_exceeded.into(),
+            InstantUploadErr::TooManyUploads => UPLOAD_LIMIT.too_many_uploads.into(),
+            InstantUploadErr::FsDomain(f) => f.into(),
+        }
+    }
+}
+
+impl From<ListVersionsErr> for ApiError {
+    fn from(value: ListVersionsErr) -> Self {
+        match value {
+            ListVersionsErr::NotFound => LIST_VERSIONS.not_found.into(),
+        }
+    }
+}
+
+impl From<RestoreVersionErr> for ApiError {
+    fn from(value: RestoreVersionErr) -> Self {
+        match value {
+            RestoreVersionErr::FileNotFound => RESTORE_VERSION.file_not_found.into(),
+            RestoreVersionErr::VersionNotFound => RESTORE_VERSION.version_not_found.into(),
+            RestoreVersionErr::FsDomain(f) => f.into(),
+        }
+    }
+}
+
+impl From<CreateCommentErr> for ApiError {
+    fn from(value: CreateCommentErr) -> Self {
+        match value {
+            CreateCommentErr::FileNotFound => CREATE_COMMENT.file_not_found.into(),
+            CreateCommentErr::BadContent => CREATE_COMMENT.bad_content.into(),
+        }
+    }
+}
+
+impl From<UpdateCommentErr> for ApiError {
+    fn from(value: UpdateCommentErr) -> Self {
+        match value {
+            UpdateCommentErr::NotFound => UPDATE_COMMENT.not_found.into(),
+            UpdateCommentErr::Comment(CommentErr::BadContent) => UPDATE_COMMENT.bad_content.into(),
+            UpdateCommentErr::Comment(CommentErr::NotAuthor) => UPDATE_COMMENT.not_author.into(),
+        }
+    }
+}
+
+impl From<DeleteCommentErr> for ApiError {
+    fn from(value: DeleteCommentErr) -> Self {
+        match value {
+            DeleteCommentErr::NotFound => DELETE_COMMENT.not_found.into(),
+            DeleteCommentErr::NotAllowed => DELETE_COMMENT.not_allowed.into(),
+        }
+    }
+}
+
+impl From<AddTagErr> for ApiError {
+    fn from(value: AddTagErr) -> Self {
+        match value {
+            AddTagErr::FileNotFound => ADD_TAG.file_not_found.into(),
+            AddTagErr::Tag(TagErr::BadName) => ADD_TAG.bad_name.into(),
+            AddTagErr::Tag(TagErr::TooManyTags) => ADD_TAG.too_many_tags.into(),
+        }
+    }
+}
+
+impl From<RemoveTagErr> for ApiError {
+    fn from(value: RemoveTagErr) -> Self {
+        match value {
+            RemoveTagErr::FileNotFound => REMOVE_TAG.file_not_found.into(),
+        }
+    }
+}
+
+impl From<StarErr> for ApiError {
+    fn from(value: StarErr) -> Self {
+        match value {
+            StarErr::FileNotFound => STAR.file_not_found.into(),
+        }
+    }
+}
+
+impl From<DirUsageErr> for ApiError {
+    fn from(value: DirUsageErr) -> Self {
+        match value {
+            DirUsageErr::NotFound => DIR_USAGE.not_found.into(),
+            DirUsageErr::NotDir => DIR_USAGE.not_dir.into(),
+        }
+    }
+}
+
+impl From<BulkMoveErr> for ApiError {
+    fn from(value: BulkMoveErr) -> Self {
+        match value {
+            BulkMoveErr::ParentNotFound => BULK_MOVE.parent_not_found.into(),
+        }
+    }
+}
+
+impl From<ShareFolderErr> for ApiError {
+    fn from(value: ShareFolderErr) -> Self {
+        match value {
+            ShareFolderErr::Share(ShareErr::NotDir) => SHARE_FOLDER.not_dir.into(),
+            ShareFolderErr::Share(ShareErr::ShareWithSelf) => SHARE_FOLDER.share_with_self.into(),
+            ShareFolderErr::DirNotFound => SHARE_FOLDER.dir_not_found.into(),
+            ShareFolderErr::UserNotFound => SHARE_FOLDER.user_not_found.into(),
+        }
+    }
+}
+
+impl From<UnshareFolderErr> for ApiError {
+    fn from(value: UnshareFolderErr) -> Self {
+        match value {
+            UnshareFolderErr::NotShared => UNSHARE_FOLDER.not_shared.into(),
         }
     }
 }
@@ -99,6 +698,13 @@ impl From<FinishUploadTaskErr> for ApiError {
             FinishUploadTaskErr::HashNotMatch => FINISH_UPLOAD.hash_not_match.into(),
             FinishUploadTaskErr::NoParent => FINISH_UPLOAD.no_parent.into(),
             FinishUploadTaskErr::NoSlice => FINISH_UPLOAD.no_slice.into(),
+            FinishUploadTaskErr::TypeNotAllowed(_) => FINISH_UPLOAD.type_not_allowed.into(),
+            FinishUploadTaskErr::FileTooLarge => FINISH_UPLOAD.file_too_large.into(),
+            FinishUploadTaskErr::SysBusy => FINISH_UPLOAD.sys_busy.into(),
+            FinishUploadTaskErr::QuotaExceeded => UPLOAD_LIMIT.quota_exceeded.into(),
+            FinishUploadTaskErr::Merging => FINISH_UPLOAD.merging.into(),
+            FinishUploadTaskErr::MissingSlices(_) => FINISH_UPLOAD.missing_slices.into(),
+            FinishUploadTaskErr::SizeNotMatch => FINISH_UPLOAD.size_not_match.into(),
             FinishUploadTaskErr::FsDomain(f) => f.into(),
         }
     }
@@ -113,140 +719,1167 @@ impl From<FileOperateErr> for ApiError {
             FileOperateErr::ParentNotDir => FILE_OPERATE.parent_not_dir.into(),
             FileOperateErr::NoParent => FILE_OPERATE.parent_not_found.into(),
             FileOperateErr::Recursived => FILE_OPERATE.recursived.into(),
+            FileOperateErr::NotFile => FILE_OPERATE.not_file.into(),
+            FileOperateErr::FileBusy => FILE_OPERATE.file_busy.into(),
+            FileOperateErr::InsufficientStorage => STORAGE.insufficient.into(),
             FileOperateErr::Path(p) => p.into(),
+            FileOperateErr::Locked(locked) => locked.into(),
         }
     }
 }
 
-impl From<VirtualPathErr> for ApiError {
-    fn from(value: VirtualPathErr) -> Self {
-        match value {
-            VirtualPathErr::NotAllowed => PATH_FORMAT.not_allow.into(),
-            VirtualPathErr::BadFileName => PATH_FORMAT.bad_file_name.into(),
-            VirtualPathErr::TooLong => PATH_FORMAT.too_long.into(),
-            VirtualPathErr::MustAbsolute => PATH_FORMAT.must_absolute.into(),
-        }
-    }
+/// 文件被其他用户锁定，错误数据中带上锁的持有者和到期时间
+#[derive(Debug)]
+struct FileLockedErr(FileLocked);
+
+impl std::fmt::Display for FileLockedErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, holder: {} {}, expire at: {}",
+            FILE_OPERATE.locked,
+            self.0.holder_id,
+            self.0.holder_name.as_deref().unwrap_or_default(),
+            self.0.expire_at.timestamp_millis()
+        )
+    }
+}
+
+impl crate::http::HttpBizError for FileLockedErr {
+    fn code(&self) -> u32 {
+        FILE_OPERATE.locked.code
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        Some(serde_json::json!({
+            "fileId": self.0.file_id,
+            "holderId": self.0.holder_id,
+            "holderName": self.0.holder_name,
+            "expireAt": self.0.expire_at.timestamp_millis(),
+        }))
+    }
+}
+
+impl From<FileLocked> for ApiError {
+    fn from(value: FileLocked) -> Self {
+        FileLockedErr(value).into()
+    }
+}
+
+impl From<EditLockErr> for ApiError {
+    fn from(value: EditLockErr) -> Self {
+        match value {
+            EditLockErr::Locked(locked) => locked.into(),
+            EditLockErr::InvalidTtl => EDIT_LOCK.invalid_ttl.into(),
+            EditLockErr::NotLocked => EDIT_LOCK.not_locked.into(),
+        }
+    }
+}
+
+impl From<AcquireLockErr> for ApiError {
+    fn from(value: AcquireLockErr) -> Self {
+        match value {
+            AcquireLockErr::Lock(err) => err.into(),
+            AcquireLockErr::FileNotFound => EDIT_LOCK.file_not_found.into(),
+            AcquireLockErr::NotFile => EDIT_LOCK.not_file.into(),
+        }
+    }
+}
+
+impl From<PlaybackUrlErr> for ApiError {
+    fn from(value: PlaybackUrlErr) -> Self {
+        match value {
+            PlaybackUrlErr::Token(err) => err.into(),
+            PlaybackUrlErr::FileNotFound => PLAYBACK_URL.file_not_found.into(),
+            PlaybackUrlErr::NotFile => PLAYBACK_URL.not_file.into(),
+            PlaybackUrlErr::UnknownIp => PLAYBACK_URL.unknown_ip.into(),
+        }
+    }
+}
+
+impl From<TokenErr> for ApiError {
+    fn from(value: TokenErr) -> Self {
+        match value {
+            TokenErr::NotConfigured => PLAYBACK_URL.not_configured.into(),
+            TokenErr::Expired => PLAY.expired.into(),
+            TokenErr::Malformed | TokenErr::BadSignature | TokenErr::WrongFile => {
+                PLAY.invalid_token.into()
+            }
+        }
+    }
+}
+
+impl From<VirtualPathErr> for ApiError {
+    fn from(value: VirtualPathErr) -> Self {
+        match value {
+            VirtualPathErr::NotAllowed => PATH_FORMAT.not_allow.into(),
+            VirtualPathErr::BadFileName => PATH_FORMAT.bad_file_name.into(),
+            VirtualPathErr::TooLong => PATH_FORMAT.too_long.into(),
+            VirtualPathErr::MustAbsolute => PATH_FORMAT.must_absolute.into(),
+            VirtualPathErr::EmptyName => PATH_FORMAT.empty_name.into(),
+            VirtualPathErr::ReservedName => PATH_FORMAT.reserved_name.into(),
+        }
+    }
+}
+
+status_doc!();
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        load_home,
+        load_home_admin,
+        capability_admin,
+        browse_admin,
+        download_dir_admin,
+        create_dir,
+        create_dir_admin,
+        delete,
+        delete_admin,
+        copy,
+        copy_admin,
+        move_to,
+        move_to_admin,
+        rename,
+        rename_admin,
+        download_dir,
+        dir_manifest,
+        list_changes,
+        resolve_path,
+        ancestors,
+        attach_subtitle,
+        list_versions,
+        restore_version,
+        create_comment,
+        list_comments,
+        update_comment,
+        delete_comment,
+        add_tag,
+        remove_tag,
+        star_file,
+        unstar_file,
+        dir_usage,
+        share_folder,
+        unshare_folder,
+        share_members,
+        acquire_lock,
+        release_lock,
+        steal_lock,
+        playback_url,
+        play,
+        bulk_delete,
+        bulk_move,
+        bulk_copy,
+        thumbnail_paths,
+        thumbnail_paths_admin,
+        thumbnail_file,
+        register_upload_task,
+        del_upload_task,
+        get_upload_tasks,
+        clear_upload_tasks,
+        upload_status,
+        upload_limits,
+        upload_slice,
+        upload_finished,
+        upload_small,
+        instant_upload,
+        import_url,
+        import_status,
+        import_authorize_url,
+        import_connect,
+        import_list,
+        import_start,
+        import_job,
+        file_parsed,
+        thumbnail_generated,
+        verify_admin,
+        orphan_scan_admin,
+        consistency_check_admin,
+        dir_cache_stats_admin,
+        backfill_video_info_admin,
+        ingest_admin,
+        ingest_progress_admin,
+        upload_tasks_admin,
+        expire_upload_task_admin,
+        complete_upload_task_admin,
+    ),
+    components(schemas(
+        DirTree,
+        CreateDirDto,
+        CreateDirResp,
+        AdminCreateDirDto,
+        DeleteDto,
+        AdminDeleteDto,
+        MoveToParams,
+        AdminMoveToParams,
+        RenameParams,
+        AdminRenameParams,
+        AdminFsCapabilityDto,
+        AdminFileEntry,
+        AttachSubtitleDto,
+        FileVersionDto,
+        RestoreVersionDto,
+        CreateCommentDto,
+        CreateCommentResp,
+        UpdateCommentDto,
+        DeleteCommentDto,
+        CommentList,
+        FileComment,
+        FileTagDto,
+        StarDto,
+        DirUsageDto,
+        SubDirUsage,
+        ShareFolderDto,
+        UnshareFolderDto,
+        ShareMemberDto,
+        SharePermission,
+        AcquireLockDto,
+        ReleaseLockDto,
+        FileLockDto,
+        PlaybackUrlDto,
+        PlaybackUrl,
+        BulkItemResult,
+        RegisterUploadTaskDto,
+        RegisterUploadTaskResp,
+        UploadTaskDto,
+        UploadStatusDto,
+        UploadLimits,
+        DelUplodTask,
+        UploadSliceForm,
+        UploadSmallForm,
+        InstantUploadDto,
+        UploadFinishedParam,
+        UploadedUserFile,
+        ImportUrlDto,
+        ImportUrlResp,
+        ImportTaskDto,
+        ImportSource,
+        ConnectDto,
+        RemoteFile,
+        StartImportDto,
+        StartImportResp,
+        ImportJobDto,
+        ImportJobFileDto,
+        VerifyResult,
+        OrphanScanDto,
+        OrphanScanResult,
+        OrphanDir,
+        ConsistencyCheckDto,
+        ConsistencyReport,
+        Inconsistency,
+        DirCacheStats,
+        IngestDto,
+        IngestProgress,
+        AdminUploadTaskDto,
+        ManifestEntry,
+        ChangeKind,
+        FileChange,
+        FileChangeList,
+        ResolvedPath,
+        Ancestor,
+    ))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("register_upload_task", "RegisterUploadTask"),
+    ("upload_slice", "UploadSlice"),
+    ("upload_status", "UploadStatus"),
+    ("download_dir", "DownloadDir"),
+    ("download_dir_admin", "DownloadDir"),
+    ("dir_manifest", "Manifest"),
+    ("resolve_path", "ResolvePath"),
+    ("ancestors", "Ancestors"),
+    ("browse_admin", "AdminBrowse"),
+    ("attach_subtitle", "AttachSubtitle"),
+    ("list_versions", "ListVersions"),
+    ("restore_version", "RestoreVersion"),
+    ("create_comment", "CreateComment"),
+    ("list_comments", "ListComments"),
+    ("update_comment", "UpdateComment"),
+    ("delete_comment", "DeleteComment"),
+    ("add_tag", "AddTag"),
+    ("remove_tag", "RemoveTag"),
+    ("star_file", "Star"),
+    ("dir_usage", "DirUsage"),
+    ("share_folder", "ShareFolder"),
+    ("unshare_folder", "UnshareFolder"),
+    ("share_members", "ShareFolder"),
+    ("acquire_lock", "EditLock"),
+    ("release_lock", "EditLock"),
+    ("steal_lock", "EditLock"),
+    ("playback_url", "PlaybackUrl"),
+    ("play", "Play"),
+    ("bulk_move", "BulkMove"),
+    ("bulk_copy", "BulkMove"),
+    ("verify_admin", "Verify"),
+    ("backfill_video_info_admin", "BackfillVideoInfo"),
+    ("ingest_admin", "Ingest"),
+    ("expire_upload_task_admin", "AdminUploadTask"),
+    ("complete_upload_task_admin", "AdminUploadTask"),
+    ("upload_small", "UploadSmall"),
+    ("instant_upload", "InstantUpload"),
+    ("import_url", "ImportUrl"),
+    ("import_status", "ImportStatus"),
+    ("import_connect", "ConnectImportSource"),
+    ("import_start", "StartImportJob"),
+    ("import_job", "ImportJob"),
+    ("upload_finished", "FinishUpload"),
+];
+
+pub fn actix_config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/fs")
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
+            .service(web::resource("/home").route(web::get().to(load_home)))
+            .service(
+                web::resource("/create_dir")
+                    .wrap(Idempotent)
+                    .route(web::post().to(create_dir)),
+            )
+            .service(web::resource("/delete").route(web::post().to(delete)))
+            .service(web::resource("/copy").route(web::post().to(copy)))
+            .service(web::resource("/move").route(web::post().to(move_to)))
+            .service(web::resource("/rename").route(web::post().to(rename)))
+            // 逐项处理，返回每一项的结果
+            .service(web::resource("/bulk/delete").route(web::post().to(bulk_delete)))
+            .service(web::resource("/bulk/move").route(web::post().to(bulk_move)))
+            .service(web::resource("/bulk/copy").route(web::post().to(bulk_copy)))
+            .service(web::resource("/download_dir/{dir_id}").route(web::get().to(download_dir)))
+            .service(web::resource("/manifest/{dir_id}").route(web::get().to(dir_manifest)))
+            .service(web::resource("/changes").route(web::get().to(list_changes)))
+            .service(web::resource("/resolve_path").route(web::get().to(resolve_path)))
+            .service(web::resource("/ancestors/{file_id}").route(web::get().to(ancestors)))
+            .service(web::resource("/attach_subtitle").route(web::post().to(attach_subtitle)))
+            .service(web::resource("/versions/{file_id}").route(web::get().to(list_versions)))
+            .service(web::resource("/restore_version").route(web::post().to(restore_version)))
+            // comment
+            .service(web::resource("/comment/create").route(web::post().to(create_comment)))
+            .service(web::resource("/comments/{file_id}").route(web::get().to(list_comments)))
+            .service(web::resource("/comment/update").route(web::post().to(update_comment)))
+            .service(web::resource("/comment/delete").route(web::post().to(delete_comment)))
+            // tag
+            .service(web::resource("/tag/add").route(web::post().to(add_tag)))
+            .service(web::resource("/tag/remove").route(web::post().to(remove_tag)))
+            // star
+            .service(web::resource("/star").route(web::post().to(star_file)))
+            .service(web::resource("/unstar").route(web::post().to(unstar_file)))
+            .service(web::resource("/usage/{dir_id}").route(web::get().to(dir_usage)))
+            .service(web::resource("/share").route(web::post().to(share_folder)))
+            .service(web::resource("/unshare").route(web::post().to(unshare_folder)))
+            .service(web::resource("/share_members/{dir_id}").route(web::get().to(share_members)))
+            .service(web::resource("/lock").route(web::post().to(acquire_lock)))
+            .service(web::resource("/lock/steal").route(web::post().to(steal_lock)))
+            .service(web::resource("/unlock").route(web::post().to(release_lock)))
+            .service(web::resource("/playback_url").route(web::post().to(playback_url)))
+            .service(web::resource("/play/{file_id}").route(web::get().to(play)))
+            // thumbnail
+            .service(web::resource("/thumbnails").route(web::get().to(thumbnail_paths)))
+            .service(thumbnail_file)
+            // upload
+            .service(
+                web::resource("/register_upload_task").route(web::post().to(register_upload_task)),
+            )
+            .service(web::resource("/del_upload_task").route(web::post().to(del_upload_task)))
+            .service(
+                web::resource("/upload_tasks")
+                    .route(web::get().to(get_upload_tasks))
+                    .route(web::delete().to(clear_upload_tasks)),
+            )
+            .service(web::resource("/upload_status/{task_id}").route(web::get().to(upload_status)))
+            .service(web::resource("/limits").route(web::get().to(upload_limits)))
+            .service(web::resource("/upload_slice").route(web::post().to(upload_slice)))
+            .service(
+                web::resource("/finish_upload")
+                    .wrap(Idempotent)
+                    .route(web::post().to(upload_finished)),
+            )
+            .service(web::resource("/upload_small").route(web::post().to(upload_small)))
+            .service(
+                web::resource("/instant_upload")
+                    .wrap(Idempotent)
+                    .route(web::post().to(instant_upload)),
+            )
+            .service(
+                web::resource("/import_url")
+                    .wrap(Idempotent)
+                    .route(web::post().to(import_url)),
+            )
+            .service(web::resource("/import_url/{task_id}").route(web::get().to(import_status)))
+            .service(
+                web::scope("/import")
+                    .service(
+                        web::resource("/authorize_url").route(web::get().to(import_authorize_url)),
+                    )
+                    .service(web::resource("/connect").route(web::post().to(import_connect)))
+                    .service(web::resource("/list").route(web::get().to(import_list)))
+                    .service(
+                        web::resource("/jobs")
+                            .wrap(Idempotent)
+                            .route(web::post().to(import_start)),
+                    )
+                    .service(web::resource("/jobs/{job_id}").route(web::get().to(import_job))),
+            ),
+    )
+    .service(
+        // from factory
+        web::scope("/internal/fs")
+            .wrap(InternalAuth)
+            .service(web::resource("/file_parsed").route(web::post().to(file_parsed)))
+            .service(
+                web::resource("/thumbnail_generated").route(web::post().to(thumbnail_generated)),
+            ),
+    )
+    .service(
+        web::scope("/admin/fs")
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
+            .service(web::resource("/home").route(web::get().to(load_home_admin)))
+            // 只读，任何员工都可以调用
+            .service(web::resource("/capability").route(web::get().to(capability_admin)))
+            .service(web::resource("/browse/{user_id}/{dir_id}").route(web::get().to(browse_admin)))
+            .service(
+                web::resource("/download_dir/{user_id}/{dir_id}")
+                    .route(web::get().to(download_dir_admin)),
+            )
+            // 只读权限的员工调用时返回 read_only
+            .service(web::resource("/create_dir").route(web::post().to(create_dir_admin)))
+            .service(web::resource("/delete").route(web::post().to(delete_admin)))
+            .service(web::resource("/copy").route(web::post().to(copy_admin)))
+            .service(web::resource("/move").route(web::post().to(move_to_admin)))
+            .service(web::resource("/rename").route(web::post().to(rename_admin)))
+            .service(web::resource("/thumbnails").route(web::get().to(thumbnail_paths_admin)))
+            .service(thumbnail_file)
+            .service(web::resource("/verify/{sys_file_id}").route(web::post().to(verify_admin)))
+            .service(web::resource("/orphan_scan").route(web::post().to(orphan_scan_admin)))
+            .service(
+                web::resource("/consistency_check").route(web::post().to(consistency_check_admin)),
+            )
+            .service(web::resource("/dir_cache").route(web::get().to(dir_cache_stats_admin)))
+            .service(
+                web::resource("/backfill_video_info")
+                    .route(web::post().to(backfill_video_info_admin)),
+            )
+            .service(
+                web::resource("/ingest")
+                    .route(web::post().to(ingest_admin))
+                    .route(web::get().to(ingest_progress_admin)),
+            )
+            .service(web::resource("/upload_tasks").route(web::get().to(upload_tasks_admin)))
+            .service(
+                web::resource("/upload_tasks/{task_id}/expire")
+                    .route(web::post().to(expire_upload_task_admin)),
+            )
+            .service(
+                web::resource("/upload_tasks/{task_id}/complete")
+                    .route(web::post().to(complete_upload_task_admin)),
+            ),
+    );
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+struct LoadHomeParams {
+    user_id: UserId,
+}
+
+/// 加载用户的目录树
+#[utoipa::path(
+    get,
+    path = "/admin/fs/home",
+    params(LoadHomeParams),
+    responses((status = 200, body = DirTree)),
+    tag = "fs"
+)]
+async fn load_home_admin(_id: Identity, params: Query<LoadHomeParams>) -> ApiResult<DirTree> {
+    let tree = service::load_home(params.user_id).await?;
+    ApiResponse::Ok(tree)
+}
+
+/// 当前员工对用户文件的权限，由登录时的角色决定
+fn fs_capability(req: &HttpRequest) -> anyhow::Result<FsCapability> {
+    let role = req.get_session().get::<Role>("role")?;
+    Ok(FsCapability::of(role))
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct AdminFsCapabilityDto {
+    /// 为 true 时只能浏览、下载和预览，修改类的接口会返回错误
+    read_only: bool,
+}
+
+/// 当前员工对用户文件的权限
+#[utoipa::path(
+    get,
+    path = "/admin/fs/capability",
+    responses((status = 200, body = AdminFsCapabilityDto)),
+    tag = "fs"
+)]
+async fn capability_admin(_id: Identity, req: HttpRequest) -> ApiResult<AdminFsCapabilityDto> {
+    let cap = fs_capability(&req)?;
+    ApiResponse::Ok(AdminFsCapabilityDto {
+        read_only: !cap.writable(),
+    })
+}
+
+/// 浏览用户的目录
+#[utoipa::path(
+    get,
+    path = "/admin/fs/browse/{user_id}/{dir_id}",
+    params(
+        ("user_id" = UserId, Path, description = "用户 id"),
+        ("dir_id" = UserFileId, Path, description = "文件夹 id"),
+    ),
+    responses((status = 200, body = [AdminFileEntry])),
+    tag = "fs"
+)]
+async fn browse_admin(
+    _id: Identity,
+    path: web::Path<(UserId, UserFileId)>,
+) -> ApiResult<Vec<AdminFileEntry>> {
+    let (user_id, dir_id) = path.into_inner();
+    let entries = admin::list_dir(user_id, dir_id).await??;
+    ApiResponse::Ok(entries)
+}
+
+/// 以 zip 压缩包下载用户的文件夹
+#[utoipa::path(
+    get,
+    path = "/admin/fs/download_dir/{user_id}/{dir_id}",
+    params(
+        ("user_id" = UserId, Path, description = "用户 id"),
+        ("dir_id" = UserFileId, Path, description = "文件夹 id"),
+    ),
+    responses((status = 200, body = String, content_type = "application/zip")),
+    tag = "fs"
+)]
+async fn download_dir_admin(
+    _id: Identity,
+    path: web::Path<(UserId, UserFileId)>,
+) -> Result<HttpResponse, ApiError> {
+    let (user_id, dir_id) = path.into_inner();
+    let archive = service::dir_archive(user_id, dir_id).await??;
+
+    let disposition = attachment(archive.name.clone());
+    let resp = HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(disposition)
+        .streaming(archive.into_stream());
+    Ok(resp)
+}
+
+/// 加载目录树
+#[utoipa::path(
+    get,
+    path = "/api/fs/home",
+    responses((status = 200, body = DirTree)),
+    tag = "fs"
+)]
+async fn load_home(owner: SpaceOwner) -> ApiResult<DirTree> {
+    let id = owner.0;
+    let tree = service::load_home(id).await?;
+    ApiResponse::Ok(tree)
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+struct CreateDirDto {
+    pub parent_id: UserFileId,
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[aliases(
+    AdminCreateDirDto = AdminParams<CreateDirDto>,
+    AdminDeleteDto = AdminParams<DeleteDto>,
+    AdminMoveToParams = AdminParams<MoveToParams>,
+    AdminRenameParams = AdminParams<RenameParams>
+)]
+struct AdminParams<T> {
+    user_id: UserId,
+    #[serde(flatten)]
+    params: T,
+}
+
+/// 为用户创建文件夹
+#[utoipa::path(
+    post,
+    path = "/admin/fs/create_dir",
+    request_body = AdminCreateDirDto,
+    responses((status = 200, body = CreateDirResp)),
+    tag = "fs"
+)]
+async fn create_dir_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<AdminParams<CreateDirDto>>,
+) -> ApiResult<CreateDirResp> {
+    let AdminParams {
+        user_id,
+        params: CreateDirDto { parent_id, name },
+    } = params.into_inner();
+    let cap = fs_capability(&req)?;
+    let file_id = admin::create_dir(cap, user_id, parent_id, &name).await??;
+    ApiResponse::Ok(CreateDirResp { file_id })
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct CreateDirResp {
+    pub file_id: UserFileId,
+}
+
+/// 创建文件夹
+#[utoipa::path(
+    post,
+    path = "/api/fs/create_dir",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = CreateDirDto,
+    responses((status = 200, body = CreateDirResp)),
+    tag = "fs"
+)]
+async fn create_dir(
+    owner: SpaceOwner,
+    params: ValidJson<CreateDirDto>,
+) -> ApiResult<CreateDirResp> {
+    let id = owner.0;
+    let CreateDirDto { parent_id, name } = params.into_inner();
+    let file_id = service::create_dir(id, parent_id, &name).await??;
+    ApiResponse::Ok(CreateDirResp { file_id })
+}
+
+/// 以 zip 压缩包下载文件夹
+#[utoipa::path(
+    get,
+    path = "/api/fs/download_dir/{dir_id}",
+    params(("dir_id" = UserFileId, Path, description = "文件夹 id")),
+    responses((status = 200, body = String, content_type = "application/zip")),
+    tag = "fs"
+)]
+async fn download_dir(
+    owner: SpaceOwner,
+    dir_id: web::Path<UserFileId>,
+) -> Result<HttpResponse, ApiError> {
+    let id = owner.0;
+    let dir_id = dir_id.into_inner();
+    let archive = service::dir_archive(id, dir_id).await??;
+    recent::touch(id, dir_id).await;
+    access_log::record(dir_id, AccessKind::Download, Some(id));
+
+    let disposition = attachment(archive.name.clone());
+    let resp = HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(disposition)
+        .streaming(archive.into_stream());
+    Ok(resp)
+}
+
+/// 文件夹下所有文件的清单，每行一个 ManifestEntry，按路径排序。客户端据此只上传有变化的文件
+#[utoipa::path(
+    get,
+    path = "/api/fs/manifest/{dir_id}",
+    params(("dir_id" = UserFileId, Path, description = "文件夹 id")),
+    responses((status = 200, body = ManifestEntry, content_type = "application/x-ndjson")),
+    tag = "fs"
+)]
+async fn dir_manifest(
+    owner: SpaceOwner,
+    dir_id: web::Path<UserFileId>,
+) -> Result<HttpResponse, ApiError> {
+    let manifest = manifest::dir_manifest(owner.0, dir_id.into_inner()).await??;
+    let resp = HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(manifest.into_stream());
+    Ok(resp)
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListChangesParams {
+    /// 上次返回的游标，为空时只返回当前的游标
+    since: Option<String>,
+    #[serde(default = "default_changes_limit")]
+    #[validate(range(min = 1, max = 1000))]
+    limit: u32,
+}
+
+fn default_changes_limit() -> u32 {
+    500
+}
+
+/// 游标之后按顺序发生的创建、修改、重命名、移动和删除，用于增量同步。
+/// 新建的目录只有一条记录，其中的内容需要读取目录清单
+#[utoipa::path(
+    get,
+    path = "/api/fs/changes",
+    params(ListChangesParams),
+    responses((status = 200, body = FileChangeList)),
+    tag = "fs"
+)]
+async fn list_changes(
+    owner: SpaceOwner,
+    params: ValidQuery<ListChangesParams>,
+) -> ApiResult<FileChangeList> {
+    let changes = FileChangeList::load(owner.0, params.since.as_deref(), params.limit).await?;
+    ApiResponse::Ok(changes)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResolvePathParams {
+    /// 文件的路径，可以是重命名或移动前的路径
+    path: String,
+}
+
+/// 按路径查找文件。文件被重命名或移动过时，旧路径返回文件现在的 id 和路径
+#[utoipa::path(
+    get,
+    path = "/api/fs/resolve_path",
+    params(ResolvePathParams),
+    responses((status = 200, body = ResolvedPath)),
+    tag = "fs"
+)]
+async fn resolve_path(
+    owner: SpaceOwner,
+    params: Query<ResolvePathParams>,
+) -> ApiResult<ResolvedPath> {
+    let resolved = path_history::resolve(owner.0, &params.path).await??;
+    ApiResponse::Ok(resolved)
+}
+
+/// 文件的上级目录链，从根目录开始排列，用于显示文件所在的位置
+#[utoipa::path(
+    get,
+    path = "/api/fs/ancestors/{file_id}",
+    params(("file_id" = UserFileId, Path, description = "文件 id")),
+    responses((status = 200, body = [Ancestor])),
+    tag = "fs"
+)]
+async fn ancestors(owner: SpaceOwner, file_id: web::Path<UserFileId>) -> ApiResult<Vec<Ancestor>> {
+    let ancestors = breadcrumb::ancestors(owner.0, file_id.into_inner()).await??;
+    ApiResponse::Ok(ancestors)
+}
+
+/// 将字幕关联到视频
+#[utoipa::path(
+    post,
+    path = "/api/fs/attach_subtitle",
+    request_body = AttachSubtitleDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn attach_subtitle(owner: SpaceOwner, params: Json<AttachSubtitleDto>) -> ApiResult<()> {
+    let id = owner.0;
+    subtitle::attach_subtitle(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 文件的历史版本，从新到旧排列
+#[utoipa::path(
+    get,
+    path = "/api/fs/versions/{file_id}",
+    params(("file_id" = UserFileId, Path, description = "文件 id")),
+    responses((status = 200, body = [FileVersionDto])),
+    tag = "fs"
+)]
+async fn list_versions(
+    owner: SpaceOwner,
+    file_id: web::Path<UserFileId>,
+) -> ApiResult<Vec<FileVersionDto>> {
+    let id = owner.0;
+    let resp = version::list_versions(id, file_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 恢复到历史版本，当前内容保存为新的历史版本
+#[utoipa::path(
+    post,
+    path = "/api/fs/restore_version",
+    request_body = RestoreVersionDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn restore_version(owner: SpaceOwner, params: Json<RestoreVersionDto>) -> ApiResult<()> {
+    let id = owner.0;
+    version::restore_version(id, owner.member(), params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 评论文件，可以指定视频时间点
+#[utoipa::path(
+    post,
+    path = "/api/fs/comment/create",
+    request_body = CreateCommentDto,
+    responses((status = 200, body = CreateCommentResp)),
+    tag = "fs"
+)]
+async fn create_comment(
+    id: Identity,
+    params: ValidJson<CreateCommentDto>,
+) -> ApiResult<CreateCommentResp> {
+    let id = id.id()?.parse::<UserId>()?;
+    let resp = comment::create_comment(id, params.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListCommentsParams {
+    #[validate(range(min = 1))]
+    page: u32,
+    #[validate(range(min = 1, max = 100))]
+    page_size: u32,
+}
+
+/// 分页加载文件的评论，按时间顺序排列。有读权限即可查看
+#[utoipa::path(
+    get,
+    path = "/api/fs/comments/{file_id}",
+    params(
+        ("file_id" = UserFileId, Path, description = "文件 id"),
+        ListCommentsParams
+    ),
+    responses((status = 200, body = CommentList)),
+    tag = "fs"
+)]
+async fn list_comments(
+    id: Identity,
+    file_id: web::Path<UserFileId>,
+    params: ValidQuery<ListCommentsParams>,
+) -> ApiResult<CommentList> {
+    let id = id.id()?.parse::<UserId>()?;
+    let page = Paginate {
+        page: params.page,
+        page_size: params.page_size,
+    };
+    let file_id = file_id.into_inner();
+    let Some(owner) = share::readable_owner(id, file_id).await? else {
+        return Err(LIST_COMMENTS.not_found.into());
+    };
+    let Some(list) = CommentList::load_owned(owner, file_id, page).await? else {
+        return Err(LIST_COMMENTS.not_found.into());
+    };
+    ApiResponse::Ok(list)
+}
+
+/// 修改评论内容
+#[utoipa::path(
+    post,
+    path = "/api/fs/comment/update",
+    request_body = UpdateCommentDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn update_comment(id: Identity, params: ValidJson<UpdateCommentDto>) -> ApiResult<()> {
+    let id = id.id()?.parse::<UserId>()?;
+    comment::update_comment(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteCommentDto {
+    comment_id: FileCommentId,
+}
+
+/// 删除评论
+#[utoipa::path(
+    post,
+    path = "/api/fs/comment/delete",
+    request_body = DeleteCommentDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn delete_comment(id: Identity, params: Json<DeleteCommentDto>) -> ApiResult<()> {
+    let id = id.id()?.parse::<UserId>()?;
+    comment::delete_comment(id, params.comment_id).await??;
+    ApiResponse::Ok(())
+}
+
+/// 给文件添加标签，标签不存在时自动创建
+#[utoipa::path(
+    post,
+    path = "/api/fs/tag/add",
+    request_body = FileTagDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn add_tag(owner: SpaceOwner, params: ValidJson<FileTagDto>) -> ApiResult<()> {
+    let id = owner.0;
+    tag::add_tag(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 移除文件的标签
+#[utoipa::path(
+    post,
+    path = "/api/fs/tag/remove",
+    request_body = FileTagDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn remove_tag(owner: SpaceOwner, params: ValidJson<FileTagDto>) -> ApiResult<()> {
+    let id = owner.0;
+    tag::remove_tag(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 收藏文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/star",
+    request_body = StarDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn star_file(id: Identity, params: Json<StarDto>) -> ApiResult<()> {
+    let id = id.id()?.parse::<UserId>()?;
+    star::star(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 取消收藏文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/unstar",
+    request_body = StarDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn unstar_file(id: Identity, params: Json<StarDto>) -> ApiResult<()> {
+    let id = id.id()?.parse::<UserId>()?;
+    star::unstar(id, params.into_inner()).await?;
+    ApiResponse::Ok(())
+}
+
+/// 目录占用的空间，包含所有子目录，并列出各个直接子目录的占用
+#[utoipa::path(
+    get,
+    path = "/api/fs/usage/{dir_id}",
+    params(("dir_id" = UserFileId, Path, description = "目录 id")),
+    responses((status = 200, body = DirUsageDto)),
+    tag = "fs"
+)]
+async fn dir_usage(owner: SpaceOwner, dir_id: web::Path<UserFileId>) -> ApiResult<DirUsageDto> {
+    let id = owner.0;
+    let resp = usage::dir_usage(id, dir_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
 }
 
-status_doc!();
+/// 按邮箱把目录分享给已注册的用户，已经分享过时修改权限
+#[utoipa::path(
+    post,
+    path = "/api/fs/share",
+    request_body = ShareFolderDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn share_folder(owner: SpaceOwner, params: Json<ShareFolderDto>) -> ApiResult<()> {
+    let id = owner.0;
+    share::share_folder(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
 
-pub fn actix_config(cfg: &mut web::ServiceConfig) {
-    let m_limit = MultipartFormConfig::default().memory_limit(1024 * 1024 * 100);
-    cfg.service(
-        web::scope("/api/fs")
-            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
-            .service(web::resource("/home").route(web::get().to(load_home)))
-            .service(web::resource("/create_dir").route(web::post().to(create_dir)))
-            .service(web::resource("/delete").route(web::post().to(delete)))
-            .service(web::resource("/copy").route(web::post().to(copy)))
-            .service(web::resource("/move").route(web::post().to(move_to)))
-            .service(web::resource("/rename").route(web::post().to(rename)))
-            // thumbnail
-            .service(web::resource("/thumbnails").route(web::get().to(thumbnail_paths)))
-            .service(thumbnail_file)
-            // upload
-            .service(
-                web::resource("/register_upload_task").route(web::post().to(register_upload_task)),
-            )
-            .service(web::resource("/del_upload_task").route(web::post().to(del_upload_task)))
-            .service(
-                web::resource("/upload_tasks")
-                    .route(web::get().to(get_upload_tasks))
-                    .route(web::delete().to(clear_upload_tasks)),
-            )
-            .service(
-                web::resource("/upload_slice")
-                    .app_data(m_limit.clone())
-                    .route(web::post().to(upload_slice)),
-            )
-            .service(web::resource("/finish_upload").route(web::post().to(upload_finished)))
-            // from factory
-            .service(web::resource("/file_parsed").route(web::post().to(file_parsed)))
-            .service(
-                web::resource("/thumbnail_generated").route(web::post().to(thumbnail_generated)),
-            ),
-    )
-    .service(
-        web::scope("/admin/fs")
-            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
-            .service(web::resource("/home").route(web::get().to(load_home_admin)))
-            .service(web::resource("/create_dir").route(web::post().to(create_dir_admin)))
-            .service(web::resource("/delete").route(web::post().to(delete_admin)))
-            .service(web::resource("/copy").route(web::post().to(copy_admin)))
-            .service(web::resource("/move").route(web::post().to(move_to_admin)))
-            .service(web::resource("/rename").route(web::post().to(rename_admin)))
-            .service(web::resource("/thumbnails").route(web::get().to(thumbnail_paths)))
-            .service(thumbnail_file),
-    );
+/// 取消分享
+#[utoipa::path(
+    post,
+    path = "/api/fs/unshare",
+    request_body = UnshareFolderDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn unshare_folder(owner: SpaceOwner, params: Json<UnshareFolderDto>) -> ApiResult<()> {
+    let id = owner.0;
+    share::unshare_folder(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct LoadHomeParams {
-    user_id: UserId,
+/// 目录分享给的所有成员及权限
+#[utoipa::path(
+    get,
+    path = "/api/fs/share_members/{dir_id}",
+    params(("dir_id" = UserFileId, Path, description = "目录 id")),
+    responses((status = 200, body = Vec<ShareMemberDto>)),
+    tag = "fs"
+)]
+async fn share_members(
+    owner: SpaceOwner,
+    dir_id: web::Path<UserFileId>,
+) -> ApiResult<Vec<ShareMemberDto>> {
+    let id = owner.0;
+    let members = share::members(id, dir_id.into_inner()).await??;
+    ApiResponse::Ok(members)
 }
 
-async fn load_home_admin(_id: Identity, params: Query<LoadHomeParams>) -> ApiResult<DirTree> {
-    let tree = service::load_home(params.user_id).await?;
-    ApiResponse::Ok(tree)
+/// 锁定文件，已经持有时续期。锁定期间其他用户不能覆盖上传、重命名、移动或删除该文件，
+/// 被锁定时返回的错误数据中带有持有者和到期时间
+#[utoipa::path(
+    post,
+    path = "/api/fs/lock",
+    request_body = AcquireLockDto,
+    responses((status = 200, body = FileLockDto)),
+    tag = "fs"
+)]
+async fn acquire_lock(owner: SpaceOwner, params: Json<AcquireLockDto>) -> ApiResult<FileLockDto> {
+    let id = owner.0;
+    let lock = edit_lock::acquire(id, owner.member(), params.into_inner()).await??;
+    ApiResponse::Ok(lock)
 }
 
-async fn load_home(id: Identity) -> ApiResult<DirTree> {
-    let id = id.id()?.parse::<UserId>()?;
-    let tree = service::load_home(id).await?;
-    ApiResponse::Ok(tree)
+/// 释放自己持有的锁
+#[utoipa::path(
+    post,
+    path = "/api/fs/unlock",
+    request_body = ReleaseLockDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn release_lock(owner: SpaceOwner, params: Json<ReleaseLockDto>) -> ApiResult<()> {
+    edit_lock::release(owner.member(), params.into_inner()).await??;
+    ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct CreateDirDto {
-    pub parent_id: UserFileId,
-    pub name: String,
+/// 抢占其他用户持有的锁，需要文件的写权限
+#[utoipa::path(
+    post,
+    path = "/api/fs/lock/steal",
+    request_body = AcquireLockDto,
+    responses((status = 200, body = FileLockDto)),
+    tag = "fs"
+)]
+async fn steal_lock(owner: SpaceOwner, params: Json<AcquireLockDto>) -> ApiResult<FileLockDto> {
+    let id = owner.0;
+    let lock = edit_lock::steal(id, owner.member(), params.into_inner()).await??;
+    ApiResponse::Ok(lock)
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct AdminParams<T> {
-    user_id: UserId,
-    #[serde(flatten)]
-    params: T,
+/// 签发短期有效的播放地址，需要文件的读权限。地址可以直接用于 video 标签或下载，不需要登录
+#[utoipa::path(
+    post,
+    path = "/api/fs/playback_url",
+    request_body = PlaybackUrlDto,
+    responses((status = 200, body = PlaybackUrl)),
+    tag = "fs"
+)]
+async fn playback_url(
+    owner: SpaceOwner,
+    req: HttpRequest,
+    params: Json<PlaybackUrlDto>,
+) -> ApiResult<PlaybackUrl> {
+    let url = playback::issue_url(owner.0, params.into_inner(), client_ip(&req)).await??;
+    ApiResponse::Ok(url)
 }
 
-async fn create_dir_admin(
-    _id: Identity,
-    params: Json<AdminParams<CreateDirDto>>,
-) -> ApiResult<CreateDirResp> {
-    let AdminParams {
-        user_id,
-        params: CreateDirDto { parent_id, name },
-    } = params.into_inner();
-    let file_id = service::create_dir(user_id, parent_id, &name).await??;
-    ApiResponse::Ok(CreateDirResp { file_id })
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PlayParams {
+    /// 签发播放地址时生成的令牌
+    token: String,
+    /// 为真时作为附件下载
+    #[serde(default)]
+    download: bool,
 }
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-struct CreateDirResp {
-    pub file_id: UserFileId,
+/// 按令牌播放或下载文件，支持 Range 请求。令牌只校验签名，不查询数据库
+#[utoipa::path(
+    get,
+    path = "/api/fs/play/{file_id}",
+    params(("file_id" = UserFileId, Path, description = "文件 id"), PlayParams),
+    responses((status = 200, body = String, content_type = "application/octet-stream")),
+    tag = "fs"
+)]
+async fn play(
+    req: HttpRequest,
+    file_id: web::Path<UserFileId>,
+    params: Query<PlayParams>,
+) -> Result<HttpResponse, ApiError> {
+    let file_id = file_id.into_inner();
+    playback_token::verify(&params.token, file_id, client_ip(&req))?;
+    let Some(file) = playback::open(file_id).await? else {
+        return Err(PLAY.file_not_found.into());
+    };
+    serve_file(&req, file, params.download).await
 }
 
-async fn create_dir(id: Identity, params: Json<CreateDirDto>) -> ApiResult<CreateDirResp> {
-    let id = id.id()?.parse::<UserId>()?;
-    let CreateDirDto { parent_id, name } = params.into_inner();
-    let file_id = service::create_dir(id, parent_id, &name).await??;
-    ApiResponse::Ok(CreateDirResp { file_id })
+/// 返回存档文件，支持 Range 请求。存档文件没有扩展名，按识别出的类型设置 Content-Type
+pub(crate) async fn serve_file(
+    req: &HttpRequest,
+    file: PlaybackFile,
+    download: bool,
+) -> Result<HttpResponse, ApiError> {
+    let PlaybackFile {
+        archived_path,
+        mime,
+        file_name,
+        ..
+    } = file;
+    let disposition = if download {
+        attachment(file_name)
+    } else {
+        ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![],
+        }
+    };
+    let file = NamedFile::open_async(archived_path)
+        .await
+        .map_err(anyhow::Error::from)?
+        .set_content_disposition(disposition);
+    let mut resp = file.into_response(req);
+    if let Some(value) = mime.and_then(|mime| HeaderValue::from_str(&mime).ok()) {
+        resp.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    Ok(resp)
+}
+
+/// 下载时的文件名。filename 只能是 ASCII，非 ASCII 字符替换为 _，完整的文件名通过 filename* 以 UTF-8 传递
+fn attachment(file_name: String) -> ContentDisposition {
+    let fallback = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![
+            DispositionParam::Filename(fallback),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: file_name.into_bytes(),
+            }),
+        ],
+    }
 }
 
 static UPLOAD_TASKS: &str = "upload-tasks";
 
+/// 注册分片上传任务
+#[utoipa::path(
+    post,
+    path = "/api/fs/register_upload_task",
+    request_body = RegisterUploadTaskDto,
+    responses((status = 200, body = RegisterUploadTaskResp)),
+    tag = "fs"
+)]
 async fn register_upload_task(
-    params: Json<RegisterUploadTaskDto>,
-    identity: Identity,
+    params: ValidJson<RegisterUploadTaskDto>,
+    owner: SpaceOwner,
     req: HttpRequest,
 ) -> ApiResult<RegisterUploadTaskResp> {
-    let id = identity.id()?.parse::<UserId>()?;
-    let resp = upload::register_upload_task(id, params.into_inner()).await??;
+    let id = owner.0;
+    let resp = upload::register_upload_task(id, owner.member(), params.into_inner()).await??;
     let ss = req.get_session();
     let tasks: Option<HashSet<UploadTaskId>> = ss.get(UPLOAD_TASKS)?;
     let mut tasks = tasks.unwrap_or_default();
@@ -255,6 +1888,13 @@ async fn register_upload_task(
     ApiResponse::Ok(resp)
 }
 
+/// 当前会话中未完成的上传任务
+#[utoipa::path(
+    get,
+    path = "/api/fs/upload_tasks",
+    responses((status = 200, body = [UploadTaskDto])),
+    tag = "fs"
+)]
 async fn get_upload_tasks(_id: Identity, req: HttpRequest) -> ApiResult<Vec<UploadTaskDto>> {
     let ss = req.get_session();
     let tasks: Option<HashSet<UploadTaskId>> = ss.get(UPLOAD_TASKS)?;
@@ -266,12 +1906,20 @@ async fn get_upload_tasks(_id: Identity, req: HttpRequest) -> ApiResult<Vec<Uplo
     ApiResponse::Ok(resp)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct DelUplodTask {
     task_id: UploadTaskId,
 }
 
+/// 删除上传任务
+#[utoipa::path(
+    post,
+    path = "/api/fs/del_upload_task",
+    request_body = DelUplodTask,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
 async fn del_upload_task(
     _id: Identity,
     params: Json<DelUplodTask>,
@@ -296,6 +1944,13 @@ fn del_session_upload_task(task_id: UploadTaskId, req: HttpRequest) -> anyhow::R
     Ok(())
 }
 
+/// 清空当前会话中的上传任务
+#[utoipa::path(
+    delete,
+    path = "/api/fs/upload_tasks",
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
 async fn clear_upload_tasks(_id: Identity, req: HttpRequest) -> ApiResult<()> {
     let ss = req.get_session();
     let tasks: Option<HashSet<UploadTaskId>> = ss.get(UPLOAD_TASKS)?;
@@ -307,28 +1962,301 @@ async fn clear_upload_tasks(_id: Identity, req: HttpRequest) -> ApiResult<()> {
     ApiResponse::Ok(())
 }
 
-#[derive(MultipartForm)]
-pub struct UploadSliceParams {
-    chunk: Bytes,
-    index: Text<u32>,
-    #[multipart(rename = "taskId")]
-    task_id: Text<String>,
+/// 上传任务的进度，注册时声明了分片方式时返回缺失的分片
+#[utoipa::path(
+    get,
+    path = "/api/fs/upload_status/{task_id}",
+    params(("task_id" = UploadTaskId, Path, description = "上传任务 id")),
+    responses((status = 200, body = UploadStatusDto)),
+    tag = "fs"
+)]
+async fn upload_status(
+    owner: SpaceOwner,
+    task_id: web::Path<UploadTaskId>,
+) -> ApiResult<UploadStatusDto> {
+    let id = owner.0;
+    let resp = upload::upload_status(id, task_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
 }
 
-pub async fn upload_slice(
-    _id: Identity,
-    MultipartForm(form): MultipartForm<UploadSliceParams>,
-) -> ApiResult<()> {
-    upload::store_slice(form.task_id.parse()?, form.index.0, &form.chunk.data).await??;
+/// 上传的大小限制
+#[utoipa::path(
+    get,
+    path = "/api/fs/limits",
+    responses((status = 200, body = UploadLimits)),
+    tag = "fs"
+)]
+async fn upload_limits(_id: Identity) -> ApiResult<UploadLimits> {
+    ApiResponse::Ok(upload::limits())
+}
+
+/// upload_slice 的 multipart 表单，只用于生成文档
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+struct UploadSliceForm {
+    task_id: UploadTaskId,
+    index: u32,
+    #[schema(value_type = String, format = Binary)]
+    chunk: Vec<u8>,
+}
+
+/// upload_small 的 multipart 表单，只用于生成文档
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
+struct UploadSmallForm {
+    parent_id: UserFileId,
+    /// 覆盖同名文件，被覆盖的内容保存为历史版本，默认为 false
+    overwrite: Option<bool>,
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// 以流的方式将片段直接写入磁盘，`taskId` 和 `index` 字段必须在 `chunk` 之前发送
+#[utoipa::path(
+    post,
+    path = "/api/fs/upload_slice",
+    request_body(content = UploadSliceForm, content_type = "multipart/form-data"),
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+pub async fn upload_slice(_id: Identity, mut payload: Multipart) -> ApiResult<()> {
+    let mut task_id: Option<UploadTaskId> = None;
+    let mut index: Option<u32> = None;
+
+    while let Some(field) = payload.try_next().await.map_err(|err| anyhow!("{err}"))? {
+        match field.name() {
+            "taskId" => task_id = Some(read_text_field(field).await?.parse()?),
+            "index" => index = Some(read_text_field(field).await?.parse()?),
+            "chunk" => {
+                let (Some(task_id), Some(index)) = (task_id, index) else {
+                    return Err(anyhow!("taskId and index must be sent before chunk").into());
+                };
+                let data = field.map(|chunk| chunk.map_err(|err| anyhow!("{err}")));
+                upload::store_slice(task_id, index, data).await??;
+                return ApiResponse::Ok(());
+            }
+            _ => continue,
+        }
+    }
+
+    Err(anyhow!("missing chunk").into())
+}
+
+/// 一次性上传小文件，`parentId` 和 `overwrite` 字段必须在 `file` 之前发送，文件名取自 `file` 字段的 filename
+#[utoipa::path(
+    post,
+    path = "/api/fs/upload_small",
+    request_body(content = UploadSmallForm, content_type = "multipart/form-data"),
+    responses((status = 200, body = UploadedUserFile)),
+    tag = "fs"
+)]
+pub async fn upload_small(
+    owner: SpaceOwner,
+    mut payload: Multipart,
+) -> ApiResult<UploadedUserFile> {
+    let id = owner.0;
+    let mut parent_id: Option<UserFileId> = None;
+    let mut overwrite = false;
+
+    while let Some(field) = payload.try_next().await.map_err(|err| anyhow!("{err}"))? {
+        match field.name() {
+            "parentId" => parent_id = Some(read_text_field(field).await?.parse()?),
+            "overwrite" => overwrite = read_text_field(field).await?.parse()?,
+            "file" => {
+                let Some(parent_id) = parent_id else {
+                    return Err(anyhow!("parentId must be sent before file").into());
+                };
+                let Some(file_name) = field.content_disposition().get_filename() else {
+                    return Err(anyhow!("missing filename").into());
+                };
+                let file_name = file_name.to_string();
+                let data = field.map(|chunk| chunk.map_err(|err| anyhow!("{err}")));
+                let resp =
+                    upload::upload_small(id, owner.member(), parent_id, file_name, overwrite, data)
+                        .await??;
+                return ApiResponse::Ok(resp);
+            }
+            _ => continue,
+        }
+    }
+
+    Err(anyhow!("missing file").into())
+}
+
+/// 秒传，系统中已存在相同 hash 的文件时直接创建用户文件，不需要上传分片
+#[utoipa::path(
+    post,
+    path = "/api/fs/instant_upload",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = InstantUploadDto,
+    responses((status = 200, body = UploadedUserFile)),
+    tag = "fs"
+)]
+async fn instant_upload(
+    owner: SpaceOwner,
+    params: ValidJson<InstantUploadDto>,
+) -> ApiResult<UploadedUserFile> {
+    let id = owner.0;
+    let resp = upload::instant_upload(id, owner.member(), params.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 服务器从 http/https 地址下载文件到用户的目录，下载在后台进行，通过返回的任务 id 查询进度
+#[utoipa::path(
+    post,
+    path = "/api/fs/import_url",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = ImportUrlDto,
+    responses((status = 200, body = ImportUrlResp)),
+    tag = "fs"
+)]
+async fn import_url(
+    owner: SpaceOwner,
+    params: ValidJson<ImportUrlDto>,
+) -> ApiResult<ImportUrlResp> {
+    let id = owner.0;
+    let resp = import_url::import_url(id, params.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 查询导入任务的进度，任务结束 10 分钟后不能再查询
+#[utoipa::path(
+    get,
+    path = "/api/fs/import_url/{task_id}",
+    params(("task_id" = ImportTaskId, Path, description = "导入任务 id")),
+    responses((status = 200, body = ImportTaskDto)),
+    tag = "fs"
+)]
+async fn import_status(
+    owner: SpaceOwner,
+    task_id: web::Path<ImportTaskId>,
+) -> ApiResult<ImportTaskDto> {
+    let id = owner.0;
+    let status = import_url::import_status(id, task_id.into_inner()).await??;
+    ApiResponse::Ok(status)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+struct ImportSourceParams {
+    source: ImportSource,
+}
+
+/// 获取外部服务的授权地址，用户授权后回调地址中的 code 和 state 用于 /api/fs/import/connect
+#[utoipa::path(
+    get,
+    path = "/api/fs/import/authorize_url",
+    params(ImportSourceParams),
+    responses((status = 200, body = String)),
+    tag = "fs"
+)]
+async fn import_authorize_url(
+    id: Identity,
+    params: Query<ImportSourceParams>,
+) -> ApiResult<String> {
+    let id = id.id()?.parse::<UserId>()?;
+    let url = import::authorize_url(id, params.source).await??;
+    ApiResponse::Ok(url)
+}
+
+/// 完成外部服务的授权
+#[utoipa::path(
+    post,
+    path = "/api/fs/import/connect",
+    request_body = ConnectDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn import_connect(id: Identity, params: Json<ConnectDto>) -> ApiResult<()> {
+    let id = id.id()?.parse::<UserId>()?;
+    import::connect(id, params.into_inner()).await??;
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+struct ImportListParams {
+    source: ImportSource,
+    /// 为空时列出根目录
+    folder_id: Option<String>,
+}
+
+/// 列出外部服务中的文件
+#[utoipa::path(
+    get,
+    path = "/api/fs/import/list",
+    params(ImportListParams),
+    responses((status = 200, body = [RemoteFile])),
+    tag = "fs"
+)]
+async fn import_list(id: Identity, params: Query<ImportListParams>) -> ApiResult<Vec<RemoteFile>> {
+    let id = id.id()?.parse::<UserId>()?;
+    let files = import::list(id, params.source, params.folder_id.as_deref()).await??;
+    ApiResponse::Ok(files)
+}
+
+/// 从外部服务导入选中的文件，在后台按顺序下载
+#[utoipa::path(
+    post,
+    path = "/api/fs/import/jobs",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = StartImportDto,
+    responses((status = 200, body = StartImportResp)),
+    tag = "fs"
+)]
+async fn import_start(id: Identity, params: Json<StartImportDto>) -> ApiResult<StartImportResp> {
+    let id = id.id()?.parse::<UserId>()?;
+    let resp = import::start_job(id, params.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 查询导入任务中每个文件的状态，任务结束 10 分钟后不能再查询
+#[utoipa::path(
+    get,
+    path = "/api/fs/import/jobs/{job_id}",
+    params(("job_id" = ImportJobId, Path, description = "导入任务 id")),
+    responses((status = 200, body = ImportJobDto)),
+    tag = "fs"
+)]
+async fn import_job(id: Identity, job_id: web::Path<ImportJobId>) -> ApiResult<ImportJobDto> {
+    let id = id.id()?.parse::<UserId>()?;
+    let job = import::job(id, job_id.into_inner()).await??;
+    ApiResponse::Ok(job)
+}
+
+async fn read_text_field(mut field: Field) -> anyhow::Result<String> {
+    const MAX_TEXT_LEN: usize = 64;
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = field.try_next().await.map_err(|err| anyhow!("{err}"))? {
+        ensure!(
+            buf.len() + chunk.len() <= MAX_TEXT_LEN,
+            "multipart text field too long"
+        );
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8(buf)?)
+}
+
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct UploadFinishedParam {
     task_id: UploadTaskId,
 }
 
+/// 合并分片，完成上传。合并中断或返回合并中时可以重试
+#[utoipa::path(
+    post,
+    path = "/api/fs/finish_upload",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = UploadFinishedParam,
+    responses((status = 200, body = UploadedUserFile)),
+    tag = "fs"
+)]
 async fn upload_finished(
     _id: Identity,
     params: Json<UploadFinishedParam>,
@@ -342,109 +2270,276 @@ async fn upload_finished(
     ApiResponse::Ok(resp)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct DeleteDto {
     file_ids: Vec<UserFileId>,
 }
 
-async fn delete(id: Identity, params: Json<DeleteDto>) -> ApiResult<()> {
-    let id = id.id()?.parse::<UserId>()?;
+/// 删除文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/delete",
+    request_body = DeleteDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn delete(owner: SpaceOwner, params: Json<DeleteDto>) -> ApiResult<()> {
+    let id = owner.0;
     let DeleteDto { file_ids } = params.into_inner();
-    service::delete(id, file_ids).await??;
+    service::delete(id, owner.member(), file_ids).await??;
     ApiResponse::Ok(())
 }
 
-async fn delete_admin(_id: Identity, params: Json<AdminParams<DeleteDto>>) -> ApiResult<()> {
+/// 删除用户的文件
+#[utoipa::path(
+    post,
+    path = "/admin/fs/delete",
+    request_body = AdminDeleteDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn delete_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<AdminParams<DeleteDto>>,
+) -> ApiResult<()> {
     let AdminParams {
         user_id,
         params: DeleteDto { file_ids },
     } = params.into_inner();
-    service::delete(user_id, file_ids).await??;
+    admin::delete(fs_capability(&req)?, user_id, file_ids).await??;
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 struct MoveToParams {
     from: Vec<UserFileId>,
     to: UserFileId,
 }
 
-async fn copy(id: Identity, params: Json<MoveToParams>) -> ApiResult<()> {
-    let id = id.id()?.parse::<UserId>()?;
+/// 复制文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/copy",
+    request_body = MoveToParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn copy(owner: SpaceOwner, params: Json<MoveToParams>) -> ApiResult<()> {
+    let id = owner.0;
     let MoveToParams { from, to } = params.into_inner();
     service::copy_to(id, from, to).await??;
     ApiResponse::Ok(())
 }
 
-async fn copy_admin(_id: Identity, params: Json<AdminParams<MoveToParams>>) -> ApiResult<()> {
+/// 复制用户的文件
+#[utoipa::path(
+    post,
+    path = "/admin/fs/copy",
+    request_body = AdminMoveToParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn copy_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<AdminParams<MoveToParams>>,
+) -> ApiResult<()> {
     let AdminParams {
         user_id,
         params: MoveToParams { from, to },
     } = params.into_inner();
-    service::copy_to(user_id, from, to).await??;
+    admin::copy_to(fs_capability(&req)?, user_id, from, to).await??;
     ApiResponse::Ok(())
 }
 
-async fn move_to(id: Identity, params: Json<MoveToParams>) -> ApiResult<()> {
-    let id = id.id()?.parse::<UserId>()?;
+/// 移动文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/move",
+    request_body = MoveToParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn move_to(owner: SpaceOwner, params: Json<MoveToParams>) -> ApiResult<()> {
+    let id = owner.0;
     let MoveToParams { from, to } = params.into_inner();
-    service::move_to(id, from, to).await??;
+    service::move_to(id, owner.member(), from, to).await??;
     ApiResponse::Ok(())
 }
 
-async fn move_to_admin(_id: Identity, params: Json<AdminParams<MoveToParams>>) -> ApiResult<()> {
+/// 移动用户的文件
+#[utoipa::path(
+    post,
+    path = "/admin/fs/move",
+    request_body = AdminMoveToParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn move_to_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<AdminParams<MoveToParams>>,
+) -> ApiResult<()> {
     let AdminParams {
         user_id,
         params: MoveToParams { from, to },
     } = params.into_inner();
-    service::move_to(user_id, from, to).await??;
+    admin::move_to(fs_capability(&req)?, user_id, from, to).await??;
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
+/// 批量操作中一项的结果
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct BulkItemResult {
+    file_id: UserFileId,
+    /// 0 表示成功，其他值与单项操作的错误码相同
+    status: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    err_msg: Option<String>,
+}
+
+fn bulk_items(results: BulkResult) -> Vec<BulkItemResult> {
+    results
+        .into_iter()
+        .map(|(file_id, result)| match result {
+            Ok(()) => BulkItemResult {
+                file_id,
+                status: 0,
+                err_msg: None,
+            },
+            Err(err) => {
+                let err = ApiError::from(err);
+                BulkItemResult {
+                    file_id,
+                    status: err.code(),
+                    err_msg: Some(err.to_string()),
+                }
+            }
+        })
+        .collect()
+}
+
+/// 批量删除文件，每一项单独处理，某一项失败不影响其他项
+#[utoipa::path(
+    post,
+    path = "/api/fs/bulk/delete",
+    request_body = DeleteDto,
+    responses((status = 200, body = [BulkItemResult])),
+    tag = "fs"
+)]
+async fn bulk_delete(owner: SpaceOwner, params: Json<DeleteDto>) -> ApiResult<Vec<BulkItemResult>> {
+    let id = owner.0;
+    let DeleteDto { file_ids } = params.into_inner();
+    let results = bulk::delete(id, owner.member(), file_ids).await?;
+    ApiResponse::Ok(bulk_items(results))
+}
+
+/// 批量移动文件，每一项单独处理，某一项失败不影响其他项
+#[utoipa::path(
+    post,
+    path = "/api/fs/bulk/move",
+    request_body = MoveToParams,
+    responses((status = 200, body = [BulkItemResult])),
+    tag = "fs"
+)]
+async fn bulk_move(
+    owner: SpaceOwner,
+    params: Json<MoveToParams>,
+) -> ApiResult<Vec<BulkItemResult>> {
+    let id = owner.0;
+    let MoveToParams { from, to } = params.into_inner();
+    let results = bulk::move_to(id, owner.member(), from, to).await??;
+    ApiResponse::Ok(bulk_items(results))
+}
+
+/// 批量复制文件，每一项单独处理，某一项失败不影响其他项
+#[utoipa::path(
+    post,
+    path = "/api/fs/bulk/copy",
+    request_body = MoveToParams,
+    responses((status = 200, body = [BulkItemResult])),
+    tag = "fs"
+)]
+async fn bulk_copy(
+    owner: SpaceOwner,
+    params: Json<MoveToParams>,
+) -> ApiResult<Vec<BulkItemResult>> {
+    let id = owner.0;
+    let MoveToParams { from, to } = params.into_inner();
+    let results = bulk::copy_to(id, from, to).await??;
+    ApiResponse::Ok(bulk_items(results))
+}
+
+#[derive(Deserialize, ToSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 struct RenameParams {
     file_id: UserFileId,
+    #[validate(length(min = 1, max = 255))]
     new_name: String,
 }
 
-async fn rename(id: Identity, params: Json<RenameParams>) -> ApiResult<()> {
-    let id = id.id()?.parse::<UserId>()?;
+/// 重命名文件
+#[utoipa::path(
+    post,
+    path = "/api/fs/rename",
+    request_body = RenameParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn rename(owner: SpaceOwner, params: ValidJson<RenameParams>) -> ApiResult<()> {
+    let id = owner.0;
     let RenameParams { file_id, new_name } = params.into_inner();
-    service::rename(id, file_id, &new_name).await??;
+    service::rename(id, owner.member(), file_id, &new_name).await??;
     ApiResponse::Ok(())
 }
 
-async fn rename_admin(_id: Identity, params: Json<AdminParams<RenameParams>>) -> ApiResult<()> {
+/// 重命名用户的文件
+#[utoipa::path(
+    post,
+    path = "/admin/fs/rename",
+    request_body = AdminRenameParams,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn rename_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<AdminParams<RenameParams>>,
+) -> ApiResult<()> {
     let AdminParams {
         user_id,
         params: RenameParams { file_id, new_name },
     } = params.into_inner();
-    service::rename(user_id, file_id, &new_name).await??;
+    admin::rename(fs_capability(&req)?, user_id, file_id, &new_name).await??;
     ApiResponse::Ok(())
 }
 
+/// 视频解析完成回调，由转码工厂调用
+#[utoipa::path(
+    post,
+    path = "/internal/fs/file_parsed",
+    request_body = Object,
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
 async fn file_parsed(params: Json<TaskResult<Option<String>>>) -> ApiResult<()> {
-    let TaskResult {
-        task_id: _,
-        file_id,
-        result,
-    } = params.into_inner();
-    debug!(?result, "video parsed");
-
-    match result {
-        Ok(ok) => {
-            video_info::file_parsed(file_id.into(), ok).await?;
-        }
-        Err(err) => {
-            warn!(%err, "parse video failed");
-        }
-    }
+    debug!(result = ?params.result, "video parsed");
+    callback::receive_file_parsed(&params).await?;
     ApiResponse::Ok(())
 }
 
+/// 缩略图生成完成回调，由转码工厂调用
+#[utoipa::path(
+    post,
+    path = "/internal/fs/thumbnail_generated",
+    request_body = Object,
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
 async fn thumbnail_generated(params: Json<TaskResult<()>>) -> ApiResult<()> {
     let TaskResult {
         task_id,
@@ -463,25 +2558,242 @@ async fn thumbnail_generated(params: Json<TaskResult<()>>) -> ApiResult<()> {
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
+/// 校验归档文件的完整性
+#[utoipa::path(
+    post,
+    path = "/admin/fs/verify/{sys_file_id}",
+    params(("sys_file_id" = SysFileId, Path, description = "归档文件 id")),
+    responses((status = 200, body = VerifyResult)),
+    tag = "fs"
+)]
+async fn verify_admin(_id: Identity, sys_file_id: web::Path<SysFileId>) -> ApiResult<VerifyResult> {
+    let result = integrity::verify(sys_file_id.into_inner()).await??;
+    ApiResponse::Ok(result)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct OrphanScanDto {
+    /// 为 true 时删除找到的目录，否则只列出
+    #[serde(default)]
+    remove: bool,
+}
+
+/// 找出没有系统文件记录的归档目录
+#[utoipa::path(
+    post,
+    path = "/admin/fs/orphan_scan",
+    request_body = OrphanScanDto,
+    responses((status = 200, body = OrphanScanResult)),
+    tag = "fs"
+)]
+async fn orphan_scan_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<OrphanScanDto>,
+) -> ApiResult<OrphanScanResult> {
+    let result = reclaim::scan_orphans(fs_capability(&req)?, params.remove).await??;
+    ApiResponse::Ok(result)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct ConsistencyCheckDto {
+    /// 为 true 时重建缺少的目录和软链接
+    #[serde(default)]
+    repair: bool,
+}
+
+/// 检查数据库中的用户文件与磁盘是否一致
+#[utoipa::path(
+    post,
+    path = "/admin/fs/consistency_check",
+    request_body = ConsistencyCheckDto,
+    responses((status = 200, body = ConsistencyReport)),
+    tag = "fs"
+)]
+async fn consistency_check_admin(
+    _id: Identity,
+    req: HttpRequest,
+    params: Json<ConsistencyCheckDto>,
+) -> ApiResult<ConsistencyReport> {
+    let report = consistency::check(fs_capability(&req)?, params.repair).await??;
+    ApiResponse::Ok(report)
+}
+
+/// 目录列表缓存的命中情况
+#[utoipa::path(
+    get,
+    path = "/admin/fs/dir_cache",
+    responses((status = 200, body = DirCacheStats)),
+    tag = "fs"
+)]
+async fn dir_cache_stats_admin(_id: Identity) -> ApiResult<DirCacheStats> {
+    ApiResponse::Ok(dir_cache::stats())
+}
+
+/// 后台补全缺失的视频信息
+#[utoipa::path(
+    post,
+    path = "/admin/fs/backfill_video_info",
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn backfill_video_info_admin(_id: Identity) -> ApiResult<()> {
+    if !video_info::start_backfill() {
+        return Err(BACKFILL_VIDEO_INFO.already_running.into());
+    }
+    ApiResponse::Ok(())
+}
+
+/// 把服务器上的目录导入到用户的源视频目录，在后台运行
+#[utoipa::path(
+    post,
+    path = "/admin/fs/ingest",
+    request_body = IngestDto,
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn ingest_admin(_id: Identity, req: HttpRequest, params: Json<IngestDto>) -> ApiResult<()> {
+    let cap = fs_capability(&req)?;
+    ingest::start(cap, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 最近一次导入的进度
+#[utoipa::path(
+    get,
+    path = "/admin/fs/ingest",
+    responses((status = 200, body = IngestProgress)),
+    tag = "fs"
+)]
+async fn ingest_progress_admin(_id: Identity) -> ApiResult<IngestProgress> {
+    ApiResponse::Ok(ingest::progress())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+struct UploadTasksAdminParams {
+    /// 只列出该用户的任务
+    user_id: Option<UserId>,
+}
+
+/// 所有用户未过期的上传任务，最早注册的在前
+#[utoipa::path(
+    get,
+    path = "/admin/fs/upload_tasks",
+    params(UploadTasksAdminParams),
+    responses((status = 200, body = [AdminUploadTaskDto])),
+    tag = "fs"
+)]
+async fn upload_tasks_admin(
+    _id: Identity,
+    params: Query<UploadTasksAdminParams>,
+) -> ApiResult<Vec<AdminUploadTaskDto>> {
+    let tasks = admin::list_upload_tasks(params.user_id).await?;
+    ApiResponse::Ok(tasks)
+}
+
+/// 删除上传任务和已上传的分片
+#[utoipa::path(
+    post,
+    path = "/admin/fs/upload_tasks/{task_id}/expire",
+    params(("task_id" = UploadTaskId, Path, description = "上传任务 id")),
+    responses((status = 200, description = "成功")),
+    tag = "fs"
+)]
+async fn expire_upload_task_admin(
+    _id: Identity,
+    req: HttpRequest,
+    task_id: web::Path<UploadTaskId>,
+) -> ApiResult<()> {
+    admin::expire_upload_task(fs_capability(&req)?, task_id.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 用已上传的分片完成上传任务
+#[utoipa::path(
+    post,
+    path = "/admin/fs/upload_tasks/{task_id}/complete",
+    params(("task_id" = UploadTaskId, Path, description = "上传任务 id")),
+    responses((status = 200, body = UploadedUserFile)),
+    tag = "fs"
+)]
+async fn complete_upload_task_admin(
+    _id: Identity,
+    req: HttpRequest,
+    task_id: web::Path<UploadTaskId>,
+) -> ApiResult<UploadedUserFile> {
+    let file = admin::complete_upload_task(fs_capability(&req)?, task_id.into_inner()).await??;
+    ApiResponse::Ok(file)
+}
+
+#[derive(Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct ThumbnailsParams {
     file_id: UserFileId,
 }
 
-pub async fn thumbnail_paths(params: Query<ThumbnailsParams>) -> ApiResult<Vec<String>> {
+/// 视频缩略图的路径列表
+#[utoipa::path(
+    get,
+    path = "/api/fs/thumbnails",
+    params(ThumbnailsParams),
+    responses((status = 200, body = [String])),
+    tag = "fs"
+)]
+pub async fn thumbnail_paths(
+    id: Option<Identity>,
+    params: Query<ThumbnailsParams>,
+) -> ApiResult<Vec<String>> {
     let ThumbnailsParams { file_id } = params.into_inner();
+    let accessor = match id {
+        Some(id) => {
+            let id = id.id()?.parse::<UserId>()?;
+            recent::touch(id, file_id).await;
+            Some(id)
+        }
+        None => None,
+    };
+    access_log::record(file_id, AccessKind::Preview, accessor);
+    ApiResponse::Ok(load_thumbnail_paths(file_id).await?)
+}
+
+/// 视频缩略图的路径列表（管理员）
+#[utoipa::path(
+    get,
+    path = "/admin/fs/thumbnails",
+    params(ThumbnailsParams),
+    responses((status = 200, body = [String])),
+    tag = "fs"
+)]
+pub async fn thumbnail_paths_admin(params: Query<ThumbnailsParams>) -> ApiResult<Vec<String>> {
+    let ThumbnailsParams { file_id } = params.into_inner();
+    ApiResponse::Ok(load_thumbnail_paths(file_id).await?)
+}
+
+async fn load_thumbnail_paths(file_id: UserFileId) -> anyhow::Result<Vec<String>> {
     let Some((hash, names)) = service::thumbnail_names(file_id).await? else {
-        return ApiResponse::Ok(Default::default());
+        return Ok(Default::default());
     };
     let paths = names
         .into_iter()
         .map(|name| format!("/{}/{}", hash, name))
         .collect::<Vec<_>>();
 
-    ApiResponse::Ok(paths)
+    Ok(paths)
 }
 
+/// 缩略图文件
+#[utoipa::path(
+    get,
+    path = "/api/fs/thumbnail/{hash}/{file_name}",
+    params(("hash" = String, Path, description = "源文件 hash"), ("file_name" = String, Path, description = "缩略图文件名")),
+    responses((status = 200, body = String, content_type = "image/jpeg")),
+    tag = "fs"
+)]
 #[actix_web::get("/thumbnail/{hash:[a-fA-F0-9]{64}}/{file_name:\\w+.*?[.jpg|.png|.jpeg]$}")]
 async fn thumbnail_file(path: web::Path<(String, String)>) -> actix_web::Result<NamedFile> {
     let (hash, file_name) = path.into_inner();
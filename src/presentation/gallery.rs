@@ -0,0 +1,322 @@
+use actix_identity::Identity;
+use actix_web::{
+    web::{self, Json},
+    HttpRequest, HttpResponse,
+};
+use serde::Deserialize;
+use utils::code;
+use utoipa::{IntoParams, OpenApi};
+use validator::Validate;
+
+use crate::{
+    application::gallery::{
+        self, GalleryErr, PublicationDto, PublishDto, PublishVideoErr, ReportVideoDto,
+        ReportVideoErr, ResolveReportDto, ResolveReportErr, UnpublishDto, UnpublishErr,
+    },
+    cqrs::{
+        gallery::{VideoReport, VideoReportList},
+        Paginate,
+    },
+    domain::{
+        gallery::{Moderation, PublishErr, ReportErr, ReportState},
+        user::user::UserId,
+    },
+    http::{client_ip::client_ip, validation::ValidQuery, ApiError, ApiResponse, ApiResult},
+    presentation::{file_system, organization::SpaceOwner},
+    status_doc,
+};
+
+code! {
+    mod = "gallery";
+    index = 17;
+    err_trait = crate::http::HttpBizError;
+
+    ---
+
+    Publish {
+        file_not_found = "文件不存在",
+        not_file = "只能发布文件",
+        not_encoded = "只能发布转码后的视频",
+        invalid_title = "标题不能为空，最长 100 个字符",
+        taken_down = "视频已被管理员下架，不能再次发布"
+    }
+
+    Unpublish {
+        not_found = "发布记录不存在"
+    }
+
+    Gallery {
+        not_found = "视频不存在或已下架"
+    }
+
+    ReportVideo {
+        not_found = "视频不存在或已下架",
+        invalid_reason = "举报原因不能为空，最长 500 个字符",
+        too_many = "举报太频繁了，请稍后再试"
+    }
+
+    ResolveReport {
+        not_found = "举报不存在",
+        already_handled = "举报已经处理过了"
+    }
+}
+
+impl From<PublishVideoErr> for ApiError {
+    fn from(value: PublishVideoErr) -> Self {
+        match value {
+            PublishVideoErr::FileNotFound => PUBLISH.file_not_found.into(),
+            PublishVideoErr::Publish(err) => match err {
+                PublishErr::NotFile => PUBLISH.not_file.into(),
+                PublishErr::NotEncoded => PUBLISH.not_encoded.into(),
+                PublishErr::InvalidTitle => PUBLISH.invalid_title.into(),
+                PublishErr::TakenDown => PUBLISH.taken_down.into(),
+            },
+        }
+    }
+}
+
+impl From<UnpublishErr> for ApiError {
+    fn from(value: UnpublishErr) -> Self {
+        match value {
+            UnpublishErr::NotFound => UNPUBLISH.not_found.into(),
+        }
+    }
+}
+
+impl From<GalleryErr> for ApiError {
+    fn from(value: GalleryErr) -> Self {
+        match value {
+            GalleryErr::NotFound => GALLERY.not_found.into(),
+        }
+    }
+}
+
+impl From<ReportVideoErr> for ApiError {
+    fn from(value: ReportVideoErr) -> Self {
+        match value {
+            ReportVideoErr::Gallery(GalleryErr::NotFound) => REPORT_VIDEO.not_found.into(),
+            ReportVideoErr::Report(ReportErr::InvalidReason) => REPORT_VIDEO.invalid_reason.into(),
+            ReportVideoErr::Report(ReportErr::AlreadyHandled) => {
+                RESOLVE_REPORT.already_handled.into()
+            }
+            ReportVideoErr::TooMany => REPORT_VIDEO.too_many.into(),
+        }
+    }
+}
+
+impl From<ResolveReportErr> for ApiError {
+    fn from(value: ResolveReportErr) -> Self {
+        match value {
+            ResolveReportErr::NotFound => RESOLVE_REPORT.not_found.into(),
+            ResolveReportErr::Report(ReportErr::AlreadyHandled) => {
+                RESOLVE_REPORT.already_handled.into()
+            }
+            ResolveReportErr::Report(ReportErr::InvalidReason) => {
+                REPORT_VIDEO.invalid_reason.into()
+            }
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/gallery")
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
+            .service(web::resource("/publish").route(web::post().to(publish)))
+            .service(web::resource("/unpublish").route(web::post().to(unpublish)))
+            .service(web::resource("/mine").route(web::get().to(my_publications)))
+            .service(web::resource("/v/{slug}").route(web::get().to(view)))
+            .service(web::resource("/v/{slug}/stream").route(web::get().to(stream)))
+            .service(web::resource("/v/{slug}/report").route(web::post().to(report))),
+    )
+    .service(
+        web::scope("/admin/gallery")
+            .service(web::resource("/reports").route(web::get().to(list_reports)))
+            .service(web::resource("/resolve_report").route(web::post().to(resolve_report))),
+    );
+}
+
+status_doc!();
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        publish,
+        unpublish,
+        my_publications,
+        view,
+        stream,
+        report,
+        list_reports,
+        resolve_report
+    ),
+    components(schemas(
+        PublishDto,
+        PublicationDto,
+        UnpublishDto,
+        ReportVideoDto,
+        ReportState,
+        Moderation,
+        ResolveReportDto,
+        VideoReport,
+        VideoReportList
+    ))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("publish", "Publish"),
+    ("unpublish", "Unpublish"),
+    ("view", "Gallery"),
+    ("stream", "Gallery"),
+    ("report", "ReportVideo"),
+    ("resolve_report", "ResolveReport"),
+];
+
+/// 公开发布转码后的视频，生成固定的短链接。已经发布时返回原来的链接
+#[utoipa::path(
+    post,
+    path = "/api/gallery/publish",
+    request_body = PublishDto,
+    responses((status = 200, body = PublicationDto)),
+    tag = "gallery"
+)]
+async fn publish(owner: SpaceOwner, params: Json<PublishDto>) -> ApiResult<PublicationDto> {
+    let publication = gallery::publish(owner.0, params.into_inner()).await??;
+    ApiResponse::Ok(publication)
+}
+
+/// 取消发布，短链接随之失效
+#[utoipa::path(
+    post,
+    path = "/api/gallery/unpublish",
+    request_body = UnpublishDto,
+    responses((status = 200, description = "成功")),
+    tag = "gallery"
+)]
+async fn unpublish(owner: SpaceOwner, params: Json<UnpublishDto>) -> ApiResult<()> {
+    gallery::unpublish(owner.0, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 发布的所有视频和观看次数，包括被下架的
+#[utoipa::path(
+    get,
+    path = "/api/gallery/mine",
+    responses((status = 200, body = Vec<PublicationDto>)),
+    tag = "gallery"
+)]
+async fn my_publications(owner: SpaceOwner) -> ApiResult<Vec<PublicationDto>> {
+    let publications = gallery::list_mine(owner.0).await?;
+    ApiResponse::Ok(publications)
+}
+
+/// 公开页面的视频信息，不需要登录。同一访客一段时间内的多次访问只计一次观看
+#[utoipa::path(
+    get,
+    path = "/api/gallery/v/{slug}",
+    params(("slug" = String, Path, description = "短链接")),
+    responses((status = 200, body = PublicationDto)),
+    tag = "gallery"
+)]
+async fn view(
+    req: HttpRequest,
+    id: Option<Identity>,
+    slug: web::Path<String>,
+) -> ApiResult<PublicationDto> {
+    let viewer_id = match id {
+        Some(id) => Some(id.id()?.parse::<UserId>()?),
+        None => None,
+    };
+    let publication = gallery::view(&slug, viewer_id, client_ip(&req)).await??;
+    ApiResponse::Ok(publication)
+}
+
+/// 播放公开的视频，不需要登录，支持 Range 请求
+#[utoipa::path(
+    get,
+    path = "/api/gallery/v/{slug}/stream",
+    params(("slug" = String, Path, description = "短链接")),
+    responses((status = 200, body = String, content_type = "video/*")),
+    tag = "gallery"
+)]
+async fn stream(req: HttpRequest, slug: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let file = gallery::stream(&slug).await??;
+    file_system::serve_file(&req, file, false).await
+}
+
+/// 举报公开的视频，未登录的访客也可以举报
+#[utoipa::path(
+    post,
+    path = "/api/gallery/v/{slug}/report",
+    params(("slug" = String, Path, description = "短链接")),
+    request_body = ReportVideoDto,
+    responses((status = 200, description = "成功")),
+    tag = "gallery"
+)]
+async fn report(
+    req: HttpRequest,
+    id: Option<Identity>,
+    slug: web::Path<String>,
+    params: Json<ReportVideoDto>,
+) -> ApiResult<()> {
+    let reporter_id = match id {
+        Some(id) => Some(id.id()?.parse::<UserId>()?),
+        None => None,
+    };
+    let ip = client_ip(&req);
+    gallery::report(&slug, reporter_id, ip, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListReportsParams {
+    /// 默认只看待处理的举报
+    #[serde(default = "pending")]
+    state: ReportState,
+    #[validate(range(min = 1))]
+    page: u32,
+    #[validate(range(min = 1, max = 100))]
+    page_size: u32,
+}
+
+fn pending() -> ReportState {
+    ReportState::Pending
+}
+
+/// 举报的审核队列，按举报时间顺序
+#[utoipa::path(
+    get,
+    path = "/admin/gallery/reports",
+    params(ListReportsParams),
+    responses((status = 200, body = VideoReportList)),
+    tag = "gallery"
+)]
+async fn list_reports(
+    _id: Identity,
+    params: ValidQuery<ListReportsParams>,
+) -> ApiResult<VideoReportList> {
+    let page = Paginate {
+        page: params.page,
+        page_size: params.page_size,
+    };
+    let reports = VideoReportList::load(params.state, page).await?;
+    ApiResponse::Ok(reports)
+}
+
+/// 处理举报：驳回，或者下架视频。下架后同一视频的其他待处理举报一并处理，用户不能再次发布
+#[utoipa::path(
+    post,
+    path = "/admin/gallery/resolve_report",
+    request_body = ResolveReportDto,
+    responses((status = 200, description = "成功")),
+    tag = "gallery"
+)]
+async fn resolve_report(id: Identity, params: Json<ResolveReportDto>) -> ApiResult<()> {
+    let employee_id = id.id()?.parse()?;
+    gallery::resolve_report(employee_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
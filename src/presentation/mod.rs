@@ -5,8 +5,14 @@ use serde::Serialize;
 
 use crate::http::{ApiResponse, ApiResult};
 
+pub mod email;
 pub mod employee;
+pub mod feature_flag;
 pub mod file_system;
+pub mod gallery;
+pub mod notification;
+pub mod openapi;
+pub mod organization;
 pub mod transcode;
 pub mod user;
 
@@ -47,7 +53,8 @@ macro_rules! status_doc {
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/admin/doc").route(web::get().to(doc)))
-        .service(web::resource("/api/doc").route(web::get().to(doc)));
+        .service(web::resource("/api/doc").route(web::get().to(doc)))
+        .service(web::resource("/api/openapi.json").route(web::get().to(openapi::openapi_json)));
 }
 
 pub async fn doc() -> ApiResult<Vec<StatusCode>> {
@@ -55,12 +62,20 @@ pub async fn doc() -> ApiResult<Vec<StatusCode>> {
     let fs_doc = file_system::biz_status_doc_inner();
     let employee_doc = employee::biz_status_doc_inner();
     let transcode_doc = transcode::biz_status_doc_inner();
+    let email_doc = email::biz_status_doc_inner();
+    let feature_flag_doc = feature_flag::biz_status_doc_inner();
+    let organization_doc = organization::biz_status_doc_inner();
+    let gallery_doc = gallery::biz_status_doc_inner();
 
     let mut doc = Vec::new();
     doc.extend(user_doc);
     doc.extend(fs_doc);
     doc.extend(employee_doc);
     doc.extend(transcode_doc);
+    doc.extend(email_doc);
+    doc.extend(feature_flag_doc);
+    doc.extend(organization_doc);
+    doc.extend(gallery_doc);
 
     let mut uniques = HashSet::new();
     doc.retain(|d| uniques.insert(d.code));
@@ -0,0 +1,197 @@
+use std::{convert::Infallible, fmt::Write, future::ready, time::Duration};
+
+use actix_identity::Identity;
+use actix_web::{
+    http::header,
+    web::{self, Bytes, Json},
+    HttpRequest, HttpResponse,
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::warn;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use validator::Validate;
+
+use crate::{
+    application::notification,
+    cqrs::{
+        notification::{Notification, NotificationList},
+        Paginate,
+    },
+    domain::{notification::NotificationId, user::user::UserId},
+    http::{validation::ValidQuery, ApiError, ApiResponse, ApiResult},
+};
+
+/// 空闲时发送注释的间隔，避免连接被代理断开
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// 一次推送的最大通知数
+const SSE_BATCH: u32 = 100;
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/notification")
+            .service(web::resource("/list").route(web::get().to(list)))
+            .service(web::resource("/mark_read").route(web::post().to(mark_read))),
+    )
+    .service(web::resource("/api/events").route(web::get().to(events)));
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list, mark_read, events),
+    components(schemas(
+        NotificationList,
+        crate::cqrs::notification::Notification,
+        crate::cqrs::notification::NotificationKind,
+        MarkReadParams,
+        MarkReadResp,
+    ))
+)]
+pub struct ApiDoc;
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    #[validate(range(min = 1))]
+    page: u32,
+    #[validate(range(min = 1, max = 100))]
+    page_size: u32,
+    #[serde(default)]
+    unread_only: bool,
+}
+
+/// 分页加载通知
+#[utoipa::path(
+    get,
+    path = "/api/notification/list",
+    operation_id = "list_notifications",
+    params(ListParams),
+    responses((status = 200, body = NotificationList)),
+    tag = "notification"
+)]
+pub async fn list(id: Identity, params: ValidQuery<ListParams>) -> ApiResult<NotificationList> {
+    let id = id.id()?.parse::<UserId>()?;
+    let page = Paginate {
+        page: params.page,
+        page_size: params.page_size,
+    };
+    let list = NotificationList::load(id, page, params.unread_only).await?;
+    ApiResponse::Ok(list)
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkReadParams {
+    /// 为空时标记全部通知
+    #[serde(default)]
+    ids: Vec<NotificationId>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkReadResp {
+    marked: usize,
+}
+
+/// 标记通知为已读
+#[utoipa::path(
+    post,
+    path = "/api/notification/mark_read",
+    operation_id = "mark_notifications_read",
+    request_body = MarkReadParams,
+    responses((status = 200, body = MarkReadResp)),
+    tag = "notification"
+)]
+pub async fn mark_read(id: Identity, params: Json<MarkReadParams>) -> ApiResult<MarkReadResp> {
+    let id = id.id()?.parse::<UserId>()?;
+    let marked = notification::mark_read(id, params.into_inner().ids).await?;
+    ApiResponse::Ok(MarkReadResp { marked })
+}
+
+/// 以 Server-Sent Events 推送新通知，用于无法使用 WebSocket 的客户端。
+/// 事件 id 为通知在用户内的序号，断线重连时带上 Last-Event-ID 从该通知之后继续推送，否则只推送连接之后的通知
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    params(("Last-Event-ID" = Option<String>, Header, description = "最后收到的事件 id")),
+    responses((status = 200, body = Notification, content_type = "text/event-stream")),
+    tag = "notification"
+)]
+pub async fn events(id: Identity, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let resume_seq: Option<i64> = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    // 先订阅再读取位置，期间产生的通知不会漏掉
+    let rx = notification::watch();
+    let latest = Notification::latest_seq(user_id).await?;
+    // 超出范围的 id 来自旧版本的客户端，从当前位置开始推送
+    let last_seq = resume_seq.filter(|seq| *seq <= latest).unwrap_or(latest);
+
+    let retry = Bytes::from_static(b"retry: 3000\n\n");
+    let events = stream::unfold((last_seq, rx), move |(last_seq, mut rx)| async move {
+        let (bytes, last_seq) = next_events(user_id, last_seq, &mut rx).await;
+        Some((bytes, (last_seq, rx)))
+    });
+    let body = stream::once(ready(retry))
+        .chain(events)
+        .map(Ok::<_, Infallible>);
+    let resp = HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // 关闭 nginx 的响应缓冲
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body);
+    Ok(resp)
+}
+
+/// 等到有新通知或需要保持连接时返回要发送的内容，以及推送到的位置。
+/// 本实例产生的通知在提交后立即唤醒；其他实例产生的通知在保持连接前的检查中推送
+async fn next_events(
+    user_id: UserId,
+    last_seq: i64,
+    rx: &mut broadcast::Receiver<UserId>,
+) -> (Bytes, i64) {
+    let mut idle = false;
+    loop {
+        match Notification::load_after(user_id, last_seq, SSE_BATCH).await {
+            Ok(notifications) if !notifications.is_empty() => {
+                let mut buf = String::new();
+                for n in &notifications {
+                    let data = serde_json::to_string(n).expect("notification is serializable");
+                    let _ = write!(
+                        buf,
+                        "id: {}\nevent: notification\ndata: {}\n\n",
+                        n.seq(),
+                        data
+                    );
+                }
+                let last_seq = notifications.last().map_or(last_seq, Notification::seq);
+                return (Bytes::from(buf), last_seq);
+            }
+            Ok(_) => {}
+            Err(err) => warn!(?err, %user_id, "load notifications for sse failed"),
+        }
+        if idle {
+            return (Bytes::from_static(b": keep-alive\n\n"), last_seq);
+        }
+        idle = tokio::time::timeout(SSE_KEEP_ALIVE, woken(user_id, rx))
+            .await
+            .is_err();
+    }
+}
+
+/// 等到该用户有新通知。错过消息时也返回，由调用方重新加载
+async fn woken(user_id: UserId, rx: &mut broadcast::Receiver<UserId>) {
+    loop {
+        match rx.recv().await {
+            Ok(id) if id == user_id => return,
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! REST 接口的 OpenAPI 3 文档
+//!
+//! 接口和请求/响应结构由各模块的 `ApiDoc` 描述，这里负责合并，并补充统一的响应包装和业务状态码：
+//! - 成功响应统一包装为 `{ status, errMsg, data }`
+//! - 业务错误统一返回 400，`status` 为业务状态码
+//! - 参数无法解析或校验失败返回 422，`errors` 中为各个字段的错误
+//! - 所有业务状态码放在 `x-biz-status-codes` 中，每个接口专属的状态码放在该接口的 `x-biz-codes` 中
+
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use actix_web::HttpResponse;
+use serde_json::{json, Map, Value};
+use utoipa::OpenApi;
+
+use super::{
+    email, employee, feature_flag, file_system, gallery, notification, organization, transcode,
+    user, StatusCode,
+};
+use crate::domain::{
+    file_system::{
+        comment::FileCommentId,
+        file::{SysFileId, UserFileId},
+        import_job::ImportJobId,
+        import_url::ImportTaskId,
+        service_upload::UploadTaskId,
+        version::FileVersionId,
+    },
+    gallery::{PublicationId, ReportId},
+    notification::NotificationId,
+    transcode_order::{rule::TranscodeRuleId, TranscodeOrderId, TranscodeTaskId},
+    user::{user::UserId, webhook::WebhookId},
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "av1-cloud",
+        description = "用户接口位于 /api 下，管理员接口位于 /admin 下，均通过 cookie 中的会话鉴权，登录后的 /api 修改类请求需要在 X-CSRF-Token 请求头中携带 XSRF-TOKEN cookie 的值。GraphQL 查询接口不在本文档中"
+    ),
+    components(schemas(
+        UserId,
+        UserFileId,
+        SysFileId,
+        UploadTaskId,
+        ImportTaskId,
+        ImportJobId,
+        FileVersionId,
+        FileCommentId,
+        TranscodeOrderId,
+        TranscodeTaskId,
+        TranscodeRuleId,
+        NotificationId,
+        WebhookId,
+        PublicationId,
+        ReportId,
+        crate::cqrs::MillionTimestamp,
+        crate::http::validation::FieldError,
+    )),
+    tags(
+        (name = "user", description = "用户"),
+        (name = "employee", description = "员工"),
+        (name = "fs", description = "文件系统"),
+        (name = "order", description = "转码订单"),
+        (name = "notification", description = "通知"),
+        (name = "email", description = "邮件模板"),
+        (name = "feature_flag", description = "功能开关"),
+        (name = "organization", description = "组织，在 X-Org-Id 请求头中指定组织即可操作组织的共享空间"),
+        (name = "gallery", description = "公开画廊，公开页面和播放地址不需要登录"),
+        (name = "internal", description = "转码工厂的回调，不对外开放"),
+    )
+)]
+struct ApiDoc;
+
+pub async fn openapi_json() -> HttpResponse {
+    static DOC: OnceLock<Value> = OnceLock::new();
+    let doc = DOC.get_or_init(build);
+    HttpResponse::Ok().json(doc)
+}
+
+fn build() -> Value {
+    let mut doc = ApiDoc::openapi();
+    doc.merge(user::ApiDoc::openapi());
+    doc.merge(employee::ApiDoc::openapi());
+    doc.merge(file_system::ApiDoc::openapi());
+    doc.merge(transcode::ApiDoc::openapi());
+    doc.merge(notification::ApiDoc::openapi());
+    doc.merge(email::ApiDoc::openapi());
+    doc.merge(feature_flag::ApiDoc::openapi());
+    doc.merge(organization::ApiDoc::openapi());
+    doc.merge(gallery::ApiDoc::openapi());
+
+    let mut doc = serde_json::to_value(doc).expect("openapi is serializable");
+
+    let modules = [
+        ("user", user::biz_status_doc_inner(), user::BIZ_ENDPOINTS),
+        (
+            "employee",
+            employee::biz_status_doc_inner(),
+            employee::BIZ_ENDPOINTS,
+        ),
+        (
+            "fs",
+            file_system::biz_status_doc_inner(),
+            file_system::BIZ_ENDPOINTS,
+        ),
+        (
+            "order",
+            transcode::biz_status_doc_inner(),
+            transcode::BIZ_ENDPOINTS,
+        ),
+        ("email", email::biz_status_doc_inner(), email::BIZ_ENDPOINTS),
+        (
+            "feature_flag",
+            feature_flag::biz_status_doc_inner(),
+            feature_flag::BIZ_ENDPOINTS,
+        ),
+        (
+            "organization",
+            organization::biz_status_doc_inner(),
+            organization::BIZ_ENDPOINTS,
+        ),
+        (
+            "gallery",
+            gallery::biz_status_doc_inner(),
+            gallery::BIZ_ENDPOINTS,
+        ),
+    ];
+
+    if let Some(paths) = doc["paths"].as_object_mut() {
+        for item in paths.values_mut().filter_map(Value::as_object_mut) {
+            for operation in item.values_mut().filter_map(Value::as_object_mut) {
+                wrap_responses(operation);
+
+                let field = |name: &str| operation.get(name).cloned().unwrap_or_default();
+                let tag = field("tags")[0].as_str().unwrap_or_default().to_string();
+                let operation_id = field("operationId")
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let codes = modules
+                    .iter()
+                    .filter(|(module, _, _)| *module == tag)
+                    .flat_map(|(_, codes, endpoints)| {
+                        let endpoint = endpoints
+                            .iter()
+                            .find(|(id, _)| *id == operation_id)
+                            .map(|(_, endpoint)| *endpoint);
+                        codes.iter().filter(move |c| Some(c.endpoint) == endpoint)
+                    })
+                    .map(|c| c.code)
+                    .collect::<Vec<_>>();
+                if !codes.is_empty() {
+                    operation.insert("x-biz-codes".to_string(), json!(codes));
+                }
+            }
+        }
+    }
+
+    let mut uniques = HashSet::new();
+    let all_codes = modules
+        .iter()
+        .flat_map(|(_, codes, _)| codes.iter())
+        .filter(|c| uniques.insert(c.code))
+        .collect::<Vec<&StatusCode>>();
+    doc["x-biz-status-codes"] = json!(all_codes);
+
+    doc
+}
+
+/// 把成功响应包装进统一的响应结构，并补充业务错误的响应
+fn wrap_responses(operation: &mut Map<String, Value>) {
+    let Some(responses) = operation
+        .get_mut("responses")
+        .and_then(Value::as_object_mut)
+    else {
+        return;
+    };
+
+    if let Some(ok) = responses.get_mut("200").and_then(Value::as_object_mut) {
+        match ok.get_mut("content").and_then(Value::as_object_mut) {
+            None => {
+                ok.insert(
+                    "content".to_string(),
+                    json!({ "application/json": { "schema": envelope(None) } }),
+                );
+            }
+            Some(content) => {
+                for (content_type, media) in content.iter_mut() {
+                    if content_type == "application/json" {
+                        let data = media["schema"].take();
+                        media["schema"] = envelope(Some(data));
+                    } else if !content_type.starts_with("text/") {
+                        media["schema"] = json!({ "type": "string", "format": "binary" });
+                    }
+                }
+            }
+        }
+    }
+
+    responses.insert(
+        "400".to_string(),
+        json!({
+            "description": "请求失败，status 为业务状态码，含义见 x-biz-codes 和 x-biz-status-codes",
+            "content": { "application/json": { "schema": envelope(None) } }
+        }),
+    );
+
+    let mut invalid = envelope(None);
+    invalid["properties"]["errors"] = json!({
+        "type": "array",
+        "items": { "$ref": "#/components/schemas/FieldError" }
+    });
+    responses.insert(
+        "422".to_string(),
+        json!({
+            "description": "参数无法解析或校验失败，status 为 900 ~ 909",
+            "content": { "application/json": { "schema": invalid } }
+        }),
+    );
+}
+
+fn envelope(data: Option<Value>) -> Value {
+    let mut properties = json!({
+        "status": {
+            "type": "integer",
+            "format": "int32",
+            "description": "业务状态码，0 表示成功"
+        },
+        "errMsg": { "type": "string", "nullable": true }
+    });
+    if let Some(data) = data {
+        properties["data"] = data;
+    }
+    json!({
+        "type": "object",
+        "required": ["status"],
+        "properties": properties
+    })
+}
@@ -0,0 +1,269 @@
+use actix_identity::Identity;
+use actix_web::{
+    dev::Payload,
+    web::{self, Json},
+    FromRequest, HttpRequest,
+};
+use futures_util::future::LocalBoxFuture;
+use utils::code;
+use utoipa::OpenApi;
+
+use crate::{
+    application::organization::{
+        self, AddMemberDto, CreateOrgDto, CreateOrgResp, ManageMemberErr, MemberDto, OrgDto,
+        RemoveMemberDto, SetOrgQuotaDto, SetOrgQuotaErr, SetRoleDto,
+    },
+    domain::{
+        organization::{OrgErr, OrgId, OrgRole},
+        user::user::UserId,
+    },
+    http::{ApiError, ApiResponse, ApiResult},
+    status_doc,
+};
+
+/// 指定操作的组织空间的请求头
+pub const ORG_ID_HEADER: &str = "X-Org-Id";
+
+code! {
+    mod = "organization";
+    index = 16;
+    err_trait = crate::http::HttpBizError;
+
+    ---
+
+    CreateOrg {
+        invalid_name = "组织名称不能为空，最长 32 个字符"
+    }
+
+    OrgSpace {
+        not_member = "不是该组织的成员"
+    }
+
+    ManageMember {
+        permission_denied = "没有管理该成员的权限",
+        last_owner = "组织至少要保留一个所有者",
+        user_not_found = "用户不存在",
+        already_member = "用户已经是组织成员",
+        member_not_found = "该用户不是组织成员"
+    }
+
+    SetOrgQuota {
+        org_not_found = "组织不存在"
+    }
+}
+
+impl From<OrgErr> for ApiError {
+    fn from(value: OrgErr) -> Self {
+        match value {
+            OrgErr::InvalidName => CREATE_ORG.invalid_name.into(),
+            OrgErr::NotMember => ORG_SPACE.not_member.into(),
+            OrgErr::PermissionDenied => MANAGE_MEMBER.permission_denied.into(),
+            OrgErr::LastOwner => MANAGE_MEMBER.last_owner.into(),
+        }
+    }
+}
+
+impl From<ManageMemberErr> for ApiError {
+    fn from(value: ManageMemberErr) -> Self {
+        match value {
+            ManageMemberErr::Org(err) => err.into(),
+            ManageMemberErr::UserNotFound => MANAGE_MEMBER.user_not_found.into(),
+            ManageMemberErr::AlreadyMember => MANAGE_MEMBER.already_member.into(),
+            ManageMemberErr::MemberNotFound => MANAGE_MEMBER.member_not_found.into(),
+        }
+    }
+}
+
+impl From<SetOrgQuotaErr> for ApiError {
+    fn from(value: SetOrgQuotaErr) -> Self {
+        match value {
+            SetOrgQuotaErr::OrgNotFound => SET_ORG_QUOTA.org_not_found.into(),
+        }
+    }
+}
+
+/// 操作的空间的所有者。请求头带 [`ORG_ID_HEADER`] 时为组织空间的账号，否则为当前用户。
+/// 同时记录当前登录的用户，组织成员共用空间的账号，文件锁等需要区分成员的地方使用 [`SpaceOwner::member`]
+pub struct SpaceOwner(pub UserId, UserId);
+
+impl SpaceOwner {
+    /// 当前登录的用户
+    pub fn member(&self) -> UserId {
+        self.1
+    }
+}
+
+impl FromRequest for SpaceOwner {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let identity = Identity::from_request(req, payload);
+        let org_id = req
+            .headers()
+            .get(ORG_ID_HEADER)
+            .map(|v| v.to_str().unwrap_or_default().to_string());
+        Box::pin(async move {
+            let owner = resolve_owner(identity.await?, org_id).await?;
+            Ok(owner)
+        })
+    }
+}
+
+async fn resolve_owner(id: Identity, org_id: Option<String>) -> Result<SpaceOwner, ApiError> {
+    let user_id: UserId = id.id()?.parse()?;
+    let Some(org_id) = org_id else {
+        return Ok(SpaceOwner(user_id, user_id));
+    };
+    let space_id = organization::resolve_space(user_id, org_id.parse()?).await??;
+    Ok(SpaceOwner(space_id, user_id))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/org")
+            .service(web::resource("/create").route(web::post().to(create_org)))
+            .service(web::resource("/list").route(web::get().to(list_orgs)))
+            .service(web::resource("/members/{org_id}").route(web::get().to(list_members)))
+            .service(web::resource("/add_member").route(web::post().to(add_member)))
+            .service(web::resource("/set_role").route(web::post().to(set_role)))
+            .service(web::resource("/remove_member").route(web::post().to(remove_member)))
+            .service(web::resource("/doc").route(web::get().to(biz_status_doc))),
+    )
+    .service(web::resource("/admin/org/set_quota").route(web::post().to(set_quota)));
+}
+
+status_doc!();
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        create_org,
+        list_orgs,
+        list_members,
+        add_member,
+        set_role,
+        remove_member,
+        set_quota
+    ),
+    components(schemas(
+        OrgId,
+        OrgRole,
+        CreateOrgDto,
+        CreateOrgResp,
+        OrgDto,
+        MemberDto,
+        AddMemberDto,
+        SetRoleDto,
+        RemoveMemberDto,
+        SetOrgQuotaDto
+    ))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("create_org", "CreateOrg"),
+    ("list_members", "OrgSpace"),
+    ("add_member", "ManageMember"),
+    ("set_role", "ManageMember"),
+    ("remove_member", "ManageMember"),
+    ("set_quota", "SetOrgQuota"),
+];
+
+/// 创建组织，同时创建组织的共享空间，创建者成为所有者
+#[utoipa::path(
+    post,
+    path = "/api/org/create",
+    request_body = CreateOrgDto,
+    responses((status = 200, body = CreateOrgResp)),
+    tag = "organization"
+)]
+async fn create_org(id: Identity, params: Json<CreateOrgDto>) -> ApiResult<CreateOrgResp> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let resp = organization::create(user_id, params.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 当前用户加入的所有组织
+#[utoipa::path(
+    get,
+    path = "/api/org/list",
+    responses((status = 200, body = Vec<OrgDto>)),
+    tag = "organization"
+)]
+async fn list_orgs(id: Identity) -> ApiResult<Vec<OrgDto>> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let orgs = organization::list_mine(user_id).await?;
+    ApiResponse::Ok(orgs)
+}
+
+/// 组织的所有成员
+#[utoipa::path(
+    get,
+    path = "/api/org/members/{org_id}",
+    params(("org_id" = OrgId, Path, description = "组织 id")),
+    responses((status = 200, body = Vec<MemberDto>)),
+    tag = "organization"
+)]
+async fn list_members(id: Identity, org_id: web::Path<OrgId>) -> ApiResult<Vec<MemberDto>> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let members = organization::members(user_id, org_id.into_inner()).await??;
+    ApiResponse::Ok(members)
+}
+
+/// 按邮箱邀请已注册的用户加入组织。管理员不能授予所有者角色，普通成员不能邀请
+#[utoipa::path(
+    post,
+    path = "/api/org/add_member",
+    request_body = AddMemberDto,
+    responses((status = 200, description = "成功")),
+    tag = "organization"
+)]
+async fn add_member(id: Identity, params: Json<AddMemberDto>) -> ApiResult<()> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    organization::add_member(user_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 修改成员角色。所有者可以修改任何人，管理员只能修改普通成员
+#[utoipa::path(
+    post,
+    path = "/api/org/set_role",
+    request_body = SetRoleDto,
+    responses((status = 200, description = "成功")),
+    tag = "organization"
+)]
+async fn set_role(id: Identity, params: Json<SetRoleDto>) -> ApiResult<()> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    organization::set_role(user_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 移出成员，移出自己表示退出组织
+#[utoipa::path(
+    post,
+    path = "/api/org/remove_member",
+    request_body = RemoveMemberDto,
+    responses((status = 200, description = "成功")),
+    tag = "organization"
+)]
+async fn remove_member(id: Identity, params: Json<RemoveMemberDto>) -> ApiResult<()> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    organization::remove_member(user_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 设置组织共享空间的存储配额，为空时按空间账号的等级
+#[utoipa::path(
+    post,
+    path = "/admin/org/set_quota",
+    request_body = SetOrgQuotaDto,
+    responses((status = 200, description = "成功")),
+    tag = "organization"
+)]
+async fn set_quota(id: Identity, params: Json<SetOrgQuotaDto>) -> ApiResult<()> {
+    let employee_id = id.id()?.parse()?;
+    organization::set_quota(employee_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
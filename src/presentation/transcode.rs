@@ -1,15 +1,46 @@
 use actix_identity::Identity;
 use actix_web::web::{self, Json};
+use anyhow::anyhow;
+use futures_util::StreamExt;
 use serde::Deserialize;
-use tracing::warn;
 use utils::code;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use validator::Validate;
 
 use crate::{
+    application::callback::{self, ReplayCallbackErr, StuckCallbackDto},
+    application::schedule::{self, RescheduleDto, RescheduleErr},
+    application::task_log::{
+        self, StoreTaskLogErr, TaskLogDetailDto, TaskLogDto, TaskLogErr, TaskStatusDto,
+    },
+    application::task_result::{self, MediaSummaryDto, TaskResultDto, TaskResultErr},
     application::transcode::{
-        self, CreateOrderErr, CreateOrderResp, TaskResult, TranscodeParamsDto,
+        self, CancelOrderErr, CapabilitiesDto, CreateOrderErr, CreateOrderResp, PreviewDto,
+        RecommendErr, RecommendationDto, TranscodeParamsDto, TranscodeResult,
+    },
+    application::transcode_rule::{
+        self, CreateRuleDto, CreateRuleErr, DeleteRuleErr, RuleParamsDto, TranscodeRuleDto,
+        UpdateRuleDto, UpdateRuleErr,
+    },
+    application::watchdog::{self, LongRunningTaskDto},
+    application::worker::{
+        self, HeartbeatDto, HeartbeatErr, RegisterWorkerDto, RegisterWorkerResp,
+    },
+    domain::{
+        file_system::file::UserFileId,
+        transcode_order::compat::ParamsErr,
+        transcode_order::params::{self, audio, zcode},
+        transcode_order::rule::{RuleErr, TranscodeRuleId},
+        transcode_order::{TranscodeOrderId, TranscodeTaskId},
+        user::user::UserId,
+    },
+    http::{
+        idempotency::Idempotent,
+        internal_auth::InternalAuth,
+        validation::{ValidJson, ValidQuery},
+        ApiError, ApiResponse, ApiResult,
     },
-    domain::user::user::UserId,
-    http::{ApiError, ApiResponse, ApiResult},
+    presentation::organization::SpaceOwner,
     status_doc,
 };
 
@@ -22,7 +53,74 @@ code! {
     CreateOrder {
         file_not_fount = "文件不存在",
         file_is_dir = "该文件是一个文件夹",
-        not_a_video = "文件文件不是一个视频"
+        not_a_video = "文件文件不是一个视频",
+        subtitle_not_attached = "字幕未关联到该视频",
+        multiple_burned_subtitles = "最多只能烧录一个字幕",
+        duplicate_rendition = "输出的清晰度重复",
+        insufficient_credits = "积分不足",
+        insufficient_storage = "存储空间不足，请稍后重试",
+        invalid_start_time = "预约时间超出允许的范围",
+        hdr_codec = "HDR 视频只能转码为 H265 或 AV1",
+        hdr_quality = "HDR 视频不能使用基础画质",
+        upscale = "输出分辨率不能高于源视频",
+        container_mismatch = "封装格式不支持该视频编码",
+        audio_container_mismatch = "封装格式不支持该音频编码",
+        too_many_active_tasks = "未完成的转码任务太多，请等待部分任务完成后再试",
+        too_many_tasks = "订单的任务数超出了会员等级允许同时转码的上限",
+    }
+
+    CancelOrder {
+        order_not_found = "订单不存在",
+        already_ended = "订单已结束",
+    }
+
+    RescheduleOrder {
+        order_not_found = "订单不存在",
+        not_scheduled = "订单已开始转码或已取消",
+        invalid_start_time = "预约时间超出允许的范围",
+    }
+
+    Recommend {
+        file_not_found = "文件不存在",
+        not_a_video = "文件不是一个视频",
+    }
+
+    ReplayCallback {
+        not_found = "回调记录不存在或已处理成功",
+    }
+
+    WorkerHeartbeat {
+        unknown_worker = "节点未注册或已被替换，请重新注册",
+    }
+
+    TaskLog {
+        task_not_found = "任务不存在",
+    }
+
+    TaskResult {
+        task_not_found = "任务不存在",
+        not_finished = "任务还没有转码成功",
+    }
+
+    UploadTaskLog {
+        task_not_found = "任务不存在",
+        too_large = "日志过大",
+    }
+
+    CreateTranscodeRule {
+        invalid_name = "规则名称不能为空，最多 64 个字符",
+        invalid_dir = "只能监听 /源视频 下的目录",
+        too_many = "规则数量已达上限",
+    }
+
+    UpdateTranscodeRule {
+        not_found = "规则不存在",
+        invalid_name = "规则名称不能为空，最多 64 个字符",
+        invalid_dir = "只能监听 /源视频 下的目录",
+    }
+
+    DeleteTranscodeRule {
+        not_found = "规则不存在",
     }
 }
 
@@ -32,37 +130,606 @@ impl From<CreateOrderErr> for ApiError {
             CreateOrderErr::FileNotFound => CREATE_ORDER.file_not_fount.into(),
             CreateOrderErr::CannotTransDir => CREATE_ORDER.file_is_dir.into(),
             CreateOrderErr::NotAVideo => CREATE_ORDER.not_a_video.into(),
+            CreateOrderErr::SubtitleNotAttached => CREATE_ORDER.subtitle_not_attached.into(),
+            CreateOrderErr::MultipleBurnedSubtitles => {
+                CREATE_ORDER.multiple_burned_subtitles.into()
+            }
+            CreateOrderErr::DuplicateRendition => CREATE_ORDER.duplicate_rendition.into(),
+            CreateOrderErr::InsufficientCredits => CREATE_ORDER.insufficient_credits.into(),
+            CreateOrderErr::InsufficientStorage => CREATE_ORDER.insufficient_storage.into(),
+            CreateOrderErr::InvalidStartTime => CREATE_ORDER.invalid_start_time.into(),
+            CreateOrderErr::TooManyActiveTasks => CREATE_ORDER.too_many_active_tasks.into(),
+            CreateOrderErr::TooManyTasks => CREATE_ORDER.too_many_tasks.into(),
+            CreateOrderErr::IncompatibleParams(err) => match err {
+                ParamsErr::HdrCodec => CREATE_ORDER.hdr_codec.into(),
+                ParamsErr::HdrQuality => CREATE_ORDER.hdr_quality.into(),
+                ParamsErr::Upscale(_) => CREATE_ORDER.upscale.into(),
+                ParamsErr::Container(..) => CREATE_ORDER.container_mismatch.into(),
+                ParamsErr::AudioContainer(..) => CREATE_ORDER.audio_container_mismatch.into(),
+            },
+        }
+    }
+}
+
+impl From<CreateRuleErr> for ApiError {
+    fn from(value: CreateRuleErr) -> Self {
+        match value {
+            CreateRuleErr::Rule(RuleErr::InvalidName) => CREATE_TRANSCODE_RULE.invalid_name.into(),
+            CreateRuleErr::Rule(RuleErr::InvalidDir) => CREATE_TRANSCODE_RULE.invalid_dir.into(),
+            CreateRuleErr::TooMany => CREATE_TRANSCODE_RULE.too_many.into(),
+        }
+    }
+}
+
+impl From<UpdateRuleErr> for ApiError {
+    fn from(value: UpdateRuleErr) -> Self {
+        match value {
+            UpdateRuleErr::NotFound => UPDATE_TRANSCODE_RULE.not_found.into(),
+            UpdateRuleErr::Rule(RuleErr::InvalidName) => UPDATE_TRANSCODE_RULE.invalid_name.into(),
+            UpdateRuleErr::Rule(RuleErr::InvalidDir) => UPDATE_TRANSCODE_RULE.invalid_dir.into(),
+        }
+    }
+}
+
+impl From<DeleteRuleErr> for ApiError {
+    fn from(value: DeleteRuleErr) -> Self {
+        match value {
+            DeleteRuleErr::NotFound => DELETE_TRANSCODE_RULE.not_found.into(),
+        }
+    }
+}
+
+impl From<RescheduleErr> for ApiError {
+    fn from(value: RescheduleErr) -> Self {
+        match value {
+            RescheduleErr::OrderNotFound => RESCHEDULE_ORDER.order_not_found.into(),
+            RescheduleErr::NotScheduled => RESCHEDULE_ORDER.not_scheduled.into(),
+            RescheduleErr::InvalidStartTime => RESCHEDULE_ORDER.invalid_start_time.into(),
+        }
+    }
+}
+
+impl From<CancelOrderErr> for ApiError {
+    fn from(value: CancelOrderErr) -> Self {
+        match value {
+            CancelOrderErr::OrderNotFound => CANCEL_ORDER.order_not_found.into(),
+            CancelOrderErr::AlreadyEnded => CANCEL_ORDER.already_ended.into(),
+        }
+    }
+}
+
+impl From<ReplayCallbackErr> for ApiError {
+    fn from(value: ReplayCallbackErr) -> Self {
+        match value {
+            ReplayCallbackErr::NotFound => REPLAY_CALLBACK.not_found.into(),
+        }
+    }
+}
+
+impl From<HeartbeatErr> for ApiError {
+    fn from(value: HeartbeatErr) -> Self {
+        match value {
+            HeartbeatErr::UnknownWorker => WORKER_HEARTBEAT.unknown_worker.into(),
+        }
+    }
+}
+
+impl From<TaskLogErr> for ApiError {
+    fn from(value: TaskLogErr) -> Self {
+        match value {
+            TaskLogErr::TaskNotFound => TASK_LOG.task_not_found.into(),
+        }
+    }
+}
+
+impl From<TaskResultErr> for ApiError {
+    fn from(value: TaskResultErr) -> Self {
+        match value {
+            TaskResultErr::TaskNotFound => TASK_RESULT.task_not_found.into(),
+            TaskResultErr::NotFinished => TASK_RESULT.not_finished.into(),
+        }
+    }
+}
+
+impl From<StoreTaskLogErr> for ApiError {
+    fn from(value: StoreTaskLogErr) -> Self {
+        match value {
+            StoreTaskLogErr::TaskNotFound => UPLOAD_TASK_LOG.task_not_found.into(),
+            StoreTaskLogErr::TooLarge => UPLOAD_TASK_LOG.too_large.into(),
+        }
+    }
+}
+
+impl From<RecommendErr> for ApiError {
+    fn from(value: RecommendErr) -> Self {
+        match value {
+            RecommendErr::FileNotFound => RECOMMEND.file_not_found.into(),
+            RecommendErr::NotAVideo => RECOMMEND.not_a_video.into(),
         }
     }
 }
 
 status_doc!();
 
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        create_order,
+        cancel_order,
+        reschedule_order,
+        recommend,
+        capabilities,
+        list_transcode_rules,
+        create_transcode_rule,
+        update_transcode_rule,
+        delete_transcode_rule,
+        task_log,
+        task_result,
+        transcode_done,
+        upload_task_log,
+        register_worker,
+        worker_heartbeat,
+        stuck_callbacks_admin,
+        replay_callback_admin,
+        long_running_tasks_admin,
+        task_log_admin
+    ),
+    components(schemas(
+        CreateOrderParams,
+        TranscodeParamsDto,
+        crate::application::transcode::RenditionDto,
+        crate::application::transcode::SubtitleParamsDto,
+        crate::application::transcode::ZcodeProcessParamsDto,
+        CreateOrderResp,
+        PreviewDto,
+        CancelOrderParams,
+        RescheduleDto,
+        RecommendationDto,
+        CapabilitiesDto,
+        crate::application::transcode::ContainerCapabilityDto,
+        TranscodeRuleDto,
+        RuleParamsDto,
+        CreateRuleDto,
+        UpdateRuleDto,
+        TaskLogDto,
+        TaskLogDetailDto,
+        TaskStatusDto,
+        TaskResultDto,
+        MediaSummaryDto,
+        StuckCallbackDto,
+        LongRunningTaskDto,
+        RegisterWorkerDto,
+        RegisterWorkerResp,
+        HeartbeatDto,
+        params::ContainerFormat,
+        params::SubtitleMode,
+        zcode::ZcodeProcessParams,
+        zcode::VideoFormat,
+        zcode::Resolution,
+        zcode::RayTracing,
+        zcode::OutputQuality,
+        audio::AudioProcessParameters,
+        audio::AudioFormat,
+        audio::AudioResampleRate,
+        audio::AudioBitRate,
+        audio::AudioTrack,
+    ))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("create_order", "CreateOrder"),
+    ("cancel_order", "CancelOrder"),
+    ("reschedule_order", "RescheduleOrder"),
+    ("recommend", "Recommend"),
+    ("create_transcode_rule", "CreateTranscodeRule"),
+    ("update_transcode_rule", "UpdateTranscodeRule"),
+    ("delete_transcode_rule", "DeleteTranscodeRule"),
+    ("replay_callback_admin", "ReplayCallback"),
+    ("worker_heartbeat", "WorkerHeartbeat"),
+    ("task_log", "TaskLog"),
+    ("task_log_admin", "TaskLog"),
+    ("task_result", "TaskResult"),
+    ("upload_task_log", "UploadTaskLog"),
+];
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/order")
-            .service(web::resource("/transcode_result").route(web::post().to(transcode_done)))
-            .service(web::resource("/create").route(web::post().to(create_order))),
+            .service(
+                web::resource("/create")
+                    .wrap(Idempotent)
+                    .route(web::post().to(create_order)),
+            )
+            .service(web::resource("/cancel").route(web::post().to(cancel_order)))
+            .service(web::resource("/reschedule").route(web::post().to(reschedule_order))),
+    )
+    .service(
+        web::scope("/api/transcode")
+            .service(web::resource("/recommend/{file_id}").route(web::get().to(recommend)))
+            .service(web::resource("/task_log/{task_id}").route(web::get().to(task_log)))
+            .service(web::resource("/result/{task_id}").route(web::get().to(task_result)))
+            .service(web::resource("/capabilities").route(web::get().to(capabilities)))
+            .service(
+                web::resource("/rules")
+                    .route(web::get().to(list_transcode_rules))
+                    .route(web::post().to(create_transcode_rule)),
+            )
+            .service(
+                web::resource("/rules/{id}")
+                    .route(web::post().to(update_transcode_rule))
+                    .route(web::delete().to(delete_transcode_rule)),
+            ),
+    )
+    // 签名校验在中间件中读取整个请求体，上传日志需要放宽请求体的大小限制，
+    // 这个配置必须在中间件的外层才能生效，并且要在 /internal/order 之前注册
+    .service(
+        web::scope("/internal/order/task_log")
+            .app_data(web::PayloadConfig::new(task_log::MAX_LOG_SIZE as usize))
+            .service(
+                web::scope("")
+                    .wrap(InternalAuth)
+                    .service(web::resource("/{task_id}").route(web::post().to(upload_task_log))),
+            ),
+    )
+    .service(
+        web::scope("/internal/order")
+            .wrap(InternalAuth)
+            .service(web::resource("/transcode_result").route(web::post().to(transcode_done))),
+    )
+    .service(
+        web::scope("/internal/worker")
+            .wrap(InternalAuth)
+            .service(web::resource("/register").route(web::post().to(register_worker)))
+            .service(web::resource("/heartbeat").route(web::post().to(worker_heartbeat))),
+    )
+    .service(
+        web::scope("/admin/callbacks")
+            .service(web::resource("/stuck").route(web::get().to(stuck_callbacks_admin)))
+            .service(web::resource("/replay/{id}").route(web::post().to(replay_callback_admin))),
+    )
+    .service(
+        web::scope("/admin/order")
+            .service(
+                web::resource("/long_running_tasks").route(web::get().to(long_running_tasks_admin)),
+            )
+            .service(web::resource("/task_log/{task_id}").route(web::get().to(task_log_admin))),
     );
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema, Validate)]
 pub struct CreateOrderParams {
+    #[validate(length(min = 1, max = 100))]
+    #[validate]
     params: Vec<TranscodeParamsDto>,
+    /// 指定时只转码视频中的一段，用于快速预览转码效果
+    #[serde(default)]
+    #[validate]
+    preview: Option<PreviewDto>,
+    /// 预约开始转码的时间，毫秒时间戳。不指定或早于当前时间时立即开始
+    #[serde(default)]
+    start_after: Option<i64>,
 }
 
+/// 创建转码订单
+#[utoipa::path(
+    post,
+    path = "/api/order/create",
+    params(("Idempotency-Key" = Option<String>, Header, description = "幂等键，重复请求时返回第一次的响应")),
+    request_body = CreateOrderParams,
+    responses((status = 200, body = CreateOrderResp)),
+    tag = "order"
+)]
 pub async fn create_order(
-    id: Identity,
-    params: Json<CreateOrderParams>,
+    owner: SpaceOwner,
+    params: ValidJson<CreateOrderParams>,
 ) -> ApiResult<CreateOrderResp> {
-    let id = id.id()?.parse::<UserId>()?;
-    let resp = transcode::create_order(id, params.into_inner().params).await??;
+    let id = owner.0;
+    let params = params.into_inner();
+    let resp =
+        transcode::create_order(id, params.params, params.preview, params.start_after).await??;
     ApiResponse::Ok(resp)
 }
 
-async fn transcode_done(params: Json<TaskResult<()>>) -> ApiResult<()> {
-    if let Err(err) = transcode::task_done(params.into_inner()).await {
-        warn!(?err, "transcode done failed");
-    }
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelOrderParams {
+    order_id: TranscodeOrderId,
+}
+
+/// 取消转码订单，退还未完成任务的积分
+#[utoipa::path(
+    post,
+    path = "/api/order/cancel",
+    request_body = CancelOrderParams,
+    responses((status = 200, description = "成功")),
+    tag = "order"
+)]
+pub async fn cancel_order(owner: SpaceOwner, params: Json<CancelOrderParams>) -> ApiResult<()> {
+    let id = owner.0;
+    transcode::cancel_order(id, params.order_id).await??;
+    ApiResponse::Ok(())
+}
+
+/// 修改预约订单的开始时间，订单开始转码后不能修改
+#[utoipa::path(
+    post,
+    path = "/api/order/reschedule",
+    request_body = RescheduleDto,
+    responses((status = 200, description = "成功")),
+    tag = "order"
+)]
+pub async fn reschedule_order(owner: SpaceOwner, params: Json<RescheduleDto>) -> ApiResult<()> {
+    let id = owner.0;
+    schedule::reschedule(id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 根据视频信息推荐转码参数
+#[utoipa::path(
+    get,
+    path = "/api/transcode/recommend/{file_id}",
+    params(("file_id" = UserFileId, Path, description = "视频文件 id")),
+    responses((status = 200, body = RecommendationDto)),
+    tag = "order"
+)]
+pub async fn recommend(
+    owner: SpaceOwner,
+    file_id: web::Path<UserFileId>,
+) -> ApiResult<RecommendationDto> {
+    let id = owner.0;
+    let resp = transcode::recommend(id, file_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 可选的封装格式、视频和音频编码组合，以及 HDR 源视频的限制，用于过滤前端的下拉选项
+#[utoipa::path(
+    get,
+    path = "/api/transcode/capabilities",
+    responses((status = 200, body = CapabilitiesDto)),
+    tag = "order"
+)]
+pub async fn capabilities() -> ApiResult<CapabilitiesDto> {
+    ApiResponse::Ok(transcode::capabilities())
+}
+
+/// 列出当前用户的自动转码规则
+#[utoipa::path(
+    get,
+    path = "/api/transcode/rules",
+    responses((status = 200, body = Vec<TranscodeRuleDto>)),
+    tag = "order"
+)]
+pub async fn list_transcode_rules(id: Identity) -> ApiResult<Vec<TranscodeRuleDto>> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let rules = transcode_rule::list(user_id).await?;
+    ApiResponse::Ok(rules)
+}
+
+/// 创建自动转码规则，之后上传到监听目录的视频按规则的参数自动转码
+#[utoipa::path(
+    post,
+    path = "/api/transcode/rules",
+    request_body = CreateRuleDto,
+    responses((status = 200, body = TranscodeRuleDto)),
+    tag = "order"
+)]
+pub async fn create_transcode_rule(
+    id: Identity,
+    params: ValidJson<CreateRuleDto>,
+) -> ApiResult<TranscodeRuleDto> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let rule = transcode_rule::create(user_id, params.into_inner()).await??;
+    ApiResponse::Ok(rule)
+}
+
+/// 修改自动转码规则，只修改请求中指定的字段
+#[utoipa::path(
+    post,
+    path = "/api/transcode/rules/{id}",
+    params(("id" = TranscodeRuleId, Path, description = "规则 id")),
+    request_body = UpdateRuleDto,
+    responses((status = 200, body = TranscodeRuleDto)),
+    tag = "order"
+)]
+pub async fn update_transcode_rule(
+    id: Identity,
+    rule_id: web::Path<TranscodeRuleId>,
+    params: ValidJson<UpdateRuleDto>,
+) -> ApiResult<TranscodeRuleDto> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let rule = transcode_rule::update(user_id, rule_id.into_inner(), params.into_inner()).await??;
+    ApiResponse::Ok(rule)
+}
+
+/// 删除自动转码规则
+#[utoipa::path(
+    delete,
+    path = "/api/transcode/rules/{id}",
+    params(("id" = TranscodeRuleId, Path, description = "规则 id")),
+    responses((status = 200, description = "成功")),
+    tag = "order"
+)]
+pub async fn delete_transcode_rule(
+    id: Identity,
+    rule_id: web::Path<TranscodeRuleId>,
+) -> ApiResult<()> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    transcode_rule::delete(user_id, rule_id.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 查看自己的转码任务的日志，只返回日志的末尾部分
+#[utoipa::path(
+    get,
+    path = "/api/transcode/task_log/{task_id}",
+    params(("task_id" = TranscodeTaskId, Path, description = "转码任务 id")),
+    responses((status = 200, body = TaskLogDto)),
+    tag = "order"
+)]
+pub async fn task_log(
+    owner: SpaceOwner,
+    task_id: web::Path<TranscodeTaskId>,
+) -> ApiResult<TaskLogDto> {
+    let id = owner.0;
+    let resp = task_log::user_log(id, task_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 查看转码成功的任务与源文件的对比，包括大小、码率、编码、分辨率、时长和压缩比
+#[utoipa::path(
+    get,
+    path = "/api/transcode/result/{task_id}",
+    params(("task_id" = TranscodeTaskId, Path, description = "转码任务 id")),
+    responses((status = 200, body = TaskResultDto)),
+    tag = "order"
+)]
+pub async fn task_result(
+    owner: SpaceOwner,
+    task_id: web::Path<TranscodeTaskId>,
+) -> ApiResult<TaskResultDto> {
+    let id = owner.0;
+    let resp = task_result::task_result(id, task_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
+
+/// 上传转码任务的日志，请求体为日志原文，由转码工厂调用
+#[utoipa::path(
+    post,
+    path = "/internal/order/task_log/{task_id}",
+    params(("task_id" = TranscodeTaskId, Path, description = "转码任务 id")),
+    request_body(content = String, content_type = "text/plain"),
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
+async fn upload_task_log(
+    task_id: web::Path<TranscodeTaskId>,
+    payload: web::Payload,
+) -> ApiResult<()> {
+    let data = payload.map(|chunk| chunk.map_err(|err| anyhow!("{err}")));
+    task_log::store(task_id.into_inner(), data).await??;
     ApiResponse::Ok(())
 }
+
+/// 转码任务完成回调，由转码工厂调用
+#[utoipa::path(
+    post,
+    path = "/internal/order/transcode_result",
+    request_body = Object,
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
+async fn transcode_done(params: Json<TranscodeResult>) -> ApiResult<()> {
+    callback::receive_transcode_done(&params).await?;
+    ApiResponse::Ok(())
+}
+
+/// 转码节点注册，返回节点 id 和心跳间隔
+#[utoipa::path(
+    post,
+    path = "/internal/worker/register",
+    request_body = RegisterWorkerDto,
+    responses((status = 200, body = RegisterWorkerResp)),
+    tag = "internal"
+)]
+async fn register_worker(params: ValidJson<RegisterWorkerDto>) -> ApiResult<RegisterWorkerResp> {
+    let resp = worker::register(params.into_inner()).await?;
+    ApiResponse::Ok(resp)
+}
+
+/// 转码节点心跳，节点未注册时需要重新注册
+#[utoipa::path(
+    post,
+    path = "/internal/worker/heartbeat",
+    request_body = HeartbeatDto,
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
+async fn worker_heartbeat(params: Json<HeartbeatDto>) -> ApiResult<()> {
+    worker::heartbeat(params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct StuckCallbacksParams {
+    #[validate(range(min = 1))]
+    page: u32,
+    #[validate(range(min = 1, max = 100))]
+    page_size: u32,
+}
+
+/// 处理失败的转码工厂回调，包括已放弃和仍在重试中的
+#[utoipa::path(
+    get,
+    path = "/admin/callbacks/stuck",
+    params(StuckCallbacksParams),
+    responses((status = 200, body = [StuckCallbackDto])),
+    tag = "internal"
+)]
+async fn stuck_callbacks_admin(
+    _id: Identity,
+    params: ValidQuery<StuckCallbacksParams>,
+) -> ApiResult<Vec<StuckCallbackDto>> {
+    let callbacks = callback::list_stuck(params.page, params.page_size).await?;
+    ApiResponse::Ok(callbacks)
+}
+
+/// 重新处理一条回调
+#[utoipa::path(
+    post,
+    path = "/admin/callbacks/replay/{id}",
+    params(("id" = i64, Path, description = "回调记录 id")),
+    responses((status = 200, description = "成功")),
+    tag = "internal"
+)]
+async fn replay_callback_admin(_id: Identity, id: web::Path<i64>) -> ApiResult<()> {
+    callback::replay(id.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+#[derive(Deserialize, IntoParams, Validate)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct LongRunningTasksParams {
+    /// 只返回已运行超过这个时间（秒）的任务
+    min_secs: u64,
+    #[validate(range(min = 1))]
+    page: u32,
+    #[validate(range(min = 1, max = 100))]
+    page_size: u32,
+}
+
+/// 长时间未结束的转码任务，运行最久的排在前面
+#[utoipa::path(
+    get,
+    path = "/admin/order/long_running_tasks",
+    params(LongRunningTasksParams),
+    responses((status = 200, body = [LongRunningTaskDto])),
+    tag = "order"
+)]
+async fn long_running_tasks_admin(
+    _id: Identity,
+    params: ValidQuery<LongRunningTasksParams>,
+) -> ApiResult<Vec<LongRunningTaskDto>> {
+    let tasks = watchdog::long_running(params.min_secs, params.page, params.page_size).await?;
+    ApiResponse::Ok(tasks)
+}
+
+/// 转码任务的完整日志和参数
+#[utoipa::path(
+    get,
+    path = "/admin/order/task_log/{task_id}",
+    params(("task_id" = TranscodeTaskId, Path, description = "转码任务 id")),
+    responses((status = 200, body = TaskLogDetailDto)),
+    tag = "order"
+)]
+async fn task_log_admin(
+    _id: Identity,
+    task_id: web::Path<TranscodeTaskId>,
+) -> ApiResult<TaskLogDetailDto> {
+    let resp = task_log::admin_log(task_id.into_inner()).await??;
+    ApiResponse::Ok(resp)
+}
@@ -1,18 +1,41 @@
+use actix_files::NamedFile;
 use actix_identity::Identity;
+use actix_session::SessionExt;
 use actix_web::{
-    web::{self, Json, Query},
-    HttpMessage, HttpRequest,
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    web::{self, Bytes, Json, Query},
+    HttpMessage, HttpRequest, HttpResponse,
 };
+use futures_util::TryStreamExt;
 use serde::{Deserialize, Serialize};
 use utils::code;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 
 use crate::{
     application::{
+        captcha::{self, CaptchaErr, Scene},
+        credits::{self, RechargeErr},
         email::{self, CheckEmailCodeErr, SendEmailCodeErr},
-        user::{self, LoginDto, ResetPasswordDto, SendSmsCodeErr, UserDto, UserUpdateDto},
+        user::{
+            self,
+            avatar::{self, UploadAvatarErr},
+            export::{self, ExportErr, ExportJobDto, ExportJobErr, UserExportParams},
+            oauth::{self, OAuthErr},
+            referral::{self, CreateCodeErr, ReferralCodeDto},
+            sms_login::{self, SmsLoginDto, SmsLoginErr},
+            tier::{self, SetLevelDto, SetLevelErr},
+            LoginDto, ResetPasswordDto, SendSmsCodeErr, UserDto, UserUpdateDto,
+        },
+        webhook::{self, CreateWebhookErr, CreatedWebhook, DeleteWebhookErr, WebhookDto},
     },
-    domain::user::service::{LoginErr, RegisterErr, ResetPasswordErr, UpdateProfileErr},
-    http::{ApiError, ApiResponse, ApiResult},
+    domain::user::{
+        avatar::AvatarErr,
+        employee::{EmployeeId, Role},
+        service::{LoginErr, RegisterErr, ResetPasswordErr, UpdateProfileErr},
+        user::UserId,
+        webhook::{WebhookId, WebhookUrlErr},
+    },
+    http::{client_ip::client_ip, csrf, ApiError, ApiResponse, ApiResult},
     log_if_err, status_doc,
 };
 
@@ -22,10 +45,13 @@ code! {
     err_trait = crate::http::HttpBizError; // http 状态码 trait 的路径
 
     pub PasswordFormat = 20 {
-        too_long = "密码太长了，请输入短于 16 个字符的密码",
-        too_short = "密码太短了， 请输入长于 8 个字符的密码",
+        too_long = "密码太长了，请输入更短的密码",
+        too_short = "密码太短了，请输入更长的密码",
         not_allowed_char = "密码中包含不允许使用的字符，请输入字母、数字或下划线",
         too_simple = "密码太简单了，请输入包含字母、数字和下划线的密码",
+        too_few_char_classes = "密码需要包含更多种类的字符，如大小写字母、数字和符号",
+        banned = "密码过于常见，请换一个密码",
+        pwned = "该密码已出现在公开泄露的数据中，请换一个密码",
     }
 
     pub UserNameFormat = 30 {
@@ -40,6 +66,9 @@ code! {
 
     pub PhoneFormatErr = 50 {
         invalid = "请输入格式正确的手机号",
+        unsupported_region = "暂不支持该国家或地区的手机号",
+        wrong_length = "手机号位数不正确，请检查后重新输入",
+        not_mobile = "请输入手机号，不支持固定电话",
     }
 
     pub SanityCheck = 60 {
@@ -48,12 +77,19 @@ code! {
         password_not_match= "密码错误，请重新输入",
     }
 
+    pub Captcha = 70 {
+        required = "请先完成人机验证",
+        invalid = "人机验证未通过，请重试",
+    }
+
     ---
 
     Register {
         use PasswordFormat,
+        use Captcha,
         alredy_register= "账号已被注册，请直接登录",
         no_email_code= "请先获取邮箱验证码，再进行注册",
+        invalid_referral_code = "邀请码无效，请检查后重新输入",
     }
 
     Login {
@@ -61,8 +97,24 @@ code! {
         account_not_match = "账号或密码错误，请重新输入",
     }
 
+    OauthLogin {
+        unknown_provider = "不支持该登录方式",
+        invalid_state = "登录请求已过期，请重新登录",
+        provider_failed = "第三方账号授权失败，请重新登录",
+        already_linked = "该第三方账号已关联其他账号",
+        no_account = "该第三方账号未关联账号，请先登录后关联",
+    }
+
+    LoginSms {
+        use PhoneFormatErr,
+        use SanityCheck,
+        too_many_attempts = "登录尝试次数太多了，请稍后再试",
+        not_registered = "该手机号未绑定账号，请使用邮箱登录",
+    }
+
     SendEmailCode {
         use EmailFormat,
+        use Captcha,
         too_frequent = "获取邮箱验证码太频繁了，请稍后再试"
     }
 
@@ -80,8 +132,48 @@ code! {
     }
 
     SendSmsCode {
+        use Captcha,
         too_frequent ="获取手机验证码太频繁了，请稍后再试"
     }
+
+    RechargeCredits {
+        not_found = "账号不存在"
+    }
+
+    SetUserLevel {
+        not_found = "账号不存在"
+    }
+
+    CreateWebhook {
+        invalid_url = "请输入正确的 http 或 https 地址",
+        too_many = "webhook 数量已达上限",
+        host_not_allowed = "不允许使用内网地址",
+    }
+
+    DeleteWebhook {
+        not_found = "webhook 不存在"
+    }
+
+    CreateReferralCode {
+        too_many = "邀请码数量已达上限",
+    }
+
+    UploadAvatar {
+        too_large = "头像文件不能超过 2MB",
+        invalid_image = "请上传 jpeg、png 或 webp 格式的图片",
+        invalid_size = "头像的宽和高需要在 32 到 4096 像素之间",
+        not_found = "头像不存在",
+    }
+
+    ExportUsers {
+        too_many_rows = "符合条件的用户过多，请使用后台导出",
+        permission_denied = "没有导出未脱敏联系方式的权限",
+    }
+
+    ExportJob {
+        not_found = "导出任务不存在",
+        not_ready = "导出任务尚未完成",
+    }
 }
 
 macro_rules! password_err {
@@ -93,6 +185,11 @@ macro_rules! password_err {
                 PASSWORD_FORMAT.not_allowed_char.into()
             }
             crate::domain::user::PasswordFormatErr::TooSimple => PASSWORD_FORMAT.too_simple.into(),
+            crate::domain::user::PasswordFormatErr::TooFewCharClasses => {
+                PASSWORD_FORMAT.too_few_char_classes.into()
+            }
+            crate::domain::user::PasswordFormatErr::Banned => PASSWORD_FORMAT.banned.into(),
+            crate::domain::user::PasswordFormatErr::Pwned => PASSWORD_FORMAT.pwned.into(),
         }
     };
 }
@@ -118,8 +215,17 @@ macro_rules! email_err {
 }
 
 macro_rules! phone_err {
-    () => {
-        PHONE_FORMAT_ERR.invalid.into()
+    ($p:expr) => {
+        match $p {
+            crate::domain::user::PhoneFormatErr::Invalid => PHONE_FORMAT_ERR.invalid.into(),
+            crate::domain::user::PhoneFormatErr::UnsupportedRegion => {
+                PHONE_FORMAT_ERR.unsupported_region.into()
+            }
+            crate::domain::user::PhoneFormatErr::WrongLength => {
+                PHONE_FORMAT_ERR.wrong_length.into()
+            }
+            crate::domain::user::PhoneFormatErr::NotMobile => PHONE_FORMAT_ERR.not_mobile.into(),
+        }
     };
 }
 
@@ -146,6 +252,7 @@ impl From<RegisterErr> for ApiError {
             RegisterErr::Password(p) => password_err!(p),
             RegisterErr::Email(e) => email_err!(e),
             RegisterErr::AlreadyRegister => REGISTER.alredy_register.into(),
+            RegisterErr::InvalidReferralCode => REGISTER.invalid_referral_code.into(),
             RegisterErr::Sanity(s) => sanity_check!(s),
         }
     }
@@ -171,6 +278,38 @@ impl From<LoginErr> for ApiError {
     }
 }
 
+impl From<SmsLoginErr> for ApiError {
+    fn from(value: SmsLoginErr) -> Self {
+        match value {
+            SmsLoginErr::Phone(e) => phone_err!(e),
+            SmsLoginErr::Sanity(e) => sanity_check!(e),
+            SmsLoginErr::TooManyAttempts => LOGIN_SMS.too_many_attempts.into(),
+            SmsLoginErr::NotRegistered => LOGIN_SMS.not_registered.into(),
+        }
+    }
+}
+
+impl From<CaptchaErr> for ApiError {
+    fn from(value: CaptchaErr) -> Self {
+        match value {
+            CaptchaErr::Required => CAPTCHA.required.into(),
+            CaptchaErr::Invalid => CAPTCHA.invalid.into(),
+        }
+    }
+}
+
+impl From<OAuthErr> for ApiError {
+    fn from(value: OAuthErr) -> Self {
+        match value {
+            OAuthErr::UnknownProvider => OAUTH_LOGIN.unknown_provider.into(),
+            OAuthErr::InvalidState => OAUTH_LOGIN.invalid_state.into(),
+            OAuthErr::ProviderFailed => OAUTH_LOGIN.provider_failed.into(),
+            OAuthErr::AlreadyLinked => OAUTH_LOGIN.already_linked.into(),
+            OAuthErr::NoAccount => OAUTH_LOGIN.no_account.into(),
+        }
+    }
+}
+
 impl From<CheckEmailCodeErr> for ApiError {
     fn from(value: CheckEmailCodeErr) -> Self {
         match value {
@@ -196,7 +335,7 @@ impl From<UpdateProfileErr> for ApiError {
         match value {
             UpdateProfileErr::Name(a) => user_name_err!(a),
             UpdateProfileErr::Password(a) => password_err!(a),
-            UpdateProfileErr::Phone(_) => PHONE_FORMAT_ERR.invalid.into(),
+            UpdateProfileErr::Phone(e) => phone_err!(e),
             UpdateProfileErr::NotFound => UPDATE_PROFILE.not_found.into(),
             UpdateProfileErr::Sanity(s) => sanity_check!(s),
             UpdateProfileErr::PhoneAlreadyBinded => UPDATE_PROFILE.phone_already_binded.into(),
@@ -204,10 +343,84 @@ impl From<UpdateProfileErr> for ApiError {
     }
 }
 
+impl From<CreateWebhookErr> for ApiError {
+    fn from(value: CreateWebhookErr) -> Self {
+        match value {
+            CreateWebhookErr::Url(WebhookUrlErr::PrivateHost) => {
+                CREATE_WEBHOOK.host_not_allowed.into()
+            }
+            CreateWebhookErr::Url(_) => CREATE_WEBHOOK.invalid_url.into(),
+            CreateWebhookErr::TooMany => CREATE_WEBHOOK.too_many.into(),
+        }
+    }
+}
+
+impl From<DeleteWebhookErr> for ApiError {
+    fn from(value: DeleteWebhookErr) -> Self {
+        match value {
+            DeleteWebhookErr::NotFound => DELETE_WEBHOOK.not_found.into(),
+        }
+    }
+}
+
+impl From<UploadAvatarErr> for ApiError {
+    fn from(value: UploadAvatarErr) -> Self {
+        match value {
+            UploadAvatarErr::TooLarge => UPLOAD_AVATAR.too_large.into(),
+            UploadAvatarErr::Avatar(AvatarErr::InvalidImage) => UPLOAD_AVATAR.invalid_image.into(),
+            UploadAvatarErr::Avatar(AvatarErr::InvalidSize(..)) => {
+                UPLOAD_AVATAR.invalid_size.into()
+            }
+        }
+    }
+}
+
+impl From<CreateCodeErr> for ApiError {
+    fn from(value: CreateCodeErr) -> Self {
+        match value {
+            CreateCodeErr::TooMany => CREATE_REFERRAL_CODE.too_many.into(),
+        }
+    }
+}
+
+impl From<RechargeErr> for ApiError {
+    fn from(value: RechargeErr) -> Self {
+        match value {
+            RechargeErr::UserNotFound => RECHARGE_CREDITS.not_found.into(),
+        }
+    }
+}
+
+impl From<SetLevelErr> for ApiError {
+    fn from(value: SetLevelErr) -> Self {
+        match value {
+            SetLevelErr::UserNotFound => SET_USER_LEVEL.not_found.into(),
+        }
+    }
+}
+
+impl From<ExportErr> for ApiError {
+    fn from(value: ExportErr) -> Self {
+        match value {
+            ExportErr::TooManyRows => EXPORT_USERS.too_many_rows.into(),
+            ExportErr::PermissionDenied => EXPORT_USERS.permission_denied.into(),
+        }
+    }
+}
+
+impl From<ExportJobErr> for ApiError {
+    fn from(value: ExportJobErr) -> Self {
+        match value {
+            ExportJobErr::NotFound => EXPORT_JOB.not_found.into(),
+            ExportJobErr::NotReady => EXPORT_JOB.not_ready.into(),
+        }
+    }
+}
+
 impl From<SendSmsCodeErr> for ApiError {
     fn from(value: SendSmsCodeErr) -> Self {
         match value {
-            SendSmsCodeErr::Phone(_) => phone_err!(),
+            SendSmsCodeErr::Phone(e) => phone_err!(e),
             SendSmsCodeErr::TooFrequent => SEND_SMS_CODE.too_frequent.into(),
         }
     }
@@ -221,34 +434,153 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .service(web::resource("/check_email_code").route(web::get().to(check_email_code)))
             .service(web::resource("/register").route(web::post().to(register)))
             .service(web::resource("/login").route(web::post().to(login)))
+            .service(web::resource("/login_sms").route(web::post().to(login_sms)))
+            .service(
+                web::resource("/oauth/{provider}/authorize").route(web::get().to(oauth_authorize)),
+            )
+            .service(
+                web::resource("/oauth/{provider}/callback").route(web::get().to(oauth_callback)),
+            )
             .service(web::resource("/ping").route(web::get().to(user_ping)))
+            .service(web::resource("/csrf_token").route(web::get().to(csrf_token)))
             .service(web::resource("/logout").route(web::post().to(logout)))
             .service(web::resource("/reset_password").route(web::post().to(reset_password)))
             .service(web::resource("/modify_info").route(web::post().to(update_profile)))
             .service(web::resource("/sms_code").route(web::get().to(send_sms_code)))
-            .service(web::resource("/send_email_code").route(web::get().to(send_email_code))),
+            .service(web::resource("/send_email_code").route(web::get().to(send_email_code)))
+            .service(
+                web::resource("/webhooks")
+                    .route(web::get().to(list_webhooks))
+                    .route(web::post().to(create_webhook)),
+            )
+            .service(web::resource("/webhooks/{id}").route(web::delete().to(delete_webhook)))
+            .service(web::resource("/avatar/{hash}").route(web::get().to(avatar_file)))
+            .service(
+                web::resource("/referral_codes")
+                    .route(web::get().to(list_referral_codes))
+                    .route(web::post().to(create_referral_code)),
+            ),
     )
     .service(
         web::scope("/admin/user")
             .service(web::resource("/doc").route(web::get().to(biz_status_doc)))
-            .service(web::resource("/modify").route(web::post().to(update_profile_by_employee))),
+            .service(web::resource("/modify").route(web::post().to(update_profile_by_employee)))
+            .service(web::resource("/recharge_credits").route(web::post().to(recharge_credits)))
+            .service(web::resource("/set_level").route(web::post().to(set_user_level)))
+            .service(web::resource("/export").route(web::post().to(export_users)))
+            .service(web::resource("/export_jobs").route(web::post().to(create_export_job)))
+            .service(web::resource("/export_jobs/{id}").route(web::get().to(export_job)))
+            .service(
+                web::resource("/export_jobs/{id}/download").route(web::get().to(download_export)),
+            ),
     );
 }
 
 status_doc!();
 
-#[derive(Deserialize)]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        check_register,
+        check_email_code,
+        register,
+        login,
+        login_sms,
+        oauth_authorize,
+        oauth_callback,
+        logout,
+        user_ping,
+        csrf_token,
+        send_email_code,
+        reset_password,
+        update_profile,
+        update_profile_by_employee,
+        send_sms_code,
+        recharge_credits,
+        set_user_level,
+        export_users,
+        create_export_job,
+        export_job,
+        download_export,
+        list_webhooks,
+        create_webhook,
+        delete_webhook,
+        list_referral_codes,
+        create_referral_code,
+        avatar_file,
+    ),
+    components(schemas(
+        CheckRgisterdResp,
+        CheckEmailCodeResp,
+        CsrfTokenResp,
+        UserDto,
+        LoginDto,
+        SmsLoginDto,
+        ResetPasswordDto,
+        UserUpdateDto,
+        crate::application::user::UpdatePassword,
+        crate::application::user::MobileNumber,
+        UserUpdateDtoByAdmin,
+        RechargeCreditsParams,
+        RechargeCreditsResp,
+        SetLevelDto,
+        UserExportParams,
+        ExportJobCreated,
+        ExportJobDto,
+        WebhookDto,
+        CreateWebhookParams,
+        CreatedWebhook,
+        ReferralCodeDto,
+    ))
+)]
+pub struct ApiDoc;
+
+/// operation id 与 code! 中接口名的对应关系
+pub const BIZ_ENDPOINTS: &[(&str, &str)] = &[
+    ("register", "Register"),
+    ("login", "Login"),
+    ("login_sms", "LoginSms"),
+    ("oauth_authorize", "OauthLogin"),
+    ("oauth_callback", "OauthLogin"),
+    ("send_email_code", "SendEmailCode"),
+    ("check_email_code", "CheckEmailCode"),
+    ("reset_password", "ResetPassword"),
+    ("update_profile", "UpdateProfile"),
+    ("update_profile_by_employee", "UpdateProfile"),
+    ("send_sms_code", "SendSmsCode"),
+    ("recharge_credits", "RechargeCredits"),
+    ("set_user_level", "SetUserLevel"),
+    ("export_users", "ExportUsers"),
+    ("create_export_job", "ExportUsers"),
+    ("export_job", "ExportJob"),
+    ("download_export", "ExportJob"),
+    ("create_webhook", "CreateWebhook"),
+    ("delete_webhook", "DeleteWebhook"),
+    ("create_referral_code", "CreateReferralCode"),
+    ("avatar_file", "UploadAvatar"),
+];
+
+#[derive(Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct CheckRgisterdParams {
     email: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckRgisterdResp {
     is_registered: bool,
 }
 
+/// 检查邮箱是否已注册
+#[utoipa::path(
+    get,
+    path = "/api/user/check_register",
+    params(CheckRgisterdParams),
+    responses((status = 200, body = CheckRgisterdResp)),
+    tag = "user"
+)]
 pub(crate) async fn check_register(
     params: Query<CheckRgisterdParams>,
 ) -> ApiResult<CheckRgisterdResp> {
@@ -259,19 +591,28 @@ pub(crate) async fn check_register(
     })
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct CheckEmailCodeParams {
     email: String,
     code: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckEmailCodeResp {
     valid: bool,
 }
 
+/// 检查邮箱验证码是否正确
+#[utoipa::path(
+    get,
+    path = "/api/user/check_email_code",
+    params(CheckEmailCodeParams),
+    responses((status = 200, body = CheckEmailCodeResp)),
+    tag = "user"
+)]
 pub(crate) async fn check_email_code(
     params: Query<CheckEmailCodeParams>,
 ) -> ApiResult<CheckEmailCodeResp> {
@@ -281,18 +622,161 @@ pub(crate) async fn check_email_code(
     ApiResponse::Ok(CheckEmailCodeResp { valid })
 }
 
+/// 人机验证的 token 通过这个请求头提交
+const CAPTCHA_HEADER: &str = "X-Captcha-Token";
+
+/// 按连接的 ip（经过可信代理时为代理转发的 ip）和目标邮箱、手机号计数
+async fn check_captcha(scene: Scene, target: &str, req: &HttpRequest) -> Result<(), ApiError> {
+    let token = req
+        .headers()
+        .get(CAPTCHA_HEADER)
+        .and_then(|v| v.to_str().ok());
+    captcha::check(scene, client_ip(req), target, token).await??;
+    Ok(())
+}
+
+/// 注册，成功后自动登录，请求过于频繁时需要人机验证
+#[utoipa::path(
+    post,
+    path = "/api/user/register",
+    request_body = UserDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub(crate) async fn register(params: Json<UserDto>, req: HttpRequest) -> ApiResult<()> {
+    check_captcha(Scene::Register, params.email(), &req).await?;
     let id = user::register(params.into_inner()).await??;
     Identity::login(&req.extensions(), id.to_string())?;
+    csrf::rotate_token(&req.get_session())?;
     ApiResponse::Ok(())
 }
 
+/// 登录
+#[utoipa::path(
+    post,
+    path = "/api/user/login",
+    request_body = LoginDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub(crate) async fn login(params: Json<LoginDto>, req: HttpRequest) -> ApiResult<()> {
     let id = user::login(params.into_inner()).await??;
     Identity::login(&req.extensions(), id.to_string())?;
+    csrf::rotate_token(&req.get_session())?;
     ApiResponse::Ok(())
 }
 
+/// 手机号和短信验证码登录，验证码通过 /api/user/sms_code 获取
+#[utoipa::path(
+    post,
+    path = "/api/user/login_sms",
+    request_body = SmsLoginDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub(crate) async fn login_sms(params: Json<SmsLoginDto>, req: HttpRequest) -> ApiResult<()> {
+    let id = sms_login::login(params.into_inner()).await??;
+    Identity::login(&req.extensions(), id.to_string())?;
+    csrf::rotate_token(&req.get_session())?;
+    ApiResponse::Ok(())
+}
+
+/// session 中保存的 state，格式为 `{provider}:{state}`
+const OAUTH_STATE_KEY: &str = "oauth_state";
+
+/// 跳转到第三方授权页面
+#[utoipa::path(
+    get,
+    path = "/api/user/oauth/{provider}/authorize",
+    params(("provider" = String, Path, description = "登录方式，如 github")),
+    responses((status = 302, description = "跳转到授权页面")),
+    tag = "user"
+)]
+pub(crate) async fn oauth_authorize(
+    provider: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let state = oauth::new_state();
+    let url = oauth::authorize_url(&provider, &state)??;
+    req.get_session()
+        .insert(OAUTH_STATE_KEY, format!("{}:{}", provider, state))?;
+    let resp = HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish();
+    Ok(resp)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthCallbackParams {
+    code: String,
+    state: String,
+}
+
+/// 第三方授权后的回调，已登录时把第三方账号关联到当前账号
+#[utoipa::path(
+    get,
+    path = "/api/user/oauth/{provider}/callback",
+    params(
+        ("provider" = String, Path, description = "登录方式，如 github"),
+        OAuthCallbackParams
+    ),
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub(crate) async fn oauth_callback(
+    id: Option<Identity>,
+    provider: web::Path<String>,
+    params: Query<OAuthCallbackParams>,
+    req: HttpRequest,
+) -> ApiResult<()> {
+    let provider = provider.into_inner();
+    let OAuthCallbackParams { code, state } = params.into_inner();
+    let session = req.get_session();
+    let saved = session
+        .remove_as::<String>(OAUTH_STATE_KEY)
+        .and_then(Result::ok);
+    if saved != Some(format!("{}:{}", provider, state)) {
+        return Err(OAuthErr::InvalidState.into());
+    }
+
+    let current = match id {
+        Some(id) => Some(id.id()?.parse::<UserId>()?),
+        None => None,
+    };
+    let user_id = oauth::callback(provider, &code, current).await??;
+    if current.is_none() {
+        Identity::login(&req.extensions(), user_id.to_string())?;
+        csrf::rotate_token(&session)?;
+    }
+    ApiResponse::Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CsrfTokenResp {
+    token: String,
+}
+
+/// 获取 CSRF token，同时通过 XSRF-TOKEN cookie 下发
+#[utoipa::path(
+    get,
+    path = "/api/user/csrf_token",
+    responses((status = 200, body = CsrfTokenResp)),
+    tag = "user"
+)]
+pub(crate) async fn csrf_token(_id: Identity, req: HttpRequest) -> ApiResult<CsrfTokenResp> {
+    let token = csrf::ensure_token(&req.get_session())?;
+    ApiResponse::Ok(CsrfTokenResp { token })
+}
+
+/// 退出登录
+#[utoipa::path(
+    post,
+    path = "/api/user/logout",
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub(crate) async fn logout(id: Identity) -> ApiResult<()> {
     let user_id = id.id()?.parse()?;
     // 不返回错误，只记录日志
@@ -301,37 +785,73 @@ pub(crate) async fn logout(id: Identity) -> ApiResult<()> {
     ApiResponse::Ok(())
 }
 
+/// 检查登录状态
+#[utoipa::path(
+    get,
+    path = "/api/user/ping",
+    responses((status = 200, body = String, content_type = "text/plain")),
+    tag = "user"
+)]
 pub(crate) async fn user_ping(_id: Identity) -> &'static str {
     "pong"
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct SendEmailCodeParams {
     email: String,
     #[serde(default)]
     fake: bool,
 }
 
-pub async fn send_email_code(params: Query<SendEmailCodeParams>) -> ApiResult<()> {
+/// 发送邮箱验证码，请求过于频繁时需要人机验证
+#[utoipa::path(
+    get,
+    path = "/api/user/send_email_code",
+    params(SendEmailCodeParams),
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub async fn send_email_code(
+    params: Query<SendEmailCodeParams>,
+    req: HttpRequest,
+) -> ApiResult<()> {
     let SendEmailCodeParams { email, fake } = params.into_inner();
+    check_captcha(Scene::EmailCode, &email, &req).await?;
 
     email::send_email_code(email, fake).await??;
     ApiResponse::Ok(())
 }
 
+/// 通过邮箱验证码重置密码
+#[utoipa::path(
+    post,
+    path = "/api/user/reset_password",
+    request_body = ResetPasswordDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub async fn reset_password(params: Json<ResetPasswordDto>) -> ApiResult<()> {
     user::reset_password(params.into_inner()).await??;
     ApiResponse::Ok(())
 }
 
+/// 修改个人资料
+#[utoipa::path(
+    post,
+    path = "/api/user/modify_info",
+    request_body = UserUpdateDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub async fn update_profile(id: Identity, params: Json<UserUpdateDto>) -> ApiResult<()> {
     let user_id = id.id()?.parse()?;
     user::update_profile(user_id, params.into_inner()).await??;
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserUpdateDtoByAdmin {
     user_id: String,
@@ -339,6 +859,14 @@ pub struct UserUpdateDtoByAdmin {
     new_profile: UserUpdateDto,
 }
 
+/// 管理员修改用户资料
+#[utoipa::path(
+    post,
+    path = "/admin/user/modify",
+    request_body = UserUpdateDtoByAdmin,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
 pub async fn update_profile_by_employee(
     _id: Identity,
     params: Json<UserUpdateDtoByAdmin>,
@@ -352,20 +880,263 @@ pub async fn update_profile_by_employee(
     ApiResponse::Ok(())
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 #[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
 pub struct SendSmsCodeParams {
     mobile_number: String,
     #[serde(default)]
     fake: bool,
 }
 
-pub async fn send_sms_code(params: Query<SendSmsCodeParams>) -> ApiResult<()> {
+/// 发送短信验证码，请求过于频繁时需要人机验证
+#[utoipa::path(
+    get,
+    path = "/api/user/sms_code",
+    params(SendSmsCodeParams),
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub async fn send_sms_code(params: Query<SendSmsCodeParams>, req: HttpRequest) -> ApiResult<()> {
     let SendSmsCodeParams {
         mobile_number,
         fake,
     } = params.into_inner();
+    check_captcha(Scene::SmsCode, &mobile_number, &req).await?;
 
     user::send_sms_code(mobile_number, fake).await??;
     ApiResponse::Ok(())
 }
+
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeCreditsParams {
+    user_id: String,
+    amount: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RechargeCreditsResp {
+    balance: i64,
+}
+
+/// 为用户充值积分
+#[utoipa::path(
+    post,
+    path = "/admin/user/recharge_credits",
+    request_body = RechargeCreditsParams,
+    responses((status = 200, body = RechargeCreditsResp)),
+    tag = "user"
+)]
+pub async fn recharge_credits(
+    _id: Identity,
+    params: Json<RechargeCreditsParams>,
+) -> ApiResult<RechargeCreditsResp> {
+    let user_id = params.user_id.parse()?;
+    let balance = credits::recharge(user_id, params.amount).await??;
+    ApiResponse::Ok(RechargeCreditsResp { balance })
+}
+
+/// 设置用户等级，存储配额、同时转码的任务数等限制随等级变化
+#[utoipa::path(
+    post,
+    path = "/admin/user/set_level",
+    request_body = SetLevelDto,
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub async fn set_user_level(id: Identity, params: Json<SetLevelDto>) -> ApiResult<()> {
+    let employee_id = id.id()?.parse()?;
+    tier::set_level(employee_id, params.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 当前登录的员工及其角色
+fn employee(id: &Identity, req: &HttpRequest) -> anyhow::Result<(EmployeeId, Role)> {
+    let employee_id = id.id()?.parse()?;
+    let Some(role) = req.get_session().get::<Role>("role")? else {
+        anyhow::bail!("employee role not found");
+    };
+    Ok((employee_id, role))
+}
+
+/// 以 csv 格式导出用户列表，数量较多时需要使用后台导出
+#[utoipa::path(
+    post,
+    path = "/admin/user/export",
+    request_body = UserExportParams,
+    responses((status = 200, body = String, content_type = "text/csv")),
+    tag = "user"
+)]
+pub async fn export_users(
+    id: Identity,
+    req: HttpRequest,
+    params: Json<UserExportParams>,
+) -> Result<HttpResponse, ApiError> {
+    let (employee_id, role) = employee(&id, &req)?;
+    let chunks = export::export_csv(employee_id, role, params.into_inner()).await??;
+
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename("users.csv".to_string())],
+    };
+    let resp = HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(disposition)
+        .streaming(chunks.map_ok(Bytes::from));
+    Ok(resp)
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJobCreated {
+    job_id: i64,
+}
+
+/// 创建后台导出任务，完成后通过任务详情中的链接下载
+#[utoipa::path(
+    post,
+    path = "/admin/user/export_jobs",
+    request_body = UserExportParams,
+    responses((status = 200, body = ExportJobCreated)),
+    tag = "user"
+)]
+pub async fn create_export_job(
+    id: Identity,
+    req: HttpRequest,
+    params: Json<UserExportParams>,
+) -> ApiResult<ExportJobCreated> {
+    let (employee_id, role) = employee(&id, &req)?;
+    let job_id = export::start_job(employee_id, role, params.into_inner()).await??;
+    ApiResponse::Ok(ExportJobCreated { job_id })
+}
+
+/// 导出任务详情
+#[utoipa::path(
+    get,
+    path = "/admin/user/export_jobs/{id}",
+    params(("id" = i64, Path, description = "导出任务 id")),
+    responses((status = 200, body = ExportJobDto)),
+    tag = "user"
+)]
+pub async fn export_job(id: Identity, job_id: web::Path<i64>) -> ApiResult<ExportJobDto> {
+    let employee_id = id.id()?.parse()?;
+    let job = export::job(employee_id, job_id.into_inner()).await??;
+    ApiResponse::Ok(job)
+}
+
+/// 下载导出任务生成的文件
+#[utoipa::path(
+    get,
+    path = "/admin/user/export_jobs/{id}/download",
+    params(("id" = i64, Path, description = "导出任务 id")),
+    responses((status = 200, body = String, content_type = "text/csv")),
+    tag = "user"
+)]
+pub async fn download_export(id: Identity, job_id: web::Path<i64>) -> Result<NamedFile, ApiError> {
+    let employee_id = id.id()?.parse()?;
+    let (path, name) = export::job_file(employee_id, job_id.into_inner()).await??;
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err(anyhow::Error::from)?;
+    let file = file.set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(name)],
+    });
+    Ok(file)
+}
+
+/// 列出当前用户的 webhook
+#[utoipa::path(
+    get,
+    path = "/api/user/webhooks",
+    responses((status = 200, body = [WebhookDto])),
+    tag = "user"
+)]
+pub async fn list_webhooks(id: Identity) -> ApiResult<Vec<WebhookDto>> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let hooks = webhook::list(user_id).await?;
+    ApiResponse::Ok(hooks)
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateWebhookParams {
+    url: String,
+}
+
+/// 创建 webhook
+#[utoipa::path(
+    post,
+    path = "/api/user/webhooks",
+    request_body = CreateWebhookParams,
+    responses((status = 200, body = CreatedWebhook)),
+    tag = "user"
+)]
+pub async fn create_webhook(
+    id: Identity,
+    params: Json<CreateWebhookParams>,
+) -> ApiResult<CreatedWebhook> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let hook = webhook::create(user_id, params.into_inner().url).await??;
+    ApiResponse::Ok(hook)
+}
+
+/// 删除 webhook
+#[utoipa::path(
+    delete,
+    path = "/api/user/webhooks/{id}",
+    params(("id" = WebhookId, Path, description = "webhook id")),
+    responses((status = 200, description = "成功")),
+    tag = "user"
+)]
+pub async fn delete_webhook(id: Identity, hook_id: web::Path<WebhookId>) -> ApiResult<()> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    webhook::delete(user_id, hook_id.into_inner()).await??;
+    ApiResponse::Ok(())
+}
+
+/// 列出当前用户的邀请码及各自邀请的人数
+#[utoipa::path(
+    get,
+    path = "/api/user/referral_codes",
+    responses((status = 200, body = [ReferralCodeDto])),
+    tag = "user"
+)]
+pub async fn list_referral_codes(id: Identity) -> ApiResult<Vec<ReferralCodeDto>> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let codes = referral::list_codes(user_id).await?;
+    ApiResponse::Ok(codes)
+}
+
+/// 生成邀请码
+#[utoipa::path(
+    post,
+    path = "/api/user/referral_codes",
+    responses((status = 200, body = ReferralCodeDto)),
+    tag = "user"
+)]
+pub async fn create_referral_code(id: Identity) -> ApiResult<ReferralCodeDto> {
+    let user_id = id.id()?.parse::<UserId>()?;
+    let code = referral::create_code(user_id).await??;
+    ApiResponse::Ok(code)
+}
+
+/// 头像原图，缩略图生成之前使用
+#[utoipa::path(
+    get,
+    path = "/api/user/avatar/{hash}",
+    params(("hash" = String, Path, description = "头像文件 hash")),
+    responses((status = 200, body = String, content_type = "image/*")),
+    tag = "user"
+)]
+async fn avatar_file(hash: web::Path<String>) -> Result<NamedFile, ApiError> {
+    let Some(path) = avatar::original(&hash).await? else {
+        return Err(UPLOAD_AVATAR.not_found.into());
+    };
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err(anyhow::Error::from)?;
+    Ok(file)
+}
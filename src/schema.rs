@@ -1,5 +1,68 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    activities (id) {
+        id -> Int8,
+        user_id -> Int8,
+        kind -> Int2,
+        message -> Varchar,
+        file_path -> Nullable<Varchar>,
+        order_id -> Nullable<Int8>,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    audit_logs (id) {
+        id -> Int8,
+        employee_id -> Int8,
+        action -> Varchar,
+        detail -> Text,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    credit_transactions (id) {
+        id -> Int8,
+        user_id -> Int8,
+        amount -> Int8,
+        balance -> Int8,
+        kind -> Int2,
+        order_id -> Nullable<Int8>,
+        task_id -> Nullable<Int8>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    dir_usages (dir_id) {
+        dir_id -> Int8,
+        user_id -> Int8,
+        size -> Int8,
+        file_count -> Int8,
+        video_duration_ms -> Int8,
+        computed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_outbox (id) {
+        id -> Int8,
+        to_addr -> Varchar,
+        subject -> Varchar,
+        body -> Text,
+        status -> Int2,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        expire_at -> Nullable<Timestamptz>,
+        last_error -> Nullable<Text>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     employees (id) {
         id -> Int8,
@@ -15,6 +78,166 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    factory_callbacks (id) {
+        id -> Int8,
+        kind -> Int2,
+        task_id -> Int8,
+        payload -> Text,
+        status -> Int2,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    factory_outbox (id) {
+        id -> Int8,
+        task_id -> Int8,
+        payload -> Text,
+        status -> Int2,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        priority -> Int2,
+    }
+}
+
+diesel::table! {
+    feature_flags (name) {
+        name -> Varchar,
+        description -> Varchar,
+        enabled -> Bool,
+        rollout_percent -> Int2,
+        user_ids -> Array<Int8>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_accesses (user_id, user_file_id) {
+        user_id -> Int8,
+        user_file_id -> Int8,
+        accessed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_access_logs (id) {
+        id -> Int8,
+        user_file_id -> Int8,
+        kind -> Varchar,
+        accessor_id -> Nullable<Int8>,
+        accessed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_comments (id) {
+        id -> Int8,
+        user_file_id -> Int8,
+        user_id -> Int8,
+        content -> Varchar,
+        timecode_ms -> Nullable<Int4>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_tags (user_file_id, tag_id) {
+        user_file_id -> Int8,
+        tag_id -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_changes (id) {
+        id -> Int8,
+        user_id -> Int8,
+        user_file_id -> Int8,
+        kind -> Int2,
+        is_dir -> Bool,
+        path -> Varchar,
+        old_path -> Nullable<Varchar>,
+        create_at -> Timestamptz,
+        seq -> Int8,
+    }
+}
+
+diesel::table! {
+    file_edit_locks (user_file_id) {
+        user_file_id -> Int8,
+        holder_id -> Int8,
+        expire_at -> Timestamptz,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_integrity_mismatches (id) {
+        id -> Int8,
+        sys_file_id -> Int8,
+        expected_hash -> Varchar,
+        actual_hash -> Nullable<Varchar>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_locks (task_id) {
+        task_id -> Int8,
+        user_file_id -> Int8,
+        user_id -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    file_quarantines (hash) {
+        hash -> Varchar,
+        scanner -> Varchar,
+        signature -> Varchar,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    folder_shares (dir_id, member_id) {
+        dir_id -> Int8,
+        member_id -> Int8,
+        owner_id -> Int8,
+        permission -> Int2,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int8,
+        user_id -> Int8,
+        kind -> Int2,
+        message -> Varchar,
+        file_id -> Nullable<Int8>,
+        task_id -> Nullable<Int8>,
+        read -> Bool,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        seq -> Int8,
+    }
+}
+
 diesel::table! {
     orders (id) {
         id -> Int8,
@@ -22,6 +245,116 @@ diesel::table! {
         status -> Int2,
         create_at -> Timestamptz,
         updated_at -> Timestamptz,
+        start_after -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    org_members (org_id, user_id) {
+        org_id -> Int8,
+        user_id -> Int8,
+        role -> Int2,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    organizations (id) {
+        id -> Int8,
+        name -> Varchar,
+        space_id -> Int8,
+        storage_quota -> Nullable<Int8>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    path_history (id) {
+        id -> Int8,
+        user_id -> Int8,
+        user_file_id -> Int8,
+        old_path -> Varchar,
+        new_path -> Varchar,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    provider_identities (provider, subject) {
+        provider -> Varchar,
+        subject -> Varchar,
+        user_id -> Int8,
+        email -> Nullable<Varchar>,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    publication_reports (id) {
+        id -> Int8,
+        publication_id -> Int8,
+        reporter_id -> Nullable<Int8>,
+        reason -> Text,
+        state -> Int2,
+        handled_by -> Nullable<Int8>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    publications (id) {
+        id -> Int8,
+        slug -> Varchar,
+        user_id -> Int8,
+        user_file_id -> Int8,
+        title -> Varchar,
+        view_count -> Int8,
+        taken_down -> Bool,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    referral_codes (code) {
+        code -> Varchar,
+        user_id -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    referrals (invitee_id) {
+        invitee_id -> Int8,
+        inviter_id -> Int8,
+        code -> Varchar,
+        inviter_credits -> Int8,
+        invitee_credits -> Int8,
+        bonus_quota -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    starred_files (user_id, user_file_id) {
+        user_id -> Int8,
+        user_file_id -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    subtitles (id) {
+        id -> Int8,
+        video_file_id -> Int8,
+        subtitle_file_id -> Int8,
+        user_id -> Int8,
+        language -> Nullable<Varchar>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -44,6 +377,40 @@ diesel::table! {
         audio_info -> Nullable<Text>,
         create_at -> Timestamptz,
         updated_at -> Timestamptz,
+        mime -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Int8,
+        user_id -> Int8,
+        name -> Varchar,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    transcode_rule_matches (rule_id, user_file_id) {
+        rule_id -> Int8,
+        user_file_id -> Int8,
+        sys_file_id -> Int8,
+        create_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    transcode_rules (id) {
+        id -> Int8,
+        user_id -> Int8,
+        name -> Varchar,
+        watch_dir -> Varchar,
+        recursive -> Bool,
+        enabled -> Bool,
+        params -> Text,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -60,6 +427,39 @@ diesel::table! {
         err_msg -> Nullable<Text>,
         create_at -> Timestamptz,
         updated_at -> Timestamptz,
+        credits -> Int8,
+        started_at -> Timestamptz,
+        retries -> Int2,
+        worker_id -> Nullable<Int8>,
+        vmaf -> Nullable<Float8>,
+        psnr -> Nullable<Float8>,
+        source_bitrate -> Nullable<Int8>,
+        output_bitrate -> Nullable<Int8>,
+        output_hash -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    user_export_jobs (id) {
+        id -> Int8,
+        employee_id -> Int8,
+        params -> Text,
+        status -> Int2,
+        row_count -> Int8,
+        error -> Nullable<Text>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_file_versions (id) {
+        id -> Int8,
+        user_file_id -> Int8,
+        user_id -> Int8,
+        sys_file_id -> Int8,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -78,6 +478,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_sequences (user_id, name) {
+        user_id -> Int8,
+        name -> Varchar,
+        value -> Int8,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int8,
@@ -90,14 +498,98 @@ diesel::table! {
         create_at -> Timestamptz,
         updated_at -> Timestamptz,
         online -> Bool,
+        credits -> Int8,
+        order_email_opt_out -> Bool,
+        max_file_versions -> Nullable<Int4>,
+        level -> Int2,
+        bonus_quota -> Int8,
+        avatar -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Int8,
+        webhook_id -> Int8,
+        event -> Varchar,
+        payload -> Text,
+        status -> Int2,
+        attempts -> Int4,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webhooks (id) {
+        id -> Int8,
+        user_id -> Int8,
+        url -> Varchar,
+        secret -> Varchar,
+        create_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    workers (id) {
+        id -> Int8,
+        endpoint -> Varchar,
+        codecs -> Array<Text>,
+        max_width -> Int4,
+        max_height -> Int4,
+        concurrency -> Int4,
+        running -> Int4,
+        last_heartbeat_at -> Timestamptz,
+        create_at -> Timestamptz,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    activities,
+    audit_logs,
+    credit_transactions,
+    dir_usages,
+    email_outbox,
     employees,
+    factory_callbacks,
+    factory_outbox,
+    feature_flags,
+    file_access_logs,
+    file_accesses,
+    file_comments,
+    file_changes,
+    file_edit_locks,
+    file_integrity_mismatches,
+    file_locks,
+    file_quarantines,
+    file_tags,
+    folder_shares,
+    notifications,
     orders,
+    org_members,
+    organizations,
+    path_history,
+    provider_identities,
+    publication_reports,
+    publications,
+    referral_codes,
+    referrals,
+    starred_files,
+    subtitles,
     sys_files,
+    tags,
+    transcode_rule_matches,
+    transcode_rules,
     transcode_tasks,
+    user_export_jobs,
+    user_file_versions,
     user_files,
+    user_sequences,
     users,
+    webhook_deliveries,
+    webhooks,
+    workers,
 );
@@ -1,16 +1,35 @@
 use std::{
+    fmt::Debug,
+    net::IpAddr,
     path::{Path, PathBuf},
     sync::OnceLock,
 };
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use config::Config;
-use serde::{Deserialize, Serialize};
+use config::{Config, Environment};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    application::file_system::FileSystemCfg,
-    infrastructure::{av1_factory::Av1FactoryCfg, email::EmailCodeCfg},
+    application::{
+        callback::CallbackCfg, credits::CreditsCfg, file_system::FileSystemCfg,
+        gallery::GalleryCfg, import::ImportCfg, outbox::OutboxCfg, schedule::ScheduleCfg,
+        transcode::PreviewCfg, user::export::UserExportCfg, user::oauth::OAuthCfg,
+        user::password::PasswordCfg, user::referral::ReferralCfg, user::sms_login::SmsLoginCfg,
+        user::tier::TierCfg, watchdog::WatchdogCfg, webhook::WebhookCfg, worker::WorkerCfg,
+    },
+    domain::user::phone::PhonePolicy,
+    http::{
+        csrf::CsrfCfg, idempotency::IdempotencyCfg, internal_auth::InternalAuthCfg,
+        playback_token::PlaybackTokenCfg,
+    },
+    infrastructure::{
+        av1_factory::Av1FactoryCfg,
+        captcha::CaptchaCfg,
+        email::{EmailCodeCfg, EmailQueueCfg, OrderEmailCfg, WelcomeEmailCfg},
+        email_template::EmailTemplateCfg,
+        sms_code::SmsCfg,
+    },
 };
 
 #[derive(Deserialize, Debug)]
@@ -28,16 +47,87 @@ pub struct Settings {
 
     pub email_code: EmailCodeCfg,
 
+    #[serde(default)]
+    pub order_email: OrderEmailCfg,
+
+    #[serde(default)]
+    pub email_queue: EmailQueueCfg,
+
+    #[serde(default)]
+    pub welcome_email: WelcomeEmailCfg,
+
+    #[serde(default)]
+    pub email_template: EmailTemplateCfg,
+
+    pub sms: SmsCfg,
+
     pub init_system: InitSystem,
 
     pub file_system: FileSystemCfg,
 
     pub av1_factory: Av1FactoryCfg,
+
+    #[serde(default)]
+    pub outbox: OutboxCfg,
+
+    #[serde(default)]
+    pub callback: CallbackCfg,
+
+    #[serde(default)]
+    pub watchdog: WatchdogCfg,
+
+    #[serde(default)]
+    pub worker: WorkerCfg,
+
+    #[serde(default)]
+    pub credits: CreditsCfg,
+
+    #[serde(default)]
+    pub transcode_preview: PreviewCfg,
+
+    #[serde(default)]
+    pub order_schedule: ScheduleCfg,
+
+    #[serde(default)]
+    pub webhook: WebhookCfg,
+
+    #[serde(default)]
+    pub user_export: UserExportCfg,
+
+    #[serde(default)]
+    pub referral: ReferralCfg,
+
+    #[serde(default)]
+    pub password: PasswordCfg,
+
+    #[serde(default)]
+    pub phone: PhonePolicy,
+
+    #[serde(default)]
+    pub sms_login: SmsLoginCfg,
+
+    #[serde(default)]
+    pub oauth: OAuthCfg,
+
+    #[serde(default)]
+    pub captcha: CaptchaCfg,
+
+    #[serde(default)]
+    pub user_tiers: TierCfg,
+
+    #[serde(default)]
+    pub import: ImportCfg,
+
+    #[serde(default)]
+    pub gallery: GalleryCfg,
 }
 
 #[derive(Deserialize, Debug, Serialize)]
 pub struct InitSystem {
     pub register_test_user: bool,
+    /// 启动时为未解析的文件补全视频信息
+    #[serde(default)]
+    pub backfill_video_info: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -45,6 +135,26 @@ pub struct HttpServer {
     pub bind: String,
     pub port: u16,
     pub session: SessionRedis,
+    #[serde(default)]
+    pub idempotency: IdempotencyCfg,
+    #[serde(default)]
+    pub csrf: CsrfCfg,
+    #[serde(default)]
+    pub internal_auth: InternalAuthCfg,
+    #[serde(default)]
+    pub playback_token: PlaybackTokenCfg,
+    #[serde(default)]
+    pub cors: CorsCfg,
+    /// 可信的反向代理，只有来自这些地址的请求才使用 X-Forwarded-For 中的客户端 IP
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// 处理时间超过这个值的请求打印警告，为 0 时不记录
+    #[serde(default = "default_slow_request_ms")]
+    pub slow_request_ms: u64,
+}
+
+fn default_slow_request_ms() -> u64 {
+    1000
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
@@ -54,14 +164,132 @@ pub struct SessionRedis {
     pub http_only: bool,
     #[serde(default = "default_max_age")]
     pub max_age_secs: u32,
+    /// 用于签名 session cookie 的密钥，至少 64 字节。为空时使用开发环境的默认密钥
+    #[serde(default)]
+    pub key: Option<Secret>,
 }
 
 fn default_max_age() -> u32 {
     3600 * 24
 }
 
+/// 跨域配置，默认只允许同源访问
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CorsCfg {
+    /// 开发环境使用，允许任意来源、方法和请求头，忽略其他配置
+    #[serde(default)]
+    pub permissive: bool,
+    /// 允许的来源，如 `https://app.example.com`，`*` 表示任意来源
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+    /// 是否允许携带 cookie
+    #[serde(default = "default_true")]
+    pub supports_credentials: bool,
+    /// 预检请求的缓存时长
+    #[serde(default = "default_cors_max_age")]
+    pub max_age_secs: Option<usize>,
+}
+
+impl Default for CorsCfg {
+    fn default() -> Self {
+        Self {
+            permissive: false,
+            allowed_origins: vec![],
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_headers(),
+            supports_credentials: default_true(),
+            max_age_secs: default_cors_max_age(),
+        }
+    }
+}
+
+fn default_cors_methods() -> Vec<String> {
+    ["GET", "POST", "DELETE"].map(String::from).to_vec()
+}
+
+fn default_cors_headers() -> Vec<String> {
+    [
+        "Content-Type",
+        "X-CSRF-Token",
+        "Idempotency-Key",
+        "X-Captcha-Token",
+        "X-Org-Id",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_cors_max_age() -> Option<usize> {
+    Some(3600)
+}
+
+/// 敏感配置项，如密码、密钥等
+///
+/// 可以直接填写明文，也可以通过 `{ file = "/run/secrets/xxx" }` 从文件（如 docker/k8s secret）中读取，
+/// Debug 和 Serialize 的输出都会被掩盖，防止密钥被打印到日志中
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(******)")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str("******")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum SecretSource {
+            Plain(String),
+            File { file: PathBuf },
+        }
+
+        match SecretSource::deserialize(deserializer)? {
+            SecretSource::Plain(secret) => Ok(Self(secret)),
+            SecretSource::File { file } => {
+                let secret = std::fs::read_to_string(&file).map_err(|err| {
+                    serde::de::Error::custom(format!("cannot read secret file {:?}: {}", file, err))
+                })?;
+                // secret 文件通常以换行符结尾
+                Ok(Self(secret.trim_end_matches(['\r', '\n']).to_string()))
+            }
+        }
+    }
+}
+
 static SETTINGS: OnceLock<Settings> = OnceLock::new();
 
+/// 环境变量的前缀，层级之间使用 `__` 分隔，如 `AV1__POSTGRES__URL` 对应 `postgres.url`
+const ENV_PREFIX: &str = "AV1";
+const ENV_SEPARATOR: &str = "__";
+
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -74,22 +302,31 @@ pub struct Args {
     /// Should register root user
     #[arg(short, long)]
     register_test_user: bool,
+
+    /// Resubmit parse tasks for files without video info
+    #[arg(long)]
+    backfill_video_info: bool,
 }
 
 pub fn load_settings() -> Result<&'static Settings> {
     let default = config::File::from(Path::new("./configs/default.toml")).required(false);
     let mut builder = Config::builder().add_source(default);
-    builder = builder;
 
     // 在测试中，会默认传入多个测试相关的参数，所以跳过解析
     #[cfg(not(test))]
-    {
-        let args: Args = Args::parse();
-        if let Some(path) = args.config {
-            println!("loading settings. path = {:?}", path);
-            builder = builder.add_source(config::File::from(path).required(true));
-        }
+    let args: Args = Args::parse();
+
+    #[cfg(not(test))]
+    if let Some(path) = &args.config {
+        println!("loading settings. path = {:?}", path);
+        builder = builder.add_source(config::File::from(path.clone()).required(true));
+    }
+
+    // 环境变量的优先级高于配置文件
+    builder = builder.add_source(Environment::with_prefix(ENV_PREFIX).separator(ENV_SEPARATOR));
 
+    #[cfg(not(test))]
+    {
         #[derive(Debug, Serialize)]
         struct CmdSettings {
             init_system: InitSystem,
@@ -98,6 +335,7 @@ pub fn load_settings() -> Result<&'static Settings> {
         let c = CmdSettings {
             init_system: InitSystem {
                 register_test_user: args.register_test_user,
+                backfill_video_info: args.backfill_video_info,
             },
         };
 
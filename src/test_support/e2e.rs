@@ -0,0 +1,211 @@
+use anyhow::{anyhow, bail, Context, Result};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use utils::db_pools::postgres::pg_conn;
+
+use crate::{
+    application::{
+        callback,
+        file_system::{upload, video_info},
+        transcode::{self, TaskResult},
+    },
+    domain::{
+        file_system::{file::UserFileId, service::path_manager},
+        transcode_order::{metrics::QualityMetrics, TranscodeTaskId},
+        user::{
+            password::PasswordPolicy,
+            user::{User, UserId},
+            Email, Password,
+        },
+    },
+    infrastructure::{repo_order, repo_user, repo_user_file},
+};
+
+use super::{block_on, create_user, env, test_tx, wait_until};
+
+/// 解析结果中创建订单需要的最少字段
+const MEDIA_INFO: &str = r#"{
+    "general": { "Format": "MPEG-4", "Duration": 10.0, "OverallBitRate": 1000000 },
+    "video": { "Format": "AVC", "Duration": 10.0, "durationMs": 10000, "Width": 1920, "Height": 1080, "FrameRate": 25.0, "FrameCount": 250 },
+    "audio": null,
+    "ext": { "default_audio_track_id": null }
+}"#;
+
+/// 单个分片上传并合并，返回创建的用户文件
+async fn upload_file(
+    user_id: UserId,
+    parent_id: UserFileId,
+    file_name: &str,
+    content: &[u8],
+) -> Result<UserFileId> {
+    let task = serde_json::from_value(json!({
+        "hash": hex::encode(Sha256::digest(content)),
+        "parentId": parent_id,
+        "fileName": file_name,
+    }))?;
+    let task = upload::register_upload_task(user_id, user_id, task)
+        .await?
+        .map_err(|err| anyhow!("register upload task: {:?}", err))?;
+
+    let slice = futures_util::stream::iter([anyhow::Ok(content.to_vec())]);
+    let Ok(()) = upload::store_slice(task.task_id, 0, slice).await? else {
+        bail!("store slice failed");
+    };
+
+    let uploaded = upload::upload_finished(task.task_id)
+        .await?
+        .map_err(|err| anyhow!("finish upload: {:?}", err))?;
+    let uploaded = serde_json::to_value(uploaded)?;
+    let file_id = uploaded["fileId"].as_str().context("no file id")?;
+    Ok(file_id.parse()?)
+}
+
+#[test]
+#[ignore = "requires docker"]
+fn t_repo_changes_rolled_back() -> Result<()> {
+    block_on(async {
+        let email = Email::try_from(format!("rollback-{}@example.com", UserId::next_id()))
+            .map_err(|_| anyhow!("invalid email"))?;
+        let password = Password::try_from_async("aabbccdd".to_string(), &PasswordPolicy::default())
+            .await
+            .map_err(|_| anyhow!("invalid password"))?;
+        let user = User::create(email, password);
+        let user_id = user.id();
+
+        test_tx(|conn| {
+            async move {
+                assert!(repo_user::save(&user, conn).await?.is_effected());
+                assert!(repo_user::exist(user_id, conn).await?);
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        let conn = &mut pg_conn().await?;
+        assert!(!repo_user::exist(user_id, conn).await?);
+        Ok(())
+    })
+}
+
+#[test]
+#[ignore = "requires docker"]
+fn t_upload_then_finish() -> Result<()> {
+    block_on(async {
+        let (user_id, dir_id) = create_user().await?;
+        let content = b"av1-cloud integration test".repeat(1024);
+        let file_id = upload_file(user_id, dir_id, "hello.txt", &content).await?;
+
+        let conn = &mut pg_conn().await?;
+        let file = repo_user_file::find_node_for_read(file_id, conn)
+            .await?
+            .context("uploaded file not found")?;
+        assert_eq!(file.file_name(), "hello.txt");
+        assert_eq!(*file.user_id(), user_id);
+        let data = file.file_data().context("no file data")?;
+        assert_eq!(data.size, content.len() as u64);
+        assert_eq!(tokio::fs::read(&data.archived_path).await?, content);
+
+        // 信息采集请求由事件订阅者在后台发送
+        let sys_file_id = data.id.0;
+        wait_until(|| async {
+            let parsed = env().await?.factory.tasks("parse");
+            Ok(parsed.iter().any(|task| task["fileId"] == sys_file_id))
+        })
+        .await?;
+
+        // 相同内容再次上传时不会重复归档
+        let copy_id = upload_file(user_id, dir_id, "hello.txt", &content).await?;
+        assert_ne!(copy_id, file_id);
+        let copy = repo_user_file::find_node_for_read(copy_id, conn)
+            .await?
+            .context("uploaded file not found")?;
+        assert_eq!(copy.file_data().unwrap().id, data.id);
+        assert_ne!(copy.file_name(), "hello.txt");
+        Ok(())
+    })
+}
+
+#[test]
+#[ignore = "requires docker"]
+fn t_transcode_callback() -> Result<()> {
+    block_on(async {
+        let (user_id, dir_id) = create_user().await?;
+        let content = b"not really a video".repeat(1024);
+        let file_id = upload_file(user_id, dir_id, "video.mp4", &content).await?;
+
+        let conn = &mut pg_conn().await?;
+        let file = repo_user_file::find_node_for_read(file_id, conn)
+            .await?
+            .context("uploaded file not found")?;
+        let data = file.file_data().context("no file data")?;
+        let (sys_file_id, hash) = (data.id, data.hash.clone());
+        video_info::file_parsed(sys_file_id, Some(MEDIA_INFO.to_string())).await?;
+
+        let params = serde_json::from_value(json!([{
+            "fileId": file_id,
+            "includeAudio": false,
+            "containerFormat": "mp4",
+            "video": { "format": "av1", "quality": "base" },
+        }]))?;
+        let Ok(created) = transcode::create_order(user_id, params, None, None).await? else {
+            bail!("create order failed");
+        };
+        let created = serde_json::to_value(created)?;
+        let task_id: TranscodeTaskId = serde_json::from_value(created["taskIds"][0].clone())?;
+
+        // 转码请求经 outbox 发送给转码工厂
+        wait_until(|| async {
+            let sent = env().await?.factory.tasks("transcode");
+            Ok(sent.iter().any(|task| task["id"] == task_id.0))
+        })
+        .await?;
+
+        // 模拟转码工厂写出结果后回调
+        let order = repo_order::find(task_id, conn)
+            .await?
+            .context("order not found")?;
+        let task = order
+            .tasks()
+            .iter()
+            .find(|task| *task.id() == task_id)
+            .unwrap();
+        let params = task.params();
+        let out_path = path_manager().transcode_dst_path(
+            &hash,
+            params.container,
+            &params.video,
+            &params.audio,
+            params.sample,
+        );
+        tokio::fs::create_dir_all(out_path.parent().unwrap()).await?;
+        tokio::fs::write(&out_path, b"transcoded".repeat(1024)).await?;
+
+        let result = TaskResult {
+            task_id,
+            file_id: sys_file_id.0,
+            result: Ok(Some(QualityMetrics {
+                vmaf: Some(95.5),
+                psnr: Some(42.0),
+                source_bitrate: Some(8_000_000),
+                output_bitrate: Some(2_000_000),
+            })),
+        };
+        callback::receive_transcode_done(&result).await?;
+
+        wait_until(|| async {
+            let conn = &mut pg_conn().await?;
+            let order = repo_order::find(task_id, conn).await?;
+            Ok(order.is_some_and(|order| order.is_finished()))
+        })
+        .await?;
+        let order = repo_order::find(task_id, conn).await?.unwrap();
+        assert_eq!(order.task_counts(), (1, 0));
+
+        // 转码结果作为新的用户文件归档，源文件不受影响
+        assert!(!out_path.exists());
+        assert!(tokio::fs::metadata(&data.archived_path).await.is_ok());
+        Ok(())
+    })
+}
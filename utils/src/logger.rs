@@ -9,6 +9,18 @@ use tracing_subscriber::{
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub level: String,
+    #[serde(default)]
+    pub format: Format,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// 便于阅读的文本格式
+    #[default]
+    Text,
+    /// 每行一个 json 对象，包含当前 span 的字段（如请求 id），用于日志采集
+    Json,
 }
 
 struct LocalTimer;
@@ -30,12 +42,20 @@ pub fn init(config: &Config) -> Result<()> {
         for d in ADDITION_DERECTIVE {
             filter = filter.add_directive(d.parse().unwrap());
         }
-        fmt::Layer::new()
+        let layer = fmt::Layer::new()
             .with_timer(LocalTimer)
             .with_target(true)
             .with_writer(std::io::stdout)
-            .with_file(false)
-            .with_filter(filter)
+            .with_file(false);
+        match config.format {
+            Format::Text => layer.with_filter(filter).boxed(),
+            Format::Json => layer
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_filter(filter)
+                .boxed(),
+        }
     };
 
     let collector_std = tracing_subscriber::registry().with(std_out);